@@ -0,0 +1,50 @@
+/**
+ * Tests for how client commands are split and refused, in src/commands.ts.
+ *
+ * Usage: npx tsx --test scripts/test-commands.ts
+ */
+
+import assert from 'node:assert/strict';
+import { test } from 'node:test';
+import { splitCommand, type CommandLimits } from '../src/commands';
+
+const LIMITS: CommandLimits = { maxChars: 50, maxQueued: 4, rejectMultiline: false };
+
+test('line breaks of every kind split a command', () => {
+  assert.deepEqual(splitCommand('look', 0, LIMITS), { lines: ['look'], error: null });
+  assert.deepEqual(splitCommand('n\r\ne\rs\nw', 0, LIMITS).lines, ['n', 'e', 's', 'w']);
+  // An empty line is still a line: it sends a bare return
+  assert.deepEqual(splitCommand('', 0, LIMITS).lines, ['']);
+  assert.deepEqual(splitCommand('say hi\n', 0, LIMITS).lines, ['say hi', '']);
+});
+
+test('control characters are dropped, tabs kept', () => {
+  assert.deepEqual(splitCommand('sa\x00y\x1b[1m hi\x7f\tthere', 0, LIMITS).lines, ['say[1m hi\tthere']);
+  // U+00FF is kept: written as UTF-8, it never becomes a raw IAC byte
+  assert.deepEqual(splitCommand('say \xff', 0, LIMITS).lines, ['say \xff']);
+});
+
+test('over-long commands are refused whole', () => {
+  const result = splitCommand('x'.repeat(51), 0, LIMITS);
+  assert.deepEqual(result.lines, []);
+  assert.equal(result.error?.code, 'command_too_long');
+  assert.equal(splitCommand('x'.repeat(50), 0, LIMITS).error, null);
+});
+
+test('multi-line commands are refused in reject mode', () => {
+  const reject = { ...LIMITS, rejectMultiline: true };
+  const result = splitCommand('n\ne', 0, reject);
+  assert.deepEqual(result.lines, []);
+  assert.equal(result.error?.code, 'multiline_command');
+  assert.deepEqual(splitCommand('look', 0, reject), { lines: ['look'], error: null });
+});
+
+test('lines past a full queue are dropped', () => {
+  const result = splitCommand('a\nb\nc', 2, LIMITS);
+  assert.deepEqual(result.lines, ['a', 'b']);
+  assert.equal(result.error?.code, 'command_queue_full');
+  assert.equal(result.error?.detail, '4 commands already waiting; dropped: c');
+  const full = splitCommand('look', 4, LIMITS);
+  assert.deepEqual(full.lines, []);
+  assert.equal(full.error?.code, 'command_queue_full');
+});
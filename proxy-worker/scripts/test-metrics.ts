@@ -0,0 +1,54 @@
+/**
+ * Tests for the Prometheus text rendering in src/metrics.ts.
+ *
+ * Usage: npx tsx --test scripts/test-metrics.ts
+ */
+
+import assert from 'node:assert/strict';
+import { test } from 'node:test';
+import { Histogram, labelled, metric } from '../src/metrics';
+
+test('histogram buckets are cumulative', () => {
+  const histogram = new Histogram([10, 100, 1000]);
+  for (const value of [5, 10, 50, 500, 5000]) histogram.observe(value);
+  assert.equal(
+    histogram.render('proxy_session_seconds', 'Session length.'),
+    [
+      '# HELP proxy_session_seconds Session length.',
+      '# TYPE proxy_session_seconds histogram',
+      'proxy_session_seconds_bucket{le="10"} 2',
+      'proxy_session_seconds_bucket{le="100"} 3',
+      'proxy_session_seconds_bucket{le="1000"} 4',
+      'proxy_session_seconds_bucket{le="+Inf"} 5',
+      'proxy_session_seconds_sum 5565',
+      'proxy_session_seconds_count 5',
+    ].join('\n'),
+  );
+});
+
+test('an empty histogram renders zeros', () => {
+  const lines = new Histogram([1]).render('h', 'Help.').split('\n');
+  assert.deepEqual(lines.slice(2), ['h_bucket{le="1"} 0', 'h_bucket{le="+Inf"} 0', 'h_sum 0', 'h_count 0']);
+});
+
+test('labelled samples render one line per value', () => {
+  const samples = labelled('reason', new Map([['origin', 3], ['auth', 1]]));
+  assert.equal(
+    metric('proxy_handshake_failures_total', 'counter', 'Refused upgrades.', samples),
+    [
+      '# HELP proxy_handshake_failures_total Refused upgrades.',
+      '# TYPE proxy_handshake_failures_total counter',
+      'proxy_handshake_failures_total{reason="origin"} 3',
+      'proxy_handshake_failures_total{reason="auth"} 1',
+    ].join('\n'),
+  );
+});
+
+test('label values are escaped', () => {
+  const samples = labelled('code', new Map([['a"b\\c\nd', 1]]));
+  assert.equal(metric('m', 'counter', 'Help.', samples).split('\n')[2], 'm{code="a\\"b\\\\c\\nd"} 1');
+});
+
+test('unlabelled samples have no braces', () => {
+  assert.equal(metric('proxy_sessions', 'gauge', 'Open sessions.', [[{}, 7]]).split('\n')[2], 'proxy_sessions 7');
+});
@@ -0,0 +1,43 @@
+/**
+ * Tests for the `/ws` origin allowlist in src/origins.ts.
+ *
+ * Usage: npx tsx --test scripts/test-origins.ts
+ */
+
+import assert from 'node:assert/strict';
+import { test } from 'node:test';
+import { originAllowed, upgradeAllowed } from '../src/origins';
+
+const ALLOWED = ['https://dartforge.app', 'https://*.example.com'];
+
+test('origins match the allowlist exactly or by wildcard', () => {
+  const cases: [string, boolean][] = [
+    ['https://dartforge.app', true],
+    ['https://dartforge.app.evil.com', false],
+    ['http://dartforge.app', false],
+    ['https://a.example.com', true],
+    ['https://b.a.example.com', true],
+    // The wildcard needs a subdomain, on the same scheme and domain
+    ['https://example.com', false],
+    ['https://.example.com', false],
+    ['https://evilexample.com', false],
+    ['http://a.example.com', false],
+    ['https://a.example.com.evil.com', false],
+    ['', false],
+  ];
+  for (const [origin, expected] of cases) {
+    assert.equal(originAllowed(origin, ALLOWED), expected, origin);
+  }
+});
+
+test('nothing is allowed by an empty allowlist', () => {
+  assert.equal(originAllowed('https://dartforge.app', []), false);
+});
+
+test('a missing origin is up to allow_missing', () => {
+  assert.equal(upgradeAllowed(null, { allowed: ALLOWED, allow_missing: true }), true);
+  assert.equal(upgradeAllowed(null, { allowed: ALLOWED, allow_missing: false }), false);
+  // A present origin is checked either way
+  assert.equal(upgradeAllowed('https://evil.com', { allowed: ALLOWED, allow_missing: true }), false);
+  assert.equal(upgradeAllowed('https://a.example.com', { allowed: ALLOWED, allow_missing: false }), true);
+});
@@ -0,0 +1,126 @@
+/**
+ * Tests for the `/ws` protocol in src/protocol.ts.
+ *
+ * Usage: npx tsx --test scripts/test-protocol.ts
+ */
+
+import assert from 'node:assert/strict';
+import { test } from 'node:test';
+import {
+  decodeOutputFrame,
+  encodeOutputFrame,
+  FRAME_FLAG_GA,
+  FRAME_OUTPUT,
+  LEGACY_PROTOCOL_VERSION,
+  negotiateVersion,
+  PROTOCOL_VERSION,
+  unknownTypeError,
+  type ClientMessage,
+  type ServerMessage,
+} from '../src/protocol';
+
+/** One of every message the proxy sends, keyed by type so a new variant can't be left out. */
+const SERVER_MESSAGES: { [T in ServerMessage['type']]: Extract<ServerMessage, { type: T }> } = {
+  hello: { type: 'hello', version: PROTOCOL_VERSION, features: ['compact_output', 'session'] },
+  error: { type: 'error', code: 'command_too_long', detail: 'Too long', ts_ms: 1_700_000_000_000 },
+  output: { type: 'output', data: 'You see a lantern.\r\n> ', ga: true },
+  status: {
+    type: 'status',
+    connected: false,
+    message: 'Connection refused',
+    reason: 'connect_refused',
+    detail: 'ECONNREFUSED',
+    attempt: 2,
+    target: 'dartmud.com:2525',
+  },
+  pong: { type: 'pong' },
+  ping: { type: 'ping' },
+  stats: {
+    type: 'stats',
+    connected: true,
+    connected_at: 1_700_000_000_000,
+    last_read_at: null,
+    uptime_secs: 12,
+    bytes_read: 2048,
+    bytes_written: 64,
+    lines: 40,
+    total_bytes_read: 4096,
+    total_bytes_written: 128,
+    total_lines: 80,
+  },
+  command_backlog: { type: 'command_backlog', depth: 25, threshold: 20 },
+  session: { type: 'session', token: 'abc123', resumed: false, grace_secs: 60 },
+};
+
+/** One of every message a client may send. */
+const CLIENT_MESSAGES: { [T in ClientMessage['type']]: ClientMessage & { type: T } } = {
+  hello: { type: 'hello', version: PROTOCOL_VERSION, features: ['compact_output'] },
+  command: { type: 'command', data: 'look' },
+  reconnect: { type: 'reconnect', host: 'dartmud.com', port: 2525 },
+  disconnect: { type: 'disconnect' },
+  ping: { type: 'ping' },
+  pong: { type: 'pong' },
+  rate_limit: { type: 'rate_limit', per_second: null },
+  resume: { type: 'resume' },
+  auth: { type: 'auth', data: 'secret' },
+};
+
+test('every message survives JSON unchanged', () => {
+  for (const msg of [...Object.values(SERVER_MESSAGES), ...Object.values(CLIENT_MESSAGES)]) {
+    assert.deepEqual(JSON.parse(JSON.stringify(msg)), msg, msg.type);
+  }
+});
+
+test('hello settles on the lower version, never below 1', () => {
+  assert.equal(negotiateVersion(PROTOCOL_VERSION + 5), PROTOCOL_VERSION);
+  assert.equal(negotiateVersion(2), Math.min(2, PROTOCOL_VERSION));
+  assert.equal(negotiateVersion(2.9), Math.min(2, PROTOCOL_VERSION));
+  for (const offered of [undefined, 0, -3, 'two']) {
+    assert.equal(negotiateVersion(offered), LEGACY_PROTOCOL_VERSION);
+  }
+});
+
+test('clients that never said hello are not sent unknown_type', () => {
+  assert.equal(unknownTypeError(LEGACY_PROTOCOL_VERSION, 'gmcp'), null);
+  // Nor are those whose hello only offered version 1
+  assert.equal(unknownTypeError(negotiateVersion(1), 'gmcp'), null);
+  assert.deepEqual(unknownTypeError(negotiateVersion(PROTOCOL_VERSION), 'gmcp'), {
+    type: 'error',
+    code: 'unknown_type',
+    detail: 'Unknown message type: gmcp',
+  });
+});
+
+test('output frames round-trip', () => {
+  for (const msg of [
+    { type: 'output' as const, data: 'You see a \x1b[1mlantern\x1b[0m here.\r\n', ga: false },
+    { type: 'output' as const, data: 'HP: 42/50 é ✓ > ', ga: true },
+  ]) {
+    assert.deepEqual(decodeOutputFrame(encodeOutputFrame(msg)), msg);
+  }
+});
+
+test('ga is the low bit of the flags byte', () => {
+  const frame = encodeOutputFrame({ type: 'output', data: 'x', ga: true });
+  assert.equal(frame[0], FRAME_OUTPUT);
+  assert.equal(frame[1], FRAME_FLAG_GA);
+  assert.equal(encodeOutputFrame({ type: 'output', data: 'x', ga: false })[1], 0);
+  // Other flag bits are left for later versions and don't read as ga
+  assert.deepEqual(decodeOutputFrame(new Uint8Array([FRAME_OUTPUT, 0x02, 0x78])), {
+    type: 'output',
+    data: 'x',
+    ga: false,
+  });
+});
+
+test('an empty payload is a two byte frame', () => {
+  const frame = encodeOutputFrame({ type: 'output', data: '', ga: true });
+  assert.deepEqual([...frame], [FRAME_OUTPUT, FRAME_FLAG_GA]);
+  assert.deepEqual(decodeOutputFrame(frame), { type: 'output', data: '', ga: true });
+});
+
+test('frames that are not output decode to null', () => {
+  assert.equal(decodeOutputFrame(new Uint8Array([])), null);
+  assert.equal(decodeOutputFrame(new Uint8Array([FRAME_OUTPUT])), null);
+  assert.equal(decodeOutputFrame(new Uint8Array([0x02, 0, 0x78])), null);
+});
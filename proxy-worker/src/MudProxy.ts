@@ -1,34 +1,129 @@
 import { connect } from 'cloudflare:sockets';
-import { processOutput } from './telnet';
-
-const MUD_HOST = 'dartmud.com';
-const MUD_PORT = 2525;
+import { loadConfig, type ProxyConfig } from './config';
+import { AUTH_DEADLINE_MS, clientIp, handshakeAuth, POLICY_VIOLATION, recordAuthFailure, tokenMatches } from './auth';
+import { splitCommand } from './commands';
+import type { Env } from './index';
+import {
+  encodeOutputFrame,
+  LEGACY_PROTOCOL_VERSION,
+  negotiateVersion,
+  PROXY_FEATURES,
+  unknownTypeError,
+  type ClientMessage,
+  type OutputMessage,
+  type ServerMessage,
+  type StatsMessage,
+  type StatusMessage,
+  type StatusReason,
+} from './protocol';
+import { hubRequest } from './ProxyHub';
+import { SessionLog } from './sessionLog';
+import { IAC_DO_TIMING_MARK, processOutput } from './telnet';
+import { TokenBucket } from './TokenBucket';
+
+/** The target when `ALLOWED_TARGETS` isn't set, and then the only one allowed. */
+const DEFAULT_TARGET = 'dartmud.com:2525';
 const MAX_RETRIES = 3;
 const RETRY_DELAY_MS = 2_000;
 const CONNECT_TIMEOUT_MS = 10_000;
+const STATS_INTERVAL_MS = 5_000;
+/** Commands held by the rate limiter before a `command_backlog` warning. */
+const BACKLOG_WARN_THRESHOLD = 20;
+/** Output characters held for a detached session; the oldest are dropped past this. */
+const MAX_DETACHED_CHARS = 256 * 1024;
+/** WebSocket close code for an oversized message. */
+const MESSAGE_TOO_BIG = 1009;
+
+interface MudTarget {
+  host: string;
+  port: number;
+}
 
-interface ClientMessage {
-  type: 'command' | 'reconnect' | 'disconnect' | 'ping';
-  data?: string;
+/** A `host:port` allowlist entry; a `*` port allows any port on the host. */
+interface TargetPattern {
+  host: string;
+  port: number | null;
 }
 
-interface OutputMessage {
-  type: 'output';
-  data: string;
-  ga: boolean;
+/**
+ * Parse `targets.allowed` (`host:port` or `host:*` entries). None means only
+ * `DEFAULT_TARGET`. Malformed entries are ignored.
+ */
+function parseAllowedTargets(entries: string[]): TargetPattern[] {
+  const patterns = entries.flatMap((entry): TargetPattern[] => {
+    const colon = entry.lastIndexOf(':');
+    const host = entry.slice(0, colon).toLowerCase();
+    const port = entry.slice(colon + 1);
+    if (colon <= 0) return [];
+    if (port === '*') return [{ host, port: null }];
+    const number = Number(port);
+    return Number.isInteger(number) && number > 0 && number < 65536 ? [{ host, port: number }] : [];
+  });
+  if (patterns.length > 0) return patterns;
+  const [host, port] = DEFAULT_TARGET.split(':');
+  return [{ host, port: Number(port) }];
 }
 
-interface StatusMessage {
-  type: 'status';
-  connected: boolean;
-  message: string;
+function formatTarget(target: MudTarget): string {
+  return `${target.host}:${target.port}`;
 }
 
-interface PongMessage {
-  type: 'pong';
+function randomHex(bytes: number): string {
+  return Array.from(crypto.getRandomValues(new Uint8Array(bytes)), (b) => b.toString(16).padStart(2, '0')).join('');
 }
 
-type ServerMessage = OutputMessage | StatusMessage | PongMessage;
+/** Per-connection counters (reset on connect) plus lifetime totals. */
+class ConnectionStats {
+  connectedAt: number | null = null;
+  lastReadAt: number | null = null;
+  bytesRead = 0;
+  bytesWritten = 0;
+  lines = 0;
+  totalBytesRead = 0;
+  totalBytesWritten = 0;
+  totalLines = 0;
+
+  start(): void {
+    this.connectedAt = Date.now();
+    this.lastReadAt = null;
+    this.bytesRead = 0;
+    this.bytesWritten = 0;
+    this.lines = 0;
+  }
+
+  stop(): void {
+    this.connectedAt = null;
+  }
+
+  recordRead(bytes: number, lines: number): void {
+    this.lastReadAt = Date.now();
+    this.bytesRead += bytes;
+    this.totalBytesRead += bytes;
+    this.lines += lines;
+    this.totalLines += lines;
+  }
+
+  recordWrite(bytes: number): void {
+    this.bytesWritten += bytes;
+    this.totalBytesWritten += bytes;
+  }
+
+  snapshot(): StatsMessage {
+    return {
+      type: 'stats',
+      connected: this.connectedAt !== null,
+      connected_at: this.connectedAt,
+      last_read_at: this.lastReadAt,
+      uptime_secs: this.connectedAt !== null ? Math.floor((Date.now() - this.connectedAt) / 1000) : 0,
+      bytes_read: this.bytesRead,
+      bytes_written: this.bytesWritten,
+      lines: this.lines,
+      total_bytes_read: this.totalBytesRead,
+      total_bytes_written: this.totalBytesWritten,
+      total_lines: this.totalLines,
+    };
+  }
+}
 
 export class MudProxy implements DurableObject {
   private ws: WebSocket | null = null;
@@ -36,38 +131,352 @@ export class MudProxy implements DurableObject {
   private tcpWriter: WritableStreamDefaultWriter<Uint8Array> | null = null;
   private remainder: Uint8Array = new Uint8Array(0);
   private abortController: AbortController | null = null;
+  private stats = new ConnectionStats();
+  private statsTimer: ReturnType<typeof setInterval> | null = null;
+  private rateLimiter: TokenBucket | null = null;
+  private commandQueue: string[] = [];
+  private drainTimer: ReturnType<typeof setTimeout> | null = null;
+  private backlogged = false;
+  private allowedTargets: TargetPattern[];
+  /** Set once the client sends `resume`; `<object id>.<secret>`. */
+  private sessionToken: string | null = null;
+  private graceSecs: number;
+  private graceTimer: ReturnType<typeof setTimeout> | null = null;
+  private lastStatus: StatusMessage | null = null;
+  /** Output and statuses sent while detached, replayed on re-attach. */
+  private detached: ((OutputMessage | StatusMessage) & { ts_ms?: number })[] = [];
+  private detachedChars = 0;
+  private droppedChars = 0;
+  /** The status the client last saw before the replayed messages. */
+  private detachedStatus: StatusMessage | null = null;
+  private keepaliveSecs: number;
+  private keepaliveTimer: ReturnType<typeof setInterval> | null = null;
+  private lastHeardAt = 0;
+  /** Only clients known to answer pings are reaped for not answering. */
+  private answersPings = false;
+  private maxMessageBytes: number;
+  private maxCommandChars: number;
+  private maxQueuedCommands: number;
+  private rejectMultiline: boolean;
+  private mudIdleSecs: number;
+  private outputBatchMs: number;
+  private outputBatchChars: number;
+  private pendingOutput = '';
+  private outputTimer: ReturnType<typeof setTimeout> | null = null;
+  private log = new SessionLog(false, false);
+  private mudStaleSecs: number;
+  /** When the outstanding TIMING-MARK probe went out; any byte from the MUD clears it. */
+  private probeSentAt: number | null = null;
+  /** Negotiated by `hello`; version 1 until the client sends one. */
+  private protocolVersion = LEGACY_PROTOCOL_VERSION;
+  /** Features the client listed in `hello`, for anything that changes what it's sent. */
+  private clientFeatures = new Set<string>();
+  /** Address of the attached client, for logs. */
+  private peer = 'unknown';
+  private sessionStartedAt = 0;
+  /** Lifetime byte totals already reported to the hub. */
+  private trafficReported = { read: 0, written: 0 };
+  private config: ProxyConfig;
 
   constructor(
     private state: DurableObjectState,
-    _env: unknown,
-  ) {}
+    private env: Env,
+  ) {
+    const config = loadConfig(env);
+    this.config = config;
+    this.allowedTargets = parseAllowedTargets(config.targets.allowed);
+    this.graceSecs = config.session.grace_secs;
+    this.keepaliveSecs = config.keepalive.interval_secs;
+    this.mudIdleSecs = config.keepalive.mud_idle_secs;
+    this.mudStaleSecs = config.keepalive.mud_stale_secs;
+    this.outputBatchMs = config.output.batch_ms;
+    this.outputBatchChars = config.output.batch_chars;
+    this.maxMessageBytes = config.limits.max_message_bytes;
+    this.maxCommandChars = config.limits.max_command_chars;
+    this.maxQueuedCommands = config.limits.max_queued_commands;
+    this.rejectMultiline = config.limits.multiline_commands === 'reject';
+  }
 
   async fetch(request: Request): Promise<Response> {
     if (request.headers.get('Upgrade') !== 'websocket') {
       return new Response('Expected WebSocket', { status: 426 });
     }
 
+    const resume = new URL(request.url).searchParams.get('resume');
+    if (!this.mayAttach(resume)) {
+      this.report('/handshake/failed', { reason: 'session_not_found' });
+      return new Response('Session not found', { status: 404 });
+    }
+
+    // With `PROXY_AUTH_TOKEN` set, the token comes in the handshake or as the first message
+    const ip = clientIp(request);
+    const authToken = this.config.auth.token;
+    const handshake: ReturnType<typeof handshakeAuth> = authToken ? handshakeAuth(request, authToken) : { ok: true };
+    if (handshake.ok === false) {
+      await recordAuthFailure(this.env, ip, 'handshake');
+      this.report('/handshake/failed', { reason: 'unauthorized' });
+      return new Response('Unauthorized', { status: 401 });
+    }
+
     const pair = new WebSocketPair();
     const [client, server] = [pair[0], pair[1]];
 
     server.accept();
-    this.ws = server;
+    if (handshake.ok) {
+      this.attach(server, resume, ip);
+    } else {
+      this.awaitAuth(server, resume, ip, authToken!);
+    }
 
-    server.addEventListener('message', (event) => {
-      this.handleMessage(event.data as string);
+    const headers = handshake.protocol ? { 'Sec-WebSocket-Protocol': handshake.protocol } : undefined;
+    return new Response(null, { status: 101, webSocket: client, headers });
+  }
+
+  /** Only the session's own token may take over a live session. */
+  private mayAttach(resume: string | null): boolean {
+    const resuming = resume !== null && resume === this.sessionToken;
+    return resuming || !(this.ws || this.tcpSocket || this.sessionToken);
+  }
+
+  /**
+   * Hold a socket until its first message authenticates it, closing it with a
+   * policy violation on a wrong token, any other message, or the deadline.
+   */
+  private awaitAuth(ws: WebSocket, resume: string | null, ip: string, expected: string): void {
+    let settled = false;
+    const reject = (how: string) => {
+      settled = true;
+      clearTimeout(deadline);
+      recordAuthFailure(this.env, ip, how).catch(() => {});
+      this.report('/handshake/failed', { reason: 'unauthorized' });
+      try {
+        ws.close(POLICY_VIOLATION, 'Authentication required');
+      } catch {
+        /* already closed */
+      }
+    };
+    const deadline = setTimeout(() => reject('deadline'), AUTH_DEADLINE_MS);
+
+    ws.addEventListener('message', (event) => {
+      if (settled) return;
+      let msg: ClientMessage | null = null;
+      try {
+        msg = JSON.parse(event.data as string);
+      } catch {
+        /* not JSON — rejected below */
+      }
+      if (msg?.type !== 'auth' || typeof msg.data !== 'string' || !tokenMatches(msg.data, expected)) {
+        reject('message');
+        return;
+      }
+      settled = true;
+      clearTimeout(deadline);
+      if (!this.mayAttach(resume)) {
+        ws.close(POLICY_VIOLATION, 'Session not found');
+        return;
+      }
+      this.attach(ws, resume, ip);
     });
+    ws.addEventListener('close', () => {
+      settled = true;
+      clearTimeout(deadline);
+    });
+  }
 
-    server.addEventListener('close', () => {
-      this.cleanup();
+  /** Make `ws` the session's socket, replacing any previous one, and greet it. */
+  private attach(ws: WebSocket, resume: string | null, ip: string): void {
+    const resuming = resume !== null && resume === this.sessionToken;
+    if (!resuming) this.log = new SessionLog(this.config.logging.session_log, this.config.logging.log_command_content);
+    this.log.event('attached', { ip, resumed: resuming });
+    const previous = this.ws;
+    this.ws = ws;
+    this.peer = ip;
+    this.protocolVersion = LEGACY_PROTOCOL_VERSION;
+    this.clientFeatures = new Set();
+    this.startKeepalive();
+    if (previous) {
+      try {
+        previous.close(1000, 'Session resumed elsewhere');
+      } catch {
+        /* already closed */
+      }
+    }
+    if (this.graceTimer) {
+      clearTimeout(this.graceTimer);
+      this.graceTimer = null;
+    }
+
+    // Events from a socket that has since been replaced are ignored
+    ws.addEventListener('message', (event) => {
+      if (this.ws !== ws) return;
+      this.lastHeardAt = Date.now();
+      const size = typeof event.data === 'string' ? new TextEncoder().encode(event.data).length : event.data.byteLength;
+      if (size > this.maxMessageBytes) {
+        this.send({
+          type: 'error',
+          code: 'message_too_large',
+          detail: `Message of ${size} bytes exceeds the ${this.maxMessageBytes} byte limit`,
+        });
+        ws.close(MESSAGE_TOO_BIG, 'Message too large');
+        this.detach();
+        return;
+      }
+      if (typeof event.data === 'string') this.handleMessage(event.data);
     });
 
-    server.addEventListener('error', () => {
-      this.cleanup();
+    ws.addEventListener('close', () => {
+      if (this.ws === ws) this.detach();
+    });
+
+    ws.addEventListener('error', () => {
+      if (this.ws === ws) this.detach();
     });
 
-    this.send({ type: 'status', connected: false, message: 'Ready to connect' });
+    if (resuming) {
+      this.resync();
+    } else {
+      this.sessionStartedAt = Date.now();
+      this.report('/session/opened', { ip });
+      // A stale token starts over with a fresh resumable session
+      if (resume !== null) this.openSession();
+      // Nothing to auto-reconnect to yet — the client decides when to connect
+      this.send({
+        type: 'status',
+        connected: false,
+        message: resume !== null ? 'Session expired, ready to connect' : 'Ready to connect',
+        reason: 'user_disconnect',
+      });
+    }
+  }
+
+  private startKeepalive(): void {
+    this.stopKeepalive();
+    this.lastHeardAt = Date.now();
+    this.answersPings = false;
+    if (this.keepaliveSecs === 0) return;
+    const interval = this.keepaliveSecs * 1000;
+    this.keepaliveTimer = setInterval(() => {
+      const ws = this.ws;
+      if (!ws) return;
+      const idle = Date.now() - this.lastHeardAt;
+      if (this.answersPings && idle > 2 * interval) {
+        console.log(`reaping client ${this.peer} after ${Math.round(idle / 1000)}s without a pong`);
+        this.log.event('reaped', { idle_secs: Math.round(idle / 1000) });
+        try {
+          ws.close(1001, 'Keepalive timeout');
+        } catch {
+          /* already closed */
+        }
+        this.detach();
+        return;
+      }
+      this.send({ type: 'ping' });
+    }, interval);
+  }
 
-    return new Response(null, { status: 101, webSocket: client });
+  private stopKeepalive(): void {
+    if (this.keepaliveTimer) {
+      clearInterval(this.keepaliveTimer);
+      this.keepaliveTimer = null;
+    }
+  }
+
+  /** Issue (or repeat) this session's resume token. */
+  private openSession(): void {
+    this.sessionToken ??= `${this.state.id.toString()}.${randomHex(16)}`;
+    this.send({ type: 'session', token: this.sessionToken, resumed: false, grace_secs: this.graceSecs });
+  }
+
+  /**
+   * The socket went away. A resumable session keeps its MUD connection and
+   * holds output for the grace period; anything else is torn down now.
+   */
+  private detach(): void {
+    this.ws = null;
+    this.stopKeepalive();
+    this.log.event('detached', { resumable: this.sessionToken !== null });
+    if (this.sessionToken === null) {
+      this.cleanup();
+      return;
+    }
+    this.detachedStatus = this.lastStatus;
+    this.graceTimer = setTimeout(() => this.endSession(), this.graceSecs * 1000);
+  }
+
+  private endSession(): void {
+    this.graceTimer = null;
+    this.sessionToken = null;
+    if (this.droppedChars > 0) this.report('/output/dropped', { chars: String(this.droppedChars) });
+    this.detached = [];
+    this.detachedChars = 0;
+    this.droppedChars = 0;
+    this.detachedStatus = null;
+    this.cleanup();
+  }
+
+  /** Hold a message for a detached client, dropping the oldest output past the cap. */
+  private hold(msg: ServerMessage): void {
+    if (msg.type === 'status') {
+      this.detached.push(msg);
+      return;
+    }
+    if (msg.type !== 'output') return;
+    this.detached.push(msg);
+    this.detachedChars += msg.data.length;
+    while (this.detachedChars > MAX_DETACHED_CHARS) {
+      const first = this.detached[0];
+      if (first.type === 'status') {
+        this.detached.shift();
+        this.detachedStatus = first;
+        continue;
+      }
+      const excess = this.detachedChars - MAX_DETACHED_CHARS;
+      const cut = Math.min(excess, first.data.length);
+      if (cut === first.data.length) {
+        this.detached.shift();
+      } else {
+        this.detached[0] = { ...first, data: first.data.slice(cut) };
+      }
+      this.detachedChars -= cut;
+      this.droppedChars += cut;
+    }
+  }
+
+  /**
+   * Bring a re-attached client up to date: the status it last saw, a marker
+   * if output was dropped, then everything held in order with consecutive
+   * output merged so the final `ga` reflects the prompt state now.
+   */
+  private resync(): void {
+    const held = this.detached;
+    const dropped = this.droppedChars;
+    const status = this.detachedStatus;
+    this.detached = [];
+    this.detachedChars = 0;
+    this.droppedChars = 0;
+    this.detachedStatus = null;
+
+    this.send({ type: 'session', token: this.sessionToken!, resumed: true, grace_secs: this.graceSecs });
+    if (status) this.send(status);
+    if (dropped > 0) {
+      this.report('/output/dropped', { chars: String(dropped) });
+      this.send({ type: 'output', data: `\r\n[${dropped} characters of output dropped while detached]\r\n`, ga: false });
+    }
+    let output: (OutputMessage & { ts_ms?: number }) | null = null;
+    for (const msg of held) {
+      if (msg.type === 'output') {
+        output = output ? { type: 'output', data: output.data + msg.data, ga: msg.ga, ts_ms: output.ts_ms } : msg;
+        continue;
+      }
+      if (output) this.send(output);
+      output = null;
+      this.send(msg);
+    }
+    if (output) this.send(output);
+    this.send(this.stats.snapshot());
+    if (this.backlogged) {
+      this.send({ type: 'command_backlog', depth: this.commandQueue.length, threshold: BACKLOG_WARN_THRESHOLD });
+    }
   }
 
   private handleMessage(raw: string): void {
@@ -77,35 +486,103 @@ export class MudProxy implements DurableObject {
     } catch {
       return;
     }
+    if (!msg || typeof msg !== 'object') return;
+    this.log.clientMessage(String(msg.type), msg.data);
 
     switch (msg.type) {
       case 'command':
-        if (msg.data !== undefined) {
-          this.sendToMud(msg.data);
+        if (typeof msg.data === 'string') this.queueCommand(msg.data);
+        break;
+      case 'rate_limit':
+        if (typeof msg.per_second === 'number' && msg.per_second > 0) {
+          const burst = Math.max(1, Math.floor(msg.burst ?? Math.ceil(msg.per_second)));
+          this.rateLimiter = new TokenBucket(msg.per_second, burst);
+        } else {
+          this.rateLimiter = null;
         }
+        this.drainCommands();
         break;
-      case 'reconnect':
-        this.connectToMud();
+      case 'reconnect': {
+        const target = this.resolveTarget(msg);
+        if (target) {
+          this.connectToMud(target);
+        } else {
+          this.report('/mud/failed', { reason: 'target_not_permitted' });
+          const requested = `${msg.host ?? this.allowedTargets[0].host}:${msg.port ?? this.allowedTargets[0].port ?? '*'}`;
+          this.send({
+            type: 'status',
+            connected: false,
+            message: `Target not permitted: ${requested}`,
+            reason: 'target_not_permitted',
+            target: requested,
+          });
+        }
         break;
+      }
       case 'disconnect':
         this.disconnectMud();
-        this.send({ type: 'status', connected: false, message: 'Disconnected' });
+        this.send({
+          type: 'status',
+          connected: false,
+          message: 'Disconnected',
+          reason: 'user_disconnect',
+        });
         break;
       case 'ping':
         this.send({ type: 'pong' });
         break;
+      case 'pong':
+        this.answersPings = true;
+        break;
+      case 'resume':
+        this.openSession();
+        break;
+      case 'hello':
+        this.protocolVersion = negotiateVersion(msg.version);
+        this.clientFeatures = new Set(Array.isArray(msg.features) ? msg.features.map(String) : []);
+        this.send({ type: 'hello', version: this.protocolVersion, features: PROXY_FEATURES });
+        break;
+      case 'auth':
+        // Already authenticated (or auth is off) — nothing to do
+        break;
+      default: {
+        console.debug(`unknown client message type from ${this.peer}: ${String(msg.type)}`);
+        const error = unknownTypeError(this.protocolVersion, msg.type);
+        if (error) this.send(error);
+        break;
+      }
     }
   }
 
-  private async connectToMud(): Promise<void> {
+  /**
+   * The target a `reconnect` asks for, or null if it isn't allowed. Missing
+   * fields come from the first allowlist entry; a port is required when that
+   * entry is a wildcard.
+   */
+  private resolveTarget(msg: ClientMessage): MudTarget | null {
+    const fallback = this.allowedTargets[0];
+    const host = (msg.host ?? fallback.host).trim().toLowerCase();
+    const port = msg.port ?? fallback.port;
+    if (!host || port === null || !Number.isInteger(port) || port <= 0 || port >= 65536) return null;
+    const allowed = this.allowedTargets.some((p) => p.host === host && (p.port === null || p.port === port));
+    return allowed ? { host, port } : null;
+  }
+
+  private async connectToMud(target: MudTarget): Promise<void> {
     await this.disconnectMud();
 
-    const addr = `${MUD_HOST}:${MUD_PORT}`;
-    this.send({ type: 'status', connected: false, message: `Connecting to ${addr}...` });
+    const addr = formatTarget(target);
+    this.send({
+      type: 'status',
+      connected: false,
+      message: `Connecting to ${addr}...`,
+      reason: 'connecting',
+      target: addr,
+    });
 
     for (let attempt = 1; attempt <= MAX_RETRIES; attempt++) {
       try {
-        const socket = connect({ hostname: MUD_HOST, port: MUD_PORT });
+        const socket = connect({ hostname: target.host, port: target.port });
 
         await Promise.race([
           socket.opened,
@@ -118,23 +595,40 @@ export class MudProxy implements DurableObject {
         this.tcpWriter = socket.writable.getWriter();
         this.remainder = new Uint8Array(0);
 
-        this.send({ type: 'status', connected: true, message: `Connected to ${addr}` });
+        this.send({
+          type: 'status',
+          connected: true,
+          message: `Connected to ${addr}`,
+          reason: 'connected',
+          target: addr,
+        });
+        this.startStats();
         this.startTcpReadLoop();
         return;
       } catch (e) {
         const errMsg = e instanceof Error ? e.message : 'Unknown error';
+        const reason: StatusReason = errMsg === 'Connection timed out' ? 'timeout' : 'connect_refused';
         if (attempt < MAX_RETRIES) {
           this.send({
             type: 'status',
             connected: false,
             message: `Connection failed (${errMsg}), retrying (${attempt}/${MAX_RETRIES})...`,
+            reason,
+            detail: errMsg,
+            attempt,
+            target: addr,
           });
           await new Promise((r) => setTimeout(r, RETRY_DELAY_MS));
         } else {
+          this.report('/mud/failed', { reason });
           this.send({
             type: 'status',
             connected: false,
-            message: `Failed to connect after ${MAX_RETRIES} attempts: ${errMsg}`,
+            message: `Failed to connect to ${addr} after ${MAX_RETRIES} attempts: ${errMsg}`,
+            reason,
+            detail: errMsg,
+            attempt,
+            target: addr,
           });
         }
       }
@@ -154,9 +648,15 @@ export class MudProxy implements DurableObject {
           const { done, value } = await reader.read();
 
           if (done || !value) {
-            this.send({ type: 'status', connected: false, message: 'Disconnected' });
+            this.send({
+              type: 'status',
+              connected: false,
+              message: 'Disconnected',
+              reason: 'server_closed',
+            });
             this.tcpSocket = null;
             this.tcpWriter = null;
+            this.stopStats();
             break;
           }
 
@@ -173,11 +673,14 @@ export class MudProxy implements DurableObject {
 
           const processed = processOutput(input);
           this.remainder = processed.remainder;
+          this.stats.recordRead(value.length, processed.display.split('\n').length - 1);
+          this.probeSentAt = null;
 
           // Send IAC responses back to MUD
           for (const response of processed.responses) {
             try {
               await this.tcpWriter?.write(response);
+              this.stats.recordWrite(response.length);
             } catch {
               break;
             }
@@ -185,16 +688,18 @@ export class MudProxy implements DurableObject {
 
           // Forward display text to browser
           if (processed.display.length > 0 || processed.ga) {
-            this.send({
-              type: 'output',
-              data: processed.display,
-              ga: processed.ga,
-            });
+            this.queueOutput(processed.display, processed.ga);
           }
         }
-      } catch {
+      } catch (e) {
         if (!signal.aborted) {
-          this.send({ type: 'status', connected: false, message: 'Disconnected' });
+          this.send({
+            type: 'status',
+            connected: false,
+            message: 'Disconnected',
+            reason: 'read_error',
+            detail: e instanceof Error ? e.message : undefined,
+          });
         }
       } finally {
         try {
@@ -208,21 +713,149 @@ export class MudProxy implements DurableObject {
     readLoop();
   }
 
+  /**
+   * Queue a command as single lines, with `MULTILINE_COMMANDS=reject` refusing
+   * multi-line ones; see `splitCommand`. Commands go out as UTF-8, which never
+   * contains an IAC byte.
+   */
+  private queueCommand(data: string): void {
+    const { lines, error } = splitCommand(data, this.commandQueue.length, {
+      maxChars: this.maxCommandChars,
+      maxQueued: this.maxQueuedCommands,
+      rejectMultiline: this.rejectMultiline,
+    });
+    this.commandQueue.push(...lines);
+    if (error) this.send(error);
+    this.drainCommands();
+  }
+
+  /** Write queued commands as fast as the rate limiter allows; the rest wait, never dropped. */
+  private drainCommands(): void {
+    if (this.drainTimer) return;
+    while (this.commandQueue.length > 0) {
+      if (this.rateLimiter && !this.rateLimiter.tryTake()) {
+        const wait = this.rateLimiter.msUntilToken();
+        this.drainTimer = setTimeout(() => {
+          this.drainTimer = null;
+          this.drainCommands();
+        }, wait);
+        break;
+      }
+      this.sendToMud(this.commandQueue.shift()!);
+    }
+    this.reportBacklog();
+  }
+
+  private reportBacklog(): void {
+    const depth = this.commandQueue.length;
+    if (depth > BACKLOG_WARN_THRESHOLD && !this.backlogged) {
+      this.backlogged = true;
+      this.send({ type: 'command_backlog', depth, threshold: BACKLOG_WARN_THRESHOLD });
+    } else if (depth === 0 && this.backlogged) {
+      this.backlogged = false;
+      this.send({ type: 'command_backlog', depth, threshold: BACKLOG_WARN_THRESHOLD });
+    }
+  }
+
   private async sendToMud(command: string): Promise<void> {
     if (!this.tcpWriter) return;
     try {
       const encoder = new TextEncoder();
-      await this.tcpWriter.write(encoder.encode(`${command}\r\n`));
-    } catch {
-      this.send({ type: 'status', connected: false, message: 'Disconnected' });
+      const data = encoder.encode(`${command}\r\n`);
+      await this.tcpWriter.write(data);
+      this.stats.recordWrite(data.length);
+    } catch (e) {
+      this.send({
+        type: 'status',
+        connected: false,
+        message: 'Disconnected',
+        reason: 'write_error',
+        detail: e instanceof Error ? e.message : undefined,
+      });
       this.tcpSocket = null;
       this.tcpWriter = null;
+      this.stopStats();
+    }
+  }
+
+  /** Tell the hub about this session, for `/healthz` and `/metrics`; best effort. */
+  private report(path: string, params: Record<string, string> = {}): void {
+    hubRequest(this.env, path, { id: this.state.id.toString(), ...params }).catch(() => {});
+  }
+
+  /** Bytes moved since the last report, so the hub's totals stay current without a report per read. */
+  private reportTraffic(): void {
+    const read = this.stats.totalBytesRead - this.trafficReported.read;
+    const written = this.stats.totalBytesWritten - this.trafficReported.written;
+    if (read === 0 && written === 0) return;
+    this.trafficReported = { read: this.stats.totalBytesRead, written: this.stats.totalBytesWritten };
+    this.report('/traffic', { read: String(read), written: String(written) });
+  }
+
+  private startStats(): void {
+    this.report('/mud/connected');
+    this.stats.start();
+    if (this.statsTimer) clearInterval(this.statsTimer);
+    this.probeSentAt = null;
+    this.statsTimer = setInterval(() => {
+      this.send(this.stats.snapshot());
+      this.reportTraffic();
+      this.checkMudIdle();
+    }, STATS_INTERVAL_MS);
+  }
+
+  /**
+   * A half-open socket reads nothing forever, which looks the same as a quiet
+   * MUD. After `MUD_IDLE_SECS` of silence, ask for a TIMING-MARK, which a live
+   * server always answers; if nothing at all arrives within `MUD_STALE_SECS`,
+   * drop the connection as stalled so the client can reconnect.
+   */
+  private checkMudIdle(): void {
+    if (!this.tcpWriter || this.mudIdleSecs === 0) return;
+    const now = Date.now();
+    if (this.probeSentAt !== null) {
+      if (now - this.probeSentAt >= this.mudStaleSecs * 1000) this.declareStalled(now);
+      return;
+    }
+    const lastHeard = this.stats.lastReadAt ?? this.stats.connectedAt ?? now;
+    if (now - lastHeard < this.mudIdleSecs * 1000) return;
+    this.probeSentAt = now;
+    this.tcpWriter
+      .write(IAC_DO_TIMING_MARK)
+      .then(() => this.stats.recordWrite(IAC_DO_TIMING_MARK.length))
+      .catch(() => {});
+  }
+
+  private async declareStalled(now: number): Promise<void> {
+    const silent = Math.round((now - (this.stats.lastReadAt ?? this.stats.connectedAt ?? now)) / 1000);
+    console.warn(`MUD connection for ${this.peer} stalled: nothing read for ${silent}s`);
+    this.report('/mud/failed', { reason: 'stalled' });
+    await this.disconnectMud();
+    this.send({
+      type: 'status',
+      connected: false,
+      message: 'Connection stalled: the MUD stopped responding',
+      reason: 'stalled',
+      detail: `Nothing received for ${silent}s, including a reply to a TIMING-MARK probe`,
+    });
+  }
+
+  private stopStats(): void {
+    if (this.statsTimer) {
+      clearInterval(this.statsTimer);
+      this.statsTimer = null;
     }
+    this.report('/mud/disconnected');
+    this.reportTraffic();
+    this.stats.stop();
+    this.send(this.stats.snapshot());
   }
 
   private async disconnectMud(): Promise<void> {
+    this.clearCommandQueue();
     this.abortController?.abort();
     this.abortController = null;
+    if (this.statsTimer) this.stopStats();
 
     try {
       await this.tcpWriter?.close();
@@ -241,9 +874,34 @@ export class MudProxy implements DurableObject {
     this.remainder = new Uint8Array(0);
   }
 
+  private clearCommandQueue(): void {
+    if (this.drainTimer) {
+      clearTimeout(this.drainTimer);
+      this.drainTimer = null;
+    }
+    this.commandQueue = [];
+    this.reportBacklog();
+  }
+
   private cleanup(): void {
+    this.log.event('closed', {
+      duration_secs: Math.round((Date.now() - this.sessionStartedAt) / 1000),
+      bytes_read: this.stats.totalBytesRead,
+      bytes_written: this.stats.totalBytesWritten,
+      lines: this.stats.totalLines,
+    });
+    if (this.outputTimer) clearTimeout(this.outputTimer);
+    this.outputTimer = null;
+    this.pendingOutput = '';
+    this.reportTraffic();
+    this.report('/session/closed', { secs: String(Math.round((Date.now() - this.sessionStartedAt) / 1000)) });
+    this.clearCommandQueue();
     this.abortController?.abort();
     this.abortController = null;
+    if (this.statsTimer) {
+      clearInterval(this.statsTimer);
+      this.statsTimer = null;
+    }
     try {
       this.tcpWriter?.close();
     } catch {
@@ -257,11 +915,58 @@ export class MudProxy implements DurableObject {
     this.tcpWriter = null;
     this.tcpSocket = null;
     this.ws = null;
+    this.stopKeepalive();
+  }
+
+  /**
+   * Batch output from consecutive reads into one message, sent on GA, when
+   * it passes `OUTPUT_BATCH_CHARS`, or `OUTPUT_BATCH_MS` after the first
+   * read of the batch, so a flood of small reads isn't a flood of messages.
+   */
+  private queueOutput(data: string, ga: boolean): void {
+    this.pendingOutput += data;
+    if (ga || this.outputBatchMs === 0 || this.pendingOutput.length >= this.outputBatchChars) {
+      this.flushOutput(ga);
+      return;
+    }
+    this.outputTimer ??= setTimeout(() => this.flushOutput(false), this.outputBatchMs);
+  }
+
+  private flushOutput(ga: boolean): void {
+    if (this.outputTimer) {
+      clearTimeout(this.outputTimer);
+      this.outputTimer = null;
+    }
+    if (!this.pendingOutput && !ga) return;
+    const data = this.pendingOutput;
+    this.pendingOutput = '';
+    this.send({ type: 'output', data, ga });
   }
 
   private send(msg: ServerMessage): void {
+    if (msg.ts_ms === undefined) msg = { ...msg, ts_ms: Date.now() };
+    // Anything else goes out after the output that came before it
+    if (msg.type !== 'output' && this.pendingOutput) this.flushOutput(false);
+    if (msg.type === 'status') {
+      this.lastStatus = msg;
+      this.log.event('status', {
+        reason: msg.reason,
+        message: msg.message,
+        detail: msg.detail,
+        attempt: msg.attempt,
+        target: msg.target,
+      });
+    }
+    if (!this.ws) {
+      if (this.sessionToken !== null) this.hold(msg);
+      return;
+    }
     try {
-      this.ws?.send(JSON.stringify(msg));
+      if (msg.type === 'output' && this.clientFeatures.has('compact_output')) {
+        this.ws.send(encodeOutputFrame(msg));
+      } else {
+        this.ws.send(JSON.stringify(msg));
+      }
     } catch {
       /* WebSocket may already be closed */
     }
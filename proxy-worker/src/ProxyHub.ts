@@ -0,0 +1,248 @@
+import { AUTH_DEADLINE_MS } from './auth';
+import { loadConfig } from './config';
+import type { Env } from './index';
+import { Histogram, labelled, metric } from './metrics';
+import { TokenBucket } from './TokenBucket';
+
+/** Failed auth attempts an IP gets within the window before it's refused outright. */
+const AUTH_FAILURE_LIMIT = 5;
+const AUTH_FAILURE_WINDOW_MS = 60_000;
+/** Session duration histogram bounds, in seconds. */
+const SESSION_DURATION_BUCKETS = [10, 60, 300, 900, 1800, 3600, 7200, 14400, 28800];
+/** WebSocket upgrades per IP: sustained per second, and burst. */
+const HANDSHAKE_RATE = 0.5;
+const HANDSHAKE_BURST = 10;
+/** How often idle per-IP entries are swept. */
+const SWEEP_INTERVAL_MS = 60_000;
+/**
+ * How long a slot `/admit` reserves stays held without `/session/opened` —
+ * long enough for the socket to reach its session object and authenticate.
+ */
+const RESERVATION_MS = AUTH_DEADLINE_MS + 10_000;
+
+type Limit = 'max_sessions' | 'max_sessions_per_ip' | 'handshake_rate';
+
+/**
+ * Proxy-wide state every session shares. There's a single instance (named
+ * `hub`); sessions talk to it through `hubRequest`.
+ */
+export class ProxyHub implements DurableObject {
+  /** Failure timestamps per client IP, pruned as they age out of the window. */
+  private authFailures = new Map<string, number[]>();
+  private startedAt = Date.now();
+  /** Client IP by session object id; keyed by id so a repeated report doesn't count twice. */
+  private sessions = new Map<string, string>();
+  /** Slots `/admit` handed out that no session has opened yet, by session object id. */
+  private reservations = new Map<string, { ip: string; expiresAt: number }>();
+  private mudConnections = new Set<string>();
+  private totalSessions = 0;
+  private bytesFromMud = 0;
+  private bytesToMud = 0;
+  /** Output characters dropped from detached sessions' buffers. */
+  private outputDropped = 0;
+  /** Keyed by `StatusReason` (and handshake failure kind) — fixed sets, so bounded. */
+  private connectFailures = new Map<string, number>();
+  private handshakeFailures = new Map<string, number>();
+  private sessionDurations = new Histogram(SESSION_DURATION_BUCKETS);
+  private handshakeBuckets = new Map<string, TokenBucket>();
+  private rejections = new Map<string, number>();
+  private sweptAt = Date.now();
+  private maxSessions: number;
+  private maxSessionsPerIp: number;
+
+  constructor(
+    private state: DurableObjectState,
+    env: Env,
+  ) {
+    const { limits } = loadConfig(env);
+    this.maxSessions = limits.max_sessions;
+    this.maxSessionsPerIp = limits.max_sessions_per_ip;
+  }
+
+  async fetch(request: Request): Promise<Response> {
+    const url = new URL(request.url);
+    const ip = url.searchParams.get('ip') ?? 'unknown';
+    const id = url.searchParams.get('id') ?? '';
+
+    switch (url.pathname) {
+      case '/health':
+        return Response.json({
+          uptime_secs: Math.floor((Date.now() - this.startedAt) / 1000),
+          active_sessions: this.sessions.size,
+          active_mud_connections: this.mudConnections.size,
+          total_sessions: this.totalSessions,
+          max_sessions: this.maxSessions,
+          max_sessions_per_ip: this.maxSessionsPerIp,
+          rejections: Object.fromEntries(this.rejections),
+        });
+      case '/admit': {
+        const limit = this.admit(id, ip, url.searchParams.get('resume') === '1');
+        if (limit) {
+          bump(this.rejections, limit);
+          console.warn(`refused ${ip}: ${limit}`);
+        }
+        return Response.json({ limit });
+      }
+      case '/session/opened':
+        if (!this.sessions.has(id)) this.totalSessions++;
+        this.sessions.set(id, ip);
+        this.reservations.delete(id);
+        return Response.json({});
+      case '/metrics':
+        return new Response(this.renderMetrics(), {
+          headers: { 'Content-Type': 'text/plain; version=0.0.4; charset=utf-8' },
+        });
+      case '/session/closed':
+        if (this.sessions.has(id)) this.sessionDurations.observe(Number(url.searchParams.get('secs') ?? 0));
+        this.sessions.delete(id);
+        this.reservations.delete(id);
+        this.mudConnections.delete(id);
+        return Response.json({});
+      case '/mud/connected':
+        this.mudConnections.add(id);
+        return Response.json({});
+      case '/mud/disconnected':
+        this.mudConnections.delete(id);
+        return Response.json({});
+      case '/mud/failed':
+        bump(this.connectFailures, url.searchParams.get('reason') ?? 'unknown');
+        return Response.json({});
+      case '/handshake/failed':
+        // A session object turning the socket away gives its slot back
+        if (!this.sessions.has(id)) this.reservations.delete(id);
+        bump(this.handshakeFailures, url.searchParams.get('reason') ?? 'unknown');
+        return Response.json({});
+      case '/output/dropped':
+        this.outputDropped += Number(url.searchParams.get('chars') ?? 0);
+        return Response.json({});
+      case '/traffic':
+        this.bytesFromMud += Number(url.searchParams.get('read') ?? 0);
+        this.bytesToMud += Number(url.searchParams.get('written') ?? 0);
+        return Response.json({});
+      case '/auth/allowed':
+        return Response.json({ allowed: this.recentFailures(ip).length < AUTH_FAILURE_LIMIT });
+      case '/auth/failed': {
+        const failures = this.recentFailures(ip);
+        failures.push(Date.now());
+        this.authFailures.set(ip, failures);
+        return Response.json({ allowed: failures.length < AUTH_FAILURE_LIMIT });
+      }
+    }
+
+    return new Response('Not Found', { status: 404 });
+  }
+
+  /**
+   * The limit a new WebSocket from `ip` would break, or null to let it in
+   * and hold a slot for session object `id` until it opens or the
+   * reservation lapses. Slots count as taken from here on, so upgrades
+   * racing each other can't all squeeze under a limit before a session
+   * reports in. Re-attaching to an open session doesn't add one, so only
+   * the rate applies.
+   */
+  private admit(id: string, ip: string, resuming: boolean): Limit | null {
+    this.sweep();
+    const now = Date.now();
+    for (const [reserved, { expiresAt }] of this.reservations) {
+      if (expiresAt <= now) this.reservations.delete(reserved);
+    }
+    let bucket = this.handshakeBuckets.get(ip);
+    if (!bucket) {
+      bucket = new TokenBucket(HANDSHAKE_RATE, HANDSHAKE_BURST);
+      this.handshakeBuckets.set(ip, bucket);
+    }
+    if (!bucket.tryTake()) return 'handshake_rate';
+    if (resuming && this.sessions.has(id)) return null;
+    const reserved = [...this.reservations].filter(([slot]) => slot !== id).map(([, slot]) => slot.ip);
+    const taken = [...this.sessions.values(), ...reserved];
+    if (taken.length >= this.maxSessions) return 'max_sessions';
+    if (taken.filter((takenIp) => takenIp === ip).length >= this.maxSessionsPerIp) return 'max_sessions_per_ip';
+    this.reservations.set(id, { ip, expiresAt: now + RESERVATION_MS });
+    return null;
+  }
+
+  /** Forget IPs whose handshake bucket has refilled and whose auth failures have aged out. */
+  private sweep(): void {
+    if (Date.now() - this.sweptAt < SWEEP_INTERVAL_MS) return;
+    this.sweptAt = Date.now();
+    for (const [ip, bucket] of this.handshakeBuckets) {
+      if (bucket.full()) this.handshakeBuckets.delete(ip);
+    }
+    for (const ip of [...this.authFailures.keys()]) this.recentFailures(ip);
+  }
+
+  private renderMetrics(): string {
+    const families = [
+      metric('dartforge_proxy_sessions', 'gauge', 'Sessions currently open.', [[{}, this.sessions.size]]),
+      metric('dartforge_proxy_mud_connections', 'gauge', 'MUD connections currently open.', [
+        [{}, this.mudConnections.size],
+      ]),
+      metric('dartforge_proxy_session_limit', 'gauge', 'Most sessions allowed at once.', [[{}, this.maxSessions]]),
+      metric(
+        'dartforge_proxy_rejections_total',
+        'counter',
+        'WebSocket upgrades refused by a connection limit, by limit.',
+        labelled('limit', this.rejections),
+      ),
+      metric('dartforge_proxy_tracked_ips', 'gauge', 'Client IPs with rate limiter state.', [
+        [{}, this.handshakeBuckets.size],
+      ]),
+      metric('dartforge_proxy_sessions_total', 'counter', 'Sessions opened since the hub started.', [
+        [{}, this.totalSessions],
+      ]),
+      metric('dartforge_proxy_bytes_total', 'counter', 'Bytes proxied, by direction.', [
+        [{ direction: 'from_mud' }, this.bytesFromMud],
+        [{ direction: 'to_mud' }, this.bytesToMud],
+      ]),
+      metric(
+        'dartforge_proxy_output_dropped_chars_total',
+        'counter',
+        'Output characters dropped because a detached session held more than its buffer.',
+        [[{}, this.outputDropped]],
+      ),
+      metric(
+        'dartforge_proxy_connect_failures_total',
+        'counter',
+        'MUD connections that failed, by reason.',
+        labelled('reason', this.connectFailures),
+      ),
+      metric(
+        'dartforge_proxy_handshake_failures_total',
+        'counter',
+        'WebSocket upgrades refused, by reason.',
+        labelled('reason', this.handshakeFailures),
+      ),
+      this.sessionDurations.render('dartforge_proxy_session_duration_seconds', 'How long closed sessions lasted.'),
+    ];
+    return `${families.join('\n')}\n`;
+  }
+
+  private recentFailures(ip: string): number[] {
+    const cutoff = Date.now() - AUTH_FAILURE_WINDOW_MS;
+    const failures = (this.authFailures.get(ip) ?? []).filter((at) => at > cutoff);
+    if (failures.length > 0) {
+      this.authFailures.set(ip, failures);
+    } else {
+      this.authFailures.delete(ip);
+    }
+    return failures;
+  }
+}
+
+function bump(counts: Map<string, number>, key: string): void {
+  counts.set(key, (counts.get(key) ?? 0) + 1);
+}
+
+/** Call the hub; `path` is one of the routes in `ProxyHub.fetch`. */
+export function hubFetch(env: Env, path: string, params: Record<string, string> = {}): Promise<Response> {
+  const stub = env.PROXY_HUB.get(env.PROXY_HUB.idFromName('hub'));
+  const url = new URL(path, 'https://hub');
+  for (const [key, value] of Object.entries(params)) url.searchParams.set(key, value);
+  return stub.fetch(url.toString(), { method: 'POST' });
+}
+
+/** `hubFetch` for the JSON routes. */
+export async function hubRequest<T = unknown>(env: Env, path: string, params: Record<string, string> = {}): Promise<T> {
+  const response = await hubFetch(env, path, params);
+  return response.json<T>();
+}
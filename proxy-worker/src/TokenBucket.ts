@@ -0,0 +1,33 @@
+/** Token bucket matching the desktop limiter: `rate` per second, bursts of `capacity`. */
+export class TokenBucket {
+  private tokens: number;
+  private refilledAt = Date.now();
+
+  constructor(
+    private rate: number,
+    private capacity: number,
+  ) {
+    this.tokens = capacity;
+  }
+
+  tryTake(): boolean {
+    const now = Date.now();
+    this.tokens = Math.min(this.capacity, this.tokens + ((now - this.refilledAt) / 1000) * this.rate);
+    this.refilledAt = now;
+    if (this.tokens >= 1) {
+      this.tokens -= 1;
+      return true;
+    }
+    return false;
+  }
+
+  /** Refilled to capacity, i.e. unused for a while. */
+  full(): boolean {
+    return this.tokens + ((Date.now() - this.refilledAt) / 1000) * this.rate >= this.capacity;
+  }
+
+  /** Milliseconds until the next whole token. */
+  msUntilToken(): number {
+    return Math.max(0, ((1 - this.tokens) / this.rate) * 1000);
+  }
+}
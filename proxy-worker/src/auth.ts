@@ -0,0 +1,50 @@
+import type { Env } from './index';
+import { hubRequest } from './ProxyHub';
+
+/** How long a socket has to send `{type: "auth"}` when the handshake didn't carry the token. */
+export const AUTH_DEADLINE_MS = 5_000;
+/** WebSocket close code for failed or missing auth. */
+export const POLICY_VIOLATION = 1008;
+
+/** The client address Cloudflare saw, for logs and rate limiting. */
+export function clientIp(request: Request): string {
+  return request.headers.get('CF-Connecting-IP') ?? 'unknown';
+}
+
+/** Compare without returning early, so timing doesn't leak how much of a guess was right. */
+export function tokenMatches(candidate: string, expected: string): boolean {
+  const a = new TextEncoder().encode(candidate);
+  const b = new TextEncoder().encode(expected);
+  let diff = a.length ^ b.length;
+  for (let i = 0; i < b.length; i++) diff |= (a[i] ?? 0) ^ b[i];
+  return diff === 0;
+}
+
+/**
+ * Check a token offered during the handshake, as a `token` query parameter or
+ * a `Sec-WebSocket-Protocol` entry. `ok` is null when none was offered; a
+ * matching protocol entry comes back in `protocol` so it can be echoed, as
+ * browsers require.
+ */
+export function handshakeAuth(request: Request, expected: string): { ok: boolean | null; protocol?: string } {
+  const offered = (request.headers.get('Sec-WebSocket-Protocol') ?? '')
+    .split(',')
+    .map((entry) => entry.trim())
+    .filter(Boolean);
+  const protocol = offered.find((entry) => tokenMatches(entry, expected));
+  if (protocol) return { ok: true, protocol };
+  const query = new URL(request.url).searchParams.get('token');
+  if (query !== null) return { ok: tokenMatches(query, expected) };
+  return { ok: offered.length > 0 ? false : null };
+}
+
+/** Whether an IP is still allowed to try, given its recent failures. */
+export async function authAllowed(env: Env, ip: string): Promise<boolean> {
+  const { allowed } = await hubRequest<{ allowed: boolean }>(env, '/auth/allowed', { ip });
+  return allowed;
+}
+
+export async function recordAuthFailure(env: Env, ip: string, how: string): Promise<void> {
+  const { allowed } = await hubRequest<{ allowed: boolean }>(env, '/auth/failed', { ip });
+  console.warn(`auth failed (${how}) from ${ip}${allowed ? '' : ', now rate limited'}`);
+}
@@ -0,0 +1,48 @@
+import type { ErrorMessage } from './protocol';
+
+export interface CommandLimits {
+  maxChars: number;
+  maxQueued: number;
+  rejectMultiline: boolean;
+}
+
+/** What to do with one `command` message. */
+export interface SplitCommand {
+  /** Lines to queue, in order. */
+  lines: string[];
+  /** Why some or all of the command was refused. */
+  error: ErrorMessage | null;
+}
+
+/**
+ * A command as the single lines to queue behind `queued` others. Line breaks
+ * split it (or, with `rejectMultiline`, refuse it) so nothing reaches the MUD
+ * mid-line; other control characters are dropped. Lines past a full queue
+ * are refused.
+ */
+export function splitCommand(data: string, queued: number, limits: CommandLimits): SplitCommand {
+  if (data.length > limits.maxChars) {
+    return {
+      lines: [],
+      error: {
+        type: 'error',
+        code: 'command_too_long',
+        detail: `Command of ${data.length} characters exceeds the ${limits.maxChars} character limit`,
+      },
+    };
+  }
+  const lines = data.split(/\r\n|\r|\n/).map((line) => line.replace(/[\x00-\x08\x0b-\x1f\x7f]/g, ''));
+  if (lines.length > 1 && limits.rejectMultiline) {
+    return { lines: [], error: { type: 'error', code: 'multiline_command', detail: 'Commands must be a single line' } };
+  }
+  const room = Math.max(0, limits.maxQueued - queued);
+  if (lines.length <= room) return { lines, error: null };
+  return {
+    lines: lines.slice(0, room),
+    error: {
+      type: 'error',
+      code: 'command_queue_full',
+      detail: `${queued + room} commands already waiting; dropped: ${lines[room].slice(0, 80)}`,
+    },
+  };
+}
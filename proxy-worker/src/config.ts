@@ -0,0 +1,237 @@
+import type { Env } from './index';
+
+/**
+ * Everything the proxy can be configured with. Settings come from the
+ * optional `PROXY_CONFIG` var — a JSON object (or a TOML table in
+ * `wrangler.toml`) with these sections — and each can be overridden by the
+ * env var named next to it, which is how they were set before there was a
+ * config object. Anything left unset takes the default shown.
+ *
+ * Config is read when an isolate first needs it; changing it means
+ * redeploying, which picks the new values up everywhere.
+ */
+export interface ProxyConfig {
+  auth: {
+    /** `PROXY_AUTH_TOKEN`; null lets anyone connect. */
+    token: string | null;
+  };
+  origins: {
+    /**
+     * `ALLOWED_ORIGINS` (comma-separated): browser origins allowed to open
+     * `/ws`, exactly or as `https://*.example.com` for any subdomain.
+     * Defaults to the hosted client and local dev servers.
+     */
+    allowed: string[];
+    /**
+     * `ALLOW_MISSING_ORIGIN`, true: accept upgrades without an `Origin`, i.e.
+     * non-browser clients. Browsers always send one, so this doesn't let other
+     * sites in.
+     */
+    allow_missing: boolean;
+  };
+  targets: {
+    /** `ALLOWED_TARGETS` (comma-separated); `host:port` or `host:*`. Empty allows dartmud.com:2525 only. */
+    allowed: string[];
+  };
+  session: {
+    /** `SESSION_GRACE_SECS`, 300: how long a resumable session outlives its WebSocket. */
+    grace_secs: number;
+  };
+  keepalive: {
+    /** `KEEPALIVE_SECS`, 30: between pings to the client; 0 turns them off. */
+    interval_secs: number;
+    /** `MUD_IDLE_SECS`, 120: MUD silence before a TIMING-MARK probe; 0 turns probing off. */
+    mud_idle_secs: number;
+    /** `MUD_STALE_SECS`, 30: how long an unanswered probe waits before the connection is dropped. */
+    mud_stale_secs: number;
+  };
+  limits: {
+    /** `MAX_SESSIONS`, 100 */
+    max_sessions: number;
+    /** `MAX_SESSIONS_PER_IP`, 5 */
+    max_sessions_per_ip: number;
+    /** `MAX_MESSAGE_BYTES`, 64 KiB: bigger client messages close the socket. */
+    max_message_bytes: number;
+    /** `MAX_COMMAND_CHARS`, 4096 */
+    max_command_chars: number;
+    /** `MAX_QUEUED_COMMANDS`, 200: commands waiting on the rate limiter before new ones are dropped. */
+    max_queued_commands: number;
+    /** `MULTILINE_COMMANDS`, `split`: send each line separately, or `reject` the command. */
+    multiline_commands: 'split' | 'reject';
+  };
+  output: {
+    /** `OUTPUT_BATCH_MS`, 16: how long output waits to be batched; 0 sends every read at once. */
+    batch_ms: number;
+    /** `OUTPUT_BATCH_CHARS`, 16384: batched output is sent at once past this. */
+    batch_chars: number;
+  };
+  logging: {
+    /** `SESSION_LOG`: per-session event log (see `sessionLog.ts`). */
+    session_log: boolean;
+    /** `LOG_COMMAND_CONTENT`: include command text in it. */
+    log_command_content: boolean;
+  };
+}
+
+const DEFAULT_ORIGINS = [
+  'https://dartforge.netlify.app',
+  'http://localhost:5173',
+  'http://localhost:3000',
+  'http://localhost:1420',
+];
+
+type Section = Record<string, unknown>;
+
+/** Reads one setting at a time, env var first, collecting every problem rather than stopping at the first. */
+class Reader {
+  errors: string[] = [];
+
+  constructor(private file: Record<string, Section>) {}
+
+  private raw(path: string, envName: string, envValue: string | undefined): { value: unknown; source: string } {
+    if (envValue !== undefined && envValue !== '') return { value: envValue, source: envName };
+    const [section, key] = path.split('.');
+    return { value: this.file[section]?.[key], source: `PROXY_CONFIG ${path}` };
+  }
+
+  private fail(source: string, expected: string, value: unknown): void {
+    this.errors.push(`${source}: expected ${expected}, got ${JSON.stringify(value)}`);
+  }
+
+  /** A non-negative number; 0 usually means off. */
+  duration(path: string, envName: string, envValue: string | undefined, fallback: number): number {
+    const { value, source } = this.raw(path, envName, envValue);
+    if (value === undefined) return fallback;
+    const number = Number(value);
+    if (Number.isFinite(number) && number >= 0) return number;
+    this.fail(source, 'a number of 0 or more', value);
+    return fallback;
+  }
+
+  /** A positive integer. */
+  limit(path: string, envName: string, envValue: string | undefined, fallback: number): number {
+    const { value, source } = this.raw(path, envName, envValue);
+    if (value === undefined) return fallback;
+    const number = Number(value);
+    if (Number.isInteger(number) && number > 0) return number;
+    this.fail(source, 'a whole number above 0', value);
+    return fallback;
+  }
+
+  /** `true`/`false`, or `1`/`0` from an env var. */
+  flag(path: string, envName: string, envValue: string | undefined, fallback = false): boolean {
+    const { value, source } = this.raw(path, envName, envValue);
+    if (value === undefined) return fallback;
+    if (value === true || value === '1' || value === 'true') return true;
+    if (value === false || value === '0' || value === 'false') return false;
+    this.fail(source, 'true or false (1 or 0)', value);
+    return fallback;
+  }
+
+  choice<T extends string>(path: string, envName: string, envValue: string | undefined, options: T[]): T {
+    const { value, source } = this.raw(path, envName, envValue);
+    if (value === undefined) return options[0];
+    if (options.includes(value as T)) return value as T;
+    this.fail(source, `one of ${options.join(', ')}`, value);
+    return options[0];
+  }
+
+  string(path: string, envName: string, envValue: string | undefined): string | null {
+    const { value, source } = this.raw(path, envName, envValue);
+    if (value === undefined || value === '') return null;
+    if (typeof value === 'string') return value;
+    this.fail(source, 'a string', value);
+    return null;
+  }
+
+  /** A list, as an array or a comma-separated string. */
+  list(path: string, envName: string, envValue: string | undefined, fallback: string[] = []): string[] {
+    const { value, source } = this.raw(path, envName, envValue);
+    if (value === undefined) return fallback;
+    const items = typeof value === 'string' ? value.split(',') : value;
+    if (Array.isArray(items) && items.every((item) => typeof item === 'string')) {
+      return items.map((item) => item.trim()).filter(Boolean);
+    }
+    this.fail(source, 'a list of strings', value);
+    return fallback;
+  }
+}
+
+function parseFile(value: Env['PROXY_CONFIG']): { file: Record<string, Section>; error?: string } {
+  if (value === undefined || value === '') return { file: {} };
+  let parsed: unknown = value;
+  if (typeof value === 'string') {
+    try {
+      parsed = JSON.parse(value);
+    } catch (e) {
+      return { file: {}, error: `PROXY_CONFIG: not valid JSON (${e instanceof Error ? e.message : e})` };
+    }
+  }
+  if (!parsed || typeof parsed !== 'object' || Array.isArray(parsed)) {
+    return { file: {}, error: 'PROXY_CONFIG: expected an object of sections' };
+  }
+  return { file: parsed as Record<string, Section> };
+}
+
+function buildConfig(env: Env): ProxyConfig {
+  const { file, error } = parseFile(env.PROXY_CONFIG);
+  const read = new Reader(file);
+  if (error) read.errors.push(error);
+
+  const config: ProxyConfig = {
+    auth: { token: read.string('auth.token', 'PROXY_AUTH_TOKEN', env.PROXY_AUTH_TOKEN) },
+    origins: {
+      allowed: read.list('origins.allowed', 'ALLOWED_ORIGINS', env.ALLOWED_ORIGINS, DEFAULT_ORIGINS),
+      allow_missing: read.flag('origins.allow_missing', 'ALLOW_MISSING_ORIGIN', env.ALLOW_MISSING_ORIGIN, true),
+    },
+    targets: { allowed: read.list('targets.allowed', 'ALLOWED_TARGETS', env.ALLOWED_TARGETS) },
+    session: { grace_secs: read.duration('session.grace_secs', 'SESSION_GRACE_SECS', env.SESSION_GRACE_SECS, 300) },
+    keepalive: {
+      interval_secs: read.duration('keepalive.interval_secs', 'KEEPALIVE_SECS', env.KEEPALIVE_SECS, 30),
+      mud_idle_secs: read.duration('keepalive.mud_idle_secs', 'MUD_IDLE_SECS', env.MUD_IDLE_SECS, 120),
+      mud_stale_secs: read.duration('keepalive.mud_stale_secs', 'MUD_STALE_SECS', env.MUD_STALE_SECS, 30),
+    },
+    limits: {
+      max_sessions: read.limit('limits.max_sessions', 'MAX_SESSIONS', env.MAX_SESSIONS, 100),
+      max_sessions_per_ip: read.limit('limits.max_sessions_per_ip', 'MAX_SESSIONS_PER_IP', env.MAX_SESSIONS_PER_IP, 5),
+      max_message_bytes: read.limit('limits.max_message_bytes', 'MAX_MESSAGE_BYTES', env.MAX_MESSAGE_BYTES, 64 * 1024),
+      max_command_chars: read.limit('limits.max_command_chars', 'MAX_COMMAND_CHARS', env.MAX_COMMAND_CHARS, 4096),
+      max_queued_commands: read.limit('limits.max_queued_commands', 'MAX_QUEUED_COMMANDS', env.MAX_QUEUED_COMMANDS, 200),
+      multiline_commands: read.choice('limits.multiline_commands', 'MULTILINE_COMMANDS', env.MULTILINE_COMMANDS, [
+        'split',
+        'reject',
+      ]),
+    },
+    output: {
+      batch_ms: read.duration('output.batch_ms', 'OUTPUT_BATCH_MS', env.OUTPUT_BATCH_MS, 16),
+      batch_chars: read.limit('output.batch_chars', 'OUTPUT_BATCH_CHARS', env.OUTPUT_BATCH_CHARS, 16 * 1024),
+    },
+    logging: {
+      session_log: read.flag('logging.session_log', 'SESSION_LOG', env.SESSION_LOG),
+      log_command_content: read.flag('logging.log_command_content', 'LOG_COMMAND_CONTENT', env.LOG_COMMAND_CONTENT),
+    },
+  };
+
+  if (read.errors.length > 0) {
+    throw new Error(`Invalid proxy config: ${read.errors.join('; ')}`);
+  }
+  return config;
+}
+
+const loaded = new WeakMap<Env, ProxyConfig>();
+
+/**
+ * The proxy's config, built once per isolate. Throws, naming every bad
+ * setting, if any value doesn't validate; logs the effective config (token
+ * redacted) when it's first built.
+ */
+export function loadConfig(env: Env): ProxyConfig {
+  let config = loaded.get(env);
+  if (!config) {
+    config = buildConfig(env);
+    loaded.set(env, config);
+    const redacted = { ...config, auth: { token: config.auth.token ? '<redacted>' : null } };
+    console.info(`proxy config: ${JSON.stringify(redacted)}`);
+  }
+  return config;
+}
@@ -1,21 +1,64 @@
+import { authAllowed, clientIp } from './auth';
+import { loadConfig, type ProxyConfig } from './config';
 import { MudProxy } from './MudProxy';
+import { originAllowed, upgradeAllowed } from './origins';
+import { hubFetch, hubRequest, ProxyHub } from './ProxyHub';
 
-export { MudProxy };
+export { MudProxy, ProxyHub };
 
-interface Env {
+export interface Env {
   MUD_PROXY: DurableObjectNamespace;
+  PROXY_HUB: DurableObjectNamespace;
+  /** Settings as sections (see `ProxyConfig`); each env var below overrides one of them. */
+  PROXY_CONFIG?: string | Record<string, unknown>;
+  PROXY_AUTH_TOKEN?: string;
+  ALLOWED_ORIGINS?: string;
+  ALLOW_MISSING_ORIGIN?: string;
+  ALLOWED_TARGETS?: string;
+  SESSION_GRACE_SECS?: string;
+  KEEPALIVE_SECS?: string;
+  MUD_IDLE_SECS?: string;
+  MUD_STALE_SECS?: string;
+  OUTPUT_BATCH_MS?: string;
+  OUTPUT_BATCH_CHARS?: string;
+  SESSION_LOG?: string;
+  LOG_COMMAND_CONTENT?: string;
+  MAX_SESSIONS?: string;
+  MAX_SESSIONS_PER_IP?: string;
+  MAX_MESSAGE_BYTES?: string;
+  MAX_COMMAND_CHARS?: string;
+  MAX_QUEUED_COMMANDS?: string;
+  MULTILINE_COMMANDS?: string;
+  /** Deployed version, reported by `/healthz`. */
+  CF_VERSION_METADATA?: { id: string; tag: string };
 }
 
-const ALLOWED_ORIGINS = [
-  'https://dartforge.netlify.app',
-  'http://localhost:5173',
-  'http://localhost:3000',
-  'http://localhost:1420',
-];
+/** The DO a resume token (`<id>.<secret>`) belongs to, or null if it isn't one. */
+function sessionId(env: Env, token: string | null): DurableObjectId | null {
+  if (!token) return null;
+  try {
+    return env.MUD_PROXY.idFromString(token.split('.')[0]);
+  } catch {
+    return null;
+  }
+}
+
+/**
+ * Complete the upgrade only to say why the session is refused, then close —
+ * browsers don't show WebSocket clients an HTTP error body.
+ */
+function refuseSession(reason: 'server_full' | 'rate_limited', message: string): Response {
+  const pair = new WebSocketPair();
+  const [client, server] = [pair[0], pair[1]];
+  server.accept();
+  server.send(JSON.stringify({ type: 'status', connected: false, message, reason, ts_ms: Date.now() }));
+  server.close(1013, message);
+  return new Response(null, { status: 101, webSocket: client });
+}
 
-function corsHeaders(request: Request): Record<string, string> {
+function corsHeaders(request: Request, origins: string[]): Record<string, string> {
   const origin = request.headers.get('Origin') ?? '';
-  const allowed = ALLOWED_ORIGINS.includes(origin) ? origin : ALLOWED_ORIGINS[0];
+  const allowed = originAllowed(origin, origins) ? origin : (origins[0] ?? '');
   return {
     'Access-Control-Allow-Origin': allowed,
     'Access-Control-Allow-Methods': 'GET, OPTIONS',
@@ -24,9 +67,17 @@ function corsHeaders(request: Request): Record<string, string> {
 }
 
 export default {
-  async fetch(request: Request, env: Env): Promise<Response> {
+  async fetch(request: Request, env: Env, ctx: ExecutionContext): Promise<Response> {
     const url = new URL(request.url);
-    const cors = corsHeaders(request);
+
+    let config: ProxyConfig;
+    try {
+      config = loadConfig(env);
+    } catch (e) {
+      console.error(e instanceof Error ? e.message : e);
+      return new Response('Proxy misconfigured', { status: 500 });
+    }
+    const cors = corsHeaders(request, config.origins.allowed);
 
     if (request.method === 'OPTIONS') {
       return new Response(null, { status: 204, headers: cors });
@@ -35,11 +86,41 @@ export default {
     // WebSocket upgrade: /ws
     if (url.pathname === '/ws') {
       if (request.headers.get('Upgrade') !== 'websocket') {
+        ctx.waitUntil(hubFetch(env, '/handshake/failed', { reason: 'not_websocket' }));
         return new Response('Expected WebSocket', { status: 426, headers: cors });
       }
 
-      // Each connection gets its own DO instance
-      const id = env.MUD_PROXY.newUniqueId();
+      // Other sites' pages must not be able to use a visitor's browser to reach the MUD
+      const origin = request.headers.get('Origin');
+      if (!upgradeAllowed(origin, config.origins)) {
+        console.warn(`refused origin ${origin ?? '(none)'} from ${clientIp(request)}`);
+        ctx.waitUntil(hubFetch(env, '/handshake/failed', { reason: 'origin' }));
+        return new Response('Origin not allowed', { status: 403, headers: cors });
+      }
+
+      // Addresses with too many recent auth failures don't get to try again yet
+      if (config.auth.token && !(await authAllowed(env, clientIp(request)))) {
+        ctx.waitUntil(hubFetch(env, '/handshake/failed', { reason: 'rate_limited' }));
+        return new Response('Too many failed attempts', { status: 429, headers: cors });
+      }
+
+      // Each session gets its own DO instance; a resume token routes back to it.
+      // The id is picked first so the hub can hold its slot
+      const resume = url.searchParams.get('resume');
+      const id = sessionId(env, resume) ?? env.MUD_PROXY.newUniqueId();
+      const { limit } = await hubRequest<{ limit: string | null }>(env, '/admit', {
+        id: id.toString(),
+        ip: clientIp(request),
+        resume: resume ? '1' : '',
+      });
+      if (limit === 'handshake_rate') {
+        return refuseSession('rate_limited', 'Too many connection attempts, try again shortly');
+      }
+      if (limit) {
+        const message = limit === 'max_sessions' ? 'Server full' : 'Too many connections from your address';
+        return refuseSession('server_full', message);
+      }
+
       const stub = env.MUD_PROXY.get(id);
       return stub.fetch(request);
     }
@@ -48,6 +129,18 @@ export default {
       return new Response('ok', { headers: cors });
     }
 
+    // Prometheus scrape target; labels are bounded, nothing per client
+    if (url.pathname === '/metrics') {
+      return hubFetch(env, '/metrics');
+    }
+
+    // Readiness for load balancers and monitors: session counts from the hub
+    if (url.pathname === '/healthz') {
+      const counts = await hubRequest<Record<string, number>>(env, '/health');
+      const version = env.CF_VERSION_METADATA?.tag || env.CF_VERSION_METADATA?.id || 'dev';
+      return Response.json({ status: 'ok', version, ...counts }, { headers: cors });
+    }
+
     return new Response('Not Found', { status: 404, headers: cors });
   },
 };
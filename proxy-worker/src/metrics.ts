@@ -0,0 +1,53 @@
+/** Prometheus text exposition for the hub's `/metrics`. Labels only ever take values from fixed sets. */
+
+type Labels = Record<string, string>;
+
+/** Cumulative histogram with fixed upper bounds, rendered with the usual `_bucket`/`_sum`/`_count` series. */
+export class Histogram {
+  private counts: number[];
+  private sum = 0;
+  private count = 0;
+
+  constructor(private bounds: number[]) {
+    this.counts = bounds.map(() => 0);
+  }
+
+  observe(value: number): void {
+    this.bounds.forEach((bound, i) => {
+      if (value <= bound) this.counts[i]++;
+    });
+    this.sum += value;
+    this.count++;
+  }
+
+  render(name: string, help: string): string {
+    const lines = [`# HELP ${name} ${help}`, `# TYPE ${name} histogram`];
+    this.bounds.forEach((bound, i) => lines.push(`${name}_bucket{le="${bound}"} ${this.counts[i]}`));
+    lines.push(`${name}_bucket{le="+Inf"} ${this.count}`, `${name}_sum ${this.sum}`, `${name}_count ${this.count}`);
+    return lines.join('\n');
+  }
+}
+
+function formatLabels(labels: Labels): string {
+  // The exposition format escapes a newline as `\n`, not a backslash and a line break
+  const escape = (value: string) => value.replace(/["\\\n]/g, (c) => (c === '\n' ? '\\n' : `\\${c}`));
+  const pairs = Object.entries(labels).map(([key, value]) => `${key}="${escape(value)}"`);
+  return pairs.length > 0 ? `{${pairs.join(',')}}` : '';
+}
+
+/** One metric family: a counter or gauge with any number of labelled samples. */
+export function metric(
+  name: string,
+  type: 'counter' | 'gauge',
+  help: string,
+  samples: [Labels, number][],
+): string {
+  const lines = [`# HELP ${name} ${help}`, `# TYPE ${name} ${type}`];
+  for (const [labels, value] of samples) lines.push(`${name}${formatLabels(labels)} ${value}`);
+  return lines.join('\n');
+}
+
+/** Samples for a counter keyed by one label. */
+export function labelled(label: string, counts: Map<string, number>): [Labels, number][] {
+  return [...counts].map(([value, count]) => [{ [label]: value }, count]);
+}
@@ -0,0 +1,16 @@
+import type { ProxyConfig } from './config';
+
+/** Whether `origin` is in the allowlist, exactly or under a `scheme://*.domain` entry. */
+export function originAllowed(origin: string, allowed: string[]): boolean {
+  return allowed.some((pattern) => {
+    const wildcard = pattern.match(/^([a-z]+:\/\/)\*(\..+)$/i);
+    if (!wildcard) return pattern === origin;
+    const [, scheme, domain] = wildcard;
+    return origin.startsWith(scheme) && origin.endsWith(domain) && origin.length > scheme.length + domain.length;
+  });
+}
+
+/** Whether a `/ws` upgrade with this `Origin` header (null when it had none) may go ahead. */
+export function upgradeAllowed(origin: string | null, origins: ProxyConfig['origins']): boolean {
+  return origin === null ? origins.allow_missing : originAllowed(origin, origins.allowed);
+}
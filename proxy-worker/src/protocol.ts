@@ -0,0 +1,205 @@
+/**
+ * The JSON messages exchanged over `/ws`. Every message is an object with a
+ * `type`; unknown fields are ignored in both directions so either side can
+ * grow without breaking the other.
+ *
+ * Version 1 is the protocol as it was before `hello`: a client that never
+ * sends `hello` gets exactly that. A client that does gets back the version
+ * both sides speak (the lower of the two) and the proxy's feature list, and
+ * from then on unknown message types are answered with an `unknown_type`
+ * error rather than dropped.
+ *
+ * A client that lists `compact_output` in its `hello` features gets `output`
+ * as binary frames instead (see `encodeOutputFrame`); everything else stays
+ * JSON text frames.
+ *
+ * Since version 3 every JSON message from the proxy also carries `ts_ms`,
+ * when the proxy produced it in Unix millis; held output and statuses keep
+ * theirs when they're delivered on resume. Binary output frames don't carry
+ * it. Older clients ignore the field, so it goes to every client.
+ */
+
+/** The newest protocol version this proxy speaks. */
+export const PROTOCOL_VERSION = 3;
+/** Version assumed for clients that don't send `hello`. */
+export const LEGACY_PROTOCOL_VERSION = 1;
+/** Optional features, advertised in `hello`. */
+export const PROXY_FEATURES = ['auth', 'compact_output', 'keepalive', 'rate_limit', 'session', 'targets'];
+
+/** First byte of a binary frame: what it carries. */
+export const FRAME_OUTPUT = 0x01;
+/** Second byte of an output frame: bit 0 is `ga`. */
+export const FRAME_FLAG_GA = 0x01;
+
+/**
+ * `resume` opts the session in to outliving its WebSocket and is answered with
+ * a `session` message. Clients that never send it are torn down with their
+ * socket, as before. `auth` carries `PROXY_AUTH_TOKEN` and must come first
+ * when it's set and the handshake didn't already present it. `hello` may
+ * come first (after `auth`) to negotiate a protocol version.
+ */
+export interface ClientMessage {
+  type: 'hello' | 'command' | 'reconnect' | 'disconnect' | 'ping' | 'pong' | 'rate_limit' | 'resume' | 'auth';
+  /** The command for `command`; the shared token for `auth`. */
+  data?: string;
+  /** For `rate_limit`: sustained commands/second, or null to remove the limit. */
+  per_second?: number | null;
+  burst?: number;
+  /**
+   * For `reconnect`: the MUD to connect to, which must be in `ALLOWED_TARGETS`.
+   * Either may be left out; they default to the first allowed target's.
+   */
+  host?: string;
+  port?: number;
+  /** For `hello`: the client's protocol version and the optional features it supports. */
+  version?: number;
+  features?: string[];
+}
+
+export interface OutputMessage {
+  type: 'output';
+  data: string;
+  ga: boolean;
+}
+
+/** Mirrors the desktop `StatusReason` — the contract clients key behavior off of. */
+export type StatusReason =
+  | 'connecting'
+  | 'connected'
+  | 'user_disconnect'
+  | 'server_closed'
+  | 'read_error'
+  | 'write_error'
+  | 'dns_failure'
+  | 'timeout'
+  | 'connect_refused'
+  | 'proxy_error'
+  /** The MUD stopped answering, probes included; the proxy dropped the connection. */
+  | 'stalled'
+  /** The requested target isn't in `ALLOWED_TARGETS`; nothing was dialed. */
+  | 'target_not_permitted'
+  /** Refused by the proxy's session limits; the socket closes after this. */
+  | 'server_full'
+  /** Too many connection attempts from this address; the socket closes after this. */
+  | 'rate_limited';
+
+export interface StatusMessage {
+  type: 'status';
+  connected: boolean;
+  message: string;
+  reason: StatusReason;
+  detail?: string;
+  attempt?: number;
+  /** `host:port` being connected to, for statuses about a connection attempt. */
+  target?: string;
+}
+
+export interface PongMessage {
+  type: 'pong';
+}
+
+/**
+ * Keepalive, every `KEEPALIVE_SECS`; answered with `{type: "pong"}`. A client
+ * that has answered one and then goes two intervals without sending anything
+ * is closed as dead.
+ */
+export interface PingMessage {
+  type: 'ping';
+}
+
+/** Connection traffic counters — same shape as the desktop `mud:stats` event. */
+export interface StatsMessage {
+  type: 'stats';
+  connected: boolean;
+  connected_at: number | null;
+  last_read_at: number | null;
+  uptime_secs: number;
+  bytes_read: number;
+  bytes_written: number;
+  lines: number;
+  total_bytes_read: number;
+  total_bytes_written: number;
+  total_lines: number;
+}
+
+/** Same payload as the desktop `mud:command-backlog` event. */
+export interface CommandBacklogMessage {
+  type: 'command_backlog';
+  depth: number;
+  threshold: number;
+}
+
+/**
+ * Sent in reply to `resume` and whenever a socket attaches with a token. The
+ * token goes in the `resume` query parameter of the next `/ws` to pick the
+ * session back up within `grace_secs` of the socket dropping; `resumed` says
+ * whether this socket did, or got a fresh session because the old one expired.
+ */
+export interface SessionMessage {
+  type: 'session';
+  token: string;
+  resumed: boolean;
+  grace_secs: number;
+}
+
+/**
+ * A client message was refused. `message_too_large` closes the socket after
+ * it; `command_too_long`, `multiline_command` and `command_queue_full` only
+ * drop that command. `unknown_type` (a `type` this proxy doesn't know) is
+ * only sent to clients that said `hello`; version 1 clients never see it.
+ */
+export interface ErrorMessage {
+  type: 'error';
+  code: 'message_too_large' | 'command_too_long' | 'multiline_command' | 'command_queue_full' | 'unknown_type';
+  detail: string;
+}
+
+/** Answer to the client's `hello`: the version both sides will speak and the proxy's features. */
+export interface HelloMessage {
+  type: 'hello';
+  version: number;
+  features: string[];
+}
+
+/** `ts_ms` is added by `MudProxy.send` when it isn't set yet. */
+export type ServerMessage = (
+  | HelloMessage
+  | ErrorMessage
+  | OutputMessage
+  | StatusMessage
+  | PongMessage
+  | PingMessage
+  | StatsMessage
+  | CommandBacklogMessage
+  | SessionMessage
+) & { ts_ms?: number };
+
+/** The version to speak with a client whose `hello` offered `offered`: the lower of the two, and never below 1. */
+export function negotiateVersion(offered: unknown): number {
+  return Math.max(
+    LEGACY_PROTOCOL_VERSION,
+    Math.min(PROTOCOL_VERSION, Math.floor(Number(offered) || LEGACY_PROTOCOL_VERSION)),
+  );
+}
+
+/** The answer to a message of a `type` this proxy doesn't know, or null for version 1 clients, which get none. */
+export function unknownTypeError(version: number, type: unknown): ErrorMessage | null {
+  if (version <= LEGACY_PROTOCOL_VERSION) return null;
+  return { type: 'error', code: 'unknown_type', detail: `Unknown message type: ${String(type)}` };
+}
+
+/** An `output` message as a binary frame: `FRAME_OUTPUT`, flags, then the text as UTF-8. */
+export function encodeOutputFrame(msg: OutputMessage): Uint8Array {
+  const text = new TextEncoder().encode(msg.data);
+  const frame = new Uint8Array(text.length + 2);
+  frame[0] = FRAME_OUTPUT;
+  frame[1] = msg.ga ? FRAME_FLAG_GA : 0;
+  frame.set(text, 2);
+  return frame;
+}
+
+/** The inverse of `encodeOutputFrame`, or null for a frame that isn't output. */
+export function decodeOutputFrame(frame: Uint8Array): OutputMessage | null {
+  if (frame.length < 2 || frame[0] !== FRAME_OUTPUT) return null;
+  return { type: 'output', data: new TextDecoder().decode(frame.subarray(2)), ga: (frame[1] & FRAME_FLAG_GA) !== 0 };
+}
@@ -0,0 +1,36 @@
+/**
+ * Per-session event log for working out what happened to a session after the
+ * fact: lifecycle, client message types, statuses (which cover MUD connect
+ * attempts and results) and byte counts. Workers have no disk, so each event
+ * is one JSON line on the console, where Workers Logs or Logpush keep it for
+ * their retention period; filter by `session_log` to follow one session.
+ *
+ * Off unless `SESSION_LOG=1`. Command text is never logged unless
+ * `LOG_COMMAND_CONTENT=1` — the proxy can't tell when a password is being
+ * typed.
+ */
+export class SessionLog {
+  /** Start time plus a short random suffix, e.g. `20261014T081500Z-3f9c1a`. */
+  readonly id: string;
+
+  constructor(
+    private enabled: boolean,
+    private logCommands: boolean,
+  ) {
+    const stamp = new Date().toISOString().replace(/[-:]/g, '').replace(/\.\d+/, '');
+    const suffix = Array.from(crypto.getRandomValues(new Uint8Array(3)), (b) => b.toString(16).padStart(2, '0')).join('');
+    this.id = `${stamp}-${suffix}`;
+  }
+
+  event(event: string, fields: Record<string, unknown> = {}): void {
+    if (!this.enabled) return;
+    console.log(JSON.stringify({ session_log: this.id, at: new Date().toISOString(), event, ...fields }));
+  }
+
+  /** A message from the client: its type, and a command's text only when allowed. */
+  clientMessage(type: string, data: unknown): void {
+    if (!this.enabled) return;
+    const content = type === 'command' && this.logCommands && typeof data === 'string' ? { data } : {};
+    this.event('client_message', { type, ...content });
+  }
+}
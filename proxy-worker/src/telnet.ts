@@ -7,6 +7,11 @@ const DONT = 0xfe;
 const SB = 0xfa;
 const SE = 0xf0;
 const GA = 0xf9;
+/** Telnet TIMING-MARK option (RFC 860) */
+const TIMING_MARK = 0x06;
+
+/** Liveness probe: the server answers WILL or WONT TIMING-MARK once it has caught up. */
+export const IAC_DO_TIMING_MARK = new Uint8Array([IAC, DO, TIMING_MARK]);
 
 export interface ProcessedOutput {
   /** Display text with IAC stripped, ANSI preserved */
@@ -66,7 +71,9 @@ export function processOutput(raw: Uint8Array): ProcessedOutput {
           }
           const cmd = raw[i + 1];
           const option = raw[i + 2];
-          if (cmd === DO) {
+          if (option === TIMING_MARK && (cmd === WILL || cmd === WONT)) {
+            // The reply to our own DO TIMING-MARK; nothing to answer
+          } else if (cmd === DO) {
             responses.push(new Uint8Array([IAC, WONT, option]));
           } else if (cmd === WILL) {
             responses.push(new Uint8Array([IAC, DONT, option]));
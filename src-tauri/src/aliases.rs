@@ -0,0 +1,501 @@
+//! Backend alias expansion, applied in `send_command` before commands are
+//! routed to the connection (and to backend trigger commands).
+//!
+//! Aliases come from the alias panel's `aliases.json` (the `global` section
+//! plus the active character's). Only aliases with `"engine": "backend"` are
+//! expanded here; on desktop the webview skips those and sends the input
+//! through unexpanded. Argument substitution follows the webview engine:
+//! `$1`–`$9`, `$*` (all args), `$-` (all but last), `$!` (last),
+//! `$opposite1`–`$opposite9`, and `$me`/`$Me`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{debug, info, warn};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+pub const ALIASES_FILE: &str = "aliases.json";
+const GLOBAL_SECTION: &str = "global";
+const BACKEND_ENGINE: &str = "backend";
+/// The alias panel's default command separator.
+pub const DEFAULT_SEPARATOR: &str = ";;";
+/// Nested alias expansions beyond this depth are sent literally.
+const MAX_EXPANSION_DEPTH: usize = 10;
+/// One input can't expand into more commands than this.
+const MAX_EXPANDED_COMMANDS: usize = 100;
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AliasMatchMode {
+    /// The whole input, case-insensitively.
+    #[default]
+    Exact,
+    /// The first word(s); the rest become arguments.
+    Prefix,
+    /// A regex; its groups become the arguments.
+    Regex,
+}
+
+/// An alias as stored by the alias panel (camelCase, like the rest of
+/// aliases.json). Only the fields the backend uses are read.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AliasRule {
+    pub id: String,
+    pub pattern: String,
+    pub match_mode: AliasMatchMode,
+    pub body: String,
+    pub body_mode: Option<String>,
+    pub enabled: bool,
+    pub engine: Option<String>,
+}
+
+struct CompiledAlias {
+    rule: AliasRule,
+    regex: Option<Regex>,
+}
+
+#[derive(Default)]
+struct AliasInner {
+    /// Enabled backend aliases in match order: exact, then prefix, then regex.
+    aliases: Vec<CompiledAlias>,
+    character: Option<String>,
+    separator: Option<String>,
+}
+
+#[derive(Default)]
+pub struct AliasTable {
+    inner: Mutex<AliasInner>,
+}
+
+fn opposite_direction(dir: &str) -> &'static str {
+    match dir.to_lowercase().as_str() {
+        "n" => "s",
+        "s" => "n",
+        "e" => "w",
+        "w" => "e",
+        "ne" => "sw",
+        "sw" => "ne",
+        "nw" => "se",
+        "se" => "nw",
+        "u" => "d",
+        "d" => "u",
+        "in" => "out",
+        "out" => "in",
+        _ => "",
+    }
+}
+
+/// `$0`–`$9`, as positional token names for `expand_tokens`.
+pub(crate) const DIGIT_TOKENS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+const OPPOSITE_TOKENS: [&str; 9] = [
+    "opposite1",
+    "opposite2",
+    "opposite3",
+    "opposite4",
+    "opposite5",
+    "opposite6",
+    "opposite7",
+    "opposite8",
+    "opposite9",
+];
+
+/// `word` with its first letter capitalized, for `$Me`.
+pub(crate) fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Replace each `$name` in `body` with its value from `tokens` in a single
+/// left-to-right pass, so text a value brings in (arguments, a MUD line) is
+/// never expanded itself. Where names overlap the longest wins; a `$` that
+/// starts no name is kept.
+pub(crate) fn expand_tokens(body: &str, tokens: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(at) = rest.find('$') {
+        out.push_str(&rest[..at]);
+        let after = &rest[at + 1..];
+        let token = tokens
+            .iter()
+            .filter(|(name, _)| after.starts_with(name))
+            .max_by_key(|(name, _)| name.len());
+        match token {
+            Some((name, value)) => {
+                out.push_str(value);
+                rest = &after[name.len()..];
+            }
+            None => {
+                out.push('$');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn substitute_args(body: &str, args: &[String], character: &str) -> String {
+    let all = args.join(" ");
+    let all_but_last = args[..args.len().saturating_sub(1)].join(" ");
+    let last = args.last().map_or("", String::as_str);
+    let capitalized = capitalize(character);
+    let arg = |i: usize| args.get(i - 1).map_or("", String::as_str);
+
+    let mut tokens = vec![
+        ("*", all.as_str()),
+        ("-", all_but_last.as_str()),
+        ("!", last),
+        ("Me", capitalized.as_str()),
+        ("me", character),
+    ];
+    for i in 1..=9 {
+        tokens.push((DIGIT_TOKENS[i], arg(i)));
+        tokens.push((OPPOSITE_TOKENS[i - 1], opposite_direction(arg(i))));
+    }
+    expand_tokens(body, &tokens)
+}
+
+/// Split on unescaped separators and newlines; `\` before the separator
+/// keeps it literal.
+pub(crate) fn split_commands(input: &str, separator: &str) -> Vec<String> {
+    let escaped = format!("\\{separator}");
+    let mut parts = Vec::new();
+    for line in input.split('\n') {
+        let mut current = String::new();
+        let mut rest = line;
+        loop {
+            let next_sep = rest.find(separator);
+            let next_escape = rest.find(&escaped);
+            match (next_escape, next_sep) {
+                (Some(esc), Some(sep)) if esc < sep => {
+                    current.push_str(&rest[..esc]);
+                    current.push_str(separator);
+                    rest = &rest[esc + escaped.len()..];
+                }
+                (_, Some(sep)) => {
+                    current.push_str(&rest[..sep]);
+                    parts.push(std::mem::take(&mut current));
+                    rest = &rest[sep + separator.len()..];
+                }
+                (_, None) => {
+                    current.push_str(rest);
+                    parts.push(current);
+                    break;
+                }
+            }
+        }
+    }
+    parts.into_iter().map(|p| p.replace('\r', "")).collect()
+}
+
+impl CompiledAlias {
+    /// The alias's arguments if it matches `input` (already trimmed).
+    fn matches(&self, input: &str) -> Option<Vec<String>> {
+        let words = || input.split_whitespace().map(str::to_string);
+        match self.rule.match_mode {
+            AliasMatchMode::Exact => input
+                .eq_ignore_ascii_case(&self.rule.pattern)
+                .then(Vec::new),
+            AliasMatchMode::Prefix => {
+                let pattern = &self.rule.pattern;
+                if input.eq_ignore_ascii_case(pattern) {
+                    return Some(Vec::new());
+                }
+                let head = input.get(..pattern.len())?;
+                let rest = input[pattern.len()..].strip_prefix(' ')?;
+                head.eq_ignore_ascii_case(pattern)
+                    .then(|| rest.split_whitespace().map(str::to_string).collect())
+            }
+            AliasMatchMode::Regex => {
+                let caps = self.regex.as_ref()?.captures(input)?;
+                if caps.len() > 1 {
+                    Some(
+                        caps.iter()
+                            .skip(1)
+                            .map(|m| m.map_or(String::new(), |m| m.as_str().to_string()))
+                            .collect(),
+                    )
+                } else {
+                    Some(words().skip(1).collect())
+                }
+            }
+        }
+    }
+}
+
+struct Expansion<'a> {
+    aliases: &'a [CompiledAlias],
+    character: &'a str,
+    separator: &'a str,
+    /// Ids of the aliases being expanded, outermost first. An alias never
+    /// matches inside its own expansion, which also breaks longer cycles.
+    chain: Vec<&'a str>,
+    out: Vec<String>,
+    expanded: bool,
+}
+
+impl<'a> Expansion<'a> {
+    fn segment(&mut self, segment: &str) -> Result<(), String> {
+        let trimmed = segment.trim();
+        if self.out.len() >= MAX_EXPANDED_COMMANDS {
+            return Err(format!(
+                "Alias expansion produced more than {MAX_EXPANDED_COMMANDS} commands"
+            ));
+        }
+        let aliases = self.aliases;
+        let found = aliases
+            .iter()
+            .filter(|a| !self.chain.contains(&a.rule.id.as_str()))
+            .find_map(|a| a.matches(trimmed).map(|args| (a, args)));
+        let Some((alias, args)) = found else {
+            self.out.push(trimmed.to_string());
+            return Ok(());
+        };
+        if self.chain.len() >= MAX_EXPANSION_DEPTH {
+            warn!("Alias recursion limit reached; sending {trimmed:?} as typed");
+            self.out.push(trimmed.to_string());
+            return Ok(());
+        }
+
+        self.expanded = true;
+        let body = substitute_args(&alias.rule.body, &args, self.character);
+        self.chain.push(&alias.rule.id);
+        for sub in split_commands(&body, self.separator) {
+            let sub = sub.trim();
+            if sub.is_empty() {
+                continue;
+            }
+            if sub.starts_with('/') {
+                debug!("Skipping client directive in backend alias: {sub}");
+                continue;
+            }
+            self.segment(sub)?;
+        }
+        self.chain.pop();
+        Ok(())
+    }
+}
+
+impl AliasTable {
+    fn lock(&self) -> std::sync::MutexGuard<'_, AliasInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Set the character whose aliases apply and the command separator, used
+    /// by this and later reloads.
+    pub fn configure(&self, character: Option<String>, separator: Option<String>) {
+        let mut inner = self.lock();
+        inner.character = character
+            .map(|c| c.trim().to_lowercase())
+            .filter(|c| !c.is_empty());
+        inner.separator = separator.filter(|s| !s.is_empty());
+    }
+
+    /// Re-read aliases.json. Returns how many backend aliases are active.
+    pub fn reload(&self, dir: &Path) -> Result<usize, String> {
+        let sections: BTreeMap<String, BTreeMap<String, serde_json::Value>> =
+            match std::fs::read_to_string(dir.join(ALIASES_FILE)) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .map_err(|e| format!("Failed to parse {ALIASES_FILE}: {e}"))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(e) => return Err(format!("Failed to read {ALIASES_FILE}: {e}")),
+            };
+
+        let mut inner = self.lock();
+        let mut keys: Vec<&str> = inner.character.as_deref().into_iter().collect();
+        keys.push(GLOBAL_SECTION);
+        let mut aliases = Vec::new();
+        for key in keys {
+            let Some(section) = sections.get(key) else {
+                continue;
+            };
+            for value in section.values() {
+                let Ok(rule) = serde_json::from_value::<AliasRule>(value.clone()) else {
+                    continue;
+                };
+                if rule.engine.as_deref() != Some(BACKEND_ENGINE) || !rule.enabled {
+                    continue;
+                }
+                if rule.body_mode.as_deref() == Some("script") {
+                    warn!(
+                        "Alias {} is a script alias, which the backend does not support",
+                        rule.id
+                    );
+                    continue;
+                }
+                let regex = match rule.match_mode {
+                    AliasMatchMode::Regex => match RegexBuilder::new(&rule.pattern)
+                        .case_insensitive(true)
+                        .size_limit(REGEX_SIZE_LIMIT)
+                        .build()
+                    {
+                        Ok(regex) => Some(regex),
+                        Err(e) => {
+                            warn!("Skipping alias {}: invalid pattern: {e}", rule.id);
+                            continue;
+                        }
+                    },
+                    _ => None,
+                };
+                aliases.push(CompiledAlias { rule, regex });
+            }
+        }
+        // Stable, so character aliases still win within a mode
+        aliases.sort_by_key(|a| a.rule.match_mode);
+
+        let count = aliases.len();
+        inner.aliases = aliases;
+        info!("Loaded {count} backend alias(es)");
+        Ok(count)
+    }
+
+    /// The configured command separator.
+    pub fn separator(&self) -> String {
+        self.lock()
+            .separator
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SEPARATOR.to_string())
+    }
+
+    /// Expand one command that is already split, such as a backend trigger's:
+    /// escaped separators in it are kept literal rather than split on, both
+    /// when it's sent as is and in the arguments of an alias it matches.
+    pub fn expand_command(&self, command: &str) -> Result<Vec<String>, String> {
+        let inner = self.lock();
+        let separator = inner.separator.as_deref().unwrap_or(DEFAULT_SEPARATOR);
+        let literal = || vec![command.replace(&format!("\\{separator}"), separator)];
+        if inner.aliases.is_empty() {
+            return Ok(literal());
+        }
+        let mut expansion = Expansion {
+            aliases: &inner.aliases,
+            character: inner.character.as_deref().unwrap_or(""),
+            separator,
+            chain: Vec::new(),
+            out: Vec::new(),
+            expanded: false,
+        };
+        expansion.segment(command)?;
+        if !expansion.expanded {
+            return Ok(literal());
+        }
+        Ok(expansion.out)
+    }
+
+    /// The commands `input` expands to. Input that matches no alias comes
+    /// back unchanged, as a single command.
+    pub fn expand(&self, input: &str) -> Result<Vec<String>, String> {
+        let inner = self.lock();
+        if inner.aliases.is_empty() {
+            return Ok(vec![input.to_string()]);
+        }
+        let separator = inner.separator.as_deref().unwrap_or(DEFAULT_SEPARATOR);
+        let mut expansion = Expansion {
+            aliases: &inner.aliases,
+            character: inner.character.as_deref().unwrap_or(""),
+            separator,
+            chain: Vec::new(),
+            out: Vec::new(),
+            expanded: false,
+        };
+        for segment in split_commands(input, separator) {
+            if !segment.trim().is_empty() {
+                expansion.segment(&segment)?;
+            }
+        }
+        if !expansion.expanded {
+            return Ok(vec![input.to_string()]);
+        }
+        Ok(expansion.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(aliases: &[(&str, AliasMatchMode, &str)]) -> AliasTable {
+        let table = AliasTable::default();
+        table.lock().aliases = aliases
+            .iter()
+            .map(|(pattern, match_mode, body)| CompiledAlias {
+                rule: AliasRule {
+                    id: pattern.to_string(),
+                    pattern: pattern.to_string(),
+                    match_mode: *match_mode,
+                    body: body.to_string(),
+                    enabled: true,
+                    ..Default::default()
+                },
+                regex: None,
+            })
+            .collect();
+        table
+    }
+
+    #[test]
+    fn split_honors_escapes_and_newlines() {
+        assert_eq!(split_commands("a;;b\\;;c\nd\r", ";;"), ["a", "b;;c", "d"]);
+        assert_eq!(split_commands("a||b;;c", "||"), ["a", "b;;c"]);
+    }
+
+    #[test]
+    fn expand_splits_alias_bodies() {
+        let table = table(&[("k", AliasMatchMode::Prefix, "kill $1;;get all from corpse")]);
+        assert_eq!(
+            table.expand("k orc").unwrap(),
+            ["kill orc", "get all from corpse"]
+        );
+    }
+
+    #[test]
+    fn escaped_command_is_sent_as_one() {
+        let table = AliasTable::default();
+        assert_eq!(
+            table.expand_command("say hi\\;;give all").unwrap(),
+            ["say hi;;give all"]
+        );
+        let table = self::table(&[("k", AliasMatchMode::Exact, "kill orc")]);
+        assert_eq!(
+            table.expand_command("say hi\\;;give all").unwrap(),
+            ["say hi;;give all"]
+        );
+    }
+
+    #[test]
+    fn escaped_argument_stays_in_one_command() {
+        let table = table(&[("s", AliasMatchMode::Prefix, "say $*")]);
+        assert_eq!(
+            table.expand_command("s hi\\;;give all").unwrap(),
+            ["say hi;;give all"]
+        );
+    }
+
+    #[test]
+    fn configured_separator() {
+        let table = table(&[("k", AliasMatchMode::Exact, "kill orc||loot")]);
+        table.configure(None, Some("||".to_string()));
+        assert_eq!(table.separator(), "||");
+        assert_eq!(table.expand("k").unwrap(), ["kill orc", "loot"]);
+    }
+
+    #[test]
+    fn arguments_are_not_expanded_again() {
+        let table = table(&[("s", AliasMatchMode::Prefix, "say $1 to $me, $opposite2")]);
+        table.configure(Some("ahab".to_string()), None);
+        assert_eq!(table.expand("s $2$Me n").unwrap(), ["say $2$Me to ahab, s"]);
+    }
+
+    #[test]
+    fn tokens_expand_in_one_pass() {
+        let tokens = [("1", "$2"), ("2", "two"), ("line", "$1")];
+        assert_eq!(expand_tokens("$1 $2 $line $x $", &tokens), "$2 two $1 $x $");
+    }
+}
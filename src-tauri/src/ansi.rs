@@ -7,10 +7,24 @@ const DONT: u8 = 0xFE;
 const SB: u8 = 0xFA;
 const SE: u8 = 0xF0;
 const GA: u8 = 0xF9;
+const NOP: u8 = 0xF1;
+/// Telnet ECHO option (RFC 857)
+const ECHO: u8 = 0x01;
+/// Telnet TIMING-MARK option (RFC 860)
+const TIMING_MARK: u8 = 0x06;
+/// Generic MUD Communication Protocol
+const GMCP: u8 = 201;
+
+/// Telnet no-op, used as an application-level keepalive.
+pub const IAC_NOP: [u8; 2] = [IAC, NOP];
+/// Asks the server to mark its output stream; it replies with WILL or WONT
+/// TIMING-MARK once everything before the request has been handled.
+pub const IAC_DO_TIMING_MARK: [u8; 3] = [IAC, DO, TIMING_MARK];
 
 /// Result of processing raw MUD output.
 /// Contains the display text (with IAC stripped), any Telnet responses to send back,
 /// any leftover bytes from incomplete IAC sequences, and whether GA was received.
+#[derive(Default)]
 pub struct ProcessedOutput {
     pub display: String,
     pub responses: Vec<Vec<u8>>,
@@ -20,34 +34,152 @@ pub struct ProcessedOutput {
     /// True if an IAC GA (Go Ahead) was received in this chunk,
     /// signalling the server has finished sending and is awaiting input.
     pub ga: bool,
+    /// Set when the server negotiated ECHO in this chunk: `Some(true)` on
+    /// IAC WILL ECHO (it is echoing itself, e.g. password entry), `Some(false)`
+    /// on IAC WONT ECHO. `None` when unchanged.
+    pub echo: Option<bool>,
+    /// The server sent IAC WILL or WONT TIMING-MARK in this chunk, replying
+    /// to an `IAC_DO_TIMING_MARK` latency probe.
+    pub timing_mark: bool,
+    /// GMCP messages in this chunk as `(package, data)`, the data being the
+    /// JSON text after the package name (empty if none).
+    pub gmcp: Vec<(String, String)>,
+    /// Every telnet command in the chunk, from `process_output_traced` only.
+    pub telnet: Vec<TelnetEvent>,
+}
+
+/// One telnet command (negotiation, subnegotiation or two-byte command).
+#[derive(Debug, Clone)]
+pub struct TelnetEvent {
+    /// The whole sequence, starting with IAC.
+    pub bytes: Vec<u8>,
+    /// What was sent back in reply, if anything.
+    pub response: Option<Vec<u8>>,
+}
+
+impl TelnetEvent {
+    pub fn command(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    /// The option negotiated or subnegotiated, for DO/DONT/WILL/WONT/SB.
+    pub fn option(&self) -> Option<u8> {
+        matches!(self.command(), DO | DONT | WILL | WONT | SB)
+            .then(|| self.bytes.get(2).copied())
+            .flatten()
+    }
+}
+
+/// Name of a telnet command byte (the one after IAC).
+pub fn command_name(command: u8) -> &'static str {
+    match command {
+        SE => "SE",
+        NOP => "NOP",
+        0xF2 => "DM",
+        0xF3 => "BRK",
+        0xF4 => "IP",
+        0xF5 => "AO",
+        0xF6 => "AYT",
+        0xF7 => "EC",
+        0xF8 => "EL",
+        GA => "GA",
+        SB => "SB",
+        WILL => "WILL",
+        WONT => "WONT",
+        DO => "DO",
+        DONT => "DONT",
+        0xEF => "EOR",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Name of a telnet option, for the standard ones and common MUD protocols.
+pub fn option_name(option: u8) -> Option<&'static str> {
+    Some(match option {
+        0 => "BINARY",
+        ECHO => "ECHO",
+        3 => "SGA",
+        5 => "STATUS",
+        6 => "TIMING-MARK",
+        24 => "TTYPE",
+        25 => "EOR",
+        31 => "NAWS",
+        32 => "TSPEED",
+        33 => "LFLOW",
+        34 => "LINEMODE",
+        36 => "ENVIRON",
+        39 => "NEW-ENVIRON",
+        42 => "CHARSET",
+        69 => "MSDP",
+        70 => "MSSP",
+        85 => "MCCP1",
+        86 => "MCCP2",
+        87 => "MCCP3",
+        90 => "MSP",
+        91 => "MXP",
+        93 => "ZMP",
+        200 => "ATCP",
+        201 => "GMCP",
+        _ => return None,
+    })
 }
 
-/// Process raw bytes from the MUD server.
-/// Strips Telnet IAC sequences and generates appropriate responses.
-/// Passes ANSI escape sequences through for xterm.js to render.
-/// Returns any trailing partial IAC sequence as `remainder` for reassembly.
 /// Convert display bytes to String, using fast path for valid UTF-8
 fn display_string(bytes: Vec<u8>) -> String {
     String::from_utf8(bytes)
         .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
 }
 
+/// Process raw bytes from the MUD server.
+/// Strips Telnet IAC sequences and generates appropriate responses.
+/// Passes ANSI escape sequences through for xterm.js to render.
+/// Returns any trailing partial IAC sequence as `remainder` for reassembly.
 pub fn process_output(raw: &[u8]) -> ProcessedOutput {
+    process(raw, false)
+}
+
+/// As `process_output`, also listing each telnet command in `telnet`.
+pub fn process_output_traced(raw: &[u8]) -> ProcessedOutput {
+    process(raw, true)
+}
+
+fn process(raw: &[u8], trace: bool) -> ProcessedOutput {
     let mut display_bytes: Vec<u8> = Vec::with_capacity(raw.len());
     let mut responses: Vec<Vec<u8>> = Vec::with_capacity(4);
     let mut ga = false;
+    let mut echo = None;
+    let mut timing_mark = false;
+    let mut gmcp = Vec::new();
+    let mut telnet = Vec::new();
+    let partial =
+        |display_bytes, responses, remainder: &[u8], ga, echo, timing_mark, gmcp, telnet| {
+            ProcessedOutput {
+                display: display_string(display_bytes),
+                responses,
+                remainder: remainder.to_vec(),
+                ga,
+                echo,
+                timing_mark,
+                gmcp,
+                telnet,
+            }
+        };
     let mut i = 0;
 
     while i < raw.len() {
         if raw[i] == IAC {
             // Not enough bytes to determine the IAC command — save as remainder
             if i + 1 >= raw.len() {
-                return ProcessedOutput {
-                    display: display_string(display_bytes),
+                return partial(
+                    display_bytes,
                     responses,
-                    remainder: raw[i..].to_vec(),
+                    &raw[i..],
                     ga,
-                };
+                    echo,
+                    timing_mark,
+                    gmcp,
+                    telnet,
+                );
             }
 
             match raw[i + 1] {
@@ -60,20 +192,41 @@ pub fn process_output(raw: &[u8]) -> ProcessedOutput {
                 DO | WILL | WONT | DONT => {
                     if i + 2 >= raw.len() {
                         // Incomplete 3-byte sequence — save as remainder
-                        return ProcessedOutput {
-                            display: display_string(display_bytes),
+                        return partial(
+                            display_bytes,
                             responses,
-                            remainder: raw[i..].to_vec(),
+                            &raw[i..],
                             ga,
-                        };
+                            echo,
+                            timing_mark,
+                            gmcp,
+                            telnet,
+                        );
                     }
                     let cmd = raw[i + 1];
                     let option = raw[i + 2];
-                    match cmd {
-                        DO => responses.push(vec![IAC, WONT, option]),
-                        WILL => responses.push(vec![IAC, DONT, option]),
-                        _ => {} // WONT/DONT — just acknowledge by skipping
+                    if option == ECHO && (cmd == WILL || cmd == WONT) {
+                        echo = Some(cmd == WILL);
                     }
+                    if option == TIMING_MARK && (cmd == WILL || cmd == WONT) {
+                        timing_mark = true;
+                    }
+                    let response = match cmd {
+                        DO => Some(vec![IAC, WONT, option]),
+                        // The reply to our own DO TIMING-MARK; nothing to answer
+                        WILL if option == TIMING_MARK => None,
+                        // Accepted so scripts can receive it
+                        WILL if option == GMCP => Some(vec![IAC, DO, option]),
+                        WILL => Some(vec![IAC, DONT, option]),
+                        _ => None, // WONT/DONT — just acknowledge by skipping
+                    };
+                    if trace {
+                        telnet.push(TelnetEvent {
+                            bytes: raw[i..i + 3].to_vec(),
+                            response: response.clone(),
+                        });
+                    }
+                    responses.extend(response);
                     i += 3;
                 }
                 // Subnegotiation — skip until IAC SE
@@ -83,6 +236,15 @@ pub fn process_output(raw: &[u8]) -> ProcessedOutput {
                     let mut j = i + 2;
                     while j < raw.len() {
                         if raw[j] == IAC && j + 1 < raw.len() && raw[j + 1] == SE {
+                            if raw[i + 2] == GMCP {
+                                gmcp.push(gmcp_message(&raw[i + 3..j]));
+                            }
+                            if trace {
+                                telnet.push(TelnetEvent {
+                                    bytes: raw[i..j + 2].to_vec(),
+                                    response: None,
+                                });
+                            }
                             i = j + 2;
                             found_se = true;
                             break;
@@ -91,21 +253,37 @@ pub fn process_output(raw: &[u8]) -> ProcessedOutput {
                     }
                     if !found_se {
                         // Incomplete subnegotiation — save everything from IAC SB onward
-                        return ProcessedOutput {
-                            display: display_string(display_bytes),
+                        return partial(
+                            display_bytes,
                             responses,
-                            remainder: raw[i..].to_vec(),
+                            &raw[i..],
                             ga,
-                        };
+                            echo,
+                            timing_mark,
+                            gmcp,
+                            telnet,
+                        );
                     }
                 }
                 // Go Ahead — server is done sending, prompt is ready
                 GA => {
                     ga = true;
+                    if trace {
+                        telnet.push(TelnetEvent {
+                            bytes: raw[i..i + 2].to_vec(),
+                            response: None,
+                        });
+                    }
                     i += 2;
                 }
                 // Other 2-byte IAC commands (NOP, EOR, etc.) — skip
                 _ => {
+                    if trace {
+                        telnet.push(TelnetEvent {
+                            bytes: raw[i..i + 2].to_vec(),
+                            response: None,
+                        });
+                    }
                     i += 2;
                 }
             }
@@ -115,10 +293,232 @@ pub fn process_output(raw: &[u8]) -> ProcessedOutput {
         }
     }
 
-    ProcessedOutput {
-        display: display_string(display_bytes),
+    partial(
+        display_bytes,
         responses,
-        remainder: Vec::new(),
+        &[],
         ga,
+        echo,
+        timing_mark,
+        gmcp,
+        telnet,
+    )
+}
+
+/// Split a GMCP payload (IAC-escaped) into its package name and data.
+fn gmcp_message(payload: &[u8]) -> (String, String) {
+    let mut bytes = Vec::with_capacity(payload.len());
+    let mut iter = payload.iter().peekable();
+    while let Some(&b) = iter.next() {
+        if b == IAC && iter.peek() == Some(&&IAC) {
+            iter.next();
+        }
+        bytes.push(b);
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    match text.split_once([' ', '\t', '\n']) {
+        Some((package, data)) => (package.to_string(), data.trim().to_string()),
+        None => (text.trim().to_string(), String::new()),
+    }
+}
+
+/// Decode an escaped byte representation such as `\x1b[31mred\xff\xf9`.
+/// Supports `\xHH`, `\e`, `\n`, `\r`, `\t`, `\0` and `\\`; everything else
+/// is taken literally as UTF-8.
+pub fn unescape_bytes(escaped: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0u8; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 2)
+                    .ok_or_else(|| format!("Invalid escape \\x{hex}: expected two hex digits"))?;
+                out.push(byte);
+            }
+            Some('e') => out.push(0x1B),
+            Some('n') => out.push(b'\n'),
+            Some('r') => out.push(b'\r'),
+            Some('t') => out.push(b'\t'),
+            Some('0') => out.push(0),
+            Some('\\') => out.push(b'\\'),
+            Some(other) => return Err(format!("Unknown escape \\{other}")),
+            None => return Err("Trailing backslash".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// Remove ANSI escape sequences (CSI, OSC and two-byte escapes) and carriage
+/// returns, leaving the plain text a line of output reads as. Used for
+/// matching; display text keeps its escapes for xterm.js.
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: parameters/intermediates up to a final byte in @..~
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: terminated by BEL or ESC \
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' && chars.peek() == Some(&'\\') {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Byte length of the escape sequence at the start of `text` (which starts
+/// with ESC), or `None` if the text ends before the sequence does.
+pub fn escape_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    match bytes.get(1)? {
+        b'[' => bytes[2..]
+            .iter()
+            .position(|b| (b'@'..=b'~').contains(b))
+            .map(|pos| pos + 3),
+        b']' => {
+            let body = &bytes[2..];
+            let end = body
+                .iter()
+                .enumerate()
+                .find(|&(i, &b)| b == 0x07 || (b == 0x1b && body.get(i + 1) == Some(&b'\\')))?;
+            Some(2 + end.0 + if *end.1 == 0x07 { 1 } else { 2 })
+        }
+        _ => Some(1 + text[1..].chars().next()?.len_utf8()),
+    }
+}
+
+/// Graphic rendition state as set by `ESC [ … m` sequences, so text inserted
+/// into the stream can put back whatever colors the server had active.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SgrState {
+    /// Bit n is set while attribute n (1 bold … 9 strikethrough) is on.
+    attrs: u16,
+    fg: Option<String>,
+    bg: Option<String>,
+}
+
+impl SgrState {
+    /// Update the state from one escape sequence; anything but SGR is ignored.
+    pub fn apply(&mut self, seq: &str) {
+        let Some(params) = seq.strip_prefix("\x1b[").and_then(|s| s.strip_suffix('m')) else {
+            return;
+        };
+        if !params
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b == b';' || b == b':')
+        {
+            return;
+        }
+        let parts: Vec<&str> = params.split(';').collect();
+        let mut i = 0;
+        while i < parts.len() {
+            let part = parts[i];
+            i += 1;
+            // Colon form, e.g. 38:5:208, kept verbatim
+            if part.contains(':') {
+                match part.split(':').next() {
+                    Some("38") => self.fg = Some(part.to_string()),
+                    Some("48") => self.bg = Some(part.to_string()),
+                    _ => {}
+                }
+                continue;
+            }
+            let code = if part.is_empty() {
+                0
+            } else {
+                part.parse::<u16>().unwrap_or(u16::MAX)
+            };
+            match code {
+                0 => *self = Self::default(),
+                1..=9 => self.attrs |= 1 << code,
+                22 => self.attrs &= !(1 << 1 | 1 << 2),
+                23 => self.attrs &= !(1 << 3),
+                24 => self.attrs &= !(1 << 4),
+                25 => self.attrs &= !(1 << 5 | 1 << 6),
+                27 => self.attrs &= !(1 << 7),
+                28 => self.attrs &= !(1 << 8),
+                29 => self.attrs &= !(1 << 9),
+                30..=37 | 90..=97 => self.fg = Some(part.to_string()),
+                39 => self.fg = None,
+                40..=47 | 100..=107 => self.bg = Some(part.to_string()),
+                49 => self.bg = None,
+                38 | 48 => {
+                    // 38;5;n (256-color) or 38;2;r;g;b (truecolor)
+                    let len = match parts.get(i) {
+                        Some(&"5") => 2,
+                        Some(&"2") => 4,
+                        _ => continue,
+                    };
+                    let end = (i + len).min(parts.len());
+                    let color = Some(parts[i - 1..end].join(";"));
+                    i = end;
+                    if code == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether attribute `attr` (1 bold … 9 strikethrough) is on.
+    pub fn has_attr(&self, attr: u16) -> bool {
+        attr <= 9 && self.attrs & (1 << attr) != 0
+    }
+
+    /// Foreground color parameters as sent (`31`, `38;5;208`, `38:2::r:g:b`).
+    pub fn fg(&self) -> Option<&str> {
+        self.fg.as_deref()
+    }
+
+    /// Background color parameters as sent.
+    pub fn bg(&self) -> Option<&str> {
+        self.bg.as_deref()
+    }
+
+    /// A sequence that resets rendition and then re-applies this state.
+    pub fn restore(&self) -> String {
+        let mut seq = String::from("\x1b[0");
+        for attr in 1..=9 {
+            if self.attrs & (1 << attr) != 0 {
+                seq.push_str(&format!(";{attr}"));
+            }
+        }
+        for color in [&self.fg, &self.bg].into_iter().flatten() {
+            seq.push(';');
+            seq.push_str(color);
+        }
+        seq.push('m');
+        seq
     }
 }
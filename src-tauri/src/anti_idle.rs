@@ -0,0 +1,158 @@
+//! Anti-idle: DartMUD logs a character out after 30 minutes without input.
+//! With anti-idle on, a command from the configured list is sent once no
+//! command has been typed for the interval, picked at random but never the
+//! same twice running. Only a limited number are sent in a row; once they are
+//! used up the character is left to idle out and `mud:idle-limit` is emitted,
+//! so an AFK player isn't kept online for hours. Typing anything starts the
+//! count over.
+//!
+//! Nothing is sent while the server has echo turned off (login and password
+//! screens), and the timer only runs while connected. Commands sent this way
+//! are echoed and logged marked as anti-idle.
+
+use std::sync::Mutex;
+
+use log::info;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::time::{Duration, Instant};
+
+pub const DEFAULT_MINUTES: u64 = 20;
+pub const DEFAULT_MAX_CYCLES: u32 = 6;
+/// Longest allowed list of commands.
+const MAX_COMMANDS: usize = 50;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AntiIdleSettings {
+    pub enabled: bool,
+    /// Minutes without a typed command before one is sent; defaults to 20.
+    pub minutes: Option<u64>,
+    /// Harmless commands to pick from.
+    pub commands: Vec<String>,
+    /// Sends in a row before giving up; defaults to 6.
+    pub max_cycles: Option<u32>,
+}
+
+impl AntiIdleSettings {
+    /// Reject settings that can't be used.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.minutes == Some(0) {
+            return Err("The anti-idle interval must be at least a minute".to_string());
+        }
+        if self.commands.len() > MAX_COMMANDS {
+            return Err(format!(
+                "At most {MAX_COMMANDS} anti-idle commands are allowed"
+            ));
+        }
+        if self.commands.iter().any(|c| c.contains(['\r', '\n'])) {
+            return Err("Anti-idle commands must be single lines".to_string());
+        }
+        if self.enabled && self.commands.iter().all(|c| c.trim().is_empty()) {
+            return Err("Anti-idle needs at least one command".to_string());
+        }
+        Ok(())
+    }
+
+    fn interval(&self) -> Option<Duration> {
+        let minutes = self.minutes.unwrap_or(DEFAULT_MINUTES).max(1);
+        (self.enabled && !self.commands.is_empty()).then(|| Duration::from_secs(minutes * 60))
+    }
+}
+
+/// What the read loop should do at `AntiIdle::deadline`.
+pub enum Tick {
+    /// Write this command.
+    Send(String),
+    /// The cap was reached after this many sends; nothing more is sent.
+    Limit(u32),
+    Idle,
+}
+
+#[derive(Default)]
+struct Inner {
+    settings: AntiIdleSettings,
+    /// The last typed command or anti-idle send; `None` while disconnected.
+    last_activity: Option<Instant>,
+    /// Anti-idle sends since the last typed command.
+    sent: u32,
+    last_pick: Option<usize>,
+    limited: bool,
+}
+
+/// Per-session anti-idle state.
+#[derive(Default)]
+pub struct AntiIdle {
+    inner: Mutex<Inner>,
+}
+
+impl AntiIdle {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Apply new settings, including to a live connection.
+    pub fn configure(&self, settings: AntiIdleSettings) {
+        self.lock().settings = settings;
+    }
+
+    /// Start timing a freshly established connection.
+    pub fn start(&self) {
+        let mut inner = self.lock();
+        inner.last_activity = Some(Instant::now());
+        inner.sent = 0;
+        inner.limited = false;
+    }
+
+    pub fn stop(&self) {
+        self.lock().last_activity = None;
+    }
+
+    /// A command was typed: the idle time and the cap start over.
+    pub fn user_input(&self) {
+        let mut inner = self.lock();
+        if inner.last_activity.is_some() {
+            inner.last_activity = Some(Instant::now());
+        }
+        inner.sent = 0;
+        inner.limited = false;
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        let inner = self.lock();
+        if inner.limited {
+            return None;
+        }
+        Some(inner.last_activity? + inner.settings.interval()?)
+    }
+
+    /// `server_echo` is whether the server has echo turned off (a password
+    /// prompt), which postpones the send by another interval.
+    pub fn on_deadline(&self, server_echo: bool) -> Tick {
+        let mut inner = self.lock();
+        inner.last_activity = Some(Instant::now());
+        let commands: Vec<usize> = (0..inner.settings.commands.len())
+            .filter(|i| !inner.settings.commands[*i].trim().is_empty())
+            .collect();
+        if server_echo || commands.is_empty() {
+            return Tick::Idle;
+        }
+        let max_cycles = inner.settings.max_cycles.unwrap_or(DEFAULT_MAX_CYCLES);
+        if inner.sent >= max_cycles {
+            info!("Anti-idle stopped after {} command(s) in a row", inner.sent);
+            inner.limited = true;
+            return Tick::Limit(inner.sent);
+        }
+        let choices: Vec<usize> = match commands.len() {
+            1 => commands,
+            _ => commands
+                .into_iter()
+                .filter(|i| Some(*i) != inner.last_pick)
+                .collect(),
+        };
+        let pick = choices[rand::thread_rng().gen_range(0..choices.len())];
+        inner.last_pick = Some(pick);
+        inner.sent += 1;
+        Tick::Send(inner.settings.commands[pick].clone())
+    }
+}
@@ -0,0 +1,259 @@
+//! Application logging. Records go to stderr and to `app.log` in the data
+//! dir, so packaged builds (where nobody sees stderr) leave something to
+//! attach to a bug report. The file rotates by size, keeping `app.log.1` and
+//! `app.log.2` behind it.
+//!
+//! The data dir isn't known until setup, so records logged before then are
+//! held in memory (up to `MAX_PENDING_BYTES`) and written once the file is
+//! opened. The filter uses `RUST_LOG` syntax and can be changed at runtime
+//! with `set_log_level`.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+use env_filter::Filter;
+use log::{Log, Metadata, Record};
+
+pub const APP_LOG_FILE: &str = "app.log";
+/// Rotate once the file reaches this size.
+const MAX_LOG_BYTES: u64 = 2 * 1024 * 1024;
+/// Files kept, counting `app.log` itself.
+const KEEP_FILES: usize = 3;
+/// Records held before the file is opened; the oldest are dropped past this.
+const MAX_PENDING_BYTES: usize = 256 * 1024;
+/// Used when neither `RUST_LOG` nor `--log-level` says otherwise.
+const DEFAULT_FILTER: &str = "info";
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+
+    /// Shift `app.log` → `app.log.1` → `app.log.2`, dropping the oldest, and
+    /// start a new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        let numbered = |n: usize| self.path.with_file_name(format!("{APP_LOG_FILE}.{n}"));
+        let _ = fs::remove_file(numbered(KEEP_FILES - 1));
+        for n in (1..KEEP_FILES - 1).rev() {
+            let _ = fs::rename(numbered(n), numbered(n + 1));
+        }
+        fs::rename(&self.path, numbered(1))?;
+        *self = Self::open(self.path.clone())?;
+        Ok(())
+    }
+
+    fn write(&mut self, line: &[u8]) {
+        if self.size + line.len() as u64 > MAX_LOG_BYTES && self.size > 0 {
+            if let Err(e) = self.rotate() {
+                let _ = writeln!(
+                    io::stderr(),
+                    "Failed to rotate {}: {e}",
+                    self.path.display()
+                );
+            }
+        }
+        if self.file.write_all(line).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Sink {
+    file: Option<LogFile>,
+    /// Records from before the file was opened.
+    pending: VecDeque<Vec<u8>>,
+    pending_bytes: usize,
+    /// Once a file has been opened (or given up on), nothing more is held.
+    attached_once: bool,
+}
+
+struct AppLogger {
+    filter: RwLock<(Filter, String)>,
+    sink: Mutex<Sink>,
+}
+
+static LOGGER: std::sync::OnceLock<AppLogger> = std::sync::OnceLock::new();
+
+fn build_filter(spec: &str) -> Result<Filter, String> {
+    let mut builder = env_filter::Builder::new();
+    builder.try_parse(spec).map_err(|e| e.to_string())?;
+    Ok(builder.build())
+}
+
+/// Check a filter in `RUST_LOG` syntax, e.g. `debug` or `dartforge_lib=trace,info`.
+pub fn validate_filter(spec: &str) -> Result<(), String> {
+    build_filter(spec).map(|_| ())
+}
+
+impl Log for AppLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .0
+            .enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self
+            .filter
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .0
+            .matches(record)
+        {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} {}: {}\n",
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        let _ = io::stderr().write_all(line.as_bytes());
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(file) = sink.file.as_mut() {
+            file.write(line.as_bytes());
+        } else if !sink.attached_once {
+            sink.pending_bytes += line.len();
+            sink.pending.push_back(line.into_bytes());
+            while sink.pending_bytes > MAX_PENDING_BYTES {
+                let Some(dropped) = sink.pending.pop_front() else {
+                    break;
+                };
+                sink.pending_bytes -= dropped.len();
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self
+            .sink
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .file
+            .as_mut()
+        {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Install the logger: `RUST_LOG` (or `info`), then `filter` from
+/// `--log-level` on top.
+pub fn init(filter: Option<&str>) {
+    let mut spec = std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_FILTER.to_string());
+    if let Some(filter) = filter {
+        spec = format!("{spec},{filter}");
+    }
+    let built = build_filter(&spec).unwrap_or_else(|_| {
+        spec = DEFAULT_FILTER.to_string();
+        build_filter(DEFAULT_FILTER).expect("default log filter is valid")
+    });
+    let max_level = built.filter();
+    let logger = LOGGER.get_or_init(|| AppLogger {
+        filter: RwLock::new((built, spec)),
+        sink: Mutex::default(),
+    });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Log to `app.log` in `dir` from now on, instead of any previous data dir.
+/// Records held from before the first call are written first.
+pub fn attach(dir: &Path) {
+    let Some(logger) = LOGGER.get() else {
+        return;
+    };
+    let path = dir.join(APP_LOG_FILE);
+    let opened = LogFile::open(path.clone());
+    let mut sink = logger.sink.lock().unwrap_or_else(|e| e.into_inner());
+    sink.attached_once = true;
+    match opened {
+        Ok(mut file) => {
+            for line in std::mem::take(&mut sink.pending) {
+                file.write(&line);
+            }
+            sink.pending_bytes = 0;
+            sink.file = Some(file);
+        }
+        Err(e) => {
+            sink.file = None;
+            drop(sink);
+            log::warn!("Failed to open {}: {e}", path.display());
+        }
+    }
+}
+
+/// Stop writing to the log file, e.g. when another instance owns the data dir.
+pub fn detach() {
+    if let Some(logger) = LOGGER.get() {
+        logger.sink.lock().unwrap_or_else(|e| e.into_inner()).file = None;
+    }
+}
+
+/// The filter in use, in `RUST_LOG` syntax.
+pub fn current_filter() -> String {
+    LOGGER
+        .get()
+        .map(|logger| {
+            logger
+                .filter
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .1
+                .clone()
+        })
+        .unwrap_or_default()
+}
+
+/// The log files in `dir`, newest first: `app.log`, then the rotated ones.
+pub fn log_files(dir: &Path) -> Vec<PathBuf> {
+    std::iter::once(dir.join(APP_LOG_FILE))
+        .chain((1..KEEP_FILES).map(|n| dir.join(format!("{APP_LOG_FILE}.{n}"))))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// Up to the last `max_bytes` of the text file at `path`, from a line boundary.
+pub fn tail_file(path: &Path, max_bytes: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(max_bytes);
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.take(max_bytes).read_to_end(&mut bytes)?;
+    if start > 0 {
+        if let Some(pos) = bytes.iter().position(|&b| b == b'\n') {
+            bytes.drain(..=pos);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Change which records are logged, without a restart. `level` uses
+/// `RUST_LOG` syntax: a level (`debug`) or `target=level` directives. Lasts
+/// until the app exits.
+#[tauri::command]
+pub fn set_log_level(level: String) -> Result<(), String> {
+    let logger = LOGGER.get().ok_or("Logging is not set up")?;
+    let filter = build_filter(&level)?;
+    log::set_max_level(filter.filter());
+    *logger.filter.write().unwrap_or_else(|e| e.into_inner()) = (filter, level.clone());
+    log::info!("Log filter set to {level}");
+    Ok(())
+}
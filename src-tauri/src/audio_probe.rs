@@ -0,0 +1,476 @@
+//! Identify imported sound files by content, and read their duration, with
+//! just enough header parsing for each supported container: WAV (RIFF),
+//! MP3 (ID3 / MPEG frame sync, Xing or CBR), Ogg (Vorbis / Opus) and WebM
+//! (EBML).
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+    Ogg,
+    Webm,
+}
+
+impl AudioFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Ogg => "ogg",
+            AudioFormat::Webm => "webm",
+        }
+    }
+}
+
+/// Enough of a file to sniff it, or to find an MP3's first frame after its
+/// tag.
+const HEADER_BYTES: u64 = 64 * 1024;
+/// How far into a WebM file to look for its duration.
+const WEBM_SCAN_BYTES: u64 = 1024 * 1024;
+/// How far back from the end of an Ogg file to look for its last page.
+const OGG_TAIL_BYTES: u64 = 64 * 1024;
+
+fn read_range(file: &mut File, start: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    file.take(len).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// The supported format `header` starts with, if any.
+pub fn sniff(header: &[u8]) -> Option<AudioFormat> {
+    if header.len() >= 12 && &header[..4] == b"RIFF" && &header[8..12] == b"WAVE" {
+        Some(AudioFormat::Wav)
+    } else if header.starts_with(b"OggS") {
+        Some(AudioFormat::Ogg)
+    } else if header.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        Some(AudioFormat::Webm)
+    } else if header.starts_with(b"ID3") || mp3_frame(header).is_some() {
+        Some(AudioFormat::Mp3)
+    } else {
+        None
+    }
+}
+
+/// What an unsupported file looks like, for the rejection message.
+pub fn describe(header: &[u8]) -> &'static str {
+    const KNOWN: &[(usize, &[u8], &str)] = &[
+        (0, b"MZ", "a Windows executable"),
+        (0, b"\x7fELF", "a Linux executable"),
+        (0, &[0xCF, 0xFA, 0xED, 0xFE], "a macOS executable"),
+        (0, b"PK\x03\x04", "a zip archive"),
+        (0, b"%PDF", "a PDF document"),
+        (0, b"\x89PNG", "a PNG image"),
+        (0, &[0xFF, 0xD8, 0xFF], "a JPEG image"),
+        (0, b"GIF8", "a GIF image"),
+        (0, b"fLaC", "FLAC audio"),
+        (4, b"ftyp", "MP4/M4A media"),
+        (0, b"#!", "a script"),
+    ];
+    KNOWN
+        .iter()
+        .find(|(offset, magic, _)| header.get(*offset..offset + magic.len()) == Some(*magic))
+        .map_or("unrecognized data", |(_, _, name)| *name)
+}
+
+pub enum Probe {
+    /// `duration_secs` is `None` if it couldn't be worked out.
+    Audio {
+        format: AudioFormat,
+        duration_secs: Option<f64>,
+    },
+    /// Not a supported format; what it looks like instead.
+    Other(&'static str),
+}
+
+/// Sniff the file at `path` and, if it's supported audio, read its duration.
+pub fn probe(path: &Path) -> io::Result<Probe> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let header = read_range(&mut file, 0, HEADER_BYTES)?;
+    let Some(format) = sniff(&header) else {
+        return Ok(Probe::Other(describe(&header)));
+    };
+    let duration = match format {
+        AudioFormat::Wav => wav_duration(&mut file),
+        AudioFormat::Mp3 => mp3_duration(&header, &mut file, len),
+        AudioFormat::Ogg => ogg_duration(&header, &mut file, len),
+        AudioFormat::Webm => webm_duration(&read_range(&mut file, 0, WEBM_SCAN_BYTES)?),
+    };
+    let duration_secs = duration.filter(|secs| secs.is_finite() && *secs >= 0.0);
+    Ok(Probe::Audio {
+        format,
+        duration_secs,
+    })
+}
+
+fn u16_le(b: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(b.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_le(b: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(b.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u32_be(b: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(b.get(at..at + 4)?.try_into().ok()?))
+}
+
+/* ── WAV ─────────────────────────────────────────────────── */
+
+/// The `data` chunk's size over the `fmt ` chunk's byte rate.
+fn wav_duration(file: &mut File) -> Option<f64> {
+    let mut pos = 12u64;
+    let mut byte_rate = None;
+    loop {
+        let header = read_range(file, pos, 8).ok()?;
+        let size = u32_le(&header, 4)? as u64;
+        match &header[..4] {
+            b"fmt " => byte_rate = u32_le(&read_range(file, pos + 8, 16).ok()?, 8),
+            b"data" => return Some(size as f64 / f64::from(byte_rate.filter(|r| *r > 0)?)),
+            _ => {}
+        }
+        // Chunks are padded to an even size
+        pos += 8 + size + (size & 1);
+    }
+}
+
+/* ── MP3 ─────────────────────────────────────────────────── */
+
+struct Mp3Frame {
+    /// Bits per second.
+    bitrate: u32,
+    sample_rate: u32,
+    samples_per_frame: u32,
+    /// MPEG-1 rather than 2 / 2.5, which moves the Xing header.
+    mpeg1: bool,
+    mono: bool,
+}
+
+/// Parse an MPEG audio frame header at the start of `b`.
+fn mp3_frame(b: &[u8]) -> Option<Mp3Frame> {
+    let header = u32_be(b, 0)?;
+    if header >> 21 != 0x7FF {
+        return None;
+    }
+    let version = (header >> 19) & 3; // 3 = MPEG-1, 2 = MPEG-2, 0 = MPEG-2.5
+    let layer = (header >> 17) & 3; // 1 = layer III
+    let bitrate_index = ((header >> 12) & 0xF) as usize;
+    let rate_index = ((header >> 10) & 3) as usize;
+    if version == 1 || layer != 1 || bitrate_index == 0 || bitrate_index == 15 || rate_index == 3 {
+        return None;
+    }
+    const BITRATES_V1: [u32; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const BITRATES_V2: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    const RATES: [u32; 3] = [44100, 48000, 32000];
+    let mpeg1 = version == 3;
+    let (bitrate, divisor) = match version {
+        3 => (BITRATES_V1[bitrate_index], 1),
+        2 => (BITRATES_V2[bitrate_index], 2),
+        _ => (BITRATES_V2[bitrate_index], 4),
+    };
+    Some(Mp3Frame {
+        bitrate: bitrate * 1000,
+        sample_rate: RATES[rate_index] / divisor,
+        samples_per_frame: if mpeg1 { 1152 } else { 576 },
+        mpeg1,
+        mono: (header >> 6) & 3 == 3,
+    })
+}
+
+/// From the Xing/Info frame count when there is one, otherwise estimated
+/// from the first frame's bitrate, as for a constant-bitrate file.
+fn mp3_duration(header: &[u8], file: &mut File, len: u64) -> Option<f64> {
+    let mut start = 0;
+    if header.starts_with(b"ID3") {
+        // The tag size is syncsafe: 7 bits per byte
+        let size = header
+            .get(6..10)?
+            .iter()
+            .fold(0u64, |acc, b| (acc << 7) | u64::from(b & 0x7F));
+        start = 10 + size;
+    }
+    // Tags with cover art can run well past the sniffed header
+    let header = read_range(file, start, HEADER_BYTES).ok()?;
+    let first =
+        (0..header.len().saturating_sub(4)).find(|&at| mp3_frame(&header[at..]).is_some())?;
+    let frame = mp3_frame(&header[first..])?;
+    let side_info = match (frame.mpeg1, frame.mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let xing = first + 4 + side_info;
+    if matches!(header.get(xing..xing + 4), Some(b"Xing" | b"Info"))
+        && u32_be(&header, xing + 4)? & 1 == 1
+    {
+        let frames = u32_be(&header, xing + 8)?;
+        return Some(
+            f64::from(frames) * f64::from(frame.samples_per_frame) / f64::from(frame.sample_rate),
+        );
+    }
+    Some(len.saturating_sub(start + first as u64) as f64 * 8.0 / f64::from(frame.bitrate))
+}
+
+/* ── Ogg ─────────────────────────────────────────────────── */
+
+/// The last page's granule position over the stream's sample rate (always
+/// 48 kHz for Opus, less its pre-skip).
+fn ogg_duration(header: &[u8], file: &mut File, len: u64) -> Option<f64> {
+    let (rate, pre_skip) = if let Some(at) = find(header, b"\x01vorbis") {
+        (u32_le(header, at + 12)?, 0)
+    } else if let Some(at) = find(header, b"OpusHead") {
+        (48000, u16_le(header, at + 10)?)
+    } else {
+        return None;
+    };
+    let tail_start = len.saturating_sub(OGG_TAIL_BYTES);
+    let tail = read_range(file, tail_start, OGG_TAIL_BYTES).ok()?;
+    let last_page = tail.windows(4).rposition(|w| w == b"OggS")?;
+    let granule = u64::from_le_bytes(tail.get(last_page + 6..last_page + 14)?.try_into().ok()?);
+    if rate == 0 {
+        return None;
+    }
+    Some(granule.saturating_sub(pre_skip.into()) as f64 / f64::from(rate))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/* ── WebM ────────────────────────────────────────────────── */
+
+const EBML_SEGMENT: u64 = 0x1853_8067;
+const EBML_INFO: u64 = 0x1549_A966;
+const EBML_TIMECODE_SCALE: u64 = 0x2A_D7B1;
+const EBML_DURATION: u64 = 0x4489;
+
+/// An EBML variable-length integer at `at`: its value (with the length
+/// marker kept for IDs, dropped for sizes) and how many bytes it took.
+/// Sizes of all ones mean "unknown" and come back as `None`.
+fn ebml_vint(b: &[u8], at: usize, keep_marker: bool) -> Option<(Option<u64>, usize)> {
+    let first = *b.get(at)?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 {
+        return None;
+    }
+    let mut value = if keep_marker {
+        u64::from(first)
+    } else {
+        u64::from(first) & (0xFF >> len)
+    };
+    for i in 1..len {
+        value = (value << 8) | u64::from(*b.get(at + i)?);
+    }
+    let all_ones = !keep_marker && value == (1u64 << (7 * len)) - 1;
+    Some(((!all_ones).then_some(value), len))
+}
+
+/// Segment > Info > Duration, in units of TimecodeScale nanoseconds.
+fn webm_duration(b: &[u8]) -> Option<f64> {
+    let mut pos = 0;
+    let mut end = b.len();
+    let mut scale = 1_000_000u64;
+    let mut duration = None;
+    while pos < end {
+        let (id, id_len) = ebml_vint(b, pos, true)?;
+        let (size, size_len) = ebml_vint(b, pos + id_len, false)?;
+        let data = pos + id_len + size_len;
+        let id = id?;
+        match id {
+            // Containers: step inside
+            EBML_SEGMENT => {
+                pos = data;
+                continue;
+            }
+            EBML_INFO => {
+                // Stop at the end of Info; its children are all we need
+                end = size.map_or(end, |size| end.min(data.saturating_add(size as usize)));
+                pos = data;
+                continue;
+            }
+            _ => {}
+        }
+        let size = size? as usize;
+        let value = b.get(data..data.saturating_add(size));
+        match (id, value) {
+            (EBML_TIMECODE_SCALE, Some(v)) => {
+                scale = v.iter().fold(0, |acc, byte| (acc << 8) | u64::from(*byte))
+            }
+            (EBML_DURATION, Some(v)) if v.len() == 4 => {
+                duration = Some(f64::from(f32::from_be_bytes(v.try_into().ok()?)))
+            }
+            (EBML_DURATION, Some(v)) if v.len() == 8 => {
+                duration = Some(f64::from_be_bytes(v.try_into().ok()?))
+            }
+            _ => {}
+        }
+        pos = data.saturating_add(size);
+    }
+    Some(duration? * scale as f64 / 1e9)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe_bytes(name: &str, bytes: &[u8]) -> Probe {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, bytes).unwrap();
+        probe(&path).unwrap()
+    }
+
+    fn audio(probe: Probe) -> (AudioFormat, Option<f64>) {
+        match probe {
+            Probe::Audio {
+                format,
+                duration_secs,
+            } => (format, duration_secs),
+            Probe::Other(kind) => panic!("not audio: {kind}"),
+        }
+    }
+
+    fn assert_secs(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("no duration");
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{actual} s, not {expected} s"
+        );
+    }
+
+    /// 16-bit mono at 44.1 kHz, with an odd-sized chunk before the data.
+    fn wav(data_bytes: u32) -> Vec<u8> {
+        let mut b = b"RIFF\0\0\0\0WAVE".to_vec();
+        b.extend_from_slice(b"fmt ");
+        b.extend_from_slice(&16u32.to_le_bytes());
+        b.extend_from_slice(&[1, 0, 1, 0]);
+        b.extend_from_slice(&44100u32.to_le_bytes());
+        b.extend_from_slice(&88200u32.to_le_bytes());
+        b.extend_from_slice(&[2, 0, 16, 0]);
+        b.extend_from_slice(b"LIST");
+        b.extend_from_slice(&3u32.to_le_bytes());
+        b.extend_from_slice(b"abc\0");
+        b.extend_from_slice(b"data");
+        b.extend_from_slice(&data_bytes.to_le_bytes());
+        b.resize(b.len() + data_bytes as usize, 0);
+        b
+    }
+
+    /// MPEG-1 layer III, 128 kbps, 44.1 kHz, stereo.
+    const MP3_FRAME: [u8; 4] = [0xFF, 0xFB, 0x90, 0x00];
+
+    #[test]
+    fn wav_duration_from_data_and_byte_rate() {
+        let (format, secs) = audio(probe_bytes("ding.wav", &wav(44100)));
+        assert_eq!(format, AudioFormat::Wav);
+        assert_secs(secs, 0.5);
+    }
+
+    #[test]
+    fn cbr_mp3_after_id3_tag() {
+        let mut b = b"ID3\x04\0\0\0\0\0\x64".to_vec();
+        b.resize(10 + 100, 0);
+        for _ in 0..40 {
+            b.extend_from_slice(&MP3_FRAME);
+            b.resize(b.len() + 396, 0);
+        }
+        // 128 kbps over 16000 bytes of frames
+        let (format, secs) = audio(probe_bytes("ding.mp3", &b));
+        assert_eq!(format, AudioFormat::Mp3);
+        assert_secs(secs, 1.0);
+    }
+
+    #[test]
+    fn vbr_mp3_from_xing_frame_count() {
+        let mut b = MP3_FRAME.to_vec();
+        b.resize(4 + 32, 0);
+        b.extend_from_slice(b"Xing");
+        b.extend_from_slice(&1u32.to_be_bytes());
+        b.extend_from_slice(&100u32.to_be_bytes());
+        b.resize(4096, 0);
+        let (format, secs) = audio(probe_bytes("ding.mp3", &b));
+        assert_eq!(format, AudioFormat::Mp3);
+        assert_secs(secs, 100.0 * 1152.0 / 44100.0);
+    }
+
+    fn ogg_page(granule: u64, packet: &[u8]) -> Vec<u8> {
+        let mut b = b"OggS\0\x02".to_vec();
+        b.extend_from_slice(&granule.to_le_bytes());
+        b.resize(b.len() + 13, 0);
+        b.extend_from_slice(packet);
+        b
+    }
+
+    #[test]
+    fn ogg_vorbis_and_opus_from_last_granule() {
+        let mut ident = b"\x01vorbis\0\0\0\0\x02".to_vec();
+        ident.extend_from_slice(&44100u32.to_le_bytes());
+        let mut vorbis = ogg_page(0, &ident);
+        vorbis.extend(ogg_page(88200, &[0; 100]));
+        let (format, secs) = audio(probe_bytes("ding.ogg", &vorbis));
+        assert_eq!(format, AudioFormat::Ogg);
+        assert_secs(secs, 2.0);
+
+        let mut head = b"OpusHead\x01\x02".to_vec();
+        head.extend_from_slice(&312u16.to_le_bytes());
+        let mut opus = ogg_page(0, &head);
+        opus.extend(ogg_page(48000 + 312, &[0; 100]));
+        assert_secs(audio(probe_bytes("ding.ogg", &opus)).1, 1.0);
+    }
+
+    #[test]
+    fn webm_duration_from_segment_info() {
+        let mut b = vec![0x1A, 0x45, 0xDF, 0xA3, 0x84, 0x42, 0x86, 0x81, 0x01];
+        // Segment of unknown size
+        b.extend_from_slice(&[
+            0x18, 0x53, 0x80, 0x67, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        ]);
+        b.extend_from_slice(&[0x15, 0x49, 0xA9, 0x66, 0x8B]);
+        b.extend_from_slice(&[0x2A, 0xD7, 0xB1, 0x83, 0x0F, 0x42, 0x40]);
+        b.extend_from_slice(&[0x44, 0x89, 0x84]);
+        b.extend_from_slice(&1500f32.to_be_bytes());
+        let (format, secs) = audio(probe_bytes("ding.webm", &b));
+        assert_eq!(format, AudioFormat::Webm);
+        assert_secs(secs, 1.5);
+    }
+
+    #[test]
+    fn content_wins_over_extension() {
+        let (format, _) = audio(probe_bytes("ding.mp3", &wav(100)));
+        assert_eq!(format, AudioFormat::Wav);
+        let Probe::Other(kind) = probe_bytes("ding.mp3", b"MZ\x90\0\x03\0\0\0") else {
+            panic!("executable taken for audio");
+        };
+        assert_eq!(kind, "a Windows executable");
+    }
+
+    #[test]
+    fn sniffing_and_describing_other_data() {
+        assert_eq!(sniff(b""), None);
+        assert_eq!(sniff(b"RIFF\0\0\0\0AVI "), None);
+        // Reserved MPEG version and layer I frames aren't taken for mp3
+        assert_eq!(sniff(&[0xFF, 0xEB, 0x90, 0x00]), None);
+        assert_eq!(sniff(&[0xFF, 0xFF, 0x90, 0x00]), None);
+        assert_eq!(describe(b"fLaC\0\0\0\x22"), "FLAC audio");
+        assert_eq!(describe(b"\0\0\0\x20ftypM4A "), "MP4/M4A media");
+        assert_eq!(describe(b"hello"), "unrecognized data");
+    }
+
+    #[test]
+    fn damaged_headers_give_no_duration() {
+        let mut b = wav(0);
+        b.truncate(30);
+        assert_eq!(audio(probe_bytes("ding.wav", &b)), (AudioFormat::Wav, None));
+        assert_eq!(
+            audio(probe_bytes("ding.ogg", b"OggS\0\x02 no codec here")),
+            (AudioFormat::Ogg, None)
+        );
+    }
+}
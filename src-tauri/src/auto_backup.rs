@@ -0,0 +1,128 @@
+//! Automatic backups while the app runs. Shortly after startup and then
+//! every few minutes, the newest backup with the auto tag is checked; once
+//! it's older than the configured interval a new one is made and backups are
+//! pruned by the retention policy. Each attempt is reported as a
+//! `storage:auto-backup` event.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::time::Duration;
+
+use crate::events::{AutoBackupPayload, AUTO_BACKUP_EVENT};
+use crate::storage::{self, StorageState};
+
+pub const AUTO_BACKUP_FILE: &str = "autobackup.json";
+/// Gives the frontend time to resolve the data directory first.
+const STARTUP_DELAY: Duration = Duration::from_secs(60);
+const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoBackupSettings {
+    pub interval_hours: u32,
+    pub tag: String,
+    /// Backups to keep per tag; `None` keeps every backup with that tag.
+    /// Tags not listed fall back to `prune_backups`'s `keep`.
+    pub retention: HashMap<String, Option<usize>>,
+}
+
+impl Default for AutoBackupSettings {
+    fn default() -> Self {
+        Self {
+            interval_hours: 1,
+            tag: "auto".to_string(),
+            retention: HashMap::from([
+                ("auto".to_string(), Some(24)),
+                ("session-start".to_string(), Some(30)),
+                ("pre-restore".to_string(), Some(3)),
+                ("pre-import".to_string(), Some(3)),
+                ("manual".to_string(), None),
+            ]),
+        }
+    }
+}
+
+impl AutoBackupSettings {
+    pub fn load(dir: &Path) -> Self {
+        storage::read_json_file(dir, AUTO_BACKUP_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        storage::write_json_file(dir, AUTO_BACKUP_FILE, self)
+    }
+}
+
+/// The settings panel's auto-backup switch, which lives in the frontend's
+/// `settings.json`. On unless turned off.
+fn enabled(dir: &Path) -> bool {
+    storage::read_json_file::<serde_json::Value>(dir, "settings.json")
+        .and_then(|settings| settings.get("autoBackupEnabled")?.as_bool())
+        .unwrap_or(true)
+}
+
+/// Make a backup if one is due, and prune afterwards. `None` if none was due.
+fn backup_if_due(app: &AppHandle) -> Option<Result<String, String>> {
+    let state = app.state::<StorageState>();
+    let dir = state.get_dir();
+    if !enabled(&dir) {
+        return None;
+    }
+    let settings = AutoBackupSettings::load(&dir);
+    let due_after = chrono::Duration::hours(settings.interval_hours.max(1).into());
+    let newest = storage::newest_backup_time(&dir.join("backups"), &settings.tag);
+    if newest.is_some_and(|at| chrono::Local::now().naive_local() - at < due_after) {
+        return None;
+    }
+    let result = storage::create_backup(
+        settings.tag.clone(),
+        None,
+        None,
+        None,
+        None,
+        app.clone(),
+        state.clone(),
+    );
+    if result.is_ok() {
+        storage::prune_backups_by_policy(&dir.join("backups"), None, &settings.retention);
+    }
+    Some(result)
+}
+
+/// Check for a due backup every `CHECK_INTERVAL` for the life of the app.
+pub async fn run(app: AppHandle) {
+    tokio::time::sleep(STARTUP_DELAY).await;
+    loop {
+        let handle = app.clone();
+        if let Ok(Some(result)) = tokio::task::spawn_blocking(move || backup_if_due(&handle)).await
+        {
+            let tag = AutoBackupSettings::load(&app.state::<StorageState>().get_dir()).tag;
+            let payload = match result {
+                // An empty name means there was nothing to back up
+                Ok(filename) => {
+                    if !filename.is_empty() {
+                        info!("Auto-backup written to {filename}");
+                    }
+                    AutoBackupPayload {
+                        tag,
+                        filename: Some(filename).filter(|f| !f.is_empty()),
+                        error: None,
+                    }
+                }
+                Err(e) => {
+                    warn!("Auto-backup failed: {e}");
+                    AutoBackupPayload {
+                        tag,
+                        filename: None,
+                        error: Some(e),
+                    }
+                }
+            };
+            let _ = app.emit(AUTO_BACKUP_EVENT, payload);
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
@@ -0,0 +1,122 @@
+//! Automatic login for connection profiles: sends the character name when the
+//! login prompt appears (or after a fixed delay), the password when asked for
+//! it, and then any post-login commands.
+
+use regex::Regex;
+use tokio::time::{Duration, Instant};
+
+use crate::connection::Outgoing;
+
+/// DartMUD's name prompt.
+pub const DEFAULT_LOGIN_PROMPT: &str = r"(?i)what name shall you be known by";
+const PASSWORD_PROMPT: &str = r"(?i)password:";
+/// How much recent output is kept for prompt matching, so a prompt split
+/// across two reads still matches.
+const MATCH_WINDOW: usize = 512;
+
+#[derive(Clone)]
+pub struct AutoLogin {
+    pub username: String,
+    pub password: Option<String>,
+    pub commands: Vec<String>,
+    pub prompt: Regex,
+    /// Send everything after this delay instead of watching for prompts.
+    pub delay: Option<Duration>,
+}
+
+enum Stage {
+    AwaitName,
+    AwaitPassword,
+    Done,
+}
+
+/// Drives one connection's login from its incoming output.
+pub struct LoginDriver {
+    login: AutoLogin,
+    stage: Stage,
+    recent: String,
+    password_prompt: Regex,
+    deadline: Option<Instant>,
+}
+
+impl LoginDriver {
+    pub fn new(login: AutoLogin) -> Self {
+        let deadline = login.delay.map(|delay| Instant::now() + delay);
+        Self {
+            login,
+            stage: Stage::AwaitName,
+            recent: String::new(),
+            password_prompt: Regex::new(PASSWORD_PROMPT).expect("valid password prompt regex"),
+            deadline,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.stage, Stage::Done)
+    }
+
+    /// When the delayed login fires, in delay mode.
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.stage {
+            Stage::AwaitName => self.deadline,
+            _ => None,
+        }
+    }
+
+    /// Feed display text from the server; returns anything to send now.
+    pub fn on_output(&mut self, text: &str) -> Vec<Outgoing> {
+        if self.deadline.is_some() || self.is_done() {
+            return Vec::new();
+        }
+        self.recent.push_str(text);
+        if self.recent.len() > MATCH_WINDOW {
+            let mut cut = self.recent.len() - MATCH_WINDOW;
+            while !self.recent.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.recent.drain(..cut);
+        }
+
+        match self.stage {
+            Stage::AwaitName if self.login.prompt.is_match(&self.recent) => {
+                self.recent.clear();
+                let mut out = vec![Outgoing::Command(self.login.username.clone())];
+                if self.login.password.is_some() {
+                    self.stage = Stage::AwaitPassword;
+                } else {
+                    out.extend(self.finish());
+                }
+                out
+            }
+            Stage::AwaitPassword if self.password_prompt.is_match(&self.recent) => {
+                self.recent.clear();
+                let mut out: Vec<Outgoing> = self
+                    .login
+                    .password
+                    .clone()
+                    .map(Outgoing::Secret)
+                    .into_iter()
+                    .collect();
+                out.extend(self.finish());
+                out
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The login delay elapsed: send the whole sequence at once.
+    pub fn on_deadline(&mut self) -> Vec<Outgoing> {
+        let mut out = vec![Outgoing::Command(self.login.username.clone())];
+        out.extend(self.login.password.clone().map(Outgoing::Secret));
+        out.extend(self.finish());
+        out
+    }
+
+    fn finish(&mut self) -> Vec<Outgoing> {
+        self.stage = Stage::Done;
+        std::mem::take(&mut self.login.commands)
+            .into_iter()
+            .map(Outgoing::Command)
+            .collect()
+    }
+}
@@ -0,0 +1,319 @@
+//! Passphrase encryption for backup zips. The finished zip is encrypted as a
+//! whole, so an encrypted backup reveals nothing but its size; a magic header
+//! marks it, so it can be recognised without the passphrase.
+//!
+//! The key comes from Argon2id with a random salt, and the zip is sealed with
+//! XChaCha20-Poly1305 in 64 KiB chunks (the STREAM construction), so damage or
+//! truncation anywhere is caught without holding the whole file in memory.
+//! The header also holds a check value derived from the passphrase, so a wrong
+//! passphrase is told apart from a damaged file.
+//!
+//! Layout: magic, format version, Argon2 memory/time/lanes (u32 LE each),
+//! salt, stream nonce, check value, then the chunks, each authenticated along
+//! with the header. The last chunk is always shorter than a full one.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305};
+use rand::RngCore;
+use subtle::ConstantTimeEq as _;
+
+const MAGIC: &[u8; 7] = b"DFBKENC";
+const VERSION: u8 = b'1';
+const SALT_LEN: usize = 16;
+/// XChaCha20's 24-byte nonce less the 5 bytes STREAM uses for its counter.
+const NONCE_LEN: usize = 19;
+const CHECK_LEN: usize = 32;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 3 * 4 + SALT_LEN + NONCE_LEN + CHECK_LEN;
+const TAG_LEN: usize = 16;
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Argon2id cost: 64 MiB, three passes, one lane.
+const KDF: Kdf = Kdf {
+    memory_kib: 64 * 1024,
+    passes: 3,
+    lanes: 1,
+};
+/// Far above anything this writes; a header asking for more is damaged.
+const MAX_KDF: Kdf = Kdf {
+    memory_kib: 1 << 20,
+    passes: 100,
+    lanes: 16,
+};
+
+pub enum DecryptError {
+    WrongPassphrase,
+    Corrupt(String),
+}
+
+#[derive(Clone, Copy)]
+struct Kdf {
+    memory_kib: u32,
+    passes: u32,
+    lanes: u32,
+}
+
+/// Whether `path` starts with the encrypted backup magic. A version this
+/// build can't read still counts, so opening it says why.
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut magic = [0u8; MAGIC.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && &magic == MAGIC
+}
+
+/// The encryption key and the passphrase check value.
+fn derive_keys(passphrase: &str, salt: &[u8], kdf: Kdf) -> Result<([u8; 32], [u8; 32]), String> {
+    let params =
+        Params::new(kdf.memory_kib, kdf.passes, kdf.lanes, Some(64)).map_err(|e| e.to_string())?;
+    let mut out = [0u8; 64];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut out)
+        .map_err(|e| e.to_string())?;
+    let (key, check) = out.split_at(32);
+    Ok((
+        key.try_into().unwrap_or_default(),
+        check.try_into().unwrap_or_default(),
+    ))
+}
+
+/// Read until `buf` is full or the input ends.
+fn fill(input: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Encrypt the file at `source` into a new file at `dest`.
+pub fn encrypt(source: &Path, dest: &Path, passphrase: &str) -> Result<(), String> {
+    encrypt_with(source, dest, passphrase, KDF)
+}
+
+fn encrypt_with(source: &Path, dest: &Path, passphrase: &str, kdf: Kdf) -> Result<(), String> {
+    let fail = |e: io::Error| format!("Failed to encrypt backup: {e}");
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    let (key, check) = derive_keys(passphrase, &salt, kdf)
+        .map_err(|e| format!("Failed to encrypt backup: {e}"))?;
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.push(VERSION);
+    for cost in [kdf.memory_kib, kdf.passes, kdf.lanes] {
+        header.extend_from_slice(&cost.to_le_bytes());
+    }
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+    header.extend_from_slice(&check);
+
+    let mut input = BufReader::new(File::open(source).map_err(fail)?);
+    let mut output = BufWriter::new(File::create(dest).map_err(fail)?);
+    output.write_all(&header).map_err(fail)?;
+
+    let sealed = || "Failed to encrypt backup".to_string();
+    let mut stream = EncryptorBE32::from_aead(XChaCha20Poly1305::new(&key.into()), &nonce.into());
+    let mut buf = Vec::with_capacity(CHUNK_BYTES + TAG_LEN);
+    loop {
+        buf.resize(CHUNK_BYTES, 0);
+        let n = fill(&mut input, &mut buf).map_err(fail)?;
+        buf.truncate(n);
+        if n < CHUNK_BYTES {
+            stream
+                .encrypt_last_in_place(header.as_slice(), &mut buf)
+                .map_err(|_| sealed())?;
+            output.write_all(&buf).map_err(fail)?;
+            break;
+        }
+        stream
+            .encrypt_next_in_place(header.as_slice(), &mut buf)
+            .map_err(|_| sealed())?;
+        output.write_all(&buf).map_err(fail)?;
+    }
+    output
+        .into_inner()
+        .map_err(|e| fail(e.into_error()))?
+        .sync_all()
+        .map_err(fail)
+}
+
+/// Decrypt the encrypted backup at `source` into a new file at `dest`. The
+/// whole file is only known to be intact once all of it has been read, so
+/// `dest` must not be used unless this returns `Ok`; it's removed on failure.
+pub fn decrypt(source: &Path, dest: &Path, passphrase: &str) -> Result<(), DecryptError> {
+    let result = decrypt_into(source, dest, passphrase);
+    if result.is_err() {
+        let _ = fs::remove_file(dest);
+    }
+    result
+}
+
+fn decrypt_into(source: &Path, dest: &Path, passphrase: &str) -> Result<(), DecryptError> {
+    let corrupt = |e: io::Error| DecryptError::Corrupt(e.to_string());
+    let damaged = |reason: &str| DecryptError::Corrupt(reason.to_string());
+    let mut input = BufReader::new(File::open(source).map_err(corrupt)?);
+    let mut magic = [0u8; MAGIC.len() + 1];
+    fill(&mut input, &mut magic).map_err(corrupt)?;
+    if &magic[..MAGIC.len()] != MAGIC {
+        return Err(damaged("not an encrypted backup"));
+    }
+    if magic[MAGIC.len()] != VERSION {
+        return Err(damaged("written by a newer version of DartForge"));
+    }
+
+    let mut header = [0u8; HEADER_LEN];
+    header[..magic.len()].copy_from_slice(&magic);
+    if fill(&mut input, &mut header[magic.len()..]).map_err(corrupt)? < HEADER_LEN - magic.len() {
+        return Err(damaged("file is truncated"));
+    }
+    let (costs, rest) = header[magic.len()..].split_at(3 * 4);
+    let cost =
+        |i: usize| u32::from_le_bytes(costs[i * 4..i * 4 + 4].try_into().unwrap_or_default());
+    let kdf = Kdf {
+        memory_kib: cost(0),
+        passes: cost(1),
+        lanes: cost(2),
+    };
+    if kdf.memory_kib > MAX_KDF.memory_kib
+        || kdf.passes > MAX_KDF.passes
+        || kdf.lanes > MAX_KDF.lanes
+    {
+        return Err(damaged("header is damaged"));
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, check) = rest.split_at(NONCE_LEN);
+    let (key, expected_check) =
+        derive_keys(passphrase, salt, kdf).map_err(|_| damaged("header is damaged"))?;
+    if !bool::from(expected_check.ct_eq(check)) {
+        return Err(DecryptError::WrongPassphrase);
+    }
+
+    let mut output = BufWriter::new(File::create(dest).map_err(corrupt)?);
+    let mut stream = DecryptorBE32::from_aead(
+        XChaCha20Poly1305::new(&key.into()),
+        GenericArray::from_slice(nonce),
+    );
+    let mismatch = || damaged("contents don't match their checksum");
+    let mut buf = Vec::with_capacity(CHUNK_BYTES + TAG_LEN);
+    loop {
+        buf.resize(CHUNK_BYTES + TAG_LEN, 0);
+        let n = fill(&mut input, &mut buf).map_err(corrupt)?;
+        buf.truncate(n);
+        if n < CHUNK_BYTES + TAG_LEN {
+            stream
+                .decrypt_last_in_place(header.as_slice(), &mut buf)
+                .map_err(|_| mismatch())?;
+            output.write_all(&buf).map_err(corrupt)?;
+            break;
+        }
+        stream
+            .decrypt_next_in_place(header.as_slice(), &mut buf)
+            .map_err(|_| mismatch())?;
+        output.write_all(&buf).map_err(corrupt)?;
+    }
+    output.flush().map_err(corrupt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Argon2's minimum cost, so the tests don't spend seconds per key.
+    const TEST_KDF: Kdf = Kdf {
+        memory_kib: 8,
+        passes: 1,
+        lanes: 1,
+    };
+
+    struct Files {
+        _dir: tempfile::TempDir,
+        plain: std::path::PathBuf,
+        sealed: std::path::PathBuf,
+        opened: std::path::PathBuf,
+    }
+
+    fn encrypted(contents: &[u8]) -> Files {
+        let dir = tempfile::tempdir().unwrap();
+        let files = Files {
+            plain: dir.path().join("backup.zip"),
+            sealed: dir.path().join("backup.enc"),
+            opened: dir.path().join("backup.out"),
+            _dir: dir,
+        };
+        fs::write(&files.plain, contents).unwrap();
+        encrypt_with(&files.plain, &files.sealed, "hunter2", TEST_KDF).unwrap();
+        files
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn round_trip_across_chunk_boundaries() {
+        for len in [0, 1, CHUNK_BYTES - 1, CHUNK_BYTES, 2 * CHUNK_BYTES + 5] {
+            let files = encrypted(&sample(len));
+            assert!(is_encrypted(&files.sealed));
+            assert!(!is_encrypted(&files.plain));
+            assert!(
+                decrypt(&files.sealed, &files.opened, "hunter2").is_ok(),
+                "{len} bytes"
+            );
+            assert_eq!(fs::read(&files.opened).unwrap(), sample(len));
+        }
+    }
+
+    #[test]
+    fn wrong_passphrase_is_told_apart() {
+        let files = encrypted(&sample(100));
+        let result = decrypt(&files.sealed, &files.opened, "hunter3");
+        assert!(matches!(result, Err(DecryptError::WrongPassphrase)));
+        assert!(!files.opened.exists());
+    }
+
+    #[test]
+    fn truncation_is_caught() {
+        // An exact number of chunks ends with an empty last chunk, which
+        // must not be droppable either
+        for (len, cut) in [
+            (100, 1),
+            (CHUNK_BYTES, TAG_LEN),
+            (2 * CHUNK_BYTES + 5, 5 + TAG_LEN),
+        ] {
+            let files = encrypted(&sample(len));
+            let sealed = fs::read(&files.sealed).unwrap();
+            fs::write(&files.sealed, &sealed[..sealed.len() - cut]).unwrap();
+            let result = decrypt(&files.sealed, &files.opened, "hunter2");
+            assert!(
+                matches!(result, Err(DecryptError::Corrupt(_))),
+                "{len} bytes less {cut}"
+            );
+            assert!(!files.opened.exists());
+        }
+    }
+
+    #[test]
+    fn tampering_is_caught() {
+        let files = encrypted(&sample(2 * CHUNK_BYTES));
+        let mut sealed = fs::read(&files.sealed).unwrap();
+        sealed[HEADER_LEN + CHUNK_BYTES + 7] ^= 1;
+        fs::write(&files.sealed, &sealed).unwrap();
+        let result = decrypt(&files.sealed, &files.opened, "hunter2");
+        assert!(matches!(result, Err(DecryptError::Corrupt(_))));
+        assert!(!files.opened.exists());
+    }
+}
@@ -0,0 +1,462 @@
+//! Portable settings bundles: a `.dartforge` file (a zip with a manifest)
+//! holding chosen categories of data, for moving to another machine. Unlike
+//! backups, bundles are written wherever the user picks, never hold secrets
+//! (passwords and tokens are stripped from the files that have them), and can
+//! be merged into existing data instead of replacing it.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::storage::{self, StorageState};
+
+/// Bumped when the bundle layout changes in a way older versions can't read.
+const BUNDLE_SCHEMA_VERSION: u32 = 1;
+const BUNDLE_MANIFEST: &str = "manifest.json";
+/// JSON entries are parsed for merging, so they're held to a smaller size.
+const MAX_JSON_ENTRY_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Categories and the data files each covers. `sounds` is the whole
+/// `sounds/` folder.
+const CATEGORIES: [(&str, &[&str]); 6] = [
+    (
+        "settings",
+        &[
+            "settings.json",
+            crate::connection::CONNECTION_SETTINGS_FILE,
+            crate::session_log::SESSION_LOG_SETTINGS_FILE,
+            crate::log_rotation::LOG_ROTATION_FILE,
+            storage::BACKUP_OPTIONS_FILE,
+            crate::auto_backup::AUTO_BACKUP_FILE,
+        ],
+    ),
+    ("profiles", &[crate::profiles::PROFILES_FILE]),
+    ("triggers", &[crate::triggers::TRIGGERS_FILE]),
+    ("aliases", &[crate::aliases::ALIASES_FILE]),
+    ("highlights", &[crate::highlights::HIGHLIGHTS_FILE]),
+    ("sounds", &[]),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BundleManifest {
+    schema_version: u32,
+    app_version: String,
+    /// RFC 3339, local time.
+    created_at: String,
+    categories: Vec<String>,
+    files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Each file in the bundle overwrites the current one.
+    Replace,
+    /// JSON files are merge-patched into the current ones; other files are
+    /// overwritten.
+    Merge,
+}
+
+/// A value the bundle overwrote while merging.
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleConflict {
+    pub file: String,
+    /// Dotted path of the key within the file; empty for the whole file.
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleImport {
+    pub written: Vec<String>,
+    pub conflicts: Vec<BundleConflict>,
+    /// The backup taken before importing.
+    pub backup: String,
+}
+
+/// Whether `name` is a file the bundle format carries.
+fn in_category(name: &str) -> bool {
+    name.starts_with("sounds/") || CATEGORIES.iter().any(|(_, files)| files.contains(&name))
+}
+
+/// The files to bundle for `include`, as `(entry name, path)`.
+fn bundle_files(data_dir: &Path, include: &[String]) -> Result<Vec<(String, PathBuf)>, String> {
+    let mut files = Vec::new();
+    for category in include {
+        let Some((_, names)) = CATEGORIES.iter().find(|(name, _)| name == category) else {
+            let known: Vec<&str> = CATEGORIES.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "Unknown bundle category {category}; expected one of {}",
+                known.join(", ")
+            ));
+        };
+        if category == "sounds" {
+            files.extend(storage::files_under(data_dir, "sounds"));
+        }
+        files.extend(
+            names
+                .iter()
+                .map(|name| (name.to_string(), data_dir.join(name)))
+                .filter(|(_, path)| path.is_file()),
+        );
+    }
+    Ok(files)
+}
+
+/// `profiles.json` without any plaintext passwords left over from before
+/// they moved to the keychain.
+fn strip_profile_secrets(contents: &[u8]) -> Result<Vec<u8>, String> {
+    let mut profiles: serde_json::Value = serde_json::from_slice(contents)
+        .map_err(|e| format!("profiles.json is not valid JSON: {e}"))?;
+    if let Some(profiles) = profiles.as_object_mut() {
+        for profile in profiles.values_mut() {
+            if let Some(login) = profile
+                .get_mut("login")
+                .and_then(|login| login.as_object_mut())
+            {
+                login.remove("password");
+            }
+        }
+    }
+    serde_json::to_vec_pretty(&profiles).map_err(|e| e.to_string())
+}
+
+/// `connection-settings.json` without the outbound proxy's password or the
+/// WebSocket proxy's token.
+fn strip_connection_secrets(contents: &[u8]) -> Result<Vec<u8>, String> {
+    let mut settings: serde_json::Value = serde_json::from_slice(contents).map_err(|e| {
+        format!(
+            "{} is not valid JSON: {e}",
+            crate::connection::CONNECTION_SETTINGS_FILE
+        )
+    })?;
+    for (section, secret) in [("proxy", "password"), ("transport", "token")] {
+        if let Some(section) = settings
+            .get_mut(section)
+            .and_then(|section| section.as_object_mut())
+        {
+            section.remove(secret);
+        }
+    }
+    serde_json::to_vec_pretty(&settings).map_err(|e| e.to_string())
+}
+
+fn write_bundle(
+    output: &Path,
+    manifest: &BundleManifest,
+    files: &[(String, PathBuf)],
+) -> Result<(), String> {
+    let zip_file =
+        fs::File::create(output).map_err(|e| format!("Cannot write {}: {e}", output.display()))?;
+    let mut zip_writer = zip::ZipWriter::new(BufWriter::new(zip_file));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    let add = |zip_writer: &mut zip::ZipWriter<_>, name: &str, data: &mut dyn Read| {
+        zip_writer
+            .start_file(name, options)
+            .map_err(|e| format!("Failed to add {name} to bundle: {e}"))?;
+        std::io::copy(data, zip_writer)
+            .map_err(|e| format!("Failed to add {name} to bundle: {e}"))?;
+        Ok::<(), String>(())
+    };
+
+    let manifest = serde_json::to_vec_pretty(manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {e}"))?;
+    add(&mut zip_writer, BUNDLE_MANIFEST, &mut manifest.as_slice())?;
+    for (name, path) in files {
+        // The files that hold secrets go in with them stripped
+        let strip = match name.as_str() {
+            crate::profiles::PROFILES_FILE => strip_profile_secrets,
+            crate::connection::CONNECTION_SETTINGS_FILE => strip_connection_secrets,
+            _ => {
+                let mut file =
+                    fs::File::open(path).map_err(|e| format!("Failed to read {name}: {e}"))?;
+                add(&mut zip_writer, name, &mut file)?;
+                continue;
+            }
+        };
+        let contents = fs::read(path).map_err(|e| format!("Failed to read {name}: {e}"))?;
+        add(&mut zip_writer, name, &mut strip(&contents)?.as_slice())?;
+    }
+    zip_writer
+        .finish()
+        .map_err(|e| format!("Failed to finalize bundle: {e}"))?
+        .flush()
+        .map_err(|e| format!("Failed to finalize bundle: {e}"))
+}
+
+/// Write a bundle of the `include` categories (`settings`, `profiles`,
+/// `triggers`, `aliases`, `highlights`, `sounds`) to `output_path`
+/// (absolute) or wherever a save dialog puts it. Returns the path written,
+/// or `None` if the dialog was cancelled.
+#[tauri::command]
+pub async fn export_bundle(
+    app: AppHandle,
+    output_path: Option<String>,
+    include: Vec<String>,
+) -> Result<Option<String>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let data_dir = app.state::<StorageState>().get_dir();
+        let files = bundle_files(&data_dir, &include)?;
+        let suggested = format!(
+            "dartforge-settings-{}.dartforge",
+            chrono::Local::now().format("%Y-%m-%d")
+        );
+        let Some(output) = crate::resolve_export_path(
+            &app,
+            output_path,
+            &suggested,
+            "DartForge bundle",
+            &["dartforge"],
+        )?
+        else {
+            return Ok(None);
+        };
+        let manifest = BundleManifest {
+            schema_version: BUNDLE_SCHEMA_VERSION,
+            app_version: app.package_info().version.to_string(),
+            created_at: chrono::Local::now().to_rfc3339(),
+            categories: include,
+            files: files.iter().map(|(name, _)| name.clone()).collect(),
+        };
+        // Through a temp file, so a failed export doesn't clobber an old bundle
+        let tmp = storage::temp_path(&output);
+        if let Err(e) = write_bundle(&tmp, &manifest, &files).and_then(|()| {
+            fs::rename(&tmp, &output).map_err(|e| format!("Cannot write {}: {e}", output.display()))
+        }) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        info!("Exported {} file(s) to {}", files.len(), output.display());
+        Ok(Some(output.to_string_lossy().into_owned()))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Record, as dotted key paths under `prefix`, each value in `patch` that
+/// would change a different value already in `target`.
+fn merge_conflicts(
+    target: &serde_json::Value,
+    patch: &serde_json::Value,
+    prefix: &str,
+    out: &mut Vec<String>,
+) {
+    let (Some(target), Some(patch)) = (target.as_object(), patch.as_object()) else {
+        if target != patch {
+            out.push(prefix.to_string());
+        }
+        return;
+    };
+    for (key, value) in patch {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        if let Some(current) = target.get(key) {
+            merge_conflicts(current, value, &path, out);
+        }
+    }
+}
+
+/// Import the bundle at `path` (absolute). Every entry is checked first:
+/// its name must be one the bundle format carries, and the bundle must not
+/// come from a newer format than this version reads. A backup is taken
+/// before anything is written.
+#[tauri::command]
+pub async fn import_bundle(
+    app: AppHandle,
+    path: String,
+    mode: ImportMode,
+) -> Result<BundleImport, String> {
+    tauri::async_runtime::spawn_blocking(move || import(&app, Path::new(&path), mode))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn import(app: &AppHandle, path: &Path, mode: ImportMode) -> Result<BundleImport, String> {
+    if !path.is_absolute() {
+        return Err(format!("Bundle path must be absolute: {}", path.display()));
+    }
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open bundle: {e}"))?;
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))
+        .map_err(|e| format!("Not a DartForge bundle: {e}"))?;
+    let manifest: BundleManifest = archive
+        .by_name(BUNDLE_MANIFEST)
+        .ok()
+        .and_then(|entry| serde_json::from_reader(entry).ok())
+        .ok_or("Not a DartForge bundle: it has no manifest")?;
+    if manifest.schema_version > BUNDLE_SCHEMA_VERSION {
+        return Err(format!(
+            "This bundle was made by DartForge {} in a newer format (version {}); update DartForge to import it",
+            manifest.app_version, manifest.schema_version
+        ));
+    }
+
+    let state = app.state::<StorageState>();
+    let _write = state.begin_write()?;
+    let data_dir = state.get_dir();
+    let max_entry_bytes = storage::BackupOptions::load(&data_dir).max_entry_bytes;
+    let mut names = Vec::new();
+    let mut seen = HashSet::new();
+    for i in 0..archive.len() {
+        let entry = archive
+            .by_index(i)
+            .map_err(|e| format!("Failed to read bundle entry: {e}"))?;
+        let name = entry.name().to_string();
+        if name == BUNDLE_MANIFEST || entry.is_dir() {
+            continue;
+        }
+        storage::validate_backup_entry(&name)?;
+        if !in_category(&name) {
+            return Err(format!("Bundle contains an unexpected file: {name}"));
+        }
+        if let Some(problem) = storage::entry_size_problem(&entry, max_entry_bytes) {
+            return Err(format!("Refusing to import {name}: it {problem}"));
+        }
+        if name.ends_with(".json") && entry.size() > MAX_JSON_ENTRY_BYTES {
+            return Err(format!("Refusing to import {name}: it is too large"));
+        }
+        if seen.insert(name.clone()) {
+            names.push((i, name));
+        }
+    }
+
+    let backup = storage::create_backup(
+        "pre-import".to_string(),
+        None,
+        None,
+        None,
+        None,
+        app.clone(),
+        state.clone(),
+    )?;
+
+    let mut written = Vec::new();
+    let mut conflicts = Vec::new();
+    for (i, name) in names {
+        let entry = archive
+            .by_index(i)
+            .map_err(|e| format!("Failed to read {name} from bundle: {e}"))?;
+        let dest = data_dir.join(&name);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to import {name}: {e}"))?;
+        }
+        let declared = entry.size();
+        if mode == ImportMode::Merge && dest.is_file() {
+            if name.ends_with(".json") {
+                let mut incoming = Vec::new();
+                entry
+                    .take(declared)
+                    .read_to_end(&mut incoming)
+                    .map_err(|e| format!("Failed to read {name} from bundle: {e}"))?;
+                let patch: serde_json::Value = serde_json::from_slice(&incoming)
+                    .map_err(|e| format!("{name} in the bundle is not valid JSON: {e}"))?;
+                let current = fs::read(&dest)
+                    .ok()
+                    .and_then(|data| serde_json::from_slice(&data).ok());
+                let merged = match current {
+                    Some(mut current) => {
+                        let mut keys = Vec::new();
+                        merge_conflicts(&current, &patch, "", &mut keys);
+                        conflicts.extend(keys.into_iter().map(|key| BundleConflict {
+                            file: name.clone(),
+                            key,
+                        }));
+                        storage::merge_patch(&mut current, &patch);
+                        current
+                    }
+                    // An unreadable current file is replaced outright
+                    None => {
+                        conflicts.push(BundleConflict {
+                            file: name.clone(),
+                            key: String::new(),
+                        });
+                        patch
+                    }
+                };
+                let data = serde_json::to_vec_pretty(&merged).map_err(|e| e.to_string())?;
+                storage::atomic_write(&dest, &data, true)?;
+            } else {
+                let differs = storage::file_crc(&dest)
+                    .is_ok_and(|(size, crc)| size != declared || crc != entry.crc32());
+                if differs {
+                    conflicts.push(BundleConflict {
+                        file: name.clone(),
+                        key: String::new(),
+                    });
+                }
+                storage::atomic_write_from(&dest, entry.take(declared), false)
+                    .map_err(|e| format!("Failed to import {name}: {e}"))?;
+            }
+        } else {
+            storage::atomic_write_from(&dest, entry.take(declared), name.ends_with(".json"))
+                .map_err(|e| format!("Failed to import {name}: {e}"))?;
+        }
+        crate::data_file_written(app, &data_dir, &name, None);
+        written.push(name);
+    }
+    info!("Imported {} file(s) from {}", written.len(), path.display());
+    Ok(BundleImport {
+        written,
+        conflicts,
+        backup,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exported_settings_carry_no_proxy_secrets() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = serde_json::json!({
+            "proxy": {
+                "kind": "socks5",
+                "host": "proxy.example.com",
+                "port": 1080,
+                "username": "me",
+                "password": "proxy-pass-1234",
+            },
+            "transport": {
+                "kind": "web_socket_proxy",
+                "url": "wss://proxy.example.com/ws",
+                "token": "auth-token-5678",
+            },
+        });
+        fs::write(
+            dir.path().join(crate::connection::CONNECTION_SETTINGS_FILE),
+            serde_json::to_vec(&settings).unwrap(),
+        )
+        .unwrap();
+        let files = bundle_files(dir.path(), &["settings".to_string()]).unwrap();
+        let manifest = BundleManifest {
+            schema_version: BUNDLE_SCHEMA_VERSION,
+            app_version: "test".to_string(),
+            created_at: String::new(),
+            categories: vec!["settings".to_string()],
+            files: files.iter().map(|(name, _)| name.clone()).collect(),
+        };
+        let output = dir.path().join("out.dartforge");
+        write_bundle(&output, &manifest, &files).unwrap();
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(&output).unwrap()).unwrap();
+        let mut contents = String::new();
+        archive
+            .by_name(crate::connection::CONNECTION_SETTINGS_FILE)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert!(!contents.contains("proxy-pass-1234"));
+        assert!(!contents.contains("auth-token-5678"));
+        let bundled: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(bundled["proxy"]["username"], "me");
+        assert_eq!(bundled["transport"]["url"], "wss://proxy.example.com/ws");
+    }
+}
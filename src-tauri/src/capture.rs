@@ -0,0 +1,211 @@
+//! Raw session capture: the exact byte stream of a connection, both
+//! directions, recorded to `captures/` in the data dir for bug reports, and
+//! replayed offline through the normal telnet/ANSI processing.
+//!
+//! File format: the `MAGIC` header, then records of
+//! `[direction: u8][elapsed_ms: u64 LE][len: u32 LE][len bytes]`, where
+//! direction is `DIR_IN` for bytes read from the server (before
+//! `process_output`) and `DIR_OUT` for bytes written to it.
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use log::{info, warn};
+use tauri::AppHandle;
+use tokio::time::Duration;
+
+use crate::ansi;
+use crate::connection::DEFAULT_SESSION;
+use crate::events::{self, MudOutputPayload, MUD_OUTPUT_EVENT};
+
+pub const CAPTURES_DIR: &str = "captures";
+const MAGIC: &[u8; 8] = b"DFCAP01\n";
+const DIR_IN: u8 = 0;
+const DIR_OUT: u8 = 1;
+/// Longest pause honored during replay, so idle stretches don't stall it.
+const MAX_REPLAY_GAP: Duration = Duration::from_secs(5);
+
+struct CaptureWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    started: Instant,
+}
+
+/// The active capture, if any. Recording failures stop the capture rather than
+/// the connection.
+#[derive(Default)]
+pub struct RawCapture {
+    writer: Mutex<Option<CaptureWriter>>,
+}
+
+fn capture_path(data_dir: &Path, filename: &str) -> Result<PathBuf, String> {
+    crate::storage::validate_filename(filename)?;
+    Ok(data_dir.join(CAPTURES_DIR).join(filename))
+}
+
+impl RawCapture {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<CaptureWriter>> {
+        self.writer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start recording to `captures/<filename>`, replacing any capture in progress.
+    pub fn start(&self, data_dir: &Path, filename: &str) -> Result<PathBuf, String> {
+        let path = capture_path(data_dir, filename)?;
+        crate::data_lock::check_writable(&path)?;
+        fs::create_dir_all(data_dir.join(CAPTURES_DIR))
+            .map_err(|e| format!("Failed to create captures dir: {e}"))?;
+        let mut file = BufWriter::new(
+            File::create(&path).map_err(|e| format!("Failed to create capture file: {e}"))?,
+        );
+        file.write_all(MAGIC)
+            .map_err(|e| format!("Failed to write capture file: {e}"))?;
+
+        let mut writer = self.lock();
+        if let Some(mut previous) = writer.take() {
+            let _ = previous.file.flush();
+        }
+        info!("Raw capture started: {}", path.display());
+        *writer = Some(CaptureWriter {
+            path: path.clone(),
+            file,
+            started: Instant::now(),
+        });
+        Ok(path)
+    }
+
+    /// Stop recording. Returns the finished file's path, if a capture was running.
+    pub fn stop(&self) -> Result<Option<PathBuf>, String> {
+        let Some(mut capture) = self.lock().take() else {
+            return Ok(None);
+        };
+        capture
+            .file
+            .flush()
+            .map_err(|e| format!("Failed to flush capture file: {e}"))?;
+        info!("Raw capture stopped: {}", capture.path.display());
+        Ok(Some(capture.path))
+    }
+
+    pub fn record_read(&self, data: &[u8]) {
+        self.record(DIR_IN, data);
+    }
+
+    pub fn record_write(&self, data: &[u8]) {
+        self.record(DIR_OUT, data);
+    }
+
+    fn record(&self, direction: u8, data: &[u8]) {
+        let mut writer = self.lock();
+        let Some(capture) = writer.as_mut() else {
+            return;
+        };
+        let elapsed = capture.started.elapsed().as_millis() as u64;
+        let result = capture
+            .file
+            .write_all(&[direction])
+            .and_then(|_| capture.file.write_all(&elapsed.to_le_bytes()))
+            .and_then(|_| capture.file.write_all(&(data.len() as u32).to_le_bytes()))
+            .and_then(|_| capture.file.write_all(data));
+        if let Err(e) = result {
+            warn!("Raw capture write failed, stopping capture: {e}");
+            *writer = None;
+        }
+    }
+}
+
+struct Record {
+    direction: u8,
+    elapsed_ms: u64,
+    data: Vec<u8>,
+}
+
+fn read_records(path: &Path) -> Result<Vec<Record>, String> {
+    let mut reader =
+        BufReader::new(File::open(path).map_err(|e| format!("Failed to open capture: {e}"))?);
+    let mut magic = [0u8; 8];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|e| format!("Failed to read capture: {e}"))?;
+    if &magic != MAGIC {
+        return Err("Not a DartForge capture file".to_string());
+    }
+
+    let mut records = Vec::new();
+    loop {
+        let mut header = [0u8; 13];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(format!("Failed to read capture: {e}")),
+        }
+        let elapsed_ms = u64::from_le_bytes(header[1..9].try_into().unwrap());
+        let len = u32::from_le_bytes(header[9..13].try_into().unwrap()) as usize;
+        let mut data = vec![0u8; len];
+        if reader.read_exact(&mut data).is_err() {
+            // Truncated final record (e.g. the app crashed mid-capture)
+            warn!("Capture {} ends with a truncated record", path.display());
+            break;
+        }
+        records.push(Record {
+            direction: header[0],
+            elapsed_ms,
+            data,
+        });
+    }
+    Ok(records)
+}
+
+/// Feed the inbound side of a capture back through `process_output`, emitting
+/// `mud:output` as if it came from the default session's server. `speed` scales the recorded
+/// timing (2.0 = twice as fast); 0 replays without delays. Returns how many
+/// server reads were replayed. The output carries `generation`, the default
+/// session's when the replay started.
+pub async fn replay(
+    app: &AppHandle,
+    data_dir: &Path,
+    filename: &str,
+    speed: f64,
+    generation: u64,
+) -> Result<usize, String> {
+    let path = capture_path(data_dir, filename)?;
+    let records = tokio::task::spawn_blocking(move || read_records(&path))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    info!("Replaying capture {filename} at {speed}x");
+    let mut remainder: Vec<u8> = Vec::new();
+    let mut last_ms = 0u64;
+    let mut replayed = 0;
+    for record in records.into_iter().filter(|r| r.direction == DIR_IN) {
+        if speed > 0.0 {
+            let gap =
+                Duration::from_millis(record.elapsed_ms.saturating_sub(last_ms)).div_f64(speed);
+            tokio::time::sleep(gap.min(MAX_REPLAY_GAP)).await;
+        }
+        last_ms = record.elapsed_ms;
+
+        let mut input = std::mem::take(&mut remainder);
+        input.extend_from_slice(&record.data);
+        let processed = ansi::process_output(&input);
+        remainder = processed.remainder;
+        if !processed.display.is_empty() {
+            let session = DEFAULT_SESSION.to_string();
+            let _ = events::emit(
+                app,
+                MUD_OUTPUT_EVENT,
+                MudOutputPayload {
+                    data: processed.display,
+                    ga: processed.ga,
+                    session,
+                    buffered: false,
+                    generation,
+                },
+            );
+        }
+        replayed += 1;
+    }
+    Ok(replayed)
+}
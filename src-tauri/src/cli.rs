@@ -0,0 +1,204 @@
+//! Command-line arguments. The same parser handles a second launch's
+//! arguments, which the running instance receives (see `instance`).
+//!
+//! Flags take their value as the next argument or after `=`
+//! (`--send look`, `--send=look`). Unrecognized arguments are collected
+//! rather than rejected, since OSes and launchers add their own.
+//!
+//! At startup:
+//! - `--data-dir <path>` uses that data folder instead of the configured one
+//! - `--profile <name>` connects with that profile once the frontend is ready
+//! - `--no-autoconnect` only selects the `--profile`, without connecting
+//! - `--safe-mode` loads backend triggers and timers but keeps them from
+//!   running, and skips auto-login
+//! - `--log-level <filter>` sets the log filter (`debug`, `dartforge_lib=trace,info`)
+//!
+//! Forwarded from a second launch, `--profile` connects and `--send <command>`
+//! sends a command; the others only apply at startup.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+
+use crate::storage;
+
+/// Longest command accepted from `--send`.
+const MAX_SEND_CHARS: usize = 4096;
+/// Most `--send` commands taken from one invocation.
+const MAX_SENDS: usize = 50;
+
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    /// Commands to send, in order.
+    pub send: Vec<String>,
+    /// Connection profile to connect with.
+    pub profile: Option<String>,
+    pub data_dir: Option<PathBuf>,
+    pub no_autoconnect: bool,
+    pub safe_mode: bool,
+    pub log_level: Option<String>,
+    /// Arguments that weren't recognized.
+    pub unknown: Vec<String>,
+}
+
+impl Args {
+    /// The startup-only flags given, which a running instance ignores.
+    pub fn startup_only_flags(&self) -> Vec<&'static str> {
+        [
+            ("--data-dir", self.data_dir.is_some()),
+            ("--no-autoconnect", self.no_autoconnect),
+            ("--safe-mode", self.safe_mode),
+            ("--log-level", self.log_level.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(flag, given)| given.then_some(flag))
+        .collect()
+    }
+}
+
+fn flag_value(
+    flag: &str,
+    inline: Option<&str>,
+    rest: &mut impl Iterator<Item = String>,
+) -> Result<String, String> {
+    let value = match inline {
+        Some(value) => value.to_string(),
+        None => rest.next().ok_or_else(|| format!("{flag} needs a value"))?,
+    };
+    if value.trim().is_empty() {
+        return Err(format!("{flag} needs a value"));
+    }
+    Ok(value)
+}
+
+/// Parse `args`, which start with the program name as in `std::env::args`.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut rest = args.into_iter().skip(1);
+    let mut parsed = Args::default();
+    while let Some(arg) = rest.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        match flag {
+            "--send" => {
+                let command = flag_value(flag, inline, &mut rest)?;
+                if command.chars().count() > MAX_SEND_CHARS {
+                    return Err(format!(
+                        "--send command is longer than {MAX_SEND_CHARS} characters"
+                    ));
+                }
+                if command.contains(['\r', '\n']) {
+                    return Err("--send takes one command per flag".to_string());
+                }
+                if parsed.send.len() == MAX_SENDS {
+                    return Err(format!("At most {MAX_SENDS} --send commands are accepted"));
+                }
+                parsed.send.push(command);
+            }
+            "--profile" => {
+                if parsed.profile.is_some() {
+                    return Err("--profile given more than once".to_string());
+                }
+                parsed.profile = Some(flag_value(flag, inline, &mut rest)?.trim().to_string());
+            }
+            "--data-dir" => {
+                parsed.data_dir = Some(PathBuf::from(flag_value(flag, inline, &mut rest)?))
+            }
+            "--log-level" => {
+                let filter = flag_value(flag, inline, &mut rest)?;
+                crate::app_log::validate_filter(&filter)
+                    .map_err(|e| format!("--log-level: {e}"))?;
+                parsed.log_level = Some(filter);
+            }
+            "--no-autoconnect" if inline.is_none() => parsed.no_autoconnect = true,
+            "--safe-mode" if inline.is_none() => parsed.safe_mode = true,
+            _ => parsed.unknown.push(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+/// What this launch was started with, as reported by `get_launch_options`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LaunchOptions {
+    /// The `--data-dir` in use, made absolute.
+    pub data_dir: Option<String>,
+    pub profile: Option<String>,
+    /// Whether `profile` is connected once the frontend is ready.
+    pub autoconnect: bool,
+    pub safe_mode: bool,
+    pub log_level: Option<String>,
+    /// Problems with the arguments; the affected ones were ignored.
+    pub errors: Vec<String>,
+}
+
+/// Launch options plus the one-shot auto-connect.
+pub struct Launch {
+    pub options: LaunchOptions,
+    connect_pending: AtomicBool,
+}
+
+/// Create `dir` if missing and check it can be written to.
+fn prepare_data_dir(dir: PathBuf) -> Result<PathBuf, String> {
+    let dir =
+        std::path::absolute(&dir).map_err(|e| format!("--data-dir {}: {e}", dir.display()))?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("--data-dir {}: {e}", dir.display()))?;
+    if !storage::is_dir_writable(&dir) {
+        return Err(format!("--data-dir {} is not writable", dir.display()));
+    }
+    Ok(dir)
+}
+
+impl Launch {
+    /// Settle the parsed arguments: `parsed` is what `parse` returned for
+    /// this process's arguments. With a parse error every argument is ignored.
+    pub fn new(parsed: Result<Args, String>) -> Self {
+        let mut options = LaunchOptions::default();
+        let args = parsed.unwrap_or_else(|e| {
+            options.errors.push(e);
+            Args::default()
+        });
+        if !args.send.is_empty() {
+            options
+                .errors
+                .push("--send only applies when DartForge is already running".to_string());
+        }
+        if let Some(dir) = args.data_dir {
+            match prepare_data_dir(dir) {
+                Ok(dir) => options.data_dir = Some(dir.to_string_lossy().to_string()),
+                Err(e) => options.errors.push(e),
+            }
+        }
+        options.autoconnect = args.profile.is_some() && !args.no_autoconnect;
+        options.profile = args.profile;
+        options.safe_mode = args.safe_mode;
+        options.log_level = args.log_level;
+        for error in &options.errors {
+            log::warn!("Launch arguments: {error}");
+        }
+        let connect_pending = AtomicBool::new(options.autoconnect);
+        Self {
+            options,
+            connect_pending,
+        }
+    }
+
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        self.options.data_dir.as_ref().map(PathBuf::from)
+    }
+
+    pub fn safe_mode(&self) -> bool {
+        self.options.safe_mode
+    }
+
+    /// The profile to auto-connect with, the first time this is called.
+    pub fn take_autoconnect(&self) -> Option<&str> {
+        if self.connect_pending.swap(false, Ordering::SeqCst) {
+            self.options.profile.as_deref()
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,1406 @@
+//! Importing triggers, aliases and highlights from other MUD clients: TinTin++
+//! `.tin` scripts (`#action`, `#alias`, `#highlight`, `#substitute`, `#gag`)
+//! and Mudlet package exports (`.xml`, or an `.mpackage` zip holding one).
+//!
+//! What converts is merged into the `global` sections of triggers.json and
+//! aliases.json and into highlights.json; nothing already there is replaced.
+//! An item whose name is taken is imported as "name (imported)", an item
+//! identical to an existing one is skipped, and an alias whose pattern an
+//! existing alias already matches is imported disabled. Everything that
+//! couldn't be converted (Lua scripts, TinTin variables, timers, ...) is listed
+//! in the report with the reason. A backup is taken before anything is written.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use chrono::SecondsFormat;
+use log::info;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tauri::{AppHandle, Manager};
+
+use crate::aliases::{AliasMatchMode, ALIASES_FILE};
+use crate::highlights::{Color, HighlightRule, HighlightStyle, HIGHLIGHTS_FILE};
+use crate::storage::{self, StorageState};
+use crate::triggers::{MatchMode, TRIGGERS_FILE};
+
+/// Files bigger than this aren't read.
+const MAX_IMPORT_BYTES: u64 = 16 * 1024 * 1024;
+const GLOBAL_SECTION: &str = "global";
+/// The trigger and alias panels' command separator.
+const COMMAND_SEPARATOR: &str = ";;";
+/// Skipped statements are quoted in the report up to this many characters.
+const MAX_QUOTE_CHARS: usize = 120;
+/// Bodies can refer to `$0`–`$9` only.
+const MAX_CAPTURE: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientFormat {
+    Tintin,
+    Mudlet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportKind {
+    Trigger,
+    Alias,
+    Highlight,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedItem {
+    pub kind: ImportKind,
+    /// The name it was saved under.
+    pub name: String,
+    /// Why it doesn't look exactly like the original: renamed or disabled.
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedItem {
+    /// The statement or item, e.g. `#ticker {tick} {...}` or `Mudlet timer "heal"`.
+    pub source: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientImport {
+    pub imported: Vec<ImportedItem>,
+    pub skipped: Vec<SkippedItem>,
+    /// The backup taken before importing; none when nothing could be imported.
+    pub backup: Option<String>,
+}
+
+struct NewTrigger {
+    name: Option<String>,
+    pattern: String,
+    match_mode: MatchMode,
+    body: String,
+    group: String,
+    enabled: bool,
+    gag: bool,
+    replacement: Option<String>,
+}
+
+struct NewAlias {
+    name: Option<String>,
+    pattern: String,
+    match_mode: AliasMatchMode,
+    body: String,
+    group: String,
+    enabled: bool,
+}
+
+/// Everything read from the file, before merging.
+#[derive(Default)]
+struct Converted {
+    triggers: Vec<NewTrigger>,
+    aliases: Vec<NewAlias>,
+    highlights: Vec<HighlightRule>,
+    skipped: Vec<SkippedItem>,
+}
+
+impl Converted {
+    fn skip(&mut self, source: impl Into<String>, reason: impl Into<String>) {
+        let source: String = source.into();
+        let source = match source.char_indices().nth(MAX_QUOTE_CHARS) {
+            Some((end, _)) => format!("{}…", &source[..end]),
+            None => source,
+        };
+        self.skipped.push(SkippedItem {
+            source,
+            reason: reason.into(),
+        });
+    }
+}
+
+/// Import the TinTin++ script or Mudlet package at `path` (absolute).
+#[tauri::command]
+pub async fn import_client_config(
+    app: AppHandle,
+    path: String,
+    format: ClientFormat,
+) -> Result<ClientImport, String> {
+    tauri::async_runtime::spawn_blocking(move || import(&app, Path::new(&path), format))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn import(app: &AppHandle, path: &Path, format: ClientFormat) -> Result<ClientImport, String> {
+    if !path.is_absolute() {
+        return Err(format!("Import path must be absolute: {}", path.display()));
+    }
+    let size = fs::metadata(path)
+        .map_err(|e| format!("Failed to open {}: {e}", path.display()))?
+        .len();
+    if size > MAX_IMPORT_BYTES {
+        return Err(format!(
+            "{} is too large to import ({size} bytes)",
+            path.display()
+        ));
+    }
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let converted = match format {
+        ClientFormat::Tintin => tintin::convert(&String::from_utf8_lossy(&data)),
+        ClientFormat::Mudlet => mudlet::convert(&mudlet::package_xml(data)?)?,
+    };
+
+    let state = app.state::<StorageState>();
+    let _write = state.begin_write()?;
+    let data_dir = state.get_dir();
+    let mut triggers = read_sections(&data_dir, TRIGGERS_FILE)?;
+    let mut aliases = read_sections(&data_dir, ALIASES_FILE)?;
+    let mut highlights = read_highlights(&data_dir)?;
+
+    let mut imported = Vec::new();
+    let mut skipped = converted.skipped;
+    merge_triggers(
+        section(&mut triggers),
+        converted.triggers,
+        &mut imported,
+        &mut skipped,
+    );
+    merge_aliases(
+        section(&mut aliases),
+        converted.aliases,
+        &mut imported,
+        &mut skipped,
+    );
+    merge_highlights(
+        &mut highlights,
+        converted.highlights,
+        &mut imported,
+        &mut skipped,
+    );
+    if imported.is_empty() {
+        return Ok(ClientImport {
+            imported,
+            skipped,
+            backup: None,
+        });
+    }
+
+    let backup = storage::create_backup(
+        "pre-import".to_string(),
+        None,
+        None,
+        None,
+        None,
+        app.clone(),
+        state.clone(),
+    )?;
+    let written = [
+        (TRIGGERS_FILE, ImportKind::Trigger, &triggers),
+        (ALIASES_FILE, ImportKind::Alias, &aliases),
+        (HIGHLIGHTS_FILE, ImportKind::Highlight, &highlights),
+    ];
+    for (name, kind, value) in written {
+        if !imported.iter().any(|item| item.kind == kind) {
+            continue;
+        }
+        let data = serde_json::to_vec_pretty(value).map_err(|e| e.to_string())?;
+        storage::atomic_write(&data_dir.join(name), &data, true)?;
+        crate::data_file_written(app, &data_dir, name, None);
+    }
+    info!(
+        "Imported {} item(s) from {}, skipped {}",
+        imported.len(),
+        path.display(),
+        skipped.len()
+    );
+    Ok(ClientImport {
+        imported,
+        skipped,
+        backup: Some(backup),
+    })
+}
+
+/// triggers.json or aliases.json as JSON, so fields this module doesn't know
+/// survive the rewrite. A missing file is empty; an unreadable one stops the
+/// import rather than being replaced.
+fn read_sections(dir: &Path, name: &str) -> Result<Value, String> {
+    match fs::read(dir.join(name)) {
+        Ok(data) => match serde_json::from_slice::<Value>(&data) {
+            Ok(value) if value.is_object() => Ok(value),
+            _ => Err(format!(
+                "{name} couldn't be read; fix or restore it before importing"
+            )),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(json!({})),
+        Err(e) => Err(format!("Failed to read {name}: {e}")),
+    }
+}
+
+fn read_highlights(dir: &Path) -> Result<Value, String> {
+    let mut value = read_sections(dir, HIGHLIGHTS_FILE)?;
+    if !value["highlights"].is_object() {
+        value["highlights"] = json!({});
+    }
+    Ok(value)
+}
+
+/// The `global` section, created if missing.
+fn section(file: &mut Value) -> &mut Map<String, Value> {
+    let root = file.as_object_mut().expect("sections file is an object");
+    let entry = root.entry(GLOBAL_SECTION).or_insert_with(|| json!({}));
+    if !entry.is_object() {
+        *entry = json!({});
+    }
+    entry.as_object_mut().expect("section is an object")
+}
+
+/// An id in the panels' format: milliseconds and six base-36 characters.
+fn new_id(taken: &Map<String, Value>) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut rng = rand::thread_rng();
+    loop {
+        let suffix: String = (0..6)
+            .map(|_| DIGITS[rng.gen_range(0..DIGITS.len())] as char)
+            .collect();
+        let id = format!("{}-{suffix}", crate::events::now_ms());
+        if !taken.contains_key(&id) {
+            return id;
+        }
+    }
+}
+
+fn now_iso() -> String {
+    chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// The name a rule is listed under: its name, or its pattern when unnamed.
+fn display_name(rule: &Value) -> String {
+    match rule["name"].as_str().filter(|name| !name.trim().is_empty()) {
+        Some(name) => name.to_string(),
+        None => rule["pattern"].as_str().unwrap_or_default().to_string(),
+    }
+}
+
+/// `name`, or the first of "name (imported)", "name (imported 2)", ... not in
+/// `names`.
+fn free_name(name: &str, names: &HashSet<String>) -> String {
+    if !names.contains(name) {
+        return name.to_string();
+    }
+    (1..)
+        .map(|n| {
+            if n == 1 {
+                format!("{name} (imported)")
+            } else {
+                format!("{name} (imported {n})")
+            }
+        })
+        .find(|candidate| !names.contains(candidate))
+        .expect("some name is free")
+}
+
+fn merge_triggers(
+    rules: &mut Map<String, Value>,
+    incoming: Vec<NewTrigger>,
+    imported: &mut Vec<ImportedItem>,
+    skipped: &mut Vec<SkippedItem>,
+) {
+    let mut names: HashSet<String> = rules.values().map(display_name).collect();
+    for trigger in incoming {
+        let wanted = trigger
+            .name
+            .clone()
+            .unwrap_or_else(|| trigger.pattern.clone());
+        let duplicate = rules.values().any(|rule| {
+            rule["pattern"] == trigger.pattern.as_str()
+                && rule["body"] == trigger.body.as_str()
+                && rule["replacement"].as_str() == trigger.replacement.as_deref()
+                && rule["gag"] == trigger.gag
+        });
+        if duplicate {
+            skipped.push(SkippedItem {
+                source: format!("trigger {wanted:?}"),
+                reason: "An identical trigger exists".to_string(),
+            });
+            continue;
+        }
+        let name = free_name(&wanted, &names);
+        let note = (name != wanted).then(|| format!("Renamed from {wanted:?}"));
+        let now = now_iso();
+        let id = new_id(rules);
+        let rule = json!({
+            "id": id,
+            "name": (trigger.name.is_some() || note.is_some()).then(|| name.clone()),
+            "pattern": trigger.pattern,
+            "matchMode": trigger.match_mode,
+            "body": trigger.body,
+            "enabled": trigger.enabled,
+            "group": trigger.group,
+            "cooldownMs": 0,
+            "gag": trigger.gag,
+            "highlight": null,
+            "replacement": trigger.replacement,
+            "soundName": null,
+            "createdAt": now,
+            "updatedAt": now,
+        });
+        rules.insert(id, rule);
+        names.insert(name.clone());
+        imported.push(ImportedItem {
+            kind: ImportKind::Trigger,
+            name,
+            note,
+        });
+    }
+}
+
+fn merge_aliases(
+    rules: &mut Map<String, Value>,
+    incoming: Vec<NewAlias>,
+    imported: &mut Vec<ImportedItem>,
+    skipped: &mut Vec<SkippedItem>,
+) {
+    let mut names: HashSet<String> = rules.values().map(display_name).collect();
+    for alias in incoming {
+        let mode = serde_json::to_value(alias.match_mode).unwrap_or_default();
+        let same_pattern: Vec<&Value> = rules
+            .values()
+            .filter(|rule| rule["pattern"] == alias.pattern.as_str() && rule["matchMode"] == mode)
+            .collect();
+        let wanted = alias.name.clone().unwrap_or_else(|| alias.pattern.clone());
+        if same_pattern
+            .iter()
+            .any(|rule| rule["body"] == alias.body.as_str())
+        {
+            skipped.push(SkippedItem {
+                source: format!("alias {wanted:?}"),
+                reason: "An identical alias exists".to_string(),
+            });
+            continue;
+        }
+        let name = free_name(&wanted, &names);
+        let mut notes = Vec::new();
+        if name != wanted {
+            notes.push(format!("Renamed from {wanted:?}"));
+        }
+        let shadowed = same_pattern.iter().any(|rule| rule["enabled"] == true);
+        if shadowed && alias.enabled {
+            notes.push("Disabled: an existing alias has the same pattern".to_string());
+        }
+        let now = now_iso();
+        let id = new_id(rules);
+        let rule = json!({
+            "id": id,
+            "name": (alias.name.is_some() || name != wanted).then(|| name.clone()),
+            "pattern": alias.pattern,
+            "matchMode": mode,
+            "body": alias.body,
+            "enabled": alias.enabled && !shadowed,
+            "group": alias.group,
+            "createdAt": now,
+            "updatedAt": now,
+        });
+        rules.insert(id, rule);
+        names.insert(name.clone());
+        let note = (!notes.is_empty()).then(|| notes.join("; "));
+        imported.push(ImportedItem {
+            kind: ImportKind::Alias,
+            name,
+            note,
+        });
+    }
+}
+
+fn merge_highlights(
+    file: &mut Value,
+    incoming: Vec<HighlightRule>,
+    imported: &mut Vec<ImportedItem>,
+    skipped: &mut Vec<SkippedItem>,
+) {
+    let rules = file["highlights"]
+        .as_object_mut()
+        .expect("highlights is an object");
+    let mut names: HashSet<String> = rules.values().map(display_name).collect();
+    for mut highlight in incoming {
+        let Ok(mut value) = serde_json::to_value(&highlight) else {
+            continue;
+        };
+        let wanted = highlight
+            .name
+            .clone()
+            .unwrap_or_else(|| highlight.pattern.clone());
+        if rules.values().any(|rule| *rule == value) {
+            skipped.push(SkippedItem {
+                source: format!("highlight {wanted:?}"),
+                reason: "An identical highlight exists".to_string(),
+            });
+            continue;
+        }
+        let name = free_name(&wanted, &names);
+        let note = (name != wanted).then(|| format!("Renamed from {wanted:?}"));
+        if highlight.name.is_some() || note.is_some() {
+            highlight.name = Some(name.clone());
+            value["name"] = json!(name);
+        }
+        rules.insert(new_id(rules), value);
+        names.insert(name.clone());
+        imported.push(ImportedItem {
+            kind: ImportKind::Highlight,
+            name,
+            note,
+        });
+    }
+}
+
+/// Escape `text` for a regex, so the result reads the same to the Rust and the
+/// webview engines.
+fn escape_regex(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}/".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+mod tintin {
+    //! TinTin++ scripts: statements are `#command {arg} {arg} ...`, split by
+    //! `;` or newlines outside braces.
+
+    use super::*;
+
+    /// The commands understood, matched by any prefix of three or more letters
+    /// as TinTin does.
+    const COMMANDS: [&str; 7] = [
+        "action",
+        "alias",
+        "class",
+        "gag",
+        "highlight",
+        "nop",
+        "substitute",
+    ];
+
+    pub(super) fn convert(script: &str) -> Converted {
+        let mut out = Converted::default();
+        let mut class: Option<String> = None;
+        for statement in statements(script) {
+            let Some(rest) = statement.strip_prefix('#') else {
+                out.skip(
+                    &statement,
+                    "Commands sent when the script loads aren't imported",
+                );
+                continue;
+            };
+            let word_end = rest
+                .find(|c: char| c.is_whitespace() || c == '{')
+                .unwrap_or(rest.len());
+            let word = rest[..word_end].to_lowercase();
+            let args = arguments(&rest[word_end..]);
+            let command = COMMANDS
+                .iter()
+                .find(|c| word.len() >= 3 && c.starts_with(word.as_str()));
+            let group = class.clone().unwrap_or_else(|| "TinTin++".to_string());
+            let result = match command.copied() {
+                Some("nop") => Ok(()),
+                Some("class") => {
+                    match args.get(1).map(|a| a.to_lowercase()).as_deref() {
+                        Some("open") => class = args.first().cloned(),
+                        Some("close") => class = None,
+                        _ => {}
+                    }
+                    Ok(())
+                }
+                Some("action") => action(&args, group, &mut out),
+                Some("alias") => alias(&args, group, &mut out),
+                Some("gag") => substitute(&args[..args.len().min(1)], true, group, &mut out),
+                Some("highlight") => highlight(&args, &mut out),
+                Some("substitute") => substitute(&args, false, group, &mut out),
+                _ => Err(format!("#{word} has no equivalent in DartForge")),
+            };
+            if let Err(reason) = result {
+                out.skip(&statement, reason);
+            }
+        }
+        out
+    }
+
+    /// Top-level statements, with `/* ... */` comments removed.
+    fn statements(script: &str) -> Vec<String> {
+        let mut statements = Vec::new();
+        let mut current = String::new();
+        let mut depth = 0usize;
+        let mut chars = script.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    current.push(c);
+                    if let Some(next) = chars.next() {
+                        current.push(next);
+                    }
+                }
+                '/' if depth == 0 && chars.peek() == Some(&'*') => {
+                    let mut previous = '\0';
+                    for c in chars.by_ref() {
+                        if previous == '*' && c == '/' {
+                            break;
+                        }
+                        previous = c;
+                    }
+                }
+                '{' => {
+                    depth += 1;
+                    current.push(c);
+                }
+                '}' => {
+                    depth = depth.saturating_sub(1);
+                    current.push(c);
+                }
+                ';' | '\n' | '\r' if depth == 0 => {
+                    if !current.trim().is_empty() {
+                        statements.push(current.trim().to_string());
+                    }
+                    current.clear();
+                }
+                _ => current.push(c),
+            }
+        }
+        if !current.trim().is_empty() {
+            statements.push(current.trim().to_string());
+        }
+        statements
+    }
+
+    /// Split on top-level occurrences of `separator`, keeping braces intact.
+    fn split_top_level(text: &str, separator: char) -> Vec<String> {
+        let mut parts = Vec::new();
+        let mut current = String::new();
+        let mut depth = 0usize;
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    current.push(c);
+                    if let Some(next) = chars.next() {
+                        current.push(next);
+                    }
+                    continue;
+                }
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                _ if c == separator && depth == 0 => {
+                    parts.push(std::mem::take(&mut current));
+                    continue;
+                }
+                _ => {}
+            }
+            current.push(c);
+        }
+        parts.push(current);
+        parts
+    }
+
+    /// A command's arguments: each `{...}` (without the outer braces) or bare
+    /// word.
+    fn arguments(text: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut chars = text.trim().chars().peekable();
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            let Some(first) = chars.next() else {
+                break;
+            };
+            let mut arg = String::new();
+            if first == '{' {
+                let mut depth = 1;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            arg.push(c);
+                            if let Some(next) = chars.next() {
+                                arg.push(next);
+                            }
+                            continue;
+                        }
+                        '{' => depth += 1,
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    arg.push(c);
+                }
+            } else {
+                arg.push(first);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    arg.push(c);
+                }
+            }
+            args.push(arg);
+        }
+        args
+    }
+
+    /// A TinTin pattern as a regex, and which TinTin variable (`%1`, ...)
+    /// each capture group fills.
+    struct Pattern {
+        regex: String,
+        vars: Vec<usize>,
+    }
+
+    impl Pattern {
+        /// The `$n` a body should use for TinTin's `%var`.
+        fn capture(&self, var: usize) -> Option<usize> {
+            if var == 0 {
+                return Some(0);
+            }
+            self.vars.iter().position(|&v| v == var).map(|i| i + 1)
+        }
+    }
+
+    /// Wildcards: `%1`–`%99` and `%*` match anything, the letters match a
+    /// character class; a `!` after the `%` makes one non-capturing. `{...}`
+    /// is a raw regex, `^` and a final `$` anchor the line.
+    fn pattern(text: &str) -> Result<Pattern, String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut regex = String::new();
+        let mut vars = Vec::new();
+        let mut i = 0;
+        let mut group = |regex: &mut String, body: &str, capturing: bool, var: Option<usize>| {
+            if capturing {
+                let var = var.unwrap_or(vars.len() + 1);
+                vars.push(var);
+                regex.push_str(&format!("({body})"));
+            } else {
+                regex.push_str(&format!("(?:{body})"));
+            }
+        };
+        while i < chars.len() {
+            let c = chars[i];
+            let last = i + 1 == chars.len();
+            match c {
+                '^' if i == 0 => regex.push('^'),
+                '$' if last => regex.push('$'),
+                '\\' if !last => {
+                    regex.push_str(&escape_regex(&chars[i + 1].to_string()));
+                    i += 1;
+                }
+                '{' => {
+                    let mut depth = 1;
+                    let mut j = i + 1;
+                    while j < chars.len() {
+                        match chars[j] {
+                            '{' => depth += 1,
+                            '}' => {
+                                depth -= 1;
+                                if depth == 0 {
+                                    break;
+                                }
+                            }
+                            _ => {}
+                        }
+                        j += 1;
+                    }
+                    let body: String = chars[i + 1..j.min(chars.len())].iter().collect();
+                    group(&mut regex, &body, true, None);
+                    i = j;
+                }
+                '%' if !last => {
+                    let mut j = i + 1;
+                    let capturing = chars[j] != '!';
+                    if !capturing {
+                        j += 1;
+                    }
+                    let digits: String = chars[j..]
+                        .iter()
+                        .take(2)
+                        .take_while(|c| c.is_ascii_digit())
+                        .collect();
+                    let (class, var) = if !digits.is_empty() {
+                        j += digits.len() - 1;
+                        (".", digits.parse::<usize>().ok())
+                    } else {
+                        let class = match chars.get(j) {
+                            Some('*' | 'a') => ".",
+                            Some('d') => "[0-9]",
+                            Some('D') => "[^0-9]",
+                            Some('w') => "[A-Za-z0-9_]",
+                            Some('W') => "[^A-Za-z0-9_]",
+                            Some('s') => "\\s",
+                            Some('S') => "\\S",
+                            Some('%') => {
+                                regex.push('%');
+                                i = j + 1;
+                                continue;
+                            }
+                            Some(other) => {
+                                return Err(format!(
+                                    "Its pattern uses %{other}, which has no equivalent"
+                                ))
+                            }
+                            None => return Err("Its pattern ends in a lone %".to_string()),
+                        };
+                        (class, None)
+                    };
+                    // Like TinTin, a wildcard is lazy unless nothing follows it.
+                    let ends =
+                        j + 1 == chars.len() || (j + 2 == chars.len() && chars[j + 1] == '$');
+                    let body = format!("{class}*{}", if ends { "" } else { "?" });
+                    group(&mut regex, &body, capturing, var);
+                    i = j;
+                }
+                _ => regex.push_str(&escape_regex(&c.to_string())),
+            }
+            i += 1;
+        }
+        Ok(Pattern { regex, vars })
+    }
+
+    /// A TinTin command list as a `;;`-separated body. `capture` maps `%n` to
+    /// the text to substitute; `None` for a variable the pattern doesn't set.
+    fn body(text: &str, capture: &dyn Fn(usize) -> Option<String>) -> Result<String, String> {
+        let mut commands = Vec::new();
+        for command in split_top_level(text, ';') {
+            let command = command.trim();
+            if command.is_empty() {
+                continue;
+            }
+            if let Some(rest) = command.strip_prefix('#') {
+                let word: String = rest.chars().take_while(|c| c.is_alphanumeric()).collect();
+                return Err(format!("Its commands use #{word}, which has no equivalent"));
+            }
+            if command.contains(['{', '}']) {
+                return Err("Its commands use braces, which can't be converted".to_string());
+            }
+            let chars: Vec<char> = command.chars().collect();
+            let mut out = String::new();
+            let mut i = 0;
+            while i < chars.len() {
+                match chars[i] {
+                    '$' | '&'
+                        if chars
+                            .get(i + 1)
+                            .is_some_and(|c| c.is_alphabetic() || *c == '{') =>
+                    {
+                        return Err("Its commands use TinTin variables".to_string());
+                    }
+                    '%' if chars.get(i + 1) == Some(&'%') => {
+                        out.push('%');
+                        i += 1;
+                    }
+                    '%' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                        let digits: String = chars[i + 1..]
+                            .iter()
+                            .take(2)
+                            .take_while(|c| c.is_ascii_digit())
+                            .collect();
+                        let var = digits.parse::<usize>().unwrap_or_default();
+                        let replacement = capture(var)
+                            .ok_or_else(|| format!("Its commands use %{var}, which isn't set"))?;
+                        out.push_str(&replacement);
+                        i += digits.len();
+                    }
+                    '\\' if i + 1 < chars.len() => {
+                        out.push(chars[i + 1]);
+                        i += 1;
+                    }
+                    c => out.push(c),
+                }
+                i += 1;
+            }
+            commands.push(out);
+        }
+        Ok(commands.join(COMMAND_SEPARATOR))
+    }
+
+    /// `$n` for a trigger capture, refusing ones past `$9`.
+    fn trigger_capture(pattern: &Pattern) -> impl Fn(usize) -> Option<String> + '_ {
+        |var| {
+            pattern
+                .capture(var)
+                .filter(|&n| n <= MAX_CAPTURE)
+                .map(|n| format!("${n}"))
+        }
+    }
+
+    fn action(args: &[String], group: String, out: &mut Converted) -> Result<(), String> {
+        let [pattern_text, commands, ..] = args else {
+            return Err("It has no commands".to_string());
+        };
+        let pattern = pattern(pattern_text)?;
+        let body = body(commands, &trigger_capture(&pattern))?;
+        out.triggers.push(NewTrigger {
+            name: None,
+            pattern: pattern.regex,
+            match_mode: MatchMode::Regex,
+            body,
+            group,
+            enabled: true,
+            gag: false,
+            replacement: None,
+        });
+        Ok(())
+    }
+
+    /// TinTin substitutes only the matched text, so the rest of the line is
+    /// captured around it and put back in the replacement. `#gag` and a
+    /// replacement of `.` hide the line.
+    fn substitute(
+        args: &[String],
+        gag: bool,
+        group: String,
+        out: &mut Converted,
+    ) -> Result<(), String> {
+        let Some(pattern_text) = args.first() else {
+            return Err("It has no pattern".to_string());
+        };
+        let replacement = args.get(1).map(String::as_str);
+        if gag || replacement == Some(".") {
+            let pattern = pattern(pattern_text)?;
+            out.triggers.push(NewTrigger {
+                name: None,
+                pattern: pattern.regex,
+                match_mode: MatchMode::Regex,
+                body: String::new(),
+                group,
+                enabled: true,
+                gag: true,
+                replacement: None,
+            });
+            return Ok(());
+        }
+        let Some(replacement) = replacement else {
+            return Err("It has no replacement".to_string());
+        };
+        if replacement.contains('<') && replacement.contains('>') {
+            return Err("Color codes in substitutions can't be converted".to_string());
+        }
+        let mut pattern = pattern(pattern_text)?;
+        let (before, after) = (
+            !pattern.regex.starts_with('^'),
+            !pattern.regex.ends_with('$'),
+        );
+        if before {
+            pattern.regex = format!("^(.*?){}", pattern.regex);
+            pattern.vars.insert(0, 0);
+        }
+        if after {
+            pattern.regex.push_str("(.*)$");
+            pattern.vars.push(0);
+        }
+        let offset = usize::from(before);
+        let capture = |var: usize| match var {
+            // %0 is the matched text, not the whole line
+            0 => None,
+            var => pattern
+                .vars
+                .iter()
+                .skip(offset)
+                .position(|&v| v == var)
+                .map(|i| i + 1 + offset),
+        };
+        let capture = |var: usize| {
+            capture(var)
+                .filter(|&n| n <= MAX_CAPTURE)
+                .map(|n| format!("${n}"))
+        };
+        let mut text = body(replacement, &capture)?.replace(COMMAND_SEPARATOR, ";");
+        if before {
+            text = format!("$1{text}");
+        }
+        if after {
+            let last = pattern.vars.len();
+            if last > MAX_CAPTURE {
+                return Err("Its pattern has too many wildcards".to_string());
+            }
+            text.push_str(&format!("${last}"));
+        }
+        out.triggers.push(NewTrigger {
+            name: None,
+            pattern: pattern.regex,
+            match_mode: MatchMode::Regex,
+            body: String::new(),
+            group,
+            enabled: true,
+            gag: false,
+            replacement: Some(text),
+        });
+        Ok(())
+    }
+
+    /// Without wildcards an alias is a prefix alias and, as in TinTin, a body
+    /// that uses no `%n` gets the arguments appended. With wildcards it
+    /// becomes a regex over the whole input.
+    fn alias(args: &[String], group: String, out: &mut Converted) -> Result<(), String> {
+        let [name, commands, ..] = args else {
+            return Err("It has no commands".to_string());
+        };
+        if !name.contains('%') && !name.contains(char::is_whitespace) {
+            let capture = |var: usize| match var {
+                0 => Some("$*".to_string()),
+                var if var <= MAX_CAPTURE => Some(format!("${var}")),
+                _ => None,
+            };
+            let mut body = body(commands, &capture)?;
+            if !commands.contains('%') {
+                body.push_str(" $*");
+            }
+            out.aliases.push(NewAlias {
+                name: None,
+                pattern: name.clone(),
+                match_mode: AliasMatchMode::Prefix,
+                body,
+                group,
+                enabled: true,
+            });
+            return Ok(());
+        }
+        let mut pattern = pattern(name)?;
+        if !pattern.regex.starts_with('^') {
+            pattern.regex.insert(0, '^');
+        }
+        if !pattern.regex.ends_with('$') {
+            pattern.regex.push('$');
+        }
+        let capture = |var: usize| match var {
+            0 => Some("$*".to_string()),
+            var => trigger_capture(&pattern)(var),
+        };
+        let body = body(commands, &capture)?;
+        out.aliases.push(NewAlias {
+            name: None,
+            pattern: pattern.regex.clone(),
+            match_mode: AliasMatchMode::Regex,
+            body,
+            group,
+            enabled: true,
+        });
+        Ok(())
+    }
+
+    /// Colors are space- or comma-separated: names (`light` or `bold` before
+    /// one makes it bright, `b` makes it the background), `<abc>` from the
+    /// 6×6×6 cube and `<Frrggbb>`/`<Brrggbb>` true colors.
+    fn highlight(args: &[String], out: &mut Converted) -> Result<(), String> {
+        let [pattern_text, colors, ..] = args else {
+            return Err("It has no color".to_string());
+        };
+        let pattern = pattern(pattern_text)?;
+        let mut style = HighlightStyle::default();
+        let mut bright = false;
+        let mut background = false;
+        for token in colors
+            .split([' ', ','])
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+        {
+            let token = token.to_lowercase();
+            let color = match token.as_str() {
+                "bold" => {
+                    style.bold = true;
+                    bright = true;
+                    continue;
+                }
+                "light" => {
+                    bright = true;
+                    continue;
+                }
+                "b" => {
+                    background = true;
+                    continue;
+                }
+                "reset" | "underscore" | "blink" | "reverse" | "dim" | "faint" => continue,
+                "black" | "red" | "green" | "yellow" | "blue" | "magenta" | "cyan" | "white" => {
+                    let name = if bright {
+                        format!("bright_{token}")
+                    } else {
+                        token.clone()
+                    };
+                    Color::Name(name)
+                }
+                _ => cube_color(&token)
+                    .ok_or_else(|| format!("The color {token:?} can't be converted"))?,
+            };
+            if background {
+                style.bg = Some(color);
+            } else {
+                style.fg = Some(color);
+            }
+            bright = false;
+            background = false;
+        }
+        if style.fg.is_none() && style.bg.is_none() && !style.bold {
+            return Err("It sets no color DartForge can show".to_string());
+        }
+        out.highlights.push(HighlightRule {
+            pattern: pattern.regex,
+            enabled: true,
+            name: None,
+            ignore_case: Some(false),
+            style,
+        });
+        Ok(())
+    }
+
+    /// `<abc>` (each digit 0–5) as a 256-color index, `<frrggbb>` as `#rrggbb`.
+    /// A `<brrggbb>` background isn't told apart here; the caller's `b` is.
+    fn cube_color(token: &str) -> Option<Color> {
+        let inner = token.strip_prefix('<')?.strip_suffix('>')?;
+        let digits: Vec<u8> = inner.bytes().collect();
+        match digits.as_slice() {
+            [r, g, b] if [r, g, b].iter().all(|d| (b'0'..=b'5').contains(d)) => {
+                let index = 16 + 36 * (r - b'0') + 6 * (g - b'0') + (b - b'0');
+                Some(Color::Index(index))
+            }
+            [b'f' | b'b', hex @ ..] if hex.len() == 6 && hex.iter().all(u8::is_ascii_hexdigit) => {
+                Some(Color::Name(format!("#{}", &inner[1..])))
+            }
+            _ => None,
+        }
+    }
+}
+
+mod mudlet {
+    //! Mudlet packages: `<TriggerPackage>` and `<AliasPackage>` trees of items
+    //! and folders. Only plain commands and Lua scripts made of `send(...)`
+    //! and `deleteLine()` calls convert; other Lua has no equivalent.
+
+    use std::io::Read;
+
+    use roxmltree::Node;
+
+    use super::*;
+
+    /// Zip entries bigger than this aren't read.
+    const MAX_XML_BYTES: u64 = 16 * 1024 * 1024;
+    /// Packages whose items aren't imported, and how to name one.
+    const OTHER_PACKAGES: [(&str, &str); 5] = [
+        ("TimerPackage", "timer"),
+        ("ScriptPackage", "script"),
+        ("KeyPackage", "key binding"),
+        ("ActionPackage", "button"),
+        ("VariablePackage", "variable"),
+    ];
+
+    /// The package XML: the file itself, or the first `.xml` in an
+    /// `.mpackage` zip.
+    pub(super) fn package_xml(data: Vec<u8>) -> Result<String, String> {
+        if !data.starts_with(b"PK") {
+            return String::from_utf8(data)
+                .map_err(|_| "Not a Mudlet package: it isn't text".to_string());
+        }
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+            .map_err(|e| format!("Not a Mudlet package: {e}"))?;
+        let index = (0..archive.len())
+            .find(|&i| {
+                archive
+                    .by_index(i)
+                    .is_ok_and(|entry| entry.is_file() && entry.name().ends_with(".xml"))
+            })
+            .ok_or("Not a Mudlet package: it holds no .xml file")?;
+        let entry = archive
+            .by_index(index)
+            .map_err(|e| format!("Failed to read the package: {e}"))?;
+        if entry.size() > MAX_XML_BYTES {
+            return Err("The package's XML is too large to import".to_string());
+        }
+        let mut xml = String::new();
+        entry
+            .take(MAX_XML_BYTES)
+            .read_to_string(&mut xml)
+            .map_err(|e| format!("Failed to read the package: {e}"))?;
+        Ok(xml)
+    }
+
+    pub(super) fn convert(xml: &str) -> Result<Converted, String> {
+        let options = roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let doc = roxmltree::Document::parse_with_options(xml, options)
+            .map_err(|e| format!("Not a Mudlet package: {e}"))?;
+        let root = doc.root_element();
+        if root.tag_name().name() != "MudletPackage" {
+            return Err("Not a Mudlet package: it has no <MudletPackage>".to_string());
+        }
+        let mut out = Converted::default();
+        for package in root.children().filter(Node::is_element) {
+            match package.tag_name().name() {
+                "TriggerPackage" => triggers(package, "Mudlet", true, &mut out),
+                "AliasPackage" => aliases(package, "Mudlet", true, &mut out),
+                other => {
+                    let Some((_, kind)) = OTHER_PACKAGES.iter().find(|(name, _)| *name == other)
+                    else {
+                        continue;
+                    };
+                    for item in package.children().filter(Node::is_element) {
+                        out.skip(
+                            format!("Mudlet {kind} {:?}", text(item, "name")),
+                            format!("Mudlet {kind}s aren't imported"),
+                        );
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn text(node: Node, name: &str) -> String {
+        node.children()
+            .find(|child| child.has_tag_name(name))
+            .and_then(|child| child.text())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn flag(node: Node, name: &str) -> bool {
+        node.attribute(name) == Some("yes")
+    }
+
+    fn triggers(parent: Node, group: &str, enabled: bool, out: &mut Converted) {
+        for node in parent
+            .children()
+            .filter(|n| n.has_tag_name("Trigger") || n.has_tag_name("TriggerGroup"))
+        {
+            let name = text(node, "name");
+            let active = enabled && flag(node, "isActive");
+            if flag(node, "isFolder") {
+                triggers(node, &name, active, out);
+                continue;
+            }
+            let source = format!("Mudlet trigger {name:?}");
+            if let Err(reason) = trigger(node, &name, group, active, out) {
+                out.skip(&source, reason);
+            }
+            for child in node
+                .children()
+                .filter(|n| n.has_tag_name("Trigger") || n.has_tag_name("TriggerGroup"))
+            {
+                out.skip(
+                    format!("Mudlet trigger {:?}", text(child, "name")),
+                    format!("It only runs as part of {source}'s chain"),
+                );
+            }
+        }
+    }
+
+    /// One trigger is made per pattern, since Mudlet fires on any of them.
+    fn trigger(
+        node: Node,
+        name: &str,
+        group: &str,
+        enabled: bool,
+        out: &mut Converted,
+    ) -> Result<(), String> {
+        if flag(node, "isMultiline") {
+            return Err("Multi-line (AND) triggers can't be converted".to_string());
+        }
+        if flag(node, "isFilterTrigger") {
+            return Err("Filter triggers can't be converted".to_string());
+        }
+        let patterns = list(node, "regexCodeList");
+        let kinds = list(node, "regexCodePropertyList");
+        if patterns.is_empty() {
+            return Err("It has no patterns".to_string());
+        }
+        let (commands, gag) = commands(node)?;
+        if flag(node, "isSoundTrigger") && !text(node, "mSoundFile").is_empty() {
+            out.skip(
+                format!("Mudlet trigger {name:?} sound"),
+                "Sound files aren't imported",
+            );
+        }
+        let colors = flag(node, "isColorizerTrigger")
+            .then(|| colorizer(node))
+            .flatten();
+        for (i, pattern) in patterns.iter().enumerate() {
+            let label = if patterns.len() > 1 {
+                format!("{name} ({})", i + 1)
+            } else {
+                name.to_string()
+            };
+            let kind = kinds.get(i).and_then(|k| k.parse::<u8>().ok()).unwrap_or(0);
+            let (pattern, match_mode) = match kind {
+                0 => (pattern.clone(), MatchMode::Substring),
+                1 => (pattern.clone(), MatchMode::Regex),
+                2 => (format!("^{}", escape_regex(pattern)), MatchMode::Regex),
+                3 => (pattern.clone(), MatchMode::Exact),
+                _ => {
+                    let what = match kind {
+                        4 => "Lua function patterns",
+                        5 => "Line spacer patterns",
+                        6 => "Color patterns",
+                        7 => "Prompt patterns",
+                        _ => "Unknown pattern types",
+                    };
+                    out.skip(
+                        format!("Mudlet trigger {label:?}"),
+                        format!("{what} can't be converted"),
+                    );
+                    continue;
+                }
+            };
+            if let Some(style) = &colors {
+                let regex = match match_mode {
+                    MatchMode::Substring => escape_regex(&pattern),
+                    MatchMode::Exact => format!("^{}$", escape_regex(&pattern)),
+                    MatchMode::Regex => pattern.clone(),
+                };
+                out.highlights.push(HighlightRule {
+                    pattern: regex,
+                    enabled,
+                    name: Some(label.clone()),
+                    ignore_case: Some(false),
+                    style: style.clone(),
+                });
+            }
+            if commands.is_empty() && !gag && colors.is_some() {
+                continue;
+            }
+            out.triggers.push(NewTrigger {
+                name: Some(label),
+                pattern,
+                match_mode,
+                body: commands.clone(),
+                group: group.to_string(),
+                enabled,
+                gag,
+                replacement: None,
+            });
+        }
+        Ok(())
+    }
+
+    fn list(node: Node, name: &str) -> Vec<String> {
+        node.children()
+            .find(|child| child.has_tag_name(name))
+            .map(|list| {
+                list.children()
+                    .filter(Node::is_element)
+                    .map(|item| item.text().unwrap_or_default().to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// A colorizer trigger's `#rrggbb` colors; `transparent` is no color.
+    fn colorizer(node: Node) -> Option<HighlightStyle> {
+        let color = |name: &str| {
+            let value = text(node, name);
+            (value.len() == 7 && value.starts_with('#')).then(|| Color::Name(value.to_lowercase()))
+        };
+        let style = HighlightStyle {
+            fg: color("mFgColor"),
+            bg: color("mBgColor"),
+            bold: false,
+        };
+        (style.fg.is_some() || style.bg.is_some()).then_some(style)
+    }
+
+    /// The item's body: its command followed by what its script sends, and
+    /// whether the script deletes the line. `matches[1]` is the whole match
+    /// (`$0`), `matches[n]` is group n-1.
+    fn commands(node: Node) -> Result<(String, bool), String> {
+        let mut commands = Vec::new();
+        let command = format!("{}{}", text(node, "mCommand"), text(node, "command"));
+        if !command.trim().is_empty() {
+            commands.push(command.trim().to_string());
+        }
+        let mut gag = false;
+        for line in text(node, "script").lines() {
+            let line = line.trim().trim_end_matches(';').trim();
+            if line.is_empty() || line.starts_with("--") {
+                continue;
+            }
+            if line == "deleteLine()" {
+                gag = true;
+                continue;
+            }
+            let sent = line
+                .strip_prefix("send(")
+                .and_then(|rest| rest.strip_suffix(')'))
+                .map(|args| args.trim_end_matches(", false").trim_end_matches(", true"))
+                .and_then(concatenation);
+            match sent {
+                Some(text) => commands.push(text),
+                None => return Err("Lua script bodies can't be converted".to_string()),
+            }
+        }
+        Ok((commands.join(COMMAND_SEPARATOR), gag))
+    }
+
+    /// A Lua expression of string literals and `matches[n]` joined with `..`.
+    fn concatenation(expr: &str) -> Option<String> {
+        let mut out = String::new();
+        let mut rest = expr.trim();
+        loop {
+            if let Some(quoted) = rest.strip_prefix('"').or_else(|| rest.strip_prefix('\'')) {
+                let quote = rest.chars().next()?;
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    let (i, c) = chars.next()?;
+                    match c {
+                        '\\' => out.push(chars.next().filter(|(_, c)| "\\\"'".contains(*c))?.1),
+                        c if c == quote => break i,
+                        c => out.push(c),
+                    }
+                };
+                rest = quoted[end + 1..].trim_start();
+            } else if let Some(quoted) = rest.strip_prefix("[[") {
+                let end = quoted.find("]]")?;
+                out.push_str(&quoted[..end]);
+                rest = quoted[end + 2..].trim_start();
+            } else if let Some(index) = rest.strip_prefix("matches[") {
+                let end = index.find(']')?;
+                let n: usize = index[..end].trim().parse().ok()?;
+                if n == 0 || n > MAX_CAPTURE + 1 {
+                    return None;
+                }
+                out.push_str(&format!("${}", n - 1));
+                rest = index[end + 1..].trim_start();
+            } else {
+                return None;
+            }
+            if rest.is_empty() {
+                return Some(out);
+            }
+            rest = rest.strip_prefix("..")?.trim_start();
+        }
+    }
+
+    fn aliases(parent: Node, group: &str, enabled: bool, out: &mut Converted) {
+        for node in parent
+            .children()
+            .filter(|n| n.has_tag_name("Alias") || n.has_tag_name("AliasGroup"))
+        {
+            let name = text(node, "name");
+            let active = enabled && flag(node, "isActive");
+            if flag(node, "isFolder") {
+                aliases(node, &name, active, out);
+                continue;
+            }
+            let pattern = text(node, "regex");
+            let result = match commands(node) {
+                _ if pattern.is_empty() => Err("It has no pattern".to_string()),
+                Ok((_, true)) => Err("deleteLine() has no meaning in an alias".to_string()),
+                Ok((body, false)) => Ok(body),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(body) => out.aliases.push(NewAlias {
+                    name: (!name.is_empty()).then_some(name),
+                    pattern,
+                    match_mode: AliasMatchMode::Regex,
+                    body,
+                    group: group.to_string(),
+                    enabled: active,
+                }),
+                Err(reason) => out.skip(format!("Mudlet alias {name:?}"), reason),
+            }
+        }
+    }
+}
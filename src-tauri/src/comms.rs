@@ -0,0 +1,386 @@
+//! Communication routing: tells, says, shouts and channel lines are picked
+//! out of the line stream by the rules in `channels.json` (DartMUD's formats
+//! by default) and emitted as `mud:comms`, besides appearing in the output as
+//! usual. Each message is also appended to `logs/<profile>/comms-<date>.txt`.
+//! A rule with `gag` set takes its lines out of the output instead.
+//!
+//! A message can run over several lines: indented lines after it are always
+//! part of it, and with `open_until` so is every line until one matches that
+//! pattern (a closing quote, say). The message is emitted once the next line
+//! that isn't part of it, or a prompt, arrives.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{info, warn};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::connection::ConnectionShared;
+use crate::events::{self, CommsPayload, COMMS_EVENT};
+use crate::lines;
+use crate::session_log::LOGS_DIR;
+
+pub const CHANNELS_FILE: &str = "channels.json";
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+/// Enabled rules beyond this many are not loaded.
+const MAX_RULES: usize = 100;
+/// A message is cut off after this many lines.
+const MAX_MESSAGE_LINES: usize = 20;
+/// Ends a quoted message: the closing quote, perhaps followed by a period.
+const CLOSING_QUOTE: &str = r"'\.?$";
+
+/// Shipped rules as `(id, channel, pattern, open_until)`.
+const DEFAULT_RULES: [(&str, &str, &str, Option<&str>); 8] = [
+    (
+        "tell",
+        "tell",
+        r#"^A mental touch tells you:\s*['"](?P<text>.*)"#,
+        None,
+    ),
+    (
+        "own_say",
+        "say",
+        r"^(?P<speaker>You) (?:say|ask|exclaim) in \w+, '(?P<text>.*)",
+        Some(CLOSING_QUOTE),
+    ),
+    (
+        "own_shout",
+        "shout",
+        r"^(?P<speaker>You) (?:shout|yell) in \w+, '(?P<text>.*)",
+        Some(CLOSING_QUOTE),
+    ),
+    (
+        "own_ooc",
+        "ooc",
+        r"^(?P<speaker>You) say \(OOC\),\s*'(?P<text>.*)",
+        Some(CLOSING_QUOTE),
+    ),
+    (
+        "say",
+        "say",
+        r"^(?P<speaker>\w+) (?:says|asks|exclaims)(?: (?:to )?\w+)? in \w+, '(?P<text>.*)",
+        Some(CLOSING_QUOTE),
+    ),
+    (
+        "shout",
+        "shout",
+        r"^(?P<speaker>\w+) (?:shouts|yells) in \w+, '(?P<text>.*)",
+        Some(CLOSING_QUOTE),
+    ),
+    (
+        "ooc",
+        "ooc",
+        r"^(?P<speaker>\w+) says \(OOC\),\s*'(?P<text>.*)",
+        Some(CLOSING_QUOTE),
+    ),
+    (
+        "voice",
+        "voice",
+        r"^A voice seems to say: '(?P<text>.*)",
+        Some(CLOSING_QUOTE),
+    ),
+];
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommsRule {
+    /// Regex matched against the ANSI-stripped line. Optional `speaker`,
+    /// `text` and `channel` groups fill in the message; without `text` the
+    /// whole line is the text.
+    pub pattern: String,
+    /// Channel reported unless the pattern has a `channel` group.
+    pub channel: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Remove the message's lines from the output.
+    #[serde(default)]
+    pub gag: bool,
+    /// Lines after the first belong to the message until one matches this.
+    #[serde(default)]
+    pub open_until: Option<String>,
+    /// Case-insensitive unless set to false.
+    #[serde(default)]
+    pub ignore_case: Option<bool>,
+}
+
+/// Contents of channels.json. Rules are keyed by id and tried in id order,
+/// before the shipped ones; a rule with a shipped rule's id replaces it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelsFile {
+    pub rules: BTreeMap<String, CommsRule>,
+    pub use_defaults: bool,
+    /// Append messages to the comms log.
+    pub log: bool,
+}
+
+impl Default for ChannelsFile {
+    fn default() -> Self {
+        Self {
+            rules: BTreeMap::new(),
+            use_defaults: true,
+            log: true,
+        }
+    }
+}
+
+impl ChannelsFile {
+    pub fn load(dir: &Path) -> Self {
+        crate::storage::read_json_file(dir, CHANNELS_FILE).unwrap_or_default()
+    }
+}
+
+fn compile(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {e}"))
+}
+
+#[derive(Clone)]
+struct CompiledRule {
+    regex: Regex,
+    channel: String,
+    gag: bool,
+    open_until: Option<Regex>,
+}
+
+impl CompiledRule {
+    fn new(rule: &CommsRule) -> Result<Self, String> {
+        let ignore_case = rule.ignore_case.unwrap_or(true);
+        Ok(Self {
+            regex: compile(&rule.pattern, ignore_case)?,
+            channel: rule.channel.clone(),
+            gag: rule.gag,
+            open_until: rule
+                .open_until
+                .as_deref()
+                .map(|p| compile(p, ignore_case))
+                .transpose()?,
+        })
+    }
+}
+
+fn default_rules() -> impl Iterator<Item = (String, CommsRule)> {
+    DEFAULT_RULES
+        .iter()
+        .map(|(id, channel, pattern, open_until)| {
+            let rule = CommsRule {
+                pattern: pattern.to_string(),
+                channel: channel.to_string(),
+                enabled: true,
+                gag: false,
+                open_until: open_until.map(str::to_string),
+                // DartMUD's formats are matched as written
+                ignore_case: Some(false),
+            };
+            (id.to_string(), rule)
+        })
+}
+
+struct CompiledRules {
+    rules: Vec<CompiledRule>,
+    log: bool,
+}
+
+pub struct CommsRules {
+    inner: Mutex<CompiledRules>,
+}
+
+impl Default for CommsRules {
+    fn default() -> Self {
+        let rules = default_rules()
+            .filter_map(|(_, rule)| CompiledRule::new(&rule).ok())
+            .collect();
+        Self {
+            inner: Mutex::new(CompiledRules { rules, log: true }),
+        }
+    }
+}
+
+impl CommsRules {
+    fn lock(&self) -> std::sync::MutexGuard<'_, CompiledRules> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Re-read channels.json. Rules with invalid patterns are skipped.
+    /// Returns how many rules are in use.
+    pub fn reload(&self, dir: &Path) -> usize {
+        let file = ChannelsFile::load(dir);
+        let defaults =
+            default_rules().filter(|(id, _)| file.use_defaults && !file.rules.contains_key(id));
+        let mut rules = Vec::new();
+        for (id, rule) in file
+            .rules
+            .clone()
+            .into_iter()
+            .chain(defaults)
+            .filter(|(_, rule)| rule.enabled)
+        {
+            if rules.len() == MAX_RULES {
+                warn!("Only the first {MAX_RULES} channel rules are used; skipping {id} and later rules");
+                break;
+            }
+            match CompiledRule::new(&rule) {
+                Ok(compiled) => rules.push(compiled),
+                Err(e) => warn!("Skipping channel rule {id}: {e}"),
+            }
+        }
+        let count = rules.len();
+        *self.lock() = CompiledRules {
+            rules,
+            log: file.log,
+        };
+        info!("Loaded {count} channel rule(s)");
+        count
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.lock().rules.is_empty()
+    }
+
+    fn classify(&self, text: &str) -> Option<Pending> {
+        let inner = self.lock();
+        inner.rules.iter().find_map(|rule| {
+            let caps = rule.regex.captures(text)?;
+            let group = |name| caps.name(name).map(|m| m.as_str().trim().to_string());
+            Some(Pending {
+                channel: group("channel").unwrap_or_else(|| rule.channel.clone()),
+                speaker: group("speaker"),
+                text: group("text").unwrap_or_else(|| text.to_string()),
+                raw: vec![text.to_string()],
+                gag: rule.gag,
+                open: rule
+                    .open_until
+                    .as_ref()
+                    .is_some_and(|end| !end.is_match(text)),
+                open_until: rule.open_until.clone(),
+            })
+        })
+    }
+
+    fn logging(&self) -> bool {
+        self.lock().log
+    }
+}
+
+/// A message whose continuation lines may still be coming.
+struct Pending {
+    channel: String,
+    speaker: Option<String>,
+    text: String,
+    raw: Vec<String>,
+    gag: bool,
+    /// Waiting for a line matching `open_until`.
+    open: bool,
+    open_until: Option<Regex>,
+}
+
+/// Per-connection routing: the message being stitched together.
+#[derive(Default)]
+pub struct CommsRouter {
+    pending: Option<Pending>,
+    /// Generation of the connection it routes for, carried by its events.
+    generation: u64,
+}
+
+impl CommsRouter {
+    pub fn new(generation: u64) -> Self {
+        Self {
+            generation,
+            ..Default::default()
+        }
+    }
+
+    /// Route a complete line. Returns whether to gag it.
+    pub fn on_line<R: Runtime>(
+        &mut self,
+        app: &AppHandle<R>,
+        shared: &ConnectionShared,
+        line: &str,
+    ) -> bool {
+        let text = lines::match_text(line);
+        let text = text.trim_end();
+        if let Some(pending) = self.pending.as_mut() {
+            let indented = text.starts_with([' ', '\t']) && !text.trim().is_empty();
+            if pending.open || indented {
+                let part = text.trim();
+                pending.text = format!("{} {part}", pending.text);
+                pending.raw.push(text.to_string());
+                if let Some(end) = &pending.open_until {
+                    pending.open = pending.open && !end.is_match(part);
+                }
+                let gag = pending.gag;
+                if pending.raw.len() >= MAX_MESSAGE_LINES {
+                    self.finish(app, shared);
+                }
+                return gag;
+            }
+            self.finish(app, shared);
+        }
+        let Some(pending) = shared.comms.classify(text.trim_start_matches("> ")) else {
+            return false;
+        };
+        let gag = pending.gag;
+        self.pending = Some(pending);
+        gag
+    }
+
+    /// Emit the message being stitched together, if any.
+    pub fn finish<R: Runtime>(&mut self, app: &AppHandle<R>, shared: &ConnectionShared) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        // The closing quote of a spoken message, and a period after it, aren't part of it
+        let text = pending
+            .text
+            .strip_suffix('.')
+            .filter(|t| t.ends_with(['\'', '"']))
+            .unwrap_or(&pending.text);
+        let payload = CommsPayload {
+            session: shared.session.clone(),
+            channel: pending.channel,
+            speaker: pending.speaker,
+            text: text.trim_end_matches(['\'', '"']).to_string(),
+            raw: pending.raw.join("\n"),
+            generation: self.generation,
+        };
+        if shared.comms.logging() {
+            let dir = app.state::<crate::storage::StorageState>().get_dir();
+            if let Err(e) = append_log(&dir, &shared.session_log.profile_dir(), &payload) {
+                warn!("Failed to write the comms log: {e}");
+            }
+        }
+        let _ = events::emit(app, COMMS_EVENT, payload);
+    }
+}
+
+fn append_log(data_dir: &Path, profile_dir: &str, message: &CommsPayload) -> std::io::Result<()> {
+    let now = chrono::Local::now();
+    let dir = data_dir.join(LOGS_DIR).join(profile_dir);
+    crate::data_lock::check_writable(&dir).map_err(std::io::Error::other)?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("comms-{}.txt", now.format("%Y-%m-%d")));
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let speaker = message
+        .speaker
+        .as_deref()
+        .map(|s| format!(" {s}:"))
+        .unwrap_or_default();
+    writeln!(
+        file,
+        "[{}] [{}]{speaker} {}",
+        now.format("%H:%M:%S"),
+        message.channel,
+        message.text
+    )
+}
@@ -1,17 +1,58 @@
-use std::net::ToSocketAddrs;
-use log::{error, info, warn};
-use tauri::{AppHandle, Emitter};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use tauri::{AppHandle, Manager, Runtime};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
-use tokio::time::{timeout, Duration};
+use tokio::time::{timeout, Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::sync::Mutex as TokioMutex;
 
+use crate::aliases::AliasTable;
 use crate::ansi;
+use crate::anti_idle::{self, AntiIdle, AntiIdleSettings};
+use crate::autologin::{AutoLogin, LoginDriver};
+use crate::capture::RawCapture;
+use crate::comms::{CommsRouter, CommsRules};
 use crate::companion::CompanionMessage;
-use crate::events::{ConnectionStatusPayload, MudOutputPayload, CONNECTION_STATUS_EVENT, MUD_OUTPUT_EVENT};
+use crate::events::{
+    self, now_ms, CommandQueueFlushedPayload, ConnectionStatusPayload, CurrentStatus,
+    GaggedLinesPayload, IdleLimitPayload, InputEchoPayload, MudOutputPayload, SocketOptionsPayload,
+    StatusReason, COMMAND_QUEUE_FLUSHED_EVENT, CONNECTION_STATUS_EVENT, GAGGED_LINES_EVENT,
+    IDLE_LIMIT_EVENT, INPUT_ECHO_EVENT, STATS_EVENT, TRIGGER_FIRED_EVENT,
+};
+use crate::filters::GagFilters;
+use crate::forwarder::{self, CommandRequest, RateLimit};
+use crate::happy_eyeballs::{self, AttemptError};
+use crate::highlights::{HighlightRules, Highlighter};
+use crate::history::CommandHistory;
+use crate::latency::{self, LatencyProbe, Tick};
+use crate::lines::LineSplitter;
+use crate::outbound::{self, OutboundProxy, ProxyError};
+use crate::output_pause::OutputPause;
+use crate::plain_output::PlainLines;
+use crate::profiles::Profile;
+use crate::rooms::{self, MapStore, RoomRules, RoomTracker};
+use crate::scripting::Scripts;
+use crate::scrollback::{Scrollback, DEFAULT_SCROLLBACK_BYTES};
+use crate::session_log::SessionLog;
+use crate::session_summary::{SessionCounters, SessionTracker};
+use crate::sleep_watch::SleepWatch;
+use crate::stats::ConnectionStats;
+use crate::telnet_debug::{self, TelnetDebug};
+use crate::timers::TimerScheduler;
+use crate::triggers::TriggerEngine;
+use crate::vitals::{PromptParsers, PromptWatch};
+use crate::ws_proxy::{
+    self, ClientMessage, ProxyReader, ProxySocket, ProxyWriter, ServerMessage, WsError,
+};
 
 /// Shared type for tracking the last connection status.
 pub type LastStatus = Arc<TokioMutex<Option<(bool, String)>>>;
@@ -25,72 +66,1292 @@ fn set_status(last_status: &LastStatus, connected: bool, message: &str) {
 
 const MUD_HOST: &str = "dartmud.com";
 const MUD_PORT: u16 = 2525;
-const READ_BUF_SIZE: usize = 4096;
+const DEFAULT_READ_BUF_SIZE: usize = 4096;
+/// Bounds on the configurable read buffer.
+pub const MIN_READ_BUF_SIZE: usize = 512;
+pub const MAX_READ_BUF_SIZE: usize = 1024 * 1024;
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 const MAX_RETRIES: u32 = 3;
 const RETRY_DELAY: Duration = Duration::from_secs(2);
+/// How long the WebSocket proxy gets to reach the MUD once asked, its own
+/// retries included.
+const PROXY_DIAL_TIMEOUT: Duration = Duration::from_secs(45);
 /// Max time a single write may block before we treat the connection as stalled.
 /// Commands are tiny, so a write that can't drain in this window means the
 /// socket is half-open (server gone / TCP backpressure with no drain) and the
 /// connection should be torn down rather than silently swallowing input.
 const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Max time a cancelled connection gets to flush queued commands (and the quit
+/// command) before the socket is shut down anyway.
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+/// The longest a cancelled `run_session` spends shutting down: draining the
+/// forwarder, queueing the quit command and draining the writer, each within
+/// `SHUTDOWN_FLUSH_TIMEOUT`.
+pub const SHUTDOWN_BUDGET: Duration = Duration::from_secs(3 * SHUTDOWN_FLUSH_TIMEOUT.as_secs());
+/// How long a connection has to answer after the machine wakes from sleep
+/// before it is taken to be dead and reconnected.
+const RESUME_CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+/// A stalled connection that has read nothing for this long is reconnected,
+/// even without `reconnect_on_stall`.
+const STALE_READ_AFTER: Duration = Duration::from_secs(5 * 60);
+/// How often `mud:stats` is emitted while connected.
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+/// Default output coalescing: flush buffered display text this long after its
+/// first byte arrived (about one frame), or once this much is buffered.
+const OUTPUT_MAX_DELAY: Duration = Duration::from_millis(16);
+const OUTPUT_MAX_BYTES: usize = 64 * 1024;
 
-pub async fn connect(
+/// Max commands held while disconnected or still connecting.
+pub const MAX_PENDING_COMMANDS: usize = 50;
+
+#[derive(Default)]
+struct CommandQueueInner {
+    /// Sender into the live connection's command forwarder, set once connected.
+    tx: Option<mpsc::Sender<CommandRequest>>,
+    /// Commands typed while there was no live connection, oldest first.
+    pending: VecDeque<String>,
+}
+
+/// Where a command from `send_command` should go.
+pub enum Routed {
+    /// Connected — deliver the command through this sender.
+    Live(mpsc::Sender<CommandRequest>, String),
+    /// Not connected yet — the command was queued for the next connection.
+    Queued,
+}
+
+/// Routes user commands to the live connection, or holds them in a bounded
+/// queue while disconnected/connecting. Sender and queue share one lock so a
+/// connection coming up can't reorder queued and newly typed commands.
+#[derive(Default)]
+pub struct CommandQueue {
+    inner: std::sync::Mutex<CommandQueueInner>,
+}
+
+impl CommandQueue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, CommandQueueInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn route(&self, cmd: String) -> Result<Routed, String> {
+        let mut inner = self.lock();
+        if let Some(tx) = &inner.tx {
+            return Ok(Routed::Live(tx.clone(), cmd));
+        }
+        if inner.pending.len() >= MAX_PENDING_COMMANDS {
+            return Err(format!(
+                "Not connected (command queue full, {MAX_PENDING_COMMANDS} pending)"
+            ));
+        }
+        inner.pending.push_back(cmd);
+        Ok(Routed::Queued)
+    }
+
+    /// The live connection's sender, if connected.
+    pub fn live(&self) -> Option<mpsc::Sender<CommandRequest>> {
+        self.lock().tx.clone()
+    }
+
+    /// Make `tx` the live sender, first draining queued commands into it in
+    /// order. Returns how many queued commands were flushed.
+    fn attach(&self, tx: mpsc::Sender<CommandRequest>) -> usize {
+        let mut inner = self.lock();
+        let mut flushed = 0;
+        while let Some(cmd) = inner.pending.pop_front() {
+            if tx.try_send(CommandRequest::Send(cmd)).is_err() {
+                break;
+            }
+            flushed += 1;
+        }
+        inner.tx = Some(tx);
+        flushed
+    }
+
+    /// Drop the live sender; subsequent commands are queued.
+    pub fn detach(&self) {
+        self.lock().tx = None;
+    }
+
+    /// Put commands a dropped connection never wrote back at the front of the
+    /// queue, oldest first, up to the queue limit. Returns how many fit.
+    fn requeue(&self, unsent: Vec<String>) -> usize {
+        let mut inner = self.lock();
+        let room = MAX_PENDING_COMMANDS.saturating_sub(inner.pending.len());
+        let count = unsent.len().min(room);
+        for cmd in unsent.into_iter().take(count).rev() {
+            inner.pending.push_front(cmd);
+        }
+        count
+    }
+
+    /// Discard all queued commands, returning how many were dropped.
+    pub fn clear(&self) -> usize {
+        let mut inner = self.lock();
+        let count = inner.pending.len();
+        inner.pending.clear();
+        count
+    }
+}
+
+/// Where to connect. Defaults to dartmud.com; can be pointed at a literal IP
+/// when DNS is flaky, or keep the hostname but pin it to a known address.
+/// Lives for the app session only — it isn't persisted.
+#[derive(Debug, Clone)]
+pub struct ConnectionTarget {
+    host: String,
+    port: u16,
+    resolve_override: Option<IpAddr>,
+}
+
+impl Default for ConnectionTarget {
+    fn default() -> Self {
+        Self {
+            host: MUD_HOST.to_string(),
+            port: MUD_PORT,
+            resolve_override: None,
+        }
+    }
+}
+
+impl ConnectionTarget {
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Validate user input. `host` may be a hostname or a literal IPv4/IPv6
+    /// address (brackets allowed); `None` or blank means dartmud.com. `port`
+    /// defaults to the standard DartMUD port.
+    pub fn parse(
+        host: Option<&str>,
+        port: Option<u16>,
+        resolve_override: Option<&str>,
+    ) -> Result<Self, String> {
+        let port = match port {
+            Some(0) => return Err("Port must be between 1 and 65535".to_string()),
+            Some(port) => port,
+            None => MUD_PORT,
+        };
+        let host = host
+            .map(str::trim)
+            .filter(|h| !h.is_empty())
+            .unwrap_or(MUD_HOST);
+        let host = match parse_ip(host) {
+            Some(ip) => ip.to_string(),
+            None if is_valid_hostname(host) => host.to_ascii_lowercase(),
+            None => {
+                return Err(format!(
+                    "\"{host}\" is not a valid hostname or IP address (expected something like dartmud.com, 192.0.2.10, or 2001:db8::1)"
+                ))
+            }
+        };
+
+        let resolve_override = match resolve_override.map(str::trim).filter(|o| !o.is_empty()) {
+            None => None,
+            Some(raw) => {
+                let ip = parse_ip(raw).ok_or_else(|| {
+                    format!("DNS override \"{raw}\" must be an IPv4 or IPv6 address")
+                })?;
+                if parse_ip(&host).is_some() {
+                    return Err(
+                        "A DNS override only applies to a hostname, not a literal IP address"
+                            .to_string(),
+                    );
+                }
+                Some(ip)
+            }
+        };
+
+        Ok(Self {
+            host,
+            port,
+            resolve_override,
+        })
+    }
+
+    /// The address to connect to without a DNS lookup, if any.
+    fn pinned_ip(&self) -> Option<IpAddr> {
+        parse_ip(&self.host).or(self.resolve_override)
+    }
+
+    /// "host:port" for status messages, with IPv6 literals bracketed.
+    fn label(&self) -> String {
+        match parse_ip(&self.host) {
+            Some(ip) => SocketAddr::new(ip, self.port).to_string(),
+            None => format!("{}:{}", self.host, self.port),
+        }
+    }
+}
+
+fn parse_ip(s: &str) -> Option<IpAddr> {
+    s.strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(s)
+        .parse()
+        .ok()
+}
+
+fn is_valid_hostname(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        // An all-numeric name is a mistyped IP, not a hostname
+        && !host.split('.').all(|label| label.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Id of the session that single-session callers (the tray, second launches,
+/// commands without a `session_id`) act on.
+pub const DEFAULT_SESSION: &str = "default";
+
+/// State of one session, shared between the Tauri command handlers and its
+/// connection task. Triggers, aliases, gags, highlights, prompt and room
+/// parsers, maps and the command history are common to all sessions; the
+/// rest belongs to this one. Timers only run in the default session.
+#[derive(Default)]
+pub struct ConnectionShared {
+    /// Session id, carried by this session's output, status and echo events.
+    pub session: String,
+    pub commands: CommandQueue,
+    pub stats: ConnectionStats,
+    pub target: Mutex<ConnectionTarget>,
+    /// Echo marker while local echo is enabled. Read on every write so
+    /// `set_local_echo` applies to a live connection.
+    pub local_echo: Mutex<Option<String>>,
+    pub scrollback: Scrollback,
+    pub capture: RawCapture,
+    /// Partial IAC sequence left over from the last raw `simulate_output`.
+    pub simulated_remainder: Mutex<Vec<u8>>,
+    /// Profile the connection was started from; reconnects reuse it.
+    pub active_profile: Mutex<Option<Profile>>,
+    pub triggers: Arc<TriggerEngine>,
+    pub aliases: Arc<AliasTable>,
+    pub filters: Arc<GagFilters>,
+    pub highlights: Arc<HighlightRules>,
+    pub prompt_parsers: Arc<PromptParsers>,
+    pub comms: Arc<CommsRules>,
+    pub session_counters: Arc<SessionCounters>,
+    pub scripts: Arc<Scripts>,
+    pub session_tracker: SessionTracker,
+    pub anti_idle: AntiIdle,
+    pub rooms: Arc<RoomRules>,
+    pub maps: Arc<MapStore>,
+    /// Direction of the last movement command written, and when.
+    pub last_move: Mutex<Option<(String, Instant)>>,
+    pub timers: TimerScheduler,
+    pub history: Arc<CommandHistory>,
+    pub session_log: SessionLog,
+    /// The server has taken over echoing (password entry), so typed input
+    /// is neither echoed nor recorded in history.
+    pub server_echo: AtomicBool,
+    pub telnet_debug: TelnetDebug,
+    /// The last status emitted; see `StatusEmitter`.
+    pub status: Mutex<CurrentStatus>,
+    pub output_pause: OutputPause,
+    /// Emit `mud:output-plain` alongside the normal output; shared by all
+    /// sessions.
+    pub accessible_output: Arc<AtomicBool>,
+    /// Bumped each time a connection task is spawned, once the previous one
+    /// has stopped. Its statuses carry this value, so anything a lingering
+    /// old task emits can be told apart and discarded.
+    pub generation: AtomicU64,
+}
+
+impl ConnectionShared {
+    pub fn new(session: &str) -> Self {
+        Self {
+            session: session.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Another session, with the rules and history common to all sessions
+    /// taken from this one.
+    pub fn sibling(&self, session: &str) -> Self {
+        Self {
+            session: session.to_string(),
+            triggers: self.triggers.clone(),
+            aliases: self.aliases.clone(),
+            filters: self.filters.clone(),
+            highlights: self.highlights.clone(),
+            prompt_parsers: self.prompt_parsers.clone(),
+            comms: self.comms.clone(),
+            session_counters: self.session_counters.clone(),
+            scripts: self.scripts.clone(),
+            rooms: self.rooms.clone(),
+            maps: self.maps.clone(),
+            history: self.history.clone(),
+            accessible_output: self.accessible_output.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Some backend rule needs the output split into lines.
+    fn wants_lines(&self) -> bool {
+        self.triggers.is_active()
+            || self.filters.is_active()
+            || self.comms.is_active()
+            || self.session_counters.is_active()
+            || self.scripts.wants_lines()
+            || self.accessible_output.load(Ordering::Relaxed)
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.session == DEFAULT_SESSION
+    }
+
+    /// Start a new generation, returning it.
+    pub fn next_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+}
+
+/// How a connection reaches the MUD.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Transport {
+    /// Telnet over TCP, directly or through the outbound `proxy`.
+    #[default]
+    Tcp,
+    /// Through the web proxy's `/ws` endpoint (e.g. `wss://proxy.example.com/ws`),
+    /// for networks that only let HTTPS out. The proxy dials the MUD and does
+    /// the telnet handling itself, so the outbound proxy and DNS override
+    /// don't apply, and `token` is its `PROXY_AUTH_TOKEN`.
+    WebSocketProxy { url: String, token: Option<String> },
+}
+
+pub const CONNECTION_SETTINGS_FILE: &str = "connection-settings.json";
+pub const DEFAULT_ECHO_MARKER: &str = "> ";
+
+/// Backend connection settings persisted in the data dir. Loaded fresh on every
+/// connect so changes apply to the next (re)connect without a restart.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectionSettings {
+    /// Sent to the MUD when the connection is cancelled (disconnect/reconnect)
+    /// so the character logs out cleanly instead of going linkdead.
+    pub quit_command: Option<String>,
+    /// Enable OS-level TCP keepalive (SO_KEEPALIVE) probing after this many
+    /// idle seconds.
+    pub tcp_keepalive_secs: Option<u64>,
+    /// Send an application-level keepalive after this many seconds without
+    /// any write, so NAT/idle timeouts never see a silent connection.
+    pub telnet_nop_secs: Option<u64>,
+    /// Command to send as the application keepalive instead of `IAC NOP`
+    /// (e.g. an empty string for a blank line).
+    pub idle_command: Option<String>,
+    /// Reach the MUD through a SOCKS5 or HTTP CONNECT proxy.
+    pub proxy: Option<OutboundProxy>,
+    /// Raw TCP, or the WebSocket proxy.
+    pub transport: Transport,
+    /// Emit `mud:input-echo` for every command written to the socket.
+    pub local_echo: bool,
+    /// Prefix for echoed lines in transcripts; defaults to `DEFAULT_ECHO_MARKER`.
+    pub echo_marker: Option<String>,
+    /// Cap on the outbound command rate; commands over it wait their turn.
+    pub rate_limit: Option<RateLimit>,
+    /// Unlocks protocol debugging tools such as `send_raw_bytes`.
+    pub developer_mode: bool,
+    /// Size of the backend scrollback buffer; defaults to 1MB.
+    pub scrollback_bytes: Option<usize>,
+    /// Keep scrollback from the previous connection instead of starting fresh.
+    pub keep_scrollback_on_reconnect: bool,
+    /// Output coalescing window in ms (0 emits every read as it arrives).
+    pub output_max_delay_ms: Option<u64>,
+    /// Flush coalesced output early once this many bytes are buffered.
+    pub output_max_bytes: Option<usize>,
+    /// Entries kept in the command history; defaults to 10,000.
+    pub history_max_entries: Option<usize>,
+    /// Seconds between latency probes; defaults to 30, 0 turns them off.
+    pub latency_probe_secs: Option<u64>,
+    /// Command to probe latency with, timed to the next prompt, instead of
+    /// `IAC DO TIMING-MARK`. Should be harmless and not trigger anything.
+    pub latency_probe_command: Option<String>,
+    /// Flag the connection stalled after a probe goes unanswered this many
+    /// seconds; defaults to 20.
+    pub stall_secs: Option<u64>,
+    /// Drop and reconnect a stalled connection instead of only flagging it.
+    pub reconnect_on_stall: bool,
+    /// Disable Nagle's algorithm so short commands go out at once; defaults
+    /// to on.
+    pub tcp_nodelay: Option<bool>,
+    /// SO_RCVBUF / SO_SNDBUF sizes to ask for; the OS default when unset.
+    pub recv_buffer_bytes: Option<usize>,
+    pub send_buffer_bytes: Option<usize>,
+    /// Bytes taken per read from the socket; defaults to 4096.
+    pub read_buffer_bytes: Option<usize>,
+    /// Send a harmless command now and then to avoid the idle logout.
+    pub anti_idle: AntiIdleSettings,
+    /// Emit the plain-text `mud:output-plain` stream for screen readers.
+    pub accessible_output: bool,
+}
+
+impl ConnectionSettings {
+    pub fn load(dir: &Path) -> Self {
+        crate::storage::read_json_file(dir, CONNECTION_SETTINGS_FILE).unwrap_or_default()
+    }
+
+    /// The echo marker if local echo is enabled.
+    pub fn echo_marker(&self) -> Option<String> {
+        self.local_echo.then(|| {
+            self.echo_marker
+                .clone()
+                .unwrap_or_else(|| DEFAULT_ECHO_MARKER.to_string())
+        })
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        crate::storage::write_json_file(dir, CONNECTION_SETTINGS_FILE, self)
+    }
+
+    /// Interval and bytes for the write-idle keepalive, if enabled.
+    fn keepalive(&self) -> Option<(Duration, Vec<u8>)> {
+        let secs = self.telnet_nop_secs.filter(|s| *s > 0)?;
+        let payload = match &self.idle_command {
+            Some(cmd) => command_bytes(cmd),
+            None => ansi::IAC_NOP.to_vec(),
+        };
+        Some((Duration::from_secs(secs), payload))
+    }
+
+    fn read_buffer_size(&self) -> usize {
+        self.read_buffer_bytes
+            .unwrap_or(DEFAULT_READ_BUF_SIZE)
+            .clamp(MIN_READ_BUF_SIZE, MAX_READ_BUF_SIZE)
+    }
+
+    /// The latency prober for a new connection, if probing is enabled.
+    fn latency_probe(&self) -> Option<LatencyProbe> {
+        let secs = self
+            .latency_probe_secs
+            .unwrap_or(latency::DEFAULT_INTERVAL_SECS);
+        if secs == 0 {
+            return None;
+        }
+        let stall_secs = self
+            .stall_secs
+            .filter(|s| *s > 0)
+            .unwrap_or(latency::DEFAULT_STALL_SECS);
+        let command = self.latency_probe_command.as_deref().map(command_bytes);
+        Some(LatencyProbe::new(
+            Duration::from_secs(secs),
+            Duration::from_secs(stall_secs),
+            command,
+        ))
+    }
+}
+
+/// Apply socket-level options from the settings to a freshly connected
+/// stream, returning what is in effect afterwards.
+fn apply_socket_options(stream: &TcpStream, settings: &ConnectionSettings) -> SocketOptionsPayload {
+    let socket = socket2::SockRef::from(stream);
+    if let Some(secs) = settings.tcp_keepalive_secs.filter(|s| *s > 0) {
+        let interval = Duration::from_secs(secs);
+        let keepalive = socket2::TcpKeepalive::new()
+            .with_time(interval)
+            .with_interval(interval);
+        if let Err(e) = socket.set_tcp_keepalive(&keepalive) {
+            warn!("Failed to enable TCP keepalive: {e}");
+        }
+    }
+    if let Err(e) = stream.set_nodelay(settings.tcp_nodelay.unwrap_or(true)) {
+        warn!("Failed to set TCP_NODELAY: {e}");
+    }
+    if let Some(size) = settings.recv_buffer_bytes {
+        if let Err(e) = socket.set_recv_buffer_size(size) {
+            warn!("Failed to set the receive buffer to {size} bytes: {e}");
+        }
+    }
+    if let Some(size) = settings.send_buffer_bytes {
+        if let Err(e) = socket.set_send_buffer_size(size) {
+            warn!("Failed to set the send buffer to {size} bytes: {e}");
+        }
+    }
+    SocketOptionsPayload {
+        nodelay: stream.nodelay().unwrap_or(false),
+        recv_buffer: socket.recv_buffer_size().ok(),
+        send_buffer: socket.send_buffer_size().ok(),
+        read_buffer: settings.read_buffer_size(),
+    }
+}
+
+/// Sleep until `deadline`, or forever if there is none.
+pub(crate) async fn sleep_until_opt(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Frame a user command for the wire: the command bytes followed by CRLF.
+fn command_bytes(cmd: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(cmd.len() + 2);
+    data.extend_from_slice(cmd.as_bytes());
+    data.extend_from_slice(b"\r\n");
+    data
+}
+
+/// Emits a session's connection status to the frontend and companion
+/// clients, and records it in `ConnectionShared::status` and the companion's
+/// last-status cache, from one place so they can't drift apart. The tray,
+/// window title and companion follow the default session only, using the
+/// recorded status.
+#[derive(Clone)]
+pub struct StatusEmitter {
     app: AppHandle,
-    mut cmd_rx: mpsc::Receiver<String>,
     broadcast_tx: tokio::sync::broadcast::Sender<CompanionMessage>,
     last_status: LastStatus,
+    shared: Arc<ConnectionShared>,
+    profile: Option<String>,
+    generation: u64,
+}
+
+impl StatusEmitter {
+    /// Statuses are tagged with the session's current generation.
+    pub fn new(
+        app: AppHandle,
+        broadcast_tx: tokio::sync::broadcast::Sender<CompanionMessage>,
+        last_status: LastStatus,
+        shared: Arc<ConnectionShared>,
+    ) -> Self {
+        let generation = shared.generation();
+        Self {
+            app,
+            broadcast_tx,
+            last_status,
+            shared,
+            profile: None,
+            generation,
+        }
+    }
+
+    /// The generation this emitter's statuses carry, captured when it was
+    /// made; the connection's other events carry it too.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Tag every status with the active profile's name.
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// A stale status (from a generation older than the last one recorded)
+    /// still reaches the frontend, tagged, but changes nothing else.
+    pub fn emit(&self, mut status: ConnectionStatusPayload) {
+        status.profile = self.profile.clone();
+        status.generation = self.generation;
+        status.session = self.shared.session.clone();
+        let current = self
+            .shared
+            .status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .update(&status);
+        if !current {
+            debug!(
+                "Stale status from connection generation {}: {}",
+                self.generation, status.message
+            );
+        } else if self.shared.is_default() {
+            let _ = self.broadcast_tx.send(CompanionMessage::ConnectionStatus {
+                connected: status.connected,
+                message: status.message.clone(),
+            });
+            set_status(&self.last_status, status.connected, &status.message);
+            crate::tray::status_changed(&self.app);
+            self.app
+                .state::<crate::window_title::TitleManager>()
+                .status_changed();
+        }
+        let _ = events::emit(&self.app, CONNECTION_STATUS_EVENT, status);
+    }
+}
+
+/// Run a connection until the server closes it, it fails, or `cancel` fires.
+///
+/// Cancellation is cooperative: queued commands are flushed, the configured
+/// quit command is sent, and the socket is shut down (FIN) before returning.
+/// The final "Disconnected" status is emitted exactly once on every exit path
+/// after the connection was attempted. A connection found dead after the
+/// machine slept, or stalled (see `STALE_READ_AFTER` and `reconnect_on_stall`),
+/// is disconnected and made again, logging in again, rather than ending the
+/// run.
+pub async fn connect(
+    app: AppHandle,
+    shared: Arc<ConnectionShared>,
+    status: StatusEmitter,
+    settings: ConnectionSettings,
+    login: Option<AutoLogin>,
+    cancel: CancellationToken,
 ) {
-    let addr = format!("{MUD_HOST}:{MUD_PORT}");
-    info!("Connecting to {addr}...");
+    let target = shared
+        .target
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    *shared.local_echo.lock().unwrap_or_else(|e| e.into_inner()) = settings.echo_marker();
+    // Outside the default session, logs without a profile go under the session id
+    let profile = shared
+        .active_profile
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|p| p.name.clone());
+    shared.session_log.set_profile(
+        profile
+            .clone()
+            .or_else(|| (!shared.is_default()).then(|| shared.session.clone())),
+    );
+    loop {
+        let link = tokio::select! {
+            link = establish(&status, &settings, &target) => link,
+            _ = cancel.cancelled() => {
+                info!("Connection attempt cancelled");
+                status.emit(ConnectionStatusPayload::new(StatusReason::UserDisconnect, "Disconnected"));
+                return;
+            }
+        };
+        let Some(link) = link else {
+            return;
+        };
+        let socket_options = link
+            .tcp()
+            .map(|stream| apply_socket_options(stream, &settings));
+        shared.scrollback.set_capacity(
+            settings
+                .scrollback_bytes
+                .unwrap_or(DEFAULT_SCROLLBACK_BYTES),
+        );
+        if !settings.keep_scrollback_on_reconnect {
+            shared.scrollback.clear();
+        }
+
+        // Only accept commands once connected; anything typed earlier was queued
+        // and is replayed in order now.
+        let (cmd_tx, cmd_rx) = mpsc::channel::<CommandRequest>(100);
+        shared.stats.start();
+        if let Some(socket_options) = socket_options {
+            shared.stats.set_socket_options(socket_options);
+        }
+        shared.session_tracker.start(profile.clone());
+        shared.anti_idle.configure(settings.anti_idle.clone());
+        shared.anti_idle.start();
+        shared.scripts.connected(&shared.session);
+        let flushed = shared.commands.attach(cmd_tx);
+        shared.timers.set_connected(true);
+        if flushed > 0 {
+            info!("Flushing {flushed} queued command(s)");
+            let _ = events::emit(
+                &app,
+                COMMAND_QUEUE_FLUSHED_EVENT,
+                CommandQueueFlushedPayload { count: flushed },
+            );
+        }
+
+        let (reason, detail, unsent) = run_session(
+            &app,
+            link,
+            cmd_rx,
+            &shared,
+            &settings,
+            login.clone(),
+            &cancel,
+            status.generation(),
+        )
+        .await;
+        shared.session_log.flush();
+        shared.commands.detach();
+        shared.timers.set_connected(false);
+        shared.server_echo.store(false, Ordering::Relaxed);
+        if !unsent.is_empty() {
+            let total = unsent.len();
+            let requeued = shared.commands.requeue(unsent);
+            if requeued < total {
+                warn!(
+                    "Connection lost with {total} unsent command(s); queue full, dropped {}",
+                    total - requeued
+                );
+            } else {
+                info!("Re-queued {requeued} unsent command(s) for the next connection");
+            }
+        }
+        shared.session_tracker.finish(&app, &shared, reason);
+        shared.anti_idle.stop();
+        shared.stats.stop();
+        let _ = events::emit(&app, STATS_EVENT, shared.stats.snapshot());
+
+        let mut disconnected = ConnectionStatusPayload::new(reason, "Disconnected");
+        if let Some(detail) = detail {
+            disconnected = disconnected.with_detail(detail);
+        }
+        status.emit(disconnected);
+        if !matches!(reason, StatusReason::Stalled | StatusReason::ResumedStale) {
+            break;
+        }
+        info!("Reconnecting after the connection went stale");
+    }
+}
+
+/// Classify a failed connect attempt for the status reason.
+fn connect_failure_reason(e: &std::io::Error) -> StatusReason {
+    match e.kind() {
+        std::io::ErrorKind::TimedOut => StatusReason::Timeout,
+        _ => StatusReason::ConnectRefused,
+    }
+}
+
+/// An established connection: a telnet stream to the MUD (direct or through
+/// the outbound proxy), or a WebSocket to the web proxy, which has connected
+/// to the MUD for us.
+enum Link {
+    Tcp(TcpStream),
+    WebSocket(Box<ProxySocket>),
+}
+
+impl Link {
+    fn tcp(&self) -> Option<&TcpStream> {
+        match self {
+            Link::Tcp(stream) => Some(stream),
+            Link::WebSocket(socket) => socket.tcp(),
+        }
+    }
+
+    fn into_split(self) -> (LinkReader, LinkWriter) {
+        match self {
+            Link::Tcp(stream) => {
+                let (reader, writer) = stream.into_split();
+                (LinkReader::Tcp(reader), LinkWriter::Tcp(writer))
+            }
+            Link::WebSocket(socket) => {
+                let (reader, writer) = socket.into_split();
+                (LinkReader::WebSocket(reader), LinkWriter::WebSocket(writer))
+            }
+        }
+    }
+}
+
+/// What a read from the link produced.
+enum Received {
+    /// This many bytes from the MUD in the read buffer; 0 when it closed.
+    Bytes(usize),
+    /// Output (or a pong, as `timing_mark`) from the WebSocket proxy, whose
+    /// telnet it already handled.
+    Processed(ansi::ProcessedOutput),
+    /// The WebSocket proxy's keepalive, to be answered.
+    Ping,
+    /// The WebSocket proxy lost the MUD, or closed.
+    Ended(StatusReason, String),
+}
+
+enum LinkReader {
+    Tcp(OwnedReadHalf),
+    WebSocket(ProxyReader),
+}
+
+impl LinkReader {
+    /// Cancel-safe, so it can sit in a `select!`.
+    async fn read(&mut self, buf: &mut [u8]) -> Result<Received, String> {
+        let reader = match self {
+            LinkReader::Tcp(reader) => {
+                return reader
+                    .read(buf)
+                    .await
+                    .map(Received::Bytes)
+                    .map_err(|e| e.to_string())
+            }
+            LinkReader::WebSocket(reader) => reader,
+        };
+        loop {
+            match reader.recv().await {
+                Ok(ServerMessage::Output { data, ga }) => {
+                    return Ok(Received::Processed(ansi::ProcessedOutput {
+                        display: data,
+                        ga,
+                        ..Default::default()
+                    }))
+                }
+                Ok(ServerMessage::Pong) => {
+                    return Ok(Received::Processed(ansi::ProcessedOutput {
+                        timing_mark: true,
+                        ..Default::default()
+                    }))
+                }
+                Ok(ServerMessage::Ping) => return Ok(Received::Ping),
+                Ok(ServerMessage::Status(status))
+                    if !status.connected && status.reason != "connecting" =>
+                {
+                    info!("WebSocket proxy lost the MUD: {}", status.message);
+                    return Ok(Received::Ended(status.status_reason(), status.hop_detail()));
+                }
+                Ok(ServerMessage::Error { code, detail }) => {
+                    warn!("WebSocket proxy refused a command ({code}): {detail}")
+                }
+                Ok(_) => {}
+                Err(e @ WsError::Closed(_)) => {
+                    info!("WebSocket proxy closed the connection");
+                    return Ok(Received::Ended(
+                        StatusReason::ServerClosed,
+                        format!("WebSocket proxy: {e}"),
+                    ));
+                }
+                Err(e) => return Err(format!("WebSocket proxy: {e}")),
+            }
+        }
+    }
+}
+
+/// How an outgoing item goes on the wire.
+#[derive(Clone, Copy)]
+enum Frame {
+    /// A command line, ending in CRLF.
+    Line,
+    /// Telnet bytes, only meaningful on a telnet stream.
+    Raw,
+    Ping,
+    Pong,
+}
+
+enum LinkWriter {
+    Tcp(OwnedWriteHalf),
+    WebSocket(ProxyWriter),
+}
+
+impl LinkWriter {
+    /// Write `data`, returning false if it has no meaning on this link and
+    /// was skipped.
+    async fn write(&mut self, data: &[u8], frame: Frame) -> Result<bool, String> {
+        let writer = match self {
+            LinkWriter::Tcp(writer) => {
+                return writer
+                    .write_all(data)
+                    .await
+                    .map(|()| true)
+                    .map_err(|e| e.to_string())
+            }
+            LinkWriter::WebSocket(writer) => writer,
+        };
+        let line = String::from_utf8_lossy(data);
+        let msg = match frame {
+            Frame::Line => ClientMessage::Command {
+                data: line.strip_suffix("\r\n").unwrap_or(&line),
+            },
+            Frame::Ping => ClientMessage::Ping,
+            Frame::Pong => ClientMessage::Pong,
+            // The proxy answers the MUD's negotiation itself
+            Frame::Raw => return Ok(false),
+        };
+        writer
+            .send(&msg)
+            .await
+            .map(|()| true)
+            .map_err(|e| format!("WebSocket proxy: {e}"))
+    }
+
+    async fn shutdown(&mut self) {
+        match self {
+            LinkWriter::Tcp(writer) => {
+                let _ = writer.shutdown().await;
+            }
+            LinkWriter::WebSocket(writer) => writer.shutdown().await,
+        }
+    }
+}
 
-    let msg = format!("Connecting to {addr}...");
-    let _ = app.emit(CONNECTION_STATUS_EVENT, ConnectionStatusPayload { connected: false, message: msg.clone() });
-    let _ = broadcast_tx.send(CompanionMessage::ConnectionStatus { connected: false, message: msg.clone() });
-    set_status(&last_status, false, &msg);
+/// Open a connection to the MUD, retrying on failure. Emits the
+/// "Connecting"/"Connected"/failure statuses along the way.
+async fn establish(
+    status: &StatusEmitter,
+    settings: &ConnectionSettings,
+    target: &ConnectionTarget,
+) -> Option<Link> {
+    match (&settings.transport, &settings.proxy) {
+        (Transport::WebSocketProxy { url, token }, _) => {
+            establish_via_websocket(status, url, token.as_deref(), target).await
+        }
+        (Transport::Tcp, Some(proxy)) => establish_via_proxy(status, proxy, target)
+            .await
+            .map(Link::Tcp),
+        (Transport::Tcp, None) => establish_direct(status, target).await.map(Link::Tcp),
+    }
+}
 
-    // Resolve DNS on a blocking thread to get the actual IP address
-    let resolved = tokio::task::spawn_blocking(move || {
-        format!("{MUD_HOST}:{MUD_PORT}").to_socket_addrs()
-    }).await;
+/// Open a WebSocket to the web proxy, retrying on failure, and have it
+/// connect to the MUD. The proxy retries its own connection, and reports each
+/// attempt, so a MUD it can't reach isn't retried here. Failures say which hop
+/// failed: the WebSocket to the proxy, or the proxy's connection to the MUD.
+async fn establish_via_websocket(
+    status: &StatusEmitter,
+    url: &str,
+    token: Option<&str>,
+    target: &ConnectionTarget,
+) -> Option<Link> {
+    let addr = target.label();
+    let via = format!("WebSocket proxy {}", ws_proxy::label(url));
+    info!("Connecting to {addr} via {via}...");
+    status.emit(ConnectionStatusPayload::new(
+        StatusReason::Connecting,
+        format!("Connecting to {addr} via {via}..."),
+    ));
 
-    let addrs: Vec<_> = match resolved {
-        Ok(Ok(iter)) => iter.collect(),
+    let mut socket = None;
+    let mut last_failure = (
+        StatusReason::ConnectRefused,
+        String::from("WebSocket proxy unreachable"),
+        String::new(),
+    );
+    for attempt in 1..=MAX_RETRIES {
+        match timeout(CONNECT_TIMEOUT, ProxySocket::open(url, token)).await {
+            Ok(Ok(s)) => {
+                socket = Some(s);
+                break;
+            }
+            Ok(Err(e)) if e.http_status().is_some() => {
+                // Refused by the proxy (origin, rate limit, ...), not unreachable
+                error!("WebSocket proxy refused the connection: {e}");
+                status.emit(
+                    ConnectionStatusPayload::new(
+                        StatusReason::ProxyError,
+                        "WebSocket proxy refused connection",
+                    )
+                    .with_detail(format!("WebSocket proxy: {e}"))
+                    .with_attempt(attempt),
+                );
+                return None;
+            }
+            Ok(Err(e)) => {
+                warn!("WebSocket proxy connection failed: {e}");
+                let reason = e
+                    .io()
+                    .map_or(StatusReason::ProxyError, connect_failure_reason);
+                last_failure = (
+                    reason,
+                    String::from("WebSocket proxy unreachable"),
+                    format!("WebSocket proxy: {e}"),
+                );
+            }
+            Err(_) => {
+                warn!(
+                    "Connection to {via} timed out after {}s",
+                    CONNECT_TIMEOUT.as_secs()
+                );
+                last_failure = (
+                    StatusReason::Timeout,
+                    String::from("WebSocket proxy connection timed out"),
+                    format!(
+                        "WebSocket proxy: timed out after {}s",
+                        CONNECT_TIMEOUT.as_secs()
+                    ),
+                );
+            }
+        }
+        if attempt < MAX_RETRIES {
+            status.emit(
+                ConnectionStatusPayload::new(
+                    last_failure.0,
+                    format!("{}, retrying ({attempt}/{MAX_RETRIES})...", last_failure.1),
+                )
+                .with_detail(last_failure.2.clone())
+                .with_attempt(attempt),
+            );
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+    let Some(mut socket) = socket else {
+        error!("Failed to connect to {via} after {MAX_RETRIES} attempts");
+        let (reason, message, detail) = last_failure;
+        status.emit(
+            ConnectionStatusPayload::new(
+                reason,
+                format!("{message} (after {MAX_RETRIES} attempts)"),
+            )
+            .with_detail(detail)
+            .with_attempt(MAX_RETRIES),
+        );
+        return None;
+    };
+
+    // The proxy resolves the host, against its own allowlist. Ends with the
+    // status it failed with, or `None` once it's connected.
+    let dial = async {
+        socket
+            .send(&ClientMessage::Reconnect {
+                host: &target.host,
+                port: target.port,
+            })
+            .await?;
+        loop {
+            match socket.recv().await? {
+                ServerMessage::Status(s) if s.connected => return Ok::<_, WsError>(None),
+                // Our own status already said we're connecting; "ready" greets every socket
+                ServerMessage::Status(s)
+                    if matches!(s.reason.as_str(), "connecting" | "user_disconnect") => {}
+                // The proxy retries as many times as we would
+                ServerMessage::Status(s)
+                    if s.attempt.is_some_and(|a| a < MAX_RETRIES) && !s.is_refusal() =>
+                {
+                    let mut retry =
+                        ConnectionStatusPayload::new(s.status_reason(), s.message.clone())
+                            .with_detail(s.hop_detail());
+                    retry.attempt = s.attempt;
+                    status.emit(retry);
+                }
+                ServerMessage::Status(s) => return Ok(Some(s)),
+                ServerMessage::Ping => socket.send(&ClientMessage::Pong).await?,
+                _ => {}
+            }
+        }
+    };
+    match timeout(PROXY_DIAL_TIMEOUT, dial).await {
+        Ok(Ok(None)) => {
+            info!("Connected to {addr} via {via}");
+            let peer = socket.tcp().and_then(|s| s.peer_addr().ok());
+            status.emit(
+                ConnectionStatusPayload::new(
+                    StatusReason::Connected,
+                    format!("Connected to {addr} via {via}"),
+                )
+                .with_peer(peer),
+            );
+            Some(Link::WebSocket(Box::new(socket)))
+        }
+        Ok(Ok(Some(failed))) => {
+            error!("{via} couldn't connect to {addr}: {}", failed.message);
+            let mut payload =
+                ConnectionStatusPayload::new(failed.status_reason(), failed.message.clone())
+                    .with_detail(failed.hop_detail());
+            payload.attempt = failed.attempt;
+            status.emit(payload);
+            None
+        }
         Ok(Err(e)) => {
-            error!("DNS resolution failed for {addr}: {e}");
-            let msg = format!("DNS resolution failed: {e}");
-            let _ = app.emit(CONNECTION_STATUS_EVENT, ConnectionStatusPayload { connected: false, message: msg.clone() });
-            let _ = broadcast_tx.send(CompanionMessage::ConnectionStatus { connected: false, message: msg.clone() });
-            set_status(&last_status, false, &msg);
-            return;
+            error!("{via} failed while connecting to {addr}: {e}");
+            status.emit(
+                ConnectionStatusPayload::new(
+                    StatusReason::ProxyError,
+                    "WebSocket proxy connection lost",
+                )
+                .with_detail(format!("WebSocket proxy: {e}")),
+            );
+            None
         }
-        Err(e) => {
-            error!("DNS resolution task failed: {e}");
-            let msg = format!("DNS resolution failed: {e}");
-            let _ = app.emit(CONNECTION_STATUS_EVENT, ConnectionStatusPayload { connected: false, message: msg.clone() });
-            let _ = broadcast_tx.send(CompanionMessage::ConnectionStatus { connected: false, message: msg.clone() });
-            set_status(&last_status, false, &msg);
-            return;
+        Err(_) => {
+            error!(
+                "{via} didn't connect to {addr} within {}s",
+                PROXY_DIAL_TIMEOUT.as_secs()
+            );
+            status.emit(
+                ConnectionStatusPayload::new(
+                    StatusReason::Timeout,
+                    format!("Proxy couldn't reach {addr}"),
+                )
+                .with_detail(format!(
+                    "MUD connection: no answer within {}s",
+                    PROXY_DIAL_TIMEOUT.as_secs()
+                )),
+            );
+            None
+        }
+    }
+}
+
+/// Connect through the configured outbound proxy. The proxy resolves the MUD
+/// host, so local DNS is skipped entirely.
+async fn establish_via_proxy(
+    status: &StatusEmitter,
+    proxy: &OutboundProxy,
+    target: &ConnectionTarget,
+) -> Option<TcpStream> {
+    let addr = target.label();
+    // A pinned address is handed to the proxy as-is instead of the hostname
+    let target_host = target
+        .pinned_ip()
+        .map_or_else(|| target.host.clone(), |ip| ip.to_string());
+    let via = format!("{} proxy {}:{}", proxy.kind.label(), proxy.host, proxy.port);
+    info!("Connecting to {addr} via {via}...");
+    status.emit(ConnectionStatusPayload::new(
+        StatusReason::Connecting,
+        format!("Connecting to {addr} via {via}..."),
+    ));
+
+    let mut last_failure = (
+        StatusReason::ConnectRefused,
+        String::from("Proxy unreachable"),
+        String::new(),
+    );
+    for attempt in 1..=MAX_RETRIES {
+        match timeout(
+            CONNECT_TIMEOUT,
+            outbound::connect_via(proxy, &target_host, target.port),
+        )
+        .await
+        {
+            Ok(Ok(s)) => {
+                info!("Connected to {addr} via {via}");
+                status.emit(
+                    ConnectionStatusPayload::new(
+                        StatusReason::Connected,
+                        format!("Connected to {addr} via {via}"),
+                    )
+                    .with_peer(s.peer_addr().ok()),
+                );
+                return Some(s);
+            }
+            Ok(Err(ProxyError::AuthFailed(detail))) => {
+                // Retrying with the same credentials can't succeed
+                error!("Proxy authentication failed: {detail}");
+                status.emit(
+                    ConnectionStatusPayload::new(
+                        StatusReason::ProxyError,
+                        "Proxy authentication failed",
+                    )
+                    .with_detail(detail)
+                    .with_attempt(attempt),
+                );
+                return None;
+            }
+            Ok(Err(e)) => {
+                warn!("Proxy connection failed: {e}");
+                last_failure = match &e {
+                    ProxyError::Io(io) => (
+                        connect_failure_reason(io),
+                        String::from("Proxy unreachable"),
+                        e.to_string(),
+                    ),
+                    ProxyError::Refused(_) => (
+                        StatusReason::ProxyError,
+                        String::from("Proxy refused connection"),
+                        e.to_string(),
+                    ),
+                    _ => (
+                        StatusReason::ProxyError,
+                        String::from("Proxy error"),
+                        e.to_string(),
+                    ),
+                };
+            }
+            Err(_) => {
+                warn!(
+                    "Connection via {via} timed out after {}s",
+                    CONNECT_TIMEOUT.as_secs()
+                );
+                last_failure = (
+                    StatusReason::Timeout,
+                    String::from("Proxy connection timed out"),
+                    format!("timed out after {}s", CONNECT_TIMEOUT.as_secs()),
+                );
+            }
+        }
+        if attempt < MAX_RETRIES {
+            status.emit(
+                ConnectionStatusPayload::new(
+                    last_failure.0,
+                    format!("{}, retrying ({attempt}/{MAX_RETRIES})...", last_failure.1),
+                )
+                .with_detail(last_failure.2.clone())
+                .with_attempt(attempt),
+            );
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+    }
+
+    error!("Failed to connect to {addr} via {via} after {MAX_RETRIES} attempts");
+    let (reason, message, detail) = last_failure;
+    status.emit(
+        ConnectionStatusPayload::new(reason, format!("{message} (after {MAX_RETRIES} attempts)"))
+            .with_detail(detail)
+            .with_attempt(MAX_RETRIES),
+    );
+    None
+}
+
+/// Resolve the MUD host and connect to it directly. DNS is skipped when the
+/// target is a literal IP or has a resolve override.
+async fn establish_direct(status: &StatusEmitter, target: &ConnectionTarget) -> Option<TcpStream> {
+    let addr = target.label();
+    let connecting = match target.resolve_override {
+        Some(ip) => format!("Connecting to {addr} ({ip})..."),
+        None => format!("Connecting to {addr}..."),
+    };
+    info!("{connecting}");
+
+    status.emit(ConnectionStatusPayload::new(
+        StatusReason::Connecting,
+        connecting,
+    ));
+
+    let addrs: Vec<SocketAddr> = if let Some(ip) = target.pinned_ip() {
+        vec![SocketAddr::new(ip, target.port)]
+    } else {
+        // Resolve DNS on a blocking thread to get the actual IP address
+        let (host, port) = (target.host.clone(), target.port);
+        let resolved =
+            tokio::task::spawn_blocking(move || (host.as_str(), port).to_socket_addrs()).await;
+
+        match resolved {
+            Ok(Ok(iter)) => iter.collect(),
+            Ok(Err(e)) => {
+                error!("DNS resolution failed for {addr}: {e}");
+                status.emit(
+                    ConnectionStatusPayload::new(
+                        StatusReason::DnsFailure,
+                        format!("DNS resolution failed: {e}"),
+                    )
+                    .with_detail(e.to_string()),
+                );
+                return None;
+            }
+            Err(e) => {
+                error!("DNS resolution task failed: {e}");
+                status.emit(
+                    ConnectionStatusPayload::new(
+                        StatusReason::DnsFailure,
+                        format!("DNS resolution failed: {e}"),
+                    )
+                    .with_detail(e.to_string()),
+                );
+                return None;
+            }
         }
     };
 
     info!("Resolved {addr} to {addrs:?}");
 
-    let mut stream: Option<TcpStream> = None;
+    let mut stream: Option<(TcpStream, SocketAddr)> = None;
+    let mut last_failure = (
+        StatusReason::ConnectRefused,
+        String::from("No addresses resolved"),
+    );
     for attempt in 1..=MAX_RETRIES {
-        for resolved_addr in &addrs {
-            info!("Connection attempt {attempt}/{MAX_RETRIES} to {resolved_addr}");
-            match timeout(CONNECT_TIMEOUT, TcpStream::connect(resolved_addr)).await {
-                Ok(Ok(s)) => {
-                    info!("Connected to {addr} ({resolved_addr})");
-                    stream = Some(s);
-                    break;
-                }
-                Ok(Err(e)) => {
-                    warn!("Failed to connect to {resolved_addr}: {e}");
-                }
-                Err(_) => {
-                    warn!("Connection to {resolved_addr} timed out after {}s", CONNECT_TIMEOUT.as_secs());
+        info!("Connection attempt {attempt}/{MAX_RETRIES} to {addrs:?}");
+        match happy_eyeballs::race(&addrs, CONNECT_TIMEOUT).await {
+            Ok(connected) => {
+                stream = Some(connected);
+            }
+            Err(failures) => {
+                if let Some((failed_addr, e)) = failures.last() {
+                    last_failure = match e {
+                        AttemptError::Io(e) => {
+                            (connect_failure_reason(e), format!("{failed_addr}: {e}"))
+                        }
+                        AttemptError::TimedOut => (
+                            StatusReason::Timeout,
+                            format!(
+                                "{failed_addr}: timed out after {}s",
+                                CONNECT_TIMEOUT.as_secs()
+                            ),
+                        ),
+                    };
                 }
             }
         }
@@ -99,96 +1360,502 @@ pub async fn connect(
         }
         if attempt < MAX_RETRIES {
             info!("Retrying in {}s...", RETRY_DELAY.as_secs());
-            let msg = format!("Connection failed, retrying ({attempt}/{MAX_RETRIES})...");
-            let _ = app.emit(CONNECTION_STATUS_EVENT, ConnectionStatusPayload { connected: false, message: msg.clone() });
-            let _ = broadcast_tx.send(CompanionMessage::ConnectionStatus { connected: false, message: msg.clone() });
-            set_status(&last_status, false, &msg);
+            status.emit(
+                ConnectionStatusPayload::new(
+                    last_failure.0,
+                    format!("Connection failed, retrying ({attempt}/{MAX_RETRIES})..."),
+                )
+                .with_detail(last_failure.1.clone())
+                .with_attempt(attempt),
+            );
             tokio::time::sleep(RETRY_DELAY).await;
         }
     }
 
-    let stream = match stream {
-        Some(s) => {
-            let msg = format!("Connected to {addr}");
-            let _ = app.emit(CONNECTION_STATUS_EVENT, ConnectionStatusPayload { connected: true, message: msg.clone() });
-            let _ = broadcast_tx.send(CompanionMessage::ConnectionStatus { connected: true, message: msg.clone() });
-            set_status(&last_status, true, &msg);
-            s
+    match stream {
+        Some((s, peer)) => {
+            info!("Connected to {addr} ({peer})");
+            let message = if target.label() == peer.to_string() {
+                format!("Connected to {addr}")
+            } else {
+                format!("Connected to {addr} ({})", peer.ip())
+            };
+            status.emit(
+                ConnectionStatusPayload::new(StatusReason::Connected, message)
+                    .with_peer(Some(peer)),
+            );
+            Some(s)
         }
         None => {
             error!("Failed to connect to {addr} after {MAX_RETRIES} attempts");
-            let msg = format!("Failed to connect after {MAX_RETRIES} attempts");
-            let _ = app.emit(CONNECTION_STATUS_EVENT, ConnectionStatusPayload { connected: false, message: msg.clone() });
-            let _ = broadcast_tx.send(CompanionMessage::ConnectionStatus { connected: false, message: msg.clone() });
-            set_status(&last_status, false, &msg);
+            let (reason, detail) = last_failure;
+            status.emit(
+                ConnectionStatusPayload::new(
+                    reason,
+                    format!("Failed to connect after {MAX_RETRIES} attempts"),
+                )
+                .with_detail(detail)
+                .with_attempt(MAX_RETRIES),
+            );
+            None
+        }
+    }
+}
+
+/// Buffers display text across reads so a burst of spam becomes one
+/// `mud:output` emit instead of one per 4KB read. Flushed on GA (the prompt is
+/// ready), when the buffer is full, or `max_delay` after its first byte.
+struct OutputCoalescer {
+    pending: String,
+    /// Lines gagged by backend rules since the last flush, for the session log.
+    gagged: Vec<String>,
+    highlighter: Highlighter,
+    deadline: Option<Instant>,
+    max_delay: Duration,
+    max_bytes: usize,
+    generation: u64,
+}
+
+impl OutputCoalescer {
+    fn new(settings: &ConnectionSettings, generation: u64) -> Self {
+        Self {
+            pending: String::new(),
+            gagged: Vec::new(),
+            highlighter: Highlighter::default(),
+            deadline: None,
+            max_delay: settings
+                .output_max_delay_ms
+                .map_or(OUTPUT_MAX_DELAY, Duration::from_millis),
+            max_bytes: settings.output_max_bytes.unwrap_or(OUTPUT_MAX_BYTES),
+            generation,
+        }
+    }
+
+    /// Buffer `display`. Returns true if the buffer should be flushed now.
+    fn push(&mut self, display: &str, ga: bool) -> bool {
+        if self.deadline.is_none() {
+            self.deadline = Some(Instant::now() + self.max_delay);
+        }
+        self.pending.push_str(display);
+        ga || self.max_delay.is_zero() || self.pending.len() >= self.max_bytes
+    }
+
+    /// Emit everything buffered as a single output event, preceded by the
+    /// lines gagged meanwhile.
+    fn flush<R: Runtime>(&mut self, app: &AppHandle<R>, shared: &ConnectionShared, ga: bool) {
+        self.deadline = None;
+        if !self.gagged.is_empty() {
+            let lines = std::mem::take(&mut self.gagged);
+            shared.session_log.log_gagged(&lines);
+            let _ = events::emit(
+                app,
+                GAGGED_LINES_EVENT,
+                GaggedLinesPayload {
+                    lines,
+                    generation: self.generation,
+                },
+            );
+        }
+        if self.pending.is_empty() {
             return;
         }
+        let data = std::mem::take(&mut self.pending);
+        shared.session_log.log_output(&data);
+        let data = shared.highlights.apply(&mut self.highlighter, &data);
+        shared.scrollback.append(&data);
+        let payload = MudOutputPayload {
+            data,
+            ga,
+            session: shared.session.clone(),
+            buffered: false,
+            generation: self.generation,
+        };
+        shared.output_pause.deliver(app, payload);
+        app.state::<crate::window_title::TitleManager>().output();
+    }
+}
+
+/// Route one line to comms and the session counters, and run backend gag
+/// rules and triggers on it, collecting it into `gagged` if any of them
+/// suppresses it. Gagged lines skip triggers, and prompts are never gagged.
+/// Returns whether the line is gagged.
+fn filter_line<R: Runtime>(
+    app: &AppHandle<R>,
+    shared: &ConnectionShared,
+    comms: &mut CommsRouter,
+    gagged: &mut Vec<String>,
+    line: &str,
+    prompt: bool,
+) -> bool {
+    if prompt {
+        comms.finish(app, shared);
+        run_triggers(app, shared, line);
+        return false;
+    }
+    shared
+        .session_tracker
+        .on_line(&shared.session_counters, line);
+    shared.scripts.line(&shared.session, line);
+    let gag = comms.on_line(app, shared, line) | shared.filters.gag(line)
+        || run_triggers(app, shared, line);
+    if gag {
+        gagged.push(line.trim_end_matches(['\r', '\n']).to_string());
+    }
+    gag
+}
+
+/// Send a command from a backend trigger or timer to the live connection,
+/// through alias expansion and the rate limiter like typed ones. Returns
+/// false if it couldn't all be sent (not connected, or the channel is full).
+pub(crate) fn send_backend_command(shared: &ConnectionShared, command: &str, source: &str) -> bool {
+    send_expanded(shared, shared.aliases.expand(command), command, source)
+}
+
+/// Queue the commands an alias expansion of `command` produced.
+fn send_expanded(
+    shared: &ConnectionShared,
+    expanded: Result<Vec<String>, String>,
+    command: &str,
+    source: &str,
+) -> bool {
+    let Some(tx) = shared.commands.live() else {
+        return false;
     };
+    let expanded = expanded.unwrap_or_else(|e| {
+        warn!("{source} command {command:?}: {e}");
+        Vec::new()
+    });
+    for command in expanded {
+        if tx.try_send(CommandRequest::Send(command)).is_err() {
+            warn!("Command channel full; dropping {source} command");
+            return false;
+        }
+    }
+    true
+}
+
+/// Evaluate backend triggers for one line: queue their commands and report
+/// each firing. Returns whether a trigger gags the line.
+fn run_triggers<R: Runtime>(app: &AppHandle<R>, shared: &ConnectionShared, line: &str) -> bool {
+    let outcome = shared.triggers.evaluate(line, &shared.aliases.separator());
+    for command in &outcome.commands {
+        // Already split, so text the MUD sent can't turn into more commands
+        if !send_expanded(
+            shared,
+            shared.aliases.expand_command(command),
+            command,
+            "Trigger",
+        ) {
+            break;
+        }
+    }
+    for mut fired in outcome.fired {
+        if let Some(sound) = fired.sound.as_deref().filter(|_| fired.played_natively) {
+            // If it can't be played here, leave it to the webview
+            fired.played_natively = crate::native_audio::play(app, sound, 1.0).is_ok();
+        }
+        let _ = events::emit(app, TRIGGER_FIRED_EVENT, fired);
+    }
+    for notification in outcome.notifications {
+        let app = app.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            crate::notifications::send(&app, notification)
+        });
+    }
+    outcome.gag
+}
+
+/// Flag the session stalled, or clear the flag, in its status and stats.
+fn set_stalled<R: Runtime>(app: &AppHandle<R>, shared: &ConnectionShared, stalled: bool) {
+    shared
+        .status
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .stalled = stalled;
+    shared.stats.set_stalled(stalled);
+    let _ = events::emit(app, STATS_EVENT, shared.stats.snapshot());
+}
 
-    let (mut reader, mut writer) = stream.into_split();
+/// Something queued for the socket writer.
+pub(crate) enum Outgoing {
+    /// A command; CRLF is appended on write, and it is echoed afterwards.
+    Command(String),
+    /// Protocol bytes (telnet responses, keepalives), written verbatim.
+    Raw(Vec<u8>),
+    /// A password: written like a command but never echoed, and redacted in
+    /// raw captures.
+    Secret(String),
+    /// A command the client sent on its own (anti-idle); echoed and logged
+    /// marked as such.
+    Synthetic(String),
+    /// A probe the other end must answer: `IAC DO TIMING-MARK` to the MUD, or
+    /// a `ping` through the WebSocket proxy, whose `pong` reads as the reply.
+    Ping,
+    /// Answers the WebSocket proxy's keepalive; nothing on a telnet stream.
+    Pong,
+}
+
+/// Drive the read/write loops of an established connection until it ends.
+/// Returns why it ended, with optional detail, plus any commands that were
+/// still waiting to be written when the connection was lost. Every event it
+/// emits carries `generation`, its connection task's.
+#[allow(clippy::too_many_arguments)]
+async fn run_session<R: Runtime>(
+    app: &AppHandle<R>,
+    link: Link,
+    cmd_rx: mpsc::Receiver<CommandRequest>,
+    shared: &Arc<ConnectionShared>,
+    settings: &ConnectionSettings,
+    login: Option<AutoLogin>,
+    cancel: &CancellationToken,
+    generation: u64,
+) -> (StatusReason, Option<String>, Vec<String>) {
+    let (mut reader, mut writer) = link.into_split();
 
     // Channel for sending data to the writer (both user commands and telnet responses)
-    let (write_tx, mut write_rx) = mpsc::channel::<Vec<u8>>(100);
+    let (write_tx, mut write_rx) = mpsc::channel::<Outgoing>(100);
     let write_tx_for_cmds = write_tx.clone();
 
     // Spawn write loop — handles both user commands and telnet responses.
     // Each write is bounded by WRITE_TIMEOUT so a stalled/half-open socket
     // tears the task down instead of blocking forever (which would leave the
-    // connection looking healthy while silently dropping commands).
+    // connection looking healthy while silently dropping commands). Once every
+    // sender is dropped the queue has drained and the socket is shut down
+    // cleanly so the server sees a FIN rather than a reset. With a keepalive
+    // configured, the keepalive payload is written whenever nothing else has
+    // been written for its interval; any real write resets the idle timer.
+    // Commands are echoed only once written, and not while the server has
+    // taken over echoing (password entry).
+    // Returns the failure reason if the writer died rather than drained.
+    let keepalive = settings.keepalive();
+    let writer_shared = shared.clone();
+    let writer_app = app.clone();
+    shared.server_echo.store(false, Ordering::Relaxed);
     let mut write_handle = tokio::spawn(async move {
-        while let Some(data) = write_rx.recv().await {
-            match timeout(WRITE_TIMEOUT, writer.write_all(&data)).await {
-                Ok(Ok(())) => {}
+        let mut idle_deadline = keepalive.as_ref().map(|(every, _)| Instant::now() + *every);
+        loop {
+            let outgoing = tokio::select! {
+                outgoing = write_rx.recv() => match outgoing {
+                    Some(outgoing) => outgoing,
+                    None => break,
+                },
+                _ = sleep_until_opt(idle_deadline) => match &keepalive {
+                    Some((_, payload)) => Outgoing::Raw(payload.clone()),
+                    None => continue,
+                },
+            };
+            let synthetic = matches!(outgoing, Outgoing::Synthetic(_));
+            let (data, echo, secret, frame) = match outgoing {
+                Outgoing::Command(cmd) | Outgoing::Synthetic(cmd) => {
+                    (command_bytes(&cmd), Some(cmd), false, Frame::Line)
+                }
+                Outgoing::Raw(data) => (data, None, false, Frame::Raw),
+                Outgoing::Secret(secret) => (command_bytes(&secret), None, true, Frame::Line),
+                Outgoing::Ping => (ansi::IAC_DO_TIMING_MARK.to_vec(), None, false, Frame::Ping),
+                Outgoing::Pong => (Vec::new(), None, false, Frame::Pong),
+            };
+            if let Some((every, _)) = &keepalive {
+                idle_deadline = Some(Instant::now() + *every);
+            }
+            match timeout(WRITE_TIMEOUT, writer.write(&data, frame)).await {
+                Ok(Ok(false)) => {}
+                Ok(Ok(true)) => {
+                    writer_shared.stats.record_write(data.len());
+                    if echo.is_none() && !secret && writer_shared.telnet_debug.is_enabled() {
+                        writer_shared.telnet_debug.record_write(&writer_app, &data);
+                    }
+                    if secret {
+                        writer_shared.capture.record_write(b"<redacted>\r\n");
+                    } else {
+                        writer_shared.capture.record_write(&data);
+                    }
+                    if let Some(command) = echo.as_ref().filter(|_| !synthetic) {
+                        rooms::command_sent(&writer_shared, command);
+                        writer_shared.session_tracker.command_sent();
+                    }
+                    if let Some(command) =
+                        echo.filter(|_| !writer_shared.server_echo.load(Ordering::Relaxed))
+                    {
+                        let marker = writer_shared
+                            .local_echo
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .clone();
+                        writer_shared.session_log.log_input(
+                            &command,
+                            marker.as_deref().unwrap_or(DEFAULT_ECHO_MARKER),
+                            synthetic,
+                        );
+                        if let Some(marker) = marker {
+                            let _ = events::emit(
+                                &writer_app,
+                                INPUT_ECHO_EVENT,
+                                InputEchoPayload {
+                                    command,
+                                    marker,
+                                    session: writer_shared.session.clone(),
+                                    synthetic,
+                                    generation,
+                                },
+                            );
+                        }
+                    }
+                }
                 Ok(Err(e)) => {
                     error!("Write error: {e}");
-                    break;
+                    return Some((StatusReason::WriteError, e.to_string()));
                 }
                 Err(_) => {
                     error!(
                         "Write stalled (>{}s); treating connection as dead",
                         WRITE_TIMEOUT.as_secs()
                     );
-                    break;
+                    return Some((
+                        StatusReason::Timeout,
+                        format!("Write stalled for more than {}s", WRITE_TIMEOUT.as_secs()),
+                    ));
                 }
             }
         }
+        let _ = timeout(WRITE_TIMEOUT, writer.shutdown()).await;
+        None
     });
 
-    // Forward user commands to the write channel
-    let cmd_handle = tokio::spawn(async move {
-        while let Some(cmd) = cmd_rx.recv().await {
-            let mut data = Vec::with_capacity(cmd.len() + 2);
-            data.extend_from_slice(cmd.as_bytes());
-            data.extend_from_slice(b"\r\n");
-            if write_tx_for_cmds.send(data).await.is_err() {
-                break;
-            }
-        }
-    });
+    // Forward user commands (and paced batches) to the write channel
+    let forwarder_stop = CancellationToken::new();
+    let mut cmd_handle = tokio::spawn(forwarder::run(
+        app.clone(),
+        cmd_rx,
+        write_tx_for_cmds,
+        settings.rate_limit,
+        forwarder_stop.clone(),
+    ));
 
     // Read loop — remainder holds partial IAC sequences between reads
-    let mut buf = vec![0u8; READ_BUF_SIZE];
+    let mut buf = vec![0u8; settings.read_buffer_size()];
     let mut remainder: Vec<u8> = Vec::new();
-    loop {
+    let mut writer_done = false;
+    let mut stats_tick = tokio::time::interval(STATS_INTERVAL);
+    let mut output = OutputCoalescer::new(settings, generation);
+    let mut login = login.map(LoginDriver::new);
+    let mut line_splitter = LineSplitter::default();
+    let mut probe = settings.latency_probe();
+    let mut prompt_watch = PromptWatch::new(generation);
+    let mut room_tracker = RoomTracker::new(generation);
+    let mut comms_router = CommsRouter::new(generation);
+    let mut plain_lines = PlainLines::new(generation);
+    let mut sleep_watch = SleepWatch::new();
+    // After waking from sleep, when to give up on hearing from the server
+    let mut resume_deadline: Option<Instant> = None;
+    shared.triggers.reset_session();
+    let (reason, detail) = loop {
         let read_result = tokio::select! {
+            _ = cancel.cancelled() => break (StatusReason::UserDisconnect, None),
+            _ = sleep_until_opt(login.as_ref().and_then(LoginDriver::deadline)) => {
+                if let Some(mut driver) = login.take() {
+                    info!("Sending delayed auto-login");
+                    for outgoing in driver.on_deadline() {
+                        let _ = write_tx.send(outgoing).await;
+                    }
+                }
+                continue;
+            }
+            _ = sleep_until_opt(output.deadline) => {
+                output.flush(app, shared, false);
+                continue;
+            }
+            _ = sleep_until_opt(line_splitter.deadline()) => {
+                // The rest of a split line never came; show what there is
+                let held = line_splitter.release();
+                if shared.accessible_output.load(Ordering::Relaxed) {
+                    plain_lines.on_release(app, shared, &held);
+                }
+                if output.push(&held, false) {
+                    output.flush(app, shared, false);
+                }
+                continue;
+            }
+            _ = sleep_until_opt(probe.as_ref().and_then(LatencyProbe::deadline)) => {
+                let Some(probe) = probe.as_mut() else {
+                    continue;
+                };
+                match probe.on_deadline(&shared.stats) {
+                    Tick::Send(outgoing) => {
+                        let _ = write_tx.send(outgoing).await;
+                    }
+                    Tick::Stalled if settings.reconnect_on_stall => {
+                        break (StatusReason::Stalled, Some("No reply to a latency probe".to_string()));
+                    }
+                    Tick::Stalled => set_stalled(app, shared, true),
+                    Tick::Idle => {}
+                }
+                continue;
+            }
+            _ = sleep_until_opt(shared.anti_idle.deadline()) => {
+                match shared.anti_idle.on_deadline(shared.server_echo.load(Ordering::Relaxed)) {
+                    anti_idle::Tick::Send(command) => {
+                        info!("Sending anti-idle command");
+                        let _ = write_tx.send(Outgoing::Synthetic(command)).await;
+                    }
+                    anti_idle::Tick::Limit(sent) => {
+                        let _ = events::emit(app, IDLE_LIMIT_EVENT, IdleLimitPayload { session: shared.session.clone(), sent, generation });
+                    }
+                    anti_idle::Tick::Idle => {}
+                }
+                continue;
+            }
+            slept = sleep_watch.tick() => {
+                if let Some(slept) = slept {
+                    info!("Woke after about {}s asleep; checking the connection", slept.as_secs());
+                    resume_deadline = Some(Instant::now() + RESUME_CHECK_TIMEOUT);
+                    let _ = write_tx.send(Outgoing::Ping).await;
+                }
+                let since_read = shared.stats.snapshot().last_read_at.map(|at| now_ms().saturating_sub(at));
+                if probe.as_ref().is_some_and(LatencyProbe::is_stalled)
+                    && since_read.is_some_and(|ms| ms >= STALE_READ_AFTER.as_millis() as u64)
+                {
+                    warn!("Stalled with nothing read for {}s; reconnecting", STALE_READ_AFTER.as_secs());
+                    break (
+                        StatusReason::Stalled,
+                        Some(format!("Nothing received for {} minutes", STALE_READ_AFTER.as_secs() / 60)),
+                    );
+                }
+                continue;
+            }
+            _ = sleep_until_opt(resume_deadline) => {
+                warn!("No reply after waking from sleep; reconnecting");
+                let detail = "No reply from the server after waking from sleep".to_string();
+                break (StatusReason::ResumedStale, Some(detail));
+            }
+            _ = stats_tick.tick() => {
+                let _ = events::emit(app, STATS_EVENT, shared.stats.snapshot());
+                continue;
+            }
             result = reader.read(&mut buf) => result,
-            _ = &mut write_handle => {
+            result = &mut write_handle => {
+                writer_done = true;
                 // Writer task exited (write error or stall). Output may still be
                 // arriving, but we can no longer send commands — tear the
                 // connection down so a disconnect is surfaced and the normal
                 // reconnect path can run, rather than appearing healthy while
                 // silently dropping input.
                 warn!("Writer task exited; tearing down half-open connection");
-                break;
+                let (reason, detail) = result
+                    .ok()
+                    .flatten()
+                    .unwrap_or((StatusReason::WriteError, "Writer task exited".to_string()));
+                break (reason, Some(detail));
             }
         };
 
-        match read_result {
-            Ok(0) => {
+        let (n, processed) = match read_result {
+            Ok(Received::Bytes(0)) => {
                 info!("Connection closed by server");
-                break;
+                break (StatusReason::ServerClosed, None);
             }
-            Ok(n) => {
+            Ok(Received::Bytes(n)) => {
+                shared.capture.record_read(&buf[..n]);
+
                 // Prepend any leftover bytes from the previous read
                 // Reuse remainder's allocation when possible to avoid per-read Vec allocs
                 let input = if remainder.is_empty() {
@@ -200,41 +1867,302 @@ pub async fn connect(
                     combined
                 };
 
-                let processed = ansi::process_output(&input);
-                remainder = processed.remainder;
-
-                // Send telnet responses back to server
-                for response in processed.responses {
-                    if write_tx.send(response).await.is_err() {
-                        break;
-                    }
-                }
-
-                // Emit display text to frontend (companion gets post-gag output from frontend)
+                let processed = if shared.telnet_debug.is_enabled() {
+                    let processed = ansi::process_output_traced(&input);
+                    shared
+                        .telnet_debug
+                        .record(app, telnet_debug::DIR_IN, &processed.telnet);
+                    processed
+                } else {
+                    ansi::process_output(&input)
+                };
+                (n, processed)
+            }
+            Ok(Received::Processed(processed)) => {
                 if !processed.display.is_empty() {
-                    let _ = app.emit(MUD_OUTPUT_EVENT, MudOutputPayload { data: processed.display, ga: processed.ga });
+                    shared.capture.record_read(processed.display.as_bytes());
                 }
+                (processed.display.len(), processed)
+            }
+            Ok(Received::Ping) => {
+                let _ = write_tx.send(Outgoing::Pong).await;
+                continue;
             }
+            Ok(Received::Ended(reason, detail)) => break (reason, Some(detail)),
             Err(e) => {
                 error!("Read error: {e}");
+                break (StatusReason::ReadError, Some(e));
+            }
+        };
+        if resume_deadline.take().is_some() {
+            info!("Connection survived the sleep");
+        }
+
+        remainder = processed.remainder;
+        if let Some(echo) = processed.echo {
+            shared.server_echo.store(echo, Ordering::Relaxed);
+        }
+        if !processed.responses.is_empty() || processed.echo.is_some() {
+            debug!(
+                "Telnet negotiation: server echo {:?}, replies {:02X?}",
+                processed.echo, processed.responses
+            );
+        }
+
+        // Send telnet responses back to server
+        for response in processed.responses {
+            if write_tx.send(Outgoing::Raw(response)).await.is_err() {
                 break;
             }
         }
+
+        if let Some(driver) = &mut login {
+            for outgoing in driver.on_output(&processed.display) {
+                let _ = write_tx.send(outgoing).await;
+            }
+            if driver.is_done() {
+                info!("Auto-login sent");
+                login = None;
+            }
+        }
+
+        let lines = processed.display.bytes().filter(|b| *b == b'\n').count();
+        shared.stats.record_read(n, lines);
+
+        if let Some(prompt) = prompt_watch.feed(
+            app,
+            &shared.prompt_parsers,
+            &shared.session,
+            &processed.display,
+            processed.ga,
+        ) {
+            shared.scripts.prompt(&shared.session, &prompt);
+        }
+        for (package, data) in &processed.gmcp {
+            shared.scripts.gmcp(&shared.session, package, data);
+        }
+        room_tracker.feed(app, shared, &processed.display, processed.ga);
+
+        if let Some(probe) = probe.as_mut() {
+            let was_stalled = probe.is_stalled();
+            if let Some(rtt) = probe.on_read(processed.timing_mark, processed.ga, &shared.stats) {
+                debug!("Latency probe answered in {}ms", rtt.as_millis());
+                shared.stats.record_latency(rtt);
+            }
+            if was_stalled {
+                info!("Output resumed; connection no longer stalled");
+                set_stalled(app, shared, false);
+            }
+        }
+
+        let display = if shared.wants_lines() {
+            let gagged = &mut output.gagged;
+            let plain = shared.accessible_output.load(Ordering::Relaxed);
+            let display = line_splitter.feed(&processed.display, processed.ga, |line, prompt| {
+                let gag = filter_line(app, shared, &mut comms_router, gagged, line, prompt);
+                if plain && !gag {
+                    plain_lines.on_line(app, shared, line, prompt);
+                }
+                gag
+            });
+            plain_lines.flush(app, shared);
+            display
+        } else {
+            let mut display = line_splitter.flush();
+            display.push_str(&processed.display);
+            display
+        };
+
+        // Emit display text to frontend (companion gets post-gag output from frontend)
+        if (!display.is_empty() || processed.ga || !output.gagged.is_empty())
+            && output.push(&display, processed.ga)
+        {
+            output.flush(app, shared, processed.ga);
+        }
+    };
+
+    // Anything still buffered goes out before the disconnect status
+    comms_router.finish(app, shared);
+    let held = line_splitter.flush();
+    if shared.accessible_output.load(Ordering::Relaxed) {
+        plain_lines.on_release(app, shared, &held);
     }
+    output.push(&held, false);
+    output.flush(app, shared, false);
 
-    cmd_handle.abort();
-    write_handle.abort();
+    let mut unsent = Vec::new();
+    if reason == StatusReason::UserDisconnect && !writer_done {
+        // The command sender was dropped by the canceller, so the forwarder
+        // ends once it has drained whatever was already queued.
+        if timeout(SHUTDOWN_FLUSH_TIMEOUT, &mut cmd_handle)
+            .await
+            .is_err()
+        {
+            cmd_handle.abort();
+        }
+        if let Some(quit) = settings.quit_command.as_deref().filter(|q| !q.is_empty()) {
+            info!("Sending quit command before disconnecting");
+            let _ = timeout(
+                SHUTDOWN_FLUSH_TIMEOUT,
+                write_tx.send(Outgoing::Command(quit.to_string())),
+            )
+            .await;
+        }
+        // Dropping the last sender lets the writer drain and shut down the socket
+        drop(write_tx);
+        if timeout(SHUTDOWN_FLUSH_TIMEOUT, &mut write_handle)
+            .await
+            .is_err()
+        {
+            warn!(
+                "Writer did not drain within {}s; aborting",
+                SHUTDOWN_FLUSH_TIMEOUT.as_secs()
+            );
+            write_handle.abort();
+        }
+    } else {
+        forwarder_stop.cancel();
+        match timeout(SHUTDOWN_FLUSH_TIMEOUT, &mut cmd_handle).await {
+            Ok(Ok(leftover)) => unsent = leftover,
+            _ => cmd_handle.abort(),
+        }
+        write_handle.abort();
+    }
 
-    let _ = app.emit(
-        CONNECTION_STATUS_EVENT,
-        ConnectionStatusPayload {
-            connected: false,
-            message: "Disconnected".to_string(),
-        },
-    );
-    let _ = broadcast_tx.send(CompanionMessage::ConnectionStatus {
-        connected: false,
-        message: "Disconnected".to_string(),
-    });
-    set_status(&last_status, false, "Disconnected");
+    (reason, detail, unsent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use tauri::Listener;
+    use tokio::net::TcpListener;
+
+    /// Everything a session emits on its own.
+    const SESSION_EVENTS: [&str; 8] = [
+        crate::events::MUD_OUTPUT_EVENT,
+        GAGGED_LINES_EVENT,
+        INPUT_ECHO_EVENT,
+        STATS_EVENT,
+        TRIGGER_FIRED_EVENT,
+        IDLE_LIMIT_EVENT,
+        crate::events::BATCH_PROGRESS_EVENT,
+        crate::events::COMMAND_BACKLOG_EVENT,
+    ];
+
+    // Multi-threaded, so a task the session left running would carry on
+    // emitting alongside the test rather than finishing before it resumes.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn nothing_is_emitted_once_a_disconnected_session_returns() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+
+        let app = tauri::test::mock_app();
+        let emitted = Arc::new(AtomicUsize::new(0));
+        for event in SESSION_EVENTS {
+            let emitted = emitted.clone();
+            app.listen_any(event, move |_| {
+                emitted.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        let shared = Arc::new(ConnectionShared::new(DEFAULT_SESSION));
+        *shared.local_echo.lock().unwrap() = Some(DEFAULT_ECHO_MARKER.to_string());
+        let settings = ConnectionSettings {
+            quit_command: Some("quit".to_string()),
+            ..Default::default()
+        };
+        let (cmd_tx, cmd_rx) = mpsc::channel(8);
+        let cancel = CancellationToken::new();
+        let task = tokio::spawn({
+            let app = app.handle().clone();
+            let shared = shared.clone();
+            let cancel = cancel.clone();
+            async move {
+                run_session(
+                    &app,
+                    Link::Tcp(client),
+                    cmd_rx,
+                    &shared,
+                    &settings,
+                    None,
+                    &cancel,
+                    1,
+                )
+                .await
+            }
+        });
+
+        cmd_tx
+            .send(CommandRequest::Batch {
+                commands: vec!["north".to_string(), "east".to_string()],
+                delay: Duration::from_millis(50),
+                interleave: false,
+            })
+            .await
+            .unwrap();
+        cmd_tx
+            .send(CommandRequest::Send("look".to_string()))
+            .await
+            .unwrap();
+        drop(cmd_tx);
+        cancel.cancel();
+        let (reason, _, unsent) = timeout(Duration::from_secs(10), task)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reason, StatusReason::UserDisconnect);
+        assert!(unsent.is_empty());
+
+        let after_return = emitted.load(Ordering::SeqCst);
+        assert!(after_return > 0);
+        // The batch is discarded, but the command held behind it and the
+        // quit command go out before the FIN
+        let mut received = Vec::new();
+        timeout(Duration::from_secs(5), server.read_to_end(&mut received))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, b"look\r\nquit\r\n");
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(emitted.load(Ordering::SeqCst), after_return);
+    }
+
+    // `connect` needs a running app, so this drives the link it shuts down.
+    #[tokio::test]
+    async fn shutdown_sends_queued_command_then_fin() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+        let (mut reader, mut writer) = Link::Tcp(client).into_split();
+
+        assert!(writer
+            .write(&command_bytes("quit"), Frame::Line)
+            .await
+            .unwrap());
+        writer.shutdown().await;
+
+        let mut received = Vec::new();
+        timeout(Duration::from_secs(5), server.read_to_end(&mut received))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, b"quit\r\n");
+
+        // The server's close reaches the reader as end of stream
+        drop(server);
+        let mut buf = [0u8; 16];
+        let read = timeout(Duration::from_secs(5), reader.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(read, Received::Bytes(0)));
+    }
 }
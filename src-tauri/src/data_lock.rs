@@ -0,0 +1,260 @@
+//! One running app per data directory. The active data dir holds
+//! `.dartforge.lock`, locked with an OS file lock for as long as the app uses
+//! that dir and naming the process that holds it. A second instance that
+//! finds the lock taken goes read-only there (every write into the dir
+//! refuses, backend ones included) and emits `storage:data-dir-locked`,
+//! instead of racing the first to overwrite settings.
+//!
+//! The OS drops the lock when a process dies, so a crash leaves nothing
+//! stale. Where file locks aren't supported (some network drives), the PID in
+//! the file decides instead: a lock whose process is still alive is honoured.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::events::{DataDirLockedPayload, DATA_DIR_LOCKED_EVENT};
+
+pub const LOCK_FILE: &str = ".dartforge.lock";
+
+pub(crate) const READ_ONLY_MESSAGE: &str =
+    "Read-only: another DartForge window is using this data folder, so changes here aren't saved";
+
+/// The data dir this process has read-only, checked by writers that only
+/// know the path they write to.
+static READ_ONLY_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+fn set_read_only(dir: Option<&Path>) {
+    *READ_ONLY_DIR.lock().unwrap_or_else(|e| e.into_inner()) = dir.map(Path::to_path_buf);
+}
+
+/// Refuse to write `path` if it's in the data dir this process has
+/// read-only. Every write into the data dir goes through this, so a second
+/// instance can't overwrite the first's files from the backend either.
+pub(crate) fn check_writable(path: &Path) -> Result<(), String> {
+    match READ_ONLY_DIR
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+    {
+        Some(dir) if path.starts_with(dir) => Err(READ_ONLY_MESSAGE.to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LockInfo {
+    pid: u32,
+    /// RFC 3339, local time.
+    started_at: String,
+}
+
+/// Whether process `pid` is running, by asking the OS's own tools.
+fn process_alive(pid: u32) -> bool {
+    #[cfg(windows)]
+    let output = std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/NH"])
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).contains(&pid.to_string()));
+    #[cfg(not(windows))]
+    let output = std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .output()
+        .map(|out| out.status.success());
+    // If it can't be checked, assume the holder is alive rather than risk
+    // two writers
+    output.unwrap_or(true)
+}
+
+#[derive(Default)]
+pub struct DataDirLock {
+    /// The dir locked and its open lock file; dropping the file unlocks it.
+    held: Mutex<Option<(PathBuf, File)>>,
+}
+
+impl DataDirLock {
+    pub fn read_only(&self) -> bool {
+        READ_ONLY_DIR
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+
+    /// Lock `dir` for this process, releasing any dir locked before. If
+    /// another instance holds it, go read-only and tell the frontend. The app
+    /// log is written to whichever dir this process owns.
+    pub fn acquire(&self, dir: &Path, app: &AppHandle) {
+        let mut held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+        if held.as_ref().is_some_and(|(locked, _)| locked == dir) {
+            return;
+        }
+        release(held.take());
+        match try_lock(dir) {
+            Ok(file) => {
+                *held = file.map(|file| (dir.to_path_buf(), file));
+                set_read_only(None);
+                crate::app_log::attach(dir);
+            }
+            Err(holder) => {
+                warn!(
+                    "{} is in use by another DartForge (pid {}); opening it read-only",
+                    dir.display(),
+                    holder
+                        .as_ref()
+                        .map_or("unknown".to_string(), |h| h.pid.to_string())
+                );
+                set_read_only(Some(dir));
+                crate::app_log::detach();
+                let payload = DataDirLockedPayload {
+                    dir: dir.to_string_lossy().into_owned(),
+                    pid: holder.as_ref().map(|h| h.pid),
+                    started_at: holder.map(|h| h.started_at),
+                };
+                let _ = app.emit(DATA_DIR_LOCKED_EVENT, payload);
+            }
+        }
+    }
+
+    /// Unlock and remove the lock file, on exit.
+    pub fn release(&self) {
+        release(self.held.lock().unwrap_or_else(|e| e.into_inner()).take());
+    }
+}
+
+fn release(held: Option<(PathBuf, File)>) {
+    if let Some((dir, file)) = held {
+        drop(file);
+        let _ = fs::remove_file(dir.join(LOCK_FILE));
+    }
+}
+
+fn read_info(path: &Path) -> Option<LockInfo> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+enum Opened {
+    Locked(File),
+    /// Another process holds the lock.
+    Held,
+    /// Opened, but the filesystem won't lock it.
+    #[cfg_attr(windows, allow(dead_code))]
+    Unlocked(File, io::Error),
+}
+
+#[cfg(unix)]
+fn open_locked(path: &Path) -> io::Result<Opened> {
+    use rustix::fs::{flock, FlockOperation};
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    match flock(&file, FlockOperation::NonBlockingLockExclusive) {
+        Ok(()) => Ok(Opened::Locked(file)),
+        Err(rustix::io::Errno::WOULDBLOCK) => Ok(Opened::Held),
+        Err(e) => Ok(Opened::Unlocked(file, e.into())),
+    }
+}
+
+/// Windows locks by share mode: while this handle is open, nobody else can
+/// open the file for writing, though they can still read who holds it.
+#[cfg(windows)]
+fn open_locked(path: &Path) -> io::Result<Opened> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_SHARE_READ: u32 = 0x1;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    let opened = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .share_mode(FILE_SHARE_READ)
+        .open(path);
+    match opened {
+        Ok(file) => Ok(Opened::Locked(file)),
+        Err(e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => Ok(Opened::Held),
+        Err(e) => Err(e),
+    }
+}
+
+/// Lock `dir`'s lock file and write this process into it. `Ok(None)` if the
+/// file can't be created at all (a read-only folder, where writes fail
+/// anyway). On failure, whatever the file says about the holder.
+fn try_lock(dir: &Path) -> Result<Option<File>, Option<LockInfo>> {
+    let path = dir.join(LOCK_FILE);
+    let mut file = match open_locked(&path) {
+        Ok(Opened::Locked(file)) => file,
+        Ok(Opened::Held) => return Err(read_info(&path)),
+        Ok(Opened::Unlocked(file, e)) => {
+            let holder =
+                read_info(&path).filter(|h| h.pid != std::process::id() && process_alive(h.pid));
+            if holder.is_some() {
+                return Err(holder);
+            }
+            info!(
+                "File locks unsupported in {} ({e}); relying on the PID in {LOCK_FILE}",
+                dir.display()
+            );
+            file
+        }
+        Err(e) => {
+            warn!("Failed to open {}: {e}", path.display());
+            return Ok(None);
+        }
+    };
+    let info = LockInfo {
+        pid: std::process::id(),
+        started_at: chrono::Local::now().to_rfc3339(),
+    };
+    let written = serde_json::to_vec(&info)
+        .map_err(io::Error::other)
+        .and_then(|data| {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&data)?;
+            file.flush()
+        });
+    if let Err(e) = written {
+        warn!("Failed to write {}: {e}", path.display());
+    }
+    Ok(Some(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_lock_on_a_dir_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = try_lock(dir.path()).unwrap();
+        assert!(first.is_some());
+        let holder = try_lock(dir.path()).unwrap_err().unwrap();
+        assert_eq!(holder.pid, std::process::id());
+        release(first.map(|file| (dir.path().to_path_buf(), file)));
+        assert!(try_lock(dir.path()).is_ok());
+    }
+
+    #[test]
+    fn backend_writes_into_a_read_only_dir_are_refused() {
+        let locked = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        set_read_only(Some(locked.path()));
+        let refused = crate::storage::write_json_file(locked.path(), "timers.json", &1);
+        let nested = crate::storage::atomic_write(&locked.path().join("x/map.json"), b"{}", false);
+        let elsewhere = crate::storage::write_json_file(other.path(), "timers.json", &1);
+        set_read_only(None);
+
+        assert_eq!(refused.unwrap_err(), READ_ONLY_MESSAGE);
+        assert_eq!(nested.unwrap_err(), READ_ONLY_MESSAGE);
+        assert!(!locked.path().join("timers.json").exists());
+        elsewhere.unwrap();
+        crate::storage::write_json_file(locked.path(), "timers.json", &1).unwrap();
+    }
+}
@@ -0,0 +1,341 @@
+//! Diagnostic bundles for bug reports: one zip with version and OS info, the
+//! end of the app log and of any telnet debug log, connection stats, a
+//! listing of the data dir, and the settings files with anything that looks
+//! like a secret redacted. Session log content is only included when asked
+//! for.
+
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager};
+
+use crate::storage::{self, StorageState};
+
+/// How much of the end of the app log to include.
+const APP_LOG_TAIL_BYTES: u64 = 512 * 1024;
+/// How much of the end of each session log to include, when asked.
+const SESSION_LOG_TAIL_BYTES: u64 = 256 * 1024;
+/// Settings files larger than this are left out.
+const MAX_SETTINGS_BYTES: u64 = 2 * 1024 * 1024;
+/// Bounds on the data dir listing.
+const MAX_LISTING_ENTRIES: usize = 5000;
+const MAX_LISTING_DEPTH: usize = 4;
+/// Key names whose values are redacted wherever they appear, matched as
+/// substrings of the lowercased key.
+const SECRET_KEY_PARTS: [&str; 9] = [
+    "password",
+    "passwd",
+    "passphrase",
+    "secret",
+    "token",
+    "credential",
+    "apikey",
+    "api_key",
+    "authorization",
+];
+const REDACTED: &str = "[redacted]";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticBundle {
+    pub path: String,
+    /// Entry names in the zip.
+    pub files: Vec<String>,
+}
+
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SECRET_KEY_PARTS.iter().any(|part| key.contains(part))
+}
+
+/// Replace the value of every secret-looking key, at any depth.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_secret_key(key) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// A short OS description, e.g. "linux 6.8.0 (Ubuntu 24.04 LTS)".
+fn os_version() -> String {
+    let run = |program: &str, args: &[&str]| {
+        std::process::Command::new(program)
+            .args(args)
+            .output()
+            .ok()
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+            .filter(|out| !out.is_empty())
+    };
+    let detail = if cfg!(target_os = "linux") {
+        fs::read_to_string("/etc/os-release")
+            .ok()
+            .and_then(|release| {
+                release
+                    .lines()
+                    .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+                    .map(|name| name.trim_matches('"').to_string())
+            })
+    } else if cfg!(target_os = "macos") {
+        run("sw_vers", &["-productVersion"])
+    } else if cfg!(windows) {
+        run("cmd", &["/C", "ver"])
+    } else {
+        None
+    };
+    let kernel = if cfg!(unix) {
+        run("uname", &["-r"])
+    } else {
+        None
+    };
+    let mut version = std::env::consts::OS.to_string();
+    if let Some(kernel) = kernel {
+        version = format!("{version} {kernel}");
+    }
+    if let Some(detail) = detail {
+        version = format!("{version} ({detail})");
+    }
+    version
+}
+
+fn system_info(app: &AppHandle, data_dir: &Path) -> Value {
+    let storage = app.state::<StorageState>();
+    json!({
+        "app_version": app.package_info().version.to_string(),
+        "tauri_version": tauri::VERSION,
+        "os": os_version(),
+        "arch": std::env::consts::ARCH,
+        "created_at": chrono::Local::now().to_rfc3339(),
+        "data_dir": data_dir.to_string_lossy(),
+        "data_dir_read_only": storage.lock.read_only(),
+        "log_filter": crate::app_log::current_filter(),
+        "launch_options": app.state::<crate::cli::Launch>().options,
+    })
+}
+
+fn connection_info(app: &AppHandle) -> Value {
+    let shared = &app.state::<crate::ConnectionState>().shared;
+    let host = shared
+        .target
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .host()
+        .to_string();
+    let profile = shared
+        .active_profile
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|p| p.name.clone());
+    json!({
+        "host": host,
+        "profile": profile,
+        "status": *shared.status.lock().unwrap_or_else(|e| e.into_inner()),
+        "stats": shared.stats.snapshot(),
+        "triggers": shared.triggers.stats().len(),
+        "timers": shared.timers.list().len(),
+    })
+}
+
+/// Every file under `dir` with its size, relative to `dir`.
+fn listing(dir: &Path) -> Value {
+    fn walk(dir: &Path, prefix: &str, depth: usize, out: &mut Vec<Value>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            if out.len() >= MAX_LISTING_ENTRIES {
+                return;
+            }
+            let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                out.push(json!({ "path": format!("{name}/"), "size": null }));
+                if depth + 1 < MAX_LISTING_DEPTH {
+                    walk(&entry.path(), &format!("{name}/"), depth + 1, out);
+                }
+            } else {
+                out.push(json!({ "path": name, "size": meta.len() }));
+            }
+        }
+    }
+    let mut files = Vec::new();
+    walk(dir, "", 0, &mut files);
+    files.sort_by(|a, b| a["path"].as_str().cmp(&b["path"].as_str()));
+    json!({ "truncated": files.len() >= MAX_LISTING_ENTRIES, "files": files })
+}
+
+/// The top-level JSON files in the data dir, redacted, as `(name, contents)`.
+/// Ones that don't parse are listed with the error instead.
+fn settings_files(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<(String, Vec<u8>)> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter(|e| {
+            e.metadata()
+                .is_ok_and(|meta| meta.is_file() && meta.len() <= MAX_SETTINGS_BYTES)
+        })
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            let contents = fs::read(e.path()).ok()?;
+            let value = match serde_json::from_slice::<Value>(&contents) {
+                Ok(mut value) => {
+                    redact(&mut value);
+                    value
+                }
+                Err(e) => json!({ "unreadable": e.to_string() }),
+            };
+            Some((
+                format!("settings/{name}"),
+                serde_json::to_vec_pretty(&value).ok()?,
+            ))
+        })
+        .collect();
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    files
+}
+
+/// The most recently modified file directly in `dir` or its subfolders.
+fn newest_file(dir: &Path) -> Option<PathBuf> {
+    let mut newest: Option<(std::time::SystemTime, PathBuf)> = None;
+    let mut dirs = vec![(dir.to_path_buf(), 0)];
+    while let Some((dir, depth)) = dirs.pop() {
+        for entry in fs::read_dir(&dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+        {
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if meta.is_dir() && depth + 1 < MAX_LISTING_DEPTH {
+                dirs.push((entry.path(), depth + 1));
+            } else if let Ok(modified) = meta.modified() {
+                if meta.is_file() && newest.as_ref().is_none_or(|(at, _)| modified > *at) {
+                    newest = Some((modified, entry.path()));
+                }
+            }
+        }
+    }
+    newest.map(|(_, path)| path)
+}
+
+fn collect(app: &AppHandle, data_dir: &Path, include_session_logs: bool) -> Vec<(String, Vec<u8>)> {
+    let pretty = |value: Value| serde_json::to_vec_pretty(&value).unwrap_or_default();
+    let mut entries = vec![
+        (
+            "system.json".to_string(),
+            pretty(system_info(app, data_dir)),
+        ),
+        ("connection.json".to_string(), pretty(connection_info(app))),
+        ("data-dir.json".to_string(), pretty(listing(data_dir))),
+    ];
+    // The current file and the one before it, in case it just rotated
+    for path in crate::app_log::log_files(data_dir).into_iter().take(2) {
+        if let Ok(log) = crate::app_log::tail_file(&path, APP_LOG_TAIL_BYTES) {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            entries.push((name, log));
+        }
+    }
+    let telnet_log = data_dir.join(crate::telnet_debug::TELNET_DEBUG_LOG);
+    if let Ok(log) = crate::app_log::tail_file(&telnet_log, APP_LOG_TAIL_BYTES) {
+        entries.push((crate::telnet_debug::TELNET_DEBUG_LOG.to_string(), log));
+    }
+    entries.extend(settings_files(data_dir));
+    if include_session_logs {
+        for subdir in ["sessions", crate::session_log::LOGS_DIR] {
+            let Some(path) = newest_file(&data_dir.join(subdir)) else {
+                continue;
+            };
+            if let Ok(tail) = crate::app_log::tail_file(&path, SESSION_LOG_TAIL_BYTES) {
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                entries.push((format!("session-logs/{subdir}/{name}"), tail));
+            }
+        }
+    }
+    entries
+}
+
+fn write_zip(output: &Path, entries: &[(String, Vec<u8>)]) -> Result<(), String> {
+    let file =
+        fs::File::create(output).map_err(|e| format!("Cannot write {}: {e}", output.display()))?;
+    let mut zip_writer = zip::ZipWriter::new(BufWriter::new(file));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for (name, data) in entries {
+        zip_writer
+            .start_file(name.as_str(), options)
+            .and_then(|()| zip_writer.write_all(data).map_err(Into::into))
+            .map_err(|e| format!("Failed to add {name} to the bundle: {e}"))?;
+    }
+    zip_writer
+        .finish()
+        .map_err(|e| format!("Failed to finalize bundle: {e}"))?
+        .flush()
+        .map_err(|e| format!("Failed to finalize bundle: {e}"))
+}
+
+/// Write a diagnostic zip to `output_path` (absolute) or wherever a save
+/// dialog puts it, and list what went in so it can be checked before it is
+/// shared. With `include_session_logs`, the end of the newest session log is
+/// added too. `None` if the dialog was cancelled.
+#[tauri::command]
+pub async fn create_diagnostic_bundle(
+    app: AppHandle,
+    output_path: Option<String>,
+    include_session_logs: Option<bool>,
+) -> Result<Option<DiagnosticBundle>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let suggested = format!(
+            "dartforge-diagnostics-{}.zip",
+            chrono::Local::now().format("%Y-%m-%d-%H%M%S")
+        );
+        let Some(output) =
+            crate::resolve_export_path(&app, output_path, &suggested, "Zip archive", &["zip"])?
+        else {
+            return Ok(None);
+        };
+        let data_dir = app.state::<StorageState>().get_dir();
+        let entries = collect(&app, &data_dir, include_session_logs.unwrap_or(false));
+        let tmp = storage::temp_path(&output);
+        if let Err(e) = write_zip(&tmp, &entries).and_then(|()| {
+            fs::rename(&tmp, &output).map_err(|e| format!("Cannot write {}: {e}", output.display()))
+        }) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        info!(
+            "Wrote diagnostic bundle with {} file(s) to {}",
+            entries.len(),
+            output.display()
+        );
+        Ok(Some(DiagnosticBundle {
+            path: output.to_string_lossy().into_owned(),
+            files: entries.into_iter().map(|(name, _)| name).collect(),
+        }))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
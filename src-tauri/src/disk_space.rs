@@ -0,0 +1,221 @@
+//! Free space on the data volume. Writes that can be large (backups, copies
+//! to another folder, big log appends) check for room first and fail with
+//! how much they need, rather than leaving a half-written file behind. A
+//! monitor emits `storage:low-disk` when free space drops below the
+//! configured floor, and `get_storage_usage` breaks down what the data dir
+//! itself takes up.
+
+use std::fs;
+use std::path::Path;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::time::Duration;
+
+use crate::events::{LowDiskPayload, LOW_DISK_EVENT};
+use crate::storage::{self, StorageState};
+
+pub const DISK_SPACE_FILE: &str = "disk-space.json";
+const DEFAULT_LOW_DISK_FLOOR_BYTES: u64 = 500 * 1024 * 1024;
+/// Left free on top of what a write needs, for the filesystem's own use.
+const HEADROOM_BYTES: u64 = 16 * 1024 * 1024;
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Bounds the usage scan; past this many entries the totals are partial.
+const MAX_SCAN_ENTRIES: usize = 200_000;
+const MAX_SCAN_DEPTH: usize = 8;
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiskSpaceSettings {
+    /// Emit `storage:low-disk` once free space falls below this.
+    pub low_disk_floor_bytes: u64,
+}
+
+impl Default for DiskSpaceSettings {
+    fn default() -> Self {
+        Self {
+            low_disk_floor_bytes: DEFAULT_LOW_DISK_FLOOR_BYTES,
+        }
+    }
+}
+
+impl DiskSpaceSettings {
+    pub fn load(dir: &Path) -> Self {
+        storage::read_json_file(dir, DISK_SPACE_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        storage::write_json_file(dir, DISK_SPACE_FILE, self)
+    }
+}
+
+/// Bytes free to this user on the volume holding `path`. `None` if the OS
+/// won't say.
+#[cfg(unix)]
+pub fn available_bytes(path: &Path) -> Option<u64> {
+    let stat = rustix::fs::statvfs(path).ok()?;
+    Some(stat.f_bavail.saturating_mul(stat.f_frsize))
+}
+
+#[cfg(windows)]
+pub fn available_bytes(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    // SAFETY: `wide` is NUL-terminated and outlives the call; the other
+    // out-pointers may be null
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    (ok != 0).then_some(available)
+}
+
+/// Fail early if the volume holding `path` can't take `needed` more bytes.
+/// Passes when free space can't be read, leaving the write to fail on its own.
+pub fn ensure_space(path: &Path, needed: u64) -> Result<(), String> {
+    let Some(available) = available_bytes(path) else {
+        return Ok(());
+    };
+    if available >= needed.saturating_add(HEADROOM_BYTES) {
+        return Ok(());
+    }
+    Err(format!(
+        "Not enough disk space: need ~{} MB, only {} MB available",
+        needed.div_ceil(MB),
+        available / MB
+    ))
+}
+
+/// Total size of `paths`' files, for sizing a write ahead of time.
+pub fn total_size<'a>(paths: impl IntoIterator<Item = &'a Path>) -> u64 {
+    paths
+        .into_iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StorageUsage {
+    pub data_bytes: u64,
+    pub logs_bytes: u64,
+    pub sounds_bytes: u64,
+    pub backups_bytes: u64,
+    pub total_bytes: u64,
+    pub available_bytes: Option<u64>,
+    /// The scan hit its entry limit, so the totals are lower bounds.
+    pub truncated: bool,
+}
+
+/// Bytes under `dir`, counting each entry seen against `budget`.
+fn dir_size(dir: &Path, depth: usize, budget: &mut usize) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut total = 0;
+    for entry in entries.flatten() {
+        if *budget == 0 {
+            break;
+        }
+        *budget -= 1;
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() && depth < MAX_SCAN_DEPTH {
+            total += dir_size(&entry.path(), depth + 1, budget);
+        } else if file_type.is_file() {
+            total += entry.metadata().map(|meta| meta.len()).unwrap_or(0);
+        }
+    }
+    total
+}
+
+fn storage_usage(data_dir: &Path) -> StorageUsage {
+    let mut usage = StorageUsage {
+        available_bytes: available_bytes(data_dir),
+        ..Default::default()
+    };
+    let mut budget = MAX_SCAN_ENTRIES;
+    let Ok(entries) = fs::read_dir(data_dir) else {
+        return usage;
+    };
+    for entry in entries.flatten() {
+        if budget == 0 {
+            break;
+        }
+        budget -= 1;
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if file_type.is_file() {
+            usage.data_bytes += entry.metadata().map(|meta| meta.len()).unwrap_or(0);
+            continue;
+        }
+        if !file_type.is_dir() {
+            continue;
+        }
+        let size = dir_size(&entry.path(), 1, &mut budget);
+        match name.as_str() {
+            "logs" | "sessions" | "captures" => usage.logs_bytes += size,
+            "sounds" => usage.sounds_bytes += size,
+            "backups" => usage.backups_bytes += size,
+            _ => usage.data_bytes += size,
+        }
+    }
+    usage.truncated = budget == 0;
+    usage.total_bytes =
+        usage.data_bytes + usage.logs_bytes + usage.sounds_bytes + usage.backups_bytes;
+    usage
+}
+
+/// Bytes the data dir uses, by kind: data files (and their subfolders),
+/// logs, sounds and backups, plus the volume's free space. The walk runs on
+/// a blocking thread so other commands aren't held up.
+#[tauri::command]
+pub async fn get_storage_usage(app: AppHandle) -> Result<StorageUsage, String> {
+    let data_dir = app.state::<StorageState>().get_dir();
+    tauri::async_runtime::spawn_blocking(move || storage_usage(&data_dir))
+        .await
+        .map_err(|e| format!("Storage usage scan failed: {e}"))
+}
+
+/// Check free space every `CHECK_INTERVAL` for the life of the app, emitting
+/// `storage:low-disk` each time it falls below the floor.
+pub async fn run(app: AppHandle) {
+    let mut low = false;
+    loop {
+        // Sleep first, so the frontend is listening by the first check
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        let dir = app.state::<StorageState>().get_dir();
+        let floor = DiskSpaceSettings::load(&dir).low_disk_floor_bytes;
+        match available_bytes(&dir) {
+            Some(available) if available < floor => {
+                if !low {
+                    warn!(
+                        "Low disk space: {} MB free in {}",
+                        available / MB,
+                        dir.display()
+                    );
+                    let payload = LowDiskPayload {
+                        dir: dir.to_string_lossy().into_owned(),
+                        available_bytes: available,
+                        floor_bytes: floor,
+                    };
+                    let _ = app.emit(LOW_DISK_EVENT, payload);
+                }
+                low = true;
+            }
+            Some(_) => low = false,
+            None => {}
+        }
+    }
+}
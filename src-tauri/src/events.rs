@@ -1,16 +1,669 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+
 use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Runtime};
 
 pub const MUD_OUTPUT_EVENT: &str = "mud:output";
 pub const CONNECTION_STATUS_EVENT: &str = "mud:connection-status";
+pub const COMMAND_QUEUE_FLUSHED_EVENT: &str = "mud:command-queue-flushed";
+pub const STATS_EVENT: &str = "mud:stats";
+pub const INPUT_ECHO_EVENT: &str = "mud:input-echo";
+pub const BATCH_PROGRESS_EVENT: &str = "mud:batch-progress";
+pub const COMMAND_BACKLOG_EVENT: &str = "mud:command-backlog";
+pub const TRIGGER_FIRED_EVENT: &str = "mud:trigger-fired";
+pub const GAGGED_LINES_EVENT: &str = "mud:gagged-lines";
+pub const TIMER_FIRED_EVENT: &str = "mud:timer-fired";
+pub const SESSION_LOG_EVENT: &str = "mud:session-log";
+pub const STORAGE_FILE_CHANGED_EVENT: &str = "storage:file-changed";
+pub const BACKUP_PROGRESS_EVENT: &str = "storage:backup-progress";
+pub const AUTO_BACKUP_EVENT: &str = "storage:auto-backup";
+pub const DATA_DIR_MIGRATED_EVENT: &str = "storage:data-dir-migrated";
+pub const DATA_DIR_LOCKED_EVENT: &str = "storage:data-dir-locked";
+pub const LOW_DISK_EVENT: &str = "storage:low-disk";
+pub const SECOND_INSTANCE_EVENT: &str = "app:second-instance";
+pub const TELNET_DEBUG_EVENT: &str = "mud:telnet-debug";
+pub const OUTPUT_PAUSED_EVENT: &str = "mud:output-paused";
+pub const PROMPT_EVENT: &str = "mud:prompt";
+pub const VITALS_EVENT: &str = "mud:vitals";
+pub const ROOM_EVENT: &str = "mud:room";
+pub const COMMS_EVENT: &str = "mud:comms";
+pub const SESSION_SUMMARY_EVENT: &str = "mud:session-summary";
+pub const IDLE_LIMIT_EVENT: &str = "mud:idle-limit";
+pub const SCRIPT_ERROR_EVENT: &str = "mud:script-error";
+pub const PLAIN_OUTPUT_EVENT: &str = "mud:output-plain";
+
+/// Current wall-clock time as Unix milliseconds.
+pub fn now_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64
+}
+
+/// A payload as it goes out, with `ts_ms` alongside its own fields.
+#[derive(Clone, Serialize)]
+struct Stamped<P> {
+    #[serde(flatten)]
+    payload: P,
+    ts_ms: u64,
+}
+
+/// Emit a `mud:*` event with `ts_ms` (Unix milliseconds, taken now) added to
+/// its payload. Every `mud:*` event goes through this so none goes out
+/// without one; the payload must serialize as a struct.
+pub fn emit<R: Runtime, P: Serialize + Clone>(
+    app: &AppHandle<R>,
+    event: &str,
+    payload: P,
+) -> tauri::Result<()> {
+    app.emit(
+        event,
+        Stamped {
+            payload,
+            ts_ms: now_ms(),
+        },
+    )
+}
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct MudOutputPayload {
     pub data: String,
     pub ga: bool,
+    /// The session the output came from.
+    pub session: String,
+    /// Output held while paused, delivered on resume.
+    pub buffered: bool,
+    /// The connection task it came from, as on statuses (see
+    /// `ConnectionShared::generation`). After a reconnect, events from an
+    /// older generation than the latest status's are from the replaced
+    /// connection and should be dropped; the same goes for every payload
+    /// carrying a generation.
+    pub generation: u64,
+}
+
+/// Whether a session's output is paused, and how much is held.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OutputPausedPayload {
+    pub session: String,
+    pub paused: bool,
+    pub held_bytes: usize,
+    /// Held text dropped to stay within the limit.
+    pub dropped_bytes: usize,
+    /// Unix millis of the last prompt (GA) while paused.
+    pub last_prompt_at: Option<u64>,
+}
+
+/// A GA-terminated prompt, ANSI-stripped.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PromptPayload {
+    pub session: String,
+    pub prompt: String,
+    /// Connection generation; see `MudOutputPayload::generation`.
+    pub generation: u64,
+}
+
+/// Accessible output: complete lines as plain text, joined with `\n`, or a
+/// prompt on its own.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlainOutputPayload {
+    pub session: String,
+    pub text: String,
+    pub is_prompt: bool,
+    /// Connection generation; see `MudOutputPayload::generation`.
+    pub generation: u64,
+}
+
+/// Numeric fields the prompt parsers found, emitted when they change.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VitalsPayload {
+    pub session: String,
+    pub vitals: BTreeMap<String, f64>,
+    /// The prompt they came from.
+    pub prompt: String,
+    /// Connection generation; see `MudOutputPayload::generation`.
+    pub generation: u64,
+}
+
+/// A room seen in the output.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoomPayload {
+    pub session: String,
+    pub name: String,
+    pub exits: Vec<String>,
+    /// Direction of the movement command that led here, if one was sent
+    /// just before.
+    pub moved_via: Option<String>,
+    /// Connection generation; see `MudOutputPayload::generation`.
+    pub generation: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CommsPayload {
+    pub session: String,
+    pub channel: String,
+    pub speaker: Option<String>,
+    /// The message, continuation lines joined with spaces.
+    pub text: String,
+    /// The lines it came from, ANSI-stripped.
+    pub raw: String,
+    /// Connection generation; see `MudOutputPayload::generation`.
+    pub generation: u64,
+}
+
+/// One connection's activity, emitted on disconnect and recorded in the
+/// profile's sessions.jsonl.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionSummaryPayload {
+    pub session: String,
+    pub profile: Option<String>,
+    pub started_at: u64,
+    /// `None` while the session is still connected.
+    pub ended_at: Option<u64>,
+    pub duration_secs: u64,
+    pub commands: u64,
+    pub lines: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Lines matched by each session counter.
+    pub counters: BTreeMap<String, u64>,
+    /// Why the session ended.
+    pub reason: Option<StatusReason>,
+}
+
+/// Why the connection is in its current state. This, not `message`, is the
+/// contract the UI keys behavior (e.g. offering auto-reconnect) off of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusReason {
+    Connecting,
+    Connected,
+    UserDisconnect,
+    ServerClosed,
+    ReadError,
+    WriteError,
+    DnsFailure,
+    Timeout,
+    ConnectRefused,
+    /// Latency probes went unanswered for the stall threshold, with
+    /// `reconnect_on_stall` set.
+    Stalled,
+    /// The machine woke from sleep and the connection didn't answer a probe
+    /// afterwards.
+    ResumedStale,
+    /// The outbound proxy rejected our credentials or refused the connection.
+    ProxyError,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ConnectionStatusPayload {
     pub connected: bool,
+    /// Human-readable status for display only.
     pub message: String,
+    pub reason: StatusReason,
+    /// Underlying error text, when there is one.
+    pub detail: Option<String>,
+    /// Connection attempt number for retry/failure statuses.
+    pub attempt: Option<u32>,
+    /// Name of the connection profile in use, if any.
+    pub profile: Option<String>,
+    /// Address of the socket's remote end (the proxy, when using one), on
+    /// `connected` statuses.
+    pub peer: Option<String>,
+    /// Which connection task this status came from; see
+    /// `ConnectionShared::generation`. Statuses from an older generation than
+    /// one already seen are stale and should be ignored.
+    pub generation: u64,
+    /// The session this status is for.
+    pub session: String,
+}
+
+impl ConnectionStatusPayload {
+    pub fn new(reason: StatusReason, message: impl Into<String>) -> Self {
+        Self {
+            connected: reason == StatusReason::Connected,
+            message: message.into(),
+            reason,
+            detail: None,
+            attempt: None,
+            profile: None,
+            peer: None,
+            generation: 0,
+            session: String::new(),
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_attempt(mut self, attempt: u32) -> Self {
+        self.attempt = Some(attempt);
+        self
+    }
+
+    pub fn with_peer(mut self, peer: Option<SocketAddr>) -> Self {
+        self.peer = peer.map(|peer| peer.to_string());
+        self
+    }
+}
+
+/// The connection status as of the last status event, from
+/// `get_connection_status`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CurrentStatus {
+    pub connected: bool,
+    pub message: String,
+    /// `None` until the first connection attempt.
+    pub reason: Option<StatusReason>,
+    pub detail: Option<String>,
+    pub attempt: Option<u32>,
+    pub profile: Option<String>,
+    /// Remote address while connected.
+    pub peer: Option<String>,
+    /// Unix millis when the current connection was established.
+    pub connected_since: Option<u64>,
+    /// Generation of the status this was taken from.
+    pub generation: u64,
+    /// Connected, but a latency probe went unanswered and nothing has
+    /// arrived since.
+    pub stalled: bool,
+}
+
+impl Default for CurrentStatus {
+    fn default() -> Self {
+        Self {
+            connected: false,
+            message: "Disconnected".to_string(),
+            reason: None,
+            detail: None,
+            attempt: None,
+            profile: None,
+            peer: None,
+            connected_since: None,
+            generation: 0,
+            stalled: false,
+        }
+    }
+}
+
+impl CurrentStatus {
+    /// Take on a status that was just emitted, unless it is from an older
+    /// generation than the last one. Returns whether it was taken.
+    pub fn update(&mut self, status: &ConnectionStatusPayload) -> bool {
+        if status.generation < self.generation {
+            return false;
+        }
+        self.generation = status.generation;
+        self.connected_since = match (status.connected, self.connected_since) {
+            (true, Some(since)) => Some(since),
+            (true, None) => Some(now_ms()),
+            (false, _) => None,
+        };
+        self.connected = status.connected;
+        self.message = status.message.clone();
+        self.reason = Some(status.reason);
+        self.detail = status.detail.clone();
+        self.attempt = status.attempt;
+        self.profile = status.profile.clone();
+        self.peer = if status.connected {
+            status.peer.clone()
+        } else {
+            None
+        };
+        self.stalled = false;
+        true
+    }
+
+    /// One word for the state: connected, connecting or disconnected.
+    pub fn label(&self) -> &'static str {
+        match (self.connected, self.reason) {
+            (true, _) => "connected",
+            (false, Some(StatusReason::Connecting)) => "connecting",
+            (false, _) => "disconnected",
+        }
+    }
+}
+
+/// Emitted when commands queued while disconnected are handed to a freshly
+/// established connection.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CommandQueueFlushedPayload {
+    pub count: usize,
+}
+
+/// A command exactly as written to the socket (without the trailing CRLF),
+/// emitted after the write succeeds. `marker` is the configured prefix for
+/// transcripts and session logs.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InputEchoPayload {
+    pub command: String,
+    pub marker: String,
+    pub session: String,
+    /// Sent by anti-idle rather than typed.
+    pub synthetic: bool,
+    /// Connection generation; see `MudOutputPayload::generation`.
+    pub generation: u64,
+}
+
+/// A script failed to load or a hook call failed. `hook` is the hook's name,
+/// `load` for compiling and the top level, or `timer`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScriptErrorPayload {
+    pub script: String,
+    pub hook: String,
+    pub message: String,
+}
+
+/// Anti-idle sent its last command in a row and stopped.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct IdleLimitPayload {
+    pub session: String,
+    pub sent: u32,
+    /// Connection generation; see `MudOutputPayload::generation`.
+    pub generation: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchState {
+    Sending,
+    Completed,
+    Cancelled,
+    /// Dropped by a manual disconnect.
+    Discarded,
+    /// The connection was lost; the rest went to the offline command queue.
+    Interrupted,
+}
+
+/// Progress of a `send_commands` batch, emitted after every command written.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BatchProgressPayload {
+    pub sent: usize,
+    pub total: usize,
+    pub state: BatchState,
+}
+
+/// Emitted when the rate limiter's backlog grows past `threshold`, and again
+/// with `depth: 0` once it has drained.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CommandBacklogPayload {
+    pub depth: usize,
+    pub threshold: usize,
+}
+
+/// Lines the backend removed from `mud:output` (gag rules or gagging
+/// triggers), emitted just before the output they were cut from so session
+/// logs can still record them.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GaggedLinesPayload {
+    pub lines: Vec<String>,
+    /// Connection generation; see `MudOutputPayload::generation`.
+    pub generation: u64,
+}
+
+/// A backend trigger matched a line. `sound` is for the frontend to play,
+/// unless `played_natively`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TriggerFiredPayload {
+    pub id: String,
+    pub name: Option<String>,
+    /// The matched line, ANSI-stripped.
+    pub line: String,
+    /// `$0` followed by the regex groups.
+    pub captures: Vec<String>,
+    pub sound: Option<String>,
+    /// The backend already played `sound` (rules with `nativeSound`), so the
+    /// frontend shouldn't.
+    pub played_natively: bool,
+    pub gagged: bool,
+}
+
+/// A backend timer came due. `sent` is false if its command couldn't be
+/// handed to the connection.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimerFiredPayload {
+    pub name: String,
+    pub command: String,
+    pub sent: bool,
+}
+
+/// Whether the backend session log is recording, for a recording indicator.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionLogStatusPayload {
+    pub active: bool,
+    /// The file being written; set once the first output has been logged.
+    pub path: Option<String>,
+    /// Unix millis.
+    pub started_at: Option<u64>,
+    pub bytes_written: u64,
+}
+
+/// A data file was changed by something other than the app.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StorageFileChangedPayload {
+    pub filename: String,
+    /// `created`, `modified` or `removed`.
+    pub kind: String,
+}
+
+/// A file was added to a backup being created.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BackupProgressPayload {
+    /// 1-based: the file just added.
+    pub index: usize,
+    pub total: usize,
+    /// Uncompressed bytes added so far.
+    pub bytes: u64,
+}
+
+/// A scheduled backup was attempted.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AutoBackupPayload {
+    pub tag: String,
+    /// The backup written; `None` on failure or when there was nothing to back up.
+    pub filename: Option<String>,
+    pub error: Option<String>,
+}
+
+/// `migrate_data_dir` switched to a new data folder.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DataDirMigratedPayload {
+    pub from: String,
+    pub to: String,
+    /// Files copied.
+    pub files: usize,
+    /// Whether the originals were deleted.
+    pub moved: bool,
+}
+
+/// Another instance holds the data dir, so this one is read-only there.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DataDirLockedPayload {
+    pub dir: String,
+    /// The other instance, from the lock file, when it could be read.
+    pub pid: Option<u32>,
+    pub started_at: Option<String>,
+}
+
+/// Free space on the data dir's volume fell below the configured floor.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LowDiskPayload {
+    pub dir: String,
+    pub available_bytes: u64,
+    pub floor_bytes: u64,
+}
+
+/// A command forwarded from a second launch with `--send`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ForwardedCommand {
+    pub command: String,
+    /// Held for the next connection rather than sent.
+    pub queued: bool,
+}
+
+/// DartForge was launched again; its arguments were handled here.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SecondInstancePayload {
+    /// The arguments, without the program name.
+    pub args: Vec<String>,
+    /// The profile connected with via `--profile`.
+    pub profile: Option<String>,
+    /// Commands delivered via `--send`.
+    pub sent: Vec<ForwardedCommand>,
+    /// Arguments that couldn't be parsed or acted on.
+    pub errors: Vec<String>,
+}
+
+/// A telnet command seen while telnet debugging is on. Byte fields are
+/// space-separated hex.
+#[derive(Clone, Serialize)]
+pub struct TelnetDebugPayload {
+    /// `in` from the server, `out` to it.
+    pub direction: &'static str,
+    /// e.g. `WILL`, `SB`, `GA`.
+    pub command: &'static str,
+    pub option: Option<u8>,
+    pub option_name: Option<&'static str>,
+    pub bytes: String,
+    /// The reply generated for it, for commands from the server.
+    pub response: Option<String>,
+    /// Unix millis.
+    pub at: u64,
+}
+
+/// Snapshot of connection traffic counters. Timestamps are Unix millis.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ConnectionStatsPayload {
+    pub connected: bool,
+    pub connected_at: Option<u64>,
+    pub last_read_at: Option<u64>,
+    pub uptime_secs: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub lines: u64,
+    pub total_bytes_read: u64,
+    pub total_bytes_written: u64,
+    pub total_lines: u64,
+    /// Round-trip times of recent latency probes, in ms.
+    pub latency_last_ms: Option<u64>,
+    pub latency_min_ms: Option<u64>,
+    pub latency_avg_ms: Option<u64>,
+    /// Probes answered, up to the window size the min and avg cover.
+    pub latency_samples: usize,
+    /// A latency probe went unanswered; cleared once output arrives.
+    pub stalled: bool,
+    /// Socket options in effect on the current connection.
+    pub socket: Option<SocketOptionsPayload>,
+}
+
+/// Socket options as read back from a connected socket; the kernel may round
+/// or cap the buffer sizes asked for.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SocketOptionsPayload {
+    pub nodelay: bool,
+    pub recv_buffer: Option<usize>,
+    pub send_buffer: Option<usize>,
+    /// Bytes taken per read from the socket.
+    pub read_buffer: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::connection::ConnectionShared;
+
+    fn status(reason: StatusReason, generation: u64) -> ConnectionStatusPayload {
+        let mut status = ConnectionStatusPayload::new(reason, format!("{reason:?}"));
+        status.generation = generation;
+        status
+    }
+
+    fn record(shared: &ConnectionShared, status: ConnectionStatusPayload) -> bool {
+        shared
+            .status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .update(&status)
+    }
+
+    #[test]
+    fn stale_generation_is_ignored() {
+        let mut current = CurrentStatus::default();
+        assert!(current.update(&status(StatusReason::Connected, 2)));
+        assert!(!current.update(&status(StatusReason::UserDisconnect, 1)));
+        assert!(current.connected);
+        assert_eq!(current.generation, 2);
+        assert!(current.update(&status(StatusReason::UserDisconnect, 2)));
+        assert_eq!(current.label(), "disconnected");
+    }
+
+    #[test]
+    fn generation_is_part_of_the_wire_format() {
+        let output = MudOutputPayload {
+            data: "hi".to_string(),
+            ga: false,
+            session: "default".to_string(),
+            buffered: false,
+            generation: 7,
+        };
+        let gagged = GaggedLinesPayload {
+            lines: vec!["spam".to_string()],
+            generation: 7,
+        };
+        let mut current = CurrentStatus::default();
+        current.update(&status(StatusReason::Connected, 7));
+        for value in [
+            serde_json::to_value(&output).unwrap(),
+            serde_json::to_value(&gagged).unwrap(),
+            serde_json::to_value(status(StatusReason::Connected, 7)).unwrap(),
+            // What get_connection_status returns
+            serde_json::to_value(&current).unwrap(),
+        ] {
+            assert_eq!(value["generation"], 7, "{value}");
+        }
+    }
+
+    /// Each reconnect starts a new generation while the tasks it replaced are
+    /// still winding down and emitting; whatever order their statuses land
+    /// in, the last generation's is the one that sticks.
+    #[tokio::test]
+    async fn rapid_reconnects_settle_on_the_last_generation() {
+        const RECONNECTS: u64 = 100;
+        let shared = Arc::new(ConnectionShared::new(crate::connection::DEFAULT_SESSION));
+        let mut tasks = Vec::new();
+        for i in 0..RECONNECTS {
+            let generation = shared.next_generation();
+            let shared = shared.clone();
+            let last = i + 1 == RECONNECTS;
+            tasks.push(tokio::spawn(async move {
+                let pause = || async {
+                    for _ in 0..(generation * 7 % 5) {
+                        tokio::task::yield_now().await;
+                    }
+                };
+                record(&shared, status(StatusReason::Connecting, generation));
+                pause().await;
+                record(&shared, status(StatusReason::Connected, generation));
+                pause().await;
+                // Replaced tasks report their own disconnect, late
+                for _ in 0..(RECONNECTS - i) {
+                    tokio::task::yield_now().await;
+                }
+                if !last {
+                    let taken = record(&shared, status(StatusReason::UserDisconnect, generation));
+                    assert!(!taken, "generation {generation}'s disconnect was taken");
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(shared.generation(), RECONNECTS);
+        let current = shared.status.lock().unwrap_or_else(|e| e.into_inner());
+        assert_eq!(current.generation, RECONNECTS);
+        assert_eq!(current.label(), "connected");
+        assert_eq!(current.reason, Some(StatusReason::Connected));
+    }
 }
@@ -0,0 +1,165 @@
+//! Gag rules: lines matching one of the patterns in `filters.json` are
+//! removed from `mud:output` before it reaches the frontend. Gagged lines are
+//! still reported (as `mud:gagged-lines`) so the session log keeps them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{info, warn};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::lines;
+
+pub const FILTERS_FILE: &str = "filters.json";
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GagRule {
+    /// Regex matched against the ANSI-stripped line.
+    pub pattern: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Case-insensitive unless set to false.
+    #[serde(default)]
+    pub ignore_case: Option<bool>,
+}
+
+/// Contents of filters.json. Gag rules are keyed by id.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FiltersFile {
+    pub gags: BTreeMap<String, GagRule>,
+}
+
+impl FiltersFile {
+    pub fn load(dir: &Path) -> Self {
+        crate::storage::read_json_file(dir, FILTERS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        crate::storage::write_json_file(dir, FILTERS_FILE, self)
+    }
+}
+
+#[derive(Serialize)]
+pub struct GagStats {
+    pub id: String,
+    pub name: Option<String>,
+    pub enabled: bool,
+    pub suppressed: u64,
+}
+
+#[derive(Serialize)]
+pub struct FilterStats {
+    /// Lines suppressed by gag rules since the app started.
+    pub suppressed: u64,
+    pub gags: Vec<GagStats>,
+}
+
+#[derive(Default)]
+struct FiltersInner {
+    /// Enabled rules, in id order.
+    gags: Vec<(String, Regex)>,
+    rules: BTreeMap<String, GagRule>,
+    /// Suppressed-line counts by rule id; kept across reloads.
+    suppressed: HashMap<String, u64>,
+    total: u64,
+}
+
+#[derive(Default)]
+pub struct GagFilters {
+    inner: Mutex<FiltersInner>,
+}
+
+impl GagFilters {
+    fn lock(&self) -> std::sync::MutexGuard<'_, FiltersInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Re-read filters.json. Rules with invalid patterns are skipped.
+    /// Returns how many gag rules are enabled.
+    pub fn reload(&self, dir: &Path) -> usize {
+        let file = FiltersFile::load(dir);
+        let gags: Vec<(String, Regex)> = file
+            .gags
+            .iter()
+            .filter(|(_, rule)| rule.enabled)
+            .filter_map(|(id, rule)| {
+                RegexBuilder::new(&rule.pattern)
+                    .case_insensitive(rule.ignore_case.unwrap_or(true))
+                    .size_limit(REGEX_SIZE_LIMIT)
+                    .build()
+                    .map_err(|e| warn!("Skipping gag {id}: invalid pattern: {e}"))
+                    .ok()
+                    .map(|regex| (id.clone(), regex))
+            })
+            .collect();
+
+        let count = gags.len();
+        let mut inner = self.lock();
+        inner.gags = gags;
+        inner.rules = file.gags;
+        info!("Loaded {count} gag rule(s)");
+        count
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.lock().gags.is_empty()
+    }
+
+    /// Whether a line should be gagged, counting it if so.
+    pub fn gag(&self, line: &str) -> bool {
+        let mut inner = self.lock();
+        if inner.gags.is_empty() {
+            return false;
+        }
+        let text = lines::match_text(line);
+        let Some(id) = inner
+            .gags
+            .iter()
+            .find(|(_, regex)| regex.is_match(&text))
+            .map(|(id, _)| id.clone())
+        else {
+            return false;
+        };
+        *inner.suppressed.entry(id).or_default() += 1;
+        inner.total += 1;
+        true
+    }
+
+    pub fn stats(&self) -> FilterStats {
+        let inner = self.lock();
+        FilterStats {
+            suppressed: inner.total,
+            gags: inner
+                .rules
+                .iter()
+                .map(|(id, rule)| GagStats {
+                    id: id.clone(),
+                    name: rule.name.clone(),
+                    enabled: rule.enabled,
+                    suppressed: inner.suppressed.get(id).copied().unwrap_or(0),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Persist a gag rule's enabled flag.
+pub fn set_enabled(dir: &Path, id: &str, enabled: bool) -> Result<(), String> {
+    let mut file = FiltersFile::load(dir);
+    let rule = file
+        .gags
+        .get_mut(id)
+        .ok_or_else(|| format!("No gag rule with id {id}"))?;
+    rule.enabled = enabled;
+    file.save(dir)
+}
@@ -0,0 +1,366 @@
+//! Command forwarder: sits between the command channel fed by the Tauri
+//! commands and the socket writer. Single commands pass straight through;
+//! batches from `send_commands` are written one at a time with a fixed
+//! spacing so pasted sequences don't trip the MUD's flood protection. An
+//! optional token-bucket limiter caps the overall command rate, holding
+//! commands (never dropping them) while the bucket is empty.
+
+use std::collections::VecDeque;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::connection::{sleep_until_opt, Outgoing};
+use crate::events::{
+    self, BatchProgressPayload, BatchState, CommandBacklogPayload, BATCH_PROGRESS_EVENT,
+    COMMAND_BACKLOG_EVENT,
+};
+
+/// Commands held by the rate limiter before `mud:command-backlog` warns.
+pub const BACKLOG_WARN_THRESHOLD: usize = 20;
+
+/// A request on the live connection's command channel.
+pub enum CommandRequest {
+    Send(String),
+    /// Bytes written verbatim (no CRLF, not rate limited), for protocol work.
+    Raw(Vec<u8>),
+    /// Write `commands` in order, `delay` apart. With `interleave`, single
+    /// commands sent meanwhile go out immediately; otherwise they wait until
+    /// the batch finishes.
+    Batch {
+        commands: Vec<String>,
+        delay: Duration,
+        interleave: bool,
+    },
+    /// Drop whatever is left of the running batch.
+    CancelBatch,
+}
+
+/// Outbound command rate limit: a sustained `per_second` with bursts of up to
+/// `burst` commands.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub per_second: f32,
+    pub burst: u32,
+}
+
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// None for a rate that can't limit anything (zero, negative or not a
+    /// number), which a hand-edited settings file can hold; that means no
+    /// limit rather than a bucket that never refills.
+    fn new(limit: RateLimit) -> Option<Self> {
+        if !(limit.per_second.is_finite() && limit.per_second > 0.0) {
+            warn!(
+                "Ignoring command rate limit of {} per second",
+                limit.per_second
+            );
+            return None;
+        }
+        let capacity = f64::from(limit.burst.max(1));
+        Some(Self {
+            rate: f64::from(limit.per_second),
+            capacity,
+            tokens: capacity,
+            refilled_at: Instant::now(),
+        })
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    fn try_take(&mut self) -> bool {
+        self.refill(Instant::now());
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// When the next whole token will be available.
+    fn next_token_at(&self) -> Instant {
+        let missing = (1.0 - self.tokens).max(0.0);
+        self.refilled_at + Duration::from_secs_f64(missing / self.rate)
+    }
+}
+
+/// Commands ready to be written, drained as fast as the limiter allows.
+struct Outbox {
+    ready: VecDeque<String>,
+    bucket: Option<TokenBucket>,
+    /// True while above the backlog threshold, so the warning fires once per
+    /// crossing rather than on every command.
+    backlogged: bool,
+}
+
+impl Outbox {
+    fn new(limit: Option<RateLimit>) -> Self {
+        Self {
+            ready: VecDeque::new(),
+            bucket: limit.and_then(TokenBucket::new),
+            backlogged: false,
+        }
+    }
+
+    /// Write as many ready commands as the limiter allows. Returns false once
+    /// the writer is gone; the command that failed stays queued.
+    async fn flush<R: Runtime>(
+        &mut self,
+        app: &AppHandle<R>,
+        write_tx: &mpsc::Sender<Outgoing>,
+    ) -> bool {
+        while let Some(cmd) = self.ready.front() {
+            if let Some(bucket) = &mut self.bucket {
+                if !bucket.try_take() {
+                    break;
+                }
+            }
+            if write_tx.send(Outgoing::Command(cmd.clone())).await.is_err() {
+                return false;
+            }
+            self.ready.pop_front();
+        }
+        self.report_backlog(app);
+        true
+    }
+
+    fn report_backlog<R: Runtime>(&mut self, app: &AppHandle<R>) {
+        let depth = self.ready.len();
+        if depth > BACKLOG_WARN_THRESHOLD && !self.backlogged {
+            self.backlogged = true;
+            warn!("Rate limiter is holding {depth} command(s)");
+            let _ = events::emit(
+                app,
+                COMMAND_BACKLOG_EVENT,
+                CommandBacklogPayload {
+                    depth,
+                    threshold: BACKLOG_WARN_THRESHOLD,
+                },
+            );
+        } else if depth == 0 && self.backlogged {
+            self.backlogged = false;
+            let _ = events::emit(
+                app,
+                COMMAND_BACKLOG_EVENT,
+                CommandBacklogPayload {
+                    depth,
+                    threshold: BACKLOG_WARN_THRESHOLD,
+                },
+            );
+        }
+    }
+
+    /// When the limiter will next let a held command through.
+    fn due(&self) -> Option<Instant> {
+        match (&self.bucket, self.ready.is_empty()) {
+            (Some(bucket), false) => Some(bucket.next_token_at()),
+            _ => None,
+        }
+    }
+}
+
+struct Batch {
+    remaining: VecDeque<String>,
+    delay: Duration,
+    interleave: bool,
+    sent: usize,
+    total: usize,
+}
+
+fn emit_progress<R: Runtime>(app: &AppHandle<R>, batch: &Batch, state: BatchState) {
+    let _ = events::emit(
+        app,
+        BATCH_PROGRESS_EVENT,
+        BatchProgressPayload {
+            sent: batch.sent,
+            total: batch.total,
+            state,
+        },
+    );
+}
+
+/// Forward requests from `rx` to the writer until the channel closes or
+/// `stop` fires.
+///
+/// A closed channel means a user disconnect: the running batch is discarded
+/// but single commands held behind it still go out before the quit command.
+/// `stop` means the socket is gone: nothing more is written, and every command
+/// that didn't make it out is returned so it can be re-queued.
+pub async fn run<R: Runtime>(
+    app: AppHandle<R>,
+    mut rx: mpsc::Receiver<CommandRequest>,
+    write_tx: mpsc::Sender<Outgoing>,
+    rate_limit: Option<RateLimit>,
+    stop: CancellationToken,
+) -> Vec<String> {
+    let mut outbox = Outbox::new(rate_limit);
+    let mut batch: Option<Batch> = None;
+    // Single commands waiting for a non-interleaved batch to finish
+    let mut held: VecDeque<String> = VecDeque::new();
+    let mut next_due: Option<Instant> = None;
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = stop.cancelled() => break,
+            request = rx.recv() => match request {
+                None => {
+                    if let Some(b) = batch.take() {
+                        info!("Discarding batch at {}/{} on disconnect", b.sent, b.total);
+                        emit_progress(&app, &b, BatchState::Discarded);
+                    }
+                    outbox.ready.extend(held.drain(..));
+                    // Drain at the limited rate; the caller bounds how long this may take
+                    while outbox.flush(&app, &write_tx).await {
+                        match outbox.due() {
+                            Some(due) => tokio::time::sleep_until(due).await,
+                            None => break,
+                        }
+                    }
+                    return Vec::new();
+                }
+                Some(CommandRequest::Raw(data)) => {
+                    if write_tx.send(Outgoing::Raw(data)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(CommandRequest::Send(cmd)) => match &batch {
+                    Some(b) if !b.interleave => held.push_back(cmd),
+                    _ => outbox.ready.push_back(cmd),
+                },
+                Some(CommandRequest::Batch { commands, delay, interleave }) => {
+                    if commands.is_empty() {
+                        continue;
+                    }
+                    match &mut batch {
+                        // Another paste while one is running: append to it
+                        Some(b) => {
+                            b.total += commands.len();
+                            b.remaining.extend(commands);
+                            emit_progress(&app, b, BatchState::Sending);
+                        }
+                        None => {
+                            let b = Batch {
+                                total: commands.len(),
+                                remaining: commands.into(),
+                                delay,
+                                interleave,
+                                sent: 0,
+                            };
+                            info!("Starting batch of {} command(s), {}ms apart", b.total, delay.as_millis());
+                            emit_progress(&app, &b, BatchState::Sending);
+                            batch = Some(b);
+                            next_due = Some(Instant::now());
+                        }
+                    }
+                }
+                Some(CommandRequest::CancelBatch) => {
+                    if let Some(b) = batch.take() {
+                        info!("Batch cancelled at {}/{}", b.sent, b.total);
+                        emit_progress(&app, &b, BatchState::Cancelled);
+                        next_due = None;
+                        outbox.ready.extend(held.drain(..));
+                    }
+                }
+            },
+            _ = sleep_until_opt(next_due) => {
+                let Some(b) = batch.as_mut() else {
+                    next_due = None;
+                    continue;
+                };
+                if let Some(cmd) = b.remaining.pop_front() {
+                    outbox.ready.push_back(cmd);
+                    b.sent += 1;
+                }
+                if b.remaining.is_empty() {
+                    emit_progress(&app, b, BatchState::Completed);
+                    batch = None;
+                    next_due = None;
+                    outbox.ready.extend(held.drain(..));
+                } else {
+                    emit_progress(&app, b, BatchState::Sending);
+                    next_due = Some(Instant::now() + b.delay);
+                }
+            }
+            _ = sleep_until_opt(outbox.due()) => {}
+        }
+
+        if !outbox.flush(&app, &write_tx).await {
+            break;
+        }
+    }
+
+    // The connection dropped: hand back everything that wasn't written
+    let mut unsent: Vec<String> = outbox.ready.into();
+    if let Some(b) = batch {
+        info!("Connection lost mid-batch at {}/{}", b.sent, b.total);
+        emit_progress(&app, &b, BatchState::Interrupted);
+        unsent.extend(b.remaining);
+    }
+    unsent.extend(held);
+    while let Ok(request) = rx.try_recv() {
+        match request {
+            CommandRequest::Send(cmd) => unsent.push(cmd),
+            CommandRequest::Batch { commands, .. } => unsent.extend(commands),
+            CommandRequest::Raw(_) | CommandRequest::CancelBatch => {}
+        }
+    }
+    unsent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unusable_rates_mean_no_limit() {
+        for per_second in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(
+                TokenBucket::new(RateLimit {
+                    per_second,
+                    burst: 1
+                })
+                .is_none(),
+                "{per_second}"
+            );
+        }
+    }
+
+    #[test]
+    fn bucket_refills_at_its_rate() {
+        let mut bucket = TokenBucket::new(RateLimit {
+            per_second: 2.0,
+            burst: 2,
+        })
+        .unwrap();
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+        let wait = bucket
+            .next_token_at()
+            .saturating_duration_since(bucket.refilled_at);
+        assert!(
+            wait <= Duration::from_millis(500) && wait > Duration::from_millis(400),
+            "{wait:?}"
+        );
+        bucket.refill(bucket.refilled_at + Duration::from_secs(1));
+        assert!(bucket.try_take());
+    }
+}
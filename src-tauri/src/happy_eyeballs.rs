@@ -0,0 +1,114 @@
+//! Happy-Eyeballs style connection racing (RFC 8305, simplified). Attempts are
+//! staggered rather than sequential: if the first address hasn't connected
+//! within a short delay the next one starts in parallel, and the first to
+//! succeed wins. An unreachable AAAA record therefore costs ~300ms instead of
+//! a full connect timeout.
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+
+use log::{info, warn};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tokio::time::{sleep_until, timeout, Duration, Instant};
+
+/// How long an attempt gets before the next address is started alongside it.
+pub const ATTEMPT_DELAY: Duration = Duration::from_millis(300);
+
+/// Why a single address failed.
+pub enum AttemptError {
+    Io(io::Error),
+    TimedOut,
+}
+
+/// Order addresses so the families alternate, starting with whichever family
+/// the resolver listed first. Relative order within a family is kept.
+pub fn interleave(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return Vec::new();
+    };
+    let (mut preferred, mut other): (VecDeque<_>, VecDeque<_>) = addrs
+        .iter()
+        .copied()
+        .partition(|a| a.is_ipv6() == first.is_ipv6());
+
+    let mut ordered = Vec::with_capacity(addrs.len());
+    while !preferred.is_empty() || !other.is_empty() {
+        ordered.extend(preferred.pop_front());
+        ordered.extend(other.pop_front());
+    }
+    ordered
+}
+
+/// Race connections to `addrs`, starting a new attempt every `ATTEMPT_DELAY`
+/// (or immediately when one fails). Returns the first stream to connect and
+/// its peer address; the losing attempts are aborted. If every address fails,
+/// returns each failure in the order it happened.
+pub async fn race(
+    addrs: &[SocketAddr],
+    attempt_timeout: Duration,
+) -> Result<(TcpStream, SocketAddr), Vec<(SocketAddr, AttemptError)>> {
+    let mut pending: VecDeque<SocketAddr> = interleave(addrs).into();
+    let mut in_flight = JoinSet::new();
+    let mut failures = Vec::new();
+    let mut next_start = Instant::now();
+
+    loop {
+        if in_flight.is_empty() {
+            // Nothing to wait on — start the next address right away
+            let Some(addr) = pending.pop_front() else {
+                break;
+            };
+            start(&mut in_flight, addr, attempt_timeout);
+            next_start = Instant::now() + ATTEMPT_DELAY;
+        }
+
+        tokio::select! {
+            Some(joined) = in_flight.join_next() => {
+                let Ok((addr, result)) = joined else {
+                    continue;
+                };
+                match result {
+                    Ok(Ok(stream)) => {
+                        in_flight.abort_all();
+                        return Ok((stream, addr));
+                    }
+                    Ok(Err(e)) => {
+                        warn!("Failed to connect to {addr}: {e}");
+                        failures.push((addr, AttemptError::Io(e)));
+                    }
+                    Err(_) => {
+                        warn!("Connection to {addr} timed out after {}s", attempt_timeout.as_secs());
+                        failures.push((addr, AttemptError::TimedOut));
+                    }
+                }
+                // A failure frees the slot: don't make the next address wait out the delay
+                next_start = Instant::now();
+            }
+            _ = sleep_until(next_start), if !pending.is_empty() => {
+                if let Some(addr) = pending.pop_front() {
+                    start(&mut in_flight, addr, attempt_timeout);
+                }
+                next_start = Instant::now() + ATTEMPT_DELAY;
+            }
+        }
+    }
+
+    Err(failures)
+}
+
+type AttemptResult = (
+    SocketAddr,
+    Result<io::Result<TcpStream>, tokio::time::error::Elapsed>,
+);
+
+fn start(in_flight: &mut JoinSet<AttemptResult>, addr: SocketAddr, attempt_timeout: Duration) {
+    info!("Starting connection attempt to {addr}");
+    in_flight.spawn(async move {
+        (
+            addr,
+            timeout(attempt_timeout, TcpStream::connect(addr)).await,
+        )
+    });
+}
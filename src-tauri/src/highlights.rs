@@ -0,0 +1,365 @@
+//! Highlight rules: text matching one of the patterns in `highlights.json` is
+//! wrapped in the rule's colors before output reaches the frontend, so every
+//! client (and scrollback) sees them. After each match the server's own
+//! rendition is restored, tracked across chunks by `Highlighter`.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use log::{info, warn};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::ansi::{self, SgrState};
+use crate::lines::MAX_MATCH_LEN;
+
+pub const HIGHLIGHTS_FILE: &str = "highlights.json";
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+/// Enabled rules beyond this many are not loaded.
+const MAX_RULES: usize = 100;
+/// Matches colored per line, across all rules.
+const MAX_MATCHES_PER_LINE: usize = 32;
+/// Matching a line stops after this long; later rules don't get a turn.
+const LINE_BUDGET: Duration = Duration::from_millis(2);
+/// An escape sequence cut off at the end of a chunk is only carried over to
+/// the next one up to this length.
+const MAX_PENDING_ESCAPE: usize = 256;
+
+fn default_true() -> bool {
+    true
+}
+
+/// A color: a 256-color index, a name (`red`, `bright_red`, ...), or `#rrggbb`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Color {
+    Index(u8),
+    Name(String),
+}
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl Color {
+    /// SGR parameters selecting this color as foreground or background.
+    fn sgr(&self, background: bool) -> Result<String, String> {
+        let (base, extended) = if background { (40, 48) } else { (30, 38) };
+        let name = match self {
+            Color::Index(index) => return Ok(format!("{extended};5;{index}")),
+            Color::Name(name) => name.trim().to_lowercase(),
+        };
+        if let Some(hex) = name.strip_prefix('#') {
+            let channel = |i: usize| {
+                hex.get(i..i + 2)
+                    .and_then(|c| u8::from_str_radix(c, 16).ok())
+            };
+            return match (hex.len(), channel(0), channel(2), channel(4)) {
+                (6, Some(r), Some(g), Some(b)) => Ok(format!("{extended};2;{r};{g};{b}")),
+                _ => Err(format!("Invalid color {name}")),
+            };
+        }
+        let (name, offset) = match name.strip_prefix("bright_") {
+            Some(rest) => (rest, 60),
+            None => (name.as_str(), 0),
+        };
+        COLOR_NAMES
+            .iter()
+            .position(|&n| n == name)
+            .map(|i| (base + offset + i).to_string())
+            .ok_or_else(|| format!("Unknown color {name}"))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighlightStyle {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+}
+
+impl HighlightStyle {
+    /// The escape sequence that switches this style on.
+    fn sgr(&self) -> Result<String, String> {
+        let mut params = Vec::new();
+        if self.bold {
+            params.push("1".to_string());
+        }
+        if let Some(fg) = &self.fg {
+            params.push(fg.sgr(false)?);
+        }
+        if let Some(bg) = &self.bg {
+            params.push(bg.sgr(true)?);
+        }
+        if params.is_empty() {
+            return Err("Style sets no color or attribute".to_string());
+        }
+        Ok(format!("\x1b[{}m", params.join(";")))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighlightRule {
+    /// Regex matched against the ANSI-stripped line.
+    pub pattern: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Case-insensitive unless set to false.
+    #[serde(default)]
+    pub ignore_case: Option<bool>,
+    #[serde(flatten)]
+    pub style: HighlightStyle,
+}
+
+/// Contents of highlights.json. Rules are keyed by id and tried in id order;
+/// where two rules match the same text, the first wins.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighlightsFile {
+    pub highlights: BTreeMap<String, HighlightRule>,
+}
+
+impl HighlightsFile {
+    pub fn load(dir: &Path) -> Self {
+        crate::storage::read_json_file(dir, HIGHLIGHTS_FILE).unwrap_or_default()
+    }
+}
+
+struct CompiledHighlight {
+    regex: Regex,
+    open: String,
+}
+
+fn compile(
+    pattern: &str,
+    style: &HighlightStyle,
+    ignore_case: Option<bool>,
+) -> Result<CompiledHighlight, String> {
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case.unwrap_or(true))
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {e}"))?;
+    Ok(CompiledHighlight {
+        regex,
+        open: style.sgr()?,
+    })
+}
+
+/// Per-connection position in the display stream: the server's current
+/// rendition and any escape sequence split across chunks.
+#[derive(Default)]
+pub struct Highlighter {
+    sgr: SgrState,
+    pending_escape: String,
+}
+
+enum Piece {
+    Text(Range<usize>),
+    Escape(Range<usize>),
+}
+
+impl Highlighter {
+    /// Color the matches of `rules` in a display chunk.
+    fn annotate(&mut self, rules: &[CompiledHighlight], text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        // Finish the escape sequence the last chunk ended in
+        if !self.pending_escape.is_empty() {
+            let mut seq = std::mem::take(&mut self.pending_escape);
+            let carried = seq.len();
+            seq.push_str(rest);
+            match ansi::escape_len(&seq) {
+                Some(len) => {
+                    self.sgr.apply(&seq[..len]);
+                    out.push_str(&rest[..len - carried]);
+                    rest = &rest[len - carried..];
+                }
+                None => {
+                    if seq.len() <= MAX_PENDING_ESCAPE {
+                        self.pending_escape = seq;
+                    }
+                    out.push_str(rest);
+                    return out;
+                }
+            }
+        }
+        for line in rest.split_inclusive('\n') {
+            self.annotate_line(rules, line, &mut out);
+        }
+        out
+    }
+
+    fn annotate_line(&mut self, rules: &[CompiledHighlight], line: &str, out: &mut String) {
+        // Split into text and escapes; `plain` is the text the rules see
+        let mut pieces = Vec::new();
+        let mut plain = String::with_capacity(line.len());
+        let mut i = 0;
+        while i < line.len() {
+            if line.as_bytes()[i] == 0x1b {
+                let len = match ansi::escape_len(&line[i..]) {
+                    Some(len) => len,
+                    None => {
+                        if !line.ends_with('\n') && line.len() - i <= MAX_PENDING_ESCAPE {
+                            self.pending_escape = line[i..].to_string();
+                        }
+                        line.len() - i
+                    }
+                };
+                pieces.push(Piece::Escape(i..i + len));
+                i += len;
+            } else {
+                let end = line[i..].find('\x1b').map_or(line.len(), |pos| i + pos);
+                pieces.push(Piece::Text(i..end));
+                plain.push_str(&line[i..end]);
+                i = end;
+            }
+        }
+
+        let spans = find_spans(rules, &plain);
+        let mut spans = spans.iter().peekable();
+        let mut active: Option<(usize, &str)> = None;
+        let mut offset = 0;
+        for piece in pieces {
+            match piece {
+                Piece::Escape(range) => {
+                    out.push_str(&line[range.clone()]);
+                    self.sgr.apply(&line[range]);
+                    // Server colors inside a match don't end the highlight
+                    if let Some((_, open)) = active {
+                        out.push_str(open);
+                    }
+                }
+                Piece::Text(range) => {
+                    let text = &line[range];
+                    let mut pos = 0;
+                    while pos < text.len() {
+                        if let Some((end, _)) = active {
+                            let stop = (end - offset).min(text.len());
+                            out.push_str(&text[pos..stop]);
+                            pos = stop;
+                            if offset + pos == end {
+                                out.push_str(&self.sgr.restore());
+                                active = None;
+                            }
+                            continue;
+                        }
+                        match spans.peek() {
+                            Some(&&(start, end, rule)) if start < offset + text.len() => {
+                                out.push_str(&text[pos..start - offset]);
+                                pos = start - offset;
+                                out.push_str(&rules[rule].open);
+                                active = Some((end, &rules[rule].open));
+                                spans.next();
+                            }
+                            _ => {
+                                out.push_str(&text[pos..]);
+                                pos = text.len();
+                            }
+                        }
+                    }
+                    offset += text.len();
+                }
+            }
+        }
+    }
+}
+
+/// Non-overlapping `(start, end, rule)` matches in `plain`, in order.
+fn find_spans(rules: &[CompiledHighlight], plain: &str) -> Vec<(usize, usize, usize)> {
+    let mut spans: Vec<(usize, usize, usize)> = Vec::new();
+    if rules.is_empty() {
+        return spans;
+    }
+    let body = plain.trim_end_matches(['\r', '\n']);
+    let trimmed = body.trim_start_matches('\r');
+    let lead = body.len() - trimmed.len();
+    let mut cut = trimmed.len().min(MAX_MATCH_LEN);
+    while !trimmed.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    let text = &trimmed[..cut];
+    if text.is_empty() {
+        return spans;
+    }
+
+    let started = Instant::now();
+    'rules: for (index, rule) in rules.iter().enumerate() {
+        if started.elapsed() > LINE_BUDGET {
+            warn!(
+                "Highlight matching exceeded {}ms; skipping the remaining rules for this line",
+                LINE_BUDGET.as_millis()
+            );
+            break;
+        }
+        for m in rule.regex.find_iter(text) {
+            if spans.len() >= MAX_MATCHES_PER_LINE {
+                break 'rules;
+            }
+            let (start, end) = (lead + m.start(), lead + m.end());
+            if start == end || spans.iter().any(|&(s, e, _)| start < e && s < end) {
+                continue;
+            }
+            spans.push((start, end, index));
+        }
+    }
+    spans.sort_unstable_by_key(|&(start, _, _)| start);
+    spans
+}
+
+#[derive(Default)]
+pub struct HighlightRules {
+    rules: Mutex<Vec<CompiledHighlight>>,
+}
+
+impl HighlightRules {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<CompiledHighlight>> {
+        self.rules.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Re-read highlights.json. Rules with invalid patterns or styles are
+    /// skipped. Returns how many rules are enabled.
+    pub fn reload(&self, dir: &Path) -> usize {
+        let file = HighlightsFile::load(dir);
+        let mut rules = Vec::new();
+        for (id, rule) in file.highlights.iter().filter(|(_, rule)| rule.enabled) {
+            if rules.len() == MAX_RULES {
+                warn!("Only the first {MAX_RULES} highlight rules are used; skipping {id} and later rules");
+                break;
+            }
+            match compile(&rule.pattern, &rule.style, rule.ignore_case) {
+                Ok(compiled) => rules.push(compiled),
+                Err(e) => warn!("Skipping highlight {id}: {e}"),
+            }
+        }
+
+        let count = rules.len();
+        *self.lock() = rules;
+        info!("Loaded {count} highlight rule(s)");
+        count
+    }
+
+    /// Color a display chunk. Chunks must all pass through here, in order,
+    /// even with no rules loaded, so the server's rendition stays tracked.
+    pub fn apply(&self, highlighter: &mut Highlighter, text: &str) -> String {
+        highlighter.annotate(&self.lock(), text)
+    }
+}
+
+/// `sample` as a rule with `pattern` and `style` would show it.
+pub fn test(
+    pattern: &str,
+    sample: &str,
+    style: &HighlightStyle,
+    ignore_case: Option<bool>,
+) -> Result<String, String> {
+    let rule = compile(pattern, style, ignore_case)?;
+    Ok(Highlighter::default().annotate(&[rule], sample))
+}
@@ -0,0 +1,228 @@
+//! Persistent command history. Commands sent through `send_command` are
+//! recorded in memory and appended to `command-history.jsonl` by a
+//! background flush, so recording never waits on the disk.
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::time::Duration;
+
+use crate::connection::ConnectionShared;
+use crate::events;
+
+pub const HISTORY_FILE: &str = "command-history.jsonl";
+pub const DEFAULT_MAX_ENTRIES: usize = 10_000;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub command: String,
+    /// Unix millis.
+    pub timestamp: u64,
+    pub profile: Option<String>,
+}
+
+struct HistoryInner {
+    /// Oldest first.
+    entries: VecDeque<HistoryEntry>,
+    /// How many of the newest entries haven't been written yet.
+    unsaved: usize,
+    /// Lines in the file, including ones trimmed from memory since.
+    on_disk: usize,
+    max_entries: usize,
+}
+
+impl Default for HistoryInner {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            unsaved: 0,
+            on_disk: 0,
+            max_entries: DEFAULT_MAX_ENTRIES,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CommandHistory {
+    inner: Mutex<HistoryInner>,
+    /// Serializes file writes, so `clear` can't race a flush.
+    io: Mutex<()>,
+}
+
+impl CommandHistory {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HistoryInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_io(&self) -> std::sync::MutexGuard<'_, ()> {
+        self.io.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Read the saved history, keeping the newest `max_entries`. Unreadable
+    /// lines are skipped.
+    pub fn load(&self, dir: &Path) {
+        let _io = self.lock_io();
+        let mut entries = VecDeque::new();
+        let mut on_disk = 0;
+        if let Ok(file) = fs::File::open(dir.join(HISTORY_FILE)) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                on_disk += 1;
+                if let Ok(entry) = serde_json::from_str::<HistoryEntry>(&line) {
+                    entries.push_back(entry);
+                }
+            }
+        }
+        let mut inner = self.lock();
+        while entries.len() > inner.max_entries {
+            entries.pop_front();
+        }
+        info!("Loaded {} command history entries", entries.len());
+        inner.entries = entries;
+        inner.unsaved = 0;
+        inner.on_disk = on_disk;
+    }
+
+    pub fn set_max_entries(&self, max_entries: usize) {
+        let mut inner = self.lock();
+        inner.max_entries = max_entries;
+        while inner.entries.len() > max_entries {
+            inner.entries.pop_front();
+        }
+        inner.unsaved = inner.unsaved.min(inner.entries.len());
+    }
+
+    /// Record a sent command. Repeats of the previous command are skipped.
+    pub fn record(&self, command: &str, profile: Option<String>) {
+        if command.trim().is_empty() {
+            return;
+        }
+        let mut inner = self.lock();
+        if inner
+            .entries
+            .back()
+            .is_some_and(|last| last.command == command)
+        {
+            return;
+        }
+        inner.entries.push_back(HistoryEntry {
+            command: command.to_string(),
+            timestamp: events::now_ms(),
+            profile,
+        });
+        inner.unsaved += 1;
+        if inner.entries.len() > inner.max_entries {
+            inner.entries.pop_front();
+            inner.unsaved = inner.unsaved.min(inner.entries.len());
+        }
+    }
+
+    /// Up to `limit` entries, newest first, skipping the newest `offset`.
+    pub fn page(&self, limit: usize, offset: usize) -> Vec<HistoryEntry> {
+        self.lock()
+            .entries
+            .iter()
+            .rev()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Up to `limit` entries containing `query` (case-insensitive), newest
+    /// first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<HistoryEntry> {
+        let query = query.to_lowercase();
+        self.lock()
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.command.to_lowercase().contains(&query))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self, dir: &Path) -> Result<(), String> {
+        let _io = self.lock_io();
+        {
+            let mut inner = self.lock();
+            inner.entries.clear();
+            inner.unsaved = 0;
+            inner.on_disk = 0;
+        }
+        crate::data_lock::check_writable(dir)?;
+        match fs::remove_file(dir.join(HISTORY_FILE)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to clear history: {e}"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Write out unsaved entries. Once the file holds well over the cap it
+    /// is rewritten with just the entries kept in memory, dropping the oldest.
+    pub fn flush(&self, dir: &Path) -> Result<(), String> {
+        let _io = self.lock_io();
+        let (entries, rewrite) = {
+            let mut inner = self.lock();
+            if inner.unsaved == 0 {
+                return Ok(());
+            }
+            let rewrite =
+                inner.on_disk + inner.unsaved > inner.max_entries + inner.max_entries / 10;
+            let skip = if rewrite {
+                0
+            } else {
+                inner.entries.len() - inner.unsaved
+            };
+            let entries: Vec<HistoryEntry> = inner.entries.iter().skip(skip).cloned().collect();
+            inner.on_disk = if rewrite {
+                entries.len()
+            } else {
+                inner.on_disk + entries.len()
+            };
+            inner.unsaved = 0;
+            (entries, rewrite)
+        };
+
+        let mut data = String::new();
+        for entry in &entries {
+            data.push_str(&serde_json::to_string(entry).map_err(|e| e.to_string())?);
+            data.push('\n');
+        }
+        crate::data_lock::check_writable(dir)?;
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create data dir: {e}"))?;
+        let path = dir.join(HISTORY_FILE);
+        if rewrite {
+            crate::storage::atomic_write(&path, data.as_bytes(), false)
+        } else {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(data.as_bytes()))
+                .map_err(|e| format!("Failed to append history: {e}"))
+        }
+    }
+}
+
+/// Flush new history entries every `FLUSH_INTERVAL`, for the life of the app.
+pub async fn run(app: AppHandle, shared: Arc<ConnectionShared>) {
+    let mut tick = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        tick.tick().await;
+        let dir = app.state::<crate::storage::StorageState>().get_dir();
+        let history = shared.clone();
+        let result = tokio::task::spawn_blocking(move || history.history.flush(&dir)).await;
+        if let Ok(Err(e)) = result {
+            warn!("{e}");
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Export of ANSI-colored text (a log file or a string from the frontend) as
+//! a self-contained HTML page. SGR sequences become inline-styled `<span>`s;
+//! every other escape sequence is dropped. The input is converted in fixed
+//! size chunks, so a huge log or a single enormous line never has to fit in
+//! memory.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::ansi::{self, SgrState};
+
+const CHUNK_BYTES: usize = 64 * 1024;
+/// An escape sequence cut off at the end of a chunk is only carried over to
+/// the next one up to this length.
+const MAX_PENDING_ESCAPE: usize = 256;
+const DEFAULT_FG: &str = "#c0c0c0";
+const DEFAULT_BG: &str = "#000000";
+
+/// The xterm palette for the 16 basic colors.
+const BASIC_COLORS: [&str; 16] = [
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+];
+
+fn palette(index: u8) -> String {
+    match index {
+        0..=15 => BASIC_COLORS[index as usize].to_string(),
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let i = index - 16;
+            format!(
+                "#{:02x}{:02x}{:02x}",
+                level(i / 36),
+                level(i / 6 % 6),
+                level(i % 6)
+            )
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            format!("#{gray:02x}{gray:02x}{gray:02x}")
+        }
+    }
+}
+
+/// CSS color for SGR color parameters as kept by `SgrState`: `31`, `91`,
+/// `38;5;n`, `38;2;r;g;b`, or their colon forms.
+fn css_color(params: &str) -> Option<String> {
+    let parts: Vec<&str> = params.split([';', ':']).collect();
+    let code: u8 = parts.first()?.parse().ok()?;
+    match code {
+        30..=37 | 40..=47 => Some(palette(code % 10)),
+        90..=97 | 100..=107 => Some(palette(code % 10 + 8)),
+        38 | 48 => match parts.get(1).copied()? {
+            "5" => Some(palette(parts.get(2)?.parse().ok()?)),
+            // 38:2::r:g:b carries an (empty) color space id before r
+            "2" if parts.len() >= 5 => {
+                let rgb: Vec<u8> = parts[parts.len() - 3..]
+                    .iter()
+                    .filter_map(|p| p.parse().ok())
+                    .collect();
+                (rgb.len() == 3).then(|| format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Inline style for a rendition, or `None` when it's the default one.
+fn css(sgr: &SgrState) -> Option<String> {
+    let mut fg = sgr.fg().and_then(css_color);
+    let mut bg = sgr.bg().and_then(css_color);
+    if sgr.has_attr(7) {
+        let (inverse_fg, inverse_bg) = (
+            bg.unwrap_or_else(|| DEFAULT_BG.into()),
+            fg.unwrap_or_else(|| DEFAULT_FG.into()),
+        );
+        fg = Some(inverse_fg);
+        bg = Some(inverse_bg);
+    }
+    let mut style = String::new();
+    if let Some(fg) = fg {
+        style.push_str(&format!("color:{fg};"));
+    }
+    if let Some(bg) = bg {
+        style.push_str(&format!("background-color:{bg};"));
+    }
+    if sgr.has_attr(1) {
+        style.push_str("font-weight:bold;");
+    }
+    if sgr.has_attr(2) {
+        style.push_str("opacity:0.7;");
+    }
+    if sgr.has_attr(3) {
+        style.push_str("font-style:italic;");
+    }
+    let decorations: Vec<&str> = [(4, "underline"), (9, "line-through")]
+        .into_iter()
+        .filter(|&(attr, _)| sgr.has_attr(attr))
+        .map(|(_, name)| name)
+        .collect();
+    if !decorations.is_empty() {
+        style.push_str(&format!("text-decoration:{};", decorations.join(" ")));
+    }
+    if sgr.has_attr(8) {
+        style.push_str("visibility:hidden;");
+    }
+    (!style.is_empty()).then_some(style)
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' | '\t' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+}
+
+/// Streaming ANSI-to-HTML conversion. Spans never cross a line break: each
+/// line closes its span and the next reopens it, so the rendition carries on
+/// as in a terminal while every line stays balanced markup.
+struct HtmlWriter<W: Write> {
+    out: W,
+    sgr: SgrState,
+    style: Option<String>,
+    span_open: bool,
+    pending_escape: String,
+    /// Bytes of a UTF-8 character split across chunks.
+    partial_char: Vec<u8>,
+}
+
+impl<W: Write> HtmlWriter<W> {
+    fn new(mut out: W, title: &str) -> io::Result<Self> {
+        let mut head = String::new();
+        escape_html(title, &mut head);
+        write!(
+            out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{head}</title>\n<style>\n\
+             body {{ margin: 0; background: {DEFAULT_BG}; color: {DEFAULT_FG}; }}\n\
+             pre {{ margin: 0; padding: 1em; font-family: Menlo, Consolas, \"DejaVu Sans Mono\", monospace; \
+             font-size: 13px; white-space: pre-wrap; word-wrap: break-word; }}\n\
+             </style>\n</head>\n<body>\n<pre>"
+        )?;
+        Ok(Self {
+            out,
+            sgr: SgrState::default(),
+            style: None,
+            span_open: false,
+            pending_escape: String::new(),
+            partial_char: Vec::new(),
+        })
+    }
+
+    fn close_span(&mut self, html: &mut String) {
+        if self.span_open {
+            html.push_str("</span>");
+            self.span_open = false;
+        }
+    }
+
+    fn text(&mut self, text: &str, html: &mut String) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.close_span(html);
+                html.push('\n');
+            }
+            if line.trim_matches('\r').is_empty() {
+                continue;
+            }
+            if !self.span_open {
+                if let Some(style) = &self.style {
+                    html.push_str(&format!("<span style=\"{style}\">"));
+                    self.span_open = true;
+                }
+            }
+            escape_html(line, html);
+        }
+    }
+
+    fn feed_str(&mut self, chunk: &str) -> io::Result<()> {
+        let text = std::mem::take(&mut self.pending_escape) + chunk;
+        let mut html = String::with_capacity(text.len() + text.len() / 4);
+        let mut rest = text.as_str();
+        while let Some(pos) = rest.find('\x1b') {
+            let plain = &rest[..pos];
+            self.text(plain, &mut html);
+            let Some(len) = ansi::escape_len(&rest[pos..]) else {
+                if rest.len() - pos <= MAX_PENDING_ESCAPE {
+                    self.pending_escape = rest[pos..].to_string();
+                }
+                rest = "";
+                break;
+            };
+            let before = self.sgr.clone();
+            self.sgr.apply(&rest[pos..pos + len]);
+            if self.sgr != before {
+                self.close_span(&mut html);
+                self.style = css(&self.sgr);
+            }
+            rest = &rest[pos + len..];
+        }
+        self.text(rest, &mut html);
+        self.out.write_all(html.as_bytes())
+    }
+
+    fn feed(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let mut data = std::mem::take(&mut self.partial_char);
+        data.extend_from_slice(bytes);
+        let valid = match std::str::from_utf8(&data) {
+            Ok(_) => data.len(),
+            // Hold back a character cut off at the end of the chunk
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => data.len(),
+        };
+        self.partial_char = data[valid..].to_vec();
+        self.feed_str(&String::from_utf8_lossy(&data[..valid]))
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        let partial = std::mem::take(&mut self.partial_char);
+        self.feed_str(&String::from_utf8_lossy(&partial))?;
+        let mut html = String::new();
+        self.close_span(&mut html);
+        html.push_str("</pre>\n</body>\n</html>\n");
+        self.out.write_all(html.as_bytes())?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Convert everything `input` yields and write the page to `output`.
+/// Returns the number of bytes written. A failed export leaves no file.
+fn export(mut input: impl Read, output: &Path, title: &str) -> Result<u64, String> {
+    let file =
+        File::create(output).map_err(|e| format!("Cannot write {}: {e}", output.display()))?;
+    let result = (|| {
+        let mut writer = HtmlWriter::new(BufWriter::new(file), title)?;
+        let mut buf = vec![0u8; CHUNK_BYTES];
+        loop {
+            let n = input.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            writer.feed(&buf[..n])?;
+        }
+        let file = writer.finish()?.into_inner().map_err(|e| e.into_error())?;
+        Ok::<u64, io::Error>(file.metadata()?.len())
+    })();
+    result.map_err(|e| {
+        let _ = fs::remove_file(output);
+        format!("Failed to export HTML: {e}")
+    })
+}
+
+/// Export a log file (gzipped rotated logs included) to `output`.
+pub fn export_log(path: &Path, output: &Path) -> Result<u64, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open log: {e}"))?;
+    let title = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if crate::storage::is_gzip(path) {
+        export(
+            flate2::read::GzDecoder::new(BufReader::new(file)),
+            output,
+            &title,
+        )
+    } else {
+        export(BufReader::new(file), output, &title)
+    }
+}
+
+/// Export `text` (e.g. a scrollback selection) to `output`.
+pub fn export_text(text: &str, output: &Path, title: &str) -> Result<u64, String> {
+    export(text.as_bytes(), output, title)
+}
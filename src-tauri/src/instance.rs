@@ -0,0 +1,76 @@
+//! A second launch while DartForge is running hands its arguments to the
+//! running instance and exits, so two processes never share the data dir.
+//! The running instance brings its window forward and acts on them:
+//! `--profile` connects with that profile, then each `--send` command goes
+//! through the same path as `send_command`. The outcome is reported to the
+//! frontend as `app:second-instance`.
+
+use log::{info, warn};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::cli;
+use crate::events::{ForwardedCommand, SecondInstancePayload, SECOND_INSTANCE_EVENT};
+
+/// Single-instance plugin callback, run in this process for the new launch.
+pub fn on_second_instance(app: &AppHandle, argv: Vec<String>, _cwd: String) {
+    info!(
+        "DartForge launched again with {} argument(s)",
+        argv.len().saturating_sub(1)
+    );
+    crate::tray::show_window(app);
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move { handle(&app, argv).await });
+}
+
+async fn handle(app: &AppHandle, argv: Vec<String>) {
+    let mut payload = SecondInstancePayload {
+        args: argv.iter().skip(1).cloned().collect(),
+        profile: None,
+        sent: Vec::new(),
+        errors: Vec::new(),
+    };
+    match cli::parse(argv) {
+        Ok(args) => {
+            if !args.unknown.is_empty() {
+                info!("Ignoring forwarded arguments: {:?}", args.unknown);
+            }
+            for flag in args.startup_only_flags() {
+                payload
+                    .errors
+                    .push(format!("{flag} only applies when DartForge starts"));
+            }
+            let state = app.state::<crate::ConnectionState>();
+            if let Some(name) = args.profile {
+                let companion = app.state::<crate::CompanionState>();
+                let storage = app.state::<crate::storage::StorageState>();
+                match crate::connect_with_profile(
+                    app,
+                    &state.default_session(),
+                    &companion,
+                    &storage,
+                    &name,
+                )
+                .await
+                {
+                    Ok(()) => payload.profile = Some(name),
+                    Err(e) => payload.errors.push(format!("--profile {name}: {e}")),
+                }
+            }
+            // After the profile, so these go to (or queue for) its connection
+            for command in args.send {
+                match crate::send_input(&state.shared, &command).await {
+                    Ok(result) => payload.sent.push(ForwardedCommand {
+                        command,
+                        queued: result.queued,
+                    }),
+                    Err(e) => payload.errors.push(format!("--send {command}: {e}")),
+                }
+            }
+        }
+        Err(e) => payload.errors.push(e),
+    }
+    for error in &payload.errors {
+        warn!("Forwarded arguments: {error}");
+    }
+    let _ = app.emit(SECOND_INSTANCE_EVENT, payload);
+}
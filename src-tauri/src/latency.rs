@@ -0,0 +1,157 @@
+//! Round-trip latency probing. Every interval the connection sends a probe
+//! and times the reply. By default the probe is `IAC DO TIMING-MARK`, which
+//! the server answers with WILL or WONT TIMING-MARK after everything sent
+//! before it, so no ordinary output can be taken for the reply. With a probe
+//! command configured, that is written instead and the reply is the next
+//! prompt (GA); the sample is thrown away if anything else was written in
+//! the meantime, since the prompt could be answering that.
+//!
+//! A probe only counts towards stalls once the server has answered one. If
+//! it never does, while still sending other output, the server is taken not
+//! to support the probe and probing stops. After any answered probe, one
+//! left unanswered for the stall threshold flags the connection stalled until
+//! output arrives again.
+
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::time::Instant;
+
+use crate::connection::Outgoing;
+use crate::stats::ConnectionStats;
+
+pub const DEFAULT_INTERVAL_SECS: u64 = 30;
+pub const DEFAULT_STALL_SECS: u64 = 20;
+/// Probes sent with nothing at all coming back before giving up on a server
+/// that has never answered one.
+const MAX_UNCONFIRMED: u32 = 3;
+
+struct Pending {
+    sent_at: Instant,
+    /// Bytes read and written on the connection before the probe was queued.
+    read_before: u64,
+    written_before: u64,
+}
+
+/// What the read loop should do at `LatencyProbe::deadline`.
+pub enum Tick {
+    /// Write this probe.
+    Send(Outgoing),
+    /// The probe went unanswered; the connection is now stalled.
+    Stalled,
+    Idle,
+}
+
+pub struct LatencyProbe {
+    every: Duration,
+    stall_after: Duration,
+    /// The command written as the probe, with its line ending, instead of
+    /// TIMING-MARK.
+    command: Option<Vec<u8>>,
+    /// The server has answered a probe.
+    confirmed: bool,
+    unanswered: u32,
+    /// Probing gave up on this connection.
+    stopped: bool,
+    next_at: Instant,
+    pending: Option<Pending>,
+    stalled: bool,
+}
+
+impl LatencyProbe {
+    pub fn new(every: Duration, stall_after: Duration, command: Option<Vec<u8>>) -> Self {
+        Self {
+            every,
+            stall_after,
+            command,
+            confirmed: false,
+            unanswered: 0,
+            stopped: false,
+            next_at: Instant::now() + every,
+            pending: None,
+            stalled: false,
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.stopped || self.stalled {
+            return None;
+        }
+        Some(match &self.pending {
+            Some(pending) => pending.sent_at + self.stall_after,
+            None => self.next_at,
+        })
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+
+    pub fn on_deadline(&mut self, stats: &ConnectionStats) -> Tick {
+        let now = Instant::now();
+        let (read, written) = stats.bytes();
+        let Some(pending) = self.pending.take() else {
+            self.pending = Some(Pending {
+                sent_at: now,
+                read_before: read,
+                written_before: written,
+            });
+            return Tick::Send(self.command.clone().map_or(Outgoing::Ping, Outgoing::Raw));
+        };
+        self.next_at = now + self.every;
+        if self.confirmed {
+            warn!(
+                "No reply to latency probe within {}s; connection stalled",
+                self.stall_after.as_secs()
+            );
+            self.stalled = true;
+            return Tick::Stalled;
+        }
+        self.unanswered += 1;
+        if read > pending.read_before || self.unanswered >= MAX_UNCONFIRMED {
+            info!("Server does not answer latency probes; stopping them for this connection");
+            self.stopped = true;
+        }
+        Tick::Idle
+    }
+
+    /// Take note of a read, returning the round-trip time if it answered the
+    /// outstanding probe. `timing_mark` and `ga` are from the read's
+    /// telnet processing.
+    pub fn on_read(
+        &mut self,
+        timing_mark: bool,
+        ga: bool,
+        stats: &ConnectionStats,
+    ) -> Option<Duration> {
+        self.stalled = false;
+        let pending = self.pending.as_ref()?;
+        match &self.command {
+            None if !timing_mark => return None,
+            None => {}
+            Some(command) => {
+                if !ga {
+                    return None;
+                }
+                let (_, written) = stats.bytes();
+                let probe_written = pending.written_before + command.len() as u64;
+                if written < probe_written {
+                    // A prompt from before the probe went out
+                    return None;
+                }
+                if written > probe_written {
+                    // Something else was written too; the prompt may be its
+                    self.pending = None;
+                    self.next_at = Instant::now() + self.every;
+                    return None;
+                }
+            }
+        }
+        let rtt = pending.sent_at.elapsed();
+        self.next_at = pending.sent_at + self.every;
+        self.pending = None;
+        self.confirmed = true;
+        self.unanswered = 0;
+        Some(rtt)
+    }
+}
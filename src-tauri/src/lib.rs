@@ -1,111 +1,1787 @@
+mod aliases;
 mod ansi;
+mod anti_idle;
+mod app_log;
+mod audio_probe;
+mod auto_backup;
+mod autologin;
+mod backup_crypto;
+mod bundle;
+mod capture;
+mod cli;
+mod client_import;
+mod comms;
 mod companion;
 mod connection;
+mod data_lock;
+mod diagnostics;
+mod disk_space;
 mod events;
+mod filters;
+mod forwarder;
+mod happy_eyeballs;
+mod highlights;
+mod history;
+mod html_export;
+mod instance;
+mod latency;
+mod lines;
+mod log_rotation;
+mod native_audio;
+mod notifications;
+mod outbound;
+mod output_pause;
+mod plain_output;
+mod profiles;
+mod rooms;
+mod scripting;
+mod scrollback;
+mod secrets;
+mod session_log;
+mod session_summary;
+mod sleep_watch;
+mod stats;
 mod storage;
+mod storage_watch;
+mod telnet_debug;
+mod timers;
+mod tray;
+mod triggers;
+mod vitals;
+mod window_title;
+mod ws_proxy;
 
-use std::sync::Mutex;
-use tauri::{Emitter, Manager};
-use tokio::sync::{broadcast, mpsc};
+use std::sync::Arc;
+use tauri::Manager;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
 
 use companion::CompanionState;
 
+/// How long a cancelled connection task gets to flush and close its socket
+/// before it is aborted outright: its own shutdown budget, plus time to emit
+/// its final status.
+const TASK_SHUTDOWN_TIMEOUT: std::time::Duration =
+    std::time::Duration::from_secs(connection::SHUTDOWN_BUDGET.as_secs() + 2);
+const _: () = assert!(TASK_SHUTDOWN_TIMEOUT.as_secs() > connection::SHUTDOWN_BUDGET.as_secs());
+
+struct ConnectionTask {
+    cancel: CancellationToken,
+    handle: tauri::async_runtime::JoinHandle<()>,
+}
+
+/// Most sessions open at once, counting the default one.
+const MAX_SESSIONS: usize = 8;
+
+/// `copy_scrollback_to_clipboard` copies at most this much text.
+const MAX_CLIPBOARD_BYTES: usize = 4 * 1024 * 1024;
+
+/// One MUD session: its state and its connection task, if any.
+struct Session {
+    shared: Arc<connection::ConnectionShared>,
+    /// Async mutex so a (re)connect holds it while the old task shuts down,
+    /// serializing concurrent reconnect/disconnect calls.
+    task: tokio::sync::Mutex<Option<ConnectionTask>>,
+}
+
+impl Session {
+    fn new(shared: connection::ConnectionShared) -> Arc<Self> {
+        Arc::new(Self {
+            shared: Arc::new(shared),
+            task: tokio::sync::Mutex::new(None),
+        })
+    }
+}
+
+/// Sessions by id. The default session always exists; `shared` is its state,
+/// which commands without a `session_id` act on. Other sessions are created
+/// by the first (re)connect or target change that names them, and stay until
+/// the app exits.
 struct ConnectionState {
-    cmd_tx: Mutex<Option<mpsc::Sender<String>>>,
-    task_handle: Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+    shared: Arc<connection::ConnectionShared>,
+    sessions: std::sync::Mutex<std::collections::HashMap<String, Arc<Session>>>,
+}
+
+fn validate_session_id(id: &str) -> Result<(), String> {
+    if id.is_empty()
+        || id.len() > 64
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+    {
+        return Err(format!(
+            "Invalid session id {id:?}: use up to 64 letters, digits, '-' or '_'"
+        ));
+    }
+    Ok(())
+}
+
+impl ConnectionState {
+    fn new() -> Self {
+        let default = Session::new(connection::ConnectionShared::new(
+            connection::DEFAULT_SESSION,
+        ));
+        Self {
+            shared: default.shared.clone(),
+            sessions: std::sync::Mutex::new(
+                [(connection::DEFAULT_SESSION.to_string(), default)].into(),
+            ),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, std::collections::HashMap<String, Arc<Session>>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn default_session(&self) -> Arc<Session> {
+        self.lock()[connection::DEFAULT_SESSION].clone()
+    }
+
+    /// The session `id`, or the default one for `None`.
+    fn session(&self, id: Option<&str>) -> Result<Arc<Session>, String> {
+        let id = id.unwrap_or(connection::DEFAULT_SESSION);
+        self.lock()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| format!("No session {id:?}"))
+    }
+
+    /// Like `session`, creating the session if there is none by that id yet.
+    /// A new session starts logging if session logging is on.
+    fn session_or_create(
+        &self,
+        app: &tauri::AppHandle,
+        id: Option<&str>,
+    ) -> Result<Arc<Session>, String> {
+        let id = id.unwrap_or(connection::DEFAULT_SESSION);
+        let mut sessions = self.lock();
+        if let Some(session) = sessions.get(id) {
+            return Ok(session.clone());
+        }
+        validate_session_id(id)?;
+        if sessions.len() >= MAX_SESSIONS {
+            return Err(format!("At most {MAX_SESSIONS} sessions can be open"));
+        }
+        let session = Session::new(self.shared.sibling(id));
+        sessions.insert(id.to_string(), session.clone());
+        drop(sessions);
+        log::info!("Created session {id}");
+        let dir = app.state::<storage::StorageState>().get_dir();
+        let log_settings = session_log::SessionLogSettings::load(&dir);
+        if log_settings.enabled {
+            session
+                .shared
+                .session_log
+                .start(app.clone(), &dir, log_settings.options);
+        }
+        tauri::async_runtime::spawn(session_log::run(session.shared.clone()));
+        Ok(session)
+    }
+
+    /// Every session, by id.
+    fn all(&self) -> Vec<Arc<Session>> {
+        let mut sessions: Vec<_> = self.lock().values().cloned().collect();
+        sessions.sort_by(|a, b| a.shared.session.cmp(&b.shared.session));
+        sessions
+    }
+}
+
+/// Cooperatively stop a running connection task and wait for it to finish.
+/// Falls back to aborting it if it doesn't exit within TASK_SHUTDOWN_TIMEOUT.
+/// Returns whether a task ended on its own, having emitted its final status;
+/// false if there was none or it had to be aborted.
+async fn stop_task(task: &mut Option<ConnectionTask>) -> bool {
+    let Some(ConnectionTask { cancel, mut handle }) = task.take() else {
+        return false;
+    };
+    cancel.cancel();
+    if tokio::time::timeout(TASK_SHUTDOWN_TIMEOUT, &mut handle)
+        .await
+        .is_err()
+    {
+        log::warn!("Connection task did not shut down in time; aborting");
+        handle.abort();
+        // So nothing it was about to emit lands after the status emitted for it
+        let _ = handle.await;
+        return false;
+    }
+    true
 }
 
-fn spawn_connection(
+/// A status emitter for `shared`'s session, at its current connection generation.
+fn status_emitter(
     app: &tauri::AppHandle,
-    state: &ConnectionState,
     companion_state: &CompanionState,
+    shared: &Arc<connection::ConnectionShared>,
+) -> connection::StatusEmitter {
+    connection::StatusEmitter::new(
+        app.clone(),
+        companion_state.broadcast_tx.clone(),
+        companion_state.last_status.clone(),
+        shared.clone(),
+    )
+}
+
+async fn spawn_connection(
+    app: &tauri::AppHandle,
+    session: &Session,
+    companion_state: &CompanionState,
+    storage_state: &storage::StorageState,
     startup_delay: bool,
 ) {
-    // Drop old sender and abort old task
-    {
-        let mut tx = state.cmd_tx.lock().unwrap_or_else(|e| e.into_inner());
-        *tx = None;
-        let mut handle = state.task_handle.lock().unwrap_or_else(|e| e.into_inner());
-        if let Some(h) = handle.take() {
-            h.abort();
-        }
-    }
+    let mut task = session.task.lock().await;
 
-    let (tx, rx) = mpsc::channel::<String>(100);
-    *state.cmd_tx.lock().unwrap_or_else(|e| e.into_inner()) = Some(tx);
+    // Drop old sender and wait for the old task to wind down, so two tasks
+    // never emit interleaved output/status events. The new task gets its own
+    // generation, so anything an old task that had to be aborted still
+    // emits is recognizably stale.
+    session.shared.commands.detach();
+    session.shared.timers.set_connected(false);
+    stop_task(&mut task).await;
+    let generation = session.shared.next_generation();
+    log::debug!(
+        "Starting session {} connection generation {generation}",
+        session.shared.session
+    );
 
+    let shared = session.shared.clone();
     let app_handle = app.clone();
-    let broadcast_tx = companion_state.broadcast_tx.clone();
-    let last_status = companion_state.last_status.clone();
-    let join = tauri::async_runtime::spawn(async move {
+    let mut settings = connection::ConnectionSettings::load(&storage_state.get_dir());
+    let profile = session
+        .shared
+        .active_profile
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    if let Some(profile) = &profile {
+        profile.settings.apply(&mut settings);
+    }
+    let safe_mode = app.state::<cli::Launch>().safe_mode();
+    let login = profile
+        .as_ref()
+        .filter(|_| !safe_mode)
+        .and_then(|p| match p.auto_login() {
+            Ok(login) => login,
+            Err(e) => {
+                log::warn!("Auto-login for profile {} disabled: {e}", p.name);
+                None
+            }
+        });
+    let status =
+        status_emitter(app, companion_state, &session.shared).with_profile(profile.map(|p| p.name));
+    let cancel = CancellationToken::new();
+    let task_cancel = cancel.clone();
+    let handle = tauri::async_runtime::spawn(async move {
         if startup_delay {
             // Brief delay on first launch lets WebView2 finish initialization
-            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+            tokio::select! {
+                _ = tokio::time::sleep(tokio::time::Duration::from_secs(2)) => {}
+                _ = task_cancel.cancelled() => return,
+            }
         }
-        connection::connect(app_handle, rx, broadcast_tx, last_status).await;
+        connection::connect(app_handle, shared, status, settings, login, task_cancel).await;
     });
 
-    *state.task_handle.lock().unwrap_or_else(|e| e.into_inner()) = Some(join);
+    *task = Some(ConnectionTask { cancel, handle });
+}
+
+#[derive(serde::Serialize)]
+struct SendCommandResult {
+    /// True if the command was held for the next connection rather than sent.
+    queued: bool,
 }
 
 #[tauri::command]
 async fn send_command(
     state: tauri::State<'_, ConnectionState>,
     command: String,
-) -> Result<(), String> {
-    let tx = {
-        let guard = state.cmd_tx.lock().map_err(|e| e.to_string())?;
-        guard.clone()
+    session_id: Option<String>,
+) -> Result<SendCommandResult, String> {
+    send_input(&state.session(session_id.as_deref())?.shared, &command).await
+}
+
+/// Record `command` in history, expand aliases and send (or queue) the result.
+async fn send_input(
+    shared: &connection::ConnectionShared,
+    command: &str,
+) -> Result<SendCommandResult, String> {
+    shared.anti_idle.user_input();
+    if !shared
+        .server_echo
+        .load(std::sync::atomic::Ordering::Relaxed)
+    {
+        let profile = shared
+            .active_profile
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(|p| p.name.clone());
+        shared.history.record(command, profile);
+    }
+    let mut queued = false;
+    for command in shared.aliases.expand(command)? {
+        match shared.commands.route(command)? {
+            connection::Routed::Live(tx, command) => {
+                tx.send(forwarder::CommandRequest::Send(command))
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            connection::Routed::Queued => queued = true,
+        }
+    }
+    Ok(SendCommandResult { queued })
+}
+
+/// Hook for data files written through `write_data_file`, so backend state
+/// built from them stays current. The state is reloaded through `session_id`
+/// (the default session for `None`); rules loaded from data files are shared
+/// by every session, so that reaches them all.
+fn data_file_written(
+    app: &tauri::AppHandle,
+    dir: &std::path::Path,
+    filename: &str,
+    session_id: Option<&str>,
+) {
+    let session = match app.state::<ConnectionState>().session(session_id) {
+        Ok(session) => session,
+        Err(e) => {
+            log::warn!("Not reloading {filename}: {e}");
+            return;
+        }
     };
-    if let Some(tx) = tx {
-        tx.send(command).await.map_err(|e| e.to_string())?;
-        Ok(())
+    let shared = &session.shared;
+    if filename == aliases::ALIASES_FILE {
+        if let Err(e) = shared.aliases.reload(dir) {
+            log::warn!("Failed to reload aliases: {e}");
+        }
+    } else if filename == filters::FILTERS_FILE {
+        shared.filters.reload(dir);
+    } else if filename == highlights::HIGHLIGHTS_FILE {
+        shared.highlights.reload(dir);
+    } else if filename == vitals::PROMPT_PARSERS_FILE {
+        shared.prompt_parsers.reload(dir);
+    } else if filename == rooms::ROOM_PARSER_FILE {
+        shared.rooms.reload(dir);
+    } else if filename == comms::CHANNELS_FILE {
+        shared.comms.reload(dir);
+    } else if filename == session_summary::SESSION_COUNTERS_FILE {
+        shared.session_counters.reload(dir);
+    }
+}
+
+/// Send `commands` one at a time, `delay_ms` apart, reporting progress via
+/// `mud:batch-progress`. With `interleave`, commands sent while the batch runs
+/// go out immediately instead of after it. Requires a live connection.
+#[tauri::command]
+async fn send_commands(
+    state: tauri::State<'_, ConnectionState>,
+    commands: Vec<String>,
+    delay_ms: u64,
+    interleave: Option<bool>,
+    session_id: Option<String>,
+) -> Result<(), String> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+    let session = state.session(session_id.as_deref())?;
+    let tx = session.shared.commands.live().ok_or("Not connected")?;
+    tx.send(forwarder::CommandRequest::Batch {
+        commands,
+        delay: std::time::Duration::from_millis(delay_ms),
+        interleave: interleave.unwrap_or(false),
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Stop the running `send_commands` batch; commands not yet written are dropped.
+#[tauri::command]
+async fn cancel_pending_sends(
+    state: tauri::State<'_, ConnectionState>,
+    session_id: Option<String>,
+) -> Result<(), String> {
+    if let Some(tx) = state.session(session_id.as_deref())?.shared.commands.live() {
+        tx.send(forwarder::CommandRequest::CancelBatch)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Write base64-decoded bytes to the socket exactly as given (no CRLF, no
+/// encoding), e.g. a hand-built telnet subnegotiation. Developer mode only.
+#[tauri::command]
+async fn send_raw_bytes(
+    data_base64: String,
+    session_id: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    use base64::Engine as _;
+    if !connection::ConnectionSettings::load(&storage_state.get_dir()).developer_mode {
+        return Err("send_raw_bytes requires developer mode".to_string());
+    }
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(data_base64.trim())
+        .map_err(|e| format!("Invalid base64: {e}"))?;
+    if data.is_empty() {
+        return Ok(());
+    }
+    let session = state.session(session_id.as_deref())?;
+    let tx = session.shared.commands.live().ok_or("Not connected")?;
+    log::info!("Sending {} raw byte(s)", data.len());
+    tx.send(forwarder::CommandRequest::Raw(data))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Recent display text for repopulating the terminal after a reload — all of
+/// it, or only the last `max_bytes`.
+#[tauri::command]
+fn get_scrollback(
+    max_bytes: Option<usize>,
+    session_id: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<String, String> {
+    Ok(state
+        .session(session_id.as_deref())?
+        .shared
+        .scrollback
+        .snapshot(max_bytes))
+}
+
+/// `get_scrollback` as the chunks it was emitted in, each with its `ts_ms`,
+/// for restoring timestamped output.
+#[tauri::command]
+fn get_scrollback_chunks(
+    max_bytes: Option<usize>,
+    session_id: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<Vec<scrollback::ScrollbackChunk>, String> {
+    Ok(state
+        .session(session_id.as_deref())?
+        .shared
+        .scrollback
+        .chunks(max_bytes))
+}
+
+#[tauri::command]
+fn clear_scrollback(
+    session_id: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<(), String> {
+    state
+        .session(session_id.as_deref())?
+        .shared
+        .scrollback
+        .clear();
+    Ok(())
+}
+
+/// Copy the last `max_lines` lines of scrollback (all of it when `None`) to
+/// the clipboard as plain text, each line prefixed with the time it arrived
+/// when `include_timestamps` is set. Lines come out as the server sent them,
+/// not as the terminal wrapped them. At most `MAX_CLIPBOARD_BYTES` is copied,
+/// keeping the newest lines. Returns how many lines were copied.
+#[tauri::command]
+fn copy_scrollback_to_clipboard(
+    app: tauri::AppHandle,
+    max_lines: Option<usize>,
+    include_timestamps: Option<bool>,
+    session_id: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<usize, String> {
+    use tauri_plugin_clipboard_manager::ClipboardExt;
+
+    let lines = state
+        .session(session_id.as_deref())?
+        .shared
+        .scrollback
+        .lines(max_lines);
+    let (text, count) = scrollback::plain_text(
+        &lines,
+        include_timestamps.unwrap_or(false),
+        MAX_CLIPBOARD_BYTES,
+    );
+    app.clipboard()
+        .write_text(text)
+        .map_err(|e| format!("Failed to copy to the clipboard: {e}"))?;
+    Ok(count)
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ScrollbackFormat {
+    Txt,
+    Html,
+}
+
+/// Write the whole scrollback to `path` (absolute): ANSI-stripped text, or an
+/// HTML page keeping the colors. Returns how many lines were written.
+#[tauri::command]
+async fn export_scrollback_to_file(
+    app: tauri::AppHandle,
+    path: String,
+    format: ScrollbackFormat,
+    session_id: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<usize, String> {
+    let lines = state
+        .session(session_id.as_deref())?
+        .shared
+        .scrollback
+        .lines(None);
+    tauri::async_runtime::spawn_blocking(move || {
+        let (extension, filter) = match format {
+            ScrollbackFormat::Txt => ("txt", "Text"),
+            ScrollbackFormat::Html => ("html", "HTML"),
+        };
+        let output = resolve_export_path(&app, Some(path), "", filter, &[extension])?
+            .ok_or("No export path given")?;
+        match format {
+            ScrollbackFormat::Txt => {
+                let (mut text, _) = scrollback::plain_text(&lines, false, usize::MAX);
+                text.push('\n');
+                std::fs::write(&output, text)
+                    .map_err(|e| format!("Failed to export scrollback: {e}"))?;
+            }
+            ScrollbackFormat::Html => {
+                let text: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+                html_export::export_text(&text.join("\n"), &output, "DartForge scrollback")?;
+            }
+        }
+        log::info!(
+            "Exported {} scrollback line(s) to {}",
+            lines.len(),
+            output.display()
+        );
+        Ok(lines.len())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Size the scrollback buffer (`None` for the 1MB default) and choose whether
+/// it survives reconnects. Persisted; the size applies immediately.
+#[tauri::command]
+fn set_scrollback_options(
+    max_bytes: Option<usize>,
+    keep_on_reconnect: bool,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    if max_bytes == Some(0) {
+        return Err("Scrollback size must be greater than zero".to_string());
+    }
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.scrollback_bytes = max_bytes;
+    settings.keep_scrollback_on_reconnect = keep_on_reconnect;
+    settings.save(&dir)?;
+    for session in state.all() {
+        session
+            .shared
+            .scrollback
+            .set_capacity(max_bytes.unwrap_or(scrollback::DEFAULT_SCROLLBACK_BYTES));
+    }
+    Ok(())
+}
+
+/// Tune output coalescing: buffered MUD output is emitted at most
+/// `max_delay_ms` after it arrives (0 disables coalescing) or once `max_bytes`
+/// are buffered. `None` restores each default (16ms / 64KB). Persisted; takes
+/// effect on the next connect.
+#[tauri::command]
+fn set_output_batching(
+    max_delay_ms: Option<u64>,
+    max_bytes: Option<usize>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    if max_delay_ms.is_some_and(|ms| ms > 1000) {
+        return Err("Output delay must be at most 1000ms".to_string());
+    }
+    if max_bytes == Some(0) {
+        return Err("Output batch size must be greater than zero".to_string());
+    }
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.output_max_delay_ms = max_delay_ms;
+    settings.output_max_bytes = max_bytes;
+    settings.save(&dir)
+}
+
+/// Record the raw byte stream (both directions) to `captures/<filename>`.
+/// Returns the capture's path.
+#[tauri::command]
+fn start_raw_capture(
+    filename: String,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<String, String> {
+    let path = state
+        .shared
+        .capture
+        .start(&storage_state.get_dir(), &filename)?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Stop the running capture. Returns its path, or `None` if none was running.
+#[tauri::command]
+fn stop_raw_capture(state: tauri::State<'_, ConnectionState>) -> Result<Option<String>, String> {
+    Ok(state
+        .shared
+        .capture
+        .stop()?
+        .map(|p| p.to_string_lossy().into_owned()))
+}
+
+/// Replay a capture's server output as `mud:output` events, no connection
+/// needed. `speed` scales the recorded timing (default 1.0; 0 = no delays).
+#[tauri::command]
+async fn replay_capture(
+    app: tauri::AppHandle,
+    filename: String,
+    speed: Option<f64>,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<usize, String> {
+    let speed = speed.unwrap_or(1.0);
+    if !speed.is_finite() || speed < 0.0 {
+        return Err("Replay speed must be zero or a positive number".to_string());
+    }
+    let dir = storage_state.get_dir();
+    capture::replay(&app, &dir, &filename, speed, state.shared.generation()).await
+}
+
+/// Emit fake server output for frontend development; nothing is written to
+/// the socket. With `as_raw`, `data` is an escaped byte string (`\x1b[31m`,
+/// `\xff\xf9`) run through the telnet/ANSI processing like real traffic,
+/// including partial IAC sequences carried over between calls.
+#[tauri::command]
+fn simulate_output(
+    app: tauri::AppHandle,
+    data: String,
+    as_raw: bool,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<(), String> {
+    let (data, ga) = if as_raw {
+        let bytes = ansi::unescape_bytes(&data)?;
+        let mut remainder = state
+            .shared
+            .simulated_remainder
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let mut input = std::mem::take(&mut *remainder);
+        input.extend_from_slice(&bytes);
+        let processed = ansi::process_output(&input);
+        *remainder = processed.remainder;
+        if processed.display.is_empty() {
+            return Ok(());
+        }
+        (processed.display, processed.ga)
     } else {
-        Err("Not connected".to_string())
+        (data, false)
+    };
+    let payload = events::MudOutputPayload {
+        data,
+        ga,
+        session: state.shared.session.clone(),
+        buffered: false,
+        generation: state.shared.generation(),
+    };
+    events::emit(&app, events::MUD_OUTPUT_EVENT, payload).map_err(|e| e.to_string())
+}
+
+/// Where to write an export (an HTML page, a settings bundle): `output_path`
+/// if given (it must be absolute and in an existing directory), otherwise
+/// wherever the user picks in a save dialog offering `extensions`. `None` if
+/// the dialog was cancelled. Must not be called on the main thread when the
+/// dialog is needed.
+pub(crate) fn resolve_export_path(
+    app: &tauri::AppHandle,
+    output_path: Option<String>,
+    suggested_name: &str,
+    filter_name: &str,
+    extensions: &[&str],
+) -> Result<Option<std::path::PathBuf>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    if let Some(path) = output_path {
+        let path = std::path::PathBuf::from(path);
+        if !path.is_absolute() {
+            return Err(format!("Export path must be absolute: {}", path.display()));
+        }
+        if path.is_dir() {
+            return Err(format!("Export path is a directory: {}", path.display()));
+        }
+        if !path.parent().is_some_and(std::path::Path::is_dir) {
+            return Err(format!("Export folder does not exist: {}", path.display()));
+        }
+        return Ok(Some(path));
     }
+    let picked = app
+        .dialog()
+        .file()
+        .add_filter(filter_name, extensions)
+        .set_file_name(suggested_name)
+        .blocking_save_file();
+    match picked {
+        Some(path) => path
+            .into_path()
+            .map(Some)
+            .map_err(|e| format!("Invalid export path: {e}")),
+        None => Ok(None),
+    }
+}
+
+/// Export a log from a data-dir subfolder (e.g. `sessions`, `logs/Main`) as a
+/// standalone HTML page with its colors. Writes to `output_path` (absolute)
+/// or asks with a save dialog. Returns the path written, or `None` if the
+/// dialog was cancelled.
+#[tauri::command]
+async fn export_log_html(
+    app: tauri::AppHandle,
+    subdir: String,
+    filename: String,
+    output_path: Option<String>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<Option<String>, String> {
+    storage::validate_filename(&filename)?;
+    let path = storage::log_subdir(&storage_state.get_dir(), &subdir)?.join(&filename);
+    let suggested = format!(
+        "{}.html",
+        filename.trim_end_matches(".gz").trim_end_matches(".txt")
+    );
+    tauri::async_runtime::spawn_blocking(move || {
+        let Some(output) =
+            resolve_export_path(&app, output_path, &suggested, "HTML", &["html", "htm"])?
+        else {
+            return Ok(None);
+        };
+        html_export::export_log(&path, &output)?;
+        Ok(Some(output.to_string_lossy().into_owned()))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Like `export_log_html`, for text supplied by the frontend (such as a
+/// scrollback selection) instead of a file.
+#[tauri::command]
+async fn export_text_html(
+    app: tauri::AppHandle,
+    text: String,
+    title: Option<String>,
+    output_path: Option<String>,
+) -> Result<Option<String>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let Some(output) = resolve_export_path(
+            &app,
+            output_path,
+            "dartforge-excerpt.html",
+            "HTML",
+            &["html", "htm"],
+        )?
+        else {
+            return Ok(None);
+        };
+        html_export::export_text(
+            &text,
+            &output,
+            title.as_deref().unwrap_or("DartForge session"),
+        )?;
+        Ok(Some(output.to_string_lossy().into_owned()))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Current connection traffic counters (same payload as the `mud:stats` event).
+#[tauri::command]
+fn get_connection_stats(
+    session_id: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<events::ConnectionStatsPayload, String> {
+    Ok(state
+        .session(session_id.as_deref())?
+        .shared
+        .stats
+        .snapshot())
+}
+
+/// Activity so far on the session's connection, or `None` while disconnected.
+#[tauri::command]
+fn get_session_summary(
+    session_id: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<Option<events::SessionSummaryPayload>, String> {
+    let shared = &state.session(session_id.as_deref())?.shared;
+    Ok(shared.session_tracker.summary(shared))
+}
+
+/// The connection status as of the last `mud:connection-status` event, for
+/// catching up after a webview reload.
+#[tauri::command]
+fn get_connection_status(
+    session_id: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<events::CurrentStatus, String> {
+    let session = state.session(session_id.as_deref())?;
+    let status = session
+        .shared
+        .status
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    Ok(status)
+}
+
+#[derive(serde::Serialize)]
+struct SessionInfo {
+    id: String,
+    status: events::CurrentStatus,
+}
+
+/// Every session with its current status, the default one included.
+#[tauri::command]
+fn list_sessions(state: tauri::State<'_, ConnectionState>) -> Vec<SessionInfo> {
+    state
+        .all()
+        .into_iter()
+        .map(|session| SessionInfo {
+            id: session.shared.session.clone(),
+            status: session
+                .shared
+                .status
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        })
+        .collect()
+}
+
+/// Hold a session's output instead of emitting it, e.g. while reading
+/// scrollback. The connection keeps reading; see `output_pause`.
+#[tauri::command]
+fn pause_output(
+    session_id: Option<String>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<events::OutputPausedPayload, String> {
+    let session = state.session(session_id.as_deref())?;
+    Ok(session
+        .shared
+        .output_pause
+        .pause(&app, &session.shared.session))
+}
+
+/// Deliver the output held since `pause_output` as one `buffered` event and
+/// stop holding.
+#[tauri::command]
+fn resume_output(
+    session_id: Option<String>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<events::OutputPausedPayload, String> {
+    let session = state.session(session_id.as_deref())?;
+    Ok(session
+        .shared
+        .output_pause
+        .resume(&app, &session.shared.session))
+}
+
+/// Discard commands queued while disconnected. Returns how many were dropped.
+#[tauri::command]
+fn clear_command_queue(
+    session_id: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<usize, String> {
+    Ok(state
+        .session(session_id.as_deref())?
+        .shared
+        .commands
+        .clear())
 }
 
 #[tauri::command]
 async fn reconnect(
+    session_id: Option<String>,
     app: tauri::AppHandle,
     state: tauri::State<'_, ConnectionState>,
     companion_state: tauri::State<'_, CompanionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
 ) -> Result<(), String> {
-    spawn_connection(&app, &state, &companion_state, false);
+    let session = state.session_or_create(&app, session_id.as_deref())?;
+    spawn_connection(&app, &session, &companion_state, &storage_state, false).await;
     Ok(())
 }
 
 #[tauri::command]
 async fn disconnect(
+    session_id: Option<String>,
     app: tauri::AppHandle,
     state: tauri::State<'_, ConnectionState>,
     companion_state: tauri::State<'_, CompanionState>,
 ) -> Result<(), String> {
-    {
-        let mut tx = state.cmd_tx.lock().map_err(|e| e.to_string())?;
-        *tx = None;
+    let session = state.session(session_id.as_deref())?;
+    disconnect_now(&app, &session, &companion_state).await;
+    Ok(())
+}
+
+/// Disconnect every session.
+#[tauri::command]
+async fn disconnect_all(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, ConnectionState>,
+    companion_state: tauri::State<'_, CompanionState>,
+) -> Result<(), String> {
+    let sessions = state.all();
+    futures_util::future::join_all(
+        sessions
+            .iter()
+            .map(|session| disconnect_now(&app, session, &companion_state)),
+    )
+    .await;
+    Ok(())
+}
+
+async fn disconnect_now(
+    app: &tauri::AppHandle,
+    session: &Session,
+    companion_state: &CompanionState,
+) {
+    let mut task = session.task.lock().await;
+    // A user-initiated disconnect must not replay stale commands on the next connect
+    session.shared.commands.detach();
+    session.shared.commands.clear();
+    // A running task emits its own "Disconnected" once it has closed the
+    // socket; an aborted one never got to
+    if stop_task(&mut task).await {
+        return;
     }
-    {
-        let mut handle = state.task_handle.lock().map_err(|e| e.to_string())?;
-        if let Some(h) = handle.take() {
-            h.abort();
+    status_emitter(app, companion_state, &session.shared).emit(
+        events::ConnectionStatusPayload::new(events::StatusReason::UserDisconnect, "Disconnected"),
+    );
+}
+
+/// Set (or clear, with `None`/empty) the command sent to the MUD before a
+/// disconnect or reconnect closes the socket. Takes effect on the next connect.
+#[tauri::command]
+fn set_quit_command(
+    command: Option<String>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.quit_command = command.filter(|c| !c.trim().is_empty());
+    settings.save(&dir)
+}
+
+/// Configure connection keepalives: OS-level TCP keepalive probing after
+/// `tcp_secs` idle seconds, and an application-level keepalive (`IAC NOP`, or
+/// `idle_command` if given) after `telnet_nop_secs` without a write. `None`
+/// disables each. Persisted; takes effect on the next connect.
+#[tauri::command]
+fn set_keepalive(
+    tcp_secs: Option<u64>,
+    telnet_nop_secs: Option<u64>,
+    idle_command: Option<String>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.tcp_keepalive_secs = tcp_secs;
+    settings.telnet_nop_secs = telnet_nop_secs;
+    settings.idle_command = idle_command;
+    settings.save(&dir)
+}
+
+/// Configure anti-idle: after `minutes` (20 when `None`) without a typed
+/// command, send one of `commands`, up to `max_cycles` (6 when `None`) in a
+/// row before stopping with `mud:idle-limit`. Persisted, and applied to live
+/// connections.
+#[tauri::command]
+fn set_anti_idle(
+    enabled: bool,
+    minutes: Option<u64>,
+    commands: Vec<String>,
+    max_cycles: Option<u32>,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let anti_idle = anti_idle::AntiIdleSettings {
+        enabled,
+        minutes,
+        commands,
+        max_cycles,
+    };
+    anti_idle.validate()?;
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.anti_idle = anti_idle.clone();
+    settings.save(&dir)?;
+    for session in state.all() {
+        session.shared.anti_idle.configure(anti_idle.clone());
+    }
+    Ok(())
+}
+
+/// Turn the accessible output stream (`mud:output-plain`) on or off for all
+/// sessions. Persisted, and applied to live connections.
+#[tauri::command]
+fn set_accessible_output(
+    enabled: bool,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.accessible_output = enabled;
+    settings.save(&dir)?;
+    state
+        .shared
+        .accessible_output
+        .store(enabled, std::sync::atomic::Ordering::Relaxed);
+    Ok(())
+}
+
+/// Configure latency probing: a probe every `interval_secs` (30 when `None`,
+/// 0 disables), timed with `IAC DO TIMING-MARK` or with `command` against the
+/// next prompt. The connection is flagged stalled once a probe goes
+/// unanswered for `stall_secs` (20 when `None`), and with
+/// `reconnect_on_stall` it is reconnected instead. Persisted; takes effect on
+/// the next connect.
+#[tauri::command]
+fn set_latency_probe(
+    interval_secs: Option<u64>,
+    command: Option<String>,
+    stall_secs: Option<u64>,
+    reconnect_on_stall: bool,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    if command.as_deref().is_some_and(|c| c.contains(['\r', '\n'])) {
+        return Err("The probe command must be a single line".to_string());
+    }
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.latency_probe_secs = interval_secs;
+    settings.latency_probe_command = command.filter(|c| !c.trim().is_empty());
+    settings.stall_secs = stall_secs;
+    settings.reconnect_on_stall = reconnect_on_stall;
+    settings.save(&dir)
+}
+
+/// Toggle `mud:input-echo` events for sent commands. `marker` prefixes echoed
+/// lines in transcripts (`"> "` when omitted). Persisted and applied to live
+/// connections immediately.
+#[tauri::command]
+fn set_local_echo(
+    enabled: bool,
+    marker: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.local_echo = enabled;
+    if marker.is_some() {
+        settings.echo_marker = marker;
+    }
+    settings.save(&dir)?;
+    for session in state.all() {
+        *session
+            .shared
+            .local_echo
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = settings.echo_marker();
+    }
+    Ok(())
+}
+
+/// Cap outbound commands at `per_second` with bursts of `burst`; commands over
+/// the limit are held, not dropped. `None` removes the limit. Persisted; takes
+/// effect on the next connect.
+#[tauri::command]
+fn set_command_rate_limit(
+    per_second: Option<f32>,
+    burst: Option<u32>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let rate_limit = match per_second {
+        None => None,
+        Some(per_second) if !(per_second.is_finite() && per_second > 0.0) => {
+            return Err("Rate must be a positive number of commands per second".to_string())
         }
+        Some(per_second) => Some(forwarder::RateLimit {
+            per_second,
+            burst: burst.unwrap_or(per_second.ceil() as u32).max(1),
+        }),
+    };
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.rate_limit = rate_limit;
+    settings.save(&dir)
+}
+
+/// Tune the MUD socket: `nodelay` sends short commands without Nagle delay,
+/// `recv_buffer` / `send_buffer` ask for kernel buffer sizes (the OS default
+/// when `None`), and `read_buffer` is how much is taken per read (4096 when
+/// `None`). Persisted; takes effect on the next connect. The values in effect
+/// are reported by `get_connection_stats`.
+#[tauri::command]
+fn set_socket_options(
+    nodelay: bool,
+    recv_buffer: Option<usize>,
+    send_buffer: Option<usize>,
+    read_buffer: Option<usize>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    if let Some(size) = read_buffer {
+        let (min, max) = (connection::MIN_READ_BUF_SIZE, connection::MAX_READ_BUF_SIZE);
+        if !(min..=max).contains(&size) {
+            return Err(format!(
+                "The read buffer must be between {min} and {max} bytes"
+            ));
+        }
+    }
+    if recv_buffer.is_some_and(|size| size == 0) || send_buffer.is_some_and(|size| size == 0) {
+        return Err("Socket buffer sizes must be positive".to_string());
+    }
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.tcp_nodelay = Some(nodelay);
+    settings.recv_buffer_bytes = recv_buffer;
+    settings.send_buffer_bytes = send_buffer;
+    settings.read_buffer_bytes = read_buffer;
+    settings.save(&dir)
+}
+
+/// Toggle developer mode, which unlocks protocol debugging commands. Persisted.
+#[tauri::command]
+fn set_developer_mode(
+    enabled: bool,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.developer_mode = enabled;
+    settings.save(&dir)
+}
+
+/// Point a session's connection at a hostname or literal IP (`None` for
+/// dartmud.com), optionally pinning the hostname to `resolve_override` instead
+/// of using DNS. Not persisted; takes effect on the next connect.
+#[tauri::command]
+fn set_connection_target(
+    host: Option<String>,
+    port: Option<u16>,
+    resolve_override: Option<String>,
+    session_id: Option<String>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<(), String> {
+    let target =
+        connection::ConnectionTarget::parse(host.as_deref(), port, resolve_override.as_deref())?;
+    let session = state.session_or_create(&app, session_id.as_deref())?;
+    log::info!(
+        "Connection target for session {} set to {target:?}",
+        session.shared.session
+    );
+    *session
+        .shared
+        .target
+        .lock()
+        .unwrap_or_else(|e| e.into_inner()) = target;
+    // A hand-picked target no longer matches any profile
+    *session
+        .shared
+        .active_profile
+        .lock()
+        .unwrap_or_else(|e| e.into_inner()) = None;
+    Ok(())
+}
+
+#[tauri::command]
+fn list_profiles(storage_state: tauri::State<'_, storage::StorageState>) -> Vec<profiles::Profile> {
+    profiles::load(&storage_state.get_dir())
+        .into_iter()
+        .map(|(name, settings)| profiles::Profile { name, settings })
+        .collect()
+}
+
+/// Create or replace a profile. Returns the stored (sanitized) name.
+#[tauri::command]
+fn save_profile(
+    name: String,
+    settings: profiles::ProfileSettings,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<String, String> {
+    let name = profiles::sanitize_name(&name)?;
+    settings.validate()?;
+    // A password passed in goes to the keychain, never into profiles.json
+    if let Some(password) = settings.login.as_ref().and_then(|l| l.password.as_deref()) {
+        profiles::set_password(&name, Some(password))?;
+    }
+    let dir = storage_state.get_dir();
+    let mut all = profiles::load(&dir);
+    all.insert(name.clone(), settings);
+    profiles::save(&dir, &all)?;
+    Ok(name)
+}
+
+/// Delete a profile. A connection started from it keeps running.
+#[tauri::command]
+fn delete_profile(
+    name: String,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let name = profiles::sanitize_name(&name)?;
+    let dir = storage_state.get_dir();
+    let mut all = profiles::load(&dir);
+    if all.remove(&name).is_none() {
+        return Err(format!("No profile named {name}"));
     }
-    let _ = app.emit(
-        crate::events::CONNECTION_STATUS_EVENT,
-        crate::events::ConnectionStatusPayload {
-            connected: false,
-            message: "Disconnected".to_string(),
-        },
+    profiles::save(&dir, &all)?;
+    profiles::set_password(&name, None)
+}
+
+/// Store (or with `None`, remove) a profile's login password in the OS keychain.
+#[tauri::command]
+fn set_profile_password(
+    name: String,
+    password: Option<String>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let profile = profiles::get(&storage_state.get_dir(), &name)?;
+    profiles::set_password(&profile.name, password.as_deref().filter(|p| !p.is_empty()))
+}
+
+/// (Re)connect a session using a stored profile's target and settings. Later
+/// reconnects keep using it until another target is chosen.
+#[tauri::command]
+async fn connect_profile(
+    name: String,
+    session_id: Option<String>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, ConnectionState>,
+    companion_state: tauri::State<'_, CompanionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let session = state.session_or_create(&app, session_id.as_deref())?;
+    connect_with_profile(&app, &session, &companion_state, &storage_state, &name).await
+}
+
+/// Make `name` the session's active profile without connecting; the next
+/// (re)connect uses its target and settings.
+fn select_profile(
+    shared: &connection::ConnectionShared,
+    dir: &std::path::Path,
+    name: &str,
+) -> Result<(), String> {
+    let profile = profiles::get(dir, name)?;
+    profile.settings.validate()?;
+    let target = profile.settings.target()?;
+    *shared.target.lock().unwrap_or_else(|e| e.into_inner()) = target;
+    *shared
+        .active_profile
+        .lock()
+        .unwrap_or_else(|e| e.into_inner()) = Some(profile);
+    Ok(())
+}
+
+async fn connect_with_profile(
+    app: &tauri::AppHandle,
+    session: &Session,
+    companion_state: &CompanionState,
+    storage_state: &storage::StorageState,
+    name: &str,
+) -> Result<(), String> {
+    select_profile(&session.shared, &storage_state.get_dir(), name)?;
+    log::info!(
+        "Connecting session {} with profile {name}",
+        session.shared.session
     );
-    let _ = companion_state.broadcast_tx.send(companion::CompanionMessage::ConnectionStatus {
-        connected: false,
-        message: "Disconnected".to_string(),
+    spawn_connection(app, session, companion_state, storage_state, false).await;
+    Ok(())
+}
+
+/// How this launch was started: `--data-dir`, `--profile`, `--safe-mode` etc.
+#[tauri::command]
+fn get_launch_options(launch: tauri::State<'_, cli::Launch>) -> cli::LaunchOptions {
+    launch.options.clone()
+}
+
+/// The frontend is listening for connection events. With `--profile` (and
+/// no `--no-autoconnect`), this is when that profile connects.
+#[tauri::command]
+async fn frontend_ready(
+    app: tauri::AppHandle,
+    launch: tauri::State<'_, cli::Launch>,
+    state: tauri::State<'_, ConnectionState>,
+    companion_state: tauri::State<'_, CompanionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let Some(name) = launch.take_autoconnect() else {
+        return Ok(());
+    };
+    connect_with_profile(
+        &app,
+        &state.default_session(),
+        &companion_state,
+        &storage_state,
+        name,
+    )
+    .await
+}
+
+/// Reload backend triggers from triggers.json — the global rules plus those
+/// of `character`, when given. Returns how many were loaded.
+#[tauri::command]
+fn reload_triggers(
+    character: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<usize, String> {
+    state
+        .shared
+        .triggers
+        .reload(&storage_state.get_dir(), character)
+}
+
+/// Load `scripts/*.rhai` afresh, dropping pending script timers. Returns the
+/// names of the scripts loaded; those that fail are reported as
+/// `mud:script-error`.
+#[tauri::command]
+async fn reload_scripts(
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<Vec<String>, String> {
+    let dir = storage_state.get_dir();
+    state
+        .shared
+        .scripts
+        .reload(&dir)
+        .await
+        .ok_or_else(|| "Scripting is not running".to_string())
+}
+
+/// Enable or disable a loaded backend trigger until the app restarts,
+/// without editing triggers.json.
+#[tauri::command]
+fn set_trigger_enabled(
+    id: String,
+    enabled: bool,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<(), String> {
+    state.shared.triggers.set_enabled(&id, enabled)
+}
+
+/// Try a pattern against sample text, returning the captures if it matched.
+#[tauri::command]
+fn test_trigger(
+    pattern: String,
+    sample: String,
+    match_mode: Option<triggers::MatchMode>,
+    ignore_case: Option<bool>,
+) -> Result<triggers::TriggerTestResult, String> {
+    triggers::test(
+        &pattern,
+        &sample,
+        match_mode.unwrap_or(triggers::MatchMode::Regex),
+        ignore_case,
+    )
+}
+
+/// Loaded backend triggers with their fire counts.
+#[tauri::command]
+fn get_trigger_stats(state: tauri::State<'_, ConnectionState>) -> Vec<triggers::TriggerStats> {
+    state.shared.triggers.stats()
+}
+
+/// Choose whose backend aliases apply (the global ones plus `character`'s)
+/// and the command separator, then reload aliases.json. Returns how many
+/// aliases are active. Later writes to aliases.json reload automatically.
+#[tauri::command]
+fn reload_aliases(
+    character: Option<String>,
+    separator: Option<String>,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<usize, String> {
+    state.shared.aliases.configure(character, separator);
+    state.shared.aliases.reload(&storage_state.get_dir())
+}
+
+/// What `send_command` would send for `input`, without sending anything.
+#[tauri::command]
+fn preview_alias_expansion(
+    input: String,
+    state: tauri::State<'_, ConnectionState>,
+) -> Result<Vec<String>, String> {
+    state.shared.aliases.expand(&input)
+}
+
+/// Enable or disable a gag rule in filters.json; applies immediately.
+#[tauri::command]
+fn set_gag_enabled(
+    id: String,
+    enabled: bool,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let dir = storage_state.get_dir();
+    filters::set_enabled(&dir, &id, enabled)?;
+    state.shared.filters.reload(&dir);
+    Ok(())
+}
+
+/// Gag rules with how many lines each has suppressed.
+#[tauri::command]
+fn get_filter_stats(state: tauri::State<'_, ConnectionState>) -> filters::FilterStats {
+    state.shared.filters.stats()
+}
+
+/// `sample` with a highlight rule applied, escapes included. Without a
+/// `style` matches are shown bold.
+#[tauri::command]
+fn test_highlight(
+    pattern: String,
+    sample: String,
+    style: Option<highlights::HighlightStyle>,
+    ignore_case: Option<bool>,
+) -> Result<String, String> {
+    let style = style.unwrap_or(highlights::HighlightStyle {
+        bold: true,
+        ..Default::default()
     });
-    *companion_state.last_status.lock().await = Some((false, "Disconnected".to_string()));
+    highlights::test(&pattern, &sample, &style, ignore_case)
+}
+
+/// Send `command` every `interval_secs` while connected, or just once after
+/// `interval_secs` unless `repeat`. A timer with the same name is replaced
+/// and its countdown restarted. Saved to timers.json.
+#[tauri::command]
+fn create_timer(
+    name: String,
+    interval_secs: u64,
+    command: String,
+    repeat: bool,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<timers::TimerInfo, String> {
+    let def = timers::TimerDef {
+        interval_secs,
+        command,
+        repeat,
+    };
+    state
+        .shared
+        .timers
+        .create(&storage_state.get_dir(), name, def)
+}
+
+/// Stop a timer and remove it from timers.json.
+#[tauri::command]
+fn cancel_timer(
+    name: String,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    state.shared.timers.cancel(&storage_state.get_dir(), &name)
+}
+
+/// Timers with the time left until each next fires.
+#[tauri::command]
+fn list_timers(state: tauri::State<'_, ConnectionState>) -> Vec<timers::TimerInfo> {
+    state.shared.timers.list()
+}
+
+/// Up to `limit` (default 100) commands from the history, newest first,
+/// skipping the newest `offset`.
+#[tauri::command]
+fn get_command_history(
+    limit: Option<usize>,
+    offset: Option<usize>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Vec<history::HistoryEntry> {
+    state
+        .shared
+        .history
+        .page(limit.unwrap_or(100), offset.unwrap_or(0))
+}
+
+/// Up to `limit` (default 100) history commands containing `query`, newest
+/// first. Case-insensitive.
+#[tauri::command]
+fn search_command_history(
+    query: String,
+    limit: Option<usize>,
+    state: tauri::State<'_, ConnectionState>,
+) -> Vec<history::HistoryEntry> {
+    state.shared.history.search(&query, limit.unwrap_or(100))
+}
+
+#[tauri::command]
+fn clear_command_history(
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    state.shared.history.clear(&storage_state.get_dir())
+}
+
+/// Keep at most `max_entries` commands in the history (`None` for the default
+/// of 10,000); the oldest are dropped first. Persisted and applied now.
+#[tauri::command]
+fn set_command_history_limit(
+    max_entries: Option<usize>,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    if max_entries == Some(0) {
+        return Err("History limit must be greater than zero".to_string());
+    }
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.history_max_entries = max_entries;
+    settings.save(&dir)?;
+    state
+        .shared
+        .history
+        .set_max_entries(max_entries.unwrap_or(history::DEFAULT_MAX_ENTRIES));
+    Ok(())
+}
+
+/// Start logging the session to `logs/<profile>/<YYYY-MM-DD>.txt` (restarting
+/// if already logging). Stays on across restarts until stopped.
+#[tauri::command]
+fn start_session_log(
+    options: Option<session_log::SessionLogOptions>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<events::SessionLogStatusPayload, String> {
+    let dir = storage_state.get_dir();
+    let settings = session_log::SessionLogSettings {
+        enabled: true,
+        options: options.unwrap_or_default(),
+    };
+    settings.save(&dir)?;
+    for session in state
+        .all()
+        .iter()
+        .filter(|session| !session.shared.is_default())
+    {
+        session
+            .shared
+            .session_log
+            .start(app.clone(), &dir, settings.options.clone());
+    }
+    Ok(state.shared.session_log.start(app, &dir, settings.options))
+}
+
+#[tauri::command]
+fn stop_session_log(
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<events::SessionLogStatusPayload, String> {
+    let dir = storage_state.get_dir();
+    let mut settings = session_log::SessionLogSettings::load(&dir);
+    settings.enabled = false;
+    settings.save(&dir)?;
+    for session in state
+        .all()
+        .iter()
+        .filter(|session| !session.shared.is_default())
+    {
+        session.shared.session_log.stop()?;
+    }
+    state.shared.session_log.stop()
+}
+
+/// Rotate logs once they pass `max_size_bytes` (default 10MB), keeping
+/// `keep_generations` older files (default 5); with `compress`, all but the
+/// newest are gzipped. Applies to `append_to_log` and the session log.
+/// Persisted.
+#[tauri::command]
+fn set_log_rotation(
+    max_size_bytes: Option<u64>,
+    keep_generations: Option<usize>,
+    compress: bool,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    if max_size_bytes == Some(0) {
+        return Err("Log size limit must be greater than zero".to_string());
+    }
+    if keep_generations.is_some_and(|keep| keep > log_rotation::MAX_KEEP_GENERATIONS) {
+        return Err(format!(
+            "At most {} log generations can be kept",
+            log_rotation::MAX_KEEP_GENERATIONS
+        ));
+    }
+    let dir = storage_state.get_dir();
+    let mut rotation = log_rotation::LogRotation::load(&dir);
+    rotation.max_size_bytes = max_size_bytes.unwrap_or(log_rotation::DEFAULT_MAX_SIZE_BYTES);
+    rotation.keep_generations = keep_generations.unwrap_or(log_rotation::DEFAULT_KEEP_GENERATIONS);
+    rotation.compress = compress;
+    rotation.save(&dir)?;
+    for session in state.all() {
+        session.shared.session_log.set_rotation(rotation.clone());
+    }
+    Ok(())
+}
+
+/// Delete logs under `logs/` older than `max_age_days`, then the oldest until
+/// they total at most `max_total_bytes`. The file the session log is writing
+/// is skipped. With `dry_run`, only reports what would be deleted.
+#[tauri::command]
+fn prune_logs(
+    max_age_days: Option<u32>,
+    max_total_bytes: Option<u64>,
+    dry_run: bool,
+    state: tauri::State<'_, ConnectionState>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<log_rotation::PruneSummary, String> {
+    let logs_dir = storage_state.get_dir().join(session_log::LOGS_DIR);
+    let open: Vec<_> = state
+        .all()
+        .iter()
+        .filter_map(|session| session.shared.session_log.current_path())
+        .collect();
+    log_rotation::prune(&logs_dir, max_age_days, max_total_bytes, dry_run, &open)
+}
+
+/// Report data files changed outside the app as `storage:file-changed`
+/// events. On by default; persisted per data directory.
+#[tauri::command]
+fn set_storage_watch(
+    enabled: bool,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    storage_watch::StorageWatchSettings { enabled }.save(&storage_state.get_dir())?;
+    storage_state.watch.changed();
     Ok(())
 }
 
+/// Schedule and retention for automatic backups. Persisted per data
+/// directory; the on/off switch stays in the frontend's settings.
+#[tauri::command]
+fn set_auto_backup(
+    settings: auto_backup::AutoBackupSettings,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    settings.save(&storage_state.get_dir())
+}
+
+/// Free-space floor below which `storage:low-disk` is emitted. Persisted
+/// per data directory.
+#[tauri::command]
+fn set_disk_space_settings(
+    settings: disk_space::DiskSpaceSettings,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    settings.save(&storage_state.get_dir())
+}
+
+/// Prune logs at startup with these limits (see `prune_logs`). Persisted.
+#[tauri::command]
+fn set_log_auto_prune(
+    enabled: bool,
+    max_age_days: Option<u32>,
+    max_total_bytes: Option<u64>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let dir = storage_state.get_dir();
+    let mut rotation = log_rotation::LogRotation::load(&dir);
+    rotation.prune_on_startup = enabled;
+    rotation.prune_max_age_days = max_age_days;
+    rotation.prune_max_total_bytes = max_total_bytes;
+    rotation.save(&dir)
+}
+
+#[tauri::command]
+fn get_session_log_status(
+    state: tauri::State<'_, ConnectionState>,
+) -> events::SessionLogStatusPayload {
+    state.shared.session_log.status()
+}
+
+/// Route the MUD connection through a proxy. `kind` is `"socks5"`, `"http"`,
+/// or `"none"` to connect directly. Persisted; takes effect on the next connect.
+#[tauri::command]
+fn set_outbound_proxy(
+    kind: String,
+    host: Option<String>,
+    port: Option<u16>,
+    username: Option<String>,
+    password: Option<String>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let kind = match kind.as_str() {
+        "none" => None,
+        "socks5" => Some(outbound::ProxyKind::Socks5),
+        "http" => Some(outbound::ProxyKind::Http),
+        other => return Err(format!("Unknown proxy kind: {other}")),
+    };
+    let proxy = match kind {
+        None => None,
+        Some(kind) => {
+            let host = host
+                .map(|h| h.trim().to_string())
+                .filter(|h| !h.is_empty())
+                .ok_or("Proxy host is required")?;
+            let port = port.filter(|p| *p != 0).ok_or("Proxy port is required")?;
+            Some(outbound::OutboundProxy {
+                kind,
+                host,
+                port,
+                username: username.filter(|u| !u.is_empty()),
+                password,
+            })
+        }
+    };
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.proxy = proxy;
+    settings.save(&dir)
+}
+
+/// Choose how the MUD is reached: `"tcp"` (directly, or through the outbound
+/// proxy) or `"websocket_proxy"` through the web proxy's `ws://`/`wss://`
+/// `url`, with its auth `token` if it has one. Persisted; takes effect on the
+/// next connect.
+#[tauri::command]
+fn set_transport(
+    kind: String,
+    url: Option<String>,
+    token: Option<String>,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    let transport = match kind.as_str() {
+        "tcp" => connection::Transport::Tcp,
+        "websocket_proxy" => {
+            let url = url
+                .map(|u| u.trim().to_string())
+                .filter(|u| !u.is_empty())
+                .ok_or("Proxy URL is required")?;
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                return Err(format!(
+                    "Proxy URL must start with ws:// or wss://, got \"{url}\""
+                ));
+            }
+            connection::Transport::WebSocketProxy {
+                url,
+                token: token.filter(|t| !t.is_empty()),
+            }
+        }
+        other => return Err(format!("Unknown transport: {other}")),
+    };
+    let dir = storage_state.get_dir();
+    let mut settings = connection::ConnectionSettings::load(&dir);
+    settings.transport = transport;
+    settings.save(&dir)
+}
+
 #[tauri::command]
 fn read_system_file(path: String) -> Result<String, String> {
     std::fs::read_to_string(&path)
@@ -156,28 +1832,141 @@ fn delete_credential(account: String) -> Result<(), String> {
     }
 }
 
+/// Store a named secret in the OS keychain (or the encrypted fallback file
+/// when no keychain is available).
+#[tauri::command]
+fn secret_set(
+    key: String,
+    value: String,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    secrets::set(&storage_state.get_dir(), &key, &value)
+}
+
+#[tauri::command]
+fn secret_get(
+    key: String,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<Option<String>, String> {
+    secrets::get(&storage_state.get_dir(), &key)
+}
+
+#[tauri::command]
+fn secret_delete(
+    key: String,
+    storage_state: tauri::State<'_, storage::StorageState>,
+) -> Result<(), String> {
+    secrets::delete(&storage_state.get_dir(), &key)
+}
+
+/// Which secret backend is in use, so settings can warn when it isn't the keychain.
+#[tauri::command]
+fn secret_backend_info() -> secrets::SecretBackendInfo {
+    secrets::backend_info()
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    env_logger::init();
+    let args = cli::parse(std::env::args());
+    app_log::init(
+        args.as_ref()
+            .ok()
+            .and_then(|args| args.log_level.as_deref()),
+    );
+    let launch = cli::Launch::new(args);
 
     // Create a long-lived broadcast channel for companion WebSocket clients
     let (broadcast_tx, _) = broadcast::channel::<companion::CompanionMessage>(256);
 
     tauri::Builder::default()
+        // First, so a second launch exits before doing anything else
+        .plugin(tauri_plugin_single_instance::init(
+            instance::on_second_instance,
+        ))
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_store::Builder::default().build())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_clipboard_manager::init())
-        .plugin(tauri_plugin_window_state::Builder::default().build())
-        .manage(ConnectionState {
-            cmd_tx: Mutex::new(None),
-            task_handle: Mutex::new(None),
+        .plugin(tauri_plugin_notification::init())
+        .on_window_event(|window, event| {
+            tray::on_window_event(window, event);
+            window_title::on_window_event(window, event);
         })
+        .plugin(tauri_plugin_window_state::Builder::default().build())
+        .manage(ConnectionState::new())
         .manage(CompanionState::new(broadcast_tx))
+        .manage(launch)
+        .manage(native_audio::NativeAudio::default())
+        .manage(window_title::TitleManager::default())
         .invoke_handler(tauri::generate_handler![
             send_command,
+            clear_command_queue,
+            pause_output,
+            resume_output,
+            get_connection_stats,
+            get_session_summary,
+            session_summary::get_session_history,
+            get_connection_status,
+            list_sessions,
             reconnect,
             disconnect,
+            disconnect_all,
+            set_quit_command,
+            set_keepalive,
+            set_latency_probe,
+            set_anti_idle,
+            set_accessible_output,
+            set_outbound_proxy,
+            set_transport,
+            set_connection_target,
+            list_profiles,
+            save_profile,
+            delete_profile,
+            connect_profile,
+            set_profile_password,
+            get_launch_options,
+            frontend_ready,
+            set_local_echo,
+            send_commands,
+            cancel_pending_sends,
+            set_command_rate_limit,
+            set_socket_options,
+            send_raw_bytes,
+            set_developer_mode,
+            get_scrollback,
+            get_scrollback_chunks,
+            clear_scrollback,
+            copy_scrollback_to_clipboard,
+            export_scrollback_to_file,
+            set_scrollback_options,
+            set_output_batching,
+            start_raw_capture,
+            stop_raw_capture,
+            replay_capture,
+            simulate_output,
+            reload_triggers,
+            reload_scripts,
+            set_trigger_enabled,
+            test_trigger,
+            get_trigger_stats,
+            reload_aliases,
+            preview_alias_expansion,
+            set_gag_enabled,
+            get_filter_stats,
+            test_highlight,
+            create_timer,
+            cancel_timer,
+            list_timers,
+            get_command_history,
+            search_command_history,
+            clear_command_history,
+            set_command_history_limit,
+            start_session_log,
+            stop_session_log,
+            get_session_log_status,
+            set_log_rotation,
+            prune_logs,
+            set_log_auto_prune,
             companion::start_companion,
             companion::stop_companion,
             companion::get_companion_info,
@@ -190,15 +1979,46 @@ pub fn run() {
             storage::get_active_data_dir,
             storage::read_data_file,
             storage::write_data_file,
+            storage::read_data_files,
+            storage::write_data_files,
+            storage::update_data_file,
+            storage::list_data_files,
+            storage::get_file_info,
+            storage::create_data_subdir,
+            storage::set_backup_options,
+            storage::delete_data_file,
+            storage::empty_trash,
             storage::read_text_file,
+            storage::read_text_files,
             storage::write_text_file,
             storage::delete_text_file,
+            storage::read_binary_file,
+            storage::write_binary_file,
+            storage::write_binary_file_from_path,
             storage::copy_data_to_dir,
+            storage::migrate_data_dir,
             storage::check_dir_valid,
             storage::create_backup,
             storage::list_backups,
             storage::restore_backup,
+            storage::verify_backup,
+            storage::diff_backup,
             storage::prune_backups,
+            storage::list_log_files,
+            storage::read_log_tail,
+            storage::set_write_durability,
+            set_storage_watch,
+            set_auto_backup,
+            set_disk_space_settings,
+            disk_space::get_storage_usage,
+            bundle::export_bundle,
+            bundle::import_bundle,
+            client_import::import_client_config,
+            diagnostics::create_diagnostic_bundle,
+            app_log::set_log_level,
+            telnet_debug::set_telnet_debug,
+            export_log_html,
+            export_text_html,
             storage::append_to_log,
             storage::list_session_logs,
             storage::read_session_log,
@@ -208,22 +2028,115 @@ pub fn run() {
             storage::get_sound_base64,
             storage::remove_custom_sound,
             storage::list_custom_sounds,
+            native_audio::play_sound_native,
+            native_audio::stop_native_sounds,
+            notifications::set_do_not_disturb,
+            tray::set_close_to_tray,
+            window_title::set_title_template,
             read_system_file,
             write_system_file,
             store_credential,
             get_credential,
             delete_credential,
+            secret_set,
+            secret_get,
+            secret_delete,
+            secret_backend_info,
         ])
         .setup(|app| {
-            // Initialize storage state with default app data dir
-            let data_dir = app.path().app_data_dir()
-                .map_err(|e| format!("Failed to get app data dir: {e}"))?;
+            // Initialize storage state with --data-dir or the default app data dir
+            let launch = app.state::<cli::Launch>();
+            let data_dir = match launch.data_dir() {
+                Some(dir) => dir,
+                None => app
+                    .path()
+                    .app_data_dir()
+                    .map_err(|e| format!("Failed to get app data dir: {e}"))?,
+            };
             if let Err(e) = std::fs::create_dir_all(&data_dir) {
                 log::warn!("Failed to create data dir {}: {e}", data_dir.display());
             }
-            app.manage(storage::StorageState::new(data_dir));
+            // Before anything reads or cleans up the dir, so a second
+            // instance finds out it's read-only first
+            let storage_state = storage::StorageState::new(data_dir.clone());
+            storage_state.lock.acquire(&data_dir, app.handle());
+            app.manage(storage_state);
+            storage::remove_orphaned_temp_files(&data_dir);
+            let connection_state = app.state::<ConnectionState>();
+            let shared = &connection_state.shared;
+            if launch.safe_mode() {
+                shared.triggers.suspend();
+                shared.timers.suspend();
+            }
+            if let (Some(name), false) = (&launch.options.profile, launch.options.autoconnect) {
+                if let Err(e) = select_profile(shared, &data_dir, name) {
+                    log::warn!("--profile {name}: {e}");
+                }
+            }
+            shared.filters.reload(&data_dir);
+            shared.highlights.reload(&data_dir);
+            shared.prompt_parsers.reload(&data_dir);
+            shared.rooms.reload(&data_dir);
+            shared.comms.reload(&data_dir);
+            shared.session_counters.reload(&data_dir);
+            shared.timers.load(&data_dir);
+            let connection_settings = connection::ConnectionSettings::load(&data_dir);
+            shared.accessible_output.store(
+                connection_settings.accessible_output,
+                std::sync::atomic::Ordering::Relaxed,
+            );
+            let history_limit = connection_settings.history_max_entries;
+            shared
+                .history
+                .set_max_entries(history_limit.unwrap_or(history::DEFAULT_MAX_ENTRIES));
+            shared.history.load(&data_dir);
+            tauri::async_runtime::spawn(history::run(app.handle().clone(), shared.clone()));
+            tauri::async_runtime::spawn(rooms::run(shared.maps.clone()));
+            shared.scripts.start(app.handle().clone());
+            if !launch.safe_mode() {
+                shared.scripts.reload_in_background(&data_dir);
+            }
+            let rotation = log_rotation::LogRotation::load(&data_dir);
+            if rotation.prune_on_startup {
+                // Before the session log opens a file, so nothing is in use
+                let logs_dir = data_dir.join(session_log::LOGS_DIR);
+                match log_rotation::prune(
+                    &logs_dir,
+                    rotation.prune_max_age_days,
+                    rotation.prune_max_total_bytes,
+                    false,
+                    &[],
+                ) {
+                    Ok(summary) => log::info!(
+                        "Pruned {} log file(s), {} bytes",
+                        summary.files.len(),
+                        summary.bytes_reclaimed
+                    ),
+                    Err(e) => log::warn!("Log pruning failed: {e}"),
+                }
+            }
+            let log_settings = session_log::SessionLogSettings::load(&data_dir);
+            if log_settings.enabled {
+                shared
+                    .session_log
+                    .start(app.handle().clone(), &data_dir, log_settings.options);
+            }
+            tauri::async_runtime::spawn(session_log::run(shared.clone()));
+            tauri::async_runtime::spawn(timers::run(app.handle().clone(), shared.clone()));
+            tauri::async_runtime::spawn(storage_watch::run(app.handle().clone()));
+            tauri::async_runtime::spawn(auto_backup::run(app.handle().clone()));
+            tauri::async_runtime::spawn(disk_space::run(app.handle().clone()));
+            tauri::async_runtime::spawn(window_title::run(app.handle().clone()));
+            if let Err(e) = tray::create(app.handle()) {
+                log::warn!("Failed to create tray icon: {e}");
+            }
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                app.state::<storage::StorageState>().lock.release();
+            }
+        });
 }
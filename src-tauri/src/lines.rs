@@ -0,0 +1,120 @@
+//! Line-oriented view of the display stream, for backend rules (triggers,
+//! gags) that need whole lines rather than read-sized chunks.
+
+use tokio::time::{Duration, Instant};
+
+use crate::ansi;
+
+/// Only this much of a line is matched against.
+pub const MAX_MATCH_LEN: usize = 4096;
+/// How long an unterminated line is held back waiting for the rest of it
+/// before being shown as-is. GA-terminated prompts are released at once.
+const PARTIAL_HOLD: Duration = Duration::from_millis(50);
+/// A held line longer than this is shown rather than held any longer.
+const MAX_HELD: usize = MAX_MATCH_LEN * 2;
+
+/// The text rules match a line against: ANSI-stripped, without the line
+/// ending, and truncated to `MAX_MATCH_LEN`.
+pub fn match_text(line: &str) -> String {
+    let mut text = ansi::strip_ansi(line.trim_end_matches('\n'));
+    if text.len() > MAX_MATCH_LEN {
+        let mut cut = MAX_MATCH_LEN;
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        text.truncate(cut);
+    }
+    text
+}
+
+/// Splits the display stream into lines. A line split across reads is held
+/// back briefly so it can still be matched (and gagged) whole; if the rest
+/// doesn't arrive within `PARTIAL_HOLD` it is shown anyway and can no longer
+/// be gagged. A fragment ending in GA is a prompt: it is shown immediately
+/// and never gagged.
+#[derive(Default)]
+pub struct LineSplitter {
+    partial: String,
+    /// The partial line is already on screen.
+    partial_shown: bool,
+    held_since: Option<Instant>,
+}
+
+impl LineSplitter {
+    /// Feed display text; `on_line(line, is_prompt)` is called for each line
+    /// and returns whether to gag it (ignored for prompts). Returns the text
+    /// to display.
+    pub fn feed(
+        &mut self,
+        text: &str,
+        ga: bool,
+        mut on_line: impl FnMut(&str, bool) -> bool,
+    ) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(pos) = rest.find('\n') {
+            let (segment, tail) = rest.split_at(pos + 1);
+            rest = tail;
+            let mut line = std::mem::take(&mut self.partial);
+            line.push_str(segment);
+            let shown = std::mem::take(&mut self.partial_shown);
+            self.held_since = None;
+            let gag = on_line(&line, false);
+            if shown {
+                // Its start is already on screen, so only the rest can go out
+                out.push_str(segment);
+            } else if !gag {
+                out.push_str(&line);
+            }
+        }
+
+        if !rest.is_empty() {
+            if self.partial_shown {
+                out.push_str(rest);
+                if self.partial.len() < MAX_HELD {
+                    self.partial.push_str(rest);
+                }
+            } else {
+                self.partial.push_str(rest);
+                self.held_since.get_or_insert_with(Instant::now);
+                if self.partial.len() > MAX_HELD {
+                    out.push_str(&self.release());
+                }
+            }
+        }
+
+        if ga && !self.partial.is_empty() {
+            let prompt = std::mem::take(&mut self.partial);
+            on_line(&prompt, true);
+            if !std::mem::take(&mut self.partial_shown) {
+                out.push_str(&prompt);
+            }
+            self.held_since = None;
+        }
+        out
+    }
+
+    /// When the held partial line should be shown without waiting further.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.held_since.map(|since| since + PARTIAL_HOLD)
+    }
+
+    /// Stop holding the partial line back. Returns the text to show now; the
+    /// line is still matched once it completes.
+    pub fn release(&mut self) -> String {
+        self.held_since = None;
+        if self.partial_shown {
+            return String::new();
+        }
+        self.partial_shown = true;
+        self.partial.clone()
+    }
+
+    /// Reset, returning any held text that still needs showing.
+    pub fn flush(&mut self) -> String {
+        let held = self.release();
+        self.partial.clear();
+        self.partial_shown = false;
+        held
+    }
+}
@@ -0,0 +1,234 @@
+//! Size-based rotation for log files. A log over the size limit is renamed
+//! to `name.1.ext`, older generations shift up (`name.2.ext`, ...), and only
+//! `keep_generations` are kept. With `compress`, generations from the second
+//! on are gzipped (`name.2.ext.gz`).
+//!
+//! Pruning deletes logs under the `logs` directory by age and a total-size
+//! budget, optionally at startup.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+pub const LOG_ROTATION_FILE: &str = "log-rotation.json";
+pub const DEFAULT_MAX_SIZE_BYTES: u64 = 10 * 1024 * 1024;
+pub const DEFAULT_KEEP_GENERATIONS: usize = 5;
+pub const MAX_KEEP_GENERATIONS: usize = 100;
+
+/// Held across check-rotate-append in `append_to_log`, so concurrent appends
+/// never write to a file while it is being renamed away.
+pub static APPEND_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogRotation {
+    pub max_size_bytes: u64,
+    pub keep_generations: usize,
+    pub compress: bool,
+    /// Prune with `prune_max_age_days` / `prune_max_total_bytes` at startup.
+    pub prune_on_startup: bool,
+    pub prune_max_age_days: Option<u32>,
+    pub prune_max_total_bytes: Option<u64>,
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        Self {
+            max_size_bytes: DEFAULT_MAX_SIZE_BYTES,
+            keep_generations: DEFAULT_KEEP_GENERATIONS,
+            compress: false,
+            prune_on_startup: false,
+            prune_max_age_days: None,
+            prune_max_total_bytes: None,
+        }
+    }
+}
+
+/// `dir/stem.N.ext`, plus `.gz` if compressed.
+fn generation_path(path: &Path, generation: usize, compressed: bool) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut name = match path.extension() {
+        Some(ext) => format!("{stem}.{generation}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{generation}"),
+    };
+    if compressed {
+        name.push_str(".gz");
+    }
+    path.with_file_name(name)
+}
+
+/// Whichever form of a generation exists.
+fn existing_generation(path: &Path, generation: usize) -> Option<PathBuf> {
+    [false, true]
+        .into_iter()
+        .map(|gz| generation_path(path, generation, gz))
+        .find(|p| p.exists())
+}
+
+fn gzip(from: &Path, to: &Path) -> io::Result<()> {
+    let tmp = to.with_extension("gz.tmp");
+    let mut encoder = GzEncoder::new(BufWriter::new(File::create(&tmp)?), Compression::default());
+    io::copy(&mut BufReader::new(File::open(from)?), &mut encoder)?;
+    encoder
+        .finish()?
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&tmp, to)?;
+    fs::remove_file(from)
+}
+
+impl LogRotation {
+    pub fn load(dir: &Path) -> Self {
+        crate::storage::read_json_file(dir, LOG_ROTATION_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        crate::storage::write_json_file(dir, LOG_ROTATION_FILE, self)
+    }
+
+    /// Whether a log of `size` bytes should be rotated before `incoming` more
+    /// are written. An empty log is never rotated, so one oversized write
+    /// still lands somewhere.
+    pub fn is_due(&self, size: u64, incoming: usize) -> bool {
+        size > 0 && size + incoming as u64 > self.max_size_bytes
+    }
+
+    /// Rotate `path` if it would exceed the size limit with `incoming` more
+    /// bytes. Returns whether it was rotated.
+    pub fn rotate_if_due(&self, path: &Path, incoming: usize) -> io::Result<bool> {
+        let size = match fs::metadata(path) {
+            Ok(meta) => meta.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if !self.is_due(size, incoming) {
+            return Ok(false);
+        }
+        self.rotate(path)?;
+        Ok(true)
+    }
+
+    /// Shift `path` to generation 1 and older generations up, dropping any
+    /// past `keep_generations`. The caller reopens `path` afterwards.
+    pub fn rotate(&self, path: &Path) -> io::Result<()> {
+        let keep = self.keep_generations.min(MAX_KEEP_GENERATIONS);
+        // Drop generations past the limit, including ones left by a larger
+        // earlier setting
+        let mut generation = keep.max(1);
+        while let Some(old) = existing_generation(path, generation) {
+            if generation >= keep {
+                fs::remove_file(old)?;
+            }
+            generation += 1;
+        }
+        if keep == 0 {
+            return fs::remove_file(path);
+        }
+        for generation in (1..keep).rev() {
+            let Some(from) = existing_generation(path, generation) else {
+                continue;
+            };
+            let was_compressed = from.extension().is_some_and(|ext| ext == "gz");
+            if self.compress && !was_compressed {
+                gzip(&from, &generation_path(path, generation + 1, true))?;
+            } else {
+                fs::rename(&from, generation_path(path, generation + 1, was_compressed))?;
+            }
+        }
+        fs::rename(path, generation_path(path, 1, false))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PruneSummary {
+    /// Paths relative to the logs directory, deleted (or, in a dry run,
+    /// that would be).
+    pub files: Vec<String>,
+    pub bytes_reclaimed: u64,
+    pub dry_run: bool,
+}
+
+struct LogFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Regular files under `dir`, recursively. Symlinks are never followed, so
+/// nothing outside `dir` is listed.
+fn collect_files(dir: &Path, out: &mut Vec<LogFile>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let Ok(meta) = fs::symlink_metadata(entry.path()) else {
+            continue;
+        };
+        if meta.is_dir() {
+            collect_files(&entry.path(), out);
+        } else if meta.is_file() {
+            out.push(LogFile {
+                path: entry.path(),
+                size: meta.len(),
+                modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+    }
+}
+
+/// Delete logs under `logs_dir` last modified more than `max_age_days` ago,
+/// then the oldest remaining ones until they total at most
+/// `max_total_bytes`. `open` (the files session logs are writing) are never
+/// deleted. With `dry_run` nothing is deleted.
+pub fn prune(
+    logs_dir: &Path,
+    max_age_days: Option<u32>,
+    max_total_bytes: Option<u64>,
+    dry_run: bool,
+    open: &[PathBuf],
+) -> Result<PruneSummary, String> {
+    if !dry_run {
+        crate::data_lock::check_writable(logs_dir)?;
+    }
+    let mut files = Vec::new();
+    collect_files(logs_dir, &mut files);
+    files.sort_by_key(|f| f.modified);
+
+    let cutoff = max_age_days.and_then(|days| {
+        SystemTime::now().checked_sub(Duration::from_secs(u64::from(days) * 86_400))
+    });
+    let mut total: u64 = files.iter().map(|f| f.size).sum();
+    let mut summary = PruneSummary {
+        dry_run,
+        ..Default::default()
+    };
+    for file in &files {
+        if open.contains(&file.path) {
+            continue;
+        }
+        let too_old = cutoff.is_some_and(|cutoff| file.modified < cutoff);
+        let over_budget = max_total_bytes.is_some_and(|budget| total > budget);
+        if !too_old && !over_budget {
+            continue;
+        }
+        if !dry_run {
+            fs::remove_file(&file.path)
+                .map_err(|e| format!("Failed to delete {}: {e}", file.path.display()))?;
+        }
+        total -= file.size;
+        summary.bytes_reclaimed += file.size;
+        let relative = file.path.strip_prefix(logs_dir).unwrap_or(&file.path);
+        summary.files.push(relative.to_string_lossy().into_owned());
+    }
+    Ok(summary)
+}
@@ -0,0 +1,118 @@
+//! Sound playback from the Rust side, for when the webview's autoplay policy
+//! would hold a chime back until the user interacts with the page. The output
+//! device is opened on first use and kept open on its own thread (the stream
+//! can't move between threads); every play gets its own sink, so overlapping
+//! sounds mix instead of queueing.
+
+use std::io::Cursor;
+use std::sync::mpsc;
+use std::sync::Mutex;
+
+use log::{info, warn};
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::storage::{self, StorageState};
+
+/// Sounds shipped with the frontend, played when there's no custom file.
+const BUNDLED_SOUNDS: [&str; 2] = ["chime1", "chime2"];
+const MAX_VOLUME: f32 = 2.0;
+
+#[derive(Default)]
+pub struct NativeAudio {
+    output: Mutex<Option<OutputStreamHandle>>,
+    /// Sounds still playing, so they can be stopped.
+    sinks: Mutex<Vec<Sink>>,
+}
+
+impl NativeAudio {
+    /// The default output device, opened on first use. A failure isn't
+    /// remembered, so plugging in a device and trying again works.
+    fn output(&self) -> Result<OutputStreamHandle, String> {
+        let mut output = self.output.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(handle) = output.as_ref() {
+            return Ok(handle.clone());
+        }
+        let (tx, rx) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("native-audio".to_string())
+            .spawn(move || match OutputStream::try_default() {
+                Ok((stream, handle)) => {
+                    let _ = tx.send(Ok(handle));
+                    // The stream plays for as long as it exists
+                    let _stream = stream;
+                    loop {
+                        std::thread::park();
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(format!("No audio output device: {e}")));
+                }
+            })
+            .map_err(|e| format!("Failed to start audio thread: {e}"))?;
+        let handle = rx.recv().map_err(|_| "Audio thread exited".to_string())??;
+        info!("Opened the default audio output");
+        *output = Some(handle.clone());
+        Ok(handle)
+    }
+
+    /// Decode `bytes` and start playing them over anything already playing.
+    pub fn play(&self, bytes: Vec<u8>, volume: f32) -> Result<(), String> {
+        let decoder =
+            Decoder::new(Cursor::new(bytes)).map_err(|e| format!("Can't decode sound: {e}"))?;
+        let sink = Sink::try_new(&self.output()?).map_err(|e| format!("Can't play sound: {e}"))?;
+        sink.set_volume(volume.clamp(0.0, MAX_VOLUME));
+        sink.append(decoder);
+        let mut sinks = self.sinks.lock().unwrap_or_else(|e| e.into_inner());
+        sinks.retain(|sink| !sink.empty());
+        sinks.push(sink);
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        for sink in self
+            .sinks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain(..)
+        {
+            sink.stop();
+        }
+    }
+}
+
+/// The audio for `sound_id`: its custom file in `sounds/` if there is one,
+/// otherwise the bundled sound of that name.
+fn sound_bytes<R: Runtime>(app: &AppHandle<R>, sound_id: &str) -> Result<Vec<u8>, String> {
+    storage::validate_sound_id(sound_id)?;
+    let sounds_dir = app.state::<StorageState>().get_dir().join("sounds");
+    if let Some(path) = storage::find_custom_sound(&sounds_dir, sound_id) {
+        return std::fs::read(&path).map_err(|e| format!("Failed to read {}: {e}", path.display()));
+    }
+    if BUNDLED_SOUNDS.contains(&sound_id) {
+        if let Some(asset) = app.asset_resolver().get(format!("{sound_id}.wav")) {
+            return Ok(asset.bytes);
+        }
+    }
+    Err(format!("Sound not found: {sound_id}"))
+}
+
+/// Play `sound_id` natively at `volume` (1.0 is full volume).
+pub fn play<R: Runtime>(app: &AppHandle<R>, sound_id: &str, volume: f32) -> Result<(), String> {
+    let bytes = sound_bytes(app, sound_id)?;
+    app.state::<NativeAudio>()
+        .play(bytes, volume)
+        .inspect_err(|e| warn!("Native playback of {sound_id} failed: {e}"))
+}
+
+/// Play a custom or bundled sound on the default output device.
+#[tauri::command]
+pub fn play_sound_native(sound_id: String, volume: f32, app: AppHandle) -> Result<(), String> {
+    play(&app, &sound_id, volume)
+}
+
+/// Stop every sound started with `play_sound_native`.
+#[tauri::command]
+pub fn stop_native_sounds(state: tauri::State<'_, NativeAudio>) {
+    state.stop();
+}
@@ -0,0 +1,110 @@
+//! Desktop notifications for backend triggers with `notifyOnMatch`, sent only
+//! while the main window is unfocused, at most once per rule per
+//! `RULE_INTERVAL`, and never while do-not-disturb is on.
+//!
+//! Desktop notifications can't report clicks, so the taskbar entry is also
+//! flagged for attention; clicking that brings the window forward.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, UserAttentionType};
+use tauri_plugin_notification::NotificationExt;
+use tokio::time::Duration;
+
+use crate::storage::{self, StorageState};
+
+pub const NOTIFICATIONS_FILE: &str = "notifications.json";
+const RULE_INTERVAL: Duration = Duration::from_secs(10);
+/// Longer lines are cut to this many characters.
+const MAX_BODY_CHARS: usize = 200;
+const DEFAULT_TITLE: &str = "DartForge";
+
+/// When each rule last notified.
+static LAST_SENT: LazyLock<Mutex<HashMap<String, Instant>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub do_not_disturb: bool,
+}
+
+impl NotificationSettings {
+    pub fn load(dir: &Path) -> Self {
+        storage::read_json_file(dir, NOTIFICATIONS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        storage::write_json_file(dir, NOTIFICATIONS_FILE, self)
+    }
+}
+
+/// A notification a trigger asked for.
+pub struct TriggerNotification {
+    pub rule_id: String,
+    pub title: Option<String>,
+    /// The matched line, ANSI-stripped.
+    pub line: String,
+}
+
+fn truncate(line: &str) -> String {
+    match line.char_indices().nth(MAX_BODY_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// Send `notification` unless the window has focus, do-not-disturb is on,
+/// or the rule notified too recently.
+pub fn send<R: Runtime>(app: &AppHandle<R>, notification: TriggerNotification) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    if window.is_focused().unwrap_or(true) {
+        return;
+    }
+    if NotificationSettings::load(&app.state::<StorageState>().get_dir()).do_not_disturb {
+        return;
+    }
+    {
+        let mut last_sent = LAST_SENT.lock().unwrap_or_else(|e| e.into_inner());
+        if last_sent
+            .get(&notification.rule_id)
+            .is_some_and(|at| at.elapsed() < RULE_INTERVAL)
+        {
+            return;
+        }
+        last_sent.insert(notification.rule_id, Instant::now());
+    }
+    let title = notification
+        .title
+        .filter(|t| !t.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_TITLE.to_string());
+    let shown = app
+        .notification()
+        .builder()
+        .title(title)
+        .body(truncate(&notification.line))
+        .show();
+    if let Err(e) = shown {
+        warn!("Failed to show notification: {e}");
+    }
+    let _ = window.request_user_attention(Some(UserAttentionType::Informational));
+}
+
+/// Turn do-not-disturb on or off; while on, triggers send no notifications.
+/// Persisted per data directory.
+#[tauri::command]
+pub fn set_do_not_disturb(
+    enabled: bool,
+    state: tauri::State<'_, StorageState>,
+) -> Result<(), String> {
+    NotificationSettings {
+        do_not_disturb: enabled,
+    }
+    .save(&state.get_dir())
+}
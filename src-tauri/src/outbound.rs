@@ -0,0 +1,262 @@
+//! Outbound proxy support for the MUD connection: SOCKS5 (RFC 1928/1929) and
+//! HTTP CONNECT. The handshake runs on a plain TcpStream, which is then handed
+//! to the normal read/write loops as if it were a direct connection.
+
+use std::fmt;
+
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyKind {
+    Socks5,
+    Http,
+}
+
+impl ProxyKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            ProxyKind::Socks5 => "SOCKS5",
+            ProxyKind::Http => "HTTP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboundProxy {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ProxyError {
+    /// Couldn't reach the proxy itself, or the socket failed mid-handshake.
+    Io(std::io::Error),
+    /// The proxy rejected our credentials (or requires credentials we lack).
+    AuthFailed(String),
+    /// The proxy accepted us but refused to connect to the MUD.
+    Refused(String),
+    /// The proxy spoke something we didn't understand.
+    Protocol(String),
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::Io(e) => write!(f, "Proxy unreachable: {e}"),
+            ProxyError::AuthFailed(detail) => write!(f, "Proxy authentication failed: {detail}"),
+            ProxyError::Refused(detail) => write!(f, "Proxy refused connection: {detail}"),
+            ProxyError::Protocol(detail) => write!(f, "Proxy protocol error: {detail}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for ProxyError {
+    fn from(e: std::io::Error) -> Self {
+        ProxyError::Io(e)
+    }
+}
+
+/// Connect to `target_host:target_port` through the proxy. The target name is
+/// resolved by the proxy, not locally.
+pub async fn connect_via(
+    proxy: &OutboundProxy,
+    target_host: &str,
+    target_port: u16,
+) -> Result<TcpStream, ProxyError> {
+    let mut stream = TcpStream::connect((proxy.host.as_str(), proxy.port)).await?;
+    match proxy.kind {
+        ProxyKind::Socks5 => socks5_handshake(&mut stream, proxy, target_host, target_port).await?,
+        ProxyKind::Http => http_connect(&mut stream, proxy, target_host, target_port).await?,
+    }
+    Ok(stream)
+}
+
+const SOCKS_VERSION: u8 = 0x05;
+const SOCKS_AUTH_NONE: u8 = 0x00;
+const SOCKS_AUTH_PASSWORD: u8 = 0x02;
+const SOCKS_AUTH_UNACCEPTABLE: u8 = 0xFF;
+const SOCKS_CMD_CONNECT: u8 = 0x01;
+const SOCKS_ATYP_IPV4: u8 = 0x01;
+const SOCKS_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS_ATYP_IPV6: u8 = 0x04;
+
+fn socks5_reply_message(code: u8) -> &'static str {
+    match code {
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused by destination",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+async fn socks5_handshake(
+    stream: &mut TcpStream,
+    proxy: &OutboundProxy,
+    target_host: &str,
+    target_port: u16,
+) -> Result<(), ProxyError> {
+    let credentials = proxy
+        .username
+        .as_deref()
+        .map(|u| (u, proxy.password.as_deref().unwrap_or("")));
+
+    // Greeting: offer username/password only when we have credentials
+    let greeting: &[u8] = if credentials.is_some() {
+        &[SOCKS_VERSION, 2, SOCKS_AUTH_NONE, SOCKS_AUTH_PASSWORD]
+    } else {
+        &[SOCKS_VERSION, 1, SOCKS_AUTH_NONE]
+    };
+    stream.write_all(greeting).await?;
+
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice[0] != SOCKS_VERSION {
+        return Err(ProxyError::Protocol(format!(
+            "unexpected SOCKS version {}",
+            choice[0]
+        )));
+    }
+    match choice[1] {
+        SOCKS_AUTH_NONE => {}
+        SOCKS_AUTH_PASSWORD => {
+            let Some((user, pass)) = credentials else {
+                return Err(ProxyError::AuthFailed(
+                    "proxy requires a username and password".into(),
+                ));
+            };
+            if user.len() > 255 || pass.len() > 255 {
+                return Err(ProxyError::AuthFailed(
+                    "username or password longer than 255 bytes".into(),
+                ));
+            }
+            let mut auth = Vec::with_capacity(3 + user.len() + pass.len());
+            auth.push(0x01);
+            auth.push(user.len() as u8);
+            auth.extend_from_slice(user.as_bytes());
+            auth.push(pass.len() as u8);
+            auth.extend_from_slice(pass.as_bytes());
+            stream.write_all(&auth).await?;
+
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != 0x00 {
+                return Err(ProxyError::AuthFailed("credentials rejected".into()));
+            }
+        }
+        SOCKS_AUTH_UNACCEPTABLE => {
+            return Err(ProxyError::AuthFailed(
+                "no acceptable authentication method".into(),
+            ));
+        }
+        other => {
+            return Err(ProxyError::Protocol(format!(
+                "unsupported auth method {other:#04x}"
+            )));
+        }
+    }
+
+    // CONNECT request with the target as a domain name
+    if target_host.len() > 255 {
+        return Err(ProxyError::Protocol("target host name too long".into()));
+    }
+    let mut request = Vec::with_capacity(7 + target_host.len());
+    request.extend_from_slice(&[SOCKS_VERSION, SOCKS_CMD_CONNECT, 0x00, SOCKS_ATYP_DOMAIN]);
+    request.push(target_host.len() as u8);
+    request.extend_from_slice(target_host.as_bytes());
+    request.extend_from_slice(&target_port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(ProxyError::Protocol(format!(
+            "unexpected SOCKS version {}",
+            reply[0]
+        )));
+    }
+    if reply[1] != 0x00 {
+        return Err(ProxyError::Refused(
+            socks5_reply_message(reply[1]).to_string(),
+        ));
+    }
+
+    // Consume the bound address so no handshake bytes leak into MUD output
+    let addr_len = match reply[3] {
+        SOCKS_ATYP_IPV4 => 4,
+        SOCKS_ATYP_IPV6 => 16,
+        SOCKS_ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        other => {
+            return Err(ProxyError::Protocol(format!(
+                "unknown address type {other:#04x}"
+            )))
+        }
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+/// Max size of the HTTP CONNECT response head we'll buffer.
+const MAX_HTTP_RESPONSE_HEAD: usize = 8 * 1024;
+
+async fn http_connect(
+    stream: &mut TcpStream,
+    proxy: &OutboundProxy,
+    target_host: &str,
+    target_port: u16,
+) -> Result<(), ProxyError> {
+    let authority = format!("{target_host}:{target_port}");
+    let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if let Some(user) = &proxy.username {
+        let pass = proxy.password.as_deref().unwrap_or("");
+        let token = base64::engine::general_purpose::STANDARD.encode(format!("{user}:{pass}"));
+        request.push_str(&format!("Proxy-Authorization: Basic {token}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte-by-byte up to the blank line so we never consume MUD data
+    let mut head = Vec::with_capacity(256);
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HTTP_RESPONSE_HEAD {
+            return Err(ProxyError::Protocol("response headers too large".into()));
+        }
+        let n = stream.read(&mut byte).await?;
+        if n == 0 {
+            return Err(ProxyError::Protocol(
+                "proxy closed the connection during CONNECT".into(),
+            ));
+        }
+        head.push(byte[0]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let status_line = head.lines().next().unwrap_or_default();
+    let code = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|c| c.parse::<u16>().ok())
+        .ok_or_else(|| ProxyError::Protocol(format!("malformed status line: {status_line}")))?;
+    match code {
+        200..=299 => Ok(()),
+        407 => Err(ProxyError::AuthFailed(status_line.to_string())),
+        _ => Err(ProxyError::Refused(status_line.to_string())),
+    }
+}
@@ -0,0 +1,184 @@
+//! Pausing output delivery. While a session is paused its connection keeps
+//! reading (telnet negotiation, triggers, logs and scrollback carry on), but
+//! display text is held here instead of emitted as `mud:output`. Resuming
+//! emits everything held as one event marked `buffered`. The hold is bounded;
+//! past `MAX_HELD_BYTES` the oldest text is dropped and a notice saying how
+//! much is put in front of the rest.
+//!
+//! `mud:output-paused` reports the pause state when it changes, and on every
+//! prompt while paused so the UI can show the connection is still alive.
+
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Runtime};
+
+use crate::events::{
+    self, now_ms, MudOutputPayload, OutputPausedPayload, MUD_OUTPUT_EVENT, OUTPUT_PAUSED_EVENT,
+};
+
+/// Most display text held while paused.
+const MAX_HELD_BYTES: usize = 1024 * 1024;
+
+#[derive(Default)]
+struct PauseInner {
+    paused: bool,
+    held: String,
+    /// Bytes dropped from the front of `held` since the pause began.
+    dropped: usize,
+    /// A GA arrived with the held text.
+    ga: bool,
+    last_prompt_at: Option<u64>,
+    /// Generation of the newest held output, which the held text is
+    /// delivered with.
+    generation: u64,
+}
+
+impl PauseInner {
+    fn state(&self, session: &str) -> OutputPausedPayload {
+        OutputPausedPayload {
+            session: session.to_string(),
+            paused: self.paused,
+            held_bytes: self.held.len(),
+            dropped_bytes: self.dropped,
+            last_prompt_at: self.last_prompt_at,
+        }
+    }
+
+    /// Add `payload`'s text to what's held, dropping the oldest text past
+    /// `MAX_HELD_BYTES`.
+    fn hold(&mut self, payload: &MudOutputPayload) {
+        self.held.push_str(&payload.data);
+        self.generation = payload.generation;
+        if self.held.len() > MAX_HELD_BYTES {
+            let mut cut = self.held.len() - MAX_HELD_BYTES;
+            while !self.held.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.held.drain(..cut);
+            self.dropped += cut;
+        }
+        if payload.ga {
+            self.ga = true;
+            self.last_prompt_at = Some(now_ms());
+        }
+    }
+
+    /// Everything held, as one buffered output event, if anything was.
+    fn release(self, session: &str) -> Option<MudOutputPayload> {
+        let mut data = self.held;
+        if self.dropped > 0 {
+            data.insert_str(
+                0,
+                &format!(
+                    "\r\n[{} bytes of output dropped while paused]\r\n",
+                    self.dropped
+                ),
+            );
+        }
+        (!data.is_empty()).then(|| MudOutputPayload {
+            data,
+            ga: self.ga,
+            session: session.to_string(),
+            buffered: true,
+            generation: self.generation,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct OutputPause {
+    inner: Mutex<PauseInner>,
+}
+
+impl OutputPause {
+    fn lock(&self) -> std::sync::MutexGuard<'_, PauseInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Emit `payload`, or hold its text while paused. The lock is held while
+    /// emitting so output can't overtake a concurrent resume.
+    pub fn deliver<R: Runtime>(&self, app: &AppHandle<R>, payload: MudOutputPayload) {
+        let mut inner = self.lock();
+        if !inner.paused {
+            let _ = events::emit(app, MUD_OUTPUT_EVENT, payload);
+            return;
+        }
+        inner.hold(&payload);
+        if payload.ga {
+            let _ = events::emit(app, OUTPUT_PAUSED_EVENT, inner.state(&payload.session));
+        }
+    }
+
+    pub fn pause(&self, app: &AppHandle, session: &str) -> OutputPausedPayload {
+        let mut inner = self.lock();
+        if !inner.paused {
+            *inner = PauseInner {
+                paused: true,
+                ..Default::default()
+            };
+            let _ = events::emit(app, OUTPUT_PAUSED_EVENT, inner.state(session));
+        }
+        inner.state(session)
+    }
+
+    /// Stop holding output, emitting what was held.
+    pub fn resume(&self, app: &AppHandle, session: &str) -> OutputPausedPayload {
+        let mut inner = self.lock();
+        if !inner.paused {
+            return inner.state(session);
+        }
+        if let Some(payload) = std::mem::take(&mut *inner).release(session) {
+            let _ = events::emit(app, MUD_OUTPUT_EVENT, payload);
+        }
+        let state = inner.state(session);
+        let _ = events::emit(app, OUTPUT_PAUSED_EVENT, state.clone());
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(data: &str, ga: bool, generation: u64) -> MudOutputPayload {
+        MudOutputPayload {
+            data: data.to_string(),
+            ga,
+            session: "default".to_string(),
+            buffered: false,
+            generation,
+        }
+    }
+
+    #[test]
+    fn held_output_goes_out_with_the_newest_generation() {
+        let mut inner = PauseInner::default();
+        inner.hold(&output("old link\r\n", false, 3));
+        inner.hold(&output("new link> ", true, 4));
+        assert!(inner.last_prompt_at.is_some());
+        let released = inner.release("s2").unwrap();
+        assert_eq!(released.data, "old link\r\nnew link> ");
+        assert_eq!(released.generation, 4);
+        assert_eq!(released.session, "s2");
+        assert!(released.buffered && released.ga);
+    }
+
+    #[test]
+    fn overflow_drops_oldest_text_and_says_so() {
+        let mut inner = PauseInner::default();
+        inner.hold(&output(&"a".repeat(MAX_HELD_BYTES), false, 1));
+        inner.hold(&output("é!", false, 1));
+        assert_eq!(inner.held.len(), MAX_HELD_BYTES);
+        assert_eq!(inner.dropped, 3);
+        let released = inner.release("default").unwrap();
+        assert!(released
+            .data
+            .starts_with("\r\n[3 bytes of output dropped while paused]\r\n"));
+        assert!(released.data.ends_with("é!"));
+    }
+
+    #[test]
+    fn nothing_held_releases_nothing() {
+        assert!(PauseInner::default().release("default").is_none());
+    }
+}
@@ -0,0 +1,131 @@
+//! Accessible output: a plain-text copy of the MUD stream for screen readers,
+//! emitted as `mud:output-plain` next to the normal `mud:output`. It comes
+//! from the same line split the backend rules use, so nothing is decoded
+//! twice. Only complete lines go out, gagged ones left out, with ANSI and
+//! control characters removed and runs of blank lines collapsed to one.
+//!
+//! Prompts go out as their own event with `is_prompt` set, as does a partial
+//! line the server leaves waiting without a GA (a login prompt); once that
+//! line completes, only the rest of it follows.
+
+use tauri::{AppHandle, Runtime};
+
+use crate::ansi;
+use crate::connection::ConnectionShared;
+use crate::events::{self, PlainOutputPayload, PLAIN_OUTPUT_EVENT};
+
+/// ANSI stripped, tabs as spaces, other control characters dropped, trailing
+/// whitespace trimmed.
+fn clean(line: &str) -> String {
+    let text: String = ansi::strip_ansi(line)
+        .chars()
+        .filter_map(|c| match c {
+            '\t' => Some(' '),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect();
+    text.trim_end().to_string()
+}
+
+/// Per-connection line collector; lines are batched into one event per read.
+#[derive(Default)]
+pub struct PlainLines {
+    pending: Vec<String>,
+    last_blank: bool,
+    /// A partial line already announced as a prompt.
+    announced: Option<String>,
+    /// Generation of the connection it collects for, carried by its events.
+    generation: u64,
+}
+
+impl PlainLines {
+    pub fn new(generation: u64) -> Self {
+        Self {
+            generation,
+            ..Default::default()
+        }
+    }
+
+    /// A line that wasn't gagged, or a GA-terminated prompt.
+    pub fn on_line<R: Runtime>(
+        &mut self,
+        app: &AppHandle<R>,
+        shared: &ConnectionShared,
+        line: &str,
+        prompt: bool,
+    ) {
+        let mut text = clean(line);
+        if let Some(announced) = self.announced.take() {
+            match text.strip_prefix(announced.as_str()) {
+                Some("") => return,
+                Some(rest) => text = rest.trim_start().to_string(),
+                None => {}
+            }
+        }
+        if prompt {
+            self.announce(app, shared, text);
+            return;
+        }
+        let blank = text.trim().is_empty();
+        if blank && self.last_blank {
+            return;
+        }
+        self.last_blank = blank;
+        self.pending.push(if blank { String::new() } else { text });
+    }
+
+    /// The partial line was released to the screen without its end.
+    pub fn on_release<R: Runtime>(
+        &mut self,
+        app: &AppHandle<R>,
+        shared: &ConnectionShared,
+        partial: &str,
+    ) {
+        let text = clean(partial);
+        if text.trim().is_empty() {
+            return;
+        }
+        self.announced = Some(text.clone());
+        self.announce(app, shared, text);
+    }
+
+    fn announce<R: Runtime>(
+        &mut self,
+        app: &AppHandle<R>,
+        shared: &ConnectionShared,
+        text: String,
+    ) {
+        self.flush(app, shared);
+        if text.trim().is_empty() {
+            return;
+        }
+        self.last_blank = false;
+        emit(app, shared, text, true, self.generation);
+    }
+
+    /// Emit the lines collected so far.
+    pub fn flush<R: Runtime>(&mut self, app: &AppHandle<R>, shared: &ConnectionShared) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let text = std::mem::take(&mut self.pending).join("\n");
+        emit(app, shared, text, false, self.generation);
+    }
+}
+
+fn emit<R: Runtime>(
+    app: &AppHandle<R>,
+    shared: &ConnectionShared,
+    text: String,
+    is_prompt: bool,
+    generation: u64,
+) {
+    let payload = PlainOutputPayload {
+        session: shared.session.clone(),
+        text,
+        is_prompt,
+        generation,
+    };
+    let _ = events::emit(app, PLAIN_OUTPUT_EVENT, payload);
+}
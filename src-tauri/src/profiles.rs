@@ -0,0 +1,203 @@
+//! Connection profiles: named server/character setups stored in
+//! `profiles.json`, so switching characters (or to a builder port) is a
+//! single `connect_profile` instead of editing settings each time.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::autologin::{AutoLogin, DEFAULT_LOGIN_PROMPT};
+use crate::connection::{ConnectionSettings, ConnectionTarget};
+
+pub const PROFILES_FILE: &str = "profiles.json";
+
+/// Per-profile connection settings. Unset fields fall back to the global
+/// connection settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileSettings {
+    /// Hostname or IP; `None` for dartmud.com.
+    pub host: Option<String>,
+    /// `None` for the standard DartMUD port.
+    pub port: Option<u16>,
+    pub tls: bool,
+    pub resolve_override: Option<String>,
+    pub tcp_keepalive_secs: Option<u64>,
+    pub telnet_nop_secs: Option<u64>,
+    pub idle_command: Option<String>,
+    pub login: Option<LoginSequence>,
+}
+
+/// Sent automatically once a profile's connection is up. The password lives
+/// in the OS keychain, not in profiles.json.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoginSequence {
+    pub username: String,
+    /// Only accepted on input (moved to the keychain on save) and read from
+    /// older plaintext profiles for migration; never written back out.
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    pub post_login_commands: Vec<String>,
+    /// Regex for the name prompt; defaults to DartMUD's.
+    pub prompt_pattern: Option<String>,
+    /// Send the sequence this long after connecting instead of waiting for
+    /// the prompt.
+    pub delay_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(flatten)]
+    pub settings: ProfileSettings,
+}
+
+impl ProfileSettings {
+    /// Reject settings that can't be connected with.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tls {
+            return Err("TLS connections are not supported yet".to_string());
+        }
+        if let Some(login) = &self.login {
+            if login.username.trim().is_empty() {
+                return Err("Auto-login needs a character name".to_string());
+            }
+            if let Some(pattern) = &login.prompt_pattern {
+                Regex::new(pattern).map_err(|e| format!("Invalid login prompt pattern: {e}"))?;
+            }
+        }
+        self.target().map(|_| ())
+    }
+
+    pub fn target(&self) -> Result<ConnectionTarget, String> {
+        ConnectionTarget::parse(
+            self.host.as_deref(),
+            self.port,
+            self.resolve_override.as_deref(),
+        )
+    }
+
+    /// Layer this profile's overrides onto the global settings.
+    pub fn apply(&self, settings: &mut ConnectionSettings) {
+        if self.tcp_keepalive_secs.is_some() {
+            settings.tcp_keepalive_secs = self.tcp_keepalive_secs;
+        }
+        if self.telnet_nop_secs.is_some() {
+            settings.telnet_nop_secs = self.telnet_nop_secs;
+        }
+        if self.idle_command.is_some() {
+            settings.idle_command = self.idle_command.clone();
+        }
+    }
+}
+
+impl Profile {
+    /// The runtime login sequence, with the password fetched from the keychain.
+    pub fn auto_login(&self) -> Result<Option<AutoLogin>, String> {
+        let Some(login) = &self.settings.login else {
+            return Ok(None);
+        };
+        let prompt = Regex::new(
+            login
+                .prompt_pattern
+                .as_deref()
+                .unwrap_or(DEFAULT_LOGIN_PROMPT),
+        )
+        .map_err(|e| format!("Invalid login prompt pattern: {e}"))?;
+        Ok(Some(AutoLogin {
+            username: login.username.trim().to_string(),
+            password: get_password(&self.name)?,
+            commands: login.post_login_commands.clone(),
+            prompt,
+            delay: login.delay_ms.map(Duration::from_millis),
+        }))
+    }
+}
+
+fn keyring_entry(name: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(crate::KEYRING_SERVICE, &format!("profile:{name}"))
+        .map_err(|e| format!("Keyring error: {e}"))
+}
+
+/// Store a profile's password in the keychain, or delete it with `None`.
+pub fn set_password(name: &str, password: Option<&str>) -> Result<(), String> {
+    let entry = keyring_entry(name)?;
+    match password {
+        Some(password) => entry
+            .set_password(password)
+            .map_err(|e| format!("Failed to store credential: {e}")),
+        None => match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Failed to delete credential: {e}")),
+        },
+    }
+}
+
+fn get_password(name: &str) -> Result<Option<String>, String> {
+    match keyring_entry(name)?.get_password() {
+        Ok(password) => Ok(Some(password)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to get credential: {e}")),
+    }
+}
+
+/// Normalize a profile name the same way backup tags are, rejecting names
+/// that end up empty.
+pub fn sanitize_name(name: &str) -> Result<String, String> {
+    let sanitized = crate::storage::sanitize_tag(name.trim());
+    if sanitized.is_empty() {
+        return Err(format!("Invalid profile name: {name}"));
+    }
+    Ok(sanitized)
+}
+
+/// The profile's own data directory, `profiles/<name>`, or `None` if the name
+/// can't be used as a path.
+pub(crate) fn data_dir(dir: &Path, name: &str) -> Option<PathBuf> {
+    crate::storage::validate_data_path(&format!("profiles/{name}")).ok()?;
+    Some(dir.join("profiles").join(name))
+}
+
+/// Load all profiles, moving any plaintext password left in profiles.json
+/// into the keychain (and rewriting the file without it).
+pub fn load(dir: &Path) -> BTreeMap<String, ProfileSettings> {
+    let mut profiles: BTreeMap<String, ProfileSettings> =
+        crate::storage::read_json_file(dir, PROFILES_FILE).unwrap_or_default();
+
+    let mut migrated = false;
+    for (name, settings) in profiles.iter_mut() {
+        let Some(password) = settings.login.as_mut().and_then(|l| l.password.take()) else {
+            continue;
+        };
+        match set_password(name, Some(&password)) {
+            Ok(()) => {
+                info!("Moved the password for profile {name} into the keychain");
+                migrated = true;
+            }
+            Err(e) => warn!("Could not migrate the password for profile {name}: {e}"),
+        }
+    }
+    if migrated {
+        if let Err(e) = save(dir, &profiles) {
+            warn!("Failed to rewrite {PROFILES_FILE} after password migration: {e}");
+        }
+    }
+    profiles
+}
+
+pub fn save(dir: &Path, profiles: &BTreeMap<String, ProfileSettings>) -> Result<(), String> {
+    crate::storage::write_json_file(dir, PROFILES_FILE, profiles)
+}
+
+pub fn get(dir: &Path, name: &str) -> Result<Profile, String> {
+    let name = sanitize_name(name)?;
+    let settings = load(dir)
+        .remove(&name)
+        .ok_or_else(|| format!("No profile named {name}"))?;
+    Ok(Profile { name, settings })
+}
@@ -0,0 +1,432 @@
+//! Room capture for mappers. The line stream is watched for a room's exits
+//! line; the room's name is the title line before it (the first line of the
+//! output since the last prompt, unless a title pattern says otherwise). Each
+//! room seen is emitted as `mud:room` along with the movement command that
+//! led there, if one was sent lately and didn't fail.
+//!
+//! With a profile active, rooms and the exits taken between them are added
+//! to `profiles/<name>/map.json`, written within a few seconds of a change.
+//! An edge is only added when both the room moved from and the direction are
+//! known, so a misparse adds a stray room at worst, never a wrong link. The
+//! patterns come from `room-parser.json`, with DartMUD's formats by default.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+use tokio::time::{Duration, Instant};
+
+use crate::connection::ConnectionShared;
+use crate::events::{self, now_ms, RoomPayload, ROOM_EVENT};
+use crate::lines;
+use crate::profiles;
+use crate::storage;
+
+pub const ROOM_PARSER_FILE: &str = "room-parser.json";
+pub const MAP_FILE: &str = "map.json";
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+const DEFAULT_EXITS: &str =
+    r"^There (?:is one obvious exit|are \w+ (?:obvious )?exits): (?P<exits>.+?)\.?$";
+const DEFAULT_MOVE_FAILED: [&str; 5] = [
+    r"^There is no exit in that direction\.",
+    r"^The .+ is closed\.",
+    r"^You (?:can't|cannot) go that way\.",
+    r"^You can't see to move!",
+    r"^You must swim ",
+];
+/// Movement commands and the direction each stands for.
+const DIRECTIONS: [(&str, &str); 20] = [
+    ("n", "north"),
+    ("s", "south"),
+    ("e", "east"),
+    ("w", "west"),
+    ("ne", "northeast"),
+    ("nw", "northwest"),
+    ("se", "southeast"),
+    ("sw", "southwest"),
+    ("u", "up"),
+    ("d", "down"),
+    ("north", "north"),
+    ("south", "south"),
+    ("east", "east"),
+    ("west", "west"),
+    ("northeast", "northeast"),
+    ("northwest", "northwest"),
+    ("southeast", "southeast"),
+    ("southwest", "southwest"),
+    ("up", "up"),
+    ("down", "down"),
+];
+/// A movement command older than this when a room appears didn't lead there.
+const MOVE_MAX_AGE: Duration = Duration::from_secs(10);
+/// Lines kept since the last prompt while looking for the title.
+const MAX_BLOCK_LINES: usize = 50;
+/// How often changed maps are written.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Contents of room-parser.json. Unset patterns use the DartMUD defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoomParserFile {
+    pub enabled: bool,
+    /// Matches the room's title line; a `name` group, if present, is the
+    /// name. Without it the first line since the last prompt is the title.
+    pub title: Option<String>,
+    /// Matches the exits line; the `exits` group is the comma or
+    /// "and"-separated list.
+    pub exits: Option<String>,
+    /// Lines saying the last movement command failed.
+    pub move_failed: Option<Vec<String>>,
+    /// Extra movement commands (`enter`, `climb`), taken as typed.
+    pub directions: Vec<String>,
+}
+
+impl Default for RoomParserFile {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            title: None,
+            exits: None,
+            move_failed: None,
+            directions: Vec::new(),
+        }
+    }
+}
+
+impl RoomParserFile {
+    pub fn load(dir: &Path) -> Self {
+        storage::read_json_file(dir, ROOM_PARSER_FILE).unwrap_or_default()
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {e}"))
+}
+
+struct CompiledRules {
+    enabled: bool,
+    title: Option<Regex>,
+    exits: Regex,
+    move_failed: Vec<Regex>,
+    /// Lowercased command → direction.
+    directions: HashMap<String, String>,
+}
+
+impl CompiledRules {
+    fn compile(file: &RoomParserFile) -> Self {
+        let mut directions: HashMap<String, String> = DIRECTIONS
+            .iter()
+            .map(|(command, dir)| (command.to_string(), dir.to_string()))
+            .collect();
+        for command in &file.directions {
+            let command = command.trim().to_lowercase();
+            if !command.is_empty() {
+                directions.insert(command.clone(), command);
+            }
+        }
+        let title = file.title.as_deref().and_then(|pattern| {
+            compile(pattern)
+                .map_err(|e| warn!("Room parser title: {e}"))
+                .ok()
+        });
+        let exits = file
+            .exits
+            .as_deref()
+            .and_then(|pattern| {
+                compile(pattern)
+                    .map_err(|e| warn!("Room parser exits: {e}; using the default"))
+                    .ok()
+            })
+            .unwrap_or_else(|| compile(DEFAULT_EXITS).expect("default exits pattern is valid"));
+        let move_failed = match &file.move_failed {
+            Some(patterns) => patterns.iter().map(String::as_str).collect::<Vec<_>>(),
+            None => DEFAULT_MOVE_FAILED.to_vec(),
+        };
+        let move_failed = move_failed
+            .into_iter()
+            .filter_map(|pattern| {
+                compile(pattern)
+                    .map_err(|e| warn!("Room parser move_failed: {e}"))
+                    .ok()
+            })
+            .collect();
+        Self {
+            enabled: file.enabled,
+            title,
+            exits,
+            move_failed,
+            directions,
+        }
+    }
+}
+
+pub struct RoomRules {
+    rules: Mutex<CompiledRules>,
+}
+
+impl Default for RoomRules {
+    fn default() -> Self {
+        Self {
+            rules: Mutex::new(CompiledRules::compile(&RoomParserFile::default())),
+        }
+    }
+}
+
+impl RoomRules {
+    fn lock(&self) -> std::sync::MutexGuard<'_, CompiledRules> {
+        self.rules.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Re-read room-parser.json. Invalid patterns are skipped, or replaced
+    /// by the default for the exits pattern.
+    pub fn reload(&self, dir: &Path) {
+        let file = RoomParserFile::load(dir);
+        *self.lock() = CompiledRules::compile(&file);
+        info!(
+            "Loaded room parser ({})",
+            if file.enabled { "enabled" } else { "disabled" }
+        );
+    }
+
+    /// The direction `command` moves in, if it is a movement command.
+    pub fn direction(&self, command: &str) -> Option<String> {
+        let rules = self.lock();
+        if !rules.enabled {
+            return None;
+        }
+        rules
+            .directions
+            .get(&command.trim().to_lowercase())
+            .cloned()
+    }
+}
+
+/// A room in the map, keyed by `room_id`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapRoom {
+    pub name: String,
+    pub exits: Vec<String>,
+    /// Direction taken → room id reached.
+    pub links: BTreeMap<String, String>,
+    /// Unix millis.
+    pub last_seen: u64,
+}
+
+/// Contents of `profiles/<name>/map.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapGraph {
+    pub rooms: BTreeMap<String, MapRoom>,
+}
+
+/// Rooms are told apart by name and exits, which is as much as the output
+/// gives.
+fn room_id(name: &str, exits: &[String]) -> String {
+    let mut exits = exits.to_vec();
+    exits.sort();
+    format!("{name} [{}]", exits.join(","))
+}
+
+struct LoadedMap {
+    graph: MapGraph,
+    dirty: bool,
+}
+
+/// Map graphs loaded so far, by profile folder, shared by all sessions.
+#[derive(Default)]
+pub struct MapStore {
+    maps: Mutex<HashMap<PathBuf, LoadedMap>>,
+}
+
+/// `profiles/<profile>` in the data dir, if the name is usable as a folder.
+impl MapStore {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, LoadedMap>> {
+        self.maps.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Add the room to the map in `dir`, linked from `from` if known.
+    fn record(&self, dir: PathBuf, id: &str, room: &RoomPayload, from: Option<&str>) {
+        let mut maps = self.lock();
+        let map = maps.entry(dir).or_insert_with_key(|dir| LoadedMap {
+            graph: storage::read_json_file(dir, MAP_FILE).unwrap_or_default(),
+            dirty: false,
+        });
+        let entry = map.graph.rooms.entry(id.to_string()).or_default();
+        entry.name = room.name.clone();
+        entry.exits = room.exits.clone();
+        entry.last_seen = now_ms();
+        if let (Some(from), Some(direction)) = (from, &room.moved_via) {
+            if let Some(previous) = map.graph.rooms.get_mut(from) {
+                previous.links.insert(direction.clone(), id.to_string());
+            }
+        }
+        map.dirty = true;
+    }
+
+    /// Write every map changed since the last flush.
+    pub fn flush(&self) {
+        let pending: Vec<(PathBuf, MapGraph)> = self
+            .lock()
+            .iter_mut()
+            .filter_map(|(dir, map)| {
+                std::mem::take(&mut map.dirty).then(|| (dir.clone(), map.graph.clone()))
+            })
+            .collect();
+        for (dir, graph) in pending {
+            if let Err(e) = storage::write_json_file(&dir, MAP_FILE, &graph) {
+                warn!("Failed to save {}: {e}", dir.join(MAP_FILE).display());
+            }
+        }
+    }
+}
+
+/// Per-connection room tracking: the partial line, the lines since the last
+/// prompt, and the room last seen.
+#[derive(Default)]
+pub struct RoomTracker {
+    partial: String,
+    block: Vec<String>,
+    current: Option<String>,
+    /// Generation of the connection it tracks, carried by its events.
+    generation: u64,
+}
+
+impl RoomTracker {
+    pub fn new(generation: u64) -> Self {
+        Self {
+            generation,
+            ..Default::default()
+        }
+    }
+
+    /// Follow display text.
+    pub fn feed<R: Runtime>(
+        &mut self,
+        app: &AppHandle<R>,
+        shared: &ConnectionShared,
+        display: &str,
+        ga: bool,
+    ) {
+        let mut rest = display;
+        while let Some(pos) = rest.find('\n') {
+            let mut line = std::mem::take(&mut self.partial);
+            line.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+            self.on_line(app, shared, &line);
+        }
+        if self.partial.len() < lines::MAX_MATCH_LEN {
+            self.partial.push_str(rest);
+        }
+        if ga {
+            // The text before a GA is the prompt, not part of the next room
+            self.partial.clear();
+            self.block.clear();
+        }
+    }
+
+    fn on_line<R: Runtime>(&mut self, app: &AppHandle<R>, shared: &ConnectionShared, line: &str) {
+        let text = lines::match_text(line);
+        let text = text.trim_start_matches("> ").trim();
+        let rules = shared.rooms.lock();
+        if !rules.enabled || text.is_empty() {
+            return;
+        }
+        if rules.move_failed.iter().any(|regex| regex.is_match(text)) {
+            shared
+                .last_move
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take();
+            return;
+        }
+        let Some(exits) = rules
+            .exits
+            .captures(text)
+            .and_then(|caps| caps.name("exits"))
+        else {
+            if self.block.len() < MAX_BLOCK_LINES {
+                self.block.push(text.to_string());
+            }
+            return;
+        };
+        let exits: Vec<String> = exits
+            .as_str()
+            .split(',')
+            .flat_map(|part| part.split(" and "))
+            .map(|exit| exit.trim().trim_end_matches('.').to_lowercase())
+            .filter(|exit| !exit.is_empty())
+            .collect();
+        let name = match &rules.title {
+            Some(title) => self.block.iter().rev().find_map(|line| {
+                let caps = title.captures(line)?;
+                Some(
+                    caps.name("name")
+                        .unwrap_or(caps.get(0)?)
+                        .as_str()
+                        .trim()
+                        .to_string(),
+                )
+            }),
+            None => self.block.first().cloned(),
+        };
+        drop(rules);
+        self.block.clear();
+        let Some(name) = name.filter(|name| !name.is_empty()) else {
+            return;
+        };
+        let moved_via = shared
+            .last_move
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .filter(|(_, at)| at.elapsed() <= MOVE_MAX_AGE)
+            .map(|(direction, _)| direction);
+        let room = RoomPayload {
+            session: shared.session.clone(),
+            name,
+            exits,
+            moved_via,
+            generation: self.generation,
+        };
+        let id = room_id(&room.name, &room.exits);
+        let profile = shared
+            .active_profile
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map(|p| p.name.clone());
+        let data_dir = app.state::<storage::StorageState>().get_dir();
+        if let Some(dir) = profile.and_then(|profile| profiles::data_dir(&data_dir, &profile)) {
+            shared.maps.record(dir, &id, &room, self.current.as_deref());
+        }
+        self.current = Some(id);
+        let _ = events::emit(app, ROOM_EVENT, room);
+    }
+}
+
+/// Note a command written to the socket, remembering it if it moves.
+pub fn command_sent(shared: &ConnectionShared, command: &str) {
+    if let Some(direction) = shared.rooms.direction(command) {
+        *shared.last_move.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some((direction, Instant::now()));
+    }
+}
+
+/// Write changed maps every `FLUSH_INTERVAL`. Runs for the app's lifetime.
+pub async fn run(maps: Arc<MapStore>) {
+    let mut tick = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        tick.tick().await;
+        let maps = maps.clone();
+        let _ = tokio::task::spawn_blocking(move || maps.flush()).await;
+    }
+}
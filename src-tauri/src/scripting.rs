@@ -0,0 +1,585 @@
+//! Rhai scripts for automation beyond what regex triggers can do. Every
+//! `scripts/*.rhai` in the data dir is compiled on `reload_scripts` (and at
+//! startup) and its top level run once. A script can then define any of these
+//! hooks, called for every session:
+//!
+//! - `on_line(line)`: each complete line, ANSI-stripped
+//! - `on_prompt(prompt)`: each GA-terminated prompt
+//! - `on_connect()`: once a connection is established
+//! - `on_gmcp(package, data)`: each GMCP message, `data` parsed from JSON
+//!
+//! and call back into the client with `send(cmd)` (through aliases and the
+//! rate limiter, to the session the hook ran for), `echo(text)` (a note in the
+//! output, never sent), `set_var(name, value)` / `get_var(name)` (kept in
+//! `script-vars.json`) and `timer(secs, fn)` (call `fn` once, later).
+//!
+//! Scripts run on their own thread, fed by a channel, so a slow one holds up
+//! other scripts but never the connection. Each run is capped by an operation
+//! count and `HOOK_BUDGET` of wall time, and scripts can't reach files or load
+//! modules. Errors are emitted as `mud:script-error` and the script stays
+//! loaded; the next event calls it again.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use rhai::{CallFnOptions, Dynamic, Engine, FnPtr, Scope, AST};
+use tauri::{AppHandle, Manager};
+use tokio::sync::oneshot;
+
+use crate::connection::{self, DEFAULT_SESSION};
+use crate::events::{self, MudOutputPayload, ScriptErrorPayload, SCRIPT_ERROR_EVENT};
+use crate::lines;
+
+pub const SCRIPTS_DIR: &str = "scripts";
+pub const SCRIPT_VARS_FILE: &str = "script-vars.json";
+/// Wall time one hook call (or a script's top level) may take.
+const HOOK_BUDGET: Duration = Duration::from_millis(100);
+/// Operations one hook call may take, whatever the time.
+const MAX_OPERATIONS: u64 = 5_000_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 64 * 1024;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+/// Scripts bigger than this aren't loaded.
+const MAX_SCRIPT_BYTES: u64 = 256 * 1024;
+/// Timers pending at once, across all scripts.
+const MAX_TIMERS: usize = 100;
+const MAX_VARS: usize = 1000;
+/// Events queued for the script thread; more are dropped.
+const QUEUE_SIZE: usize = 1024;
+/// Changed variables are written out at most this often.
+const VARS_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+enum ScriptEvent {
+    Line {
+        session: String,
+        line: String,
+    },
+    Prompt {
+        session: String,
+        prompt: String,
+    },
+    Connect {
+        session: String,
+    },
+    Gmcp {
+        session: String,
+        package: String,
+        data: String,
+    },
+    Reload {
+        dir: PathBuf,
+        reply: Option<oneshot::Sender<Vec<String>>>,
+    },
+}
+
+/// The hooks some loaded script defines.
+#[derive(Default)]
+struct Hooks {
+    line: AtomicBool,
+    prompt: AtomicBool,
+    connect: AtomicBool,
+    gmcp: AtomicBool,
+}
+
+/// The connection side of scripting: queues events for the script thread.
+#[derive(Default)]
+pub struct Scripts {
+    tx: Mutex<Option<SyncSender<ScriptEvent>>>,
+    hooks: Arc<Hooks>,
+    /// An event was dropped with the queue full; cleared once one fits.
+    dropping: AtomicBool,
+}
+
+impl Scripts {
+    /// Start the script thread. Scripts are loaded by the first `reload`.
+    pub fn start(&self, app: AppHandle) {
+        let (tx, rx) = mpsc::sync_channel(QUEUE_SIZE);
+        let hooks = self.hooks.clone();
+        let spawned = std::thread::Builder::new()
+            .name("scripts".into())
+            .spawn(move || {
+                let mut runner = Runner::new(app, hooks);
+                runner.run(rx);
+            });
+        match spawned {
+            Ok(_) => *self.tx.lock().unwrap_or_else(|e| e.into_inner()) = Some(tx),
+            Err(e) => warn!("Failed to start the script thread: {e}"),
+        }
+    }
+
+    fn send(&self, event: ScriptEvent) {
+        let tx = self.tx.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(tx) = tx.as_ref() else {
+            return;
+        };
+        match tx.try_send(event) {
+            Ok(()) => self.dropping.store(false, Ordering::Relaxed),
+            Err(TrySendError::Full(_)) => {
+                if !self.dropping.swap(true, Ordering::Relaxed) {
+                    warn!("Scripts are falling behind; dropping events");
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// Load the scripts in `dir` afresh, returning the names of those
+    /// loaded, or `None` if the script thread isn't running.
+    pub async fn reload(&self, dir: &Path) -> Option<Vec<String>> {
+        let (reply, loaded) = oneshot::channel();
+        self.send(ScriptEvent::Reload {
+            dir: dir.to_path_buf(),
+            reply: Some(reply),
+        });
+        loaded.await.ok()
+    }
+
+    /// Like `reload`, without waiting for it.
+    pub fn reload_in_background(&self, dir: &Path) {
+        self.send(ScriptEvent::Reload {
+            dir: dir.to_path_buf(),
+            reply: None,
+        });
+    }
+
+    /// Some script wants every line.
+    pub fn wants_lines(&self) -> bool {
+        self.hooks.line.load(Ordering::Relaxed)
+    }
+
+    pub fn line(&self, session: &str, line: &str) {
+        if self.wants_lines() {
+            let line = lines::match_text(line).trim_end_matches('\r').to_string();
+            self.send(ScriptEvent::Line {
+                session: session.to_string(),
+                line,
+            });
+        }
+    }
+
+    pub fn prompt(&self, session: &str, prompt: &str) {
+        if self.hooks.prompt.load(Ordering::Relaxed) {
+            self.send(ScriptEvent::Prompt {
+                session: session.to_string(),
+                prompt: prompt.to_string(),
+            });
+        }
+    }
+
+    pub fn connected(&self, session: &str) {
+        if self.hooks.connect.load(Ordering::Relaxed) {
+            self.send(ScriptEvent::Connect {
+                session: session.to_string(),
+            });
+        }
+    }
+
+    pub fn gmcp(&self, session: &str, package: &str, data: &str) {
+        if self.hooks.gmcp.load(Ordering::Relaxed) {
+            let (session, package, data) =
+                (session.to_string(), package.to_string(), data.to_string());
+            self.send(ScriptEvent::Gmcp {
+                session,
+                package,
+                data,
+            });
+        }
+    }
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+}
+
+struct Timer {
+    at: Instant,
+    /// Index into the scripts loaded when it was set.
+    script: usize,
+    callback: FnPtr,
+    session: String,
+}
+
+/// State the host functions share with the runner.
+#[derive(Default)]
+struct Host {
+    /// The session the running hook is for; `send` and `echo` go to it.
+    session: String,
+    /// The script running, for timers it sets.
+    script: usize,
+    vars: BTreeMap<String, serde_json::Value>,
+    vars_dirty: bool,
+    timers: Vec<Timer>,
+}
+
+struct Runner {
+    app: AppHandle,
+    hooks: Arc<Hooks>,
+    engine: Engine,
+    host: Rc<RefCell<Host>>,
+    /// When the running call must be stopped.
+    deadline: Rc<Cell<Instant>>,
+    scripts: Vec<Script>,
+    dir: Option<PathBuf>,
+    vars_saved_at: Instant,
+}
+
+impl Runner {
+    fn new(app: AppHandle, hooks: Arc<Hooks>) -> Self {
+        let host = Rc::new(RefCell::new(Host::default()));
+        let deadline = Rc::new(Cell::new(Instant::now()));
+        let engine = build_engine(&app, &host, &deadline);
+        Self {
+            app,
+            hooks,
+            engine,
+            host,
+            deadline,
+            scripts: Vec::new(),
+            dir: None,
+            vars_saved_at: Instant::now(),
+        }
+    }
+
+    fn run(&mut self, rx: mpsc::Receiver<ScriptEvent>) {
+        loop {
+            let wait = self
+                .next_wake()
+                .map(|at| at.saturating_duration_since(Instant::now()));
+            let event = match wait {
+                Some(wait) => rx.recv_timeout(wait),
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match event {
+                Ok(event) => self.handle(event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.fire_timers();
+            self.save_vars(false);
+        }
+        self.save_vars(true);
+    }
+
+    /// The next timer due, or the next chance to save changed variables.
+    fn next_wake(&self) -> Option<Instant> {
+        let host = self.host.borrow();
+        let timer = host.timers.iter().map(|t| t.at).min();
+        let save = host
+            .vars_dirty
+            .then(|| self.vars_saved_at + VARS_SAVE_INTERVAL);
+        timer.into_iter().chain(save).min()
+    }
+
+    fn handle(&mut self, event: ScriptEvent) {
+        match event {
+            ScriptEvent::Line { session, line } => {
+                self.call_hook(&session, "on_line", vec![line.into()])
+            }
+            ScriptEvent::Prompt { session, prompt } => {
+                self.call_hook(&session, "on_prompt", vec![prompt.into()])
+            }
+            ScriptEvent::Connect { session } => self.call_hook(&session, "on_connect", Vec::new()),
+            ScriptEvent::Gmcp {
+                session,
+                package,
+                data,
+            } => {
+                let data = serde_json::from_str::<serde_json::Value>(&data)
+                    .ok()
+                    .and_then(|value| rhai::serde::to_dynamic(value).ok())
+                    .unwrap_or_else(|| Dynamic::from(data));
+                self.call_hook(&session, "on_gmcp", vec![package.into(), data]);
+            }
+            ScriptEvent::Reload { dir, reply } => {
+                let loaded = self.load(&dir);
+                if let Some(reply) = reply {
+                    let _ = reply.send(loaded);
+                }
+            }
+        }
+    }
+
+    fn load(&mut self, dir: &Path) -> Vec<String> {
+        self.save_vars(true);
+        let vars: BTreeMap<String, serde_json::Value> =
+            crate::storage::read_json_file(dir, SCRIPT_VARS_FILE).unwrap_or_default();
+        *self.host.borrow_mut() = Host {
+            vars,
+            ..Host::default()
+        };
+        self.dir = Some(dir.to_path_buf());
+        self.scripts.clear();
+
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir.join(SCRIPTS_DIR))
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_default();
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "rhai") && path.is_file());
+        paths.sort();
+        for path in paths {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let source = match std::fs::metadata(&path) {
+                Ok(meta) if meta.len() > MAX_SCRIPT_BYTES => {
+                    Err(format!("Larger than {MAX_SCRIPT_BYTES} bytes"))
+                }
+                _ => std::fs::read_to_string(&path).map_err(|e| format!("Failed to read: {e}")),
+            };
+            let ast =
+                source.and_then(|source| self.engine.compile(source).map_err(|e| e.to_string()));
+            match ast {
+                Ok(ast) => self.scripts.push(Script { name, ast }),
+                Err(message) => self.error(&name, "load", message),
+            }
+        }
+
+        // Top levels run in order, so an earlier script's timers and
+        // variables are there for later ones
+        let mut failed = Vec::new();
+        for index in 0..self.scripts.len() {
+            self.begin(DEFAULT_SESSION, index);
+            if let Err(e) = self
+                .engine
+                .run_ast_with_scope(&mut Scope::new(), &self.scripts[index].ast)
+            {
+                self.error(&self.scripts[index].name, "load", describe(&e));
+                failed.push(index);
+            }
+        }
+        if !failed.is_empty() {
+            let mut index = 0;
+            self.scripts.retain(|_| {
+                index += 1;
+                !failed.contains(&(index - 1))
+            });
+            // Timers point at script indexes, which just moved
+            self.host.borrow_mut().timers.clear();
+        }
+
+        let defines = |hook: &str, params: usize| {
+            self.scripts.iter().any(|s| {
+                s.ast
+                    .iter_functions()
+                    .any(|f| f.name == hook && f.params.len() == params)
+            })
+        };
+        self.hooks
+            .line
+            .store(defines("on_line", 1), Ordering::Relaxed);
+        self.hooks
+            .prompt
+            .store(defines("on_prompt", 1), Ordering::Relaxed);
+        self.hooks
+            .connect
+            .store(defines("on_connect", 0), Ordering::Relaxed);
+        self.hooks
+            .gmcp
+            .store(defines("on_gmcp", 2), Ordering::Relaxed);
+        let names: Vec<String> = self.scripts.iter().map(|s| s.name.clone()).collect();
+        info!("Loaded {} script(s)", names.len());
+        names
+    }
+
+    /// Set up the host state and time budget for a call into `script`.
+    fn begin(&self, session: &str, script: usize) {
+        let mut host = self.host.borrow_mut();
+        host.session = session.to_string();
+        host.script = script;
+        self.deadline.set(Instant::now() + HOOK_BUDGET);
+    }
+
+    /// Call `hook` in every script that defines it.
+    fn call_hook(&self, session: &str, hook: &str, args: Vec<Dynamic>) {
+        for (index, script) in self.scripts.iter().enumerate() {
+            if !script
+                .ast
+                .iter_functions()
+                .any(|f| f.name == hook && f.params.len() == args.len())
+            {
+                continue;
+            }
+            self.begin(session, index);
+            let options = CallFnOptions::new().eval_ast(false);
+            let result = self.engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut Scope::new(),
+                &script.ast,
+                hook,
+                args.clone(),
+            );
+            if let Err(e) = result {
+                self.error(&script.name, hook, describe(&e));
+            }
+        }
+    }
+
+    fn fire_timers(&mut self) {
+        let now = Instant::now();
+        let due: Vec<Timer> = {
+            let mut host = self.host.borrow_mut();
+            let (due, pending) = std::mem::take(&mut host.timers)
+                .into_iter()
+                .partition(|t| t.at <= now);
+            host.timers = pending;
+            due
+        };
+        for timer in due {
+            let Some(script) = self.scripts.get(timer.script) else {
+                continue;
+            };
+            self.begin(&timer.session, timer.script);
+            if let Err(e) = timer
+                .callback
+                .call::<Dynamic>(&self.engine, &script.ast, ())
+            {
+                self.error(&script.name, "timer", describe(&e));
+            }
+        }
+    }
+
+    fn save_vars(&mut self, now: bool) {
+        let Some(dir) = self.dir.as_ref() else {
+            return;
+        };
+        let mut host = self.host.borrow_mut();
+        if !host.vars_dirty || (!now && self.vars_saved_at.elapsed() < VARS_SAVE_INTERVAL) {
+            return;
+        }
+        host.vars_dirty = false;
+        self.vars_saved_at = Instant::now();
+        if let Err(e) = crate::storage::write_json_file(dir, SCRIPT_VARS_FILE, &host.vars) {
+            warn!("Failed to save script variables: {e}");
+        }
+    }
+
+    fn error(&self, script: &str, hook: &str, message: String) {
+        warn!("Script {script} ({hook}): {message}");
+        let payload = ScriptErrorPayload {
+            script: script.to_string(),
+            hook: hook.to_string(),
+            message,
+        };
+        let _ = events::emit(&self.app, SCRIPT_ERROR_EVENT, payload);
+    }
+}
+
+fn describe(error: &rhai::EvalAltResult) -> String {
+    match error {
+        rhai::EvalAltResult::ErrorTerminated(..) => {
+            format!(
+                "Stopped after running longer than {}ms",
+                HOOK_BUDGET.as_millis()
+            )
+        }
+        e => e.to_string(),
+    }
+}
+
+/// The sandboxed engine with the host API registered.
+fn build_engine(app: &AppHandle, host: &Rc<RefCell<Host>>, deadline: &Rc<Cell<Instant>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+    let deadline = deadline.clone();
+    engine.on_progress(move |_| (Instant::now() > deadline.get()).then_some(Dynamic::UNIT));
+    engine.on_print(|text| debug!("Script: {text}"));
+    engine.on_debug(|text, _, _| debug!("Script: {text}"));
+
+    let (send_app, send_host) = (app.clone(), host.clone());
+    engine.register_fn("send", move |command: &str| -> bool {
+        let session = send_host.borrow().session.clone();
+        with_session(&send_app, &session, |shared| {
+            connection::send_backend_command(shared, command, "Script")
+        })
+        .unwrap_or(false)
+    });
+
+    let (echo_app, echo_host) = (app.clone(), host.clone());
+    engine.register_fn("echo", move |text: &str| {
+        let session = echo_host.borrow().session.clone();
+        with_session(&echo_app, &session, |shared| {
+            let data = format!("\x1b[36m{}\x1b[0m\r\n", text.replace('\n', "\r\n"));
+            let payload = MudOutputPayload {
+                data,
+                ga: false,
+                session: session.clone(),
+                buffered: false,
+                generation: shared.generation(),
+            };
+            shared.output_pause.deliver(&echo_app, payload);
+        });
+    });
+
+    let set_host = host.clone();
+    engine.register_fn(
+        "set_var",
+        move |name: &str, value: Dynamic| -> Result<(), Box<rhai::EvalAltResult>> {
+            let value: serde_json::Value = rhai::serde::from_dynamic(&value)?;
+            let mut host = set_host.borrow_mut();
+            if host.vars.len() >= MAX_VARS && !host.vars.contains_key(name) {
+                return Err(format!("At most {MAX_VARS} script variables can be set").into());
+            }
+            host.vars.insert(name.to_string(), value);
+            host.vars_dirty = true;
+            Ok(())
+        },
+    );
+
+    let get_host = host.clone();
+    engine.register_fn("get_var", move |name: &str| -> Dynamic {
+        let host = get_host.borrow();
+        host.vars
+            .get(name)
+            .and_then(|value| rhai::serde::to_dynamic(value).ok())
+            .unwrap_or(Dynamic::UNIT)
+    });
+
+    let timer_host = host.clone();
+    let timer = move |secs: f64, callback: FnPtr| -> Result<(), Box<rhai::EvalAltResult>> {
+        let mut host = timer_host.borrow_mut();
+        if host.timers.len() >= MAX_TIMERS {
+            return Err(format!("At most {MAX_TIMERS} timers can be pending").into());
+        }
+        let delay = Duration::try_from_secs_f64(secs.max(0.0)).map_err(|e| e.to_string())?;
+        let (script, session) = (host.script, host.session.clone());
+        host.timers.push(Timer {
+            at: Instant::now() + delay,
+            script,
+            callback,
+            session,
+        });
+        Ok(())
+    };
+    let int_timer = timer.clone();
+    engine.register_fn("timer", timer);
+    engine.register_fn("timer", move |secs: i64, callback: FnPtr| {
+        int_timer(secs as f64, callback)
+    });
+    engine
+}
+
+/// Run `f` against the session's connection state, if the session exists.
+fn with_session<T>(
+    app: &AppHandle,
+    session: &str,
+    f: impl FnOnce(&connection::ConnectionShared) -> T,
+) -> Option<T> {
+    let session = app
+        .state::<crate::ConnectionState>()
+        .session(Some(session))
+        .ok()?;
+    Some(f(&session.shared))
+}
@@ -0,0 +1,212 @@
+//! Backend copy of recent MUD display text, so the frontend can repopulate
+//! the terminal after a webview reload without losing the live connection,
+//! and so it can be copied or exported as the server sent it rather than as
+//! the terminal wrapped it.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use chrono::{Local, TimeZone};
+use serde::Serialize;
+
+use crate::ansi;
+use crate::events::now_ms;
+
+pub const DEFAULT_SCROLLBACK_BYTES: usize = 1024 * 1024;
+
+struct ScrollbackInner {
+    /// Display text in emit order, with when each chunk was appended (Unix
+    /// millis). Kept as the emitted chunks so appending never copies what's
+    /// already buffered.
+    chunks: VecDeque<(u64, String)>,
+    bytes: usize,
+    capacity: usize,
+}
+
+/// Ring buffer of the last `capacity` bytes of display text.
+pub struct Scrollback {
+    inner: Mutex<ScrollbackInner>,
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(ScrollbackInner {
+                chunks: VecDeque::new(),
+                bytes: 0,
+                capacity: DEFAULT_SCROLLBACK_BYTES,
+            }),
+        }
+    }
+}
+
+impl ScrollbackInner {
+    /// Drop the oldest text until the buffer fits its capacity. The oldest
+    /// chunk is trimmed on a char boundary rather than dropped whole, so a
+    /// single huge chunk still leaves its tail behind.
+    fn evict(&mut self) {
+        while self.bytes > self.capacity {
+            let Some((_, front)) = self.chunks.front_mut() else {
+                break;
+            };
+            let excess = self.bytes - self.capacity;
+            if front.len() <= excess {
+                self.bytes -= front.len();
+                self.chunks.pop_front();
+            } else {
+                let mut cut = excess;
+                while !front.is_char_boundary(cut) {
+                    cut += 1;
+                }
+                front.drain(..cut);
+                self.bytes -= cut;
+            }
+        }
+    }
+
+    /// The chunks holding the last `max_bytes` (all of them when `None`), the
+    /// first cut on a char boundary if it straddles the limit.
+    fn tail(&self, max_bytes: Option<usize>) -> impl Iterator<Item = (u64, &str)> {
+        let limit = max_bytes.unwrap_or(self.bytes).min(self.bytes);
+        let mut skip = self.bytes - limit;
+        self.chunks.iter().filter_map(move |(at, chunk)| {
+            if skip >= chunk.len() {
+                skip -= chunk.len();
+                return None;
+            }
+            let mut start = skip;
+            while !chunk.is_char_boundary(start) {
+                start += 1;
+            }
+            skip = 0;
+            Some((*at, &chunk[start..])).filter(|(_, text)| !text.is_empty())
+        })
+    }
+}
+
+impl Scrollback {
+    fn lock(&self) -> std::sync::MutexGuard<'_, ScrollbackInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn append(&self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let mut inner = self.lock();
+        inner.bytes += text.len();
+        inner.chunks.push_back((now_ms(), text.to_string()));
+        inner.evict();
+    }
+
+    /// The buffered text, or only its last `max_bytes` (rounded to a char
+    /// boundary) when given.
+    pub fn snapshot(&self, max_bytes: Option<usize>) -> String {
+        let inner = self.lock();
+        let mut out = String::new();
+        for (_, chunk) in inner.tail(max_bytes) {
+            out.push_str(chunk);
+        }
+        out
+    }
+
+    /// Like `snapshot`, but as the chunks it was appended in, each with when
+    /// it arrived.
+    pub fn chunks(&self, max_bytes: Option<usize>) -> Vec<ScrollbackChunk> {
+        let inner = self.lock();
+        inner
+            .tail(max_bytes)
+            .map(|(ts_ms, data)| ScrollbackChunk {
+                ts_ms,
+                data: data.to_string(),
+            })
+            .collect()
+    }
+
+    /// The buffered text split into lines, without line endings, oldest
+    /// first; only the last `max_lines` when given. A line is stamped with the
+    /// time its first chunk arrived.
+    pub fn lines(&self, max_lines: Option<usize>) -> Vec<ScrollbackLine> {
+        let inner = self.lock();
+        let mut lines = Vec::new();
+        let mut current: Option<ScrollbackLine> = None;
+        for (at, chunk) in &inner.chunks {
+            for piece in chunk.split_inclusive('\n') {
+                let line = current.get_or_insert_with(|| ScrollbackLine {
+                    at: *at,
+                    text: String::new(),
+                });
+                line.text.push_str(piece.trim_end_matches(['\r', '\n']));
+                if piece.ends_with('\n') {
+                    lines.extend(current.take());
+                }
+            }
+        }
+        lines.extend(current.filter(|line| !line.text.is_empty()));
+        let keep = max_lines.unwrap_or(lines.len()).min(lines.len());
+        lines.split_off(lines.len() - keep)
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.lock();
+        inner.capacity = capacity;
+        inner.evict();
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        inner.chunks.clear();
+        inner.bytes = 0;
+    }
+}
+
+/// A chunk of scrollback as it was emitted, for `get_scrollback_chunks`.
+#[derive(Serialize)]
+pub struct ScrollbackChunk {
+    /// When it was appended, in Unix millis: as its `mud:output` event went
+    /// out, or before that if output was paused.
+    pub ts_ms: u64,
+    /// Display text; may hold ANSI escapes.
+    pub data: String,
+}
+
+/// A line of scrollback, as it came from the server.
+pub struct ScrollbackLine {
+    /// Unix millis.
+    pub at: u64,
+    /// Display text; may hold ANSI escapes.
+    pub text: String,
+}
+
+impl ScrollbackLine {
+    /// The line ANSI-stripped, prefixed with the local time it arrived when
+    /// `timestamp` is set.
+    pub fn plain(&self, timestamp: bool) -> String {
+        let text = ansi::strip_ansi(&self.text);
+        if !timestamp {
+            return text;
+        }
+        match Local.timestamp_millis_opt(self.at as i64).single() {
+            Some(at) => format!("{} {text}", at.format("[%H:%M:%S]")),
+            None => text,
+        }
+    }
+}
+
+/// `lines` as plain text, newest kept when over `max_bytes`. Returns the text
+/// and how many lines it holds.
+pub fn plain_text(lines: &[ScrollbackLine], timestamps: bool, max_bytes: usize) -> (String, usize) {
+    let mut kept = Vec::new();
+    let mut bytes = 0;
+    for line in lines.iter().rev() {
+        let text = line.plain(timestamps);
+        bytes += text.len() + 1;
+        if bytes > max_bytes {
+            break;
+        }
+        kept.push(text);
+    }
+    kept.reverse();
+    let count = kept.len();
+    (kept.join("\n"), count)
+}
@@ -0,0 +1,395 @@
+//! Small secrets (API tokens, the backup passphrase) kept out of the plain
+//! JSON data files. The OS keychain is used when it works; otherwise secrets
+//! go to `secrets.enc` in the data dir, sealed with XChaCha20-Poly1305 under a
+//! random key in `secrets.key` beside it. The key file is created once,
+//! readable only by its owner on Unix, and never changes, so how the app was
+//! launched can't lock the secrets away.
+//!
+//! The fallback keeps `secrets.enc` unreadable on its own (neither file is
+//! backed up), not from anyone who can read the whole data dir — the keychain
+//! is preferred.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use base64::Engine as _;
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305, XNonce};
+use log::{info, warn};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+pub const SECRETS_FILE: &str = "secrets.enc";
+pub const SECRETS_KEY_FILE: &str = "secrets.key";
+const MAX_KEY_LEN: usize = 128;
+const FILE_VERSION: u32 = 1;
+const NONCE_LEN: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretBackend {
+    Keychain,
+    EncryptedFile,
+}
+
+#[derive(Serialize)]
+pub struct SecretBackendInfo {
+    pub backend: SecretBackend,
+    /// Why the keychain isn't being used, when it isn't.
+    pub detail: Option<String>,
+}
+
+/// Keys follow the same rules as data filenames, further limited to a
+/// conservative character set since they become keychain account names.
+pub fn validate_key(key: &str) -> Result<(), String> {
+    crate::storage::validate_filename(key).map_err(|_| format!("Invalid secret key: {key}"))?;
+    if key.len() > MAX_KEY_LEN
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+    {
+        return Err(format!("Invalid secret key: {key}"));
+    }
+    Ok(())
+}
+
+fn keyring_entry(key: &str) -> Result<keyring::Entry, keyring::Error> {
+    keyring::Entry::new(crate::KEYRING_SERVICE, &format!("secret:{key}"))
+}
+
+/// Which backend a keychain probe points to: a missing entry means the
+/// keychain works; a platform/storage failure means it doesn't.
+fn probe_backend(probe: keyring::Result<String>) -> (SecretBackend, Option<String>) {
+    match probe {
+        Ok(_) | Err(keyring::Error::NoEntry) => (SecretBackend::Keychain, None),
+        Err(e) => {
+            warn!("OS keychain unavailable, using encrypted file for secrets: {e}");
+            (SecretBackend::EncryptedFile, Some(e.to_string()))
+        }
+    }
+}
+
+/// Probe the keychain once per run.
+fn backend() -> &'static (SecretBackend, Option<String>) {
+    static BACKEND: OnceLock<(SecretBackend, Option<String>)> = OnceLock::new();
+    BACKEND.get_or_init(|| {
+        probe_backend(keyring_entry("__probe__").and_then(|entry| entry.get_password()))
+    })
+}
+
+pub fn backend_info() -> SecretBackendInfo {
+    let (backend, detail) = backend().clone();
+    SecretBackendInfo { backend, detail }
+}
+
+fn secret_entry(key: &str) -> Result<keyring::Entry, String> {
+    keyring_entry(key).map_err(|e| format!("Invalid keychain entry: {e}"))
+}
+
+fn keychain_set(entry: &keyring::Entry, value: &str) -> Result<(), String> {
+    entry
+        .set_password(value)
+        .map_err(|e| format!("Failed to store secret: {e}"))
+}
+
+fn keychain_get(entry: &keyring::Entry) -> Result<Option<String>, String> {
+    match entry.get_password() {
+        Ok(value) => Ok(Some(value)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to read secret: {e}")),
+    }
+}
+
+fn keychain_delete(entry: &keyring::Entry) -> Result<(), String> {
+    match entry.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(format!("Failed to delete secret: {e}")),
+    }
+}
+
+pub fn set(data_dir: &Path, key: &str, value: &str) -> Result<(), String> {
+    validate_key(key)?;
+    match backend().0 {
+        SecretBackend::Keychain => keychain_set(&secret_entry(key)?, value),
+        SecretBackend::EncryptedFile => {
+            let mut secrets = read_file(data_dir)?;
+            secrets.insert(key.to_string(), value.to_string());
+            write_file(data_dir, &secrets)
+        }
+    }
+}
+
+pub fn get(data_dir: &Path, key: &str) -> Result<Option<String>, String> {
+    validate_key(key)?;
+    match backend().0 {
+        SecretBackend::Keychain => keychain_get(&secret_entry(key)?),
+        SecretBackend::EncryptedFile => Ok(read_file(data_dir)?.remove(key)),
+    }
+}
+
+pub fn delete(data_dir: &Path, key: &str) -> Result<(), String> {
+    validate_key(key)?;
+    match backend().0 {
+        SecretBackend::Keychain => keychain_delete(&secret_entry(key)?),
+        SecretBackend::EncryptedFile => {
+            let mut secrets = read_file(data_dir)?;
+            if secrets.remove(key).is_some() {
+                write_file(data_dir, &secrets)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// --- Encrypted file fallback ---
+
+#[derive(Serialize, Deserialize)]
+struct SecretsFile {
+    version: u32,
+    nonce: String,
+    /// The sealed secrets, tag included.
+    data: String,
+}
+
+/// The key `secrets.enc` is sealed under. It's made the first time one is
+/// written (`create`); otherwise a missing key file is an error, so secrets
+/// sealed under a lost key are never silently replaced.
+fn file_key(data_dir: &Path, create: bool) -> Result<[u8; 32], String> {
+    let path = data_dir.join(SECRETS_KEY_FILE);
+    match std::fs::read(&path) {
+        Ok(bytes) => {
+            return bytes
+                .try_into()
+                .map_err(|_| format!("{SECRETS_KEY_FILE} is corrupt"))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!(
+                "{SECRETS_KEY_FILE} is missing, so {SECRETS_FILE} can't be decrypted"
+            ))
+        }
+        Err(e) => return Err(format!("Failed to read {SECRETS_KEY_FILE}: {e}")),
+    }
+
+    let mut key = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut key);
+    std::fs::create_dir_all(data_dir).map_err(|e| format!("Failed to create data dir: {e}"))?;
+    // Written in full under a temp name, then linked into place, which fails
+    // rather than replacing a key another writer made meanwhile
+    let temp = crate::storage::temp_path(&path);
+    let written = write_private(&temp, &key).and_then(|()| std::fs::hard_link(&temp, &path));
+    let _ = std::fs::remove_file(&temp);
+    match written {
+        Ok(()) => {
+            info!("Created {SECRETS_KEY_FILE}");
+            Ok(key)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => file_key(data_dir, false),
+        Err(e) => Err(format!("Failed to write {SECRETS_KEY_FILE}: {e}")),
+    }
+}
+
+/// Write a new file only its owner can read (on Unix; elsewhere the data
+/// dir's permissions apply) and flush it to disk.
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write as _;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+fn cipher(key: &[u8; 32]) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(key.into())
+}
+
+fn read_file(data_dir: &Path) -> Result<BTreeMap<String, String>, String> {
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let Some(file) = crate::storage::read_json_file::<SecretsFile>(data_dir, SECRETS_FILE) else {
+        // Don't silently start over (and then overwrite) an unreadable file
+        if data_dir.join(SECRETS_FILE).exists() {
+            return Err(format!("{SECRETS_FILE} is corrupt"));
+        }
+        return Ok(BTreeMap::new());
+    };
+    let corrupt = || format!("{SECRETS_FILE} is corrupt");
+    let decode = |s: &str| b64.decode(s).map_err(|_| corrupt());
+    if file.version != FILE_VERSION {
+        return Err(format!(
+            "Unsupported {SECRETS_FILE} version {}",
+            file.version
+        ));
+    }
+    let nonce: [u8; NONCE_LEN] = decode(&file.nonce)?.try_into().map_err(|_| corrupt())?;
+    let sealed = decode(&file.data)?;
+    let key = file_key(data_dir, false)?;
+    let data = cipher(&key)
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &sealed,
+                aad: SECRETS_FILE.as_bytes(),
+            },
+        )
+        .map_err(|_| format!("{SECRETS_FILE} could not be decrypted with {SECRETS_KEY_FILE}"))?;
+    serde_json::from_slice(&data).map_err(|_| corrupt())
+}
+
+fn write_file(data_dir: &Path, secrets: &BTreeMap<String, String>) -> Result<(), String> {
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let key = file_key(data_dir, true)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    let data = serde_json::to_vec(secrets).map_err(|e| e.to_string())?;
+    let sealed = cipher(&key)
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &data,
+                aad: SECRETS_FILE.as_bytes(),
+            },
+        )
+        .map_err(|_| format!("Failed to encrypt {SECRETS_FILE}"))?;
+
+    let file = SecretsFile {
+        version: FILE_VERSION,
+        nonce: b64.encode(nonce),
+        data: b64.encode(sealed),
+    };
+    crate::storage::write_json_file(data_dir, SECRETS_FILE, &file)?;
+    info!("Wrote {} secret(s) to {SECRETS_FILE}", secrets.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keyring::mock::MockCredential;
+
+    fn mock_entry() -> keyring::Entry {
+        keyring::Entry::new_with_credential(Box::new(MockCredential::default()))
+    }
+
+    fn fail_next(entry: &keyring::Entry, error: keyring::Error) {
+        entry
+            .get_credential()
+            .downcast_ref::<MockCredential>()
+            .unwrap()
+            .set_error(error);
+    }
+
+    fn secrets(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn keychain_round_trip() {
+        let entry = mock_entry();
+        assert_eq!(keychain_get(&entry), Ok(None));
+        keychain_set(&entry, "token").unwrap();
+        assert_eq!(keychain_get(&entry), Ok(Some("token".to_string())));
+        keychain_delete(&entry).unwrap();
+        assert_eq!(keychain_get(&entry), Ok(None));
+        // Deleting what isn't there is fine
+        keychain_delete(&entry).unwrap();
+    }
+
+    #[test]
+    fn keychain_failures_are_reported() {
+        let entry = mock_entry();
+        fail_next(&entry, keyring::Error::NoStorageAccess("locked".into()));
+        assert!(keychain_set(&entry, "token")
+            .unwrap_err()
+            .starts_with("Failed to store secret"));
+        fail_next(&entry, keyring::Error::PlatformFailure("gone".into()));
+        assert!(keychain_get(&entry)
+            .unwrap_err()
+            .starts_with("Failed to read secret"));
+        fail_next(&entry, keyring::Error::PlatformFailure("gone".into()));
+        assert!(keychain_delete(&entry)
+            .unwrap_err()
+            .starts_with("Failed to delete secret"));
+    }
+
+    #[test]
+    fn probe_picks_backend() {
+        assert_eq!(
+            probe_backend(Ok(String::new())),
+            (SecretBackend::Keychain, None)
+        );
+        assert_eq!(
+            probe_backend(Err(keyring::Error::NoEntry)),
+            (SecretBackend::Keychain, None)
+        );
+        let (backend, detail) =
+            probe_backend(Err(keyring::Error::NoStorageAccess("no dbus".into())));
+        assert_eq!(backend, SecretBackend::EncryptedFile);
+        assert!(detail.is_some());
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(read_file(dir.path()).unwrap().is_empty());
+        let stored = secrets(&[("api", "token"), ("backup", "hunter2")]);
+        write_file(dir.path(), &stored).unwrap();
+        let raw = std::fs::read_to_string(dir.path().join(SECRETS_FILE)).unwrap();
+        assert!(!raw.contains("hunter2"));
+        assert_eq!(read_file(dir.path()).unwrap(), stored);
+
+        // The key is made once and kept
+        let key = std::fs::read(dir.path().join(SECRETS_KEY_FILE)).unwrap();
+        write_file(dir.path(), &secrets(&[("api", "other")])).unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join(SECRETS_KEY_FILE)).unwrap(),
+            key
+        );
+        assert_eq!(read_file(dir.path()).unwrap(), secrets(&[("api", "other")]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_file_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt as _;
+        let dir = tempfile::tempdir().unwrap();
+        write_file(dir.path(), &secrets(&[("api", "token")])).unwrap();
+        let mode = std::fs::metadata(dir.path().join(SECRETS_KEY_FILE))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn tampered_file_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        write_file(dir.path(), &secrets(&[("api", "token")])).unwrap();
+        let mut file: SecretsFile =
+            crate::storage::read_json_file(dir.path(), SECRETS_FILE).unwrap();
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let mut data = b64.decode(&file.data).unwrap();
+        data[0] ^= 1;
+        file.data = b64.encode(data);
+        crate::storage::write_json_file(dir.path(), SECRETS_FILE, &file).unwrap();
+        assert!(read_file(dir.path()).is_err());
+    }
+
+    #[test]
+    fn missing_key_is_an_error_not_a_fresh_start() {
+        let dir = tempfile::tempdir().unwrap();
+        write_file(dir.path(), &secrets(&[("api", "token")])).unwrap();
+        std::fs::remove_file(dir.path().join(SECRETS_KEY_FILE)).unwrap();
+        assert!(read_file(dir.path()).unwrap_err().contains("missing"));
+        assert!(!dir.path().join(SECRETS_KEY_FILE).exists());
+    }
+}
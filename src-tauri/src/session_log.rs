@@ -0,0 +1,403 @@
+//! Backend session logging. While started, every output chunk, gagged line
+//! and sent command is appended to `logs/<profile>/<YYYY-MM-DD>.txt` from the
+//! connection task itself, so nothing is lost when the webview stalls. Files
+//! roll over at local midnight and when the connection switches profile.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{Local, NaiveDate};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tokio::time::Duration;
+
+use crate::ansi;
+use crate::connection::ConnectionShared;
+use crate::events::{self, SessionLogStatusPayload, SESSION_LOG_EVENT};
+use crate::log_rotation::LogRotation;
+
+/// Whether logging is on and how, so it resumes on the next launch.
+pub const SESSION_LOG_SETTINGS_FILE: &str = "session-log.json";
+pub const LOGS_DIR: &str = "logs";
+const DEFAULT_PROFILE_DIR: &str = "default";
+const FLUSH_INTERVAL: Duration = Duration::from_secs(3);
+/// An escape sequence cut off at the end of a chunk is only carried over to
+/// the next one up to this length.
+const MAX_PENDING_ESCAPE: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionLogOptions {
+    /// Remove ANSI color codes.
+    pub strip_ansi: bool,
+    /// Prefix each line with `[HH:MM:SS]`.
+    pub timestamps: bool,
+}
+
+impl Default for SessionLogOptions {
+    fn default() -> Self {
+        Self {
+            strip_ansi: true,
+            timestamps: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionLogSettings {
+    pub enabled: bool,
+    pub options: SessionLogOptions,
+}
+
+impl SessionLogSettings {
+    pub fn load(dir: &Path) -> Self {
+        crate::storage::read_json_file(dir, SESSION_LOG_SETTINGS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        crate::storage::write_json_file(dir, SESSION_LOG_SETTINGS_FILE, self)
+    }
+}
+
+/// A profile name as a directory name.
+fn profile_dir(profile: Option<&str>) -> String {
+    let name: String = profile
+        .unwrap_or("")
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        DEFAULT_PROFILE_DIR.to_string()
+    } else {
+        name
+    }
+}
+
+fn marker(text: &str) -> String {
+    format!(
+        "--- {text} {} ---\n",
+        Local::now().format("%Y-%m-%d %H:%M:%S")
+    )
+}
+
+struct OpenLog {
+    profile: String,
+    date: NaiveDate,
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// Bytes in the file, including what's still buffered.
+    size: u64,
+}
+
+struct ActiveLog {
+    app: AppHandle,
+    logs_dir: PathBuf,
+    options: SessionLogOptions,
+    rotation: LogRotation,
+    started_at: u64,
+    bytes_written: u64,
+    /// Opened on the first write, so the file follows the connection's
+    /// profile.
+    file: Option<OpenLog>,
+    at_line_start: bool,
+    pending_escape: String,
+}
+
+impl ActiveLog {
+    fn status(&self) -> SessionLogStatusPayload {
+        SessionLogStatusPayload {
+            active: true,
+            path: self
+                .file
+                .as_ref()
+                .map(|f| f.path.to_string_lossy().into_owned()),
+            started_at: Some(self.started_at),
+            bytes_written: self.bytes_written,
+        }
+    }
+
+    /// The file for `profile` today, opening it (and closing the previous
+    /// one with a pointer to it) if that's not the current one.
+    fn writer(&mut self, profile: &str) -> io::Result<&mut BufWriter<File>> {
+        let today = Local::now().date_naive();
+        let current = self
+            .file
+            .as_ref()
+            .is_some_and(|f| f.profile == profile && f.date == today);
+        if !current {
+            let dir = self.logs_dir.join(profile);
+            crate::data_lock::check_writable(&dir).map_err(io::Error::other)?;
+            fs::create_dir_all(&dir)?;
+            let path = dir.join(format!("{}.txt", today.format("%Y-%m-%d")));
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let size = file.metadata()?.len();
+            let mut writer = BufWriter::new(file);
+            let start = match self.file.take() {
+                Some(mut old) => {
+                    let newline = if self.at_line_start { "" } else { "\n" };
+                    let note = marker(&format!("Logging continues in {}", path.display()));
+                    old.writer
+                        .write_all(format!("{newline}{note}").as_bytes())?;
+                    old.writer.flush()?;
+                    marker(&format!("Logging continued from {}", old.path.display()))
+                }
+                None => marker("Logging started"),
+            };
+            writer.write_all(start.as_bytes())?;
+            self.bytes_written += start.len() as u64;
+            self.at_line_start = true;
+            let size = size + start.len() as u64;
+            self.file = Some(OpenLog {
+                profile: profile.to_string(),
+                date: today,
+                path,
+                writer,
+                size,
+            });
+        }
+        Ok(&mut self.file.as_mut().expect("log file was just opened").writer)
+    }
+
+    fn write(&mut self, profile: &str, text: &str) -> io::Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        // Open (or roll over) first: a new file starts at a line start
+        self.writer(profile)?;
+        let mut out = String::with_capacity(text.len() + 16);
+        let mut at_line_start = self.at_line_start;
+        for segment in text.split_inclusive('\n') {
+            if at_line_start && self.options.timestamps {
+                out.push_str(&Local::now().format("[%H:%M:%S] ").to_string());
+            }
+            out.push_str(segment);
+            at_line_start = segment.ends_with('\n');
+        }
+        let file = self.file.as_mut().expect("log file was opened above");
+        if self.rotation.is_due(file.size, out.len()) {
+            file.writer.flush()?;
+            self.rotation.rotate(&file.path)?;
+            file.writer = BufWriter::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&file.path)?,
+            );
+            file.size = 0;
+        }
+        file.writer.write_all(out.as_bytes())?;
+        file.size += out.len() as u64;
+        self.at_line_start = at_line_start;
+        self.bytes_written += out.len() as u64;
+        Ok(())
+    }
+
+    /// Write `line` on a line of its own.
+    fn write_line(&mut self, profile: &str, line: &str) -> io::Result<()> {
+        self.writer(profile)?;
+        let newline = if self.at_line_start { "" } else { "\n" };
+        self.write(profile, &format!("{newline}{line}\n"))
+    }
+
+    fn output(&mut self, profile: &str, text: &str) -> io::Result<()> {
+        if !self.options.strip_ansi {
+            return self.write(profile, text);
+        }
+        let mut text = std::mem::take(&mut self.pending_escape) + text;
+        // Hold back an escape sequence the chunk ends partway through
+        if let Some(pos) = text.rfind('\x1b') {
+            if ansi::escape_len(&text[pos..]).is_none() {
+                if text.len() - pos <= MAX_PENDING_ESCAPE {
+                    self.pending_escape = text[pos..].to_string();
+                }
+                text.truncate(pos);
+            }
+        }
+        self.write(profile, &ansi::strip_ansi(&text))
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            let newline = if self.at_line_start { "" } else { "\n" };
+            file.writer
+                .write_all(format!("{newline}{}", marker("Logging stopped")).as_bytes())?;
+            file.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct SessionLogInner {
+    active: Option<ActiveLog>,
+    /// Profile of the current connection; names the log directory.
+    profile: Option<String>,
+}
+
+#[derive(Default)]
+pub struct SessionLog {
+    inner: Mutex<SessionLogInner>,
+}
+
+fn inactive() -> SessionLogStatusPayload {
+    SessionLogStatusPayload {
+        active: false,
+        path: None,
+        started_at: None,
+        bytes_written: 0,
+    }
+}
+
+impl SessionLog {
+    fn lock(&self) -> std::sync::MutexGuard<'_, SessionLogInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start logging under `data_dir/logs`, restarting if already running.
+    pub fn start(
+        &self,
+        app: AppHandle,
+        data_dir: &Path,
+        options: SessionLogOptions,
+    ) -> SessionLogStatusPayload {
+        let mut inner = self.lock();
+        if let Some(previous) = inner.active.take() {
+            if let Err(e) = previous.finish() {
+                warn!("Failed to close session log: {e}");
+            }
+        }
+        let active = ActiveLog {
+            app: app.clone(),
+            logs_dir: data_dir.join(LOGS_DIR),
+            options,
+            rotation: LogRotation::load(data_dir),
+            started_at: events::now_ms(),
+            bytes_written: 0,
+            file: None,
+            at_line_start: true,
+            pending_escape: String::new(),
+        };
+        let status = active.status();
+        inner.active = Some(active);
+        drop(inner);
+        info!("Session logging started");
+        let _ = events::emit(&app, SESSION_LOG_EVENT, status.clone());
+        status
+    }
+
+    pub fn stop(&self) -> Result<SessionLogStatusPayload, String> {
+        let Some(active) = self.lock().active.take() else {
+            return Ok(inactive());
+        };
+        let app = active.app.clone();
+        let result = active
+            .finish()
+            .map_err(|e| format!("Failed to close session log: {e}"));
+        info!("Session logging stopped");
+        let _ = events::emit(&app, SESSION_LOG_EVENT, inactive());
+        result.map(|()| inactive())
+    }
+
+    pub fn status(&self) -> SessionLogStatusPayload {
+        self.lock()
+            .active
+            .as_ref()
+            .map_or_else(inactive, ActiveLog::status)
+    }
+
+    /// The file being written, if any.
+    pub fn current_path(&self) -> Option<PathBuf> {
+        self.lock()
+            .active
+            .as_ref()?
+            .file
+            .as_ref()
+            .map(|f| f.path.clone())
+    }
+
+    /// Apply new rotation settings to the running log.
+    pub fn set_rotation(&self, rotation: LogRotation) {
+        if let Some(active) = self.lock().active.as_mut() {
+            active.rotation = rotation;
+        }
+    }
+
+    /// Set the profile whose directory later writes go to.
+    pub fn set_profile(&self, profile: Option<String>) {
+        self.lock().profile = profile;
+    }
+
+    /// Directory under `logs/` for the current profile.
+    pub fn profile_dir(&self) -> String {
+        profile_dir(self.lock().profile.as_deref())
+    }
+
+    /// Run a write against the active log. A failed write stops logging.
+    fn with_active(&self, write: impl FnOnce(&mut ActiveLog, &str) -> io::Result<()>) {
+        let mut inner = self.lock();
+        let profile = profile_dir(inner.profile.as_deref());
+        let Some(active) = inner.active.as_mut() else {
+            return;
+        };
+        if let Err(e) = write(active, &profile) {
+            warn!("Session log write failed; logging stopped: {e}");
+            if let Some(active) = inner.active.take() {
+                let _ = events::emit(&active.app, SESSION_LOG_EVENT, inactive());
+            }
+        }
+    }
+
+    pub fn log_output(&self, text: &str) {
+        self.with_active(|log, profile| log.output(profile, text));
+    }
+
+    pub fn log_gagged(&self, lines: &[String]) {
+        self.with_active(|log, profile| {
+            for line in lines {
+                let line = if log.options.strip_ansi {
+                    ansi::strip_ansi(line)
+                } else {
+                    line.clone()
+                };
+                log.write_line(profile, &format!("[gagged] {line}"))?;
+            }
+            Ok(())
+        });
+    }
+
+    /// A command as sent, after `marker` (the echo marker). Anti-idle
+    /// commands are marked `[anti-idle]`.
+    pub fn log_input(&self, command: &str, marker: &str, synthetic: bool) {
+        let tag = if synthetic { "[anti-idle] " } else { "" };
+        self.with_active(|log, profile| {
+            log.write_line(profile, &format!("{tag}{marker}{command}"))
+        });
+    }
+
+    pub fn flush(&self) {
+        self.with_active(|log, _| match &mut log.file {
+            Some(file) => file.writer.flush(),
+            None => Ok(()),
+        });
+    }
+}
+
+/// Flush the session log every `FLUSH_INTERVAL`, for the life of the app.
+pub async fn run(shared: Arc<ConnectionShared>) {
+    let mut tick = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        tick.tick().await;
+        shared.session_log.flush();
+    }
+}
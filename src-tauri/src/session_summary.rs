@@ -0,0 +1,300 @@
+//! Per-character session summaries. While connected, the connection counts
+//! commands sent and lines matching the counters (skill improvements by
+//! default; `session-counters.json` adds more or replaces a shipped one by
+//! using its id). On disconnect the summary is emitted as
+//! `mud:session-summary` and, with a profile active, appended as one JSON line
+//! to `profiles/<name>/sessions.jsonl`. Each record is a single write synced
+//! before the next, so a crash loses at most the line being written, and
+//! `get_session_history` skips a torn one.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{info, warn};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::connection::ConnectionShared;
+use crate::events::{self, now_ms, SessionSummaryPayload, StatusReason, SESSION_SUMMARY_EVENT};
+use crate::lines;
+use crate::profiles;
+use crate::storage::StorageState;
+
+pub const SESSIONS_FILE: &str = "sessions.jsonl";
+pub const SESSION_COUNTERS_FILE: &str = "session-counters.json";
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+/// Enabled counters beyond this many are not loaded.
+const MAX_COUNTERS: usize = 50;
+/// Records `get_session_history` returns when no limit is given.
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+/// A sessions.jsonl bigger than this isn't read back.
+const MAX_HISTORY_FILE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Shipped counters as `(id, pattern)`, DartMUD's improvement messages.
+const DEFAULT_COUNTERS: [(&str, &str); 3] = [
+    (
+        "skill_improves",
+        r"^\* You think your .+? skill has improved\. \*$",
+    ),
+    (
+        "pet_skill_improves",
+        r"^\* You think that .+?'s .+? skill has improved\. \*$",
+    ),
+    ("mistaken_improves", r"^\(But you were mistaken\.\)$"),
+];
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionCounter {
+    /// Regex matched against each ANSI-stripped line.
+    pub pattern: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Case-sensitive unless set to true.
+    #[serde(default)]
+    pub ignore_case: bool,
+}
+
+/// Contents of session-counters.json. Counters are keyed by id, which is also
+/// their name in the summary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionCountersFile {
+    pub counters: BTreeMap<String, SessionCounter>,
+    /// Also use the shipped counters not replaced by one in `counters`.
+    pub use_defaults: bool,
+}
+
+impl Default for SessionCountersFile {
+    fn default() -> Self {
+        Self {
+            counters: BTreeMap::new(),
+            use_defaults: true,
+        }
+    }
+}
+
+impl SessionCountersFile {
+    pub fn load(dir: &Path) -> Self {
+        crate::storage::read_json_file(dir, SESSION_COUNTERS_FILE).unwrap_or_default()
+    }
+}
+
+fn compile(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {e}"))
+}
+
+pub struct SessionCounters {
+    counters: Mutex<Vec<(String, Regex)>>,
+}
+
+impl Default for SessionCounters {
+    fn default() -> Self {
+        let counters = DEFAULT_COUNTERS
+            .iter()
+            .filter_map(|(id, pattern)| Some((id.to_string(), compile(pattern, false).ok()?)))
+            .collect();
+        Self {
+            counters: Mutex::new(counters),
+        }
+    }
+}
+
+impl SessionCounters {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(String, Regex)>> {
+        self.counters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Re-read session-counters.json. Counters with invalid patterns are
+    /// skipped. Returns how many counters are in use.
+    pub fn reload(&self, dir: &Path) -> usize {
+        let file = SessionCountersFile::load(dir);
+        let custom = file
+            .counters
+            .iter()
+            .filter(|(_, counter)| counter.enabled)
+            .map(|(id, counter)| (id.as_str(), counter.pattern.as_str(), counter.ignore_case));
+        let defaults = DEFAULT_COUNTERS
+            .iter()
+            .filter(|(id, _)| file.use_defaults && !file.counters.contains_key(*id))
+            .map(|(id, pattern)| (*id, *pattern, false));
+        let mut counters = Vec::new();
+        for (id, pattern, ignore_case) in custom.chain(defaults) {
+            if counters.len() == MAX_COUNTERS {
+                warn!("Only the first {MAX_COUNTERS} session counters are used; skipping {id} and later counters");
+                break;
+            }
+            match compile(pattern, ignore_case) {
+                Ok(regex) => counters.push((id.to_string(), regex)),
+                Err(e) => warn!("Skipping session counter {id}: {e}"),
+            }
+        }
+        let count = counters.len();
+        *self.lock() = counters;
+        info!("Loaded {count} session counter(s)");
+        count
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.lock().is_empty()
+    }
+}
+
+struct LiveSession {
+    profile: Option<String>,
+    started_at: u64,
+    commands: u64,
+    counters: BTreeMap<String, u64>,
+}
+
+/// The counters for the session's current connection.
+#[derive(Default)]
+pub struct SessionTracker {
+    live: Mutex<Option<LiveSession>>,
+}
+
+impl SessionTracker {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<LiveSession>> {
+        self.live.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start counting for a freshly established connection.
+    pub fn start(&self, profile: Option<String>) {
+        *self.lock() = Some(LiveSession {
+            profile,
+            started_at: now_ms(),
+            commands: 0,
+            counters: BTreeMap::new(),
+        });
+    }
+
+    pub fn command_sent(&self) {
+        if let Some(live) = self.lock().as_mut() {
+            live.commands += 1;
+        }
+    }
+
+    /// Count a complete line against every counter it matches.
+    pub fn on_line(&self, counters: &SessionCounters, line: &str) {
+        let text = lines::match_text(line);
+        let text = text.trim_start_matches("> ").trim_end();
+        let counters = counters.lock();
+        let mut live = self.lock();
+        let Some(live) = live.as_mut() else {
+            return;
+        };
+        for (id, _) in counters.iter().filter(|(_, regex)| regex.is_match(text)) {
+            *live.counters.entry(id.clone()).or_default() += 1;
+        }
+    }
+
+    /// The summary so far, if connected.
+    pub fn summary(&self, shared: &ConnectionShared) -> Option<SessionSummaryPayload> {
+        self.lock().as_ref().map(|live| build(shared, live, None))
+    }
+
+    /// End the session: emit its summary and append it to the profile's
+    /// sessions.jsonl.
+    pub fn finish(&self, app: &AppHandle, shared: &ConnectionShared, reason: StatusReason) {
+        let Some(live) = self.lock().take() else {
+            return;
+        };
+        let summary = build(shared, &live, Some(reason));
+        if let Some(profile) = &live.profile {
+            let storage = app.state::<StorageState>();
+            let result = storage
+                .begin_write()
+                .and_then(|_write| append(&storage.get_dir(), profile, &summary));
+            if let Err(e) = result {
+                warn!("Failed to record the session summary for {profile}: {e}");
+            }
+        }
+        let _ = events::emit(app, SESSION_SUMMARY_EVENT, summary);
+    }
+}
+
+fn build(
+    shared: &ConnectionShared,
+    live: &LiveSession,
+    reason: Option<StatusReason>,
+) -> SessionSummaryPayload {
+    let stats = shared.stats.snapshot();
+    let ended_at = reason.map(|_| now_ms());
+    SessionSummaryPayload {
+        session: shared.session.clone(),
+        profile: live.profile.clone(),
+        started_at: live.started_at,
+        ended_at,
+        duration_secs: ended_at
+            .unwrap_or_else(now_ms)
+            .saturating_sub(live.started_at)
+            / 1000,
+        commands: live.commands,
+        lines: stats.lines,
+        bytes_read: stats.bytes_read,
+        bytes_written: stats.bytes_written,
+        counters: live.counters.clone(),
+        reason,
+    }
+}
+
+fn append(data_dir: &Path, profile: &str, summary: &SessionSummaryPayload) -> Result<(), String> {
+    let dir = profiles::data_dir(data_dir, profile)
+        .ok_or_else(|| format!("Invalid profile name: {profile}"))?;
+    crate::data_lock::check_writable(&dir)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    let mut record =
+        serde_json::to_string(summary).map_err(|e| format!("Failed to serialize: {e}"))?;
+    record.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(SESSIONS_FILE))
+        .map_err(|e| format!("Failed to open {SESSIONS_FILE}: {e}"))?;
+    file.write_all(record.as_bytes())
+        .map_err(|e| format!("Failed to write {SESSIONS_FILE}: {e}"))?;
+    file.sync_data()
+        .map_err(|e| format!("Failed to sync {SESSIONS_FILE}: {e}"))
+}
+
+/// The last `limit` sessions recorded for `profile`, oldest first. Lines that
+/// don't parse (a write cut off by a crash) are skipped.
+#[tauri::command]
+pub fn get_session_history(
+    profile: String,
+    limit: Option<usize>,
+    state: tauri::State<'_, StorageState>,
+) -> Result<Vec<SessionSummaryPayload>, String> {
+    let dir = profiles::data_dir(&state.get_dir(), &profile)
+        .ok_or_else(|| format!("Invalid profile name: {profile}"))?;
+    let path = dir.join(SESSIONS_FILE);
+    let size = match fs::metadata(&path) {
+        Ok(meta) => meta.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {SESSIONS_FILE}: {e}")),
+    };
+    if size > MAX_HISTORY_FILE_BYTES {
+        return Err(format!(
+            "{SESSIONS_FILE} is too large to read ({size} bytes)"
+        ));
+    }
+    let data =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read {SESSIONS_FILE}: {e}"))?;
+    let records: Vec<SessionSummaryPayload> = data
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let limit = limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+    Ok(records[records.len().saturating_sub(limit)..].to_vec())
+}
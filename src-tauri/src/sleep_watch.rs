@@ -0,0 +1,48 @@
+//! Noticing that the machine slept. A connection can't tell on its own: the
+//! socket looks open after a resume until a write fails, often minutes later.
+//! `SleepWatch` is ticked on a short interval and reports when much more time
+//! passed between ticks than the interval, by the wall clock or the monotonic
+//! one. While suspended, the monotonic clock stops on Linux and macOS but
+//! keeps counting on Windows, and the wall clock always does, so checking
+//! both catches a sleep everywhere without OS power notifications.
+
+use std::time::{Duration, SystemTime};
+
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+/// How often the clocks are compared.
+const TICK: Duration = Duration::from_secs(5);
+/// A gap between ticks longer than this, beyond the tick itself, is a sleep.
+const MIN_SLEEP: Duration = Duration::from_secs(20);
+
+pub struct SleepWatch {
+    interval: Interval,
+    wall: SystemTime,
+    mono: Instant,
+}
+
+impl SleepWatch {
+    pub fn new() -> Self {
+        let mut interval = tokio::time::interval(TICK);
+        // After a long suspend, tick once rather than catching up
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            interval,
+            wall: SystemTime::now(),
+            mono: Instant::now(),
+        }
+    }
+
+    /// Wait for the next tick, returning roughly how long the machine slept
+    /// if it did since the last one.
+    pub async fn tick(&mut self) -> Option<Duration> {
+        self.interval.tick().await;
+        let (wall, mono) = (SystemTime::now(), Instant::now());
+        // A clock set backwards reads as no time passing
+        let wall_elapsed = wall.duration_since(self.wall).unwrap_or_default();
+        let elapsed = wall_elapsed.max(mono - self.mono);
+        self.wall = wall;
+        self.mono = mono;
+        elapsed.checked_sub(TICK).filter(|slept| *slept > MIN_SLEEP)
+    }
+}
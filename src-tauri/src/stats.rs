@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::events::{now_ms, ConnectionStatsPayload, SocketOptionsPayload};
+
+#[derive(Default)]
+struct StatsInner {
+    connected_at: Option<u64>,
+    last_read_at: Option<u64>,
+    bytes_read: u64,
+    bytes_written: u64,
+    lines: u64,
+    total_bytes_read: u64,
+    total_bytes_written: u64,
+    total_lines: u64,
+    /// Latency samples for this connection in ms, oldest first.
+    latency: VecDeque<u64>,
+    stalled: bool,
+    socket: Option<SocketOptionsPayload>,
+}
+
+/// Latency samples kept for min/avg.
+const LATENCY_WINDOW: usize = 20;
+
+/// Traffic counters for the MUD connection. Per-connection counters reset on
+/// every new connection; the `total_*` counters accumulate for the app's
+/// lifetime.
+#[derive(Default)]
+pub struct ConnectionStats {
+    inner: Mutex<StatsInner>,
+}
+
+impl ConnectionStats {
+    fn lock(&self) -> std::sync::MutexGuard<'_, StatsInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Reset the per-connection counters for a freshly established connection.
+    pub fn start(&self) {
+        let mut inner = self.lock();
+        inner.connected_at = Some(now_ms());
+        inner.last_read_at = None;
+        inner.bytes_read = 0;
+        inner.bytes_written = 0;
+        inner.lines = 0;
+        inner.latency.clear();
+        inner.stalled = false;
+        inner.socket = None;
+    }
+
+    /// Mark the connection as closed. Counters are kept for inspection until
+    /// the next `start`.
+    pub fn stop(&self) {
+        self.lock().connected_at = None;
+    }
+
+    pub fn record_read(&self, bytes: usize, lines: usize) {
+        let mut inner = self.lock();
+        inner.last_read_at = Some(now_ms());
+        inner.bytes_read += bytes as u64;
+        inner.total_bytes_read += bytes as u64;
+        inner.lines += lines as u64;
+        inner.total_lines += lines as u64;
+    }
+
+    pub fn record_write(&self, bytes: usize) {
+        let mut inner = self.lock();
+        inner.bytes_written += bytes as u64;
+        inner.total_bytes_written += bytes as u64;
+    }
+
+    /// Bytes read and written on this connection so far.
+    pub fn bytes(&self) -> (u64, u64) {
+        let inner = self.lock();
+        (inner.bytes_read, inner.bytes_written)
+    }
+
+    pub fn record_latency(&self, rtt: Duration) {
+        let mut inner = self.lock();
+        if inner.latency.len() == LATENCY_WINDOW {
+            inner.latency.pop_front();
+        }
+        inner.latency.push_back(rtt.as_millis() as u64);
+    }
+
+    pub fn set_socket_options(&self, socket: SocketOptionsPayload) {
+        self.lock().socket = Some(socket);
+    }
+
+    pub fn set_stalled(&self, stalled: bool) {
+        self.lock().stalled = stalled;
+    }
+
+    pub fn snapshot(&self) -> ConnectionStatsPayload {
+        let inner = self.lock();
+        let uptime_secs = inner
+            .connected_at
+            .map(|at| now_ms().saturating_sub(at) / 1000)
+            .unwrap_or(0);
+        ConnectionStatsPayload {
+            connected: inner.connected_at.is_some(),
+            connected_at: inner.connected_at,
+            last_read_at: inner.last_read_at,
+            uptime_secs,
+            bytes_read: inner.bytes_read,
+            bytes_written: inner.bytes_written,
+            lines: inner.lines,
+            total_bytes_read: inner.total_bytes_read,
+            total_bytes_written: inner.total_bytes_written,
+            total_lines: inner.total_lines,
+            latency_last_ms: inner.latency.back().copied(),
+            latency_min_ms: inner.latency.iter().min().copied(),
+            latency_avg_ms: (!inner.latency.is_empty())
+                .then(|| inner.latency.iter().sum::<u64>() / inner.latency.len() as u64),
+            latency_samples: inner.latency.len(),
+            stalled: inner.stalled,
+            socket: inner.socket,
+        }
+    }
+}
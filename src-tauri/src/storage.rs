@@ -1,7 +1,8 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::{Read as _, Write as _};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, TryLockError};
 
 use base64::Engine as _;
 use regex::Regex;
@@ -10,15 +11,35 @@ use tauri::Manager;
 
 pub struct StorageState {
     data_dir: Mutex<PathBuf>,
+    /// One lock per data file name, held across read-modify-write.
+    file_locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    pub(crate) watch: crate::storage_watch::StorageWatch,
+    /// Taken exclusively by `migrate_data_dir`; see `begin_write`.
+    write_gate: RwLock<()>,
+    pub(crate) lock: crate::data_lock::DataDirLock,
 }
 
 impl StorageState {
     pub fn new(default_dir: PathBuf) -> Self {
         Self {
             data_dir: Mutex::new(default_dir),
+            file_locks: Mutex::new(HashMap::new()),
+            watch: Default::default(),
+            write_gate: RwLock::new(()),
+            lock: Default::default(),
         }
     }
 
+    /// The lock for `filename`, shared by every writer of that file.
+    fn file_lock(&self, filename: &str) -> Arc<Mutex<()>> {
+        self.file_locks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(filename.to_string())
+            .or_default()
+            .clone()
+    }
+
     pub fn get_dir(&self) -> PathBuf {
         self.data_dir.lock().unwrap_or_else(|e| e.into_inner()).clone()
     }
@@ -26,6 +47,23 @@ impl StorageState {
     pub fn set_dir(&self, dir: PathBuf) {
         *self.data_dir.lock().unwrap_or_else(|e| e.into_inner()) = dir;
     }
+
+    /// Held by each write command for the length of its write, so a data dir
+    /// migration can wait out writes in flight and turn new ones away. Fails
+    /// outright while the data dir is read-only because another instance
+    /// has it.
+    pub(crate) fn begin_write(&self) -> Result<RwLockReadGuard<'_, ()>, String> {
+        if self.lock.read_only() {
+            return Err(crate::data_lock::READ_ONLY_MESSAGE.to_string());
+        }
+        match self.write_gate.try_read() {
+            Ok(guard) => Ok(guard),
+            Err(TryLockError::Poisoned(e)) => Ok(e.into_inner()),
+            Err(TryLockError::WouldBlock) => {
+                Err("The data folder is being moved; try again once it's done".to_string())
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,10 +74,90 @@ pub struct BackupEntry {
     pub tag: String,
     pub size: u64,
     pub files: Vec<String>,
+    /// What `create_backup` was given as `note`; `None` for older backups
+    /// and encrypted ones, whose manifest can't be read without the
+    /// passphrase. Encrypted backups list no `files` either.
+    pub note: Option<String>,
+    pub encrypted: bool,
+}
+
+/// Secret store key of the remembered backup passphrase.
+const BACKUP_PASSPHRASE_KEY: &str = "backup-passphrase";
+
+/// A backup opened for reading: the zip itself, or for an encrypted backup a
+/// decrypted copy, removed when this is dropped.
+struct OpenedBackup {
+    path: PathBuf,
+    decrypted: bool,
+}
+
+impl Drop for OpenedBackup {
+    fn drop(&mut self) {
+        if self.decrypted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Open `backup`, decrypting it next to itself if it's encrypted. Without a
+/// `passphrase`, the remembered one is tried.
+fn open_backup(
+    backup: &Path,
+    passphrase: Option<String>,
+    data_dir: &Path,
+) -> Result<OpenedBackup, StorageError> {
+    if !crate::backup_crypto::is_encrypted(backup) {
+        return Ok(OpenedBackup {
+            path: backup.to_path_buf(),
+            decrypted: false,
+        });
+    }
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None => crate::secrets::get(data_dir, BACKUP_PASSPHRASE_KEY)?.ok_or_else(|| {
+            StorageError::PassphraseRequired {
+                message: "This backup is encrypted; enter its passphrase".to_string(),
+            }
+        })?,
+    };
+    let opened = OpenedBackup {
+        path: temp_path(backup),
+        decrypted: true,
+    };
+    crate::backup_crypto::decrypt(backup, &opened.path, &passphrase).map_err(|e| match e {
+        crate::backup_crypto::DecryptError::WrongPassphrase => StorageError::WrongPassphrase {
+            message: "Wrong passphrase for this backup".to_string(),
+        },
+        crate::backup_crypto::DecryptError::Corrupt(reason) => StorageError::CorruptBackup {
+            message: format!("Encrypted backup is damaged: {reason}"),
+        },
+    })?;
+    Ok(opened)
+}
+
+/// Name of the entry describing a backup. It's the first entry in the zip,
+/// and is never restored as a data file.
+const BACKUP_MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackupManifest {
+    app_version: String,
+    /// RFC 3339, local time.
+    created_at: String,
+    tag: String,
+    note: Option<String>,
+    files: Vec<BackupManifestFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackupManifestFile {
+    name: String,
+    size: u64,
+    crc32: u32,
 }
 
 /// Validate that a filename is safe (no path traversal or directory separators).
-fn validate_filename(filename: &str) -> Result<(), String> {
+pub(crate) fn validate_filename(filename: &str) -> Result<(), String> {
     if filename.contains("..")
         || filename.contains('/')
         || filename.contains('\\')
@@ -51,8 +169,395 @@ fn validate_filename(filename: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Most path components a data file path may have (`profiles/Alaric/map.json`).
+const MAX_PATH_COMPONENTS: usize = 4;
+/// Top-level folders data file paths may not reach into.
+const RESERVED_DIRS: [&str; 3] = ["backups", "sounds", TRASH_DIR];
+/// Backend-owned files in the data dir that no data file path may name.
+const RESERVED_FILES: [&str; 2] = [
+    crate::secrets::SECRETS_FILE,
+    crate::secrets::SECRETS_KEY_FILE,
+];
+/// Names Windows treats as devices in any folder, with any extension.
+const WINDOWS_DEVICE_NAMES: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Validate a data file path relative to the data dir: up to
+/// `MAX_PATH_COMPONENTS` `/`-separated components, each a safe filename that
+/// Windows would read the same way (no `:`, no trailing dot or space, no
+/// device names), not inside a reserved folder and not one of the secret
+/// store's files. A plain filename passes as before.
+pub(crate) fn validate_data_path(path: &str) -> Result<(), String> {
+    validate_relative_path(path, &RESERVED_DIRS)
+}
+
+/// `validate_data_path` with its own list of reserved top-level folders.
+fn validate_relative_path(path: &str, reserved: &[&str]) -> Result<(), String> {
+    let invalid = || format!("Invalid path: {path}");
+    let components: Vec<&str> = path.split('/').collect();
+    if components.len() > MAX_PATH_COMPONENTS {
+        return Err(format!(
+            "Path has too many folders (limit {}): {path}",
+            MAX_PATH_COMPONENTS - 1
+        ));
+    }
+    for component in &components {
+        validate_filename(component).map_err(|_| invalid())?;
+        let stem = component
+            .split('.')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if component.contains(':')
+            || component.ends_with('.')
+            || component.ends_with(' ')
+            || WINDOWS_DEVICE_NAMES.contains(&stem.as_str())
+        {
+            return Err(invalid());
+        }
+    }
+    if components.len() > 1
+        && reserved
+            .iter()
+            .any(|dir| components[0].eq_ignore_ascii_case(dir))
+    {
+        return Err(format!("{} is reserved: {path}", components[0]));
+    }
+    if components.len() == 1
+        && RESERVED_FILES
+            .iter()
+            .any(|file| path.eq_ignore_ascii_case(file))
+    {
+        return Err(format!("{path} is reserved"));
+    }
+    Ok(())
+}
+
+/// Read and deserialize a JSON file from the data dir, returning `None` if it
+/// is missing or malformed. Used for backend-owned settings files.
+pub(crate) fn read_json_file<T: serde::de::DeserializeOwned>(
+    dir: &Path,
+    filename: &str,
+) -> Option<T> {
+    validate_filename(filename).ok()?;
+    let contents = read_json_or_recover(&dir.join(filename)).ok()??;
+    serde_json::from_str(&contents).ok()
+}
+
+/// A JSON file's contents, or `None` if it doesn't exist. If the file is
+/// missing or empty — a crash between writing and renaming, or a rename that
+/// landed before its data did — the newest temp file from an interrupted
+/// write that holds valid JSON is moved into place and used instead.
+fn read_json_or_recover(path: &Path) -> std::io::Result<Option<String>> {
+    let existing = match fs::read_to_string(path) {
+        Ok(contents) if !contents.trim().is_empty() => return Ok(Some(contents)),
+        Ok(contents) => Some(contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let prefix = format!("{name}{TEMP_MARKER}");
+    let mut temps: Vec<(std::time::SystemTime, PathBuf)> = path
+        .parent()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    temps.sort_by(|a, b| b.0.cmp(&a.0));
+    for (_, temp) in temps {
+        let Ok(contents) = fs::read_to_string(&temp) else {
+            continue;
+        };
+        if contents.trim().is_empty()
+            || serde_json::from_str::<serde_json::Value>(&contents).is_err()
+        {
+            continue;
+        }
+        log::warn!("Recovered {name} from interrupted write {}", temp.display());
+        // Read-only, the recovered contents are used but left where they are
+        if let Err(e) = crate::data_lock::check_writable(path)
+            .and_then(|()| fs::rename(&temp, path).map_err(|e| e.to_string()))
+        {
+            log::warn!("Failed to restore {name}: {e}");
+        }
+        return Ok(Some(contents));
+    }
+    Ok(existing)
+}
+
+/// Why a data file couldn't be read. A missing file is not an error.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StorageError {
+    InvalidFilename {
+        message: String,
+    },
+    /// The file exists but isn't valid JSON (an empty file included). A copy
+    /// was saved as `backup` before returning, when possible.
+    ParseError {
+        line: usize,
+        column: usize,
+        message: String,
+        backup: Option<String>,
+    },
+    IoError {
+        message: String,
+    },
+    /// `update_data_file` was given a stale `expected_version`; the current
+    /// document and version are included so the caller can retry.
+    Conflict {
+        message: String,
+        version: u64,
+        document: serde_json::Value,
+    },
+    /// The backup is encrypted and no passphrase was given or remembered.
+    PassphraseRequired {
+        message: String,
+    },
+    WrongPassphrase {
+        message: String,
+    },
+    /// An encrypted backup that fails its integrity check.
+    CorruptBackup {
+        message: String,
+    },
+}
+
+impl From<String> for StorageError {
+    fn from(message: String) -> Self {
+        StorageError::IoError { message }
+    }
+}
+
+/// Copy a corrupt file aside as `<name>.corrupt-<timestamp>` and return the
+/// copy's file name.
+fn preserve_corrupt(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_string_lossy().into_owned();
+    let now = chrono::Local::now().format("%Y-%m-%dT%H-%M-%S");
+    let backup = format!("{name}.corrupt-{now}");
+    let copied = crate::data_lock::check_writable(path)
+        .map_err(std::io::Error::other)
+        .and_then(|()| fs::copy(path, path.with_file_name(&backup)));
+    match copied {
+        Ok(_) => {
+            log::warn!("{name} is not valid JSON; saved a copy as {backup}");
+            Some(backup)
+        }
+        Err(e) => {
+            log::warn!("{name} is not valid JSON and could not be copied: {e}");
+            None
+        }
+    }
+}
+
+pub const WRITE_DURABILITY_FILE: &str = "write-durability.json";
+/// Files fsynced under `DurabilityMode::Important` unless configured otherwise.
+const DEFAULT_DURABLE_FILES: [&str; 3] = ["settings.json", "profiles.json", "triggers.json"];
+
+/// Which data file writes are fsynced before they count as done.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DurabilityMode {
+    Always,
+    /// Only the files in `important_files`.
+    #[default]
+    Important,
+    Never,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WriteDurability {
+    pub mode: DurabilityMode,
+    pub important_files: Vec<String>,
+}
+
+impl Default for WriteDurability {
+    fn default() -> Self {
+        Self {
+            mode: DurabilityMode::Important,
+            important_files: DEFAULT_DURABLE_FILES
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl WriteDurability {
+    pub fn load(dir: &Path) -> Self {
+        read_json_file(dir, WRITE_DURABILITY_FILE).unwrap_or_default()
+    }
+
+    /// Whether writes of `filename` should be fsynced.
+    pub fn applies_to(&self, filename: &str) -> bool {
+        match self.mode {
+            DurabilityMode::Always => true,
+            DurabilityMode::Important => self.important_files.iter().any(|f| f == filename),
+            DurabilityMode::Never => false,
+        }
+    }
+}
+
+/// Configure which data file writes are fsynced: `always`, `never`, or
+/// `important` (the default) for just `important_files` (default:
+/// settings, profiles and triggers). Individual writes can still override it.
+#[tauri::command]
+pub fn set_write_durability(
+    mode: DurabilityMode,
+    important_files: Option<Vec<String>>,
+    state: tauri::State<'_, StorageState>,
+) -> Result<(), String> {
+    let dir = state.get_dir();
+    let mut durability = WriteDurability::load(&dir);
+    durability.mode = mode;
+    if let Some(files) = important_files {
+        for file in &files {
+            validate_filename(file)?;
+        }
+        durability.important_files = files;
+    }
+    write_json_file(&dir, WRITE_DURABILITY_FILE, &durability)
+}
+
+/// Marks temp files from atomic writes: `<full file name>.tmp-<random>`.
+const TEMP_MARKER: &str = ".tmp-";
+/// Temp files this old are left over from a crash and get cleaned up.
+const ORPHANED_TEMP_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Temp file next to `path` for an atomic write. The whole file name is kept
+/// and a random suffix appended, so files whose names differ only in their
+/// extension (or that have none) never share a temp file, and neither do two
+/// concurrent writes of the same file.
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{name}{TEMP_MARKER}{:08x}", rand::random::<u32>()))
+}
+
+/// Copy everything `data` yields into a new file and, if `durable`, flush it
+/// to disk.
+fn write_temp(path: &Path, mut data: impl std::io::Read, durable: bool) -> std::io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    std::io::copy(&mut data, &mut file)?;
+    if durable {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Write `data` to `path` through a temp file and a rename, so readers never
+/// see a partial file. `fs::rename` replaces an existing file on Windows too
+/// (`MoveFileExW` with `MOVEFILE_REPLACE_EXISTING`). The temp file is removed
+/// if either step fails.
+///
+/// With `durable`, the data is fsynced before the rename and (on Unix) the
+/// directory after it, so a power loss can't leave an empty file behind.
+/// Refused while the data dir is read-only (see `data_lock`).
+pub(crate) fn atomic_write(path: &Path, data: &[u8], durable: bool) -> Result<(), String> {
+    atomic_write_from(path, data, durable)
+}
+
+/// `atomic_write` streaming from a reader, for contents too big to hold in
+/// memory.
+pub(crate) fn atomic_write_from(
+    path: &Path,
+    data: impl std::io::Read,
+    durable: bool,
+) -> Result<(), String> {
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    crate::data_lock::check_writable(path)?;
+    let tmp_path = temp_path(path);
+    crate::storage_watch::note_own_write(path);
+    let result = write_temp(&tmp_path, data, durable)
+        .map_err(|e| format!("Failed to write {filename}: {e}"))
+        .and_then(|()| {
+            fs::rename(&tmp_path, path).map_err(|e| format!("Failed to rename {filename}: {e}"))
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    #[cfg(unix)]
+    if durable && result.is_ok() {
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::File::open(parent).and_then(|dir| dir.sync_all()) {
+                log::warn!("Failed to sync directory after writing {filename}: {e}");
+            }
+        }
+    }
+    result
+}
+
+/// Delete temp files older than a day that interrupted atomic writes left in
+/// the data dir or its `sounds` folder. Returns how many were removed.
+pub(crate) fn remove_orphaned_temp_files(data_dir: &Path) -> usize {
+    let mut removed = 0;
+    if crate::data_lock::check_writable(data_dir).is_err() {
+        return removed;
+    }
+    for dir in [
+        data_dir.to_path_buf(),
+        data_dir.join("sounds"),
+        data_dir.join("backups"),
+    ] {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            if !entry.file_name().to_string_lossy().contains(TEMP_MARKER) {
+                continue;
+            }
+            let stale = entry
+                .metadata()
+                .ok()
+                .filter(|meta| meta.is_file())
+                .and_then(|meta| meta.modified().ok())
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > ORPHANED_TEMP_AGE);
+            if stale && fs::remove_file(entry.path()).is_ok() {
+                removed += 1;
+            }
+        }
+    }
+    if removed > 0 {
+        log::info!(
+            "Removed {removed} orphaned temp file(s) from {}",
+            data_dir.display()
+        );
+    }
+    removed
+}
+
+/// Serialize a value to a JSON file in the data dir using the same atomic
+/// temp-file + rename pattern as `write_data_file`.
+pub(crate) fn write_json_file<T: Serialize>(
+    dir: &Path,
+    filename: &str,
+    value: &T,
+) -> Result<(), String> {
+    validate_filename(filename)?;
+    crate::data_lock::check_writable(dir)?;
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create data dir: {e}"))?;
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    let durable = WriteDurability::load(dir).applies_to(filename);
+    atomic_write(&dir.join(filename), json.as_bytes(), durable)
+}
+
 /// Sanitize a backup tag to only allow safe filename characters.
-fn sanitize_tag(tag: &str) -> String {
+pub(crate) fn sanitize_tag(tag: &str) -> String {
     tag.chars()
         .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
         .take(64)
@@ -60,7 +565,7 @@ fn sanitize_tag(tag: &str) -> String {
 }
 
 /// Check if a directory exists and is writable by creating and removing a temp file.
-fn is_dir_writable(path: &Path) -> bool {
+pub(crate) fn is_dir_writable(path: &Path) -> bool {
     if !path.is_dir() {
         return false;
     }
@@ -75,7 +580,12 @@ fn is_dir_writable(path: &Path) -> bool {
 }
 
 /// List all data files (*.json + *.txt, excluding backups dir) in a directory.
-fn list_data_files(dir: &Path) -> Vec<PathBuf> {
+fn data_file_paths(dir: &Path) -> Vec<PathBuf> {
+    data_file_paths_with(dir, &[])
+}
+
+/// As `data_file_paths`, also listing files with one of `extra_extensions`.
+fn data_file_paths_with(dir: &Path, extra_extensions: &[String]) -> Vec<PathBuf> {
     let Ok(entries) = fs::read_dir(dir) else {
         return vec![];
     };
@@ -84,10 +594,19 @@ fn list_data_files(dir: &Path) -> Vec<PathBuf> {
         .map(|e| e.path())
         .filter(|p| {
             p.is_file()
-                && p.extension()
-                    .is_some_and(|ext| ext == "json" || ext == "txt")
-                && p.file_name()
-                    .is_some_and(|name| name != "local-config.json")
+                && p.extension().is_some_and(|ext| {
+                    ext == "json"
+                        || ext == "txt"
+                        || extra_extensions
+                            .iter()
+                            .any(|extra| ext.eq_ignore_ascii_case(extra))
+                })
+                && p.file_name().is_some_and(|name| {
+                    name != "local-config.json"
+                        && !RESERVED_FILES
+                            .iter()
+                            .any(|file| name.eq_ignore_ascii_case(file))
+                })
         })
         .collect()
 }
@@ -98,11 +617,20 @@ pub fn resolve_data_dir(
     state: tauri::State<'_, StorageState>,
     app: tauri::AppHandle,
 ) -> String {
+    // `--data-dir` wins over the configured locations
+    if let Some(dir) = app.state::<crate::cli::Launch>().data_dir() {
+        state.set_dir(dir.clone());
+        state.lock.acquire(&dir, &app);
+        log::info!("Using data dir from --data-dir: {}", dir.display());
+        return dir.to_string_lossy().to_string();
+    }
     for candidate in &candidates {
         let path = PathBuf::from(candidate);
         if is_dir_writable(&path) {
             state.set_dir(path.clone());
+            state.lock.acquire(&path, &app);
             log::info!("Resolved data dir to configured path: {}", path.display());
+            remove_orphaned_temp_files(&path);
             return path.to_string_lossy().to_string();
         }
     }
@@ -119,6 +647,7 @@ pub fn resolve_data_dir(
         log::warn!("Failed to create default data dir {}: {e}", default_dir.display());
     }
     state.set_dir(default_dir.clone());
+    state.lock.acquire(&default_dir, &app);
     log::info!(
         "No configured paths valid, using default: {}",
         default_dir.display()
@@ -131,65 +660,316 @@ pub fn get_active_data_dir(state: tauri::State<'_, StorageState>) -> String {
     state.get_dir().to_string_lossy().to_string()
 }
 
+/// Read a JSON data file. `Ok(None)` means it doesn't exist, so defaults are
+/// safe to use; an error means it exists but couldn't be read, and the
+/// caller must not overwrite it.
 #[tauri::command]
 pub fn read_data_file(
     filename: String,
     state: tauri::State<'_, StorageState>,
-) -> Option<serde_json::Value> {
-    validate_filename(&filename).ok()?;
-    let path = state.get_dir().join(&filename);
-    let contents = fs::read_to_string(&path).ok()?;
-    serde_json::from_str(&contents).ok()
+) -> Result<Option<serde_json::Value>, StorageError> {
+    read_data(&state.get_dir(), &filename)
+}
+
+fn read_data(dir: &Path, filename: &str) -> Result<Option<serde_json::Value>, StorageError> {
+    validate_data_path(filename).map_err(|message| StorageError::InvalidFilename { message })?;
+    let path = dir.join(filename);
+    let contents = read_json_or_recover(&path).map_err(|e| StorageError::IoError {
+        message: format!("Failed to read {filename}: {e}"),
+    })?;
+    let Some(contents) = contents else {
+        return Ok(None);
+    };
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|e| StorageError::ParseError {
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string(),
+            backup: preserve_corrupt(&path),
+        })
+}
+
+/// One file's result in a batch read: its contents (`None` if it doesn't
+/// exist), or why it couldn't be read.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchRead<T> {
+    pub value: Option<T>,
+    pub error: Option<StorageError>,
+}
+
+impl<T> From<Result<Option<T>, StorageError>> for BatchRead<T> {
+    fn from(result: Result<Option<T>, StorageError>) -> Self {
+        match result {
+            Ok(value) => BatchRead { value, error: None },
+            Err(error) => BatchRead {
+                value: None,
+                error: Some(error),
+            },
+        }
+    }
 }
 
+/// Read each of `filenames` on its own blocking thread, all at once. A file
+/// that fails doesn't fail the batch; its error is in its entry.
+async fn read_batch<T: Send + 'static>(
+    filenames: Vec<String>,
+    dir: PathBuf,
+    read: fn(&Path, &str) -> Result<Option<T>, StorageError>,
+) -> HashMap<String, BatchRead<T>> {
+    let reads = filenames.into_iter().map(|filename| {
+        let dir = dir.clone();
+        async move {
+            let result = tauri::async_runtime::spawn_blocking({
+                let filename = filename.clone();
+                move || read(&dir, &filename)
+            })
+            .await
+            .unwrap_or_else(|e| {
+                Err(StorageError::IoError {
+                    message: format!("Read failed: {e}"),
+                })
+            });
+            (filename, BatchRead::from(result))
+        }
+    });
+    futures_util::future::join_all(reads)
+        .await
+        .into_iter()
+        .collect()
+}
+
+/// `read_data_file` for several files in one call, keyed by file name.
+#[tauri::command]
+pub async fn read_data_files(
+    filenames: Vec<String>,
+    app: tauri::AppHandle,
+) -> Result<HashMap<String, BatchRead<serde_json::Value>>, String> {
+    let dir = app.state::<StorageState>().get_dir();
+    Ok(read_batch(filenames, dir, read_data).await)
+}
+
+/// `read_text_file` for several files in one call, keyed by file name.
+/// Unlike `read_text_file`, a file that exists but can't be read reports
+/// its error.
+#[tauri::command]
+pub async fn read_text_files(
+    filenames: Vec<String>,
+    app: tauri::AppHandle,
+) -> Result<HashMap<String, BatchRead<String>>, String> {
+    let dir = app.state::<StorageState>().get_dir();
+    Ok(read_batch(filenames, dir, read_text).await)
+}
+
+/// `durable` forces fsync on or off for this write; by default it follows
+/// `set_write_durability`.
 #[tauri::command]
 pub fn write_data_file(
     filename: String,
     data: serde_json::Value,
+    durable: Option<bool>,
+    app: tauri::AppHandle,
     state: tauri::State<'_, StorageState>,
 ) -> Result<(), String> {
-    validate_filename(&filename)?;
+    let _write = state.begin_write()?;
+    write_data(&filename, &data, durable, &app, &state)
+}
+
+fn write_data(
+    filename: &str,
+    data: &serde_json::Value,
+    durable: Option<bool>,
+    app: &tauri::AppHandle,
+    state: &StorageState,
+) -> Result<(), String> {
+    validate_data_path(filename)?;
     let dir = state.get_dir();
-    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data dir: {e}"))?;
-    let path = dir.join(&filename);
+    let path = dir.join(filename);
+    fs::create_dir_all(path.parent().unwrap_or(&dir))
+        .map_err(|e| format!("Failed to create data dir: {e}"))?;
+    let lock = state.file_lock(filename);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
 
     // Atomic write: write to temp file then rename
-    let tmp_path = path.with_extension("json.tmp");
-    let json = serde_json::to_string_pretty(&data).map_err(|e| e.to_string())?;
-    fs::write(&tmp_path, json.as_bytes()).map_err(|e| format!("Failed to write {filename}: {e}"))?;
-    fs::rename(&tmp_path, &path).map_err(|e| format!("Failed to rename {filename}: {e}"))?;
+    let json = serde_json::to_string_pretty(data).map_err(|e| e.to_string())?;
+    let durable = durable.unwrap_or_else(|| WriteDurability::load(&dir).applies_to(filename));
+    atomic_write(&path, json.as_bytes(), durable)?;
 
+    crate::data_file_written(app, &dir, filename, None);
     Ok(())
 }
 
+/// `write_data_file` for several files, each written atomically on its own.
+/// Returns each file's error, or `None` if it was written; one failure
+/// doesn't stop the rest.
+#[tauri::command]
+pub fn write_data_files(
+    files: HashMap<String, serde_json::Value>,
+    durable: Option<bool>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, StorageState>,
+) -> Result<HashMap<String, Option<String>>, String> {
+    let _write = state.begin_write()?;
+    Ok(files
+        .into_iter()
+        .map(|(filename, data)| {
+            let error = write_data(&filename, &data, durable, &app, &state).err();
+            (filename, error)
+        })
+        .collect())
+}
+
+/// A data file's version: its modification time in microseconds, or 0 if
+/// it doesn't exist.
+fn file_version(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_micros() as u64)
+}
+
+/// Apply an RFC 7396 JSON merge patch: objects merge key by key, `null`
+/// removes a key, anything else replaces the target.
+pub(crate) fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    let target = target
+        .as_object_mut()
+        .expect("target was just made an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(
+                target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                value,
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DataFileUpdate {
+    pub document: serde_json::Value,
+    pub version: u64,
+}
+
+/// Apply a JSON merge patch (RFC 7396) to a data file as one locked
+/// read-modify-write, so concurrent updates of different keys can't clobber
+/// each other. With `expected_version`, the update is refused with a
+/// `Conflict` if the file changed since that version was read. Returns the
+/// new document and its version, which always increases.
+#[tauri::command]
+pub fn update_data_file(
+    filename: String,
+    patch: serde_json::Value,
+    expected_version: Option<u64>,
+    durable: Option<bool>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, StorageState>,
+) -> Result<DataFileUpdate, StorageError> {
+    let _write = state.begin_write()?;
+    let io_error = |message: String| StorageError::IoError { message };
+    validate_data_path(&filename).map_err(|message| StorageError::InvalidFilename { message })?;
+    let dir = state.get_dir();
+    let path = dir.join(&filename);
+    fs::create_dir_all(path.parent().unwrap_or(&dir))
+        .map_err(|e| io_error(format!("Failed to create data dir: {e}")))?;
+    let lock = state.file_lock(&filename);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let version = file_version(&path);
+    let mut document = match read_json_or_recover(&path)
+        .map_err(|e| io_error(format!("Failed to read {filename}: {e}")))?
+    {
+        Some(contents) => {
+            serde_json::from_str(&contents).map_err(|e| StorageError::ParseError {
+                line: e.line(),
+                column: e.column(),
+                message: e.to_string(),
+                backup: preserve_corrupt(&path),
+            })?
+        }
+        None => serde_json::Value::Null,
+    };
+    if expected_version.is_some_and(|expected| expected != version) {
+        return Err(StorageError::Conflict {
+            message: format!("{filename} was changed by another writer"),
+            version,
+            document,
+        });
+    }
+
+    merge_patch(&mut document, &patch);
+    let json = serde_json::to_string_pretty(&document).map_err(|e| io_error(e.to_string()))?;
+    let durable = durable.unwrap_or_else(|| WriteDurability::load(&dir).applies_to(&filename));
+    atomic_write(&path, json.as_bytes(), durable).map_err(io_error)?;
+
+    // Coarse filesystem timestamps can repeat; nudge the mtime past the old
+    // version so versions only go up
+    let mut new_version = file_version(&path);
+    if new_version <= version {
+        new_version = version + 1;
+        let bumped = std::time::UNIX_EPOCH + std::time::Duration::from_micros(new_version);
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(bumped))
+            .map_err(|e| io_error(format!("Failed to update {filename} version: {e}")))?;
+    }
+
+    crate::data_file_written(&app, &dir, &filename, None);
+    Ok(DataFileUpdate {
+        document,
+        version: new_version,
+    })
+}
+
 #[tauri::command]
 pub fn read_text_file(
     filename: String,
     state: tauri::State<'_, StorageState>,
 ) -> Option<String> {
-    validate_filename(&filename).ok()?;
+    validate_data_path(&filename).ok()?;
     let path = state.get_dir().join(&filename);
     fs::read_to_string(&path).ok()
 }
 
+fn read_text(dir: &Path, filename: &str) -> Result<Option<String>, StorageError> {
+    validate_data_path(filename).map_err(|message| StorageError::InvalidFilename { message })?;
+    match fs::read_to_string(dir.join(filename)) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(StorageError::IoError {
+            message: format!("Failed to read {filename}: {e}"),
+        }),
+    }
+}
+
+/// `durable` as for `write_data_file`.
 #[tauri::command]
 pub fn write_text_file(
     filename: String,
     content: String,
+    durable: Option<bool>,
     state: tauri::State<'_, StorageState>,
 ) -> Result<(), String> {
-    validate_filename(&filename)?;
+    let _write = state.begin_write()?;
+    validate_data_path(&filename)?;
     let dir = state.get_dir();
-    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data dir: {e}"))?;
     let path = dir.join(&filename);
+    fs::create_dir_all(path.parent().unwrap_or(&dir))
+        .map_err(|e| format!("Failed to create data dir: {e}"))?;
 
     // Atomic write: write to temp file then rename
-    let tmp_path = path.with_extension("txt.tmp");
-    fs::write(&tmp_path, content.as_bytes())
-        .map_err(|e| format!("Failed to write {filename}: {e}"))?;
-    fs::rename(&tmp_path, &path).map_err(|e| format!("Failed to rename {filename}: {e}"))?;
-
-    Ok(())
+    let durable = durable.unwrap_or_else(|| WriteDurability::load(&dir).applies_to(&filename));
+    atomic_write(&path, content.as_bytes(), durable)
 }
 
 #[tauri::command]
@@ -197,60 +977,777 @@ pub fn delete_text_file(
     filename: String,
     state: tauri::State<'_, StorageState>,
 ) -> Result<(), String> {
-    validate_filename(&filename)?;
+    let _write = state.begin_write()?;
+    validate_data_path(&filename)?;
     let path = state.get_dir().join(&filename);
     if path.exists() {
+        crate::storage_watch::note_own_write(&path);
         fs::remove_file(&path).map_err(|e| format!("Failed to delete {filename}: {e}"))?;
     }
     Ok(())
 }
 
-#[tauri::command]
-pub fn copy_data_to_dir(
-    target_dir: String,
-    state: tauri::State<'_, StorageState>,
-) -> Result<Vec<String>, String> {
-    let source = state.get_dir();
-    let target = PathBuf::from(&target_dir);
-
-    if !target.is_dir() {
-        return Err(format!("Target directory does not exist: {target_dir}"));
-    }
+/* ── Binary files ────────────────────────────────────────── */
 
-    let files = list_data_files(&source);
-    let mut copied = Vec::new();
+/// Default cap on a binary file sent over IPC as base64; larger files go
+/// through `write_binary_file_from_path`.
+const DEFAULT_MAX_BINARY_BYTES: u64 = 10 * 1024 * 1024;
 
-    for file in &files {
-        let file_name = file
-            .file_name()
-            .ok_or_else(|| "Invalid filename".to_string())?;
-        let dest = target.join(file_name);
-        fs::copy(file, &dest)
-            .map_err(|e| format!("Failed to copy {}: {}", file_name.to_string_lossy(), e))?;
-        copied.push(file_name.to_string_lossy().to_string());
+/// Binary files live alongside data files but never under a JSON or text
+/// name, where the JSON and text readers would pick them up.
+fn validate_binary_path(path: &str) -> Result<(), String> {
+    validate_data_path(path)?;
+    if Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext == "json" || ext == "txt")
+    {
+        return Err(format!("Binary files can't be named .json or .txt: {path}"));
     }
+    Ok(())
+}
 
-    Ok(copied)
+fn check_binary_size(filename: &str, size: u64, max_bytes: u64) -> Result<(), String> {
+    if size > max_bytes {
+        return Err(format!(
+            "{filename} is {:.1} MB, over the {:.1} MB limit",
+            size as f64 / (1024.0 * 1024.0),
+            max_bytes as f64 / (1024.0 * 1024.0)
+        ));
+    }
+    Ok(())
 }
 
+/// Read a binary file as base64; `None` if it doesn't exist. Files over
+/// `max_bytes` (10 MB by default) are refused rather than sent over IPC.
 #[tauri::command]
-pub fn check_dir_valid(path: String) -> bool {
-    is_dir_writable(Path::new(&path))
+pub fn read_binary_file(
+    filename: String,
+    max_bytes: Option<u64>,
+    state: tauri::State<'_, StorageState>,
+) -> Result<Option<String>, String> {
+    validate_binary_path(&filename)?;
+    let path = state.get_dir().join(&filename);
+    let Ok(meta) = fs::metadata(&path) else {
+        return Ok(None);
+    };
+    check_binary_size(
+        &filename,
+        meta.len(),
+        max_bytes.unwrap_or(DEFAULT_MAX_BINARY_BYTES),
+    )?;
+    let bytes = fs::read(&path).map_err(|e| format!("Failed to read {filename}: {e}"))?;
+    Ok(Some(
+        base64::engine::general_purpose::STANDARD.encode(bytes),
+    ))
 }
 
-/// Parse a backup zip filename: backup_{timestamp}.{tag}.zip
-fn parse_backup_filename(filename: &str) -> Option<(String, String)> {
-    let without_ext = filename.strip_suffix(".zip")?;
-    let without_prefix = without_ext.strip_prefix("backup_")?;
-    let (timestamp, tag) = without_prefix.rsplit_once('.')?;
-    Some((timestamp.to_string(), tag.to_string()))
+/// Write base64 `data_base64` to a binary file, refusing more than
+/// `max_bytes` (10 MB by default). `durable` as for `write_data_file`.
+#[tauri::command]
+pub fn write_binary_file(
+    filename: String,
+    data_base64: String,
+    max_bytes: Option<u64>,
+    durable: Option<bool>,
+    state: tauri::State<'_, StorageState>,
+) -> Result<(), String> {
+    let _write = state.begin_write()?;
+    validate_binary_path(&filename)?;
+    // Check the decoded size before decoding anything
+    let max_bytes = max_bytes.unwrap_or(DEFAULT_MAX_BINARY_BYTES);
+    check_binary_size(&filename, data_base64.len() as u64 / 4 * 3, max_bytes)?;
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(data_base64.trim())
+        .map_err(|e| format!("Invalid base64 for {filename}: {e}"))?;
+    let dir = state.get_dir();
+    let path = dir.join(&filename);
+    fs::create_dir_all(path.parent().unwrap_or(&dir))
+        .map_err(|e| format!("Failed to create data dir: {e}"))?;
+    let lock = state.file_lock(&filename);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let durable = durable.unwrap_or_else(|| WriteDurability::load(&dir).applies_to(&filename));
+    atomic_write(&path, &data, durable)
 }
 
+/// Copy a user-selected file into the data dir as binary file `dest_name`,
+/// without a round trip through base64. `durable` as for `write_data_file`.
 #[tauri::command]
-pub fn create_backup(
-    tag: String,
+pub fn write_binary_file_from_path(
+    source_path: String,
+    dest_name: String,
+    durable: Option<bool>,
+    state: tauri::State<'_, StorageState>,
+) -> Result<(), String> {
+    let _write = state.begin_write()?;
+    validate_binary_path(&dest_name)?;
+    let source = PathBuf::from(&source_path);
+    let meta = fs::metadata(&source)
+        .ok()
+        .filter(|meta| meta.is_file())
+        .ok_or_else(|| format!("File not found: {source_path}"))?;
+    let dir = state.get_dir();
+    let path = dir.join(&dest_name);
+    fs::create_dir_all(path.parent().unwrap_or(&dir))
+        .map_err(|e| format!("Failed to create data dir: {e}"))?;
+    crate::disk_space::ensure_space(&dir, meta.len())?;
+    let file = fs::File::open(&source).map_err(|e| format!("Failed to open {source_path}: {e}"))?;
+    let lock = state.file_lock(&dest_name);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let durable = durable.unwrap_or_else(|| WriteDurability::load(&dir).applies_to(&dest_name));
+    atomic_write_from(&path, file, durable)
+}
+
+/// Subfolders of the data dir that never hold data files.
+const NON_DATA_DIRS: [&str; 6] = [
+    "backups", "sounds", "logs", "sessions", "captures", TRASH_DIR,
+];
+/// Where `delete_data_file` moves files when asked to keep them.
+const TRASH_DIR: &str = ".trash";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataFileInfo {
+    /// Relative to the data dir; `sub/name.json` for files in subfolders.
+    pub filename: String,
+    pub size: u64,
+    pub modified: String,
+}
+
+fn data_file_info(path: &Path, filename: String) -> Option<DataFileInfo> {
+    let meta = fs::metadata(path).ok().filter(|meta| meta.is_file())?;
+    let datetime: chrono::DateTime<chrono::Local> = meta.modified().ok()?.into();
+    Some(DataFileInfo {
+        filename,
+        size: meta.len(),
+        modified: datetime.to_rfc3339(),
+    })
+}
+
+/// Data files in the subfolders of `dir`, recursively, as
+/// `(relative/path, path)`. Backups, sounds, logs, captures, the trash and
+/// hidden folders are skipped, as is anything deeper than data file paths
+/// may go. Files with one of `extra_extensions` count as data files too.
+fn nested_data_files(dir: &Path, extra_extensions: &[String]) -> Vec<(String, PathBuf)> {
+    fn walk(
+        dir: &Path,
+        prefix: &str,
+        depth: usize,
+        extra: &[String],
+        out: &mut Vec<(String, PathBuf)>,
+    ) {
+        if depth >= MAX_PATH_COMPONENTS {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            if !is_dir
+                || name.starts_with('.')
+                || (depth == 1 && NON_DATA_DIRS.contains(&name.as_str()))
+            {
+                continue;
+            }
+            let prefix = if prefix.is_empty() {
+                name
+            } else {
+                format!("{prefix}/{name}")
+            };
+            for path in data_file_paths_with(&entry.path(), extra) {
+                let file = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                out.push((format!("{prefix}/{file}"), path));
+            }
+            walk(&entry.path(), &prefix, depth + 1, extra, out);
+        }
+    }
+    let mut out = Vec::new();
+    walk(dir, "", 1, extra_extensions, &mut out);
+    out
+}
+
+/// The data files (`*.json`, `*.txt`) in the data dir, as backups see them.
+/// With `include_subdirs`, data files in subfolders are listed too, except
+/// in backups, sounds, logs, captures and the trash.
+#[tauri::command]
+pub fn list_data_files(
+    include_subdirs: Option<bool>,
+    state: tauri::State<'_, StorageState>,
+) -> Vec<DataFileInfo> {
+    let dir = state.get_dir();
+    let mut files: Vec<DataFileInfo> = data_file_paths(&dir)
+        .iter()
+        .filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            data_file_info(path, name)
+        })
+        .collect();
+    if include_subdirs.unwrap_or(false) {
+        files.extend(
+            nested_data_files(&dir, &[])
+                .into_iter()
+                .filter_map(|(name, path)| data_file_info(&path, name)),
+        );
+    }
+    files.sort_by(|a, b| a.filename.cmp(&b.filename));
+    files
+}
+
+/// Create a folder for data files, e.g. `profiles/Alaric`.
+#[tauri::command]
+pub fn create_data_subdir(
+    path: String,
+    state: tauri::State<'_, StorageState>,
+) -> Result<(), String> {
+    let _write = state.begin_write()?;
+    validate_data_path(&path)?;
+    fs::create_dir_all(state.get_dir().join(&path))
+        .map_err(|e| format!("Failed to create {path}: {e}"))
+}
+
+/// Size and modification time of a data file; `None` if it doesn't exist.
+#[tauri::command]
+pub fn get_file_info(
+    filename: String,
+    state: tauri::State<'_, StorageState>,
+) -> Result<Option<DataFileInfo>, String> {
+    validate_data_path(&filename)?;
+    let path = state.get_dir().join(&filename);
+    Ok(data_file_info(&path, filename))
+}
+
+/// Delete a data file. With `to_trash`, it is moved to `.trash/` with a
+/// timestamp suffix instead, so it can be recovered until `empty_trash`.
+#[tauri::command]
+pub fn delete_data_file(
+    filename: String,
+    to_trash: bool,
+    state: tauri::State<'_, StorageState>,
+) -> Result<(), String> {
+    let _write = state.begin_write()?;
+    validate_data_path(&filename)?;
+    let dir = state.get_dir();
+    let path = dir.join(&filename);
+    if !path.is_file() {
+        return Err(format!("No data file named {filename}"));
+    }
+    let lock = state.file_lock(&filename);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    crate::storage_watch::note_own_write(&path);
+    if to_trash {
+        let trash = dir.join(TRASH_DIR);
+        fs::create_dir_all(&trash).map_err(|e| format!("Failed to create trash folder: {e}"))?;
+        let now = chrono::Local::now().format("%Y-%m-%dT%H-%M-%S");
+        // Nested files are trashed flat, as `profiles__Alaric__map.json.<time>`
+        let flat = filename.replace('/', "__");
+        fs::rename(&path, trash.join(format!("{flat}.{now}")))
+            .map_err(|e| format!("Failed to move {filename} to the trash: {e}"))
+    } else {
+        fs::remove_file(&path).map_err(|e| format!("Failed to delete {filename}: {e}"))
+    }
+}
+
+/// Permanently delete trashed files, or only those trashed more than
+/// `older_than_days` ago. Returns how many were deleted.
+#[tauri::command]
+pub fn empty_trash(
+    older_than_days: Option<u32>,
+    state: tauri::State<'_, StorageState>,
+) -> Result<usize, String> {
+    let _write = state.begin_write()?;
+    let trash = state.get_dir().join(TRASH_DIR);
+    let Ok(entries) = fs::read_dir(&trash) else {
+        return Ok(0);
+    };
+    let cutoff = chrono::Local::now().naive_local()
+        - chrono::Duration::days(i64::from(older_than_days.unwrap_or(0)));
+    let mut deleted = 0;
+    for entry in entries.filter_map(|e| e.ok()) {
+        if !entry.path().is_file() {
+            continue;
+        }
+        // A rename keeps the file's own mtime, so go by the suffix added when
+        // it was trashed
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let trashed = name.rsplit_once('.').and_then(|(_, stamp)| {
+            chrono::NaiveDateTime::parse_from_str(stamp, "%Y-%m-%dT%H-%M-%S").ok()
+        });
+        if trashed.is_none_or(|trashed| trashed <= cutoff) {
+            fs::remove_file(entry.path()).map_err(|e| format!("Failed to empty trash: {e}"))?;
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+#[tauri::command]
+pub fn copy_data_to_dir(
+    target_dir: String,
+    state: tauri::State<'_, StorageState>,
+) -> Result<Vec<String>, String> {
+    let source = state.get_dir();
+    let target = PathBuf::from(&target_dir);
+
+    if !target.is_dir() {
+        return Err(format!("Target directory does not exist: {target_dir}"));
+    }
+
+    let files = data_file_paths(&source);
+    crate::disk_space::ensure_space(
+        &target,
+        crate::disk_space::total_size(files.iter().map(PathBuf::as_path)),
+    )?;
+    let mut copied = Vec::new();
+
+    for file in &files {
+        let file_name = file
+            .file_name()
+            .ok_or_else(|| "Invalid filename".to_string())?;
+        let dest = target.join(file_name);
+        fs::copy(file, &dest)
+            .map_err(|e| format!("Failed to copy {}: {}", file_name.to_string_lossy(), e))?;
+        copied.push(file_name.to_string_lossy().to_string());
+    }
+
+    Ok(copied)
+}
+
+/// Left in the old data dir by `migrate_data_dir`, saying where data went.
+pub const MOVED_POINTER_FILE: &str = "dartforge-moved.json";
+const LOG_FOLDERS: [&str; 3] = ["logs", "sessions", "captures"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DataDirMigration {
+    /// False when the target already had files by the same names, which are
+    /// listed in `conflicts`; nothing was copied.
+    pub migrated: bool,
+    pub conflicts: Vec<String>,
+    pub files: Vec<String>,
+}
+
+/// Every file to carry over to a new data dir, as `(relative/path, path)`:
+/// top-level files, data subfolders and sounds, and optionally the log
+/// folders and backups. Dot files, temp files and machine-local config stay.
+fn migration_files(
+    dir: &Path,
+    include_logs: bool,
+    include_backups: bool,
+) -> Vec<(String, PathBuf)> {
+    fn walk(dir: &Path, prefix: &str, out: &mut Vec<(String, PathBuf)>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') || name.contains(TEMP_MARKER) {
+                continue;
+            }
+            let path = format!("{prefix}{name}");
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => walk(&entry.path(), &format!("{path}/"), out),
+                Ok(t) if t.is_file() => out.push((path, entry.path())),
+                _ => {}
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return files;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.')
+            || name.contains(TEMP_MARKER)
+            || name == "local-config.json"
+            || name == MOVED_POINTER_FILE
+        {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(t) if t.is_file() => files.push((name, entry.path())),
+            Ok(t) if t.is_dir() => {
+                let wanted = if LOG_FOLDERS.contains(&name.as_str()) {
+                    include_logs
+                } else if name == "backups" {
+                    include_backups
+                } else {
+                    true
+                };
+                if wanted {
+                    walk(&entry.path(), &format!("{name}/"), &mut files);
+                }
+            }
+            _ => {}
+        }
+    }
+    files
+}
+
+/// Copy `files` under `target`, each through a temp file, and check each copy
+/// matches its source. On failure every copy made so far is removed.
+fn copy_verified(files: &[(String, PathBuf)], target: &Path) -> Result<(), String> {
+    let mut copied: Vec<PathBuf> = Vec::new();
+    let result = files.iter().try_for_each(|(name, source)| {
+        let dest = target.join(name);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to copy {name}: {e}"))?;
+        }
+        let tmp = temp_path(&dest);
+        let copy = fs::copy(source, &tmp).and_then(|_| fs::rename(&tmp, &dest));
+        if let Err(e) = copy {
+            let _ = fs::remove_file(&tmp);
+            return Err(format!("Failed to copy {name}: {e}"));
+        }
+        copied.push(dest.clone());
+        let matches = match (file_crc(source), file_crc(&dest)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(format!("Copy of {name} doesn't match the original"))
+        }
+    });
+    if result.is_err() {
+        for dest in copied {
+            let _ = fs::remove_file(dest);
+        }
+    }
+    result
+}
+
+/// Move the data dir to `target` and switch to it. Writes are turned away
+/// while this runs (see `StorageState::begin_write`). The target must be
+/// writable and have no files by the same names as those being copied;
+/// otherwise the clashing names come back and nothing changes. Every file is
+/// copied and checked before the switch, so until then the old dir is still
+/// the live one. A `dartforge-moved.json` pointer is left behind, and with
+/// `move_files` the originals are deleted after the switch.
+#[tauri::command]
+pub async fn migrate_data_dir(
+    target: String,
+    move_files: bool,
+    include_logs: Option<bool>,
+    include_backups: Option<bool>,
+    app: tauri::AppHandle,
+) -> Result<DataDirMigration, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<StorageState>();
+        let target = PathBuf::from(target);
+        if !target.is_absolute() {
+            return Err(format!(
+                "Data folder must be an absolute path: {}",
+                target.display()
+            ));
+        }
+        fs::create_dir_all(&target)
+            .map_err(|e| format!("Failed to create {}: {e}", target.display()))?;
+        if !is_dir_writable(&target) {
+            return Err(format!("Data folder is not writable: {}", target.display()));
+        }
+
+        if state.lock.read_only() {
+            return Err(crate::data_lock::READ_ONLY_MESSAGE.to_string());
+        }
+        // Wait for writes in flight, then hold new ones off until done
+        let _gate = state.write_gate.write().unwrap_or_else(|e| e.into_inner());
+        let source = state.get_dir();
+        let canonical = |dir: &Path| {
+            dir.canonicalize()
+                .map_err(|e| format!("Invalid folder {}: {e}", dir.display()))
+        };
+        let (source_real, target_real) = (canonical(&source)?, canonical(&target)?);
+        if source_real == target_real {
+            return Err("That is already the data folder".to_string());
+        }
+        if target_real.starts_with(&source_real) || source_real.starts_with(&target_real) {
+            return Err(
+                "The new data folder can't be inside the current one, or contain it".to_string(),
+            );
+        }
+
+        let files = migration_files(
+            &source,
+            include_logs.unwrap_or(false),
+            include_backups.unwrap_or(false),
+        );
+        let conflicts: Vec<String> = files
+            .iter()
+            .filter(|(name, _)| target.join(name).exists())
+            .map(|(name, _)| name.clone())
+            .collect();
+        if !conflicts.is_empty() {
+            return Ok(DataDirMigration {
+                migrated: false,
+                conflicts,
+                files: Vec::new(),
+            });
+        }
+        copy_verified(&files, &target)?;
+
+        state.set_dir(target.clone());
+        state.lock.acquire(&target, &app);
+        log::info!(
+            "Moved data dir from {} to {} ({} files)",
+            source.display(),
+            target.display(),
+            files.len()
+        );
+        let pointer = serde_json::json!({
+            "moved_to": target.to_string_lossy(),
+            "moved_at": chrono::Local::now().to_rfc3339(),
+        });
+        if let Err(e) = write_json_file(&source, MOVED_POINTER_FILE, &pointer) {
+            log::warn!("Failed to leave a pointer in the old data dir: {e}");
+        }
+        if move_files {
+            for (name, path) in &files {
+                if let Err(e) = fs::remove_file(path) {
+                    log::warn!("Failed to remove {name} from the old data dir: {e}");
+                }
+            }
+        }
+
+        use tauri::Emitter as _;
+        let _ = app.emit(
+            crate::events::DATA_DIR_MIGRATED_EVENT,
+            crate::events::DataDirMigratedPayload {
+                from: source.to_string_lossy().into_owned(),
+                to: target.to_string_lossy().into_owned(),
+                files: files.len(),
+                moved: move_files,
+            },
+        );
+        Ok(DataDirMigration {
+            migrated: true,
+            conflicts: Vec::new(),
+            files: files.into_iter().map(|(name, _)| name).collect(),
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn check_dir_valid(path: String) -> bool {
+    is_dir_writable(Path::new(&path))
+}
+
+/// Parse a backup zip filename: backup_{timestamp}.{tag}.zip
+fn parse_backup_filename(filename: &str) -> Option<(String, String)> {
+    let without_ext = filename.strip_suffix(".zip")?;
+    let without_prefix = without_ext.strip_prefix("backup_")?;
+    let (timestamp, tag) = without_prefix.rsplit_once('.')?;
+    Some((timestamp.to_string(), tag.to_string()))
+}
+
+pub const BACKUP_OPTIONS_FILE: &str = "backup-options.json";
+const DEFAULT_MAX_LOG_BYTES: u64 = 50 * 1024 * 1024;
+const DEFAULT_MAX_ENTRY_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+/// Entries that unpack to more than this many times their compressed size are
+/// taken for zip bombs. Only checked above `RATIO_CHECK_MIN_BYTES`, since
+/// small repetitive files compress very well.
+const MAX_COMPRESSION_RATIO: u64 = 1000;
+const RATIO_CHECK_MIN_BYTES: u64 = 1024 * 1024;
+const DEFAULT_BINARY_EXTENSIONS: [&str; 6] = ["bin", "png", "jpg", "jpeg", "gif", "webp"];
+/// Folders whose log files the `logs` backup category covers.
+const LOG_DIRS: [&str; 2] = ["logs", "sessions"];
+
+/// What a backup includes. Restores write back whatever a backup holds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupOptions {
+    /// Top-level `*.json` / `*.txt` files.
+    pub data_files: bool,
+    /// Data files in subfolders, such as `profiles/<name>/`.
+    pub data_subdirs: bool,
+    /// Binary files with these extensions (no dot) count as data files.
+    pub binary_extensions: Vec<String>,
+    /// Custom sounds in `sounds/`.
+    pub sounds: bool,
+    /// Logs in `logs/` and `sessions/`, newest first up to `max_log_bytes`.
+    pub logs: bool,
+    pub max_log_bytes: u64,
+    /// Restores and verification refuse an entry claiming to unpack to more
+    /// than this.
+    pub max_entry_bytes: u64,
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self {
+            data_files: true,
+            data_subdirs: true,
+            binary_extensions: DEFAULT_BINARY_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            sounds: false,
+            logs: false,
+            max_log_bytes: DEFAULT_MAX_LOG_BYTES,
+            max_entry_bytes: DEFAULT_MAX_ENTRY_BYTES,
+        }
+    }
+}
+
+impl BackupOptions {
+    pub fn load(dir: &Path) -> Self {
+        read_json_file(dir, BACKUP_OPTIONS_FILE).unwrap_or_default()
+    }
+}
+
+/// Save what backups include by default, for backups made without options.
+#[tauri::command]
+pub fn set_backup_options(
+    options: BackupOptions,
+    state: tauri::State<'_, StorageState>,
+) -> Result<(), String> {
+    write_json_file(&state.get_dir(), BACKUP_OPTIONS_FILE, &options)
+}
+
+/// Every file under `data_dir/subdir`, recursively, as `(relative/path,
+/// path)`. Files whose path wouldn't pass restore's checks are left out.
+pub(crate) fn files_under(data_dir: &Path, subdir: &str) -> Vec<(String, PathBuf)> {
+    fn walk(dir: &Path, prefix: &str, out: &mut Vec<(String, PathBuf)>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = format!("{prefix}/{}", entry.file_name().to_string_lossy());
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => walk(&entry.path(), &name, out),
+                Ok(t) if t.is_file() && validate_backup_entry(&name).is_ok() => {
+                    out.push((name, entry.path()))
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut out = Vec::new();
+    walk(&data_dir.join(subdir), subdir, &mut out);
+    out
+}
+
+/// Check a zip entry name before restoring it: a relative path that stays in
+/// the data dir and out of the backups and trash folders.
+pub(crate) fn validate_backup_entry(name: &str) -> Result<(), String> {
+    validate_relative_path(name, &["backups", TRASH_DIR])
+}
+
+/// The files a backup with `options` contains, as `(zip entry name, path)`.
+fn backup_files(data_dir: &Path, options: &BackupOptions) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    if options.data_files {
+        files.extend(
+            data_file_paths_with(data_dir, &options.binary_extensions)
+                .into_iter()
+                .filter_map(|path| Some((path.file_name()?.to_string_lossy().into_owned(), path)))
+                .filter(|(name, _)| name != BACKUP_MANIFEST),
+        );
+    }
+    if options.data_subdirs {
+        files.extend(nested_data_files(data_dir, &options.binary_extensions));
+    }
+    if options.sounds {
+        files.extend(files_under(data_dir, "sounds"));
+    }
+    if options.logs {
+        let mut logs: Vec<(std::time::SystemTime, u64, String, PathBuf)> = LOG_DIRS
+            .iter()
+            .flat_map(|dir| files_under(data_dir, dir))
+            .filter_map(|(name, path)| {
+                let meta = fs::metadata(&path).ok()?;
+                Some((meta.modified().ok()?, meta.len(), name, path))
+            })
+            .collect();
+        logs.sort_by(|a, b| b.0.cmp(&a.0));
+        let mut budget = options.max_log_bytes;
+        for (_, size, name, path) in logs {
+            if size <= budget {
+                budget -= size;
+                files.push((name, path));
+            }
+        }
+    }
+    files
+}
+
+/// A file's modification time as a zip timestamp, or `None` if it can't be
+/// read or is outside the range zip can store (1980–2107).
+fn zip_timestamp(path: &Path) -> Option<zip::DateTime> {
+    use chrono::{Datelike as _, Timelike as _};
+    let modified: chrono::DateTime<chrono::Local> =
+        fs::metadata(path).ok()?.modified().ok()?.into();
+    zip::DateTime::from_date_and_time(
+        u16::try_from(modified.year()).ok()?,
+        modified.month() as u8,
+        modified.day() as u8,
+        modified.hour() as u8,
+        modified.minute() as u8,
+        modified.second() as u8,
+    )
+    .ok()
+}
+
+/// Size and CRC-32 of a file, read in chunks.
+pub(crate) fn file_crc(path: &Path) -> std::io::Result<(u64, u32)> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok((size, hasher.finalize()));
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+}
+
+/// Size and CRC-32 of each file, streamed so a big file isn't held in memory.
+/// The manifest goes first in the zip, so this is a pass of its own.
+fn manifest_files(files: &[(String, PathBuf)]) -> Result<Vec<BackupManifestFile>, String> {
+    files
+        .iter()
+        .map(|(name, path)| {
+            let (size, crc32) =
+                file_crc(path).map_err(|e| format!("Failed to read {name}: {e}"))?;
+            Ok(BackupManifestFile {
+                name: name.clone(),
+                size,
+                crc32,
+            })
+        })
+        .collect()
+}
+
+/// Zip the data dir into `backups/`. `options` picks what's included; by
+/// default the saved `set_backup_options` choice is used. `note` is free text
+/// kept in the backup's manifest. Files are streamed into the zip, and a
+/// `storage:backup-progress` event follows each one.
+///
+/// With a `passphrase`, the finished zip is encrypted, and with
+/// `remember_passphrase` the passphrase is kept in the secret store for
+/// restores.
+#[tauri::command]
+pub fn create_backup(
+    tag: String,
+    options: Option<BackupOptions>,
+    note: Option<String>,
+    passphrase: Option<String>,
+    remember_passphrase: Option<bool>,
+    app: tauri::AppHandle,
     state: tauri::State<'_, StorageState>,
 ) -> Result<String, String> {
+    let _write = state.begin_write()?;
     let tag = sanitize_tag(&tag);
     if tag.is_empty() {
         return Err("Backup tag must contain at least one alphanumeric character".to_string());
@@ -260,148 +1757,675 @@ pub fn create_backup(
     fs::create_dir_all(&backup_dir)
         .map_err(|e| format!("Failed to create backup dir: {e}"))?;
 
-    let files = list_data_files(&data_dir);
+    // Zip entry names are paths relative to the data dir
+    let options = options.unwrap_or_else(|| BackupOptions::load(&data_dir));
+    let files = backup_files(&data_dir, &options);
     if files.is_empty() {
         return Ok(String::new());
     }
-
-    let now = chrono::Local::now().format("%Y-%m-%dT%H-%M-%S").to_string();
+    // The zip is at most the files' size; encrypting writes a second copy
+    let encrypting = passphrase.as_ref().is_some_and(|p| !p.is_empty());
+    let zip_bytes = crate::disk_space::total_size(files.iter().map(|(_, path)| path.as_path()));
+    crate::disk_space::ensure_space(
+        &backup_dir,
+        if encrypting { zip_bytes * 2 } else { zip_bytes },
+    )?;
+
+    let created = chrono::Local::now();
+    let manifest = BackupManifest {
+        app_version: app.package_info().version.to_string(),
+        created_at: created.to_rfc3339(),
+        tag: tag.clone(),
+        note,
+        files: manifest_files(&files)?,
+    };
+    let now = created.format("%Y-%m-%dT%H-%M-%S").to_string();
     let zip_name = format!("backup_{now}.{tag}.zip");
     let zip_path = backup_dir.join(&zip_name);
-    let tmp_path = zip_path.with_extension("zip.tmp");
+    // Under a temp name, so startup cleanup removes the plaintext zip if
+    // encrypting it is interrupted
+    let tmp_path = temp_path(&zip_path);
 
     let zip_file = fs::File::create(&tmp_path)
         .map_err(|e| format!("Failed to create backup zip: {e}"))?;
-    let mut zip_writer = zip::ZipWriter::new(zip_file);
+    let result = write_backup_zip(zip_file, &manifest, &files, |progress| {
+        use tauri::Emitter as _;
+        let _ = app.emit(crate::events::BACKUP_PROGRESS_EVENT, progress);
+    });
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+
+    if let Some(passphrase) = passphrase.filter(|p| !p.is_empty()) {
+        let encrypted_path = temp_path(&zip_path);
+        let result = crate::backup_crypto::encrypt(&tmp_path, &encrypted_path, &passphrase);
+        let _ = fs::remove_file(&tmp_path);
+        if let Err(e) = result {
+            let _ = fs::remove_file(&encrypted_path);
+            return Err(e);
+        }
+        fs::rename(&encrypted_path, &zip_path)
+            .map_err(|e| format!("Failed to rename backup zip: {e}"))?;
+        if remember_passphrase.unwrap_or(false) {
+            crate::secrets::set(&data_dir, BACKUP_PASSPHRASE_KEY, &passphrase)?;
+        }
+        return Ok(zip_name);
+    }
+
+    fs::rename(&tmp_path, &zip_path).map_err(|e| format!("Failed to rename backup zip: {e}"))?;
+
+    Ok(zip_name)
+}
+
+/// Write `manifest` and then stream `files` into a zip written to
+/// `zip_file`, with each entry stamped with its file's modification time.
+/// `progress` is told about each file once it's added.
+fn write_backup_zip(
+    zip_file: fs::File,
+    manifest: &BackupManifest,
+    files: &[(String, PathBuf)],
+    mut progress: impl FnMut(crate::events::BackupProgressPayload),
+) -> Result<(), String> {
+    let mut zip_writer = zip::ZipWriter::new(std::io::BufWriter::new(zip_file));
     let options = zip::write::SimpleFileOptions::default()
         .compression_method(zip::CompressionMethod::Deflated);
 
-    for file in &files {
-        let name = file
-            .file_name()
-            .ok_or("Invalid filename")?
-            .to_string_lossy();
-        let contents = fs::read(file)
-            .map_err(|e| format!("Failed to read {name}: {e}"))?;
+    let manifest = serde_json::to_vec_pretty(manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {e}"))?;
+    zip_writer
+        .start_file(BACKUP_MANIFEST, options)
+        .and_then(|()| zip_writer.write_all(&manifest).map_err(Into::into))
+        .map_err(|e| format!("Failed to add manifest to zip: {e}"))?;
+
+    let mut bytes = 0;
+    for (index, (name, file)) in files.iter().enumerate() {
+        let mut source = fs::File::open(file).map_err(|e| format!("Failed to read {name}: {e}"))?;
+        let size = source.metadata().map(|meta| meta.len()).unwrap_or(0);
+        let mut entry_options = options.large_file(size >= u32::MAX as u64);
+        if let Some(modified) = zip_timestamp(file) {
+            entry_options = entry_options.last_modified_time(modified);
+        }
         zip_writer
-            .start_file(name.as_ref(), options)
+            .start_file(name.as_str(), entry_options)
             .map_err(|e| format!("Failed to add {name} to zip: {e}"))?;
-        zip_writer
-            .write_all(&contents)
+        bytes += std::io::copy(&mut source, &mut zip_writer)
             .map_err(|e| format!("Failed to write {name} to zip: {e}"))?;
+        progress(crate::events::BackupProgressPayload {
+            index: index + 1,
+            total: files.len(),
+            bytes,
+        });
     }
 
     zip_writer
         .finish()
-        .map_err(|e| format!("Failed to finalize zip: {e}"))?;
-
-    fs::rename(&tmp_path, &zip_path)
-        .map_err(|e| format!("Failed to rename backup zip: {e}"))?;
+        .map_err(|e| format!("Failed to finalize zip: {e}"))?
+        .flush()
+        .map_err(|e| format!("Failed to finalize zip: {e}"))
+}
 
-    Ok(zip_name)
+/// The files in a backup and its note, from its manifest, or for backups
+/// made before manifests by listing the zip's entries.
+fn backup_contents(path: &Path) -> Option<(Vec<String>, Option<String>)> {
+    let zip_file = fs::File::open(path).ok()?;
+    let mut archive = zip::ZipArchive::new(std::io::BufReader::new(zip_file)).ok()?;
+    let manifest = archive
+        .by_name(BACKUP_MANIFEST)
+        .ok()
+        .and_then(|entry| serde_json::from_reader::<_, BackupManifest>(entry).ok());
+    if let Some(manifest) = manifest {
+        return Some((
+            manifest.files.into_iter().map(|file| file.name).collect(),
+            manifest.note,
+        ));
+    }
+    let files = archive.file_names().map(str::to_string).collect();
+    Some((files, None))
 }
 
+/// Backups in `backups/`, newest first. Runs off the command thread, since a
+/// long list means opening every zip.
 #[tauri::command]
-pub fn list_backups(state: tauri::State<'_, StorageState>) -> Vec<BackupEntry> {
-    let backup_dir = state.get_dir().join("backups");
-    let Ok(entries) = fs::read_dir(&backup_dir) else {
+pub async fn list_backups(app: tauri::AppHandle) -> Vec<BackupEntry> {
+    let backup_dir = app.state::<StorageState>().get_dir().join("backups");
+    tauri::async_runtime::spawn_blocking(move || read_backups(&backup_dir))
+        .await
+        .unwrap_or_default()
+}
+
+fn read_backups(backup_dir: &Path) -> Vec<BackupEntry> {
+    let Ok(entries) = fs::read_dir(backup_dir) else {
         return vec![];
     };
 
-    let mut backups: Vec<BackupEntry> = entries
-        .filter_map(|e| e.ok())
-        .filter(|e| {
-            e.path().is_file()
-                && e.path()
-                    .extension()
-                    .is_some_and(|ext| ext == "zip")
-        })
-        .filter_map(|e| {
-            let path = e.path();
-            let filename = path.file_name()?.to_string_lossy().to_string();
-            let size = e.metadata().ok()?.len();
-            let (timestamp, tag) = parse_backup_filename(&filename)?;
+    let mut backups: Vec<BackupEntry> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.path().is_file()
+                && e.path()
+                    .extension()
+                    .is_some_and(|ext| ext == "zip")
+        })
+        .filter_map(|e| {
+            let path = e.path();
+            let filename = path.file_name()?.to_string_lossy().to_string();
+            let size = e.metadata().ok()?.len();
+            let (timestamp, tag) = parse_backup_filename(&filename)?;
+            let encrypted = crate::backup_crypto::is_encrypted(&path);
+            let (files, note) = if encrypted {
+                (Vec::new(), None)
+            } else {
+                backup_contents(&path)?
+            };
+
+            Some(BackupEntry {
+                path: path.to_string_lossy().to_string(),
+                filename,
+                timestamp,
+                tag,
+                size,
+                files,
+                note,
+                encrypted,
+            })
+        })
+        .collect();
+
+    // Sort newest first by timestamp
+    backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    backups
+}
+
+/// Resolve `backup_path`, which must be a file in the backups directory.
+fn backup_file(backup_path: &str, state: &StorageState) -> Result<PathBuf, String> {
+    let backup = PathBuf::from(backup_path);
+    if !backup.is_file() {
+        return Err(format!("Backup file not found: {backup_path}"));
+    }
+
+    // Ensure the backup path is under our backups directory
+    let backup_dir = state.get_dir().join("backups");
+    let canonical_backup = backup
+        .canonicalize()
+        .map_err(|e| format!("Invalid backup path: {e}"))?;
+    let canonical_dir = backup_dir
+        .canonicalize()
+        .map_err(|e| format!("Backup dir error: {e}"))?;
+    if !canonical_backup.starts_with(&canonical_dir) {
+        return Err("Backup path is outside the backups directory".to_string());
+    }
+    Ok(backup)
+}
+
+/// Why an entry shouldn't be unpacked at all, judged from what its header
+/// claims: too big, or compressed suspiciously well.
+pub(crate) fn entry_size_problem(
+    entry: &zip::read::ZipFile<'_>,
+    max_entry_bytes: u64,
+) -> Option<String> {
+    let size = entry.size();
+    if size > max_entry_bytes {
+        return Some(format!(
+            "would unpack to {size} bytes, over the {max_entry_bytes} byte limit"
+        ));
+    }
+    if size > RATIO_CHECK_MIN_BYTES && size / entry.compressed_size().max(1) > MAX_COMPRESSION_RATIO
+    {
+        return Some(format!(
+            "is compressed {}:1, likely a zip bomb",
+            size / entry.compressed_size().max(1)
+        ));
+    }
+    None
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorruptEntry {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Outcome of `verify_backup`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupVerification {
+    pub ok: Vec<String>,
+    pub corrupt: Vec<CorruptEntry>,
+    /// In the manifest but not in the zip.
+    pub missing: Vec<String>,
+    /// Uncompressed bytes read and checked.
+    pub bytes_verified: u64,
+}
+
+impl BackupVerification {
+    pub fn passed(&self) -> bool {
+        self.corrupt.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Read every entry of `backup` to the end. The zip reader checks each
+/// entry's CRC against the zip's own metadata as it finishes; the CRC and size
+/// are also checked against the manifest, if the backup has one. Entries that
+/// fail `entry_size_problem` aren't read. With `only`, just those entries are
+/// checked.
+fn verify_archive(
+    backup: &Path,
+    max_entry_bytes: u64,
+    only: Option<&HashSet<String>>,
+) -> Result<BackupVerification, String> {
+    let zip_file = fs::File::open(backup).map_err(|e| format!("Failed to open backup: {e}"))?;
+    let mut archive = zip::ZipArchive::new(std::io::BufReader::new(zip_file))
+        .map_err(|e| format!("Invalid backup zip: {e}"))?;
+    let manifest: Option<BackupManifest> = archive
+        .by_name(BACKUP_MANIFEST)
+        .ok()
+        .and_then(|entry| serde_json::from_reader(entry).ok());
+    let mut expected: HashMap<String, BackupManifestFile> = manifest
+        .map(|m| {
+            m.files
+                .into_iter()
+                .map(|file| (file.name.clone(), file))
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(only) = only {
+        expected.retain(|name, _| only.contains(name));
+    }
+
+    let mut report = BackupVerification::default();
+    let mut buf = vec![0u8; 64 * 1024];
+    for i in 0..archive.len() {
+        let mut entry = match archive.by_index(i) {
+            Ok(entry) => entry,
+            Err(e) => {
+                report.corrupt.push(CorruptEntry {
+                    name: format!("entry {i}"),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let name = entry.name().to_string();
+        if only.is_some_and(|only| !only.contains(&name)) {
+            continue;
+        }
+        if let Some(reason) = entry_size_problem(&entry, max_entry_bytes) {
+            expected.remove(&name);
+            report.corrupt.push(CorruptEntry { name, reason });
+            continue;
+        }
+        let declared = entry.size();
+        let mut hasher = crc32fast::Hasher::new();
+        let mut size = 0u64;
+        let read = loop {
+            match entry.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(n) => {
+                    hasher.update(&buf[..n]);
+                    size += n as u64;
+                    // The header may understate the size; don't trust it
+                    if size > declared {
+                        break Err(format!(
+                            "unpacks to more than the {declared} bytes declared"
+                        ));
+                    }
+                }
+                Err(e) => break Err(e.to_string()),
+            }
+        };
+        report.bytes_verified += size;
+        let crc = hasher.finalize();
+        let reason = match (read, expected.remove(&name)) {
+            (Err(reason), _) => Some(reason),
+            (Ok(()), Some(file)) if file.crc32 != crc || file.size != size => {
+                Some("doesn't match the manifest's checksum".to_string())
+            }
+            (Ok(()), _) => None,
+        };
+        match reason {
+            Some(reason) => report.corrupt.push(CorruptEntry { name, reason }),
+            None => report.ok.push(name),
+        }
+    }
+    report.missing = expected.into_keys().collect();
+    report.missing.sort();
+    Ok(report)
+}
+
+/// Check that a backup in `backups/` is intact: every entry reads to the
+/// end with the right checksum, and nothing the manifest lists is missing.
+/// Encrypted backups need `passphrase` unless one is remembered; one that
+/// fails its own integrity check is reported as a single corrupt entry.
+#[tauri::command]
+pub fn verify_backup(
+    backup_path: String,
+    passphrase: Option<String>,
+    state: tauri::State<'_, StorageState>,
+) -> Result<BackupVerification, StorageError> {
+    let backup = backup_file(&backup_path, &state)?;
+    let data_dir = state.get_dir();
+    let opened = match open_backup(&backup, passphrase, &data_dir) {
+        Ok(opened) => opened,
+        Err(StorageError::CorruptBackup { message }) => {
+            let name = backup
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            return Ok(BackupVerification {
+                corrupt: vec![CorruptEntry {
+                    name,
+                    reason: message,
+                }],
+                ..Default::default()
+            });
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(verify_archive(
+        &opened.path,
+        BackupOptions::load(&data_dir).max_entry_bytes,
+        None,
+    )?)
+}
+
+/// JSON files up to this size that differ are parsed to summarise the change;
+/// anything bigger is only compared by checksum.
+const MAX_JSON_DIFF_BYTES: u64 = 8 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffStatus {
+    Identical,
+    Modified,
+    OnlyInBackup,
+    OnlyInCurrent,
+}
 
-            // Read file list from zip
-            let zip_file = fs::File::open(&path).ok()?;
-            let archive = zip::ZipArchive::new(zip_file).ok()?;
-            let files: Vec<String> = (0..archive.len())
-                .filter_map(|i| {
-                    archive
-                        .name_for_index(i)
-                        .map(|name| name.to_string())
-                })
-                .collect();
+/// Top-level keys of a JSON object that differ, going from the backup to the
+/// current file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JsonKeyChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
 
-            Some(BackupEntry {
-                path: path.to_string_lossy().to_string(),
-                filename,
-                timestamp,
-                tag,
-                size,
-                files,
-            })
-        })
-        .collect();
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupDiffEntry {
+    pub name: String,
+    pub status: DiffStatus,
+    pub backup_size: Option<u64>,
+    pub current_size: Option<u64>,
+    /// For modified JSON objects small enough to parse.
+    pub keys: Option<JsonKeyChanges>,
+}
 
-    // Sort newest first by timestamp
-    backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-    backups
+fn json_key_changes(
+    backup: &serde_json::Value,
+    current: &serde_json::Value,
+) -> Option<JsonKeyChanges> {
+    let (backup, current) = (backup.as_object()?, current.as_object()?);
+    let mut changes = JsonKeyChanges {
+        added: current
+            .keys()
+            .filter(|key| !backup.contains_key(*key))
+            .cloned()
+            .collect(),
+        removed: backup
+            .keys()
+            .filter(|key| !current.contains_key(*key))
+            .cloned()
+            .collect(),
+        changed: backup
+            .iter()
+            .filter(|(key, value)| current.get(*key).is_some_and(|v| v != *value))
+            .map(|(key, _)| key.clone())
+            .collect(),
+    };
+    changes.added.sort();
+    changes.removed.sort();
+    changes.changed.sort();
+    Some(changes)
 }
 
+/// Compare a backup with the current data: every file in the backup, plus
+/// the files a backup made now would hold. Files are compared by size and
+/// CRC-32, using the checksums the zip already stores, so the backup's
+/// contents are only unpacked to summarise a changed JSON file. Read-only,
+/// apart from the temporary decrypted copy of an encrypted backup.
 #[tauri::command]
-pub fn restore_backup(
+pub fn diff_backup(
     backup_path: String,
+    passphrase: Option<String>,
     state: tauri::State<'_, StorageState>,
-) -> Result<(), String> {
-    let backup = PathBuf::from(&backup_path);
-    if !backup.is_file() {
-        return Err(format!("Backup file not found: {backup_path}"));
+) -> Result<Vec<BackupDiffEntry>, StorageError> {
+    let backup = backup_file(&backup_path, &state)?;
+    let data_dir = state.get_dir();
+    let opened = open_backup(&backup, passphrase, &data_dir)?;
+    let zip_file =
+        fs::File::open(&opened.path).map_err(|e| format!("Failed to open backup: {e}"))?;
+    let mut archive = zip::ZipArchive::new(std::io::BufReader::new(zip_file))
+        .map_err(|e| format!("Invalid backup zip: {e}"))?;
+
+    let mut current: HashMap<String, PathBuf> =
+        backup_files(&data_dir, &BackupOptions::load(&data_dir))
+            .into_iter()
+            .collect();
+    let mut diff = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| format!("Failed to read zip entry: {e}"))?;
+        let name = entry.name().to_string();
+        if (i == 0 && name == BACKUP_MANIFEST)
+            || entry.is_dir()
+            || validate_backup_entry(&name).is_err()
+        {
+            continue;
+        }
+        let path = current
+            .remove(&name)
+            .unwrap_or_else(|| data_dir.join(&name));
+        let backup_size = Some(entry.size());
+        let Some((size, crc)) = path.is_file().then(|| file_crc(&path).ok()).flatten() else {
+            diff.push(BackupDiffEntry {
+                name,
+                status: DiffStatus::OnlyInBackup,
+                backup_size,
+                current_size: None,
+                keys: None,
+            });
+            continue;
+        };
+        if size == entry.size() && crc == entry.crc32() {
+            diff.push(BackupDiffEntry {
+                name,
+                status: DiffStatus::Identical,
+                backup_size,
+                current_size: Some(size),
+                keys: None,
+            });
+            continue;
+        }
+        let parse = name.ends_with(".json")
+            && size <= MAX_JSON_DIFF_BYTES
+            && entry.size() <= MAX_JSON_DIFF_BYTES
+            && entry_size_problem(&entry, MAX_JSON_DIFF_BYTES).is_none();
+        let keys = parse
+            .then(|| {
+                let mut contents = Vec::new();
+                (&mut entry)
+                    .take(MAX_JSON_DIFF_BYTES)
+                    .read_to_end(&mut contents)
+                    .ok()?;
+                let before: serde_json::Value = serde_json::from_slice(&contents).ok()?;
+                let after: serde_json::Value =
+                    serde_json::from_slice(&fs::read(&path).ok()?).ok()?;
+                json_key_changes(&before, &after)
+            })
+            .flatten();
+        diff.push(BackupDiffEntry {
+            name,
+            status: DiffStatus::Modified,
+            backup_size,
+            current_size: Some(size),
+            keys,
+        });
+    }
+    for (name, path) in current {
+        let current_size = fs::metadata(&path).ok().map(|meta| meta.len());
+        diff.push(BackupDiffEntry {
+            name,
+            status: DiffStatus::OnlyInCurrent,
+            backup_size: None,
+            current_size,
+            keys: None,
+        });
     }
+    diff.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(diff)
+}
 
-    // Ensure the backup path is under our backups directory
-    let backup_dir = state.get_dir().join("backups");
-    let canonical_backup = backup
-        .canonicalize()
-        .map_err(|e| format!("Invalid backup path: {e}"))?;
-    let canonical_dir = backup_dir
+/// Where `restore_backup` unpacks to: the data dir, or `restore_to`, an
+/// absolute folder that is created if needed. The flag is whether it's the
+/// live data dir.
+fn restore_target(restore_to: Option<String>, data_dir: &Path) -> Result<(PathBuf, bool), String> {
+    let Some(target) = restore_to else {
+        return Ok((data_dir.to_path_buf(), true));
+    };
+    let target = PathBuf::from(target);
+    if !target.is_absolute() {
+        return Err(format!(
+            "Restore folder must be an absolute path: {}",
+            target.display()
+        ));
+    }
+    fs::create_dir_all(&target)
+        .map_err(|e| format!("Failed to create {}: {e}", target.display()))?;
+    let canonical = target
         .canonicalize()
-        .map_err(|e| format!("Backup dir error: {e}"))?;
-    if !canonical_backup.starts_with(&canonical_dir) {
-        return Err("Backup path is outside the backups directory".to_string());
+        .map_err(|e| format!("Invalid restore folder: {e}"))?;
+    let live = data_dir.canonicalize().is_ok_and(|dir| dir == canonical);
+    if !live && canonical.starts_with(data_dir.join("backups").canonicalize().unwrap_or_default()) {
+        return Err("Cannot restore into the backups folder".to_string());
     }
+    Ok((target, live))
+}
 
-    // Create a pre-restore backup first
-    create_backup("pre-restore".to_string(), state.clone())?;
-
-    // Extract zip contents to data dir
+/// Restore a backup, after verifying it. `files` picks which entries to
+/// unpack (all of them by default), and `restore_to` unpacks into another
+/// folder instead of over the data dir, e.g. to compare side by side. Only a
+/// restore into the data dir makes a pre-restore backup first.
+///
+/// A backup that fails verification is refused unless `force` is set, though
+/// entries failing the size checks never unpack. Encrypted backups need
+/// `passphrase` unless one is remembered. Returns the files written.
+#[tauri::command]
+pub fn restore_backup(
+    backup_path: String,
+    force: Option<bool>,
+    files: Option<Vec<String>>,
+    restore_to: Option<String>,
+    passphrase: Option<String>,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, StorageState>,
+) -> Result<Vec<String>, StorageError> {
+    let _write = state.begin_write()?;
+    let backup = backup_file(&backup_path, &state)?;
     let data_dir = state.get_dir();
+    let max_entry_bytes = BackupOptions::load(&data_dir).max_entry_bytes;
+    let opened = open_backup(&backup, passphrase, &data_dir)?;
+
     let zip_file =
-        fs::File::open(&backup).map_err(|e| format!("Failed to open backup: {e}"))?;
+        fs::File::open(&opened.path).map_err(|e| format!("Failed to open backup: {e}"))?;
     let mut archive =
         zip::ZipArchive::new(zip_file).map_err(|e| format!("Invalid backup zip: {e}"))?;
+    let only: Option<HashSet<String>> = files.map(|files| files.into_iter().collect());
+    if let Some(only) = &only {
+        let mut not_found: Vec<&str> = only
+            .iter()
+            .filter(|name| *name == BACKUP_MANIFEST || archive.index_for_name(name).is_none())
+            .map(String::as_str)
+            .collect();
+        if !not_found.is_empty() {
+            not_found.sort();
+            return Err(format!("Not in this backup: {}", not_found.join(", ")).into());
+        }
+    }
 
+    if !force.unwrap_or(false) {
+        let report = verify_archive(&opened.path, max_entry_bytes, only.as_ref())?;
+        if !report.passed() {
+            let mut problems: Vec<String> = report
+                .corrupt
+                .iter()
+                .map(|entry| format!("{}: {}", entry.name, entry.reason))
+                .collect();
+            problems.extend(report.missing.iter().map(|name| format!("{name}: missing")));
+            return Err(format!("Backup failed verification: {}", problems.join("; ")).into());
+        }
+    }
+
+    let (target, live) = restore_target(restore_to, &data_dir)?;
+    if live {
+        create_backup(
+            "pre-restore".to_string(),
+            None,
+            None,
+            None,
+            None,
+            app,
+            state.clone(),
+        )?;
+    }
+
+    let mut written = Vec::new();
     for i in 0..archive.len() {
-        let mut entry = archive
+        let entry = archive
             .by_index(i)
             .map_err(|e| format!("Failed to read zip entry: {e}"))?;
         let name = entry.name().to_string();
+        if i == 0 && name == BACKUP_MANIFEST {
+            continue;
+        }
+        if only.as_ref().is_some_and(|only| !only.contains(&name)) {
+            continue;
+        }
+
+        restore_entry(entry, &target, max_entry_bytes)?;
+        written.push(name);
+    }
 
-        // Safety: only extract files with safe names
-        validate_filename(&name)?;
+    Ok(written)
+}
 
-        let dest = data_dir.join(&name);
-        let mut contents = Vec::new();
-        entry
-            .read_to_end(&mut contents)
-            .map_err(|e| format!("Failed to read {name} from zip: {e}"))?;
-        fs::write(&dest, &contents)
-            .map_err(|e| format!("Failed to restore {name}: {e}"))?;
+/// Unpack one backup entry under `target`, streaming it to disk.
+fn restore_entry(
+    entry: zip::read::ZipFile<'_>,
+    target: &Path,
+    max_entry_bytes: u64,
+) -> Result<(), String> {
+    let name = entry.name().to_string();
+    // Safety: only extract files with safe names. Old backups hold plain
+    // filenames, which pass too.
+    validate_backup_entry(&name)?;
+    if let Some(problem) = entry_size_problem(&entry, max_entry_bytes) {
+        return Err(format!("Refusing to restore {name}: it {problem}"));
     }
 
-    Ok(())
+    let dest = target.join(&name);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to restore {name}: {e}"))?;
+    }
+    // Never unpack more than the header declared
+    let declared = entry.size();
+    atomic_write_from(&dest, entry.take(declared), false)
+        .map_err(|e| format!("Failed to restore {name}: {e}"))
 }
 
+/// Appends at least this big check for free space first.
+const LOG_SPACE_CHECK_BYTES: u64 = 1024 * 1024;
+
 #[tauri::command]
 pub fn append_to_log(
     subdir: String,
@@ -409,15 +2433,30 @@ pub fn append_to_log(
     content: String,
     state: tauri::State<'_, StorageState>,
 ) -> Result<(), String> {
+    let _write = state.begin_write()?;
     validate_filename(&filename)?;
     // Validate subdir: only allow simple directory names
     if subdir.contains("..") || subdir.contains('/') || subdir.contains('\\') || subdir.contains('\0') || subdir.is_empty() {
         return Err(format!("Invalid subdirectory: {subdir}"));
     }
 
-    let dir = state.get_dir().join(&subdir);
+    let data_dir = state.get_dir();
+    let rotation = crate::log_rotation::LogRotation::load(&data_dir);
+    let dir = data_dir.join(&subdir);
     fs::create_dir_all(&dir).map_err(|e| format!("Failed to create log dir: {e}"))?;
     let path = dir.join(&filename);
+    if content.len() as u64 >= LOG_SPACE_CHECK_BYTES {
+        crate::disk_space::ensure_space(&dir, content.len() as u64)?;
+    }
+
+    // Rotate and append under one lock so no append goes to a file that is
+    // being renamed away. If rotation fails the chunk still goes out.
+    let _guard = crate::log_rotation::APPEND_LOCK
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = rotation.rotate_if_due(&path, content.len()) {
+        log::warn!("Failed to rotate {}: {e}", path.display());
+    }
 
     use std::fs::OpenOptions;
     let mut file = OpenOptions::new()
@@ -434,9 +2473,10 @@ pub fn append_to_log(
 /* ── Custom sound files ──────────────────────────────────── */
 
 const MAX_SOUND_SIZE: u64 = 5 * 1024 * 1024; // 5 MB
+const DEFAULT_MAX_SOUND_SECS: f64 = 30.0;
 const ALLOWED_SOUND_EXTENSIONS: &[&str] = &["wav", "mp3", "ogg", "webm"];
 
-fn validate_sound_id(sound_id: &str) -> Result<(), String> {
+pub(crate) fn validate_sound_id(sound_id: &str) -> Result<(), String> {
     if sound_id.is_empty() || sound_id.len() > 64 {
         return Err("Sound id must be 1–64 characters".into());
     }
@@ -452,7 +2492,7 @@ fn validate_sound_id(sound_id: &str) -> Result<(), String> {
 }
 
 /// Find an existing custom sound file for a sound id (any supported extension).
-fn find_custom_sound(sounds_dir: &Path, sound_id: &str) -> Option<PathBuf> {
+pub(crate) fn find_custom_sound(sounds_dir: &Path, sound_id: &str) -> Option<PathBuf> {
     for ext in ALLOWED_SOUND_EXTENSIONS {
         let path = sounds_dir.join(format!("custom-{sound_id}.{ext}"));
         if path.is_file() {
@@ -462,12 +2502,25 @@ fn find_custom_sound(sounds_dir: &Path, sound_id: &str) -> Option<PathBuf> {
     None
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedSound {
+    /// The file name in `sounds/`.
+    pub filename: String,
+    pub duration_secs: Option<f64>,
+}
+
+/// Import a sound, checking its content rather than its extension: a file
+/// that isn't wav, mp3, ogg or webm is refused, and one with the wrong
+/// extension is imported under the right one. Sounds longer than
+/// `max_duration_secs` (30 s by default) are refused too.
 #[tauri::command]
 pub fn import_sound_file(
     source_path: String,
     sound_id: String,
+    max_duration_secs: Option<f64>,
     state: tauri::State<'_, StorageState>,
-) -> Result<String, String> {
+) -> Result<ImportedSound, String> {
+    let _write = state.begin_write()?;
     validate_sound_id(&sound_id)?;
 
     let source = PathBuf::from(&source_path);
@@ -475,15 +2528,34 @@ pub fn import_sound_file(
         return Err(format!("File not found: {source_path}"));
     }
 
-    // Validate extension
     let ext = source
         .extension()
         .and_then(|e| e.to_str())
         .map(|e| e.to_lowercase())
         .unwrap_or_default();
-    if !ALLOWED_SOUND_EXTENSIONS.contains(&ext.as_str()) {
+    let probe = crate::audio_probe::probe(&source).map_err(|e| format!("Cannot read file: {e}"))?;
+    let (format, duration_secs) = match probe {
+        crate::audio_probe::Probe::Audio {
+            format,
+            duration_secs,
+        } => (format, duration_secs),
+        crate::audio_probe::Probe::Other(kind) => {
+            return Err(format!(
+                "Not a supported audio file: this looks like {kind}. Use wav, mp3, ogg, or webm."
+            ));
+        }
+    };
+    if ext != format.extension() {
+        log::info!(
+            "{source_path} holds {} audio; importing it under that extension",
+            format.extension()
+        );
+    }
+    let ext = format.extension();
+    let max_duration = max_duration_secs.unwrap_or(DEFAULT_MAX_SOUND_SECS);
+    if let Some(duration) = duration_secs.filter(|secs| *secs > max_duration) {
         return Err(format!(
-            "Unsupported audio format: .{ext}. Use wav, mp3, ogg, or webm."
+            "Sound is too long ({duration:.1} s). Maximum is {max_duration:.0} s."
         ));
     }
 
@@ -504,11 +2576,13 @@ pub fn import_sound_file(
     let dest = sounds_dir.join(&dest_name);
 
     // Atomic write: copy to temp file then rename (before deleting old)
-    let tmp_dest = dest.with_extension(format!("{ext}.tmp"));
+    let tmp_dest = temp_path(&dest);
     fs::copy(&source, &tmp_dest)
-        .map_err(|e| format!("Failed to copy sound file: {e}"))?;
-    fs::rename(&tmp_dest, &dest)
-        .map_err(|e| format!("Failed to finalize sound file: {e}"))?;
+        .and_then(|_| fs::rename(&tmp_dest, &dest))
+        .map_err(|e| {
+            let _ = fs::remove_file(&tmp_dest);
+            format!("Failed to copy sound file: {e}")
+        })?;
 
     // Remove old custom sound only after new one is safely in place
     // (it may have a different extension, e.g. old .mp3 replaced by new .wav)
@@ -518,7 +2592,10 @@ pub fn import_sound_file(
         }
     }
 
-    Ok(dest_name)
+    Ok(ImportedSound {
+        filename: dest_name,
+        duration_secs,
+    })
 }
 
 #[tauri::command]
@@ -552,6 +2629,7 @@ pub fn remove_custom_sound(
     sound_id: String,
     state: tauri::State<'_, StorageState>,
 ) -> Result<(), String> {
+    let _write = state.begin_write()?;
     validate_sound_id(&sound_id)?;
 
     let sounds_dir = state.get_dir().join("sounds");
@@ -790,6 +2868,7 @@ pub fn delete_session_log(
     filename: String,
     state: tauri::State<'_, StorageState>,
 ) -> Result<(), String> {
+    let _write = state.begin_write()?;
     validate_filename(&filename)?;
     let path = state.get_dir().join("sessions").join(&filename);
     if path.exists() {
@@ -798,36 +2877,673 @@ pub fn delete_session_log(
     Ok(())
 }
 
-#[tauri::command]
-pub fn prune_backups(keep: usize, state: tauri::State<'_, StorageState>) -> Result<usize, String> {
-    let backup_dir = state.get_dir().join("backups");
-    let Ok(entries) = fs::read_dir(&backup_dir) else {
-        return Ok(0);
+/// When the newest backup tagged `tag` was made, from its filename.
+pub(crate) fn newest_backup_time(backup_dir: &Path, tag: &str) -> Option<chrono::NaiveDateTime> {
+    fs::read_dir(backup_dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let (timestamp, backup_tag) = parse_backup_filename(&e.file_name().to_string_lossy())?;
+            (backup_tag == tag).then(|| {
+                chrono::NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%dT%H-%M-%S").ok()
+            })?
+        })
+        .max()
+}
+
+/// Delete old backups: for each tag in `policy`, all but the newest
+/// `keep` of that tag (or none, for `None`); the rest together down to `keep`,
+/// if given. Returns how many were deleted.
+pub(crate) fn prune_backups_by_policy(
+    backup_dir: &Path,
+    keep: Option<usize>,
+    policy: &HashMap<String, Option<usize>>,
+) -> usize {
+    let Ok(entries) = fs::read_dir(backup_dir) else {
+        return 0;
     };
 
-    let mut files: Vec<PathBuf> = entries
+    // Group by tag (None: not in the policy)
+    let mut groups: HashMap<Option<&str>, Vec<(String, PathBuf)>> = HashMap::new();
+    for path in entries
         .filter_map(|e| e.ok())
         .map(|e| e.path())
         .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "zip"))
+    {
+        let filename = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let tag = parse_backup_filename(&filename).map(|(_, tag)| tag);
+        let group = tag.and_then(|tag| policy.get_key_value(&tag).map(|(tag, _)| tag.as_str()));
+        groups.entry(group).or_default().push((filename, path));
+    }
+
+    let mut deleted = 0;
+    for (group, mut files) in groups {
+        let Some(keep) = group.map_or(keep, |tag| policy[tag]) else {
+            continue;
+        };
+        // Sort newest first by filename (timestamps are embedded)
+        files.sort_by(|a, b| b.0.cmp(&a.0));
+        for (_, file) in files.iter().skip(keep) {
+            if fs::remove_file(file).is_ok() {
+                deleted += 1;
+            }
+        }
+    }
+    deleted
+}
+
+/// Delete old backups. `policy` maps a tag to how many of its backups to
+/// keep (`null` keeps them all), defaulting to the auto-backup retention;
+/// backups with other tags are pruned together down to `keep`, or kept if
+/// it's omitted.
+#[tauri::command]
+pub fn prune_backups(
+    keep: Option<usize>,
+    policy: Option<HashMap<String, Option<usize>>>,
+    state: tauri::State<'_, StorageState>,
+) -> Result<usize, String> {
+    let _write = state.begin_write()?;
+    let data_dir = state.get_dir();
+    let policy =
+        policy.unwrap_or_else(|| crate::auto_backup::AutoBackupSettings::load(&data_dir).retention);
+    Ok(prune_backups_by_policy(
+        &data_dir.join("backups"),
+        keep,
+        &policy,
+    ))
+}
+
+/* ── Log files ───────────────────────────────────────────── */
+
+/// Cap on one `read_log_tail` chunk.
+const MAX_TAIL_BYTES: usize = 4 * 1024 * 1024;
+/// `list_log_files` estimates line counts from this much of each file.
+const LINE_SAMPLE_BYTES: usize = 64 * 1024;
+
+/// Resolve a log subdirectory of the data dir such as `sessions` or
+/// `logs/Main`. Each `/`-separated component gets the usual filename checks.
+pub(crate) fn log_subdir(data_dir: &Path, subdir: &str) -> Result<PathBuf, String> {
+    let mut dir = data_dir.to_path_buf();
+    for component in subdir.split('/') {
+        validate_filename(component).map_err(|_| format!("Invalid subdirectory: {subdir}"))?;
+        dir.push(component);
+    }
+    Ok(dir)
+}
+
+pub(crate) fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogFileEntry {
+    pub filename: String,
+    pub size: u64,
+    pub modified: String,
+    /// Rotated generation stored gzipped; read transparently.
+    pub compressed: bool,
+    /// Estimated from the first 64KB (exact for smaller files); `None` for
+    /// compressed files.
+    pub estimated_lines: Option<u64>,
+}
+
+fn estimate_lines(path: &Path, size: u64) -> Option<u64> {
+    let mut sample = Vec::with_capacity(LINE_SAMPLE_BYTES);
+    fs::File::open(path)
+        .ok()?
+        .take(LINE_SAMPLE_BYTES as u64)
+        .read_to_end(&mut sample)
+        .ok()?;
+    let newlines = sample.iter().filter(|&&b| b == b'\n').count() as u64;
+    if sample.is_empty() || size <= sample.len() as u64 {
+        return Some(newlines);
+    }
+    Some(newlines * size / sample.len() as u64)
+}
+
+#[tauri::command]
+pub fn list_log_files(
+    subdir: String,
+    state: tauri::State<'_, StorageState>,
+) -> Result<Vec<LogFileEntry>, String> {
+    let dir = log_subdir(&state.get_dir(), &subdir)?;
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(vec![]);
+    };
+
+    let mut files: Vec<LogFileEntry> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| {
+            let path = e.path();
+            let meta = e.metadata().ok()?;
+            let datetime: chrono::DateTime<chrono::Local> = meta.modified().ok()?.into();
+            let compressed = is_gzip(&path);
+            Some(LogFileEntry {
+                filename: e.file_name().to_string_lossy().to_string(),
+                size: meta.len(),
+                modified: datetime.to_rfc3339(),
+                compressed,
+                estimated_lines: if compressed {
+                    None
+                } else {
+                    estimate_lines(&path, meta.len())
+                },
+            })
+        })
         .collect();
 
-    if files.len() <= keep {
-        return Ok(0);
+    files.sort_by(|a, b| b.modified.cmp(&a.modified));
+    Ok(files)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogTail {
+    pub content: String,
+    /// Byte offset of `content` in the (decompressed) file.
+    pub start: u64,
+    /// Size of the (decompressed) file.
+    pub total_size: u64,
+    /// There is earlier content; pass `total_size - start` as
+    /// `offset_from_end` to read it.
+    pub has_more: bool,
+}
+
+/// The last `keep` bytes of a gzipped file and its decompressed size,
+/// without holding more than about twice `keep` in memory.
+fn gzip_tail(path: &Path, keep: usize) -> Result<(Vec<u8>, u64), String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open log: {e}"))?;
+    let mut decoder = flate2::read::GzDecoder::new(std::io::BufReader::new(file));
+    let mut window = Vec::new();
+    let mut total = 0u64;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = decoder
+            .read(&mut buf)
+            .map_err(|e| format!("Failed to decompress log: {e}"))?;
+        if n == 0 {
+            break;
+        }
+        total += n as u64;
+        window.extend_from_slice(&buf[..n]);
+        if window.len() > keep.max(buf.len()) * 2 {
+            window.drain(..window.len() - keep);
+        }
     }
+    if window.len() > keep {
+        window.drain(..window.len() - keep);
+    }
+    Ok((window, total))
+}
 
-    // Sort newest first by filename (timestamps are embedded)
-    files.sort_by(|a, b| {
-        b.file_name()
-            .unwrap_or_default()
-            .cmp(a.file_name().unwrap_or_default())
-    });
+/// Read up to `max_bytes` ending `offset_from_end` bytes before the end of a
+/// log, starting at a line boundary. Only the requested range is read from
+/// plain files; gzipped ones are decompressed as a stream.
+#[tauri::command]
+pub fn read_log_tail(
+    subdir: String,
+    filename: String,
+    max_bytes: usize,
+    offset_from_end: u64,
+    state: tauri::State<'_, StorageState>,
+) -> Result<LogTail, String> {
+    use std::io::{Seek, SeekFrom};
 
-    let mut deleted = 0;
-    for file in files.iter().skip(keep) {
-        if fs::remove_file(file).is_ok() {
-            deleted += 1;
+    validate_filename(&filename)?;
+    let path = log_subdir(&state.get_dir(), &subdir)?.join(&filename);
+    let max_bytes = max_bytes.min(MAX_TAIL_BYTES);
+
+    let (bytes, mut start, total_size) = if is_gzip(&path) {
+        let keep = (offset_from_end as usize).saturating_add(max_bytes);
+        let (window, total) = gzip_tail(&path, keep)?;
+        let end = total.saturating_sub(offset_from_end);
+        let start = end.saturating_sub(max_bytes as u64);
+        let base = total - window.len() as u64;
+        let from = (start.max(base) - base) as usize;
+        let to = (end.max(base) - base) as usize;
+        (window[from..to].to_vec(), start.max(base), total)
+    } else {
+        let mut file = fs::File::open(&path).map_err(|e| format!("Failed to open log: {e}"))?;
+        let total = file
+            .metadata()
+            .map_err(|e| format!("Failed to read log: {e}"))?
+            .len();
+        let end = total.saturating_sub(offset_from_end);
+        let start = end.saturating_sub(max_bytes as u64);
+        file.seek(SeekFrom::Start(start))
+            .map_err(|e| format!("Failed to read log: {e}"))?;
+        let mut bytes = Vec::with_capacity((end - start) as usize);
+        file.take(end - start)
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("Failed to read log: {e}"))?;
+        (bytes, start, total)
+    };
+
+    // Drop the partial first line unless the chunk is one unbroken line
+    let mut content = &bytes[..];
+    if start > 0 {
+        if let Some(pos) = content.iter().position(|&b| b == b'\n') {
+            content = &content[pos + 1..];
+            start += pos as u64 + 1;
         }
     }
+    Ok(LogTail {
+        content: String::from_utf8_lossy(content).into_owned(),
+        start,
+        total_size,
+        has_more: start > 0,
+    })
+}
 
-    Ok(deleted)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn age(path: &Path, secs_ago: u64) {
+        let at = std::time::SystemTime::now() - std::time::Duration::from_secs(secs_ago);
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(at))
+            .unwrap();
+    }
+
+    #[test]
+    fn reads_intact_file_without_touching_temps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aliases.json");
+        fs::write(&path, r#"{"a":1}"#).unwrap();
+        let temp = dir.path().join("aliases.json.tmp-00000001");
+        fs::write(&temp, r#"{"a":2}"#).unwrap();
+        assert_eq!(
+            read_json_or_recover(&path).unwrap().as_deref(),
+            Some(r#"{"a":1}"#)
+        );
+        assert!(temp.exists());
+    }
+
+    #[test]
+    fn recovers_newest_valid_temp_after_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aliases.json");
+        let older = dir.path().join("aliases.json.tmp-00000001");
+        let newer = dir.path().join("aliases.json.tmp-00000002");
+        let torn = dir.path().join("aliases.json.tmp-00000003");
+        let other = dir.path().join("aliases.json5.tmp-00000004");
+        fs::write(&older, r#"{"v":1}"#).unwrap();
+        fs::write(&newer, r#"{"v":2}"#).unwrap();
+        fs::write(&torn, r#"{"v":"#).unwrap();
+        fs::write(&other, r#"{"v":4}"#).unwrap();
+        age(&older, 30);
+        age(&newer, 20);
+        age(&torn, 10);
+
+        // The rename never happened
+        assert_eq!(
+            read_json_or_recover(&path).unwrap().as_deref(),
+            Some(r#"{"v":2}"#)
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), r#"{"v":2}"#);
+        assert!(!newer.exists());
+        assert!(older.exists() && torn.exists() && other.exists());
+
+        // The rename landed before the data did
+        fs::write(&path, "").unwrap();
+        assert_eq!(
+            read_json_or_recover(&path).unwrap().as_deref(),
+            Some(r#"{"v":1}"#)
+        );
+    }
+
+    #[test]
+    fn missing_file_without_temps_is_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aliases.json");
+        assert_eq!(read_json_or_recover(&path).unwrap(), None);
+        fs::write(&path, "  \n").unwrap();
+        assert_eq!(
+            read_json_or_recover(&path).unwrap().as_deref(),
+            Some("  \n")
+        );
+    }
+
+    #[test]
+    fn atomic_write_replaces_and_leaves_no_temp() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        atomic_write(&path, b"first", false).unwrap();
+        atomic_write_from(&path, &b"second"[..], true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        let names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["notes.txt"]);
+    }
+
+    #[test]
+    fn failed_atomic_write_removes_temp_and_keeps_file() {
+        struct Broken;
+        impl std::io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk on fire"))
+            }
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "kept").unwrap();
+        assert!(atomic_write_from(&path, Broken, false).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "kept");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn stale_backup_staging_zips_are_cleaned_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        fs::create_dir(&backups).unwrap();
+        let backup = backups.join("backup_2026-01-01T00-00-00.manual.zip");
+        fs::write(&backup, "kept").unwrap();
+        let stale = temp_path(&backup);
+        let fresh = temp_path(&backup);
+        for path in [&stale, &fresh] {
+            fs::write(path, "plaintext").unwrap();
+        }
+        let old = std::time::SystemTime::now() - ORPHANED_TEMP_AGE * 2;
+        fs::File::options()
+            .write(true)
+            .open(&stale)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        assert_eq!(remove_orphaned_temp_files(dir.path()), 1);
+        assert!(!stale.exists());
+        assert!(fresh.exists() && backup.exists());
+    }
+
+    #[test]
+    fn data_paths_stay_inside_the_data_dir() {
+        for ok in ["aliases.json", "profiles/Alaric/map.json", "notes/a b.txt"] {
+            assert!(validate_data_path(ok).is_ok(), "{ok}");
+        }
+        for bad in [
+            "",
+            "..",
+            "../secrets.enc",
+            "profiles/../../etc/passwd",
+            "/etc/passwd",
+            "profiles//map.json",
+            "profiles\\map.json",
+            "C:",
+            "C:/Windows/win.ini",
+            "notes.txt:stream",
+            "trailing.",
+            "trailing ",
+            "con",
+            "profiles/NUL.json",
+            "a/b/c/d/e.json",
+        ] {
+            assert!(validate_data_path(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn reserved_folders_are_refused() {
+        for bad in ["backups/x.json", "Sounds/ding.wav", ".trash/x.json"] {
+            assert!(
+                validate_data_path(bad).unwrap_err().contains("reserved"),
+                "{bad}"
+            );
+        }
+        // A file may share a reserved folder's name
+        assert!(validate_data_path("backups").is_ok());
+    }
+
+    #[test]
+    fn secret_store_files_are_refused() {
+        for bad in ["secrets.key", "secrets.enc", "Secrets.KEY"] {
+            assert!(
+                validate_data_path(bad).unwrap_err().contains("reserved"),
+                "{bad}"
+            );
+            assert!(validate_binary_path(bad).is_err(), "{bad}");
+            assert!(validate_backup_entry(bad).is_err(), "{bad}");
+        }
+        // Only the files at the top of the data dir are the secret store's
+        assert!(validate_data_path("profiles/secrets.key").is_ok());
+
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["secrets.key", "secrets.enc", "notes.txt"] {
+            fs::write(dir.path().join(name), "x").unwrap();
+        }
+        let listed = data_file_paths_with(dir.path(), &["key".into(), "enc".into()]);
+        assert_eq!(listed, vec![dir.path().join("notes.txt")]);
+    }
+
+    #[test]
+    fn backup_entries_cannot_escape_on_restore() {
+        for ok in [
+            "aliases.json",
+            "profiles/Alaric/map.json",
+            "sounds/ding.wav",
+        ] {
+            assert!(validate_backup_entry(ok).is_ok(), "{ok}");
+        }
+        for bad in [
+            "../outside.json",
+            "/abs/path.json",
+            "C:/Users/x.json",
+            "C:x.json",
+            "..\\outside.json",
+            "backups/nested.zip",
+            ".trash/old.json",
+        ] {
+            assert!(validate_backup_entry(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    fn corrupt_copies(dir: &Path, name: &str) -> Vec<String> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|n| n.starts_with(&format!("{name}.corrupt-")))
+            .collect()
+    }
+
+    #[test]
+    fn read_data_tells_missing_from_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(read_data(dir.path(), "triggers.json"), Ok(None)));
+        fs::write(dir.path().join("triggers.json"), r#"{"a": [1]}"#).unwrap();
+        assert_eq!(
+            read_data(dir.path(), "triggers.json").unwrap(),
+            Some(serde_json::json!({"a": [1]}))
+        );
+        assert!(matches!(
+            read_data(dir.path(), "../triggers.json"),
+            Err(StorageError::InvalidFilename { .. })
+        ));
+    }
+
+    #[test]
+    fn empty_and_truncated_files_are_parse_errors_and_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("empty.json"), "").unwrap();
+        let Err(StorageError::ParseError { backup, .. }) = read_data(dir.path(), "empty.json")
+        else {
+            panic!("empty file read as valid");
+        };
+        assert_eq!(corrupt_copies(dir.path(), "empty.json"), [backup.unwrap()]);
+
+        let truncated = "{\n  \"triggers\": [\n    {\"pattern\": \"x\"";
+        fs::write(dir.path().join("triggers.json"), truncated).unwrap();
+        let Err(StorageError::ParseError {
+            line,
+            backup: Some(backup),
+            ..
+        }) = read_data(dir.path(), "triggers.json")
+        else {
+            panic!("truncated file read as valid");
+        };
+        assert_eq!(line, 3);
+        assert_eq!(
+            fs::read_to_string(dir.path().join(backup)).unwrap(),
+            truncated
+        );
+        // The original is left for the user to repair
+        assert_eq!(
+            fs::read_to_string(dir.path().join("triggers.json")).unwrap(),
+            truncated
+        );
+    }
+
+    #[test]
+    fn unreadable_file_is_an_io_error() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("folder.json")).unwrap();
+        assert!(matches!(
+            read_data(dir.path(), "folder.json"),
+            Err(StorageError::IoError { .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permission_denied_is_an_io_error() {
+        use std::os::unix::fs::PermissionsExt as _;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        fs::write(&path, "{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+        // Root reads it regardless
+        if fs::read(&path).is_ok() {
+            return;
+        }
+        assert!(matches!(
+            read_data(dir.path(), "settings.json"),
+            Err(StorageError::IoError { .. })
+        ));
+        assert!(corrupt_copies(dir.path(), "settings.json").is_empty());
+    }
+
+    /// Peak resident memory of this process, where Linux reports it.
+    fn peak_rss_bytes() -> Option<u64> {
+        let status = fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+        let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kib * 1024)
+    }
+
+    /// Incompressible, so the entry doesn't read as a zip bomb on restore.
+    fn write_noise(path: &Path, size: u64) {
+        let mut out = std::io::BufWriter::new(fs::File::create(path).unwrap());
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..size / 8 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            out.write_all(&state.to_le_bytes()).unwrap();
+        }
+        out.flush().unwrap();
+    }
+
+    #[test]
+    fn restore_entry_unpacks_into_the_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = dir.path().join("backup.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        for name in ["maps/area.json", "../escape.json", "big.json"] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(br#"{"rooms":[]}"#).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let target = dir.path().join("restored");
+        let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        restore_entry(archive.by_index(0).unwrap(), &target, 1024).unwrap();
+        assert_eq!(
+            fs::read_to_string(target.join("maps/area.json")).unwrap(),
+            r#"{"rooms":[]}"#
+        );
+        assert!(restore_entry(archive.by_index(1).unwrap(), &target, 1024).is_err());
+        assert!(!dir.path().join("escape.json").exists());
+        let err = restore_entry(archive.by_index(2).unwrap(), &target, 4).unwrap_err();
+        assert!(err.contains("over the 4 byte limit"), "{err}");
+        assert!(!target.join("big.json").exists());
+    }
+
+    /// Writes about 900MB and measures this process's peak memory, so it
+    /// only runs on request and on its own:
+    /// `cargo test --lib large_files -- --ignored`.
+    #[test]
+    #[ignore = "writes about 900MB; run on its own"]
+    fn large_files_stream_through_backup_and_restore() {
+        const SIZE: u64 = 300 * 1024 * 1024;
+        let dir = tempfile::tempdir().unwrap();
+        let big = dir.path().join("map.bin");
+        write_noise(&big, SIZE);
+        fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+        let mtime =
+            std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        fs::File::options()
+            .write(true)
+            .open(dir.path().join("notes.txt"))
+            .and_then(|file| file.set_modified(mtime))
+            .unwrap();
+        let files = vec![
+            ("notes.txt".to_string(), dir.path().join("notes.txt")),
+            ("maps/map.bin".to_string(), big.clone()),
+        ];
+        let manifest = BackupManifest {
+            app_version: "test".to_string(),
+            created_at: String::new(),
+            tag: "manual".to_string(),
+            note: None,
+            files: manifest_files(&files).unwrap(),
+        };
+
+        let before = peak_rss_bytes();
+        let zip_path = dir.path().join("backup.zip");
+        let mut progress = Vec::new();
+        write_backup_zip(
+            fs::File::create(&zip_path).unwrap(),
+            &manifest,
+            &files,
+            |p| progress.push((p.index, p.total, p.bytes)),
+        )
+        .unwrap();
+        assert_eq!(progress, [(1, 2, 5), (2, 2, 5 + SIZE)]);
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), BACKUP_MANIFEST);
+        let notes = archive.by_name("notes.txt").unwrap();
+        assert_eq!(
+            notes.last_modified(),
+            zip_timestamp(&dir.path().join("notes.txt"))
+        );
+        drop(notes);
+        let entry = archive.by_name("maps/map.bin").unwrap();
+        assert_eq!(entry.size(), SIZE);
+        let target = dir.path().join("restored");
+        restore_entry(entry, &target, DEFAULT_MAX_ENTRY_BYTES).unwrap();
+        assert_eq!(
+            file_crc(&target.join("maps/map.bin")).unwrap(),
+            file_crc(&big).unwrap()
+        );
+
+        // Nothing close to the file's size was ever held in memory
+        if let (Some(before), Some(after)) = (before, peak_rss_bytes()) {
+            assert!(
+                after - before < SIZE / 4,
+                "peak memory grew by {} bytes",
+                after - before
+            );
+        }
+    }
 }
@@ -0,0 +1,221 @@
+//! Notices when data files are changed outside the app (a sync tool, another
+//! machine, a text editor) and reports each one as a `storage:file-changed`
+//! event. The data directory is polled rather than watched with OS
+//! notifications, which are unreliable on network drives and miss files that
+//! sync tools replace wholesale. A change is only reported once the file has
+//! stopped changing for a poll, and the app's own writes are skipped.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+use tokio::time::{Duration, Instant};
+
+use crate::events::{StorageFileChangedPayload, STORAGE_FILE_CHANGED_EVENT};
+use crate::storage::StorageState;
+
+pub const STORAGE_WATCH_FILE: &str = "storage-watch.json";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// A change to a file the app wrote this recently is taken to be that write.
+const OWN_WRITE_WINDOW: Duration = Duration::from_secs(10);
+
+/// Files the app itself just wrote or deleted, by path.
+static OWN_WRITES: Mutex<Option<HashMap<PathBuf, Instant>>> = Mutex::new(None);
+
+/// Record a write or delete by the app, so it isn't reported as external.
+pub fn note_own_write(path: &Path) {
+    let mut writes = OWN_WRITES.lock().unwrap_or_else(|e| e.into_inner());
+    let writes = writes.get_or_insert_with(HashMap::new);
+    let now = Instant::now();
+    writes.retain(|_, at| now.duration_since(*at) < OWN_WRITE_WINDOW);
+    writes.insert(path.to_path_buf(), now);
+}
+
+fn is_own_write(path: &Path) -> bool {
+    let writes = OWN_WRITES.lock().unwrap_or_else(|e| e.into_inner());
+    writes
+        .as_ref()
+        .and_then(|writes| writes.get(path))
+        .is_some_and(|at| at.elapsed() < OWN_WRITE_WINDOW)
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageWatchSettings {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl Default for StorageWatchSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+impl StorageWatchSettings {
+    pub fn load(dir: &Path) -> Self {
+        crate::storage::read_json_file(dir, STORAGE_WATCH_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        crate::storage::write_json_file(dir, STORAGE_WATCH_FILE, self)
+    }
+}
+
+/// Size and modification time, which together stand in for the contents.
+type Stamp = (u64, Option<SystemTime>);
+
+/// The data files directly in `dir`: not subfolders (backups, logs, sounds),
+/// temp files, preserved corrupt copies, or the machine-local config.
+fn snapshot(dir: &Path) -> HashMap<String, Stamp> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return HashMap::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.')
+                || name.contains(".tmp-")
+                || name.contains(".corrupt-")
+                || name == "local-config.json"
+            {
+                return None;
+            }
+            let meta = e.metadata().ok().filter(|meta| meta.is_file())?;
+            Some((name, (meta.len(), meta.modified().ok())))
+        })
+        .collect()
+}
+
+/// Wakes the watcher when it's switched on or off, rather than at its next
+/// poll.
+#[derive(Default)]
+pub struct StorageWatch {
+    wake: Notify,
+}
+
+impl StorageWatch {
+    pub fn changed(&self) {
+        self.wake.notify_one();
+    }
+}
+
+/// State for the directory being watched. Replaced whenever the data
+/// directory changes or watching is switched back on, so changes made while
+/// not watching aren't reported.
+struct Watched {
+    dir: PathBuf,
+    known: HashMap<String, Stamp>,
+    /// Changed since the last report and not yet stable, with their newest stamp.
+    pending: HashMap<String, Option<Stamp>>,
+}
+
+impl Watched {
+    fn new(dir: PathBuf) -> Self {
+        let known = snapshot(&dir);
+        Self {
+            dir,
+            known,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Changes that have held still since the last poll, as
+    /// `(filename, kind)`.
+    fn poll(&mut self) -> Vec<(String, &'static str)> {
+        let current = snapshot(&self.dir);
+        let mut settled = Vec::new();
+        let names: HashSet<String> = current
+            .keys()
+            .chain(self.known.keys())
+            .chain(self.pending.keys())
+            .cloned()
+            .collect();
+        for name in names {
+            let now = current.get(&name).copied();
+            let before = self.known.get(&name).copied();
+            match self.pending.get(&name) {
+                // Unchanged since the last poll: settled
+                Some(&last) if last == now => {
+                    self.pending.remove(&name);
+                    if now == before {
+                        continue;
+                    }
+                    match now {
+                        Some(stamp) => self.known.insert(name.clone(), stamp),
+                        None => self.known.remove(&name),
+                    };
+                    if is_own_write(&self.dir.join(&name)) {
+                        continue;
+                    }
+                    let kind = match (before, now) {
+                        (None, _) => "created",
+                        (_, None) => "removed",
+                        _ => "modified",
+                    };
+                    settled.push((name, kind));
+                }
+                _ if now != before || self.pending.contains_key(&name) => {
+                    self.pending.insert(name, now);
+                }
+                _ => {}
+            }
+        }
+        settled
+    }
+}
+
+/// Poll the active data directory every `POLL_INTERVAL` while watching is
+/// enabled there, for the life of the app. Switching data directories starts
+/// over with a fresh snapshot of the new one.
+pub async fn run(app: AppHandle) {
+    let mut watched: Option<Watched> = None;
+    loop {
+        let storage = app.state::<StorageState>();
+        let dir = storage.get_dir();
+        let previous = watched.take();
+        let result = tokio::task::spawn_blocking(move || {
+            if !StorageWatchSettings::load(&dir).enabled {
+                return None;
+            }
+            let mut current = match previous {
+                Some(w) if w.dir == dir => w,
+                _ => {
+                    info!("Watching {} for external changes", dir.display());
+                    Watched::new(dir)
+                }
+            };
+            let changes = current.poll();
+            Some((current, changes))
+        })
+        .await;
+        if let Ok(Some((current, changes))) = result {
+            for (filename, kind) in changes {
+                info!("{filename} was {kind} outside the app");
+                if kind != "removed" {
+                    crate::data_file_written(&app, &current.dir, &filename, None);
+                }
+                let payload = StorageFileChangedPayload {
+                    filename,
+                    kind: kind.to_string(),
+                };
+                let _ = app.emit(STORAGE_FILE_CHANGED_EVENT, payload);
+            }
+            watched = Some(current);
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            _ = storage.watch.wake.notified() => {}
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! Developer trace of telnet negotiation: while enabled, every telnet command
+//! read from or written to the server is emitted as `mud:telnet-debug` and
+//! appended to `telnet-debug.log` in the data dir. Off by default; when off
+//! the connection only checks one flag per read or write.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use log::{info, warn};
+use tauri::{AppHandle, Runtime};
+
+use crate::ansi::{self, TelnetEvent};
+use crate::events::{self, now_ms, TelnetDebugPayload, TELNET_DEBUG_EVENT};
+use crate::storage::StorageState;
+
+pub const TELNET_DEBUG_LOG: &str = "telnet-debug.log";
+/// Stop appending to the log once it reaches this size.
+const MAX_LOG_BYTES: u64 = 8 * 1024 * 1024;
+
+pub const DIR_IN: &str = "in";
+pub const DIR_OUT: &str = "out";
+
+struct DebugLog {
+    file: BufWriter<File>,
+    size: u64,
+}
+
+#[derive(Default)]
+pub struct TelnetDebug {
+    enabled: AtomicBool,
+    log: Mutex<Option<DebugLog>>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl TelnetDebug {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Turn tracing on, starting a fresh log in `dir`, or off.
+    pub fn set_enabled(&self, enabled: bool, dir: &Path) {
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(mut previous) = log.take() {
+            let _ = previous.file.flush();
+        }
+        if enabled {
+            let path = dir.join(TELNET_DEBUG_LOG);
+            let opened = crate::data_lock::check_writable(&path)
+                .map_err(std::io::Error::other)
+                .and_then(|()| {
+                    OpenOptions::new()
+                        .create(true)
+                        .write(true)
+                        .truncate(true)
+                        .open(&path)
+                });
+            match opened {
+                Ok(file) => {
+                    *log = Some(DebugLog {
+                        file: BufWriter::new(file),
+                        size: 0,
+                    })
+                }
+                Err(e) => warn!("Failed to open {}: {e}", path.display()),
+            }
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+        info!(
+            "Telnet debug {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+    }
+
+    /// Report the telnet commands in `events`, seen going `direction`.
+    pub fn record<R: Runtime>(
+        &self,
+        app: &AppHandle<R>,
+        direction: &'static str,
+        events: &[TelnetEvent],
+    ) {
+        if events.is_empty() {
+            return;
+        }
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        for event in events {
+            let option = event.option();
+            let payload = TelnetDebugPayload {
+                direction,
+                command: ansi::command_name(event.command()),
+                option,
+                option_name: option.and_then(ansi::option_name),
+                bytes: hex(&event.bytes),
+                response: event.response.as_deref().map(hex),
+                at: now_ms(),
+            };
+            if let Some(file) = log.as_mut().filter(|file| file.size < MAX_LOG_BYTES) {
+                let option_label = match (payload.option_name, option) {
+                    (Some(name), _) => format!(" {name}"),
+                    (None, Some(option)) => format!(" {option}"),
+                    (None, None) => String::new(),
+                };
+                let response = payload
+                    .response
+                    .as_ref()
+                    .map(|r| format!(" -> [{r}]"))
+                    .unwrap_or_default();
+                let line = format!(
+                    "{} {:<3} {}{option_label} [{}]{response}\n",
+                    chrono::Local::now().format("%H:%M:%S%.3f"),
+                    payload.direction,
+                    payload.command,
+                    payload.bytes,
+                );
+                if file.file.write_all(line.as_bytes()).is_ok() {
+                    file.size += line.len() as u64;
+                }
+            }
+            let _ = events::emit(app, TELNET_DEBUG_EVENT, payload);
+        }
+        if let Some(file) = log.as_mut() {
+            let _ = file.file.flush();
+        }
+    }
+
+    /// Report the telnet commands in bytes written verbatim to the server.
+    pub fn record_write<R: Runtime>(&self, app: &AppHandle<R>, data: &[u8]) {
+        let mut events = ansi::process_output_traced(data).telnet;
+        // Our own writes aren't replied to
+        events.iter_mut().for_each(|event| event.response = None);
+        self.record(app, DIR_OUT, &events);
+    }
+}
+
+/// Emit `mud:telnet-debug` for each telnet command and log them to
+/// `telnet-debug.log` (started fresh each time it is enabled). Lasts until
+/// the app exits.
+#[tauri::command]
+pub fn set_telnet_debug(
+    enabled: bool,
+    state: tauri::State<'_, crate::ConnectionState>,
+    storage: tauri::State<'_, StorageState>,
+) {
+    state
+        .shared
+        .telnet_debug
+        .set_enabled(enabled, &storage.get_dir());
+}
@@ -0,0 +1,288 @@
+//! Backend timers: commands sent on a schedule (every N seconds, or once
+//! after N seconds) while connected. Definitions persist in `timers.json`;
+//! the countdown does not, so every timer starts fresh on launch.
+//!
+//! One scheduler loop drives all timers, so cancelling a timer is just
+//! removing it — there is no per-timer task to leak. Countdowns freeze while
+//! disconnected and carry on from where they were on reconnect.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::sync::Notify;
+use tokio::time::{Duration, Instant};
+
+use crate::connection::{self, ConnectionShared};
+use crate::events::{self, TimerFiredPayload, TIMER_FIRED_EVENT};
+
+pub const TIMERS_FILE: &str = "timers.json";
+const MIN_INTERVAL_SECS: u64 = 1;
+const MAX_TIMERS: usize = 100;
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimerDef {
+    pub interval_secs: u64,
+    pub command: String,
+    /// Fire every `interval_secs`; otherwise once, after which the timer is
+    /// removed.
+    #[serde(default = "default_true")]
+    pub repeat: bool,
+}
+
+/// Contents of timers.json. Timers are keyed by name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimersFile {
+    pub timers: BTreeMap<String, TimerDef>,
+}
+
+impl TimersFile {
+    pub fn load(dir: &Path) -> Self {
+        crate::storage::read_json_file(dir, TIMERS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        crate::storage::write_json_file(dir, TIMERS_FILE, self)
+    }
+}
+
+#[derive(Serialize)]
+pub struct TimerInfo {
+    pub name: String,
+    pub interval_secs: u64,
+    pub command: String,
+    pub repeat: bool,
+    /// Time until it next fires, counting only connected time.
+    pub remaining_ms: u64,
+    /// Counting down only resumes once connected.
+    pub paused: bool,
+}
+
+struct Running {
+    def: TimerDef,
+    /// When it fires; only set while connected.
+    due: Option<Instant>,
+    /// Time left when the countdown was frozen.
+    remaining: Duration,
+}
+
+impl Running {
+    fn new(def: TimerDef, connected: bool) -> Self {
+        let interval = Duration::from_secs(def.interval_secs);
+        Self {
+            def,
+            due: connected.then(|| Instant::now() + interval),
+            remaining: interval,
+        }
+    }
+
+    fn remaining(&self, now: Instant) -> Duration {
+        self.due
+            .map_or(self.remaining, |due| due.saturating_duration_since(now))
+    }
+}
+
+#[derive(Default)]
+struct TimersInner {
+    timers: BTreeMap<String, Running>,
+    connected: bool,
+    /// Safe mode: countdowns stay frozen as if never connected.
+    suspended: bool,
+}
+
+#[derive(Default)]
+pub struct TimerScheduler {
+    inner: Mutex<TimersInner>,
+    /// Wakes the scheduler loop when timers or the connection change.
+    wake: Notify,
+}
+
+fn validate(name: &str, def: &TimerDef) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Timer name is empty".to_string());
+    }
+    if def.command.trim().is_empty() {
+        return Err(format!("Timer {name} has no command"));
+    }
+    if def.interval_secs < MIN_INTERVAL_SECS {
+        return Err(format!(
+            "Timer interval must be at least {MIN_INTERVAL_SECS}s"
+        ));
+    }
+    Ok(())
+}
+
+impl TimerScheduler {
+    fn lock(&self) -> std::sync::MutexGuard<'_, TimersInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Load the timers saved in timers.json. Invalid entries are skipped.
+    pub fn load(&self, dir: &Path) {
+        let file = TimersFile::load(dir);
+        let mut inner = self.lock();
+        let connected = inner.connected;
+        inner.timers = file
+            .timers
+            .into_iter()
+            .filter(|(name, def)| {
+                validate(name, def)
+                    .map_err(|e| warn!("Skipping timer: {e}"))
+                    .is_ok()
+            })
+            .take(MAX_TIMERS)
+            .map(|(name, def)| (name, Running::new(def, connected)))
+            .collect();
+        info!("Loaded {} timer(s)", inner.timers.len());
+        drop(inner);
+        self.wake.notify_one();
+    }
+
+    /// Create a timer, or replace the one with this name. A replaced timer
+    /// restarts its countdown with the new interval.
+    pub fn create(&self, dir: &Path, name: String, def: TimerDef) -> Result<TimerInfo, String> {
+        validate(&name, &def)?;
+        let mut inner = self.lock();
+        if !inner.timers.contains_key(&name) && inner.timers.len() >= MAX_TIMERS {
+            return Err(format!("Too many timers (limit {MAX_TIMERS})"));
+        }
+        let mut file = TimersFile::load(dir);
+        file.timers.insert(name.clone(), def.clone());
+        file.save(dir)?;
+
+        let running = Running::new(def, inner.connected);
+        let info = timer_info(&name, &running, Instant::now());
+        inner.timers.insert(name, running);
+        drop(inner);
+        self.wake.notify_one();
+        Ok(info)
+    }
+
+    pub fn cancel(&self, dir: &Path, name: &str) -> Result<(), String> {
+        if self.lock().timers.remove(name).is_none() {
+            return Err(format!("No timer named {name}"));
+        }
+        self.wake.notify_one();
+        forget(dir, name)
+    }
+
+    pub fn list(&self) -> Vec<TimerInfo> {
+        let now = Instant::now();
+        self.lock()
+            .timers
+            .iter()
+            .map(|(name, running)| timer_info(name, running, now))
+            .collect()
+    }
+
+    /// Keep every timer paused until restart, for `--safe-mode`.
+    pub fn suspend(&self) {
+        self.set_connected(false);
+        self.lock().suspended = true;
+        info!("Backend timers suspended (safe mode)");
+    }
+
+    /// Freeze countdowns when the connection drops; resume them when it's up.
+    pub fn set_connected(&self, connected: bool) {
+        let mut inner = self.lock();
+        if inner.connected == connected || inner.suspended {
+            return;
+        }
+        inner.connected = connected;
+        let now = Instant::now();
+        for running in inner.timers.values_mut() {
+            if connected {
+                running.due = Some(now + running.remaining);
+            } else {
+                running.remaining = running.remaining(now);
+                running.due = None;
+            }
+        }
+        drop(inner);
+        self.wake.notify_one();
+    }
+
+    /// Timers due by `now`, rescheduling repeating ones and dropping one-shot
+    /// ones, plus when the next one is due.
+    fn take_due(&self, now: Instant) -> (Vec<(String, TimerDef)>, Option<Instant>) {
+        let mut inner = self.lock();
+        let mut fired = Vec::new();
+        for (name, running) in inner.timers.iter_mut() {
+            if running.due.is_some_and(|due| due <= now) {
+                let interval = Duration::from_secs(running.def.interval_secs);
+                running.due = Some(now + interval);
+                running.remaining = interval;
+                fired.push((name.clone(), running.def.clone()));
+            }
+        }
+        for (name, def) in &fired {
+            if !def.repeat {
+                inner.timers.remove(name);
+            }
+        }
+        let next = inner
+            .timers
+            .values()
+            .filter_map(|running| running.due)
+            .min();
+        (fired, next)
+    }
+}
+
+fn timer_info(name: &str, running: &Running, now: Instant) -> TimerInfo {
+    TimerInfo {
+        name: name.to_string(),
+        interval_secs: running.def.interval_secs,
+        command: running.def.command.clone(),
+        repeat: running.def.repeat,
+        remaining_ms: running.remaining(now).as_millis() as u64,
+        paused: running.due.is_none(),
+    }
+}
+
+/// Remove a timer from timers.json.
+fn forget(dir: &Path, name: &str) -> Result<(), String> {
+    let mut file = TimersFile::load(dir);
+    if file.timers.remove(name).is_some() {
+        file.save(dir)?;
+    }
+    Ok(())
+}
+
+/// The scheduler loop: sends each timer's command when it comes due. Runs
+/// for the life of the app.
+pub async fn run(app: AppHandle, shared: Arc<ConnectionShared>) {
+    loop {
+        let (fired, next) = shared.timers.take_due(Instant::now());
+        for (name, def) in fired {
+            let sent = connection::send_backend_command(&shared, &def.command, "Timer");
+            let _ = events::emit(
+                &app,
+                TIMER_FIRED_EVENT,
+                TimerFiredPayload {
+                    name: name.clone(),
+                    command: def.command,
+                    sent,
+                },
+            );
+            if !def.repeat {
+                let dir = app.state::<crate::storage::StorageState>().get_dir();
+                if let Err(e) = forget(&dir, &name) {
+                    warn!("Failed to remove one-shot timer {name}: {e}");
+                }
+            }
+        }
+        tokio::select! {
+            _ = connection::sleep_until_opt(next) => {}
+            _ = shared.timers.wake.notified() => {}
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! The system tray icon. It shows the connection state: the app icon,
+//! greyed out while disconnected, and a tooltip with the latest status
+//! message. Left-click shows or hides the window; the menu has Connect,
+//! Disconnect, Show/Hide and Quit. With `close_to_tray`, closing the main
+//! window hides it instead of quitting.
+
+use std::path::Path;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tauri::image::Image;
+use tauri::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager, Window, WindowEvent};
+
+use crate::storage::{self, StorageState};
+
+pub const TRAY_FILE: &str = "tray.json";
+const TRAY_ID: &str = "main";
+const MAIN_WINDOW: &str = "main";
+const APP_NAME: &str = "DartForge";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TraySettings {
+    /// Closing the main window hides it to the tray instead of quitting.
+    pub close_to_tray: bool,
+}
+
+impl TraySettings {
+    pub fn load(dir: &Path) -> Self {
+        storage::read_json_file(dir, TRAY_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        storage::write_json_file(dir, TRAY_FILE, self)
+    }
+}
+
+/// The app icon, or a faded grey copy of it while disconnected.
+fn icon(app: &AppHandle, connected: bool) -> Option<Image<'static>> {
+    let icon = app.default_window_icon()?;
+    if connected {
+        return Some(icon.clone().to_owned());
+    }
+    let mut rgba = icon.rgba().to_vec();
+    for pixel in rgba.chunks_exact_mut(4) {
+        let grey = ((u32::from(pixel[0]) * 30
+            + u32::from(pixel[1]) * 59
+            + u32::from(pixel[2]) * 11)
+            / 100) as u8;
+        pixel[..3].fill(grey);
+        pixel[3] = (u32::from(pixel[3]) * 3 / 5) as u8;
+    }
+    Some(Image::new_owned(rgba, icon.width(), icon.height()))
+}
+
+/// Bring the main window forward, even if hidden or minimized.
+pub fn show_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window(MAIN_WINDOW) {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+fn toggle_window(app: &AppHandle) {
+    let Some(window) = app.get_webview_window(MAIN_WINDOW) else {
+        return;
+    };
+    if window.is_visible().unwrap_or(false) {
+        let _ = window.hide();
+    } else {
+        show_window(app);
+    }
+}
+
+fn on_menu_event(app: &AppHandle, event: MenuEvent) {
+    match event.id().as_ref() {
+        "connect" => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                let session = app.state::<crate::ConnectionState>().default_session();
+                let companion = app.state::<crate::CompanionState>();
+                let storage = app.state::<StorageState>();
+                crate::spawn_connection(&app, &session, &companion, &storage, false).await;
+            });
+        }
+        "disconnect" => {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                let session = app.state::<crate::ConnectionState>().default_session();
+                crate::disconnect_now(&app, &session, &app.state()).await;
+            });
+        }
+        "toggle" => toggle_window(app),
+        "quit" => app.exit(0),
+        _ => {}
+    }
+}
+
+/// Create the tray icon, showing the app as disconnected.
+pub fn create(app: &AppHandle) -> tauri::Result<()> {
+    let menu = Menu::with_items(
+        app,
+        &[
+            &MenuItem::with_id(app, "connect", "Connect", true, None::<&str>)?,
+            &MenuItem::with_id(app, "disconnect", "Disconnect", true, None::<&str>)?,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, "toggle", "Show/Hide Window", true, None::<&str>)?,
+            &MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?,
+        ],
+    )?;
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(format!("{APP_NAME} — Disconnected"))
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(on_menu_event)
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                toggle_window(tray.app_handle());
+            }
+        });
+    if let Some(icon) = icon(app, false) {
+        builder = builder.icon(icon);
+    }
+    builder.build(app)?;
+    Ok(())
+}
+
+/// Show the current connection status in the tray.
+pub fn status_changed(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let (connected, message) = {
+        let status = app.state::<crate::ConnectionState>();
+        let status = status
+            .shared
+            .status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        (status.connected, status.message.clone())
+    };
+    if let Err(e) = tray.set_icon(icon(app, connected)) {
+        warn!("Failed to update tray icon: {e}");
+    }
+    let _ = tray.set_tooltip(Some(format!("{APP_NAME} — {message}")));
+}
+
+/// Hide the main window instead of closing it, with `close_to_tray` on.
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    let WindowEvent::CloseRequested { api, .. } = event else {
+        return;
+    };
+    let Some(storage) = window.app_handle().try_state::<StorageState>() else {
+        return;
+    };
+    if window.label() == MAIN_WINDOW && TraySettings::load(&storage.get_dir()).close_to_tray {
+        api.prevent_close();
+        let _ = window.hide();
+    }
+}
+
+/// Hide to the tray instead of quitting when the main window is closed.
+/// Persisted per data directory.
+#[tauri::command]
+pub fn set_close_to_tray(
+    enabled: bool,
+    state: tauri::State<'_, StorageState>,
+) -> Result<(), String> {
+    TraySettings {
+        close_to_tray: enabled,
+    }
+    .save(&state.get_dir())
+}
@@ -0,0 +1,462 @@
+//! Backend trigger engine. Runs in the connection read loop, so triggers keep
+//! firing when the webview is busy and can gag a line before it is displayed
+//! or kept in scrollback.
+//!
+//! Rules come from the trigger panel's `triggers.json` (the `global` section
+//! plus the active character's). Only rules with `"engine": "backend"` run
+//! here; on desktop the webview engine skips those, so nothing fires twice.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use log::{debug, info, warn};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::aliases;
+use crate::ansi;
+use crate::events::TriggerFiredPayload;
+use crate::lines;
+use crate::notifications::TriggerNotification;
+
+pub const TRIGGERS_FILE: &str = "triggers.json";
+const GLOBAL_SECTION: &str = "global";
+const BACKEND_ENGINE: &str = "backend";
+/// Hard limit on evaluating all rules against one line; the remaining rules
+/// are skipped for that line so a pathological pattern can't stall output.
+const LINE_BUDGET: Duration = Duration::from_millis(5);
+/// Compiled-size cap per pattern.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    #[default]
+    Substring,
+    Exact,
+    Regex,
+}
+
+/// A rule as stored by the trigger panel (camelCase, like the rest of
+/// triggers.json). Only the fields the backend engine uses are read.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TriggerRule {
+    pub id: String,
+    pub name: Option<String>,
+    pub pattern: String,
+    pub match_mode: MatchMode,
+    /// Commands to send, separated by the command separator (`;;` unless
+    /// configured otherwise) or newlines.
+    pub body: String,
+    pub body_mode: Option<String>,
+    pub enabled: bool,
+    pub cooldown_ms: u64,
+    pub gag: bool,
+    pub sound_name: Option<String>,
+    /// Legacy flag for the default chime.
+    pub sound_alert: bool,
+    /// Play the sound from the backend rather than the webview.
+    pub native_sound: bool,
+    /// Send a desktop notification when this fires while the window is
+    /// unfocused, titled `notify_title`.
+    pub notify_on_match: bool,
+    pub notify_title: Option<String>,
+    pub multi_line: bool,
+    pub engine: Option<String>,
+    /// Defaults to case-insensitive for substring/regex and case-sensitive
+    /// for exact, as in the webview engine.
+    pub ignore_case: Option<bool>,
+    /// Fire at most once per connection.
+    pub once: bool,
+}
+
+impl TriggerRule {
+    fn sound(&self) -> Option<String> {
+        self.sound_name
+            .clone()
+            .or_else(|| self.sound_alert.then(|| "chime1".to_string()))
+    }
+}
+
+/// Compile a pattern the way a rule with this mode matches it.
+pub fn compile(pattern: &str, mode: MatchMode, ignore_case: Option<bool>) -> Result<Regex, String> {
+    let source = match mode {
+        MatchMode::Substring => regex::escape(pattern),
+        MatchMode::Exact => format!("^{}$", regex::escape(pattern)),
+        MatchMode::Regex => pattern.to_string(),
+    };
+    RegexBuilder::new(&source)
+        .case_insensitive(ignore_case.unwrap_or(mode != MatchMode::Exact))
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {e}"))
+}
+
+/// Captures as the webview engine reports them: `$0` then groups, with
+/// unmatched groups as empty strings.
+fn captures(regex: &Regex, text: &str) -> Option<Vec<String>> {
+    let caps = regex.captures(text)?;
+    Some(
+        caps.iter()
+            .map(|m| m.map_or(String::new(), |m| m.as_str().to_string()))
+            .collect(),
+    )
+}
+
+struct CompiledRule {
+    rule: TriggerRule,
+    regex: Regex,
+    last_fired: Option<Instant>,
+    spent: bool,
+}
+
+#[derive(Default)]
+struct EngineInner {
+    rules: Vec<CompiledRule>,
+    character: Option<String>,
+    /// Fire counts by rule id; kept across reloads.
+    fired: HashMap<String, u64>,
+    /// `set_trigger_enabled` overrides by rule id, until restart.
+    overrides: HashMap<String, bool>,
+}
+
+/// What the enabled rules decided about one line.
+#[derive(Default)]
+pub struct LineOutcome {
+    pub gag: bool,
+    pub commands: Vec<String>,
+    pub fired: Vec<TriggerFiredPayload>,
+    pub notifications: Vec<TriggerNotification>,
+}
+
+#[derive(Serialize)]
+pub struct TriggerStats {
+    pub id: String,
+    pub name: Option<String>,
+    pub enabled: bool,
+    pub fired: u64,
+}
+
+#[derive(Serialize)]
+pub struct TriggerTestResult {
+    pub matched: bool,
+    pub captures: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct TriggerEngine {
+    inner: Mutex<EngineInner>,
+    /// Safe mode: rules load but never fire.
+    suspended: AtomicBool,
+}
+
+impl TriggerEngine {
+    fn lock(&self) -> std::sync::MutexGuard<'_, EngineInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Reload backend rules from triggers.json for `character` (plus the
+    /// global section). Rules with invalid patterns are skipped. Returns how
+    /// many rules were loaded.
+    pub fn reload(&self, dir: &Path, character: Option<String>) -> Result<usize, String> {
+        let sections: BTreeMap<String, BTreeMap<String, serde_json::Value>> =
+            match std::fs::read_to_string(dir.join(TRIGGERS_FILE)) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .map_err(|e| format!("Failed to parse {TRIGGERS_FILE}: {e}"))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(e) => return Err(format!("Failed to read {TRIGGERS_FILE}: {e}")),
+            };
+        let character = character
+            .map(|c| c.trim().to_lowercase())
+            .filter(|c| !c.is_empty());
+
+        // Character rules first, as in the webview engine
+        let mut keys: Vec<&str> = character.as_deref().into_iter().collect();
+        keys.push(GLOBAL_SECTION);
+        let mut rules = Vec::new();
+        for key in keys {
+            let Some(section) = sections.get(key) else {
+                continue;
+            };
+            for value in section.values() {
+                let Ok(rule) = serde_json::from_value::<TriggerRule>(value.clone()) else {
+                    continue;
+                };
+                if rule.engine.as_deref() != Some(BACKEND_ENGINE) {
+                    continue;
+                }
+                if rule.multi_line || rule.body_mode.as_deref() == Some("script") {
+                    warn!("Trigger {} uses multi-line or script mode, which the backend engine does not support", rule.id);
+                    continue;
+                }
+                match compile(&rule.pattern, rule.match_mode, rule.ignore_case) {
+                    Ok(regex) => rules.push(CompiledRule {
+                        rule,
+                        regex,
+                        last_fired: None,
+                        spent: false,
+                    }),
+                    Err(e) => warn!("Skipping trigger {}: {e}", rule.id),
+                }
+            }
+        }
+
+        let count = rules.len();
+        let mut inner = self.lock();
+        // Keep cooldown/once-only state for rules that survived the reload
+        for rule in &mut rules {
+            if let Some(old) = inner.rules.iter().find(|old| old.rule.id == rule.rule.id) {
+                rule.last_fired = old.last_fired;
+                rule.spent = old.spent;
+            }
+        }
+        inner.rules = rules;
+        inner.character = character;
+        info!("Loaded {count} backend trigger(s)");
+        Ok(count)
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.suspended.load(Ordering::Relaxed) && !self.lock().rules.is_empty()
+    }
+
+    /// Keep every rule from firing until restart, for `--safe-mode`.
+    pub fn suspend(&self) {
+        self.suspended.store(true, Ordering::Relaxed);
+        info!("Backend triggers suspended (safe mode)");
+    }
+
+    /// Forget cooldowns and once-only state at the start of a connection.
+    pub fn reset_session(&self) {
+        for rule in &mut self.lock().rules {
+            rule.last_fired = None;
+            rule.spent = false;
+        }
+    }
+
+    pub fn set_enabled(&self, id: &str, enabled: bool) -> Result<(), String> {
+        let mut inner = self.lock();
+        if !inner.rules.iter().any(|r| r.rule.id == id) {
+            return Err(format!("No backend trigger with id {id}"));
+        }
+        inner.overrides.insert(id.to_string(), enabled);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Vec<TriggerStats> {
+        let inner = self.lock();
+        inner
+            .rules
+            .iter()
+            .map(|r| TriggerStats {
+                id: r.rule.id.clone(),
+                name: r.rule.name.clone(),
+                enabled: inner
+                    .overrides
+                    .get(&r.rule.id)
+                    .copied()
+                    .unwrap_or(r.rule.enabled),
+                fired: inner.fired.get(&r.rule.id).copied().unwrap_or(0),
+            })
+            .collect()
+    }
+
+    /// Run the enabled rules against one line of display text, splitting
+    /// bodies on `separator`.
+    pub fn evaluate(&self, line: &str, separator: &str) -> LineOutcome {
+        let stripped = lines::match_text(line);
+        let text = stripped.as_str();
+
+        let mut outcome = LineOutcome::default();
+        if self.suspended.load(Ordering::Relaxed) {
+            return outcome;
+        }
+        let started = Instant::now();
+        let mut inner = self.lock();
+        let EngineInner {
+            rules,
+            character,
+            fired,
+            overrides,
+        } = &mut *inner;
+        for compiled in rules.iter_mut() {
+            if started.elapsed() > LINE_BUDGET {
+                warn!(
+                    "Trigger evaluation exceeded {}ms; skipping the remaining rules for this line",
+                    LINE_BUDGET.as_millis()
+                );
+                break;
+            }
+            let rule = &compiled.rule;
+            if !overrides.get(&rule.id).copied().unwrap_or(rule.enabled) || compiled.spent {
+                continue;
+            }
+            let cooldown = Duration::from_millis(rule.cooldown_ms);
+            if compiled
+                .last_fired
+                .is_some_and(|at| at.elapsed() < cooldown)
+            {
+                continue;
+            }
+            let Some(caps) = captures(&compiled.regex, text) else {
+                continue;
+            };
+
+            compiled.last_fired = Some(Instant::now());
+            compiled.spent = rule.once;
+            *fired.entry(rule.id.clone()).or_default() += 1;
+            outcome.gag |= rule.gag;
+            outcome.commands.extend(expand_body(
+                &rule.body,
+                text,
+                &caps,
+                character.as_deref(),
+                separator,
+            ));
+            if rule.notify_on_match {
+                outcome.notifications.push(TriggerNotification {
+                    rule_id: rule.id.clone(),
+                    title: rule.notify_title.clone().or_else(|| rule.name.clone()),
+                    line: text.to_string(),
+                });
+            }
+            outcome.fired.push(TriggerFiredPayload {
+                id: rule.id.clone(),
+                name: rule.name.clone(),
+                line: text.to_string(),
+                captures: caps,
+                sound: rule.sound(),
+                played_natively: rule.native_sound,
+                gagged: rule.gag,
+            });
+        }
+        outcome
+    }
+}
+
+/// Check a pattern against sample text without loading it as a rule.
+pub fn test(
+    pattern: &str,
+    sample: &str,
+    mode: MatchMode,
+    ignore_case: Option<bool>,
+) -> Result<TriggerTestResult, String> {
+    let regex = compile(pattern, mode, ignore_case)?;
+    let caps = captures(&regex, &ansi::strip_ansi(sample));
+    Ok(TriggerTestResult {
+        matched: caps.is_some(),
+        captures: caps.unwrap_or_default(),
+    })
+}
+
+/// Split a rule body into commands, then substitute `$line`, `$Me`/`$me`,
+/// `$0` and `$1`–`$9` into each. Splitting first means text from the MUD can
+/// never add commands; the separator is escaped where it appears in that
+/// text, so alias expansion doesn't split it either (see
+/// `AliasTable::expand_command`). Client directives (`/delay`, `/echo`, ...)
+/// only exist in the webview and are skipped.
+fn expand_body(
+    body: &str,
+    line: &str,
+    caps: &[String],
+    character: Option<&str>,
+    separator: &str,
+) -> Vec<String> {
+    if body.trim().is_empty() {
+        return Vec::new();
+    }
+    let character = character.unwrap_or("");
+    let capitalized = aliases::capitalize(character);
+    let escaped = format!("\\{separator}");
+    let escape = |text: &str| text.replace(separator, &escaped);
+    let line = escape(line);
+    let caps: Vec<String> = caps.iter().map(|cap| escape(cap)).collect();
+    let mut tokens = vec![
+        ("line", line.as_str()),
+        ("Me", capitalized.as_str()),
+        ("me", character),
+    ];
+    for (i, digit) in aliases::DIGIT_TOKENS.iter().enumerate() {
+        tokens.push((*digit, caps.get(i).map_or("", String::as_str)));
+    }
+
+    aliases::split_commands(body, separator)
+        .into_iter()
+        .map(|cmd| {
+            let cmd = cmd.trim();
+            if cmd.starts_with('/') {
+                debug!("Skipping client directive in backend trigger: {cmd}");
+                return String::new();
+            }
+            aliases::expand_tokens(cmd, &tokens).trim().to_string()
+        })
+        .filter(|cmd| !cmd.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(all: &[&str]) -> Vec<String> {
+        all.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn body_splits_on_separator_and_newlines() {
+        let commands = expand_body("say hi;;bow\nwave", "", &[], None, ";;");
+        assert_eq!(commands, ["say hi", "bow", "wave"]);
+    }
+
+    #[test]
+    fn capture_with_separator_stays_one_command() {
+        let line = "Bob says: hi;;give all to bob";
+        let commands = expand_body(
+            "say $1",
+            line,
+            &caps(&[line, "hi;;give all to bob"]),
+            None,
+            ";;",
+        );
+        assert_eq!(commands, ["say hi\\;;give all to bob"]);
+    }
+
+    #[test]
+    fn line_with_separator_stays_one_command() {
+        let commands = expand_body("emote hears $line", "a||b", &[], None, "||");
+        assert_eq!(commands, ["emote hears a\\||b"]);
+    }
+
+    #[test]
+    fn configured_separator_is_used() {
+        let commands = expand_body("n||e;;w", "", &[], None, "||");
+        assert_eq!(commands, ["n", "e;;w"]);
+    }
+
+    #[test]
+    fn character_and_directives() {
+        let commands = expand_body("/delay 1;;tell $me $Me", "", &[], Some("ahab"), ";;");
+        assert_eq!(commands, ["tell ahab Ahab"]);
+    }
+
+    #[test]
+    fn mud_text_is_not_expanded_again() {
+        let line = "Bob says: $1 $Me $me $line";
+        let commands = expand_body(
+            "say $1, $me heard $line",
+            line,
+            &caps(&[line, "$2 $Me"]),
+            Some("ahab"),
+            ";;",
+        );
+        assert_eq!(
+            commands,
+            ["say $2 $Me, ahab heard Bob says: $1 $Me $me $line"]
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! Vitals from the prompt. Each GA-terminated prompt is emitted as
+//! `mud:prompt` and matched against the parsers: regexes whose named groups
+//! become numeric fields (`hp`, `hp_max`, `fatigue`, ...). When the fields
+//! differ from the last prompt's, they are emitted as `mud:vitals`. Prompts
+//! that match nothing only get the `mud:prompt` event.
+//!
+//! DartMUD's stock prompt is a bare `>`, so the shipped parsers cover the
+//! usual custom formats, `hp: 45/60`, `<45hp 30fp>` and the like, for health,
+//! fatigue and spell points. `prompt-parsers.json` adds parsers or replaces a
+//! shipped one by using its id, and `use_defaults: false` drops the shipped
+//! ones altogether. Every matching parser contributes; where two set the same
+//! field, the first wins, trying the file's parsers in id order before the
+//! shipped ones.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{info, warn};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::events::{self, PromptPayload, VitalsPayload, PROMPT_EVENT, VITALS_EVENT};
+use crate::lines;
+
+pub const PROMPT_PARSERS_FILE: &str = "prompt-parsers.json";
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+/// Enabled parsers beyond this many are not loaded.
+const MAX_PARSERS: usize = 50;
+/// A prompt longer than this isn't parsed or reported.
+const MAX_PROMPT_LEN: usize = 1024;
+
+/// Shipped parsers as `(id, pattern)`: a label then the value (`hp: 45/60`,
+/// `HP 45`), or the value with the label right after it (`45/60hp`). A label
+/// after a space isn't taken, since in `hp: 45 fp: 30` it would pair the wrong
+/// number with `fp`.
+const DEFAULT_PARSERS: [(&str, &str); 6] = [
+    (
+        "hp",
+        r"\b(?:hp|health)\s*[:=]?\s*(?P<hp>-?\d+)(?:\s*/\s*(?P<hp_max>\d+))?",
+    ),
+    (
+        "hp_suffix",
+        r"(?P<hp>-?\d+)(?:/(?P<hp_max>\d+))?(?:hp|health)\b",
+    ),
+    (
+        "fatigue",
+        r"\b(?:fp|fat|fatigue)\s*[:=]?\s*(?P<fatigue>-?\d+)(?:\s*/\s*(?P<fatigue_max>\d+))?",
+    ),
+    (
+        "fatigue_suffix",
+        r"(?P<fatigue>-?\d+)(?:/(?P<fatigue_max>\d+))?(?:fp|fat|fatigue)\b",
+    ),
+    (
+        "sp",
+        r"\b(?:sp|mana)\s*[:=]?\s*(?P<sp>-?\d+)(?:\s*/\s*(?P<sp_max>\d+))?",
+    ),
+    (
+        "sp_suffix",
+        r"(?P<sp>-?\d+)(?:/(?P<sp_max>\d+))?(?:sp|mana)\b",
+    ),
+];
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptParser {
+    /// Regex matched against the ANSI-stripped prompt; each named group that
+    /// captures a number becomes a field.
+    pub pattern: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Case-insensitive unless set to false.
+    #[serde(default)]
+    pub ignore_case: Option<bool>,
+}
+
+/// Contents of prompt-parsers.json. Parsers are keyed by id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptParsersFile {
+    pub parsers: BTreeMap<String, PromptParser>,
+    /// Also use the shipped parsers not replaced by one in `parsers`.
+    pub use_defaults: bool,
+}
+
+impl Default for PromptParsersFile {
+    fn default() -> Self {
+        Self {
+            parsers: BTreeMap::new(),
+            use_defaults: true,
+        }
+    }
+}
+
+impl PromptParsersFile {
+    pub fn load(dir: &Path) -> Self {
+        crate::storage::read_json_file(dir, PROMPT_PARSERS_FILE).unwrap_or_default()
+    }
+}
+
+fn compile(pattern: &str, ignore_case: Option<bool>) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case.unwrap_or(true))
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {e}"))
+}
+
+pub type Vitals = BTreeMap<String, f64>;
+
+pub struct PromptParsers {
+    parsers: Mutex<Vec<Regex>>,
+}
+
+impl Default for PromptParsers {
+    fn default() -> Self {
+        let parsers = DEFAULT_PARSERS
+            .iter()
+            .filter_map(|(_, pattern)| compile(pattern, None).ok())
+            .collect();
+        Self {
+            parsers: Mutex::new(parsers),
+        }
+    }
+}
+
+impl PromptParsers {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Regex>> {
+        self.parsers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Re-read prompt-parsers.json. Parsers with invalid patterns are
+    /// skipped. Returns how many parsers are in use.
+    pub fn reload(&self, dir: &Path) -> usize {
+        let file = PromptParsersFile::load(dir);
+        let custom = file
+            .parsers
+            .iter()
+            .filter(|(_, parser)| parser.enabled)
+            .map(|(id, parser)| (id.as_str(), parser.pattern.as_str(), parser.ignore_case));
+        let defaults = DEFAULT_PARSERS
+            .iter()
+            .filter(|(id, _)| file.use_defaults && !file.parsers.contains_key(*id))
+            .map(|(id, pattern)| (*id, *pattern, None));
+        let mut parsers = Vec::new();
+        for (id, pattern, ignore_case) in custom.chain(defaults) {
+            if parsers.len() == MAX_PARSERS {
+                warn!("Only the first {MAX_PARSERS} prompt parsers are used; skipping {id} and later parsers");
+                break;
+            }
+            match compile(pattern, ignore_case) {
+                Ok(regex) => parsers.push(regex),
+                Err(e) => warn!("Skipping prompt parser {id}: {e}"),
+            }
+        }
+        let count = parsers.len();
+        *self.lock() = parsers;
+        info!("Loaded {count} prompt parser(s)");
+        count
+    }
+
+    /// The numeric fields every parser finds in `prompt`.
+    pub fn parse(&self, prompt: &str) -> Vitals {
+        let mut vitals = Vitals::new();
+        for regex in self.lock().iter() {
+            let Some(caps) = regex.captures(prompt) else {
+                continue;
+            };
+            for name in regex.capture_names().flatten() {
+                if let Some(value) = caps
+                    .name(name)
+                    .and_then(|m| m.as_str().trim().parse::<f64>().ok())
+                {
+                    vitals.entry(name.to_string()).or_insert(value);
+                }
+            }
+        }
+        vitals
+    }
+}
+
+/// Per-connection prompt tracking: the display text since the last newline,
+/// and the vitals last emitted.
+#[derive(Default)]
+pub struct PromptWatch {
+    line: String,
+    /// The line outgrew `MAX_PROMPT_LEN`; the rest of it is ignored.
+    overlong: bool,
+    last: Option<Vitals>,
+    /// Generation of the connection it watches, carried by its events.
+    generation: u64,
+}
+
+impl PromptWatch {
+    pub fn new(generation: u64) -> Self {
+        Self {
+            generation,
+            ..Default::default()
+        }
+    }
+
+    /// Follow display text; at a GA, the text since the last newline is the
+    /// prompt, which is returned once reported.
+    pub fn feed<R: Runtime>(
+        &mut self,
+        app: &AppHandle<R>,
+        parsers: &PromptParsers,
+        session: &str,
+        display: &str,
+        ga: bool,
+    ) -> Option<String> {
+        let prompt = self.prompt(display, ga)?;
+        let vitals = parsers.parse(&prompt);
+        let session = session.to_string();
+        if self.changed(&vitals) {
+            let payload = VitalsPayload {
+                session: session.clone(),
+                vitals,
+                prompt: prompt.clone(),
+                generation: self.generation,
+            };
+            let _ = events::emit(app, VITALS_EVENT, payload);
+        }
+        let payload = PromptPayload {
+            session,
+            prompt: prompt.clone(),
+            generation: self.generation,
+        };
+        let _ = events::emit(app, PROMPT_EVENT, payload);
+        Some(prompt)
+    }
+
+    /// The ANSI-stripped prompt `display` completes, if `ga` ends it and it
+    /// isn't blank or overlong.
+    fn prompt(&mut self, display: &str, ga: bool) -> Option<String> {
+        let tail = match display.rfind('\n') {
+            Some(pos) => {
+                self.line.clear();
+                self.overlong = false;
+                &display[pos + 1..]
+            }
+            None => display,
+        };
+        if !self.overlong {
+            self.line.push_str(tail);
+            if self.line.len() > MAX_PROMPT_LEN {
+                self.line.clear();
+                self.overlong = true;
+            }
+        }
+        if !ga {
+            return None;
+        }
+        let raw = std::mem::take(&mut self.line);
+        if std::mem::take(&mut self.overlong) {
+            return None;
+        }
+        let prompt = lines::match_text(&raw).trim().to_string();
+        (!prompt.is_empty()).then_some(prompt)
+    }
+
+    /// Whether `vitals` should be emitted: some were found and they differ
+    /// from the last ones emitted.
+    fn changed(&mut self, vitals: &Vitals) -> bool {
+        if vitals.is_empty() || self.last.as_ref() == Some(vitals) {
+            return false;
+        }
+        self.last = Some(vitals.clone());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vitals(pairs: &[(&str, f64)]) -> Vitals {
+        pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn shipped_parsers_read_common_prompts() {
+        let parsers = PromptParsers::default();
+        let cases: [(&str, &[(&str, f64)]); 7] = [
+            (
+                "hp: 45/60 fp: 30/40 >",
+                &[
+                    ("hp", 45.0),
+                    ("hp_max", 60.0),
+                    ("fatigue", 30.0),
+                    ("fatigue_max", 40.0),
+                ],
+            ),
+            ("<45hp 30fp>", &[("hp", 45.0), ("fatigue", 30.0)]),
+            (
+                "<45/60hp 30/40fp 12/20sp>",
+                &[
+                    ("hp", 45.0),
+                    ("hp_max", 60.0),
+                    ("fatigue", 30.0),
+                    ("fatigue_max", 40.0),
+                    ("sp", 12.0),
+                    ("sp_max", 20.0),
+                ],
+            ),
+            (
+                "HP 45 Fat 3 Mana 7>",
+                &[("hp", 45.0), ("fatigue", 3.0), ("sp", 7.0)],
+            ),
+            ("Health=-5 >", &[("hp", -5.0)]),
+            ("[hp:12 fatigue:0]", &[("hp", 12.0), ("fatigue", 0.0)]),
+            (">", &[]),
+        ];
+        for (prompt, expected) in cases {
+            assert_eq!(parsers.parse(prompt), vitals(expected), "{prompt}");
+        }
+    }
+
+    #[test]
+    fn custom_parsers_replace_and_add() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = r#"{"parsers": {
+            "hp": {"pattern": "H(?P<hp>\\d+)", "ignore_case": false},
+            "gold": {"pattern": "(?P<gold>\\d+)g\\b"},
+            "broken": {"pattern": "(?P<x>"},
+            "off": {"pattern": "(?P<off>\\d+)", "enabled": false}
+        }}"#;
+        write_parsers(dir.path(), file);
+        let parsers = PromptParsers::default();
+        // hp, gold and the five shipped parsers not replaced
+        assert_eq!(parsers.reload(dir.path()), 7);
+        assert_eq!(
+            parsers.parse("H12 h99 30fp 7g"),
+            vitals(&[("hp", 12.0), ("fatigue", 30.0), ("gold", 7.0)])
+        );
+
+        write_parsers(
+            dir.path(),
+            r#"{"use_defaults": false, "parsers": {"gold": {"pattern": "(?P<gold>\\d+)g"}}}"#,
+        );
+        assert_eq!(parsers.reload(dir.path()), 1);
+        assert_eq!(parsers.parse("45hp 7g"), vitals(&[("gold", 7.0)]));
+    }
+
+    fn write_parsers(dir: &Path, contents: &str) {
+        std::fs::write(dir.join(PROMPT_PARSERS_FILE), contents).unwrap();
+    }
+
+    #[test]
+    fn prompt_is_the_text_since_the_last_newline() {
+        let mut watch = PromptWatch::new(1);
+        assert_eq!(watch.prompt("You see a rat.\r\nhp: 4", false), None);
+        assert_eq!(watch.prompt("5/60 >", true).as_deref(), Some("hp: 45/60 >"));
+        assert_eq!(
+            watch.prompt("\x1b[1;31mhp: 9\x1b[0m >", true).as_deref(),
+            Some("hp: 9 >")
+        );
+        assert_eq!(watch.prompt("text\n   ", true), None);
+    }
+
+    #[test]
+    fn overlong_line_is_not_a_prompt() {
+        let mut watch = PromptWatch::new(1);
+        assert_eq!(watch.prompt(&"x".repeat(MAX_PROMPT_LEN + 1), false), None);
+        assert_eq!(watch.prompt("hp: 3 >", true), None);
+        // The next line starts afresh
+        assert_eq!(watch.prompt("hp: 3 >", true).as_deref(), Some("hp: 3 >"));
+    }
+
+    #[test]
+    fn vitals_are_reported_only_when_they_change() {
+        let mut watch = PromptWatch::new(1);
+        let first = vitals(&[("hp", 45.0)]);
+        assert!(watch.changed(&first));
+        assert!(!watch.changed(&first));
+        assert!(!watch.changed(&Vitals::new()));
+        assert!(watch.changed(&vitals(&[("hp", 44.0)])));
+        assert!(watch.changed(&first));
+    }
+}
@@ -0,0 +1,184 @@
+//! Keeps the main window's title in step with the session: who is connected
+//! where, the connection state, and a "•" while output has arrived unseen in
+//! an unfocused window. Changes are coalesced and the title is only set when
+//! it actually changes, so output bursts don't spam the OS.
+//!
+//! The format is a template with `{unread}`, `{app}`, `{version}`, `{who}`
+//! (`character @ host`, or just the host), `{character}`, `{host}` and
+//! `{status}` placeholders.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Window, WindowEvent};
+use tokio::sync::Notify;
+use tokio::time::Duration;
+
+use crate::storage::{self, StorageState};
+
+pub const WINDOW_TITLE_FILE: &str = "window-title.json";
+pub const DEFAULT_TEMPLATE: &str = "{unread}{app} — {who} ({status})";
+const MAIN_WINDOW: &str = "main";
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowTitleSettings {
+    /// `None` uses `DEFAULT_TEMPLATE`.
+    pub template: Option<String>,
+}
+
+impl WindowTitleSettings {
+    pub fn load(dir: &Path) -> Self {
+        storage::read_json_file(dir, WINDOW_TITLE_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        storage::write_json_file(dir, WINDOW_TITLE_FILE, self)
+    }
+}
+
+#[derive(Default)]
+struct TitleState {
+    /// Focused unless told otherwise, so output before the first focus
+    /// event doesn't mark the window unread.
+    unfocused: bool,
+    unread: bool,
+}
+
+#[derive(Default)]
+pub struct TitleManager {
+    state: Mutex<TitleState>,
+    changed: Notify,
+}
+
+impl TitleManager {
+    fn update(&self, f: impl FnOnce(&mut TitleState) -> bool) {
+        let changed = f(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()));
+        if changed {
+            self.changed.notify_one();
+        }
+    }
+
+    /// Re-render with the status in `ConnectionShared::status`.
+    pub fn status_changed(&self) {
+        self.changed.notify_one();
+    }
+
+    /// Output arrived; marks the title unread if the window isn't focused.
+    pub fn output(&self) {
+        self.update(|state| state.unfocused && !std::mem::replace(&mut state.unread, true));
+    }
+
+    fn focus_changed(&self, focused: bool) {
+        self.update(|state| {
+            state.unfocused = !focused;
+            focused && std::mem::take(&mut state.unread)
+        });
+    }
+
+    /// Re-render, e.g. after the template changed.
+    pub fn refresh(&self) {
+        self.changed.notify_one();
+    }
+}
+
+fn render(app: &AppHandle, template: &str, unread: bool) -> String {
+    let shared = &app.state::<crate::ConnectionState>().shared;
+    let status = shared
+        .status
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .label();
+    let host = shared
+        .target
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .host()
+        .to_string();
+    let character = shared
+        .active_profile
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|p| {
+            p.settings
+                .login
+                .as_ref()
+                .map_or(p.name.clone(), |login| login.username.clone())
+        })
+        .unwrap_or_default();
+    let who = if character.is_empty() {
+        host.clone()
+    } else {
+        format!("{character} @ {host}")
+    };
+    template
+        .replace("{unread}", if unread { "• " } else { "" })
+        .replace("{app}", "DartForge")
+        .replace("{version}", &app.package_info().version.to_string())
+        .replace("{who}", &who)
+        .replace("{character}", &character)
+        .replace("{host}", &host)
+        .replace("{status}", status)
+}
+
+/// Set the title whenever something in it changes, at most once per
+/// `DEBOUNCE`.
+pub async fn run(app: AppHandle) {
+    let manager = app.state::<TitleManager>();
+    let mut last = String::new();
+    manager.refresh();
+    loop {
+        manager.changed.notified().await;
+        tokio::time::sleep(DEBOUNCE).await;
+        let unread = manager
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .unread;
+        let template = WindowTitleSettings::load(&app.state::<StorageState>().get_dir()).template;
+        let title = render(
+            &app,
+            template.as_deref().unwrap_or(DEFAULT_TEMPLATE),
+            unread,
+        );
+        if title == last {
+            continue;
+        }
+        if let Some(window) = app.get_webview_window(MAIN_WINDOW) {
+            if let Err(e) = window.set_title(&title) {
+                warn!("Failed to set window title: {e}");
+            }
+        }
+        last = title;
+    }
+}
+
+/// Track focus, clearing the unread mark when the window comes forward.
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    if let WindowEvent::Focused(focused) = event {
+        if window.label() == MAIN_WINDOW {
+            window
+                .app_handle()
+                .state::<TitleManager>()
+                .focus_changed(*focused);
+        }
+    }
+}
+
+/// Set the window title format (see the module docs for placeholders);
+/// `None` or blank restores the default. Persisted per data directory.
+#[tauri::command]
+pub fn set_title_template(
+    template: Option<String>,
+    app: AppHandle,
+    state: tauri::State<'_, StorageState>,
+) -> Result<(), String> {
+    let template = template.filter(|t| !t.trim().is_empty());
+    WindowTitleSettings { template }.save(&state.get_dir())?;
+    app.state::<TitleManager>().refresh();
+    Ok(())
+}
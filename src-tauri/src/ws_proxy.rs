@@ -0,0 +1,425 @@
+//! Reaching the MUD through the web proxy's `/ws` endpoint instead of raw
+//! TCP, for networks that block port 2525 but allow HTTPS. This is the client
+//! side of the JSON protocol in `proxy-worker/src/protocol.ts`; the proxy does
+//! the telnet handling, so it sends display text and statuses rather than
+//! bytes.
+
+use std::fmt;
+
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::{self, http::Uri, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::events::StatusReason;
+
+/// The protocol version offered in `hello`; see `PROTOCOL_VERSION` in
+/// protocol.ts.
+const PROTOCOL_VERSION: u32 = 2;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage<'a> {
+    /// The proxy's shared token; must come first when it has one.
+    Auth {
+        data: &'a str,
+    },
+    /// No optional features are asked for, so output stays JSON.
+    Hello {
+        version: u32,
+        features: &'a [&'a str],
+    },
+    /// Have the proxy connect to the MUD (it must be in its `ALLOWED_TARGETS`).
+    Reconnect {
+        host: &'a str,
+        port: u16,
+    },
+    Command {
+        data: &'a str,
+    },
+    Disconnect,
+    Ping,
+    Pong,
+}
+
+/// A status from the proxy, about its own connection to the MUD.
+#[derive(Debug, Deserialize)]
+pub struct ProxyStatus {
+    pub connected: bool,
+    pub message: String,
+    pub reason: String,
+    pub detail: Option<String>,
+    pub attempt: Option<u32>,
+}
+
+impl ProxyStatus {
+    /// The matching desktop reason; the proxy's mirror them, apart from its
+    /// own refusals (`target_not_permitted`, `server_full`, `rate_limited`),
+    /// which are proxy errors.
+    pub fn status_reason(&self) -> StatusReason {
+        serde_json::from_value(serde_json::Value::String(self.reason.clone()))
+            .unwrap_or(StatusReason::ProxyError)
+    }
+
+    /// Whether the proxy itself turned the request down, rather than failing
+    /// to reach the MUD.
+    pub fn is_refusal(&self) -> bool {
+        matches!(
+            self.reason.as_str(),
+            "target_not_permitted" | "server_full" | "rate_limited"
+        )
+    }
+
+    /// The failure detail, saying which hop it was about.
+    pub fn hop_detail(&self) -> String {
+        let hop = if self.is_refusal() {
+            "WebSocket proxy"
+        } else {
+            "MUD connection"
+        };
+        format!("{hop}: {}", self.detail.as_deref().unwrap_or(&self.message))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Output {
+        data: String,
+        #[serde(default)]
+        ga: bool,
+    },
+    Status(ProxyStatus),
+    /// A command was refused (too long, queue full, ...).
+    Error {
+        code: String,
+        #[serde(default)]
+        detail: String,
+    },
+    Ping,
+    Pong,
+    /// `hello`, `stats`, `session`, `command_backlog`, and anything newer.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug)]
+pub enum WsError {
+    /// The WebSocket couldn't be opened, or failed once open.
+    Socket(tungstenite::Error),
+    /// The proxy closed the WebSocket, with its reason if it gave one.
+    Closed(Option<String>),
+}
+
+impl WsError {
+    /// The HTTP status the upgrade was refused with, if it was.
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            WsError::Socket(tungstenite::Error::Http(response)) => Some(response.status().as_u16()),
+            _ => None,
+        }
+    }
+
+    pub fn io(&self) -> Option<&std::io::Error> {
+        match self {
+            WsError::Socket(tungstenite::Error::Io(e)) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WsError::Socket(tungstenite::Error::Http(response)) => {
+                write!(f, "upgrade refused with HTTP {}", response.status())
+            }
+            WsError::Socket(e) => write!(f, "{e}"),
+            WsError::Closed(Some(reason)) => write!(f, "connection closed: {reason}"),
+            WsError::Closed(None) => write!(f, "connection closed"),
+        }
+    }
+}
+
+/// "host[:port]" of a proxy URL, for status messages.
+pub fn label(url: &str) -> String {
+    url.parse::<Uri>()
+        .ok()
+        .and_then(|uri| uri.authority().map(|a| a.to_string()))
+        .unwrap_or_else(|| url.to_string())
+}
+
+async fn send_to<S>(sink: &mut S, msg: &ClientMessage<'_>) -> Result<(), WsError>
+where
+    S: Sink<Message, Error = tungstenite::Error> + Unpin,
+{
+    let text = serde_json::to_string(msg).expect("client messages serialize");
+    sink.send(Message::Text(text))
+        .await
+        .map_err(WsError::Socket)
+}
+
+/// The next protocol message, skipping frames that aren't one.
+async fn recv_from<S>(stream: &mut S) -> Result<ServerMessage, WsError>
+where
+    S: Stream<Item = Result<Message, tungstenite::Error>> + Unpin,
+{
+    loop {
+        match stream.next().await {
+            None => return Err(WsError::Closed(None)),
+            Some(Err(e)) => return Err(WsError::Socket(e)),
+            Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                Ok(msg) => return Ok(msg),
+                Err(e) => debug!("Ignoring unreadable proxy message: {e}"),
+            },
+            Some(Ok(Message::Close(frame))) => {
+                let reason = frame
+                    .map(|f| f.reason.to_string())
+                    .filter(|r| !r.is_empty());
+                return Err(WsError::Closed(reason));
+            }
+            // Binary output is only sent to clients that ask for it
+            Some(Ok(_)) => {}
+        }
+    }
+}
+
+/// An open WebSocket to the proxy, authenticated and greeted.
+pub struct ProxySocket {
+    socket: Socket,
+}
+
+impl ProxySocket {
+    pub async fn open(url: &str, token: Option<&str>) -> Result<Self, WsError> {
+        let (socket, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(WsError::Socket)?;
+        let mut proxy = Self { socket };
+        if let Some(token) = token {
+            proxy.send(&ClientMessage::Auth { data: token }).await?;
+        }
+        proxy
+            .send(&ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+                features: &[],
+            })
+            .await?;
+        Ok(proxy)
+    }
+
+    /// The TCP stream under the WebSocket, for socket options and the peer address.
+    pub fn tcp(&self) -> Option<&TcpStream> {
+        match self.socket.get_ref() {
+            MaybeTlsStream::Plain(stream) => Some(stream),
+            MaybeTlsStream::Rustls(stream) => Some(stream.get_ref().0),
+            _ => None,
+        }
+    }
+
+    pub async fn send(&mut self, msg: &ClientMessage<'_>) -> Result<(), WsError> {
+        send_to(&mut self.socket, msg).await
+    }
+
+    pub async fn recv(&mut self) -> Result<ServerMessage, WsError> {
+        recv_from(&mut self.socket).await
+    }
+
+    pub fn into_split(self) -> (ProxyReader, ProxyWriter) {
+        let (sink, stream) = self.socket.split();
+        (ProxyReader { stream }, ProxyWriter { sink })
+    }
+}
+
+pub struct ProxyReader {
+    stream: futures_util::stream::SplitStream<Socket>,
+}
+
+impl ProxyReader {
+    /// Cancel-safe: nothing is lost if the future is dropped before it resolves.
+    pub async fn recv(&mut self) -> Result<ServerMessage, WsError> {
+        recv_from(&mut self.stream).await
+    }
+}
+
+pub struct ProxyWriter {
+    sink: futures_util::stream::SplitSink<Socket, Message>,
+}
+
+impl ProxyWriter {
+    pub async fn send(&mut self, msg: &ClientMessage<'_>) -> Result<(), WsError> {
+        send_to(&mut self.sink, msg).await
+    }
+
+    /// Have the proxy drop the MUD connection, then close the WebSocket.
+    pub async fn shutdown(&mut self) {
+        let _ = self.send(&ClientMessage::Disconnect).await;
+        let _ = self.sink.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tungstenite::protocol::frame::{coding::CloseCode, CloseFrame};
+
+    fn parse(text: &str) -> ServerMessage {
+        serde_json::from_str(text).unwrap()
+    }
+
+    fn status(reason: &str, detail: Option<&str>) -> ProxyStatus {
+        ProxyStatus {
+            connected: false,
+            message: "Disconnected".to_string(),
+            reason: reason.to_string(),
+            detail: detail.map(str::to_string),
+            attempt: None,
+        }
+    }
+
+    #[test]
+    fn client_messages_serialize_as_the_proxy_expects() {
+        let json = |msg: ClientMessage| serde_json::to_value(msg).unwrap();
+        assert_eq!(
+            json(ClientMessage::Auth { data: "secret" }),
+            serde_json::json!({"type": "auth", "data": "secret"})
+        );
+        assert_eq!(
+            json(ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+                features: &["session"],
+            }),
+            serde_json::json!({
+                "type": "hello",
+                "version": PROTOCOL_VERSION,
+                "features": ["session"],
+            })
+        );
+        assert_eq!(
+            json(ClientMessage::Reconnect {
+                host: "dartmud.com",
+                port: 2525,
+            }),
+            serde_json::json!({"type": "reconnect", "host": "dartmud.com", "port": 2525})
+        );
+        assert_eq!(
+            json(ClientMessage::Command { data: "look" }),
+            serde_json::json!({"type": "command", "data": "look"})
+        );
+        for (msg, kind) in [
+            (ClientMessage::Disconnect, "disconnect"),
+            (ClientMessage::Ping, "ping"),
+            (ClientMessage::Pong, "pong"),
+        ] {
+            assert_eq!(json(msg), serde_json::json!({ "type": kind }));
+        }
+    }
+
+    #[test]
+    fn proxy_messages_parse() {
+        assert!(matches!(
+            parse(r#"{"type":"output","data":"hi\r\n","ga":true}"#),
+            ServerMessage::Output { data, ga: true } if data == "hi\r\n"
+        ));
+        assert!(matches!(
+            parse(r#"{"type":"output","data":""}"#),
+            ServerMessage::Output { ga: false, .. }
+        ));
+        let ServerMessage::Status(status) = parse(
+            r#"{"type":"status","connected":false,"message":"Lost","reason":"stalled","detail":"No reply","attempt":2}"#,
+        ) else {
+            panic!("not a status");
+        };
+        assert!(!status.connected);
+        assert_eq!(status.detail.as_deref(), Some("No reply"));
+        assert_eq!(status.attempt, Some(2));
+        assert!(matches!(parse(r#"{"type":"ping"}"#), ServerMessage::Ping));
+        assert!(matches!(parse(r#"{"type":"pong"}"#), ServerMessage::Pong));
+        // Unknown fields are ignored, as are types this client has no use for
+        assert!(matches!(
+            parse(r#"{"type":"pong","ts_ms":1700000000000}"#),
+            ServerMessage::Pong
+        ));
+        for kind in ["hello", "stats", "command_backlog", "gmcp"] {
+            assert!(matches!(
+                parse(&format!(r#"{{"type":"{kind}"}}"#)),
+                ServerMessage::Other
+            ));
+        }
+        assert!(matches!(
+            parse(r#"{"type":"session","id":"abc","resumed":false}"#),
+            ServerMessage::Other
+        ));
+        assert!(matches!(
+            parse(r#"{"type":"error","code":"command_too_long"}"#),
+            ServerMessage::Error { code, detail } if code == "command_too_long" && detail.is_empty()
+        ));
+    }
+
+    #[test]
+    fn proxy_reasons_map_to_status_reasons() {
+        for (reason, expected) in [
+            ("server_closed", StatusReason::ServerClosed),
+            ("connect_refused", StatusReason::ConnectRefused),
+            ("dns_failure", StatusReason::DnsFailure),
+            ("timeout", StatusReason::Timeout),
+            ("stalled", StatusReason::Stalled),
+            ("target_not_permitted", StatusReason::ProxyError),
+            ("server_full", StatusReason::ProxyError),
+            ("something_new", StatusReason::ProxyError),
+        ] {
+            assert_eq!(status(reason, None).status_reason(), expected, "{reason}");
+        }
+    }
+
+    #[test]
+    fn details_name_the_failing_hop() {
+        assert_eq!(
+            status("rate_limited", Some("Too many connections")).hop_detail(),
+            "WebSocket proxy: Too many connections"
+        );
+        assert_eq!(
+            status("connect_refused", Some("ECONNREFUSED")).hop_detail(),
+            "MUD connection: ECONNREFUSED"
+        );
+        // Without a detail the message stands in
+        assert_eq!(
+            status("server_closed", None).hop_detail(),
+            "MUD connection: Disconnected"
+        );
+    }
+
+    #[tokio::test]
+    async fn unreadable_frames_are_skipped_and_close_reasons_kept() {
+        let mut stream = futures_util::stream::iter([
+            Ok(Message::Text("not json".into())),
+            Ok(Message::Binary(vec![1, 2, 3])),
+            Ok(Message::Text(r#"{"type":"ping"}"#.into())),
+            Ok(Message::Close(Some(CloseFrame {
+                code: CloseCode::Policy,
+                reason: "auth failed".into(),
+            }))),
+        ]);
+        assert!(matches!(
+            recv_from(&mut stream).await,
+            Ok(ServerMessage::Ping)
+        ));
+        assert!(matches!(
+            recv_from(&mut stream).await,
+            Err(WsError::Closed(Some(reason))) if reason == "auth failed"
+        ));
+        assert!(matches!(
+            recv_from(&mut stream).await,
+            Err(WsError::Closed(None))
+        ));
+    }
+
+    #[test]
+    fn labels_are_the_authority() {
+        assert_eq!(label("wss://proxy.example.com/ws"), "proxy.example.com");
+        assert_eq!(label("ws://127.0.0.1:8787/ws"), "127.0.0.1:8787");
+    }
+}
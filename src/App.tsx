@@ -138,7 +138,8 @@ import { LogViewerModal } from './components/LogViewerModal';
 import { CompanionQRDialog } from './components/CompanionQRDialog';
 import { QuickButtonBar } from './components/QuickButtonBar';
 import { MacroPanel } from './components/MacroPanel';
-import type { QuickButton, Macro } from './types';
+import type { QuickButton, Macro, InputEchoPayload, TriggerFiredPayload, GaggedLinesPayload, SecondInstancePayload, LaunchOptions } from './types';
+import { TRIGGER_FIRED_EVENT, GAGGED_LINES_EVENT, SECOND_INSTANCE_EVENT } from './lib/tauriEvents';
 import { hotkeyToString, hotkeyFromEvent, isNumpadKey } from './types';
 
 /* ── Lazy Tauri imports for companion integration ────────────── */
@@ -173,8 +174,10 @@ const MAX_RECENT_LINES = 500;
 function App() {
   const dataStore = useDataStore();
 
-  // Set window title regardless of setup state
+  // Set window title regardless of setup state. On desktop the backend
+  // keeps it up to date instead.
   useEffect(() => {
+    if (getPlatform() === 'tauri') return;
     getAppVersion()
       .then((v) => {
         setWindowTitle(`DartForge v${v}`);
@@ -206,6 +209,16 @@ function AppMain() {
   const [debugMode, setDebugMode] = useState(false);
   const [activePanel, setActivePanel] = useState<Panel | null>(null);
   const [showCompanionQR, setShowCompanionQR] = useState(false);
+  // Desktop `--safe-mode`: triggers, timers and auto-login stay off
+  const [safeMode, setSafeMode] = useState(false);
+  useEffect(() => {
+    tauriReady
+      .then(() => tauriInvoke?.('get_launch_options'))
+      .then((options) => {
+        if ((options as LaunchOptions | undefined)?.safe_mode) setSafeMode(true);
+      })
+      .catch(console.error);
+  }, []);
   const togglePanel = useCallback((panel: Panel) => setActivePanel((v) => (v === panel ? null : panel)), []);
   const closePanel = useCallback(() => setActivePanel(null), []);
   const writeToTerm = useCallback(
@@ -259,7 +272,7 @@ function AppMain() {
   const autoLoginRef = useRef<AutoLoginConfig | null>(null);
   useEffect(() => {
     const activeChar = autoLoginCharacters[autoLoginActiveSlot];
-    if (autoLoginEnabled && activeChar?.name && activeChar?.password) {
+    if (!safeMode && autoLoginEnabled && activeChar?.name && activeChar?.password) {
       autoLoginRef.current = {
         enabled: true,
         name: activeChar.name,
@@ -268,7 +281,7 @@ function AppMain() {
     } else {
       autoLoginRef.current = null;
     }
-  }, [autoLoginEnabled, autoLoginActiveSlot, autoLoginCharacters]);
+  }, [safeMode, autoLoginEnabled, autoLoginActiveSlot, autoLoginCharacters]);
 
   const dataStore = useDataStore();
   const settingsLoadedRef = useRef(false);
@@ -659,7 +672,7 @@ function AppMain() {
           if (transformed) replacement = transformed;
         }
 
-        const matches = matchTriggers(stripped, raw, mergedTriggersRef.current);
+        const matches = matchTriggers(stripped, raw, frontendTriggersRef.current);
         if (matches.length === 0) {
           return replacement ? { gag: false, highlight: null, replacement } : undefined;
         }
@@ -874,7 +887,16 @@ function AppMain() {
   // Alias system
   const aliasState = useAliases(dataStore, activeCharacter);
   const { mergedAliases, enableSpeedwalk } = aliasState;
-  const mergedAliasesRef = useLatestRef(mergedAliases);
+  // On desktop, aliases marked for the backend are sent unexpanded and
+  // expanded in Rust instead
+  const frontendAliases = useMemo(
+    () =>
+      getPlatform() === 'tauri'
+        ? mergedAliases.filter((a) => a.engine !== 'backend')
+        : mergedAliases,
+    [mergedAliases]
+  );
+  const mergedAliasesRef = useLatestRef(frontendAliases);
   const enableSpeedwalkRef = useLatestRef(enableSpeedwalk);
   // Fast flag: skip matchAlias pre-check when no script aliases exist (common case)
   const hasScriptAliases = useMemo(
@@ -885,6 +907,17 @@ function AppMain() {
   const activeCharacterRef = useLatestRef(activeCharacter);
   const commandSeparatorRef = useLatestRef(appSettings.commandSeparator);
 
+  // Tell the backend alias engine whose aliases apply; it re-reads
+  // aliases.json by itself whenever the file is saved
+  useEffect(() => {
+    tauriReady.then(() =>
+      tauriInvoke?.('reload_aliases', {
+        character: activeCharacter,
+        separator: appSettings.commandSeparator,
+      }).catch(console.error)
+    );
+  }, [activeCharacter, appSettings.commandSeparator]);
+
   // Variable system
   const variableState = useVariables(dataStore, activeCharacter);
   const { mergedVariables, setVariable: setVar, deleteVariableByName } = variableState;
@@ -906,7 +939,17 @@ function AppMain() {
   // Trigger system
   const triggerState = useTriggers(dataStore, activeCharacter);
   const { mergedTriggers } = triggerState;
-  const mergedTriggersRef = useLatestRef(mergedTriggers);
+  // On desktop, triggers marked for the backend engine run in Rust instead
+  const frontendTriggers = useMemo(
+    () =>
+      safeMode
+        ? []
+        : getPlatform() === 'tauri'
+          ? mergedTriggers.filter((t) => t.engine !== 'backend')
+          : mergedTriggers,
+    [safeMode, mergedTriggers]
+  );
+  const frontendTriggersRef = useLatestRef(frontendTriggers);
   const triggerRunnerRef = useRef<CommandRunner>({
     send: async () => {},
     echo: () => {},
@@ -943,6 +986,7 @@ function AppMain() {
   // Timer system
   const timerState = useTimers(dataStore, activeCharacter);
   const { mergedTimers } = timerState;
+  const runnableTimers = useMemo(() => (safeMode ? [] : mergedTimers), [safeMode, mergedTimers]);
   const timerStateRef = useLatestRef(timerState);
   const triggerStateRef = useLatestRef(triggerState);
   const aliasStateRef = useLatestRef(aliasState);
@@ -1041,6 +1085,8 @@ function AppMain() {
   // Session logging ref (populated after useMudConnection provides passwordMode)
   const logOutputRef = useRef<((data: string) => void) | null>(null);
   const logCommandRef = useRef<((cmd: string) => void) | null>(null);
+  const logEchoRef = useRef<((payload: InputEchoPayload) => void) | null>(null);
+  const logGaggedRef = useRef<((lines: string[]) => void) | null>(null);
 
   // Process output chunks through the skill detection pipeline + buffer for tab completion
   const onOutputChunk = useCallback(
@@ -1094,6 +1140,10 @@ function AppMain() {
     tauriInvoke?.('broadcast_companion_output', { data });
   }, []);
 
+  const onInputEcho = useCallback((payload: InputEchoPayload) => {
+    logEchoRef.current?.(payload);
+  }, []);
+
   const { connected, passwordMode, skipHistory, sendCommand, reconnect, disconnect } =
     useMudConnection(
       terminalRef,
@@ -1105,16 +1155,24 @@ function AppMain() {
       onLogin,
       autoLoginRef,
       onFilteredOutput,
+      onInputEcho,
     );
 
+  // Connection listeners are up; lets a `--profile` launch connect
+  useEffect(() => {
+    tauriReady.then(() => tauriInvoke?.('frontend_ready')).catch(console.error);
+  }, []);
+
   // Session logger
-  const { logOutput, logCommand } = useSessionLogger(
+  const { logOutput, logCommand, logEcho, logGagged } = useSessionLogger(
     appSettings.sessionLoggingEnabled,
     passwordMode,
     appSettings.timestampFormat
   );
   logOutputRef.current = logOutput;
   logCommandRef.current = logCommand;
+  logEchoRef.current = logEcho;
+  logGaggedRef.current = logGagged;
 
   // Wrap sendCommand with action blocker — all senders (timers, triggers,
   // skill tracker, allocations, user commands) go through this ref.
@@ -1652,6 +1710,45 @@ function AppMain() {
     return () => { unlisten?.(); };
   }, [handleSendRef]);
 
+  // A second launch forwarded `--send` commands — keep them in client history
+  useEffect(() => {
+    if (!tauriListen) return;
+    let unlisten: (() => void) | null = null;
+    tauriListen(SECOND_INSTANCE_EVENT, (e: { payload: unknown }) => {
+      const { sent, errors } = e.payload as SecondInstancePayload;
+      for (const error of errors) console.warn(`Launch arguments: ${error}`);
+      const cmds = sent.map((s) => s.command.trim()).filter(Boolean).reverse();
+      if (cmds.length > 0) {
+        const current = commandHistoryRef.current;
+        handleHistoryChangeRef.current(
+          [...cmds, ...current.filter((h) => !cmds.includes(h))].slice(0, 500)
+        );
+      }
+    }).then((fn) => { unlisten = fn; });
+    return () => { unlisten?.(); };
+  }, [commandHistoryRef, handleHistoryChangeRef]);
+
+  // Backend trigger engine fired — play its sound like a frontend trigger would
+  useEffect(() => {
+    if (!tauriListen) return;
+    let unlisten: (() => void) | null = null;
+    tauriListen(TRIGGER_FIRED_EVENT, (e: { payload: unknown }) => {
+      const { sound, played_natively } = e.payload as TriggerFiredPayload;
+      if (sound && !played_natively) soundLibraryRef.current.play(sound);
+    }).then((fn) => { unlisten = fn; });
+    return () => { unlisten?.(); };
+  }, [soundLibraryRef]);
+
+  // Lines gagged by the backend never reach the terminal but still get logged
+  useEffect(() => {
+    if (!tauriListen) return;
+    let unlisten: (() => void) | null = null;
+    tauriListen(GAGGED_LINES_EVENT, (e: { payload: unknown }) => {
+      logGaggedRef.current?.((e.payload as GaggedLinesPayload).lines);
+    }).then((fn) => { unlisten = fn; });
+    return () => { unlisten?.(); };
+  }, []);
+
   // Companion reconnect/disconnect listeners
   const reconnectRef = useLatestRef(reconnect);
   const disconnectRef = useLatestRef(disconnect);
@@ -1753,7 +1850,7 @@ function AppMain() {
     babelLanguage: appSettings.babelLanguage,
     babelIntervalSeconds: appSettings.babelIntervalSeconds,
     babelPhrases: appSettings.babelPhrases,
-    mergedTimers,
+    mergedTimers: runnableTimers,
     timerState,
     sendCommandRef,
     writeToTermRef,
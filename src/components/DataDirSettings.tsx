@@ -16,6 +16,8 @@ interface BackupEntry {
   tag: string;
   size: number;
   files: string[];
+  note: string | null;
+  encrypted: boolean;
 }
 
 type Tab = 'directories' | 'backups';
@@ -221,6 +221,14 @@ interface BackupEntry {
   tag: string;
   size: number;
   files: string[];
+  note: string | null;
+  encrypted: boolean;
+}
+
+/** From `import_sound_file`. */
+interface ImportedSound {
+  filename: string;
+  duration_secs: number | null;
 }
 
 /* ── Main Panel ───────────────────────────────────────────── */
@@ -861,10 +869,10 @@ function ChimePicker({
       });
       if (!selected || typeof selected !== 'string') return;
 
-      const destName = (await invoke('import_sound_file', {
+      const { filename: destName } = await invoke<ImportedSound>('import_sound_file', {
         sourcePath: selected,
         soundId: chimeId,
-      })) as string;
+      });
 
       // Store the original filename for display
       const parts = selected.replace(/\\/g, '/').split('/');
@@ -1032,10 +1040,10 @@ function CustomSoundsSection({
       });
       if (!selected || typeof selected !== 'string') return;
 
-      const destName = (await invoke('import_sound_file', {
+      const { filename: destName } = await invoke<ImportedSound>('import_sound_file', {
         sourcePath: selected,
         soundId: name,
-      })) as string;
+      });
 
       updateCustomSounds([...customSounds, { name, fileName: destName }]);
       setNewSoundName('');
@@ -33,7 +33,10 @@ export function SetupDialog() {
 
       // Temporarily resolve to peek at the directory for existing data
       await invoke('resolve_data_dir', { candidates: [path] });
-      const data: unknown = await invoke('read_data_file', { filename: 'settings.json' });
+      // An unreadable settings file still means there's data here
+      const data: unknown = await invoke('read_data_file', { filename: 'settings.json' }).catch(
+        () => true
+      );
       setHasExistingData(data != null);
     } catch (e) {
       console.error('Browse failed:', e);
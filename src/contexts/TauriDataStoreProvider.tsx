@@ -1,15 +1,58 @@
 import { useRef, useState, useEffect, useCallback, useMemo, type ReactNode } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { load } from '@tauri-apps/plugin-store';
 import { DataStoreContext, type DataStore } from './DataStoreContext';
+import {
+  DATA_DIR_LOCKED_EVENT,
+  DATA_DIR_MIGRATED_EVENT,
+  STORAGE_FILE_CHANGED_EVENT,
+} from '../lib/tauriEvents';
+import type {
+  DataDirLockedPayload,
+  DataDirMigratedPayload,
+  LaunchOptions,
+  StorageFileChangedPayload,
+} from '../types';
 
 const LOCAL_CONFIG_FILE = 'local-config.json';
 const DATA_DIRS_KEY = 'dataDirs';
 const DEBOUNCE_MS = 200;
-const BACKUP_INTERVAL_MS = 60 * 60 * 1000; // 1 hour
+/** Read in one `read_data_files` call at startup instead of one by one. */
+const STARTUP_FILES = [
+  'settings.json',
+  'aliases.json',
+  'triggers.json',
+  'timers.json',
+  'variables.json',
+  'signatures.json',
+  'who-titles.json',
+  'counters.json',
+  'chat-history.json',
+];
 
 type FileCache = Record<string, unknown>;
 
+/** Error from `read_data_file` when a file exists but can't be read. */
+interface StorageError {
+  kind: 'invalid_filename' | 'parse_error' | 'io_error';
+  message: string;
+  line?: number;
+  column?: number;
+  backup?: string | null;
+}
+
+/** One file's result from `read_data_files`. */
+interface BatchRead {
+  value: Record<string, unknown> | null;
+  error: StorageError | null;
+}
+
+/** Read a JSON data file; `null` if it doesn't exist. Throws a StorageError otherwise. */
+async function readDataFile(filename: string): Promise<Record<string, unknown> | null> {
+  return invoke('read_data_file', { filename });
+}
+
 export function DataStoreProvider({ children }: { children: ReactNode }) {
   const [ready, setReady] = useState(false);
   const [needsSetup, setNeedsSetup] = useState(false);
@@ -21,12 +64,22 @@ export function DataStoreProvider({ children }: { children: ReactNode }) {
   const loadedRef = useRef<Set<string>>(new Set());
   // Track dirty files with pending debounced writes
   const dirtyRef = useRef<Map<string, ReturnType<typeof setTimeout>>>(new Map());
+  // Files that exist but couldn't be read — never written back, so defaults
+  // can't replace the user's data
+  const unreadableRef = useRef<Set<string>>(new Set());
 
   // Initialize: check if setup is needed
   useEffect(() => {
     let cancelled = false;
     (async () => {
       try {
+        // --data-dir overrides the configured locations, so setup isn't needed
+        const launch: LaunchOptions = await invoke('get_launch_options');
+        if (launch.data_dir) {
+          if (!cancelled) await initializeWithCandidates([]);
+          return;
+        }
+
         const localStore = await load(LOCAL_CONFIG_FILE);
         const candidates = (await localStore.get<string[]>(DATA_DIRS_KEY)) ?? [];
 
@@ -51,11 +104,10 @@ export function DataStoreProvider({ children }: { children: ReactNode }) {
   async function initializeWithCandidates(candidates: string[]) {
     const resolved: string = await invoke('resolve_data_dir', { candidates });
     setActiveDataDir(resolved);
+    await preload(STARTUP_FILES);
 
-    // Check if auto-backups are enabled (read directly — settings context isn't mounted yet)
-    const settings: Record<string, unknown> | null = await invoke('read_data_file', {
-      filename: 'settings.json',
-    });
+    // Check if auto-backups are enabled (settings context isn't mounted yet)
+    const settings = cacheRef.current.get('settings.json');
     const backupsEnabled = settings?.autoBackupEnabled !== false; // default true
 
     if (backupsEnabled) {
@@ -66,6 +118,30 @@ export function DataStoreProvider({ children }: { children: ReactNode }) {
     setReady(true);
   }
 
+  /** Load several files into the cache in one round trip. */
+  async function preload(filenames: string[]) {
+    let results: Record<string, BatchRead>;
+    try {
+      results = await invoke('read_data_files', { filenames });
+    } catch (e) {
+      // Each file will be read on first use instead
+      console.error('Failed to preload data files:', e);
+      return;
+    }
+    for (const [filename, { value, error }] of Object.entries(results)) {
+      if (error) {
+        console.error(
+          `Failed to read ${filename}; changes to it won't be saved this session:`,
+          error.message,
+          error.backup ? `(copy saved as ${error.backup})` : ''
+        );
+        unreadableRef.current.add(filename);
+      }
+      cacheRef.current.set(filename, value ?? {});
+      loadedRef.current.add(filename);
+    }
+  }
+
   // Complete first-run setup
   const completeSetup = useCallback(async (dir: string): Promise<void> => {
     // Resolve to the selected directory
@@ -84,28 +160,68 @@ export function DataStoreProvider({ children }: { children: ReactNode }) {
     setReady(true);
   }, []);
 
-  // Hourly auto-backup (checks setting from cache each tick)
+  // A file changed outside the app: drop its cached copy so the next read
+  // sees the new contents, unless there are local edits still to be written
   useEffect(() => {
     if (!ready) return;
-    const interval = setInterval(async () => {
-      try {
-        const cache = cacheRef.current.get('settings.json');
-        if (cache && cache.autoBackupEnabled === false) return;
-        await invoke('create_backup', { tag: 'auto' });
-      } catch (e) {
-        console.error('Auto-backup failed:', e);
-      }
-    }, BACKUP_INTERVAL_MS);
-    return () => clearInterval(interval);
+    const unlisten = listen<StorageFileChangedPayload>(STORAGE_FILE_CHANGED_EVENT, (event) => {
+      const { filename } = event.payload;
+      if (dirtyRef.current.has(filename)) return;
+      cacheRef.current.delete(filename);
+      loadedRef.current.delete(filename);
+      unreadableRef.current.delete(filename);
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [ready]);
+
+  // The backend moved the data folder: start from it next launch too
+  useEffect(() => {
+    if (!ready) return;
+    const unlisten = listen<DataDirMigratedPayload>(DATA_DIR_MIGRATED_EVENT, async (event) => {
+      const { to } = event.payload;
+      const localStore = await load(LOCAL_CONFIG_FILE);
+      await localStore.set(DATA_DIRS_KEY, [to]);
+      await localStore.save();
+      setActiveDataDir(to);
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
   }, [ready]);
 
+  // Another instance has the data folder; the backend refuses writes here
+  useEffect(() => {
+    const unlisten = listen<DataDirLockedPayload>(DATA_DIR_LOCKED_EVENT, (event) => {
+      const { dir, pid } = event.payload;
+      console.error(
+        `${dir} is in use by another DartForge${pid ? ` (pid ${pid})` : ''}; changes won't be saved`
+      );
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
   /** Load a file from Rust into cache if not already loaded. */
   const ensureLoaded = useCallback(async (filename: string): Promise<FileCache> => {
     if (loadedRef.current.has(filename)) {
       return cacheRef.current.get(filename) ?? {};
     }
 
-    const data: Record<string, unknown> | null = await invoke('read_data_file', { filename });
+    let data: Record<string, unknown> | null = null;
+    try {
+      data = await readDataFile(filename);
+    } catch (e) {
+      const err = e as StorageError;
+      console.error(
+        `Failed to read ${filename}; changes to it won't be saved this session:`,
+        err.message,
+        err.backup ? `(copy saved as ${err.backup})` : ''
+      );
+      unreadableRef.current.add(filename);
+    }
     const cache = data ?? {};
     cacheRef.current.set(filename, cache);
     loadedRef.current.add(filename);
@@ -120,7 +236,7 @@ export function DataStoreProvider({ children }: { children: ReactNode }) {
     const timer = setTimeout(async () => {
       dirtyRef.current.delete(filename);
       const cache = cacheRef.current.get(filename);
-      if (cache) {
+      if (cache && !unreadableRef.current.has(filename)) {
         try {
           await invoke('write_data_file', { filename, data: cache });
         } catch (e) {
@@ -159,7 +275,7 @@ export function DataStoreProvider({ children }: { children: ReactNode }) {
     }
 
     const cache = cacheRef.current.get(filename);
-    if (cache) {
+    if (cache && !unreadableRef.current.has(filename)) {
       try {
         await invoke('write_data_file', { filename, data: cache });
       } catch (e) {
@@ -221,7 +337,7 @@ export function DataStoreProvider({ children }: { children: ReactNode }) {
 
     const promises = [...toFlush].map(async (filename) => {
       const cache = cacheRef.current.get(filename);
-      if (cache) {
+      if (cache && !unreadableRef.current.has(filename)) {
         try {
           await invoke('write_data_file', { filename, data: cache });
         } catch (e) {
@@ -240,6 +356,7 @@ export function DataStoreProvider({ children }: { children: ReactNode }) {
       // Clear caches
       cacheRef.current.clear();
       loadedRef.current.clear();
+      unreadableRef.current.clear();
 
       // Save candidates to local config
       const localStore = await load(LOCAL_CONFIG_FILE);
@@ -1,7 +1,7 @@
 import { useEffect, useRef, useState, useCallback } from 'react';
 import type { Terminal } from '@xterm/xterm';
 import type { MudTransport } from '../lib/transport';
-import { MudOutputPayload, ConnectionStatusPayload } from '../types';
+import { MudOutputPayload, ConnectionStatusPayload, InputEchoPayload } from '../types';
 import { getConnectingSplash, getConnectedSplash, getDisconnectSplash } from '../lib/splash';
 import { smartWrite } from '../lib/terminalUtils';
 import { stripAnsi } from '../lib/ansiUtils';
@@ -113,6 +113,7 @@ export function useMudConnection(
   onLogin?: () => void,
   autoLoginRef?: React.RefObject<AutoLoginConfig | null>,
   onFilteredOutput?: (data: string) => void,
+  onInputEcho?: (payload: InputEchoPayload) => void,
 ) {
   const [connected, setConnected] = useState(false);
   const [statusMessage, setStatusMessage] = useState('Connecting...');
@@ -120,6 +121,8 @@ export function useMudConnection(
   const [skipHistory, setSkipHistory] = useState(false);
   const unlistenRefs = useRef<(() => void)[]>([]);
   const wasConnectedRef = useRef(false);
+  /** Newest connection generation seen in a status; older statuses and output are stale. */
+  const generationRef = useRef(0);
   const passwordModeRef = useRef(false);
   const skipHistoryRef = useRef(false);
   const captureNameRef = useRef(false);
@@ -136,6 +139,8 @@ export function useMudConnection(
   onLoginRef.current = onLogin;
   const onFilteredOutputRef = useRef(onFilteredOutput);
   onFilteredOutputRef.current = onFilteredOutput;
+  const onInputEchoRef = useRef(onInputEcho);
+  onInputEchoRef.current = onInputEcho;
 
   // Banner filtering state
   const filteringBannerRef = useRef(false);
@@ -152,6 +157,8 @@ export function useMudConnection(
       const cleanup = await transportRef.current.connect({
         onOutput: (payload: MudOutputPayload) => {
           if (cancelled || !terminalRef.current) return;
+          // Still arriving from a connection a reconnect has replaced
+          if (payload.generation != null && payload.generation < generationRef.current) return;
           const term = terminalRef.current;
 
           const detectPrompts = (data: string) => {
@@ -254,6 +261,10 @@ export function useMudConnection(
 
         onStatus: (payload: ConnectionStatusPayload) => {
           if (cancelled) return;
+          if (payload.generation != null) {
+            if (payload.generation < generationRef.current) return;
+            generationRef.current = payload.generation;
+          }
           const term = terminalRef.current;
 
           if (payload.connected && !wasConnectedRef.current && term) {
@@ -282,6 +293,25 @@ export function useMudConnection(
           setConnected(payload.connected);
           setStatusMessage(payload.message);
         },
+
+        onInputEcho: (payload: InputEchoPayload) => {
+          if (cancelled) return;
+          if (payload.generation != null && payload.generation < generationRef.current) return;
+          onInputEchoRef.current?.(payload);
+        },
+
+        onStatusSync: (payload: ConnectionStatusPayload) => {
+          // Catching up after a reload: adopt the state without the
+          // connect/disconnect splash, which belongs to the transition
+          if (cancelled) return;
+          if (payload.generation != null) {
+            if (payload.generation < generationRef.current) return;
+            generationRef.current = payload.generation;
+          }
+          wasConnectedRef.current = payload.connected;
+          setConnected(payload.connected);
+          setStatusMessage(payload.message);
+        },
       });
 
       unlistenRefs.current = [cleanup];
@@ -2,6 +2,7 @@ import { useRef, useCallback, useEffect } from 'react';
 import { stripAnsi } from '../lib/ansiUtils';
 import { getPlatform } from '../lib/platform';
 import type { TimestampFormat } from './useAppSettings';
+import type { InputEchoPayload } from '../types';
 
 let invoke: ((cmd: string, args?: Record<string, unknown>) => Promise<unknown>) | null = null;
 if (getPlatform() === 'tauri') {
@@ -49,6 +50,8 @@ export function useSessionLogger(
   formatRef.current = timestampFormat;
   // Buffer for partial ANSI escape sequences split across TCP chunks
   const partialAnsiRef = useRef('');
+  // Once the backend echoes what it actually sent, log that instead of what was typed
+  const backendEchoRef = useRef(false);
 
   const flush = useCallback(() => {
     if (!invoke || !filenameRef.current || bufferRef.current.length === 0) return;
@@ -121,11 +124,37 @@ export function useSessionLogger(
   const logCommand = useCallback(
     (command: string) => {
       if (!enabled || !filenameRef.current || passwordModeRef.current) return;
+      if (backendEchoRef.current) return;
       bufferRef.current.push(`[${formatTimestamp(formatRef.current)}] >> ${command}\n`);
       scheduleFlush();
     },
     [enabled, scheduleFlush]
   );
 
-  return { logOutput, logCommand };
+  const logEcho = useCallback(
+    (payload: InputEchoPayload) => {
+      backendEchoRef.current = true;
+      if (!enabled || !filenameRef.current || passwordModeRef.current) return;
+      bufferRef.current.push(`[${formatTimestamp(formatRef.current)}] ${payload.marker}${payload.command}\n`);
+      scheduleFlush();
+    },
+    [enabled, scheduleFlush]
+  );
+
+  /** Lines the backend gagged from the terminal — kept in the log, marked. */
+  const logGagged = useCallback(
+    (lines: string[]) => {
+      if (!enabled || !filenameRef.current) return;
+      for (const line of lines) {
+        const stripped = stripAnsi(line).trim();
+        if (stripped) {
+          bufferRef.current.push(`[${formatTimestamp(formatRef.current)}] [gagged] ${stripped}\n`);
+        }
+      }
+      scheduleFlush();
+    },
+    [enabled, scheduleFlush]
+  );
+
+  return { logOutput, logCommand, logEcho, logGagged };
 }
@@ -9,6 +9,18 @@ import type {
   TriggerScope,
 } from '../types/trigger';
 import { sanitizeRecordMap } from '../lib/sanitizeRecords';
+import { getPlatform } from '../lib/platform';
+
+const invokePromise: Promise<
+  (cmd: string, args?: Record<string, unknown>) => Promise<unknown>
+> | null = getPlatform() === 'tauri' ? import('@tauri-apps/api/core').then((m) => m.invoke) : null;
+
+/** Have the desktop backend re-read triggers.json for its own trigger engine. */
+function reloadBackendTriggers(character: string | null) {
+  invokePromise
+    ?.then((invoke) => invoke('reload_triggers', { character }))
+    .catch((e) => console.error('Failed to reload backend triggers:', e));
+}
 
 const TRIGGERS_FILE = 'triggers.json';
 const GLOBAL_KEY = 'global';
@@ -72,6 +84,7 @@ export function useTriggers(dataStore: DataStore, activeCharacter: string | null
     const ds = dataStoreRef.current;
     ds.set(TRIGGERS_FILE, GLOBAL_KEY, globalTriggers)
       .then(() => ds.save(TRIGGERS_FILE))
+      .then(() => reloadBackendTriggers(activeCharRef.current))
       .catch(console.error);
   }, [globalTriggers]);
 
@@ -82,6 +95,7 @@ export function useTriggers(dataStore: DataStore, activeCharacter: string | null
     const charKey = activeCharRef.current.toLowerCase();
     ds.set(TRIGGERS_FILE, charKey, characterTriggers)
       .then(() => ds.save(TRIGGERS_FILE))
+      .then(() => reloadBackendTriggers(charKey))
       .catch(console.error);
   }, [characterTriggers]);
 
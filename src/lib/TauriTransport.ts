@@ -1,32 +1,64 @@
 import { listen } from '@tauri-apps/api/event';
 import { invoke } from '@tauri-apps/api/core';
-import { MUD_OUTPUT_EVENT, CONNECTION_STATUS_EVENT } from './tauriEvents';
+import { MUD_OUTPUT_EVENT, CONNECTION_STATUS_EVENT, INPUT_ECHO_EVENT } from './tauriEvents';
 import type { MudTransport, TransportCallbacks } from './transport';
-import type { MudOutputPayload, ConnectionStatusPayload } from '../types';
+import type {
+  MudOutputPayload,
+  ConnectionStatusPayload,
+  CurrentConnectionStatus,
+  InputEchoPayload,
+} from '../types';
+
+/** Session the UI talks to unless told otherwise. */
+export const DEFAULT_SESSION = 'default';
 
 export class TauriTransport implements MudTransport {
+  /** Which backend session this transport drives. */
+  private sessionId: string;
+
+  constructor(sessionId: string = DEFAULT_SESSION) {
+    this.sessionId = sessionId;
+  }
+
+  /** Events without a `session` predate multi-session backends and are the default session's. */
+  private ours(payload: { session?: string }): boolean {
+    return (payload.session ?? DEFAULT_SESSION) === this.sessionId;
+  }
+
   async connect(callbacks: TransportCallbacks): Promise<() => void> {
-    const unlistenOutput = await listen<MudOutputPayload>(MUD_OUTPUT_EVENT, (event) =>
-      callbacks.onOutput(event.payload)
-    );
-    const unlistenStatus = await listen<ConnectionStatusPayload>(CONNECTION_STATUS_EVENT, (event) =>
-      callbacks.onStatus(event.payload)
-    );
+    const unlistenOutput = await listen<MudOutputPayload>(MUD_OUTPUT_EVENT, (event) => {
+      if (this.ours(event.payload)) callbacks.onOutput(event.payload);
+    });
+    const unlistenStatus = await listen<ConnectionStatusPayload>(CONNECTION_STATUS_EVENT, (event) => {
+      if (this.ours(event.payload)) callbacks.onStatus(event.payload);
+    });
+    const unlistenEcho = await listen<InputEchoPayload>(INPUT_ECHO_EVENT, (event) => {
+      if (this.ours(event.payload)) callbacks.onInputEcho?.(event.payload);
+    });
+    // Listening first, so a status emitted meanwhile is not missed. A session
+    // that doesn't exist yet has no status to catch up on.
+    const current = await invoke<CurrentConnectionStatus>('get_connection_status', {
+      sessionId: this.sessionId,
+    }).catch(() => null);
+    if (current?.reason) {
+      callbacks.onStatusSync?.({ ...current, reason: current.reason });
+    }
     return () => {
       unlistenOutput();
       unlistenStatus();
+      unlistenEcho();
     };
   }
 
   async sendCommand(command: string): Promise<void> {
-    await invoke('send_command', { command });
+    await invoke('send_command', { command, sessionId: this.sessionId });
   }
 
   async reconnect(): Promise<void> {
-    await invoke('reconnect');
+    await invoke('reconnect', { sessionId: this.sessionId });
   }
 
   async disconnect(): Promise<void> {
-    await invoke('disconnect');
+    await invoke('disconnect', { sessionId: this.sessionId });
   }
 }
@@ -1,14 +1,36 @@
 import type { MudTransport, TransportCallbacks } from './transport';
 
 const DEFAULT_PROXY_URL = 'wss://dartforge-proxy.billbergquist.workers.dev/ws';
+/** The proxy message protocol this client speaks (see proxy-worker/src/protocol.ts). */
+const PROXY_PROTOCOL_VERSION = 3;
+/** Binary output frames (`compact_output`): type byte, flags byte (bit 0 = ga), UTF-8 text. */
+const FRAME_OUTPUT = 0x01;
+const FRAME_FLAG_GA = 0x01;
+const frameDecoder = new TextDecoder();
 
 export class WebSocketTransport implements MudTransport {
   private ws: WebSocket | null = null;
   private callbacks: TransportCallbacks | null = null;
   private proxyUrl: string;
+  /** `host:port` to ask the proxy for; the proxy's default when unset. */
+  private target: { host: string; port: number } | null;
+  /** Proxy session token; a dropped socket reopens with it to keep the MUD connection. */
+  private sessionToken: string | null = null;
+  /** A `reconnect` waiting for the socket to open (or fail to resume). */
+  private pendingReconnect: string | null = null;
+  /** Sent as the first message when the proxy requires `PROXY_AUTH_TOKEN`. */
+  private authToken: string | null;
 
-  constructor(proxyUrl?: string) {
+  constructor(proxyUrl?: string, target?: string, authToken?: string) {
     this.proxyUrl = proxyUrl ?? DEFAULT_PROXY_URL;
+    this.authToken = authToken || null;
+    const match = target?.match(/^(.+):(\d+)$/);
+    this.target = match ? { host: match[1], port: Number(match[2]) } : null;
+
+    // Mobile browsers drop the socket while the page is hidden — pick the session back up
+    document.addEventListener('visibilitychange', () => {
+      if (document.visibilityState === 'visible' && this.sessionToken) this.ensureSocket();
+    });
   }
 
   async connect(callbacks: TransportCallbacks): Promise<() => void> {
@@ -27,31 +49,91 @@ export class WebSocketTransport implements MudTransport {
     };
   }
 
+  private ensureSocket() {
+    if (!this.ws || this.ws.readyState === WebSocket.CLOSED) this.openSocket();
+  }
+
   private openSocket() {
-    this.ws = new WebSocket(this.proxyUrl);
+    const url = new URL(this.proxyUrl);
+    const resuming = this.sessionToken !== null;
+    if (this.sessionToken) url.searchParams.set('resume', this.sessionToken);
+    const ws = new WebSocket(url);
+    let attached = false;
+    let resumed = false;
+    this.ws = ws;
 
-    this.ws.onmessage = (event) => {
+    ws.onopen = () => {
+      if (this.authToken) ws.send(JSON.stringify({ type: 'auth', data: this.authToken }));
+      ws.send(JSON.stringify({ type: 'hello', version: PROXY_PROTOCOL_VERSION, features: ['compact_output'] }));
+      if (resuming) return;
+      // Opt in to a resumable session; proxies without it ignore the message
+      ws.send(JSON.stringify({ type: 'resume' }));
+      this.flushPendingReconnect();
+    };
+
+    ws.binaryType = 'arraybuffer';
+    ws.onmessage = (event) => {
+      if (event.data instanceof ArrayBuffer) {
+        const frame = new Uint8Array(event.data);
+        if (frame.length >= 2 && frame[0] === FRAME_OUTPUT) {
+          const data = frameDecoder.decode(frame.subarray(2));
+          this.callbacks?.onOutput({ data, ga: (frame[1] & FRAME_FLAG_GA) !== 0 });
+        }
+        return;
+      }
       try {
         const msg = JSON.parse(event.data);
-        if (msg.type === 'output') {
-          this.callbacks?.onOutput({ data: msg.data, ga: msg.ga ?? false });
+        if (msg.type === 'session') {
+          attached = true;
+          this.sessionToken = msg.token;
+          // A resumed session replays its status next; reconnect only if that says it dropped
+          resumed = msg.resumed;
+          if (!resumed) this.flushPendingReconnect();
+        } else if (msg.type === 'error') {
+          console.warn(`Proxy refused message (${msg.code}): ${msg.detail}`);
+        } else if (msg.type === 'ping') {
+          ws.send(JSON.stringify({ type: 'pong' }));
+        } else if (msg.type === 'output') {
+          this.callbacks?.onOutput({ data: msg.data, ga: msg.ga ?? false, ts_ms: msg.ts_ms });
         } else if (msg.type === 'status') {
-          this.callbacks?.onStatus({ connected: msg.connected, message: msg.message });
+          if (resumed) {
+            resumed = false;
+            if (msg.connected || msg.reason === 'connecting') this.pendingReconnect = null;
+            else this.flushPendingReconnect();
+          }
+          this.callbacks?.onStatus({
+            connected: msg.connected,
+            message: msg.message,
+            reason: msg.reason,
+            detail: msg.detail,
+            attempt: msg.attempt,
+            ts_ms: msg.ts_ms,
+          });
         }
       } catch (e) {
         console.error('Failed to parse proxy message:', e);
       }
     };
 
-    this.ws.onclose = () => {
+    ws.onclose = () => {
+      if (this.ws !== ws) return;
       this.ws = null;
+      // The proxy refused the token — start over with a new session
+      if (resuming && !attached) {
+        this.sessionToken = null;
+        if (this.pendingReconnect) {
+          this.openSocket();
+          return;
+        }
+      }
       this.callbacks?.onStatus({
         connected: false,
         message: 'WebSocket connection closed',
       });
     };
 
-    this.ws.onerror = () => {
+    ws.onerror = () => {
+      if (this.ws !== ws) return;
       this.callbacks?.onStatus({
         connected: false,
         message: 'WebSocket connection error',
@@ -66,19 +148,22 @@ export class WebSocketTransport implements MudTransport {
   }
 
   async reconnect(): Promise<void> {
+    const message = JSON.stringify({ type: 'reconnect', ...this.target });
     if (this.ws?.readyState === WebSocket.OPEN) {
-      this.ws.send(JSON.stringify({ type: 'reconnect' }));
+      this.ws.send(message);
     } else {
-      // WebSocket is down — re-establish it, then send reconnect once open
-      this.openSocket();
-      this.ws?.addEventListener(
-        'open',
-        () => {
-          this.ws?.send(JSON.stringify({ type: 'reconnect' }));
-        },
-        { once: true }
-      );
+      // WebSocket is down — re-establish it (resuming the session if there is
+      // one), then reconnect unless the resumed session is still attached
+      this.pendingReconnect = message;
+      this.ensureSocket();
+    }
+  }
+
+  private flushPendingReconnect() {
+    if (this.pendingReconnect && this.ws?.readyState === WebSocket.OPEN) {
+      this.ws.send(this.pendingReconnect);
     }
+    this.pendingReconnect = null;
   }
 
   async disconnect(): Promise<void> {
@@ -1,2 +1,24 @@
 export const MUD_OUTPUT_EVENT = 'mud:output';
 export const CONNECTION_STATUS_EVENT = 'mud:connection-status';
+export const INPUT_ECHO_EVENT = 'mud:input-echo';
+export const TRIGGER_FIRED_EVENT = 'mud:trigger-fired';
+export const GAGGED_LINES_EVENT = 'mud:gagged-lines';
+export const TIMER_FIRED_EVENT = 'mud:timer-fired';
+export const SESSION_LOG_EVENT = 'mud:session-log';
+export const STORAGE_FILE_CHANGED_EVENT = 'storage:file-changed';
+export const BACKUP_PROGRESS_EVENT = 'storage:backup-progress';
+export const AUTO_BACKUP_EVENT = 'storage:auto-backup';
+export const DATA_DIR_MIGRATED_EVENT = 'storage:data-dir-migrated';
+export const DATA_DIR_LOCKED_EVENT = 'storage:data-dir-locked';
+export const LOW_DISK_EVENT = 'storage:low-disk';
+export const SECOND_INSTANCE_EVENT = 'app:second-instance';
+export const TELNET_DEBUG_EVENT = 'mud:telnet-debug';
+export const OUTPUT_PAUSED_EVENT = 'mud:output-paused';
+export const PROMPT_EVENT = 'mud:prompt';
+export const VITALS_EVENT = 'mud:vitals';
+export const ROOM_EVENT = 'mud:room';
+export const COMMS_EVENT = 'mud:comms';
+export const SESSION_SUMMARY_EVENT = 'mud:session-summary';
+export const IDLE_LIMIT_EVENT = 'mud:idle-limit';
+export const SCRIPT_ERROR_EVENT = 'mud:script-error';
+export const PLAIN_OUTPUT_EVENT = 'mud:output-plain';
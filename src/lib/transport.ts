@@ -1,8 +1,12 @@
-import type { MudOutputPayload, ConnectionStatusPayload } from '../types';
+import type { MudOutputPayload, ConnectionStatusPayload, InputEchoPayload } from '../types';
 
 export interface TransportCallbacks {
   onOutput: (payload: MudOutputPayload) => void;
   onStatus: (payload: ConnectionStatusPayload) => void;
+  /** Commands echoed by the backend after being written (Tauri only, when enabled). */
+  onInputEcho?: (payload: InputEchoPayload) => void;
+  /** The status already in effect when listening started, e.g. after a reload (Tauri only). */
+  onStatusSync?: (payload: ConnectionStatusPayload) => void;
 }
 
 export interface MudTransport {
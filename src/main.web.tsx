@@ -37,7 +37,9 @@ if (window.opener) {
   window.close();
 } else {
   const proxyUrl = import.meta.env.VITE_PROXY_URL as string | undefined;
-  const transport = new WebSocketTransport(proxyUrl);
+  const mudTarget = import.meta.env.VITE_MUD_TARGET as string | undefined;
+  const proxyToken = import.meta.env.VITE_PROXY_AUTH_TOKEN as string | undefined;
+  const transport = new WebSocketTransport(proxyUrl, mudTarget, proxyToken);
 
   ReactDOM.createRoot(document.getElementById('root') as HTMLElement).render(
     <React.StrictMode>
@@ -25,6 +25,12 @@ export interface Alias {
   bodyMode?: AliasBodyMode;
   /** Whether this alias is active */
   enabled: boolean;
+  /**
+   * 'backend' expands the alias in the desktop app's Rust backend, right
+   * before the command is sent (text bodies only; client directives like
+   * /delay are skipped there). The web client always expands in the frontend.
+   */
+  engine?: 'frontend' | 'backend';
   /** User-assigned group for organization */
   group: string;
   /** When this alias was created (ISO string) */
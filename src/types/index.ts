@@ -53,9 +53,366 @@ export { formatHotkey, hotkeyToString, hotkeyFromEvent, isReservedHotkey, isNump
 export interface MudOutputPayload {
   data: string;
   ga: boolean;
+  /** Backend session the output came from (desktop only). */
+  session?: string;
+  /** Output held by `pause_output`, delivered on resume (desktop only). */
+  buffered?: boolean;
+  /**
+   * Connection task the output came from, as on statuses (desktop only).
+   * Output with a lower generation than the latest status's is from a
+   * replaced connection; drop it. The same goes for every payload with one.
+   */
+  generation?: number;
+  /**
+   * When it was emitted, in Unix millis. Every desktop `mud:*` event has one;
+   * over the web proxy, only JSON output does (not binary frames).
+   */
+  ts_ms?: number;
+}
+
+/** `mud:output-paused`: a session's pause state, also sent on each prompt while paused. */
+export interface OutputPausedPayload {
+  session: string;
+  paused: boolean;
+  held_bytes: number;
+  dropped_bytes: number;
+  last_prompt_at: number | null;
+}
+
+/** A GA-terminated prompt, ANSI-stripped. */
+export interface PromptPayload {
+  session: string;
+  prompt: string;
+  /** Connection generation (desktop only); see `MudOutputPayload.generation`. */
+  generation?: number;
+  /** See `MudOutputPayload.ts_ms`. */
+  ts_ms?: number;
+}
+
+/** Numeric prompt fields (`hp`, `hp_max`, `fatigue`, ...), emitted when they change. */
+export interface VitalsPayload {
+  session: string;
+  vitals: Record<string, number>;
+  prompt: string;
+  /** Connection generation (desktop only); see `MudOutputPayload.generation`. */
+  generation?: number;
+  /** See `MudOutputPayload.ts_ms`. */
+  ts_ms?: number;
+}
+
+/** A room seen in the output, from the backend room parser. */
+export interface RoomPayload {
+  session: string;
+  name: string;
+  exits: string[];
+  /** Direction of the movement command that led here, if one was sent just before. */
+  moved_via: string | null;
+  /** Connection generation (desktop only); see `MudOutputPayload.generation`. */
+  generation?: number;
+  /** See `MudOutputPayload.ts_ms`. */
+  ts_ms?: number;
+}
+
+/** A tell, say or channel message routed by the backend's channel rules. */
+export interface CommsPayload {
+  session: string;
+  channel: string;
+  speaker: string | null;
+  /** The message, continuation lines joined with spaces. */
+  text: string;
+  /** The lines it came from, ANSI-stripped. */
+  raw: string;
+  /** Connection generation (desktop only); see `MudOutputPayload.generation`. */
+  generation?: number;
+  /** See `MudOutputPayload.ts_ms`. */
+  ts_ms?: number;
+}
+
+/** One connection's activity, from `mud:session-summary` or `get_session_summary`/`get_session_history`. */
+export interface SessionSummaryPayload {
+  session: string;
+  profile: string | null;
+  started_at: number;
+  /** `null` while the session is still connected. */
+  ended_at: number | null;
+  duration_secs: number;
+  commands: number;
+  lines: number;
+  bytes_read: number;
+  bytes_written: number;
+  /** Lines matched by each session counter. */
+  counters: Record<string, number>;
+  /** Why the session ended. */
+  reason: ConnectionStatusReason | null;
+}
+
+/** Why the connection is in its current state — key behavior off this, not `message`. */
+export type ConnectionStatusReason =
+  | 'connecting'
+  | 'connected'
+  | 'user_disconnect'
+  | 'server_closed'
+  | 'read_error'
+  | 'write_error'
+  | 'dns_failure'
+  | 'timeout'
+  | 'connect_refused'
+  | 'stalled'
+  | 'resumed_stale'
+  | 'proxy_error'
+  /** Web proxy only: the requested MUD isn't in the proxy's allowlist. */
+  | 'target_not_permitted'
+  /** Web proxy only: refused by its session limits. */
+  | 'server_full'
+  /** Web proxy only: too many connection attempts from this address. */
+  | 'rate_limited';
+
+/** A command as actually written to the MUD socket (desktop local echo). */
+export interface InputEchoPayload {
+  command: string;
+  /** Prefix for transcripts and session logs, e.g. "> ". */
+  marker: string;
+  session?: string;
+  /** Sent by anti-idle rather than typed. */
+  synthetic?: boolean;
+  /** Connection generation (desktop only); see `MudOutputPayload.generation`. */
+  generation?: number;
+  /** See `MudOutputPayload.ts_ms`. */
+  ts_ms?: number;
+}
+
+/** A script failed to load (`hook` is `load`), in a hook, or in a `timer` callback. */
+export interface ScriptErrorPayload {
+  script: string;
+  hook: string;
+  message: string;
+}
+
+/** Accessible output: complete ANSI-free lines joined with `\n`, or a prompt on its own. */
+export interface PlainOutputPayload {
+  session: string;
+  text: string;
+  is_prompt: boolean;
+  /** Connection generation (desktop only); see `MudOutputPayload.generation`. */
+  generation?: number;
+  /** See `MudOutputPayload.ts_ms`. */
+  ts_ms?: number;
+}
+
+/** Anti-idle sent its last command in a row and stopped; the character will idle out. */
+export interface IdleLimitPayload {
+  session: string;
+  sent: number;
+  /** Connection generation (desktop only); see `MudOutputPayload.generation`. */
+  generation?: number;
+  /** See `MudOutputPayload.ts_ms`. */
+  ts_ms?: number;
+}
+
+/** Lines the desktop backend gagged out of `mud:output`, for the session log. */
+export interface GaggedLinesPayload {
+  lines: string[];
+  /** Connection generation (desktop only); see `MudOutputPayload.generation`. */
+  generation?: number;
+  /** See `MudOutputPayload.ts_ms`. */
+  ts_ms?: number;
+}
+
+/** A backend-engine trigger matched a line (desktop only). */
+export interface TriggerFiredPayload {
+  id: string;
+  name: string | null;
+  /** The matched line, ANSI-stripped. */
+  line: string;
+  /** $0 followed by regex groups. */
+  captures: string[];
+  /** Sound for the frontend to play, if the trigger has one. */
+  sound: string | null;
+  /** The backend already played `sound`; don't play it again. */
+  played_natively: boolean;
+  gagged: boolean;
+}
+
+/** A backend timer came due (desktop only). */
+export interface TimerFiredPayload {
+  name: string;
+  command: string;
+  /** False if the command couldn't be handed to the connection. */
+  sent: boolean;
+}
+
+/** Whether the backend session log is recording (desktop only). */
+export interface SessionLogStatusPayload {
+  active: boolean;
+  /** The file being written, once output has been logged. */
+  path: string | null;
+  /** Unix millis. */
+  started_at: number | null;
+  bytes_written: number;
+}
+
+/** A data file was changed outside the app (desktop only). */
+export interface StorageFileChangedPayload {
+  filename: string;
+  kind: 'created' | 'modified' | 'removed';
+}
+
+/** A file was added to a backup being created (desktop only). */
+export interface BackupProgressPayload {
+  /** 1-based. */
+  index: number;
+  total: number;
+  /** Uncompressed bytes added so far. */
+  bytes: number;
+}
+
+/** A scheduled backup was attempted (desktop only). */
+export interface AutoBackupPayload {
+  tag: string;
+  /** The backup written; null on failure or when there was nothing to back up. */
+  filename: string | null;
+  error: string | null;
+}
+
+/** `migrate_data_dir` switched to a new data folder (desktop only). */
+export interface DataDirMigratedPayload {
+  from: string;
+  to: string;
+  /** Files copied. */
+  files: number;
+  /** Whether the originals were deleted. */
+  moved: boolean;
+}
+
+/** Another DartForge holds the data folder, so this one opened it read-only. */
+export interface DataDirLockedPayload {
+  dir: string;
+  /** The other instance, when its lock file could be read. */
+  pid: number | null;
+  started_at: string | null;
+}
+
+/** Free space on the data folder's volume fell below the configured floor. */
+export interface LowDiskPayload {
+  dir: string;
+  available_bytes: number;
+  floor_bytes: number;
+}
+
+/** From `get_launch_options`: the command-line flags this launch used (desktop only). */
+export interface LaunchOptions {
+  data_dir: string | null;
+  profile: string | null;
+  /** `profile` connects once `frontend_ready` is called. */
+  autoconnect: boolean;
+  /** Triggers, timers and auto-login are off. */
+  safe_mode: boolean;
+  log_level: string | null;
+  /** Problems with the arguments; those arguments were ignored. */
+  errors: string[];
+}
+
+/** DartForge was launched again and handed its arguments to this window. */
+export interface SecondInstancePayload {
+  args: string[];
+  /** Profile connected with via `--profile`. */
+  profile: string | null;
+  /** Commands delivered via `--send`; `queued` ones wait for a connection. */
+  sent: { command: string; queued: boolean }[];
+  errors: string[];
+}
+
+/** A telnet command seen while `set_telnet_debug` is on. Bytes are space-separated hex. */
+export interface TelnetDebugPayload {
+  direction: 'in' | 'out';
+  command: string;
+  option: number | null;
+  option_name: string | null;
+  bytes: string;
+  /** Reply generated for a command from the server. */
+  response: string | null;
+  at: number;
+}
+
+/** From `get_storage_usage`: bytes the data folder uses, by kind. */
+export interface StorageUsage {
+  data_bytes: number;
+  logs_bytes: number;
+  sounds_bytes: number;
+  backups_bytes: number;
+  total_bytes: number;
+  available_bytes: number | null;
+  /** The scan stopped early, so the totals are lower bounds. */
+  truncated: boolean;
+}
+
+/** Progress of a `send_commands` batch (desktop only). */
+export interface BatchProgressPayload {
+  sent: number;
+  total: number;
+  state: 'sending' | 'completed' | 'cancelled' | 'discarded' | 'interrupted';
+}
+
+/** Rate-limiter backlog warning; `depth: 0` clears it. */
+export interface CommandBacklogPayload {
+  depth: number;
+  threshold: number;
 }
 
 export interface ConnectionStatusPayload {
   connected: boolean;
+  /** Display text only. */
   message: string;
+  /** Absent for client-side transport statuses (e.g. the proxy WebSocket closing). */
+  reason?: ConnectionStatusReason;
+  detail?: string | null;
+  /** Attempt number on retry/failure statuses. */
+  attempt?: number | null;
+  /** Connection profile in use (desktop only). */
+  profile?: string | null;
+  /** Remote address on connected statuses (desktop only). */
+  peer?: string | null;
+  /**
+   * Connection task the status came from (desktop only). A status with a lower
+   * generation than one already seen is from a replaced connection; ignore it.
+   */
+  generation?: number;
+  /** When it was emitted, in Unix millis; absent on client-side transport statuses. */
+  ts_ms?: number;
+  /** Backend session the status is for (desktop only). */
+  session?: string;
+}
+
+/** From `list_sessions`. */
+export interface SessionInfo {
+  id: string;
+  status: CurrentConnectionStatus;
+}
+
+/** From `get_connection_status`: the status as of the last status event. */
+export interface CurrentConnectionStatus {
+  connected: boolean;
+  message: string;
+  /** `null` until the first connection attempt. */
+  reason: ConnectionStatusReason | null;
+  detail: string | null;
+  attempt: number | null;
+  profile: string | null;
+  peer: string | null;
+  /** Unix millis when the current connection was established. */
+  connected_since: number | null;
+  generation: number;
+  /** Connected, but a latency probe went unanswered and nothing has arrived since. */
+  stalled: boolean;
+}
+
+export type ClientImportFormat = 'tintin' | 'mudlet';
+
+/** From `import_client_config`. */
+export interface ClientImport {
+  imported: { kind: 'trigger' | 'alias' | 'highlight'; name: string; note: string | null }[];
+  /** What couldn't be converted, quoting the statement or naming the item. */
+  skipped: { source: string; reason: string }[];
+  /** The pre-import backup; `null` when nothing was imported. */
+  backup: string | null;
 }
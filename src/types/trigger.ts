@@ -47,6 +47,29 @@ export interface Trigger {
   multiLine?: boolean;
   /** Regex pattern that signals the end of a multi-line buffer */
   endPattern?: string;
+  /**
+   * 'backend' runs the trigger in the desktop app's Rust engine (still fires
+   * when the webview is busy, gags before the line is displayed). Text bodies
+   * only — client directives like /delay are skipped there, and
+   * multi-line/script triggers aren't supported. The web client always uses the frontend engine.
+   */
+  engine?: 'frontend' | 'backend';
+  /** Override case-insensitivity (default: on for substring/regex, off for exact). Backend engine only. */
+  ignoreCase?: boolean;
+  /** Fire at most once per connection. Backend engine only. */
+  once?: boolean;
+  /**
+   * Play the sound natively from the desktop app, which webview autoplay
+   * rules can't hold back. Backend engine only.
+   */
+  nativeSound?: boolean;
+  /**
+   * Send a desktop notification when this fires while the window is
+   * unfocused, titled `notifyTitle` (default: the trigger's name). Backend
+   * engine only.
+   */
+  notifyOnMatch?: boolean;
+  notifyTitle?: string | null;
   /** When this trigger was created (ISO string) */
   createdAt: string;
   /** When this trigger was last modified (ISO string) */